@@ -0,0 +1,37 @@
+//! Compare hashrates across devices.
+//!
+//! cargo run --release --example bench -- cpu 10
+//! cargo run --release --features cuda-rust --example bench -- gpu 10 0
+
+use std::env;
+
+use progpow::bench;
+use progpow::params::DynParams;
+use progpow_base::params::KawPowParams;
+
+fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let target = args.get(0).map(String::as_str).unwrap_or("cpu");
+	let seconds = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(10);
+
+	let report = match target {
+		"cpu" => bench::bench_cpu(DynParams::of::<KawPowParams>(), seconds),
+		#[cfg(any(feature = "cuda-rust", feature = "opencl-rust"))]
+		"gpu" => {
+			let device = args.get(2).and_then(|s| s.parse().ok()).unwrap_or(0);
+			bench::bench_gpu::<KawPowParams>(device, seconds)
+		}
+		other => {
+			eprintln!("unknown device {:?}, expected cpu or gpu", other);
+			std::process::exit(2);
+		}
+	};
+
+	match report {
+		Ok(report) => println!("{}", report),
+		Err(e) => {
+			eprintln!("benchmark failed: {:?}", e);
+			std::process::exit(1);
+		}
+	}
+}
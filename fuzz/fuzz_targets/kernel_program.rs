@@ -0,0 +1,336 @@
+//! Generates CUDA and OpenCL kernels for fuzzed (preset, period, height) inputs and checks
+//! that the random program spliced into the source is the one `progpow_init` and the
+//! CPU-side KISS99 sequence produce.
+//!
+//! The reference is rebuilt from `progpow_cpu::hashcore` rather than the generator's own
+//! KISS99, and every merge and math statement is evaluated on fuzzed operands against
+//! `hashcore::merge`/`hashcore::math`, so a mis-printed operator is caught as well as a
+//! diverging register sequence.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use progpow::generator::{generate_cuda_kernel, generate_opencl_kernel};
+use progpow::params::SeroParams;
+use progpow_base::params::{KawPowParams, MathMapping, ProgPowParams, ZanoParams};
+use progpow_cpu::hashcore::{self, fnv1a_hash, Kiss99};
+
+const FNV_HASH: u32 = 0x811c9dc5;
+
+/// One step of the random program, as the CPU sees it.
+#[derive(Debug, PartialEq)]
+enum Op {
+	Cache {
+		src: usize,
+		dst: usize,
+		r: u32,
+	},
+	Math {
+		src1: usize,
+		src2: usize,
+		r1: u32,
+		dst: usize,
+		r2: u32,
+	},
+	Dag {
+		word: usize,
+		dst: usize,
+		r: u32,
+	},
+}
+
+/// One step of the random program, as printed into the kernel.
+#[derive(Debug)]
+enum Stmt {
+	Cache {
+		src: usize,
+		dst: usize,
+		merge: String,
+	},
+	Math {
+		src1: usize,
+		src2: usize,
+		math: String,
+		dst: usize,
+		merge: String,
+	},
+	Dag {
+		word: usize,
+		dst: usize,
+		merge: String,
+	},
+}
+
+fn reference<P: ProgPowParams>(height: u64) -> Vec<Op> {
+	let seed = P::prog_seed(height);
+	let z = fnv1a_hash(FNV_HASH, seed as u32);
+	let w = fnv1a_hash(z, (seed >> 32) as u32);
+	let jsr = fnv1a_hash(w, seed as u32);
+	let jcong = fnv1a_hash(jsr, (seed >> 32) as u32);
+	let mut rnd = Kiss99::new(z, w, jsr, jcong);
+
+	let regs = P::REGS as usize;
+	let mut mix_seq_dst: Vec<usize> = (0..regs).collect();
+	let mut mix_seq_cache: Vec<usize> = (0..regs).collect();
+	if P::HAS_KISS99_SHUFFLE {
+		for i in (1..regs).rev() {
+			let j = rnd.next_u32() as usize % (i + 1);
+			mix_seq_dst.swap(i, j);
+			let j = rnd.next_u32() as usize % (i + 1);
+			mix_seq_cache.swap(i, j);
+		}
+	}
+
+	let mut ops = Vec::new();
+	let (mut dst_cnt, mut cache_cnt) = (0, 0);
+	let mut next_dst = || {
+		let dst = mix_seq_dst[dst_cnt % regs];
+		dst_cnt += 1;
+		dst
+	};
+	for i in 0..(P::CNT_CACHE as usize).max(P::CNT_MATH as usize) {
+		if i < P::CNT_CACHE as usize {
+			let src = mix_seq_cache[cache_cnt % regs];
+			cache_cnt += 1;
+			let dst = next_dst();
+			ops.push(Op::Cache {
+				src,
+				dst,
+				r: rnd.next_u32(),
+			});
+		}
+		if i < P::CNT_MATH as usize {
+			let src_rnd = rnd.next_u32() as usize % ((regs - 1) * regs);
+			let src1 = src_rnd % regs;
+			let mut src2 = src_rnd / regs;
+			if src2 >= src1 {
+				src2 += 1;
+			}
+			let r1 = rnd.next_u32();
+			let dst = next_dst();
+			let r2 = rnd.next_u32();
+			ops.push(Op::Math {
+				src1,
+				src2,
+				r1,
+				dst,
+				r2,
+			});
+		}
+	}
+	ops.push(Op::Dag {
+		word: 0,
+		dst: 0,
+		r: rnd.next_u32(),
+	});
+	for word in 1..P::DAG_LOADS as usize {
+		let dst = next_dst();
+		ops.push(Op::Dag {
+			word,
+			dst,
+			r: rnd.next_u32(),
+		});
+	}
+	ops
+}
+
+/// Register indices of every `mix[N]` in `line`, in order.
+fn regs(line: &str) -> Vec<usize> {
+	line.match_indices("mix[")
+		.map(|(at, _)| {
+			let rest = &line[at + 4..];
+			rest[..rest.find(']').unwrap()].parse().unwrap()
+		})
+		.collect()
+}
+
+fn parse(source: &str) -> Vec<Stmt> {
+	let lines: Vec<&str> = source.lines().map(str::trim).collect();
+	let mut stmts = Vec::new();
+	for (i, line) in lines.iter().enumerate() {
+		if line.starts_with("// cache load ") {
+			let src = regs(lines[i + 1])[0];
+			let merge = lines[i + 3];
+			stmts.push(Stmt::Cache {
+				src,
+				dst: regs(merge)[0],
+				merge: merge.to_string(),
+			});
+		} else if line.starts_with("// random math ") {
+			let math = lines[i + 1];
+			let merge = lines[i + 2];
+			let srcs = regs(math);
+			stmts.push(Stmt::Math {
+				src1: srcs[0],
+				src2: srcs[1],
+				math: math.to_string(),
+				dst: regs(merge)[0],
+				merge: merge.to_string(),
+			});
+		} else if line.starts_with("mix[") && line.contains("data_dag.s[") {
+			let rest = &line[line.find("data_dag.s[").unwrap() + 11..];
+			let word = rest[..rest.find(']').unwrap()].parse().unwrap();
+			stmts.push(Stmt::Dag {
+				word,
+				dst: regs(line)[0],
+				merge: line.to_string(),
+			});
+		}
+	}
+	stmts
+}
+
+/// Evaluate a printed `merge()` statement `mix[D] = f(mix[D], b);`.
+fn eval_merge(stmt: &str, a: u32, b: u32) -> u32 {
+	let rhs = stmt.split_once(" = ").unwrap().1.trim_end_matches(';');
+	if rhs.ends_with(" * 33") {
+		(a ^ b).wrapping_mul(33)
+	} else if rhs.contains(" * 33) + ") {
+		a.wrapping_mul(33).wrapping_add(b)
+	} else {
+		let n: u32 = rhs[rhs.find(", ").unwrap() + 2..rhs.find(')').unwrap()]
+			.parse()
+			.unwrap();
+		if rhs.starts_with("ROTL32(") {
+			a.rotate_left(n) ^ b
+		} else if rhs.starts_with("ROTR32(") {
+			a.rotate_right(n) ^ b
+		} else {
+			panic!("unrecognised merge: {}", stmt)
+		}
+	}
+}
+
+/// Evaluate a printed `math()` statement `data = f(mix[A], mix[B]);`.
+fn eval_math(stmt: &str, a: u32, b: u32) -> u32 {
+	let rhs = stmt.split_once(" = ").unwrap().1.trim_end_matches(';');
+	if rhs.starts_with("clz(") {
+		a.leading_zeros() + b.leading_zeros()
+	} else if rhs.starts_with("popcount(") {
+		a.count_ones() + b.count_ones()
+	} else if rhs.starts_with("mul_hi(") {
+		((a as u64 * b as u64) >> 32) as u32
+	} else if rhs.starts_with("min(") {
+		a.min(b)
+	} else if rhs.starts_with("ROTL32(") {
+		a.rotate_left(b % 32)
+	} else if rhs.starts_with("ROTR32(") {
+		a.rotate_right(b % 32)
+	} else if rhs.contains(" + ") {
+		a.wrapping_add(b)
+	} else if rhs.contains(" * ") {
+		a.wrapping_mul(b)
+	} else if rhs.contains(" & ") {
+		a & b
+	} else if rhs.contains(" | ") {
+		a | b
+	} else if rhs.contains(" ^ ") {
+		a ^ b
+	} else {
+		panic!("unrecognised math: {}", stmt)
+	}
+}
+
+fn check<P: ProgPowParams>(backend: &str, source: &str, height: u64, a: u32, b: u32) {
+	let mapping = match P::MATH_MAPPING {
+		MathMapping::Zano => MathMapping::Zano,
+		_ => MathMapping::Standard,
+	};
+	let expected = reference::<P>(height);
+	let stmts = parse(source);
+	assert_eq!(
+		stmts.len(),
+		expected.len(),
+		"{} {} h={}",
+		P::NAME,
+		backend,
+		height
+	);
+
+	for (i, (stmt, op)) in stmts.iter().zip(&expected).enumerate() {
+		let ctx = format!(
+			"{} {} h={} op {}: {:?} vs {:?}",
+			P::NAME,
+			backend,
+			height,
+			i,
+			stmt,
+			op
+		);
+		match (stmt, op) {
+			(Stmt::Cache { src, dst, merge }, Op::Cache { src: s, dst: d, r }) => {
+				assert_eq!((src, dst), (s, d), "{}", ctx);
+				assert_eq!(
+					eval_merge(merge, a, b),
+					hashcore::merge(a, b, *r),
+					"{}",
+					ctx
+				);
+			}
+			(
+				Stmt::Math {
+					src1,
+					src2,
+					math,
+					dst,
+					merge,
+				},
+				Op::Math {
+					src1: s1,
+					src2: s2,
+					r1,
+					dst: d,
+					r2,
+				},
+			) => {
+				assert_eq!((src1, src2, dst), (s1, s2, d), "{}", ctx);
+				assert_eq!(
+					eval_math(math, a, b),
+					hashcore::math(a, b, *r1, mapping),
+					"{}",
+					ctx
+				);
+				assert_eq!(
+					eval_merge(merge, a, b),
+					hashcore::merge(a, b, *r2),
+					"{}",
+					ctx
+				);
+			}
+			(Stmt::Dag { word, dst, merge }, Op::Dag { word: w, dst: d, r }) => {
+				assert_eq!((word, dst), (w, d), "{}", ctx);
+				assert_eq!(
+					eval_merge(merge, a, b),
+					hashcore::merge(a, b, *r),
+					"{}",
+					ctx
+				);
+			}
+			_ => panic!("{}", ctx),
+		}
+	}
+}
+
+fn run<P: ProgPowParams>(period: u64, height: u64, a: u32, b: u32) {
+	let cuda = generate_cuda_kernel::<P>(period, height);
+	check::<P>("cuda", &cuda, height, a, b);
+	let opencl = generate_opencl_kernel::<P>(period, height);
+	check::<P>("opencl", &opencl, height, a, b);
+}
+
+fuzz_target!(|data: &[u8]| {
+	if data.len() < 25 {
+		return;
+	}
+	let word = |at: usize| u64::from_le_bytes(data[at..at + 8].try_into().unwrap());
+	// Heights past a few hundred epochs only grow the DAG size constant.
+	let height = word(1) % (1 << 24);
+	let period = word(9);
+	let (a, b) = (word(17) as u32, (word(17) >> 32) as u32);
+
+	match data[0] % 3 {
+		0 => run::<KawPowParams>(period, height, a, b),
+		1 => run::<ZanoParams>(period, height, a, b),
+		_ => run::<SeroParams>(period, height, a, b),
+	}
+});
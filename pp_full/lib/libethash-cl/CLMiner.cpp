@@ -26,7 +26,9 @@ unsigned CLMiner::s_initialGlobalWorkSize = CLMiner::c_defaultGlobalWorkSizeMult
 unsigned CLMiner::s_threadsPerHash = 8;
 CLKernelName CLMiner::s_clKernelName = CLMiner::c_defaultKernelName;
 
-constexpr size_t c_maxSearchResults = 1;
+constexpr size_t c_maxSearchResults = 4;
+// Words of the search buffer: the count, then a gid and 8 mix words per result.
+constexpr size_t c_searchBufferWords = 1 + c_maxSearchResults * 9;
 
 struct CLChannel: public LogChannel
 {
@@ -210,6 +212,25 @@ static std::string ethCLErrorHelper(const char *msg, cl::Error const &clerr) {
 	return osstream.str();
 }
 
+void CLMiner::checkLost(cl::Error const& err)
+{
+	switch (err.err())
+	{
+	case CL_DEVICE_NOT_FOUND:
+	case CL_DEVICE_NOT_AVAILABLE:
+	case CL_OUT_OF_RESOURCES:
+	case CL_INVALID_DEVICE:
+	case CL_INVALID_CONTEXT:
+	case CL_INVALID_COMMAND_QUEUE:
+	case CL_NV_ILLEGAL_ACCESS:
+		cwarn << ethCLErrorHelper("GPU lost", err);
+		mark_lost();
+		return;
+	default:
+		throw;
+	}
+}
+
 namespace
 {
 
@@ -268,74 +289,274 @@ unsigned CLMiner::s_platformId = 0;
 unsigned CLMiner::s_numInstances = 0;
 vector<int> CLMiner::s_devices(MAX_MINERS, -1);
 
-CLMiner::CLMiner(unsigned _index): current(0,0,-1,0) {
+CLMiner::CLMiner(unsigned _index): current(0,0,-1) {
 	index = _index;
 }
 
-void CLMiner::compute(const void* header, uint64_t height, int epoch, uint64_t target, uint64_t startNonce)
+CLMiner::~CLMiner()
+{
+	discardPrefetched();
+}
+
+// The search kernel's target argument.
+static cl_uint8 targetArg(const void* target)
+{
+	cl_uint8 arg;
+	target_words(target, arg.s);
+	return arg;
+}
+
+void CLMiner::compute(const void* header, uint64_t height, int epoch, const void* target, uint64_t startNonce, uint32_t count)
+{
+	try
+	{
+		current.startNonce = startNonce;
+
+		if (!prepare(height, epoch))
+			return;
+
+		// The target may change within a height, so it is set on every call.
+		current.target = h256 { (const uint8_t*)target, h256::ConstructFromPointer };
+		m_searchKernel.setArg(4, targetArg(target));
+
+		if (current.header != nullptr) {
+			delete current.header;
+		}
+
+		current.header = new h256 { (const uint8_t*)header, h256::ConstructFromPointer };
+
+		// Update header constant buffer.
+		m_queue.enqueueWriteBuffer(m_header, CL_FALSE, 0, current.header->size, current.header->data());
+
+		// set start nonce
+		m_searchKernel.setArg(3, startNonce);
+		m_searchKernel.setArg(6, count);
+
+		// run search kernel
+		m_queue.enqueueNDRangeKernel(m_searchKernel, cl::NullRange, m_globalWorkSize, m_workgroupSize);
+
+		m_queue.finish();
+	}
+	catch (cl::Error const& err)
+	{
+		checkLost(err);
+	}
+}
+
+bool CLMiner::prepare(uint64_t height, int epoch)
 {
 	uint32_t const c_zero = 0;
-	current.startNonce = startNonce;
 
 	if (current.height != height || current.epoch != epoch) {
 
 		uint64_t period_seed = height / PROGPOW_PERIOD;
 
-		if (current.epoch != epoch || old_period_seed != period_seed){
+		bool rebuild = current.epoch != epoch && !takePrefetched(epoch);
+		if (rebuild)
+			discardPrefetched();
+
+		if (rebuild || old_period_seed != period_seed){
 			// initialize dag for the epoch
-			init(epoch, height);
+			if (!init(epoch, height) && cancelled())
+				return false;
 			old_period_seed = period_seed;
 		}
 
 		current.height = height;
-		current.target = target;
 		current.epoch = epoch;
 
 		// clean the return buffer (g_output)
 		m_queue.enqueueWriteBuffer(m_searchBuffer, CL_FALSE, 0, sizeof(c_zero), &c_zero);
 
 		m_searchKernel.setArg(0, m_searchBuffer);
+	}
+	return true;
+}
 
-		//set difficulty to kernel
-		m_searchKernel.setArg(4, target);
+bool CLMiner::prefetch(uint64_t height, int epoch)
+{
+	// Nothing to overlap with before the first epoch is built.
+	if (current.epoch == -1 || epoch == current.epoch || cancelled())
+		return false;
+	// Building or already resident.
+	if (m_next && m_next_epoch == epoch)
+		return true;
+	discardPrefetched();
+
+	// A second miner builds its own context, program and DAG, which takePrefetched
+	// then swaps with ours.
+	m_next.reset(new CLMiner(index));
+	m_next_epoch = epoch;
+	m_next_period = height / PROGPOW_PERIOD;
+	m_next_ok = false;
+	CLMiner* next = m_next.get();
+	m_prefetch = std::thread([this, next, height, epoch] { m_next_ok = next->init(epoch, height); });
+	return true;
+}
+
+bool CLMiner::takePrefetched(int epoch)
+{
+	if (!m_next || m_next_epoch != epoch)
+		return false;
+	// Still building: waiting is shorter than starting over.
+	if (m_prefetch.joinable())
+		m_prefetch.join();
+	if (!m_next_ok || cancelled())
+	{
+		discardPrefetched();
+		return false;
 	}
 
-	if (current.header != nullptr) {
-		delete current.header;
+	m_queue.finish();
+	// The old epoch becomes the standby, so switching back (a reorg across the boundary)
+	// is as quick. The next prefetch replaces it.
+	std::swap(m_context, m_next->m_context);
+	std::swap(m_queue, m_next->m_queue);
+	std::swap(m_searchKernel, m_next->m_searchKernel);
+	std::swap(m_dagKernel, m_next->m_dagKernel);
+	std::swap(m_dag, m_next->m_dag);
+	std::swap(m_light, m_next->m_light);
+	std::swap(m_header, m_next->m_header);
+	std::swap(m_searchBuffer, m_next->m_searchBuffer);
+	std::swap(m_globalWorkSize, m_next->m_globalWorkSize);
+	std::swap(m_workgroupSize, m_next->m_workgroupSize);
+	std::swap(m_next_period, old_period_seed);
+	m_next_epoch = current.epoch;
+	return true;
+}
+
+void CLMiner::discardPrefetched()
+{
+	if (m_prefetch.joinable())
+	{
+		m_next->cancel(true);
+		m_prefetch.join();
 	}
+	m_next.reset();
+	m_next_epoch = -1;
+}
 
-	current.header = new h256 { (const uint8_t*)header, h256::ConstructFromPointer };
+bool CLMiner::hash_one(const void* header, uint64_t height, int epoch, uint64_t nonce, void* mix)
+{
+	try
+	{
+		uint32_t const c_zero = 0;
+		uint32_t results[c_searchBufferWords];
 
-	// Update header constant buffer.
-	m_queue.enqueueWriteBuffer(m_header, CL_FALSE, 0, current.header->size, current.header->data());
+		if (!prepare(height, epoch))
+			return false;
 
-	// set start nonce
-	m_searchKernel.setArg(3, startNonce);
+		// Drops a solution still pending from compute.
+		m_queue.enqueueWriteBuffer(m_searchBuffer, CL_FALSE, 0, sizeof(c_zero), &c_zero);
+		m_queue.enqueueWriteBuffer(m_header, CL_FALSE, 0, 32, header);
+
+		// One work-group, so the nonce's lanes are all present, and only gid 0 reports.
+		m_searchKernel.setArg(3, nonce);
+		uint8_t const any[32] = {0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+			0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+			0xff, 0xff, 0xff, 0xff, 0xff, 0xff};
+		m_searchKernel.setArg(4, targetArg(any));
+		m_searchKernel.setArg(6, 1u);
+		m_queue.enqueueNDRangeKernel(m_searchKernel, cl::NullRange, m_workgroupSize, m_workgroupSize);
+		m_queue.enqueueReadBuffer(m_searchBuffer, CL_TRUE, 0, sizeof(results), &results);
+
+		m_searchKernel.setArg(4, targetArg(current.target.data()));
+		m_queue.enqueueWriteBuffer(m_searchBuffer, CL_FALSE, 0, sizeof(c_zero), &c_zero);
+		if (current.header != nullptr)
+			m_queue.enqueueWriteBuffer(m_header, CL_FALSE, 0, current.header->size, current.header->data());
 
-	// run search kernel
-	m_queue.enqueueNDRangeKernel(m_searchKernel, cl::NullRange, m_globalWorkSize, m_workgroupSize);
+		if (results[0] == 0)
+			return false;
+		memcpy(mix, results + 2, sizeof(uint32_t) * 8);
+		return true;
+	}
+	catch (cl::Error const& err)
+	{
+		checkLost(err);
+		return false;
+	}
+}
 
-	m_queue.finish();
+void CLMiner::wait()
+{
+	try
+	{
+		if (current.epoch == -1)
+			return;
+		m_queue.finish();
+	}
+	catch (cl::Error const& err)
+	{
+		checkLost(err);
+	}
 }
 
-bool CLMiner::get_solutions(void* data)
+void CLMiner::stop()
 {
-	uint32_t const c_zero = 0;
-	uint32_t results[c_maxSearchResults + 9];
+	if (current.epoch == -1)
+		return;
+	wait();
+	discardPrefetched();
+	m_dag = cl::Buffer();
+	// Force init on the next compute.
+	current.epoch = -1;
+	current.height = 0;
+}
 
-	m_queue.enqueueReadBuffer(m_searchBuffer, CL_TRUE, 0, sizeof(results), &results);
+uint32_t CLMiner::get_solutions(void* data, uint32_t max)
+{
+	try
+	{
+		uint32_t const c_zero = 0;
+		uint32_t results[c_searchBufferWords];
+
+		m_queue.enqueueReadBuffer(m_searchBuffer, CL_TRUE, 0, sizeof(results), &results);
+
+		// The count keeps growing past the slots the kernel could fill.
+		uint32_t found = std::min<uint32_t>(results[0], c_maxSearchResults);
+		uint32_t count = std::min(found, max);
+		for (uint32_t i = 0; i < count; i++) {
+			uint32_t const* result = results + 1 + i * 9;
+			uint64_t nonce = current.startNonce + result[0];
+			uint8_t* out = (uint8_t*)data + i * (sizeof(uint64_t) + sizeof(uint32_t) * 8);
+			memcpy(out, &nonce, sizeof(uint64_t));
+			memcpy(out + sizeof(uint64_t), result + 1, sizeof(uint32_t) * 8);
+		}
+		if (results[0] > 0)
+			m_queue.enqueueWriteBuffer(m_searchBuffer, CL_FALSE, 0, sizeof(c_zero), &c_zero);
 
-	if (results[0] > 0) {
-		uint64_t nonce = current.startNonce + results[1];
+		return count;
+	}
+	catch (cl::Error const& err)
+	{
+		checkLost(err);
+		return 0;
+	}
+}
 
-		m_queue.enqueueWriteBuffer(m_searchBuffer, CL_FALSE, 0, sizeof(c_zero), &c_zero);
+uint64_t CLMiner::total_memory()
+{
+	try
+	{
+		vector<cl::Platform> platforms = getPlatforms();
+		if (platforms.empty())
+			return 0;
+		vector<cl::Device> devices = getDevices(platforms, s_platformId);
+		if (devices.empty())
+			return 0;
 
-		memcpy((uint8_t*)data, &nonce, sizeof(uint64_t));
-		memcpy(((uint8_t*)data) + sizeof(uint64_t), results + 2, sizeof(uint32_t) * 8);
-		return true;
+		// Same device as init.
+		int idx = index % devices.size();
+		unsigned deviceId = s_devices[idx] > -1 ? s_devices[idx] : index;
+		cl_ulong result = 0;
+		devices[deviceId % devices.size()].getInfo(CL_DEVICE_GLOBAL_MEM_SIZE, &result);
+		return result;
+	}
+	catch (cl::Error const& err)
+	{
+		cwarn << ethCLErrorHelper("Querying device memory failed", err);
+		return 0;
 	}
-
-	return false;
 }
 
 unsigned CLMiner::getNumDevices()
@@ -353,6 +574,51 @@ unsigned CLMiner::getNumDevices()
 	return devices.size();
 }
 
+bool CLMiner::deviceInfo(unsigned _index, progpow_device_info* _info)
+{
+	try
+	{
+		vector<cl::Platform> platforms = getPlatforms();
+		if (platforms.empty())
+			return false;
+		vector<cl::Device> devices = getDevices(platforms, s_platformId);
+		if (_index >= devices.size())
+			return false;
+
+		memset(_info, 0, sizeof(*_info));
+		string name = devices[_index].getInfo<CL_DEVICE_NAME>();
+		strncpy(_info->name, name.c_str(), sizeof(_info->name) - 1);
+
+		// Vendor extensions the driver lacks fail and leave their fields zeroed.
+		cl_device_id device = devices[_index]();
+		clGetDeviceInfo(device, CL_DEVICE_UUID_KHR, sizeof(_info->uuid), _info->uuid, NULL);
+		cl_uint bus = 0;
+		cl_uint slot = 0;
+		if (clGetDeviceInfo(device, CL_DEVICE_PCI_BUS_ID_NV, sizeof(bus), &bus, NULL) == CL_SUCCESS)
+		{
+			_info->pci_bus = bus;
+			if (clGetDeviceInfo(device, CL_DEVICE_PCI_SLOT_ID_NV, sizeof(slot), &slot, NULL) == CL_SUCCESS)
+				_info->pci_device = slot >> 3;
+		}
+		else
+		{
+			// cl_device_topology_amd: the PCIe bus and device are bytes 21 and 22.
+			cl_char topology[24] = {0};
+			if (clGetDeviceInfo(device, CL_DEVICE_TOPOLOGY_AMD, sizeof(topology), topology, NULL) == CL_SUCCESS)
+			{
+				_info->pci_bus = (uint8_t)topology[21];
+				_info->pci_device = (uint8_t)topology[22];
+			}
+		}
+		return true;
+	}
+	catch (cl::Error const& err)
+	{
+		cwarn << ethCLErrorHelper("Querying device info failed", err);
+		return false;
+	}
+}
+
 bool CLMiner::configureGPU(
 	unsigned _localWorkSize,
 	unsigned _globalWorkSizeMultiplier,
@@ -574,10 +840,11 @@ bool CLMiner::init(int epoch, uint64_t block_number)
 		m_searchKernel.setArg(1, m_header);
 		m_searchKernel.setArg(2, m_dag);
 		m_searchKernel.setArg(5, 0);
+		m_searchKernel.setArg(6, ~0u);
 
 		// create mining buffers
 		//ETHCL_LOG("Creating mining buffer");
-		m_searchBuffer = cl::Buffer(m_context, CL_MEM_WRITE_ONLY, (c_maxSearchResults + 9) * sizeof(uint32_t));
+		m_searchBuffer = cl::Buffer(m_context, CL_MEM_WRITE_ONLY, c_searchBufferWords * sizeof(uint32_t));
 
 		uint32_t const work = (uint32_t)(dagBytes / sizeof(node));
 		uint32_t fullRuns = work / m_globalWorkSize;
@@ -589,11 +856,19 @@ bool CLMiner::init(int epoch, uint64_t block_number)
 		m_dagKernel.setArg(3, ~0u);
 
 		auto startDAG = std::chrono::steady_clock::now();
+		report_progress(PROGPOW_PROGRESS_GENERATE, 0, dagBytes);
 		for (uint32_t i = 0; i < fullRuns; i++)
 		{
+			if (cancelled())
+			{
+				m_dag = cl::Buffer();
+				return false;
+			}
 			m_dagKernel.setArg(0, i * m_globalWorkSize);
 			m_queue.enqueueNDRangeKernel(m_dagKernel, cl::NullRange, m_globalWorkSize, m_workgroupSize);
 			m_queue.finish();
+			uint64_t done = std::min<uint64_t>((uint64_t)(i + 1) * m_globalWorkSize, work);
+			report_progress(PROGPOW_PROGRESS_GENERATE, done * sizeof(node), dagBytes);
 		}
 		auto endDAG = std::chrono::steady_clock::now();
 
@@ -18,6 +18,8 @@
 #include "CL/cl2.hpp"
 #include <libethcore/work.h>
 #include <libethcore/miner.h>
+#include <memory>
+#include <thread>
 
 // macOS OpenCL fix:
 #ifndef CL_DEVICE_COMPUTE_CAPABILITY_MAJOR_NV
@@ -28,6 +30,27 @@
 #define CL_DEVICE_COMPUTE_CAPABILITY_MINOR_NV       0x4001
 #endif
 
+#ifndef CL_DEVICE_PCI_BUS_ID_NV
+#define CL_DEVICE_PCI_BUS_ID_NV                     0x4008
+#endif
+
+#ifndef CL_DEVICE_PCI_SLOT_ID_NV
+#define CL_DEVICE_PCI_SLOT_ID_NV                    0x4009
+#endif
+
+#ifndef CL_DEVICE_TOPOLOGY_AMD
+#define CL_DEVICE_TOPOLOGY_AMD                      0x4037
+#endif
+
+// Returned by NVIDIA drivers after an Xid error, when the context is unusable.
+#ifndef CL_NV_ILLEGAL_ACCESS
+#define CL_NV_ILLEGAL_ACCESS                        -9999
+#endif
+
+#ifndef CL_DEVICE_UUID_KHR
+#define CL_DEVICE_UUID_KHR                          0x106A
+#endif
+
 #define OPENCL_PLATFORM_UNKNOWN 0
 #define OPENCL_PLATFORM_NVIDIA  1
 #define OPENCL_PLATFORM_AMD     2
@@ -65,10 +88,12 @@ public:
 	static const CLKernelName c_defaultKernelName = CLKernelName::Stable;
 
 	CLMiner(unsigned _index);
+	~CLMiner();
 
 	static unsigned instances() { return s_numInstances > 0 ? s_numInstances : 1; }
 	static unsigned getNumDevices();
 	static void listDevices();
+	static bool deviceInfo(unsigned _index, progpow_device_info* _info);
 	static bool configureGPU(
 		unsigned _localWorkSize,
 		unsigned _globalWorkSizeMultiplier,
@@ -91,8 +116,17 @@ public:
 
 	bool init(int epoch, uint64_t block_number);
 
-	void compute(const void* header, uint64_t height, int epoch, uint64_t target, uint64_t startNonce);
-	bool get_solutions(void* data);
+	// Build the DAG for height/epoch if they changed. False if cancelled.
+	bool prepare(uint64_t height, int epoch);
+
+	void compute(const void* header, uint64_t height, int epoch, const void* target, uint64_t startNonce, uint32_t count);
+	uint32_t get_solutions(void* data, uint32_t max);
+	bool hash_one(const void* header, uint64_t height, int epoch, uint64_t nonce, void* mix);
+	uint64_t batch_size() const { return m_globalWorkSize; }
+	void wait();
+	void stop();
+	bool prefetch(uint64_t height, int epoch);
+	uint64_t total_memory();
 
 private:
 	cl::Context m_context;
@@ -106,6 +140,21 @@ private:
 	unsigned m_globalWorkSize = 0;
 	unsigned m_workgroupSize = 0;
 
+	// Switch to the context built by prefetch for epoch. False if there is none.
+	bool takePrefetched(int epoch);
+	// Abort a prefetch in progress and free what it built.
+	void discardPrefetched();
+	// Mark the miner lost if err means the device is gone, rethrow it otherwise. Called
+	// from a catch block.
+	void checkLost(cl::Error const& err);
+
+	/// Next epoch, built by prefetch in a context of its own
+	std::thread m_prefetch;
+	std::unique_ptr<CLMiner> m_next;
+	int m_next_epoch = -1;
+	uint64_t m_next_period = -1;
+	bool m_next_ok = false;
+
 	static unsigned s_platformId;
 	static unsigned s_numInstances;
 	static unsigned s_threadsPerHash;
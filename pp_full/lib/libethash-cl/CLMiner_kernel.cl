@@ -79,10 +79,8 @@ void keccak_f800_round(uint32_t st[25], const int r)
 
 // Keccak - implemented as a variant of SHAKE
 // The width is 800, with a bitrate of 576, a capacity of 224, and no padding
-// Only need 64 bits of output for mining
-uint64_t keccak_f800(__constant hash32_t const* g_header, uint64_t seed, hash32_t digest)
+void keccak_f800_state(__constant hash32_t const* g_header, uint64_t seed, hash32_t digest, uint32_t st[25])
 {
-    uint32_t st[25];
 	#pragma unroll
     for (int i = 0; i < 25; i++)
         st[i] = 0;
@@ -98,13 +96,35 @@ uint64_t keccak_f800(__constant hash32_t const* g_header, uint64_t seed, hash32_
     for (int r = 0; r < 22; r++) {
         keccak_f800_round(st, r);
     }
-    // last round can be simplified due to partial output
-    //keccak_f800_round(st, 21);
+}
+
+// The upper 64 bits of the hash, enough for the seed
+uint64_t keccak_f800(__constant hash32_t const* g_header, uint64_t seed, hash32_t digest)
+{
+    uint32_t st[25];
+    keccak_f800_state(g_header, seed, digest, st);
 
     uint64_t res = (uint64_t)st[1] << 32 | st[0];
     return as_ulong(as_uchar8(res).s76543210);
 }
 
+// Whether the hash is at or below the 256-bit target, given as words with the most
+// significant first
+bool keccak_f800_meets(__constant hash32_t const* g_header, uint64_t seed, hash32_t digest, uint8 target)
+{
+    uint32_t st[25];
+    keccak_f800_state(g_header, seed, digest, st);
+
+    uint32_t const words[8] = {target.s0, target.s1, target.s2, target.s3,
+                               target.s4, target.s5, target.s6, target.s7};
+    for (int i = 0; i < 8; i++) {
+        uint32_t word = as_uint(as_uchar4(st[i]).s3210);
+        if (word != words[i])
+            return word < words[i];
+    }
+    return true;
+}
+
 #define fnv1a(h, d) (h = (h ^ d) * 0x1000193)
 
 typedef struct {
@@ -154,8 +174,9 @@ __kernel void ethash_search(
     __constant hash32_t const* g_header,
     __global dag_t const* g_dag,
     ulong start_nonce,
-    ulong target,
-    uint hack_false
+    uint8 target,
+    uint hack_false,
+    uint gid_limit
 )
 {
     __local shuffle_t share[HASHES_PER_GROUP];
@@ -245,19 +266,17 @@ __kernel void ethash_search(
     }
 
     // keccak(header .. keccak(header..nonce) .. digest);
-    if (keccak_f800(g_header, seed, digest) <= target)
+    // Every work-item takes part in the barriers above, only those below gid_limit report.
+    if (gid < gid_limit && keccak_f800_meets(g_header, seed, digest, target))
     {
-		uint slot = atomic_inc(&g_output[0]) + 1;
-        if(slot < (MAX_OUTPUTS+1)){
-            g_output[slot] = gid;
-			g_output[2] = digest.uint32s[0];
-			g_output[3] = digest.uint32s[1];
-			g_output[4] = digest.uint32s[2];
-			g_output[5] = digest.uint32s[3];
-			g_output[6] = digest.uint32s[4];
-			g_output[7] = digest.uint32s[5];
-			g_output[8] = digest.uint32s[6];
-			g_output[9] = digest.uint32s[7];
+		// The count, then a gid and the 8 mix words per result.
+		uint slot = atomic_inc(&g_output[0]);
+        if(slot < MAX_OUTPUTS){
+            __global volatile uint* result = g_output + 1 + slot * 9;
+            result[0] = gid;
+            #pragma unroll
+            for (int i = 0; i < 8; i++)
+                result[1 + i] = digest.uint32s[i];
 		}
     }
 }
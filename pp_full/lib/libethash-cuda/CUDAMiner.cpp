@@ -20,6 +20,7 @@ along with cpp-ethereum.  If not, see <http://www.gnu.org/licenses/>.
 
 #include <libethash/internal.h>
 #include "CUDAMiner.h"
+#include <algorithm>
 #include "CUDAMiner_kernel.h"
 #include <nvrtc.h>
 
@@ -29,6 +30,9 @@ using namespace eth;
 
 #define MAX_MINERS 4
 
+// Device memory a split DAG leaves free besides the light cache.
+static const uint64_t c_splitDagReserve = 256ull << 20;
+
 unsigned CUDAMiner::s_numInstances = 0;
 
 vector<int> CUDAMiner::s_devices(MAX_MINERS, -1);
@@ -52,7 +56,7 @@ struct CUDASwitchChannel: public LogChannel
 
 CUDAMiner::CUDAMiner(unsigned _index) :
 	//m_light(getNumDevices()),
-	current(0,0,-1,0)
+	current(0,0,-1)
 {
 	index = _index;
 	m_light = new std::vector<hash64_t*>(getNumDevices());
@@ -63,6 +67,11 @@ CUDAMiner::CUDAMiner(unsigned _index) :
 
 CUDAMiner::~CUDAMiner()
 {
+	if (m_prefetch.joinable())
+	{
+		m_prefetch_abort.store(true);
+		m_prefetch.join();
+	}
 	kick_miner();
 }
 
@@ -86,8 +95,10 @@ bool CUDAMiner::init(int epoch)
 		light = EthashAux::light(epoch);
 		bytesConstRef lightData = light->data();
 
-		cuda_init(getNumDevices(), light->light, lightData.data(), lightData.size(),
-			device, (s_dagLoadMode == DAG_LOAD_MODE_SINGLE), s_dagInHostMemory, s_dagCreateDevice);
+		if (!cuda_init(getNumDevices(), light->light, lightData.data(), lightData.size(),
+			device, (s_dagLoadMode == DAG_LOAD_MODE_SINGLE), s_dagInHostMemory, s_dagCreateDevice)
+			&& cancelled())
+			return false;
 
 		/*s_dagLoadIndex++;
 
@@ -110,42 +121,82 @@ bool CUDAMiner::init(int epoch)
 	}
 }
 
-void CUDAMiner::compute(const void* header, uint64_t height, int epoch, uint64_t target, uint64_t startNonce)
+bool CUDAMiner::dagProgress(void* self, uint64_t done, uint64_t total)
 {
-	try
-	{
-		current.startNonce = startNonce;
+	CUDAMiner* miner = (CUDAMiner*)self;
+	miner->report_progress(PROGPOW_PROGRESS_GENERATE, done, total);
+	return !miner->cancelled();
+}
 
-		if (current.height != height || current.epoch != epoch)
-		{
-			current.height = height;
-			current.target = target;
+bool CUDAMiner::prefetchProgress(void* self, uint64_t done, uint64_t total)
+{
+	CUDAMiner* miner = (CUDAMiner*)self;
+	return dagProgress(self, done, total) && !miner->m_prefetch_abort.load();
+}
 
-			uint64_t period_seed = height / PROGPOW_PERIOD;
+void CUDAMiner::freeDag()
+{
+	if (m_dag)
+		CUDA_SAFE_CALL(cudaFree(m_dag));
+	m_dag = nullptr;
+	m_dag_elms = -1;
+	m_dag_host_bytes = 0;
+}
 
-			if (current.epoch != epoch || old_period_seed != period_seed){
-				if(!init(epoch))
-					exit(1);
+void CUDAMiner::allocDag(hash64_t** dag, uint64_t dagBytes, uint64_t hostBytes)
+{
+	m_dag_host_bytes = hostBytes;
+	if (hostBytes == 0)
+	{
+		CUDA_SAFE_CALL(cudaMalloc(reinterpret_cast<void**>(dag), dagBytes));
+		return;
+	}
 
-				old_period_seed = period_seed;
+	// Managed memory: the head of the DAG is placed on the device, the tail stays in host
+	// memory and is mapped for the device, so kernels read it over the bus instead of
+	// migrating pages back and forth. Every DAG access that lands in the tail is slower.
+	cudalog << "Keeping " << hostBytes << " of " << dagBytes << " DAG bytes in host memory";
+	CUDA_SAFE_CALL(cudaMallocManaged(reinterpret_cast<void**>(dag), dagBytes));
+	uint8_t* head = reinterpret_cast<uint8_t*>(*dag);
+	uint64_t deviceBytes = dagBytes - hostBytes;
+	if (deviceBytes > 0)
+	{
+		CUDA_SAFE_CALL(cudaMemAdvise(head, deviceBytes, cudaMemAdviseSetPreferredLocation, m_device_num));
+		CUDA_SAFE_CALL(cudaMemPrefetchAsync(head, deviceBytes, m_device_num, 0));
+	}
+	CUDA_SAFE_CALL(cudaMemAdvise(head + deviceBytes, hostBytes, cudaMemAdviseSetPreferredLocation, cudaCpuDeviceId));
+	CUDA_SAFE_CALL(cudaMemAdvise(head + deviceBytes, hostBytes, cudaMemAdviseSetAccessedBy, m_device_num));
+}
 
-				uint64_t dagBytes = ethash_get_datasize(height);
-				uint32_t dagElms  = (unsigned)(dagBytes / (PROGPOW_LANES * PROGPOW_DAG_LOADS * 4));
-				compileKernel(height, dagElms);
-			}
+void CUDAMiner::makeCurrent()
+{
+	// The miner may be driven from a different thread than the one that created it.
+	if (m_context)
+		CU_SAFE_CALL(cuCtxSetCurrent(m_context));
+}
 
-			current.epoch = epoch;
-		}
+void CUDAMiner::compute(const void* header, uint64_t height, int epoch, const void* target, uint64_t startNonce, uint32_t count)
+{
+	try
+	{
+		makeCurrent();
+		current.startNonce = startNonce;
+
+		if (!prepare(height, epoch))
+			return;
 
 		current.header = new h256 { (const uint8_t*)header, h256::ConstructFromPointer };
+		current.target = h256 { (const uint8_t*)target, h256::ConstructFromPointer };
 
-		search(current.header->data(), target, false, startNonce);
+		hash32_t words;
+		target_words(target, reinterpret_cast<uint32_t*>(&words));
+		search(current.header->data(), words, startNonce, count);
 	}
 	catch (cuda_runtime_error const& _e)
 	{
-		cwarn << "Fatal GPU error: " << _e.what();
-		//cwarn << "Terminating.";
-		exit(-1);
+		// A failed launch or sync leaves the context unusable, as when the device is gone.
+		cwarn << "GPU lost: " << _e.what();
+		mark_lost();
 	}
 	catch (std::runtime_error const& _e)
 	{
@@ -154,17 +205,267 @@ void CUDAMiner::compute(const void* header, uint64_t height, int epoch, uint64_t
 	}
 }
 
-bool CUDAMiner::get_solutions(void* data) {
+bool CUDAMiner::prepare(uint64_t height, int epoch)
+{
+	if (current.height != height || current.epoch != epoch)
+	{
+		current.height = height;
+
+		uint64_t period_seed = height / PROGPOW_PERIOD;
+
+		if (current.epoch != epoch && takePrefetched(epoch, period_seed))
+		{
+			old_period_seed = period_seed;
+		}
+		else if (current.epoch != epoch || old_period_seed != period_seed){
+			// init resets the device when the DAG size changes.
+			if (current.epoch != epoch)
+				discardPrefetched();
+			if(!init(epoch))
+			{
+				if (cancelled())
+				{
+					// Redo the whole epoch setup on the next compute.
+					current.epoch = -1;
+					current.height = 0;
+					return false;
+				}
+				exit(1);
+			}
+
+			old_period_seed = period_seed;
+
+			uint64_t dagBytes = ethash_get_datasize(height);
+			uint32_t dagElms  = (unsigned)(dagBytes / (PROGPOW_LANES * PROGPOW_DAG_LOADS * 4));
+			compileKernel(height, dagElms, m_module, m_kernel);
+		}
+
+		current.epoch = epoch;
+	}
+	return true;
+}
 
-	if (solution != nullptr){
-		memcpy((uint8_t*)data, &solution->nonce, sizeof(uint64_t));
-		memcpy(((uint8_t*)data) + sizeof(uint64_t), (const void*)solution->mixHash.data(), sizeof(uint32_t) * 8);
-		free(solution);
-		solution = nullptr;
+bool CUDAMiner::prefetch(uint64_t height, int epoch)
+{
+	// Nothing to overlap with before the first epoch is built.
+	if (current.epoch == -1 || epoch == current.epoch || cancelled())
+		return false;
+	// A DAG split across host memory leaves no room for a second one.
+	if (m_dag_host_bytes > 0)
+		return false;
+	// Building or already resident.
+	if (m_next_epoch == epoch)
 		return true;
+	discardPrefetched();
+
+	m_next_epoch = epoch;
+	m_next_period = height / PROGPOW_PERIOD;
+	m_prefetch_abort.store(false);
+	m_prefetch = std::thread(&CUDAMiner::prefetchDag, this, height, epoch);
+	return true;
+}
+
+void CUDAMiner::prefetchDag(uint64_t height, int epoch)
+{
+	hash64_t* light = nullptr;
+	hash64_t* dag = nullptr;
+	cudaStream_t stream = nullptr;
+	try
+	{
+		makeCurrent();
+		EthashAux::LightType lightCache = EthashAux::light(epoch);
+		bytesConstRef lightData = lightCache->data();
+		uint64_t dagBytes = ethash_get_datasize(height);
+		uint32_t dagElms = (unsigned)(dagBytes / (PROGPOW_LANES * PROGPOW_DAG_LOADS * 4));
+
+		// Both DAGs must fit until the switch, otherwise the switch rebuilds in place.
+		size_t freeMem, totalMem;
+		CUDA_SAFE_CALL(cudaMemGetInfo(&freeMem, &totalMem));
+		if (freeMem < dagBytes + lightData.size())
+		{
+			cudalog << "Not enough free GPU memory to prefetch the DAG of epoch " << epoch;
+			return;
+		}
+
+		CUDA_SAFE_CALL(cudaStreamCreate(&stream));
+		CUDA_SAFE_CALL(cudaMalloc(reinterpret_cast<void**>(&light), lightData.size()));
+		CUDA_SAFE_CALL(cudaMalloc(reinterpret_cast<void**>(&dag), dagBytes));
+		CUDA_SAFE_CALL(cudaMemcpyAsync(light, lightData.data(), lightData.size(), cudaMemcpyHostToDevice, stream));
+		uint32_t lightWords = (unsigned)(lightData.size() / sizeof(node));
+		bool done = ethash_generate_dag(dag, dagBytes, light, lightWords, s_gridSize, s_blockSize, stream, m_device_num, &CUDAMiner::prefetchProgress, this);
+		CUDA_SAFE_CALL(cudaFree(light));
+		light = nullptr;
+		CUDA_SAFE_CALL(cudaStreamDestroy(stream));
+		stream = nullptr;
+		if (!done)
+		{
+			CUDA_SAFE_CALL(cudaFree(dag));
+			return;
+		}
+
+		compileKernel(height, dagElms, m_next_module, m_next_kernel);
+		m_next_dag = dag;
+		m_next_dag_elms = dagElms;
+	}
+	catch (std::runtime_error const& _e)
+	{
+		cwarn << "DAG prefetch failed: " << _e.what();
+		if (light)
+			cudaFree(light);
+		if (dag)
+			cudaFree(dag);
+		if (stream)
+			cudaStreamDestroy(stream);
+	}
+}
+
+bool CUDAMiner::takePrefetched(int epoch, uint64_t period_seed)
+{
+	if (m_next_epoch != epoch)
+		return false;
+	// Still building: waiting is shorter than starting over.
+	if (m_prefetch.joinable())
+		m_prefetch.join();
+	if (!m_next_dag || cancelled())
+	{
+		discardPrefetched();
+		return false;
+	}
+
+	// Kernels in flight still read the old DAG.
+	CUDA_SAFE_CALL(cudaDeviceSynchronize());
+
+	// The old epoch becomes the standby, so switching back (a reorg across the boundary)
+	// is as quick. The next prefetch replaces it.
+	uint64_t next_period = m_next_period;
+	std::swap(m_dag, m_next_dag);
+	std::swap(m_dag_elms, m_next_dag_elms);
+	std::swap(m_module, m_next_module);
+	std::swap(m_kernel, m_next_kernel);
+	m_next_epoch = current.epoch;
+	m_next_period = old_period_seed;
+
+	if (next_period != period_seed)
+	{
+		CU_SAFE_CALL(cuModuleUnload(m_module));
+		compileKernel(current.height, m_dag_elms, m_module, m_kernel);
 	}
 
-	return false;
+	// Results of the old epoch are dropped with its header.
+	memset(&m_current_header, 0, sizeof(hash32_t));
+	return true;
+}
+
+void CUDAMiner::discardPrefetched()
+{
+	if (m_prefetch.joinable())
+	{
+		m_prefetch_abort.store(true);
+		m_prefetch.join();
+	}
+	makeCurrent();
+	if (m_next_dag)
+		CUDA_SAFE_CALL(cudaFree(m_next_dag));
+	if (m_next_module)
+		CU_SAFE_CALL(cuModuleUnload(m_next_module));
+	m_next_dag = nullptr;
+	m_next_module = nullptr;
+	m_next_epoch = -1;
+}
+
+bool CUDAMiner::hash_one(const void* header, uint64_t height, int epoch, uint64_t nonce, void* mix)
+{
+	try
+	{
+		makeCurrent();
+		if (!prepare(height, epoch))
+			return false;
+
+		if (m_hash_one_buf == nullptr)
+			CUDA_SAFE_CALL(cudaMallocHost(&m_hash_one_buf, sizeof(search_results)));
+		m_hash_one_buf->count = 0;
+
+		// One block, so the nonce's lanes are all present, and only gid 0 reports.
+		hash32_t hdr = *reinterpret_cast<hash32_t const *>(header);
+		hash32_t target;
+		memset(&target, 0xff, sizeof(target));
+		bool hack_false = false;
+		uint32_t gid_limit = 1;
+		void *args[] = {&nonce, &hdr, &target, &m_dag, &m_hash_one_buf, &hack_false, &gid_limit};
+		cudaStream_t stream = m_streams[0];
+		CU_SAFE_CALL(cuLaunchKernel(m_kernel, 1, 1, 1, s_blockSize, 1, 1, 0, stream, args, 0));
+		CUDA_SAFE_CALL(cudaStreamSynchronize(stream));
+
+		if (m_hash_one_buf->count == 0)
+			return false;
+		memcpy(mix, (void *)&m_hash_one_buf->result[0].mix, sizeof(m_hash_one_buf->result[0].mix));
+		return true;
+	}
+	catch (cuda_runtime_error const& _e)
+	{
+		cwarn << "GPU lost: " << _e.what();
+		mark_lost();
+		return false;
+	}
+}
+
+uint32_t CUDAMiner::get_solutions(void* data, uint32_t max) {
+	uint32_t count = std::min<size_t>(max, m_solutions.size());
+	for (uint32_t i = 0; i < count; i++) {
+		uint8_t* out = (uint8_t*)data + i * (sizeof(uint64_t) + sizeof(uint32_t) * 8);
+		memcpy(out, &m_solutions[i].nonce, sizeof(uint64_t));
+		memcpy(out + sizeof(uint64_t), (const void*)m_solutions[i].mixHash.data(), sizeof(uint32_t) * 8);
+	}
+	m_solutions.erase(m_solutions.begin(), m_solutions.begin() + count);
+	return count;
+}
+
+void CUDAMiner::wait()
+{
+	if (current.epoch == -1)
+		return;
+	try
+	{
+		makeCurrent();
+		CUDA_SAFE_CALL(cudaDeviceSynchronize());
+	}
+	catch (cuda_runtime_error const& _e)
+	{
+		cwarn << "GPU lost: " << _e.what();
+		mark_lost();
+	}
+}
+
+void CUDAMiner::stop()
+{
+	if (current.epoch == -1)
+		return;
+	wait();
+	discardPrefetched();
+	freeDag();
+	// Force init on the next compute.
+	current.epoch = -1;
+	current.height = 0;
+}
+
+uint64_t CUDAMiner::total_memory()
+{
+	try
+	{
+		int numDevices = getNumDevices();
+		if (numDevices == 0)
+			return 0;
+		int d = s_devices[index];
+		int device = min(d > -1 ? d : (int)index, numDevices - 1);
+		cudaDeviceProp props;
+		CUDA_SAFE_CALL(cudaGetDeviceProperties(&props, device));
+		return props.totalGlobalMem;
+	}
+	catch (std::runtime_error const& _e)
+	{
+		cwarn << "Error CUDA mining: " << _e.what();
+		return 0;
+	}
 }
 
 void CUDAMiner::kick_miner()
@@ -213,6 +514,23 @@ unsigned CUDAMiner::getNumDevices()
 	//throw std::runtime_error{cudaGetErrorString(err)};*/
 }
 
+bool CUDAMiner::deviceInfo(unsigned _index, progpow_device_info* _info)
+{
+	cudaDeviceProp props;
+	if (cudaGetDeviceProperties(&props, _index) != cudaSuccess)
+		return false;
+
+	memset(_info, 0, sizeof(*_info));
+#if CUDART_VERSION >= 10000
+	memcpy(_info->uuid, props.uuid.bytes, sizeof(_info->uuid));
+#endif
+	_info->pci_domain = props.pciDomainID;
+	_info->pci_bus = props.pciBusID;
+	_info->pci_device = props.pciDeviceID;
+	strncpy(_info->name, props.name, sizeof(_info->name) - 1);
+	return true;
+}
+
 void CUDAMiner::listDevices()
 {
 	try
@@ -382,22 +700,25 @@ bool CUDAMiner::cuda_init(
 		//cudalog << "Set Device to current";
 
 		hash64_t** data = m_light->data();
+		uint64_t hostBytes = 0;
 
 		if(dagElms != m_dag_elms || !m_dag)
 		{
 			//Check whether the current device has sufficient memory every time we recreate the dag
-			if (device_props.totalGlobalMem < dagBytes)
+			uint64_t shortfall = dagBytes + _lightBytes > device_props.totalGlobalMem ? dagBytes + _lightBytes - device_props.totalGlobalMem : 0;
+			if (shortfall > 0 && shortfall > host_dag_limit())
 			{
 				cudalog <<  "CUDA device " << string(device_props.name) << " has insufficient GPU memory." << device_props.totalGlobalMem << " bytes of memory found < " << dagBytes << " bytes of memory required";
 				return false;
 			}
+			// Leave room for the context, the kernel and the search buffers as well.
+			hostBytes = shortfall > 0 ? std::min(shortfall + c_splitDagReserve, dagBytes) : 0;
 			//We need to reset the device and recreate the dag
 			cudalog << "Resetting device";
 			CUDA_SAFE_CALL(cudaDeviceReset());
 			CUdevice device;
-			CUcontext context;
 			cuDeviceGet(&device, m_device_num);
-			cuCtxCreate(&context, nullptr, s_scheduleFlag, device);
+			cuCtxCreate(&m_context, nullptr, s_scheduleFlag, device);
 			//We need to reset the light and the Dag for the following code to reallocate
 			//since cudaDeviceReset() frees all previous allocated memory
 			*(data + m_device_num) = nullptr;
@@ -417,7 +738,7 @@ bool CUDAMiner::cuda_init(
 		*(data + m_device_num) = light;
 
 		if(dagElms != m_dag_elms || !dag) // create buffer for dag
-			CUDA_SAFE_CALL(cudaMalloc(reinterpret_cast<void**>(&dag), dagBytes));
+			allocDag(&dag, dagBytes, hostBytes);
 
 		if(dagElms != m_dag_elms || !dag)
 		{
@@ -430,16 +751,21 @@ bool CUDAMiner::cuda_init(
 			}
 
 			memset(&m_current_header, 0, sizeof(hash32_t));
-			m_current_target = 0;
-			m_current_nonce = 0;
+			memset(&m_current_target, 0, sizeof(hash32_t));
 			m_current_index = 0;
+			m_stream_nonce.assign(s_numStreams, 0);
 
 			if (!hostDAG)
 			{
 				if((m_device_num == dagCreateDevice) || !_cpyToHost){ //if !cpyToHost -> All devices shall generate their DAG
 					/*cudalog << "Generating DAG for GPU #" << m_device_num <<
 							   " with dagBytes: " << dagBytes <<" gridSize: " << s_gridSize;*/
-					ethash_generate_dag(dag, dagBytes, light, lightWords, s_gridSize, s_blockSize, m_streams[0], m_device_num);
+					if (!ethash_generate_dag(dag, dagBytes, light, lightWords, s_gridSize, s_blockSize, m_streams[0], m_device_num, &CUDAMiner::dagProgress, this))
+					{
+						m_dag = dag;
+						freeDag();
+						return false;
+					}
 					//cudalog << "Finished DAG";
 
 					if (_cpyToHost)
@@ -452,7 +778,15 @@ bool CUDAMiner::cuda_init(
 					}
 				}else{
 					while(!hostDAG)
+					{
+						if (cancelled())
+						{
+							m_dag = dag;
+							freeDag();
+							return false;
+						}
 						this_thread::sleep_for(chrono::milliseconds(100));
+					}
 					goto cpyDag;
 				}
 			}
@@ -460,8 +794,21 @@ bool CUDAMiner::cuda_init(
 			{
 cpyDag:
 				cudalog << "Copying DAG from host to GPU #" << m_device_num;
-				const void* hdag = (const void*)hostDAG;
-				CUDA_SAFE_CALL(cudaMemcpy(reinterpret_cast<void*>(dag), hdag, dagBytes, cudaMemcpyHostToDevice));
+				// Upload in chunks so progress can be reported.
+				const uint64_t chunk = 64ull << 20;
+				report_progress(PROGPOW_PROGRESS_UPLOAD, 0, dagBytes);
+				for (uint64_t offset = 0; offset < dagBytes; offset += chunk)
+				{
+					if (cancelled())
+					{
+						m_dag = dag;
+						freeDag();
+						return false;
+					}
+					uint64_t n = std::min(chunk, dagBytes - offset);
+					CUDA_SAFE_CALL(cudaMemcpy(reinterpret_cast<uint8_t*>(dag) + offset, hostDAG + offset, n, cudaMemcpyHostToDevice));
+					report_progress(PROGPOW_PROGRESS_UPLOAD, offset + n, dagBytes);
+				}
 			}
 		}
 
@@ -489,7 +836,9 @@ cpyDag:
 
 void CUDAMiner::compileKernel(
 	uint64_t block_number,
-	uint64_t dag_elms)
+	uint64_t dag_elms,
+	CUmodule& module,
+	CUfunction& kernel)
 {
 	const char* name = "progpow_search";
 
@@ -561,7 +910,7 @@ void CUDAMiner::compileKernel(
 		(void*)(1),
 		(void*)(1)
 	};
-	CU_SAFE_CALL(cuModuleLoadDataEx(&m_module, ptx, 6, jitOpt, jitOptVal));
+	CU_SAFE_CALL(cuModuleLoadDataEx(&module, ptx, 6, jitOpt, jitOptVal));
 	cudalog << "JIT info: \n" << jitInfo;
 	cudalog << "JIT err: \n" << jitErr;
 	delete[] ptx;
@@ -571,7 +920,7 @@ void CUDAMiner::compileKernel(
 	const char* mangledName;
 	NVRTC_SAFE_CALL(nvrtcGetLoweredName(prog, name, &mangledName));
 	//cudalog << "Mangled name: " << mangledName;
-	CU_SAFE_CALL(cuModuleGetFunction(&m_kernel, m_module, mangledName));
+	CU_SAFE_CALL(cuModuleGetFunction(&kernel, module, mangledName));
 	//cudalog << "done compiling";
 	// Destroy the program.
 	NVRTC_SAFE_CALL(nvrtcDestroyProgram(&prog));
@@ -579,9 +928,9 @@ void CUDAMiner::compileKernel(
 
 void CUDAMiner::search(
 	uint8_t const* header,
-	uint64_t target,
-	bool _ethStratum,
-	uint64_t _startN)
+	hash32_t const& target,
+	uint64_t startNonce,
+	uint32_t count)
 {
 	bool initialize = false;
 	if (memcmp(&m_current_header, header, sizeof(hash32_t)))
@@ -589,50 +938,28 @@ void CUDAMiner::search(
 		m_current_header = *reinterpret_cast<hash32_t const *>(header);
 		initialize = true;
 	}
-	if (m_current_target != target)
+	if (memcmp(&m_current_target, &target, sizeof(hash32_t)))
 	{
 		m_current_target = target;
 		initialize = true;
 	}
-	if (_ethStratum)
+	if (initialize)
 	{
-		if (initialize)
-		{
-			m_starting_nonce = 0;
-			m_current_index = 0;
-			CUDA_SAFE_CALL(cudaDeviceSynchronize());
-			for (unsigned int i = 0; i < s_numStreams; i++)
-				m_search_buf[i]->count = 0;
-		}
-		if (m_starting_nonce != _startN)
-		{
-			// reset nonce counter
-			m_starting_nonce = _startN;
-			m_current_nonce = m_starting_nonce;
-		}
+		m_current_index = 0;
+		CUDA_SAFE_CALL(cudaDeviceSynchronize());
+		for (unsigned int i = 0; i < s_numStreams; i++)
+			m_search_buf[i]->count = 0;
 	}
-	else
-	{
-		if (initialize)
-		{
-			m_current_nonce = 0;//get_start_nonce();
-			m_current_index = 0;
-			CUDA_SAFE_CALL(cudaDeviceSynchronize());
-			for (unsigned int i = 0; i < s_numStreams; i++)
-				m_search_buf[i]->count = 0;
-		}
-	}
-	const uint32_t batch_size = s_gridSize * s_blockSize;
 
 	m_current_index++;
-	m_current_nonce += batch_size;
 	auto stream_index = m_current_index % s_numStreams;
 	cudaStream_t stream = m_streams[stream_index];
 	volatile search_results* buffer = m_search_buf[stream_index];
 	uint32_t found_count = 0;
 	uint64_t nonces[SEARCH_RESULTS];
 	h256 mixes[SEARCH_RESULTS];
-	uint64_t nonce_base = m_current_nonce - s_numStreams * batch_size;
+	// The stream's previous launch, whose results are read before it is reused.
+	uint64_t nonce_base = m_stream_nonce[stream_index];
 	if (m_current_index >= s_numStreams)
 	{
 		CUDA_SAFE_CALL(cudaStreamSynchronize(stream));
@@ -648,7 +975,9 @@ void CUDAMiner::search(
 		}
 	}
 	bool hack_false = false;
-	void *args[] = {&m_current_nonce, &m_current_header, &m_current_target, &m_dag, &buffer, &hack_false};
+	uint32_t gid_limit = count;
+	m_stream_nonce[stream_index] = startNonce;
+	void *args[] = {&startNonce, &m_current_header, &m_current_target, &m_dag, &buffer, &hack_false, &gid_limit};
 	CU_SAFE_CALL(cuLaunchKernel(m_kernel,
 		s_gridSize, 1, 1,   // grid dim
 		s_blockSize, 1, 1,  // block dim
@@ -656,17 +985,8 @@ void CUDAMiner::search(
 		stream,				// stream
 		args, 0));          // arguments
 
-	//printf("cuda index: %lu", m_current_nonce);
-	if (m_current_index >= s_numStreams)
-	{
-		if (found_count)
-		{
-			for (uint32_t i = 0; i < found_count; i++) {
-				solution = new Solution(nonces[i], mixes[i]);
-				break;
-			}
-		}
-	}
+	for (uint32_t i = 0; i < found_count; i++)
+		m_solutions.push_back(Solution(nonces[i], mixes[i]));
 }
 
 
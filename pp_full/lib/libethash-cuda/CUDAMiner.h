@@ -22,6 +22,7 @@ along with cpp-ethereum.  If not, see <http://www.gnu.org/licenses/>.
 #include <time.h>
 #include <fstream>
 #include <functional>
+#include <thread>
 #include <libethash/ethash.h>
 #include <libethcore/EthashAux.h>
 #include <libdevcore/Worker.h>
@@ -69,6 +70,7 @@ public:
 	}
 	static unsigned getNumDevices();
 	static void listDevices();
+	static bool deviceInfo(unsigned _index, progpow_device_info* _info);
 	static void setParallelHash(unsigned _parallelHash);
 	static bool configureGPU(
 		unsigned _blockSize,
@@ -108,9 +110,9 @@ public:
 
 	void search(
 		uint8_t const* header,
-		uint64_t target,
-		bool _ethStratum,
-		uint64_t _startN);
+		hash32_t const& target,
+		uint64_t startNonce,
+		uint32_t count);
 
 	/* -- default values -- */
 	/// Default value of the block size. Also known as workgroup size.
@@ -123,8 +125,15 @@ public:
 	static unsigned s_dagCreateDevice;
 	static unsigned s_dagLoadMode;
 
-	void compute(const void* header, uint64_t height, int epoch, uint64_t target, uint64_t startNonce) override;
-	bool get_solutions(void* data) override;
+	void compute(const void* header, uint64_t height, int epoch, const void* target, uint64_t startNonce, uint32_t count) override;
+	uint32_t get_solutions(void* data, uint32_t max) override;
+	bool hash_one(const void* header, uint64_t height, int epoch, uint64_t nonce, void* mix) override;
+	uint64_t batch_size() const override { return (uint64_t)s_gridSize * s_blockSize; }
+	void wait() override;
+	void stop() override;
+	bool prefetch(uint64_t height, int epoch) override;
+	uint64_t total_memory() override;
+	uint64_t host_dag_bytes() override { return m_dag_host_bytes; }
 
 	static uint8_t* s_dagInHostMemory;
 
@@ -136,27 +145,64 @@ private:
 
 	bool init(int epoch);
 
+	// Build the DAG and kernel for height/epoch if they changed. False if cancelled.
+	bool prepare(uint64_t height, int epoch);
+
+	// Runs on m_prefetch: build the DAG and kernel of epoch into the m_next_ members.
+	void prefetchDag(uint64_t height, int epoch);
+
+	// Switch to the DAG built by prefetch for epoch. False if there is none.
+	bool takePrefetched(int epoch, uint64_t period_seed);
+
+	// Wait for a prefetch in progress and free what it built.
+	void discardPrefetched();
+
+	static bool dagProgress(void* self, uint64_t done, uint64_t total);
+	static bool prefetchProgress(void* self, uint64_t done, uint64_t total);
+
+	void freeDag();
+
+	// Allocate the DAG, keeping its last hostBytes in host memory if non-zero.
+	void allocDag(hash64_t** dag, uint64_t dagBytes, uint64_t hostBytes);
+
+	void makeCurrent();
+
 	uint32_t index;
 
 	hash32_t m_current_header;
-	uint64_t m_current_target;
-	uint64_t m_current_nonce;
-	uint64_t m_starting_nonce;
+	hash32_t m_current_target;
 	uint64_t m_current_index;
+	// The start nonce each stream was last launched with, to turn its results' gids
+	// back into nonces.
+	std::vector<uint64_t> m_stream_nonce;
 
 	///Constants on GPU
 	hash64_t* m_dag = nullptr;
 	std::vector<hash64_t*> *m_light;
 	uint32_t m_dag_elms = -1;
+	uint64_t m_dag_host_bytes = 0;
 	uint32_t m_device_num;
+	CUcontext m_context = nullptr;
 
-	Solution* solution = nullptr;
+	// Found by search, oldest first, until get_solutions takes them.
+	std::vector<Solution> m_solutions;
 
 	CUmodule m_module;
 	CUfunction m_kernel;
 	volatile search_results** m_search_buf;
+	volatile search_results* m_hash_one_buf = nullptr;
 	cudaStream_t  * m_streams;
 
+	///Next epoch, built by prefetch on its own stream
+	std::thread m_prefetch;
+	int m_next_epoch = -1;
+	uint64_t m_next_period = -1;
+	hash64_t* m_next_dag = nullptr;
+	uint32_t m_next_dag_elms = 0;
+	CUmodule m_next_module = nullptr;
+	CUfunction m_next_kernel = nullptr;
+	std::atomic<bool> m_prefetch_abort{false};
+
 	/// The local work size for the search
 	static unsigned s_blockSize;
 	/// The initial global work size for the searches
@@ -173,7 +219,7 @@ private:
 
 	static bool s_noeval;
 
-	void compileKernel(uint64_t block_number, uint64_t dag_words);
+	void compileKernel(uint64_t block_number, uint64_t dag_words, CUmodule& module, CUfunction& kernel);
 
 };
 
@@ -149,7 +149,7 @@ ethash_calculate_dag_item(uint32_t start, hash64_t *g_dag, uint64_t dag_bytes, h
 	}
 }
 
-void ethash_generate_dag(
+bool ethash_generate_dag(
 	hash64_t* dag,
 	uint64_t dag_bytes,
 	hash64_t * light,
@@ -157,7 +157,9 @@ void ethash_generate_dag(
 	uint32_t blocks,
 	uint32_t threads,
 	cudaStream_t stream,
-	int device
+	int device,
+	dag_progress_fn progress,
+	void* progress_ctx
 	)
 {
 	uint64_t const work = dag_bytes / sizeof(hash64_t);
@@ -165,10 +167,20 @@ void ethash_generate_dag(
 	uint32_t fullRuns = (uint32_t)(work / (blocks * threads));
 	uint32_t const restWork = (uint32_t)(work % (blocks * threads));
 	if (restWork > 0) fullRuns++;
+	if (progress && !progress(progress_ctx, 0, dag_bytes))
+		return false;
 	for (uint32_t i = 0; i < fullRuns; i++)
 	{
 		ethash_calculate_dag_item <<<blocks, threads, 0, stream >>>(i * blocks * threads, dag, dag_bytes, light, light_words);
-		CUDA_SAFE_CALL(cudaDeviceSynchronize());
+		// Only this stream, so a prefetch runs alongside the search kernels.
+		CUDA_SAFE_CALL(cudaStreamSynchronize(stream));
+		if (progress)
+		{
+			uint64_t done = (uint64_t)(i + 1) * blocks * threads;
+			if (!progress(progress_ctx, (done < work ? done : work) * sizeof(hash64_t), dag_bytes))
+				return false;
+		}
 	}
 	CUDA_SAFE_CALL(cudaGetLastError());
+	return true;
 }
\ No newline at end of file
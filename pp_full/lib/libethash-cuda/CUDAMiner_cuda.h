@@ -44,7 +44,11 @@ typedef union {
 	uint4	 uint4s[200 / sizeof(uint4)];
 } hash200_t;
 
-void ethash_generate_dag(
+// Returning false aborts the generation.
+typedef bool (*dag_progress_fn)(void* ctx, uint64_t done, uint64_t total);
+
+// Returns false if aborted by progress.
+bool ethash_generate_dag(
 	hash64_t* dag,
 	uint64_t dag_bytes,
 	hash64_t * light,
@@ -52,7 +56,9 @@ void ethash_generate_dag(
 	uint32_t blocks,
 	uint32_t threads,
 	cudaStream_t stream,
-	int device
+	int device,
+	dag_progress_fn progress,
+	void* progress_ctx
 	);
 
 struct cuda_runtime_error : public virtual std::runtime_error
@@ -79,11 +79,8 @@ __device__ __forceinline__ uint32_t cuda_swab32(const uint32_t x)
 
 // Keccak - implemented as a variant of SHAKE
 // The width is 800, with a bitrate of 576, a capacity of 224, and no padding
-// Only need 64 bits of output for mining
-__device__ __noinline__ uint64_t keccak_f800(hash32_t header, uint64_t seed, hash32_t digest)
+__device__ __forceinline__ void keccak_f800_state(hash32_t header, uint64_t seed, hash32_t digest, uint32_t st[25])
 {
-    uint32_t st[25];
-
     for (int i = 0; i < 25; i++)
         st[i] = 0;
     for (int i = 0; i < 8; i++)
@@ -96,11 +93,33 @@ __device__ __noinline__ uint64_t keccak_f800(hash32_t header, uint64_t seed, has
     for (int r = 0; r < 22; r++) {
         keccak_f800_round(st, r);
     }
+}
+
+// The upper 64 bits of the hash, enough for the seed
+__device__ __noinline__ uint64_t keccak_f800(hash32_t header, uint64_t seed, hash32_t digest)
+{
+    uint32_t st[25];
+    keccak_f800_state(header, seed, digest, st);
 
     // Byte swap so byte 0 of hash is MSB of result
     return (uint64_t)cuda_swab32(st[0]) << 32 | cuda_swab32(st[1]);
 }
 
+// Whether the hash is at or below the 256-bit target, given as words with the most
+// significant first
+__device__ __noinline__ bool keccak_f800_meets(hash32_t header, uint64_t seed, hash32_t digest, hash32_t target)
+{
+    uint32_t st[25];
+    keccak_f800_state(header, seed, digest, st);
+
+    for (int i = 0; i < 8; i++) {
+        uint32_t word = cuda_swab32(st[i]);
+        if (word != target.uint32s[i])
+            return word < target.uint32s[i];
+    }
+    return true;
+}
+
 #define fnv1a(h, d) (h = (uint32_t(h) ^ uint32_t(d)) * uint32_t(0x1000193))
 
 typedef struct {
@@ -141,10 +160,11 @@ __global__ void
 progpow_search(
     uint64_t start_nonce,
     const hash32_t header,
-    const uint64_t target,
+    const hash32_t target,
     const dag_t *g_dag,
     volatile search_results* g_output,
-    bool hack_false
+    bool hack_false,
+    const uint32_t gid_limit
     )
 {
     __shared__ uint32_t c_dag[PROGPOW_CACHE_WORDS];
@@ -205,7 +225,11 @@ progpow_search(
     }
 
     // keccak(header .. keccak(header..nonce) .. digest);
-    if (keccak_f800(header, seed, digest) > target)
+    if (!keccak_f800_meets(header, seed, digest, target))
+        return;
+
+    // Every thread takes part in the shuffles above, only those below gid_limit report.
+    if (gid >= gid_limit)
         return;
 
     uint32_t index = atomicInc((uint32_t *)&g_output->count, 0xffffffff);
@@ -1,9 +1,107 @@
 #pragma once
 
+#include <atomic>
 #include <libethash/internal.h>
 
+#define PROGPOW_PROGRESS_GENERATE 0
+#define PROGPOW_PROGRESS_UPLOAD   1
+
+// Called with the bytes of DAG processed so far for the given stage.
+typedef void (*progpow_progress_fn)(void* user, uint32_t stage, uint64_t done, uint64_t total);
+
+// Identity of a device that stays the same when devices are re-ordered. Fields the
+// driver does not report are left zeroed.
+typedef struct {
+    uint8_t uuid[16];
+    uint32_t pci_domain;
+    uint32_t pci_bus;
+    uint32_t pci_device;
+    char name[256];
+} progpow_device_info;
+
+// The 32-byte big-endian target as the kernels compare it, eight words with the most
+// significant first.
+inline void target_words(const void* target, uint32_t words[8])
+{
+    const uint8_t* bytes = (const uint8_t*)target;
+    for (int i = 0; i < 8; i++)
+        words[i] = (uint32_t)bytes[4 * i] << 24 | (uint32_t)bytes[4 * i + 1] << 16 |
+                   (uint32_t)bytes[4 * i + 2] << 8 | (uint32_t)bytes[4 * i + 3];
+}
+
 class Miner{
 public:
-    virtual void compute(const void* header, uint64_t height, int epoch, uint64_t target, uint64_t startNonce) = 0;
-    virtual bool get_solutions(void* data) = 0;
+    virtual ~Miner() {}
+
+    // target points to the 32-byte big-endian target the final hash must not exceed.
+    // Only the first count nonces from startNonce are reported.
+    virtual void compute(const void* header, uint64_t height, int epoch, const void* target, uint64_t startNonce, uint32_t count) = 0;
+    // Move up to max pending solutions to data, 40 bytes each: the native uint64_t nonce
+    // then the 32-byte mix. Returns how many were written; the rest stay pending.
+    virtual uint32_t get_solutions(void* data, uint32_t max) = 0;
+    // Run the kernel for the single nonce and write its 32-byte mix. False if cancelled.
+    virtual bool hash_one(const void* header, uint64_t height, int epoch, uint64_t nonce, void* mix) = 0;
+    // Nonces covered by one call to compute.
+    virtual uint64_t batch_size() const = 0;
+    // Block until every launched kernel has finished.
+    virtual void wait() = 0;
+    // Wait, then free the DAG. The next compute rebuilds it.
+    virtual void stop() = 0;
+    // Start building the DAG of epoch, starting at height, in the background while
+    // compute keeps using the current one. The first compute in that epoch switches to
+    // it. False if nothing was started.
+    virtual bool prefetch(uint64_t height, int epoch) = 0;
+    // Device memory in bytes, 0 if unknown.
+    virtual uint64_t total_memory() = 0;
+    // Bytes of the current DAG kept in host memory, see set_host_dag_limit.
+    virtual uint64_t host_dag_bytes() { return 0; }
+
+    void set_progress(progpow_progress_fn fn, void* user) {
+        m_progress = fn;
+        m_progressUser = user;
+    }
+
+    // Let up to bytes of the DAG live in host memory, read by the kernel over the bus,
+    // when the device is too small for all of it. 0 (the default) refuses such devices.
+    // Only the CUDA miner supports it.
+    void set_host_dag_limit(uint64_t bytes) {
+        m_hostDagLimit.store(bytes);
+    }
+
+    // Abort DAG generation or upload in progress. compute returns early, leaving the
+    // epoch uninitialized, until cancel(false).
+    void cancel(bool value) {
+        m_cancel.store(value);
+    }
+
+    // True once the device stopped responding, after it was unplugged or its driver
+    // reset. The C interface then never calls into the miner again except to delete it.
+    bool lost() const {
+        return m_lost.load();
+    }
+
+protected:
+    void report_progress(uint32_t stage, uint64_t done, uint64_t total) {
+        if (m_progress)
+            m_progress(m_progressUser, stage, done, total);
+    }
+
+    bool cancelled() const {
+        return m_cancel.load();
+    }
+
+    uint64_t host_dag_limit() const {
+        return m_hostDagLimit.load();
+    }
+
+    void mark_lost() {
+        m_lost.store(true);
+    }
+
+private:
+    progpow_progress_fn m_progress = nullptr;
+    void* m_progressUser = nullptr;
+    std::atomic<bool> m_cancel{false};
+    std::atomic<uint64_t> m_hostDagLimit{0};
+    std::atomic<bool> m_lost{false};
 };
@@ -11,12 +11,12 @@ public:
     uint64_t height;
     int epoch;
     uint64_t startNonce = 0;
-    uint64_t target;
+    // Big-endian, as passed to Miner::compute.
+    h256 target;
 
-    Work(uint64_t height, uint64_t startNonce, int epoch, uint64_t target) {
+    Work(uint64_t height, uint64_t startNonce, int epoch) {
         this->header = new h256{0};
         this->height = height;
-        this->target = target;
         this->startNonce = startNonce;
         this->epoch = epoch;
     };
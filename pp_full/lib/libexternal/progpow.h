@@ -17,8 +17,22 @@ extern "C" {
     bool progpow_destroy(void* miner);
     void* progpow_gpu_init(unsigned device, unsigned driver);
     void progpow_gpu_configure(uint32_t devicesCount);
-    void progpow_gpu_compute(void* miner, const void* header, uint64_t height, int epoch, uint64_t boundary, uint64_t startNonce);
-    bool progpow_gpu_get_solutions(void* miner, void* data);
+    void progpow_gpu_compute(void* miner, const void* header, uint64_t height, int epoch, const void* target, uint64_t startNonce, uint32_t count);
+    uint32_t progpow_gpu_get_solutions(void* miner, void* data, uint32_t max);
+    bool progpow_gpu_hash_one(void* miner, const void* header, uint64_t height, int epoch, uint64_t nonce, void* mix);
+    bool progpow_gpu_prefetch(void* miner, uint64_t height, int epoch);
+    uint64_t progpow_gpu_total_memory(void* miner);
+    uint64_t progpow_gpu_required_memory(uint64_t height);
+    void progpow_gpu_set_host_dag_limit(void* miner, uint64_t bytes);
+    uint64_t progpow_gpu_host_dag_bytes(void* miner);
+    uint64_t progpow_gpu_batch_size(void* miner);
+    void progpow_gpu_wait(void* miner);
+    void progpow_gpu_stop(void* miner);
+    void progpow_gpu_set_progress(void* miner, progpow_progress_fn fn, void* user);
+    void progpow_gpu_cancel(void* miner, bool cancel);
+    bool progpow_gpu_lost(void* miner);
+    uint32_t progpow_gpu_device_count(unsigned driver);
+    bool progpow_gpu_device_info(unsigned driver, unsigned index, progpow_device_info* info);
 #if defined(__cplusplus)
 }
 #endif
\ No newline at end of file
@@ -114,31 +114,193 @@ extern "C" {
         return miner;
     }
 
-    void progpow_gpu_compute(void* miner, const void* header, uint64_t height, int epoch, uint64_t boundary, uint64_t startNonce) {
+    // target is the 32-byte big-endian target.
+    void progpow_gpu_compute(void* miner, const void* header, uint64_t height, int epoch, const void* target, uint64_t startNonce, uint32_t count) {
         if (miner == NULL){
             exit(1);
         }
 
-        return ((Miner*) miner)->compute(header, height, epoch, boundary, startNonce);
+        if (((Miner*) miner)->lost()){
+            return;
+        }
+
+        return ((Miner*) miner)->compute(header, height, epoch, target, startNonce, count);
+    }
+
+    uint32_t progpow_gpu_get_solutions(void* miner, void* data, uint32_t max) {
+        if (miner == NULL){
+            exit(1);
+        }
+
+        if (((Miner*) miner)->lost()){
+            return 0;
+        }
+
+        return ((Miner*) miner)->get_solutions(data, max);
+    }
+
+    bool progpow_gpu_hash_one(void* miner, const void* header, uint64_t height, int epoch, uint64_t nonce, void* mix) {
+        if (miner == NULL){
+            exit(1);
+        }
+
+        if (((Miner*) miner)->lost()){
+            return false;
+        }
+
+        return ((Miner*) miner)->hash_one(header, height, epoch, nonce, mix);
+    }
+
+    bool progpow_gpu_prefetch(void* miner, uint64_t height, int epoch) {
+        if (miner == NULL){
+            exit(1);
+        }
+
+        if (((Miner*) miner)->lost()){
+            return false;
+        }
+
+        return ((Miner*) miner)->prefetch(height, epoch);
+    }
+
+    uint64_t progpow_gpu_total_memory(void* miner) {
+        if (miner == NULL){
+            exit(1);
+        }
+
+        if (((Miner*) miner)->lost()){
+            return 0;
+        }
+
+        return ((Miner*) miner)->total_memory();
+    }
+
+    // The DAG and the light cache it is generated from on the device.
+    uint64_t progpow_gpu_required_memory(uint64_t height) {
+        return ethash_get_datasize(height) + ethash_get_cachesize(height);
+    }
+
+    void progpow_gpu_set_host_dag_limit(void* miner, uint64_t bytes) {
+        if (miner == NULL){
+            exit(1);
+        }
+
+        ((Miner*) miner)->set_host_dag_limit(bytes);
+    }
+
+    uint64_t progpow_gpu_host_dag_bytes(void* miner) {
+        if (miner == NULL){
+            exit(1);
+        }
+
+        return ((Miner*) miner)->host_dag_bytes();
+    }
+
+    uint64_t progpow_gpu_batch_size(void* miner) {
+        if (miner == NULL){
+            exit(1);
+        }
+
+        return ((Miner*) miner)->batch_size();
     }
 
-    bool progpow_gpu_get_solutions(void* miner, void* data) {
+    void progpow_gpu_wait(void* miner) {
         if (miner == NULL){
             exit(1);
         }
 
-        return ((Miner*) miner)->get_solutions(data);
+        if (((Miner*) miner)->lost()){
+            return;
+        }
+
+        ((Miner*) miner)->wait();
+    }
+
+    void progpow_gpu_stop(void* miner) {
+        if (miner == NULL){
+            exit(1);
+        }
+
+        if (((Miner*) miner)->lost()){
+            return;
+        }
+
+        ((Miner*) miner)->stop();
+    }
+
+    void progpow_gpu_set_progress(void* miner, progpow_progress_fn fn, void* user) {
+        if (miner == NULL){
+            exit(1);
+        }
+
+        ((Miner*) miner)->set_progress(fn, user);
+    }
+
+    void progpow_gpu_cancel(void* miner, bool cancel) {
+        if (miner == NULL){
+            exit(1);
+        }
+
+        ((Miner*) miner)->cancel(cancel);
+    }
+
+    bool progpow_gpu_lost(void* miner) {
+        if (miner == NULL){
+            exit(1);
+        }
+
+        return ((Miner*) miner)->lost();
+    }
+
+    uint32_t progpow_gpu_device_count(unsigned driver) {
+        #if ETH_ETHASHCUDA
+        if (driver == DRIVER_CUDA){
+            try {
+                return CUDAMiner::getNumDevices();
+            } catch (std::runtime_error const&) {
+                return 0;
+            }
+        }
+        #endif
+
+        #if ETH_ETHASHCL
+        if (driver == DRIVER_OCL){
+            return CLMiner::getNumDevices();
+        }
+        #endif
+
+        return 0;
+    }
+
+    bool progpow_gpu_device_info(unsigned driver, unsigned index, progpow_device_info* info) {
+        if (info == NULL){
+            return false;
+        }
+
+        #if ETH_ETHASHCUDA
+        if (driver == DRIVER_CUDA){
+            return CUDAMiner::deviceInfo(index, info);
+        }
+        #endif
+
+        #if ETH_ETHASHCL
+        if (driver == DRIVER_OCL){
+            return CLMiner::deviceInfo(index, info);
+        }
+        #endif
+
+        return false;
     }
 
     bool progpow_destroy(void* miner) {
         if (miner != NULL){
-            #if ETH_ETHASHCL
-                free((CLMiner*) miner);
-            #endif
-            #if ETH_ETHASHCUDA
-                free((CUDAMiner*) miner);
-            #endif
+            // Nothing is in flight on a lost device, and waiting would touch it.
+            if (!((Miner*) miner)->lost()){
+                ((Miner*) miner)->wait();
+            }
+            delete (Miner*) miner;
             return true;
         }
+        return false;
     }
 }
\ No newline at end of file
@@ -0,0 +1,228 @@
+//! Identifying devices by something that survives re-ordering. Indices change when
+//! cards are moved between slots or drivers are updated; UUIDs and PCI bus ids do not.
+
+use ffi::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::ffi::CStr;
+use std::fmt;
+use std::str::FromStr;
+use types::Driver;
+
+const INVALID_PCI_BUS_ID: &str = "Invalid PCI bus id";
+const INVALID_UUID: &str = "Invalid device UUID";
+
+/// Location of a device on the PCI bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PciBusId {
+	pub domain: u32,
+	pub bus: u32,
+	pub device: u32,
+}
+
+/// `domain:bus:device` or `bus:device` in hex, as `lspci` and `nvidia-smi` print them.
+/// A trailing `.function` is ignored.
+impl FromStr for PciBusId {
+	type Err = &'static str;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.split('.').next().unwrap_or(s);
+		let parts = s
+			.split(':')
+			.map(|part| u32::from_str_radix(part, 16))
+			.collect::<Result<Vec<_>, _>>()
+			.map_err(|_| INVALID_PCI_BUS_ID)?;
+		match parts[..] {
+			[bus, device] => Ok(PciBusId {
+				domain: 0,
+				bus,
+				device,
+			}),
+			[domain, bus, device] => Ok(PciBusId {
+				domain,
+				bus,
+				device,
+			}),
+			_ => Err(INVALID_PCI_BUS_ID),
+		}
+	}
+}
+
+impl fmt::Display for PciBusId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{:04x}:{:02x}:{:02x}",
+			self.domain, self.bus, self.device
+		)
+	}
+}
+
+/// Which device `GPU::open` opens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceSelector {
+	/// Position in the driver's device list, which may change across reboots.
+	Index(u32),
+	Uuid([u8; 16]),
+	PciBusId(PciBusId),
+}
+
+impl DeviceSelector {
+	pub fn matches(&self, info: &DeviceInfo) -> bool {
+		match *self {
+			DeviceSelector::Index(index) => info.index == index,
+			DeviceSelector::Uuid(uuid) => info.uuid == Some(uuid),
+			DeviceSelector::PciBusId(pci) => info.pci == Some(pci),
+		}
+	}
+
+	/// The first device of `enumerate(driver)` the selector matches.
+	pub fn find(&self, driver: Driver) -> Option<DeviceInfo> {
+		enumerate(driver)
+			.into_iter()
+			.find(|info| self.matches(info))
+	}
+}
+
+/// A decimal index, a PCI bus id (anything with a `:`) or a UUID, with or without the
+/// `GPU-` prefix and dashes `nvidia-smi -L` prints.
+impl FromStr for DeviceSelector {
+	type Err = &'static str;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if let Ok(index) = s.parse() {
+			return Ok(DeviceSelector::Index(index));
+		}
+		if s.contains(':') {
+			return s.parse().map(DeviceSelector::PciBusId);
+		}
+
+		let hex: String = s
+			.trim_start_matches("GPU-")
+			.chars()
+			.filter(|&c| c != '-')
+			.collect();
+		if hex.len() != 32 || !hex.is_ascii() {
+			return Err(INVALID_UUID);
+		}
+		let mut uuid = [0u8; 16];
+		for (i, byte) in uuid.iter_mut().enumerate() {
+			*byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| INVALID_UUID)?;
+		}
+		Ok(DeviceSelector::Uuid(uuid))
+	}
+}
+
+impl fmt::Display for DeviceSelector {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			DeviceSelector::Index(index) => write!(f, "{}", index),
+			DeviceSelector::Uuid(uuid) => {
+				f.write_str("GPU-")?;
+				for (i, byte) in uuid.iter().enumerate() {
+					if i == 4 || i == 6 || i == 8 || i == 10 {
+						f.write_str("-")?;
+					}
+					write!(f, "{:02x}", byte)?;
+				}
+				Ok(())
+			}
+			DeviceSelector::PciBusId(pci) => fmt::Display::fmt(&pci, f),
+		}
+	}
+}
+
+/// A device as its driver reports it. `uuid` and `pci` are `None` when the driver does
+/// not expose them, as some OpenCL platforms do not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceInfo {
+	pub driver: Driver,
+	/// The index `GPU::new` takes.
+	pub index: u32,
+	pub uuid: Option<[u8; 16]>,
+	pub pci: Option<PciBusId>,
+	pub name: String,
+}
+
+/// The devices of `driver`, or of every compiled-in driver for `Auto`.
+pub fn enumerate(driver: Driver) -> Vec<DeviceInfo> {
+	let drivers = match driver {
+		Driver::Auto => Driver::compiled(),
+		driver => vec![driver],
+	};
+
+	let mut devices = Vec::new();
+	for driver in drivers {
+		let count = unsafe { progpow_gpu_device_count(driver as u32) };
+		for index in 0..count {
+			let mut raw = progpow_device_info {
+				uuid: [0; 16],
+				pci_domain: 0,
+				pci_bus: 0,
+				pci_device: 0,
+				name: [0; 256],
+			};
+			if !unsafe { progpow_gpu_device_info(driver as u32, index, &mut raw) } {
+				continue;
+			}
+			let pci = PciBusId {
+				domain: raw.pci_domain,
+				bus: raw.pci_bus,
+				device: raw.pci_device,
+			};
+			devices.push(DeviceInfo {
+				driver,
+				index,
+				uuid: Some(raw.uuid).filter(|uuid| uuid.iter().any(|&b| b != 0)),
+				pci: Some(pci).filter(|pci| pci.bus != 0 || pci.device != 0),
+				name: unsafe { CStr::from_ptr(raw.name.as_ptr()) }
+					.to_string_lossy()
+					.into_owned(),
+			});
+		}
+	}
+	devices
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_selector_parsing() {
+		assert_eq!("3".parse(), Ok(DeviceSelector::Index(3)));
+
+		let pci = PciBusId {
+			domain: 0,
+			bus: 0x41,
+			device: 0,
+		};
+		assert_eq!("41:00".parse(), Ok(DeviceSelector::PciBusId(pci)));
+		assert_eq!("0000:41:00.0".parse(), Ok(DeviceSelector::PciBusId(pci)));
+		assert_eq!(
+			"00000000:41:00.0".parse(),
+			Ok(DeviceSelector::PciBusId(pci))
+		);
+		assert!("41:zz".parse::<DeviceSelector>().is_err());
+		assert_eq!(pci.to_string(), "0000:41:00");
+
+		let uuid = "GPU-6b3a5c1e-0f2d-4e8a-9b7c-1d2e3f405162";
+		let selector: DeviceSelector = uuid.parse().unwrap();
+		assert_eq!(selector.to_string(), uuid);
+		assert_eq!("6b3a5c1e0f2d4e8a9b7c1d2e3f405162".parse(), Ok(selector));
+		assert!("GPU-6b3a5c1e".parse::<DeviceSelector>().is_err());
+
+		let info = DeviceInfo {
+			driver: Driver::CUDA,
+			index: 1,
+			uuid: None,
+			pci: Some(pci),
+			name: String::new(),
+		};
+		assert!(DeviceSelector::PciBusId(pci).matches(&info));
+		assert!(DeviceSelector::Index(1).matches(&info));
+		assert!(!selector.matches(&info));
+	}
+}
@@ -12,8 +12,9 @@ extern "C" {
 		header: *const ::std::os::raw::c_void,
 		height: u64,
 		epoch: i32,
-		target: u64,
+		target: *const ::std::os::raw::c_void,
 		start_nonce: u64,
+		count: u32,
 	);
 }
 
@@ -21,10 +22,100 @@ extern "C" {
 	pub fn progpow_destroy(miner: *mut ::std::os::raw::c_void) -> bool;
 }
 
+extern "C" {
+	pub fn progpow_gpu_batch_size(miner: *mut ::std::os::raw::c_void) -> u64;
+}
+
+extern "C" {
+	pub fn progpow_gpu_wait(miner: *mut ::std::os::raw::c_void);
+}
+
+extern "C" {
+	pub fn progpow_gpu_stop(miner: *mut ::std::os::raw::c_void);
+}
+
+extern "C" {
+	pub fn progpow_gpu_set_progress(
+		miner: *mut ::std::os::raw::c_void,
+		progress: ::std::option::Option<
+			unsafe extern "C" fn(
+				user: *mut ::std::os::raw::c_void,
+				stage: u32,
+				done: u64,
+				total: u64,
+			),
+		>,
+		user: *mut ::std::os::raw::c_void,
+	);
+}
+
 extern "C" {
 	pub fn progpow_gpu_get_solutions(
 		miner: *mut ::std::os::raw::c_void,
 		data: *mut ::std::os::raw::c_void,
+		max: u32,
+	) -> u32;
+}
+
+extern "C" {
+	pub fn progpow_gpu_hash_one(
+		miner: *mut ::std::os::raw::c_void,
+		header: *const ::std::os::raw::c_void,
+		height: u64,
+		epoch: i32,
+		nonce: u64,
+		mix: *mut ::std::os::raw::c_void,
 	) -> bool;
 }
 
+extern "C" {
+	pub fn progpow_gpu_prefetch(
+		miner: *mut ::std::os::raw::c_void,
+		height: u64,
+		epoch: i32,
+	) -> bool;
+}
+
+extern "C" {
+	pub fn progpow_gpu_total_memory(miner: *mut ::std::os::raw::c_void) -> u64;
+}
+
+extern "C" {
+	pub fn progpow_gpu_required_memory(height: u64) -> u64;
+}
+
+extern "C" {
+	pub fn progpow_gpu_set_host_dag_limit(miner: *mut ::std::os::raw::c_void, bytes: u64);
+}
+
+extern "C" {
+	pub fn progpow_gpu_host_dag_bytes(miner: *mut ::std::os::raw::c_void) -> u64;
+}
+
+extern "C" {
+	pub fn progpow_gpu_cancel(miner: *mut ::std::os::raw::c_void, cancel: bool);
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Copy, Clone)]
+pub struct progpow_device_info {
+	pub uuid: [u8; 16usize],
+	pub pci_domain: u32,
+	pub pci_bus: u32,
+	pub pci_device: u32,
+	pub name: [::std::os::raw::c_char; 256usize],
+}
+
+extern "C" {
+	pub fn progpow_gpu_device_count(driver: u32) -> u32;
+}
+
+extern "C" {
+	pub fn progpow_gpu_device_info(driver: u32, index: u32, info: *mut progpow_device_info)
+		-> bool;
+}
+
+extern "C" {
+	pub fn progpow_gpu_lost(miner: *mut ::std::os::raw::c_void) -> bool;
+}
@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Default length of the rolling window used by `GPU::hashrate`.
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(30);
+
+/// Counts searched nonces and derives hashes per second over a rolling window.
+#[derive(Debug)]
+pub struct HashrateMeter {
+	window: Duration,
+	samples: VecDeque<(Instant, u64)>,
+	total: u64,
+	started: Option<Instant>,
+}
+
+impl HashrateMeter {
+	pub fn new(window: Duration) -> Self {
+		HashrateMeter {
+			window,
+			samples: VecDeque::new(),
+			total: 0,
+			started: None,
+		}
+	}
+
+	pub fn set_window(&mut self, window: Duration) {
+		self.window = window;
+	}
+
+	/// Record `nonces` searched, finishing at `at`.
+	pub fn record(&mut self, at: Instant, nonces: u64) {
+		self.started.get_or_insert(at);
+		self.total += nonces;
+		self.samples.push_back((at, nonces));
+		while let Some(&(t, _)) = self.samples.front() {
+			if at.duration_since(t) > self.window {
+				self.samples.pop_front();
+			} else {
+				break;
+			}
+		}
+	}
+
+	/// Hashes per second over the window. The oldest sample only marks the start
+	/// of the window, its nonces were searched before it.
+	pub fn rate(&self) -> f64 {
+		let (first, last) = match (self.samples.front(), self.samples.back()) {
+			(Some(first), Some(last)) => (first.0, last.0),
+			_ => return 0.0,
+		};
+		let secs = last.duration_since(first).as_secs_f64();
+		if secs == 0.0 {
+			return 0.0;
+		}
+		let nonces: u64 = self.samples.iter().skip(1).map(|&(_, n)| n).sum();
+		nonces as f64 / secs
+	}
+
+	/// Hashes per second since the first recorded batch.
+	pub fn average(&self, now: Instant) -> f64 {
+		match self.started {
+			Some(start) if now > start => {
+				self.total as f64 / now.duration_since(start).as_secs_f64()
+			}
+			_ => 0.0,
+		}
+	}
+
+	pub fn total(&self) -> u64 {
+		self.total
+	}
+
+	pub fn reset(&mut self) {
+		self.samples.clear();
+		self.total = 0;
+		self.started = None;
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_rolling_rate() {
+		let t0 = Instant::now();
+		let mut meter = HashrateMeter::new(Duration::from_secs(10));
+		assert_eq!(meter.rate(), 0.0);
+
+		for i in 0..5 {
+			meter.record(t0 + Duration::from_secs(i), 1000);
+		}
+		assert_eq!(meter.rate(), 1000.0);
+		assert_eq!(meter.total(), 5000);
+
+		// Samples older than the window stop counting.
+		meter.record(t0 + Duration::from_secs(20), 1000);
+		assert_eq!(meter.rate(), 0.0);
+		meter.record(t0 + Duration::from_secs(22), 4000);
+		assert_eq!(meter.rate(), 2000.0);
+	}
+}
@@ -0,0 +1,183 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{Duration, Instant};
+use types::epoch_for_height;
+
+type H256 = [u8; 32];
+
+/// Solutions one kernel launch of the C++ miners can hold. Further ones are dropped.
+pub const SEARCH_RESULTS: u32 = 4;
+
+/// Why `MiningJob::validate` rejected a job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobError {
+	/// No hash meets a zero target, so the search would be wasted.
+	ZeroTarget,
+	/// `epoch` is not the epoch of `height`, so the kernel would search the wrong DAG.
+	EpochMismatch {
+		height: u64,
+		epoch: i32,
+		expected: i32,
+	},
+}
+
+impl JobError {
+	/// Message for the `&str` errors of `GPU`.
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			JobError::ZeroTarget => "Target is zero",
+			JobError::EpochMismatch { .. } => "Epoch does not match the height",
+		}
+	}
+}
+
+impl fmt::Display for JobError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			JobError::ZeroTarget => f.write_str("target is zero"),
+			JobError::EpochMismatch {
+				height,
+				epoch,
+				expected,
+			} => write!(
+				f,
+				"epoch {} does not match height {}, which is in epoch {}",
+				epoch, height, expected
+			),
+		}
+	}
+}
+
+/// One unit of work for a device. Solutions are tagged with its `job_id`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MiningJob {
+	/// Chosen by the caller. Passing a job with a new id supersedes the previous one.
+	pub job_id: u64,
+	pub header: H256,
+	pub height: u64,
+	pub epoch: i32,
+	/// Big-endian target the final hash must not exceed. The kernels compare all 256 bits.
+	pub target: H256,
+	/// Top nonce bytes fixed by the pool, see `GPU::compute`.
+	pub extranonce: Extranonce,
+}
+
+impl MiningJob {
+	pub fn new(job_id: u64, header: H256, height: u64, epoch: i32, target: H256) -> Self {
+		MiningJob {
+			job_id,
+			header,
+			height,
+			epoch,
+			target,
+			extranonce: Extranonce::default(),
+		}
+	}
+
+	pub fn with_extranonce(mut self, extranonce: Extranonce) -> Self {
+		self.extranonce = extranonce;
+		self
+	}
+
+	/// Job whose target has upper 64 bits `target` and all lower bits set, accepting the
+	/// same hashes as a 64-bit comparison.
+	pub fn with_target64(job_id: u64, header: H256, height: u64, epoch: i32, target: u64) -> Self {
+		let mut full = [0xff; 32];
+		full[..8].copy_from_slice(&target.to_be_bytes());
+		MiningJob::new(job_id, header, height, epoch, full)
+	}
+
+	/// Check the job before it reaches a kernel, see `JobError`, for a chain with
+	/// `epoch_length` blocks per epoch. `GPU::compute` does with `GPU::epoch_length`.
+	pub fn validate(&self, epoch_length: u64) -> Result<(), JobError> {
+		if self.target == [0; 32] {
+			return Err(JobError::ZeroTarget);
+		}
+		let expected = epoch_for_height(self.height, epoch_length);
+		if self.epoch != expected {
+			return Err(JobError::EpochMismatch {
+				height: self.height,
+				epoch: self.epoch,
+				expected,
+			});
+		}
+		Ok(())
+	}
+
+	/// Solutions expected among `nonces` nonces, from the top 64 bits of the target.
+	pub fn expected_solutions(&self, nonces: u64) -> f64 {
+		let mut top = [0u8; 8];
+		top.copy_from_slice(&self.target[..8]);
+		nonces as f64 * (u64::from_be_bytes(top) as f64 + 1.0) / 2f64.powi(64)
+	}
+
+	/// Whether a batch of `nonces` is expected to find more than `SEARCH_RESULTS`
+	/// solutions, so that some are dropped. Only happens at test chain difficulties.
+	pub fn floods_results(&self, nonces: u64) -> bool {
+		self.expected_solutions(nonces) > SEARCH_RESULTS as f64
+	}
+}
+
+/// The top `bytes` bytes of every nonce searched for a job. The default fixes nothing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Extranonce {
+	pub value: u64,
+	pub bytes: u32,
+}
+
+impl Extranonce {
+	/// `bytes` is capped at 7, leaving at least one byte to search. `value` is cut to fit.
+	pub fn new(value: u64, bytes: u32) -> Self {
+		let bytes = bytes.min(7);
+		let value = if bytes == 0 {
+			0
+		} else {
+			value & (u64::MAX >> (64 - 8 * bytes))
+		};
+		Extranonce { value, bytes }
+	}
+
+	/// `nonce` with its top bytes replaced by the extranonce.
+	pub fn apply(&self, nonce: u64) -> u64 {
+		if self.bytes == 0 {
+			return nonce;
+		}
+		self.value << (64 - 8 * self.bytes) | (nonce & (u64::MAX >> (8 * self.bytes)))
+	}
+
+	/// The last nonce starting with the extranonce.
+	pub fn last(&self) -> u64 {
+		self.apply(u64::MAX)
+	}
+}
+
+/// A nonce found by a device, with the job it was found for.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Solution {
+	pub nonce: u64,
+	pub mix_hash: H256,
+	/// The kernels only report the mix, so this is `None` until the hash is recomputed on
+	/// the CPU.
+	pub final_hash: Option<H256>,
+	pub device_id: u32,
+	pub job_id: u64,
+	/// Time from the first `compute` of the job until the solution was collected.
+	pub search_duration: Duration,
+}
+
+/// The job `compute` is currently searching.
+#[derive(Debug)]
+pub(crate) struct JobState {
+	pub job_id: u64,
+	pub started: Instant,
+	/// Nonces already returned by `solutions`. Searching the same nonces again finds them
+	/// again.
+	pub returned: HashSet<u64>,
+	/// Where `GPU::search_next` continues: just after the last batch searched.
+	pub next_nonce: u64,
+}
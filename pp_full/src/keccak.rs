@@ -0,0 +1,100 @@
+//! Host copy of the kernels' keccak-f800, to finish a hash whose mix came from the device.
+
+type H256 = [u8; 32];
+
+/// KawPoW's, the only parameters the C++ kernels implement.
+const KECCAK_ROUNDS: usize = 22;
+
+const KECCAKF_RNDC: [u32; 24] = [
+	0x00000001, 0x00008082, 0x0000808a, 0x80008000, 0x0000808b, 0x80000001, 0x80008081, 0x00008009,
+	0x0000008a, 0x00000088, 0x80008009, 0x8000000a, 0x8000808b, 0x0000008b, 0x00008089, 0x00008003,
+	0x00008002, 0x00000080, 0x0000800a, 0x8000000a, 0x80008081, 0x00008080, 0x80000001, 0x80008008,
+];
+
+const KECCAKF_ROTC: [u32; 24] = [
+	1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,
+];
+
+const KECCAKF_PILN: [usize; 24] = [
+	10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+];
+
+fn keccak_f800_round(st: &mut [u32; 25], r: usize) {
+	// Theta
+	let mut bc = [0u32; 5];
+	for i in 0..5 {
+		bc[i] = st[i] ^ st[i + 5] ^ st[i + 10] ^ st[i + 15] ^ st[i + 20];
+	}
+	for i in 0..5 {
+		let t = bc[(i + 4) % 5] ^ bc[(i + 1) % 5].rotate_left(1);
+		for j in (0..25).step_by(5) {
+			st[j + i] ^= t;
+		}
+	}
+
+	// Rho Pi
+	let mut t = st[1];
+	for i in 0..24 {
+		let j = KECCAKF_PILN[i];
+		bc[0] = st[j];
+		st[j] = t.rotate_left(KECCAKF_ROTC[i]);
+		t = bc[0];
+	}
+
+	// Chi
+	for j in (0..25).step_by(5) {
+		for i in 0..5 {
+			bc[i] = st[j + i];
+		}
+		for i in 0..5 {
+			st[j + i] ^= (!bc[(i + 1) % 5]) & bc[(i + 2) % 5];
+		}
+	}
+
+	// Iota
+	st[0] ^= KECCAKF_RNDC[r];
+}
+
+/// `keccak_f800` of the kernels, returning the first 8 words of the state.
+fn keccak_f800(header: &H256, seed: u64, digest: &[u32; 8]) -> [u32; 8] {
+	let mut st = [0u32; 25];
+	for i in 0..8 {
+		st[i] = u32::from_le_bytes([
+			header[4 * i],
+			header[4 * i + 1],
+			header[4 * i + 2],
+			header[4 * i + 3],
+		]);
+	}
+	st[8] = seed as u32;
+	st[9] = (seed >> 32) as u32;
+	st[10..18].copy_from_slice(digest);
+
+	for r in 0..KECCAK_ROUNDS {
+		keccak_f800_round(&mut st, r);
+	}
+
+	let mut out = [0u32; 8];
+	out.copy_from_slice(&st[..8]);
+	out
+}
+
+/// Final hash of `nonce` given the mix the device computed for it. The kernels byte swap
+/// the state words, so byte 0 is the most significant byte of the value they compare
+/// with the target.
+pub(crate) fn final_hash(header: &H256, nonce: u64, mix: &H256) -> H256 {
+	let seed = keccak_f800(header, nonce, &[0u32; 8]);
+	let seed = (seed[0].swap_bytes() as u64) << 32 | seed[1].swap_bytes() as u64;
+
+	let mut digest = [0u32; 8];
+	for i in 0..8 {
+		digest[i] =
+			u32::from_le_bytes([mix[4 * i], mix[4 * i + 1], mix[4 * i + 2], mix[4 * i + 3]]);
+	}
+
+	let mut out = [0u8; 32];
+	for (i, word) in keccak_f800(header, seed, &digest).iter().enumerate() {
+		out[4 * i..4 * i + 4].copy_from_slice(&word.to_le_bytes());
+	}
+	out
+}
@@ -1,12 +1,30 @@
 use std::{thread, time};
 
 extern crate libc;
+#[cfg(feature = "monitoring")]
+extern crate nvml_wrapper;
+#[cfg(feature = "serde")]
+extern crate serde;
 
+pub mod device;
 pub mod ffi;
+pub mod hashrate;
+pub mod job;
+mod keccak;
+#[cfg(feature = "monitoring")]
+pub mod monitor;
+pub mod progress;
+pub mod shared;
 pub mod types;
 pub mod utils;
 
-pub use types::{Driver, GPU};
+pub use device::{enumerate, DeviceInfo, DeviceSelector, PciBusId};
+pub use job::{Extranonce, JobError, MiningJob, Solution, SEARCH_RESULTS};
+#[cfg(feature = "monitoring")]
+pub use monitor::{DeviceHealth, Monitor, ThermalPolicy};
+pub use progress::{DagProgress, DagStage};
+pub use shared::SharedGpu;
+pub use types::{epoch_for_height, required_vram, Driver, VramShortage, EPOCH_LENGTH, GPU};
 
 use libc::c_void;
 
@@ -14,6 +32,21 @@ use libc::c_void;
 mod test {
 	use super::*;
 
+	#[test]
+	fn test_driver_parsing() {
+		use std::convert::TryFrom;
+
+		assert_eq!("CUDA".parse(), Ok(Driver::CUDA));
+		assert_eq!("opencl".parse(), Ok(Driver::OCL));
+		assert_eq!("auto".parse(), Ok(Driver::Auto));
+		assert!("vulkan".parse::<Driver>().is_err());
+		assert_eq!(Driver::try_from(2), Ok(Driver::OCL));
+		assert!(Driver::try_from(3).is_err());
+		for driver in Driver::compiled() {
+			assert_eq!(driver.to_string().parse(), Ok(driver));
+		}
+	}
+
 	#[test]
 	#[cfg(feature = "opencl")]
 	fn test_compute_ocl() {
@@ -21,6 +54,7 @@ mod test {
 		let epoch: i32 = 0;
 		let boundary: u64 = 10000;
 		let header_hash: [u8; 32] = [20; 32];
+		let job = MiningJob::with_target64(0, header_hash, height, epoch, boundary);
 
 		let mut pp_gpu = GPU::new(0, Driver::OCL);
 
@@ -28,14 +62,12 @@ mod test {
 		let ten_millis = time::Duration::from_millis(100);
 
 		loop {
-			pp_gpu.compute(header_hash, height, epoch, boundary, 0);
+			pp_gpu.search_next(&job);
 			thread::sleep(ten_millis);
 
-			let solution = pp_gpu.solutions().unwrap();
+			let solutions = pp_gpu.solutions().unwrap();
 
-			if let Some(s) = solution {
-				let (nonce, mix) = s;
-				println!("nonce: {:?}", nonce);
+			if !solutions.is_empty() {
 				break;
 			}
 		}
@@ -48,6 +80,7 @@ mod test {
 		let epoch: i32 = 0;
 		let boundary: u64 = 10000;
 		let header_hash: [u8; 32] = [20; 32];
+		let job = MiningJob::with_target64(0, header_hash, height, epoch, boundary);
 
 		let mut pp_gpu = GPU::new(Driver::CUDA);
 
@@ -55,14 +88,12 @@ mod test {
 		let ten_millis = time::Duration::from_millis(100);
 
 		loop {
-			pp_gpu.compute(header_hash, height, epoch, boundary, 0);
+			pp_gpu.search_next(&job);
 			thread::sleep(ten_millis);
 
-			let solution = pp_gpu.solutions().unwrap();
+			let solutions = pp_gpu.solutions().unwrap();
 
-			if let Some(s) = solution {
-				let (nonce, mix) = s;
-				println!("nonce: {:?}", nonce);
+			if !solutions.is_empty() {
 				break;
 			}
 		}
@@ -0,0 +1,191 @@
+//! Device temperature, power and fan readings, and a policy that pauses a hot device.
+//!
+//! NVIDIA devices are read through NVML, AMD devices through the amdgpu hwmon files in
+//! sysfs (Linux only).
+
+use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
+use nvml_wrapper::Nvml;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use shared::SharedGpu;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use types::Driver;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceHealth {
+	pub temp_c: f32,
+	/// `None` if the device does not report it.
+	pub power_w: Option<f32>,
+	/// `None` if the device does not report it.
+	pub fan_pct: Option<f32>,
+}
+
+/// Reads the sensors of one device.
+pub enum Monitor {
+	Nvml {
+		nvml: Nvml,
+		index: u32,
+	},
+	/// A `hwmon` directory, e.g. `/sys/class/drm/card0/device/hwmon/hwmon3`.
+	Sysfs(PathBuf),
+}
+
+fn other<E: fmt::Display>(e: E) -> io::Error {
+	io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+impl Monitor {
+	/// Monitor the device a `GPU` with this `driver` and `device` index runs on. NVML and
+	/// DRM number devices independently of CUDA and OpenCL, so this assumes the orders
+	/// match, which holds on single-vendor machines.
+	pub fn for_device(driver: &Driver, device: u32) -> io::Result<Self> {
+		match *driver {
+			Driver::CUDA => Monitor::nvml(device),
+			Driver::OCL => Monitor::sysfs_card(device),
+			Driver::Auto => Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"Auto is resolved by GPU::new",
+			)),
+		}
+	}
+
+	pub fn nvml(index: u32) -> io::Result<Self> {
+		let nvml = Nvml::init().map_err(other)?;
+		nvml.device_by_index(index).map_err(other)?;
+		Ok(Monitor::Nvml { nvml, index })
+	}
+
+	/// The hwmon directory of `/sys/class/drm/card<card>`.
+	pub fn sysfs_card(card: u32) -> io::Result<Self> {
+		let dir = PathBuf::from(format!("/sys/class/drm/card{}/device/hwmon", card));
+		for entry in fs::read_dir(&dir)? {
+			let path = entry?.path();
+			if path.join("temp1_input").exists() {
+				return Ok(Monitor::Sysfs(path));
+			}
+		}
+		Err(io::Error::new(
+			io::ErrorKind::NotFound,
+			format!("no temperature sensor under {}", dir.display()),
+		))
+	}
+
+	pub fn health(&self) -> io::Result<DeviceHealth> {
+		match *self {
+			Monitor::Nvml { ref nvml, index } => {
+				let device = nvml.device_by_index(index).map_err(other)?;
+				Ok(DeviceHealth {
+					temp_c: device.temperature(TemperatureSensor::Gpu).map_err(other)? as f32,
+					power_w: device.power_usage().ok().map(|mw| mw as f32 / 1000.0),
+					fan_pct: device.fan_speed(0).ok().map(|pct| pct as f32),
+				})
+			}
+			Monitor::Sysfs(ref dir) => sysfs_health(dir),
+		}
+	}
+}
+
+fn read_number(path: &Path) -> io::Result<f32> {
+	fs::read_to_string(path)?
+		.trim()
+		.parse()
+		.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, path.display().to_string()))
+}
+
+fn sysfs_health(dir: &Path) -> io::Result<DeviceHealth> {
+	let temp_c = read_number(&dir.join("temp1_input"))? / 1000.0;
+	let power_w = read_number(&dir.join("power1_average"))
+		.or_else(|_| read_number(&dir.join("power1_input")))
+		.ok()
+		.map(|uw| uw / 1_000_000.0);
+	let fan_pct = match (
+		read_number(&dir.join("pwm1")),
+		read_number(&dir.join("pwm1_max")),
+	) {
+		(Ok(pwm), Ok(max)) if max > 0.0 => Some(pwm * 100.0 / max),
+		(Ok(pwm), Err(_)) => Some(pwm * 100.0 / 255.0),
+		_ => None,
+	};
+	Ok(DeviceHealth {
+		temp_c,
+		power_w,
+		fan_pct,
+	})
+}
+
+/// Pauses a device once it reaches `max_temp_c` and resumes it when it has cooled to
+/// `resume_temp_c`. Only undoes its own pauses.
+#[derive(Debug)]
+pub struct ThermalPolicy {
+	pub max_temp_c: f32,
+	pub resume_temp_c: f32,
+	throttled: AtomicBool,
+}
+
+impl ThermalPolicy {
+	/// Resume 10 degrees below `max_temp_c`.
+	pub fn new(max_temp_c: f32) -> Self {
+		ThermalPolicy::with_resume(max_temp_c, max_temp_c - 10.0)
+	}
+
+	pub fn with_resume(max_temp_c: f32, resume_temp_c: f32) -> Self {
+		ThermalPolicy {
+			max_temp_c,
+			resume_temp_c,
+			throttled: AtomicBool::new(false),
+		}
+	}
+
+	/// Whether the policy currently holds the device paused.
+	pub fn is_throttled(&self) -> bool {
+		self.throttled.load(Ordering::SeqCst)
+	}
+
+	/// Read `monitor` once and pause or resume `gpu` accordingly. Call it periodically,
+	/// e.g. every few seconds from a monitoring thread.
+	pub fn apply(&self, monitor: &Monitor, gpu: &SharedGpu) -> io::Result<DeviceHealth> {
+		let health = monitor.health()?;
+		if health.temp_c >= self.max_temp_c && !self.is_throttled() {
+			self.throttled.store(true, Ordering::SeqCst);
+			gpu.pause();
+		} else if health.temp_c <= self.resume_temp_c && self.is_throttled() {
+			self.throttled.store(false, Ordering::SeqCst);
+			gpu.resume();
+		}
+		Ok(health)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_sysfs_health() {
+		let dir = ::std::env::temp_dir().join(format!("progpow-hwmon-{}", ::std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		fs::write(dir.join("temp1_input"), "71000\n").unwrap();
+		fs::write(dir.join("power1_average"), "150000000\n").unwrap();
+		fs::write(dir.join("pwm1"), "128\n").unwrap();
+		fs::write(dir.join("pwm1_max"), "256\n").unwrap();
+
+		let health = Monitor::Sysfs(dir.clone()).health().unwrap();
+		assert_eq!(
+			health,
+			DeviceHealth {
+				temp_c: 71.0,
+				power_w: Some(150.0),
+				fan_pct: Some(50.0),
+			}
+		);
+
+		fs::remove_file(dir.join("power1_average")).unwrap();
+		assert_eq!(Monitor::Sysfs(dir.clone()).health().unwrap().power_w, None);
+		let _ = fs::remove_dir_all(&dir);
+	}
+}
@@ -0,0 +1,103 @@
+use libc::c_void;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Which part of the epoch switch a `DagProgress` refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DagStage {
+	/// The DAG is being generated on the device.
+	Generating,
+	/// A host copy of the DAG is being uploaded to the device.
+	Uploading,
+}
+
+/// Progress of building the DAG on a device, in bytes.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DagProgress {
+	pub stage: DagStage,
+	pub done: u64,
+	pub total: u64,
+	/// Time since the stage started.
+	pub elapsed: Duration,
+}
+
+impl DagProgress {
+	pub fn percent(&self) -> f64 {
+		if self.total == 0 {
+			return 100.0;
+		}
+		self.done as f64 * 100.0 / self.total as f64
+	}
+
+	/// Bytes per second since the stage started.
+	pub fn throughput(&self) -> f64 {
+		let secs = self.elapsed.as_secs_f64();
+		if secs == 0.0 {
+			return 0.0;
+		}
+		self.done as f64 / secs
+	}
+}
+
+pub(crate) struct ProgressHandler {
+	handler: Box<dyn Fn(DagProgress) + Send + Sync>,
+	started: Mutex<Option<(DagStage, Instant)>>,
+}
+
+impl ProgressHandler {
+	pub(crate) fn new<F: Fn(DagProgress) + Send + Sync + 'static>(handler: F) -> Self {
+		ProgressHandler {
+			handler: Box::new(handler),
+			started: Mutex::new(None),
+		}
+	}
+
+	fn report(&self, stage: DagStage, done: u64, total: u64) {
+		let elapsed = {
+			let mut started = self.started.lock().unwrap();
+			let at = match *started {
+				Some((s, at)) if s == stage => at,
+				_ => Instant::now(),
+			};
+			*started = if done < total {
+				Some((stage, at))
+			} else {
+				None
+			};
+			at.elapsed()
+		};
+
+		(self.handler)(DagProgress {
+			stage,
+			done,
+			total,
+			elapsed,
+		});
+	}
+}
+
+impl fmt::Debug for ProgressHandler {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str("ProgressHandler")
+	}
+}
+
+/// Passed to the C++ miner, `user` points to the `ProgressHandler` owned by the `GPU`.
+pub(crate) unsafe extern "C" fn progress_callback(
+	user: *mut c_void,
+	stage: u32,
+	done: u64,
+	total: u64,
+) {
+	let handler = &*(user as *const ProgressHandler);
+	let stage = match stage {
+		0 => DagStage::Generating,
+		_ => DagStage::Uploading,
+	};
+	handler.report(stage, done, total);
+}
@@ -0,0 +1,165 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use job::{MiningJob, Solution};
+use types::GPU;
+
+/// A `GPU` that can be shared between threads.
+///
+/// The C++ miners are not reentrant, so every call that reaches them (`compute`,
+/// `solutions`, `pause`, `stop`) is serialized on an internal mutex. `resume`, `cancel`,
+/// the hashrate accessors, `is_paused` and `is_lost` never take it, so they do not block
+/// on a running kernel or DAG build. The `GPU` itself is not handed out, since its calls
+/// would bypass the mutex; `into_inner` takes it back. The CUDA miner makes its context current on every
+/// call, so the calling thread does not matter.
+pub struct SharedGpu {
+	gpu: GPU,
+	ffi: Mutex<()>,
+}
+
+// Safety: the miner pointer is only dereferenced by the C++ side while `ffi` is held.
+unsafe impl Send for SharedGpu {}
+unsafe impl Sync for SharedGpu {}
+
+impl SharedGpu {
+	/// Wrap `gpu`, initializing it first if needed. Configure it (e.g. the progress
+	/// handler) before wrapping.
+	pub fn new(mut gpu: GPU) -> Self {
+		if !gpu.is_initialized() {
+			gpu.init();
+		}
+		SharedGpu {
+			gpu,
+			ffi: Mutex::new(()),
+		}
+	}
+
+	/// The intensity sleep happens after the lock is released.
+	pub fn compute(&self, job: &MiningJob, start_nonce: u64) -> Result<(), &str> {
+		let busy = {
+			let _guard = self.ffi.lock().unwrap();
+			self.gpu.compute_unthrottled(job, start_nonce)?
+		};
+		self.gpu.throttle(busy);
+		Ok(())
+	}
+
+	/// See `GPU::search_next`.
+	pub fn search_next(&self, job: &MiningJob) -> Result<(), &str> {
+		self.compute(job, self.gpu.next_nonce(job.job_id))
+	}
+
+	pub fn set_intensity(&self, percent: u32) {
+		self.gpu.set_intensity(percent);
+	}
+
+	pub fn intensity(&self) -> u32 {
+		self.gpu.intensity()
+	}
+
+	pub fn hash_one(
+		&self,
+		header: &[u8; 32],
+		height: u64,
+		nonce: u64,
+	) -> Result<([u8; 32], [u8; 32]), &str> {
+		let _guard = self.ffi.lock().unwrap();
+		self.gpu.hash_one(header, height, nonce)
+	}
+
+	pub fn prefetch(&self, height: u64) -> Result<bool, &str> {
+		let _guard = self.ffi.lock().unwrap();
+		self.gpu.prefetch(height)
+	}
+
+	pub fn prepare_epoch(&self, epoch: u64) -> Result<bool, &str> {
+		let _guard = self.ffi.lock().unwrap();
+		self.gpu.prepare_epoch(epoch)
+	}
+
+	pub fn set_epoch_length(&self, blocks: u64) {
+		self.gpu.set_epoch_length(blocks);
+	}
+
+	pub fn epoch_length(&self) -> u64 {
+		self.gpu.epoch_length()
+	}
+
+	pub fn solutions(&self) -> Result<Vec<Solution>, &str> {
+		let _guard = self.ffi.lock().unwrap();
+		self.gpu.solutions()
+	}
+
+	/// Waits for the batch running on another thread, if any, to finish.
+	pub fn pause(&self) {
+		let _guard = self.ffi.lock().unwrap();
+		self.gpu.pause();
+	}
+
+	pub fn resume(&self) {
+		self.gpu.resume();
+	}
+
+	pub fn is_paused(&self) -> bool {
+		self.gpu.is_paused()
+	}
+
+	/// Aborts a DAG build running in `compute` on another thread.
+	pub fn cancel(&self) {
+		self.gpu.cancel();
+	}
+
+	pub fn clear_cancel(&self) {
+		self.gpu.clear_cancel();
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.gpu.is_cancelled()
+	}
+
+	pub fn is_lost(&self) -> bool {
+		self.gpu.is_lost()
+	}
+
+	pub fn stop(&self) {
+		let _guard = self.ffi.lock().unwrap();
+		self.gpu.stop();
+	}
+
+	pub fn hashrate(&self) -> f64 {
+		self.gpu.hashrate()
+	}
+
+	pub fn average_hashrate(&self) -> f64 {
+		self.gpu.average_hashrate()
+	}
+
+	pub fn nonces_searched(&self) -> u64 {
+		self.gpu.nonces_searched()
+	}
+
+	pub fn set_hashrate_window(&self, window: Duration) {
+		self.gpu.set_hashrate_window(window);
+	}
+
+	pub fn job_id(&self) -> Option<u64> {
+		self.gpu.job_id()
+	}
+
+	pub fn stale_solutions(&self) -> u64 {
+		self.gpu.stale_solutions()
+	}
+
+	pub fn duplicate_solutions(&self) -> u64 {
+		self.gpu.duplicate_solutions()
+	}
+
+	/// `false` if `new` could not initialize the miner.
+	pub fn is_initialized(&self) -> bool {
+		self.gpu.is_initialized()
+	}
+
+	pub fn into_inner(self) -> GPU {
+		self.gpu
+	}
+}
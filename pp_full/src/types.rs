@@ -1,44 +1,317 @@
+use device::DeviceSelector;
 use ffi::*;
+use hashrate::{HashrateMeter, DEFAULT_WINDOW};
+use job::{JobState, MiningJob, Solution};
+use keccak::final_hash;
 use libc::c_void;
+use progress::{progress_callback, DagProgress, ProgressHandler};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
 const MINER_UNINITIALIZED: &str = "Miner is not initialized";
+const MINER_PAUSED: &str = "Miner is paused";
+const MINER_CANCELLED: &str = "Miner was cancelled";
+const DEVICE_LOST: &str = "Device was lost";
+
+/// A solution as the C++ miners write it: the nonce, then the mix.
+const SOLUTION_BYTES: usize = 40;
+/// Solutions taken per FFI call. More pending are read by further calls.
+const SOLUTIONS_PER_READ: usize = 16;
+const MINER_OUT_OF_MEMORY: &str = "Not enough device memory for the DAG";
+/// `ETHASH_EPOCH_LENGTH` of the C++ miners, the default of `GPU::set_epoch_length`.
+pub const EPOCH_LENGTH: u64 = 30000;
+/// Longest batch time `throttle` scales. The first batch of an epoch includes the DAG
+/// build, which must not turn into minutes of sleep.
+const MAX_THROTTLE_BUSY: Duration = Duration::from_secs(1);
 
 type H256 = [u8; 32];
 
-#[derive(Debug, Clone)]
+/// Epoch of `height` on a chain with `epoch_length` blocks per epoch.
+pub fn epoch_for_height(height: u64, epoch_length: u64) -> i32 {
+	(height / epoch_length) as i32
+}
+
+/// Device memory the C++ miners need for the epoch of `height`: the DAG and the light
+/// cache it is generated from.
+pub fn required_vram(height: u64) -> u64 {
+	unsafe { progpow_gpu_required_memory(height) }
+}
+
+/// The device cannot hold the DAG, see `GPU::check_vram`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VramShortage {
+	pub needed: u64,
+	pub available: u64,
+}
+
+impl fmt::Display for VramShortage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"the DAG needs {} bytes of device memory but only {} are available",
+			self.needed, self.available
+		)
+	}
+}
+
+const UNKNOWN_DRIVER: &str = "Unknown driver";
+const DEVICE_NOT_FOUND: &str = "No device matches the selector";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Driver {
+	/// The first compiled-in driver with a working device, picked by `GPU::new`.
+	Auto = 0,
 	CUDA = 1,
 	OCL = 2,
 }
 
 impl Driver {
-	pub fn from_u8(v: u8) -> Self {
+	/// Drivers this build was compiled with, in order of preference.
+	pub fn compiled() -> Vec<Driver> {
+		let mut drivers = Vec::new();
+		if cfg!(feature = "cuda") {
+			drivers.push(Driver::CUDA);
+		}
+		if cfg!(feature = "opencl") {
+			drivers.push(Driver::OCL);
+		}
+		drivers
+	}
+
+	/// The first of `compiled` that initializes `device` and reports its memory.
+	pub fn detect(device: u32) -> Option<Driver> {
+		Driver::compiled().into_iter().find(|&driver| {
+			let mut gpu = GPU::new(device, driver);
+			gpu.init().is_some() && gpu.total_vram().is_some()
+		})
+	}
+
+	/// `detect` for `Auto`, falling back to the first compiled-in driver; other drivers
+	/// as they are.
+	fn resolve(self, device: u32) -> Driver {
+		match self {
+			Driver::Auto => Driver::detect(device)
+				.or_else(|| Driver::compiled().first().copied())
+				.unwrap_or(Driver::OCL),
+			driver => driver,
+		}
+	}
+}
+
+/// The values the C interface uses, 0 being `Auto`.
+impl TryFrom<u8> for Driver {
+	type Error = &'static str;
+
+	fn try_from(v: u8) -> Result<Self, Self::Error> {
 		match v {
-			1 => Driver::CUDA,
-			2 => Driver::OCL,
-			_ => panic!("driver not exist."),
+			0 => Ok(Driver::Auto),
+			1 => Ok(Driver::CUDA),
+			2 => Ok(Driver::OCL),
+			_ => Err(UNKNOWN_DRIVER),
+		}
+	}
+}
+
+/// `"cuda"`, `"opencl"` or `"auto"`, ignoring case.
+impl FromStr for Driver {
+	type Err = &'static str;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_ascii_lowercase().as_str() {
+			"auto" => Ok(Driver::Auto),
+			"cuda" => Ok(Driver::CUDA),
+			"opencl" | "ocl" => Ok(Driver::OCL),
+			_ => Err(UNKNOWN_DRIVER),
 		}
 	}
 }
 
+impl fmt::Display for Driver {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match self {
+			Driver::Auto => "auto",
+			Driver::CUDA => "cuda",
+			Driver::OCL => "opencl",
+		})
+	}
+}
+
 #[derive(Debug)]
 pub struct GPU {
 	pub driver: Driver,
 	pub device: u32,
 	miner: Option<*mut c_void>,
+	progress: Option<Box<ProgressHandler>>,
+	hashrate: Mutex<HashrateMeter>,
+	paused: AtomicBool,
+	cancelled: AtomicBool,
+	job: Mutex<Option<JobState>>,
+	stale: AtomicU64,
+	duplicates: AtomicU64,
+	intensity: AtomicU32,
+	/// Epoch `check_vram` last passed for, -1 if none.
+	vram_checked: AtomicI32,
+	host_dag_limit: AtomicU64,
+	epoch_length: AtomicU64,
 }
 
 impl GPU {
+	/// `Driver::Auto` is resolved here, probing `device` with each compiled-in driver.
 	pub fn new(device: u32, driver: Driver) -> Self {
 		GPU {
 			device,
-			driver,
+			driver: driver.resolve(device),
 			miner: None,
+			progress: None,
+			hashrate: Mutex::new(HashrateMeter::new(DEFAULT_WINDOW)),
+			paused: AtomicBool::new(false),
+			cancelled: AtomicBool::new(false),
+			job: Mutex::new(None),
+			stale: AtomicU64::new(0),
+			duplicates: AtomicU64::new(0),
+			intensity: AtomicU32::new(100),
+			vram_checked: AtomicI32::new(-1),
+			host_dag_limit: AtomicU64::new(0),
+			epoch_length: AtomicU64::new(EPOCH_LENGTH),
+		}
+	}
+
+	/// The device `selector` names. UUIDs and PCI bus ids are looked up in `enumerate`,
+	/// across every compiled-in driver for `Auto`, so configs survive cards being
+	/// re-ordered.
+	pub fn open(selector: &DeviceSelector, driver: Driver) -> Result<Self, &'static str> {
+		match *selector {
+			DeviceSelector::Index(index) => Ok(GPU::new(index, driver)),
+			_ => selector
+				.find(driver)
+				.map(|info| GPU::new(info.index, info.driver))
+				.ok_or(DEVICE_NOT_FOUND),
+		}
+	}
+
+	pub fn is_initialized(&self) -> bool {
+		self.miner.is_some()
+	}
+
+	/// Stop launching kernels and wait for the ones in flight. The DAG stays on the
+	/// device, so `resume` is immediate.
+	pub fn pause(&self) {
+		self.paused.store(true, Ordering::SeqCst);
+		if let Some(miner) = self.miner {
+			unsafe { progpow_gpu_wait(miner) };
+		}
+	}
+
+	pub fn resume(&self) {
+		self.paused.store(false, Ordering::SeqCst);
+	}
+
+	pub fn is_paused(&self) -> bool {
+		self.paused.load(Ordering::SeqCst)
+	}
+
+	/// Abort a DAG generation or upload in progress on another thread. `compute` fails
+	/// with a cancelled error, leaving the epoch unbuilt, until `clear_cancel`.
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::SeqCst);
+		if let Some(miner) = self.miner {
+			unsafe { progpow_gpu_cancel(miner, true) };
+		}
+	}
+
+	pub fn clear_cancel(&self) {
+		self.cancelled.store(false, Ordering::SeqCst);
+		if let Some(miner) = self.miner {
+			unsafe { progpow_gpu_cancel(miner, false) };
+		}
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.cancelled.load(Ordering::SeqCst)
+	}
+
+	/// Whether the device stopped responding, after it was unplugged, its driver crashed
+	/// or a kernel left its context unusable. Every call then fails without touching the
+	/// device; drop the `GPU` and open the device again once it is back.
+	pub fn is_lost(&self) -> bool {
+		match self.miner {
+			Some(miner) => unsafe { progpow_gpu_lost(miner) },
+			None => false,
+		}
+	}
+
+	/// Pause and free the DAG, keeping the miner. After `resume` the next `compute`
+	/// rebuilds the DAG.
+	pub fn stop(&self) {
+		self.paused.store(true, Ordering::SeqCst);
+		if let Some(miner) = self.miner {
+			unsafe { progpow_gpu_stop(miner) };
+		}
+		self.hashrate.lock().unwrap().reset();
+	}
+
+	/// Hashes per second over the rolling window, see `set_hashrate_window`.
+	pub fn hashrate(&self) -> f64 {
+		self.hashrate.lock().unwrap().rate()
+	}
+
+	/// Hashes per second since the first `compute`.
+	pub fn average_hashrate(&self) -> f64 {
+		self.hashrate.lock().unwrap().average(Instant::now())
+	}
+
+	/// Nonces searched since the first `compute`.
+	pub fn nonces_searched(&self) -> u64 {
+		self.hashrate.lock().unwrap().total()
+	}
+
+	/// Nonces one `compute` searches, 0 before `init`.
+	pub fn batch_size(&self) -> u64 {
+		match self.miner {
+			Some(miner) => unsafe { progpow_gpu_batch_size(miner) },
+			None => 0,
 		}
 	}
 
+	pub fn set_hashrate_window(&self, window: Duration) {
+		self.hashrate.lock().unwrap().set_window(window);
+	}
+
+	/// Report DAG generation and upload progress to `handler`. The DAG is built during
+	/// the first `compute` of each epoch, which otherwise blocks silently for minutes.
+	pub fn set_progress_handler<F>(&mut self, handler: F)
+	where
+		F: Fn(DagProgress) + Send + Sync + 'static,
+	{
+		self.progress = Some(Box::new(ProgressHandler::new(handler)));
+		self.register_progress();
+	}
+
+	fn register_progress(&self) {
+		if let (Some(miner), Some(progress)) = (self.miner, self.progress.as_ref()) {
+			let user = &**progress as *const ProgressHandler as *mut c_void;
+			unsafe {
+				progpow_gpu_set_progress(miner, Some(progress_callback), user);
+			}
+		}
+	}
+
+	/// Create the miner. Returns `None` if already cancelled.
 	pub fn init(&mut self) -> Option<*mut c_void> {
-		let driver = self.driver.clone() as u32;
+		if self.is_cancelled() {
+			return None;
+		}
+
+		let driver = self.driver as u32;
 
 		let miner = unsafe {
 			progpow_gpu_configure(0);
@@ -46,60 +319,357 @@ impl GPU {
 		};
 
 		self.miner = Some(miner);
+		self.register_progress();
+		unsafe { progpow_gpu_set_host_dag_limit(miner, self.host_dag_limit()) };
+		if self.is_cancelled() {
+			unsafe { progpow_gpu_cancel(miner, true) };
+			return None;
+		}
 		self.miner
 	}
 
-	pub fn compute(
+	/// Search one batch of `job` from `start_nonce`. A job with a new `job_id` supersedes
+	/// the previous one, whose pending solution is dropped.
+	///
+	/// The top bytes of `start_nonce` are replaced by `job.extranonce`, and nonces of the
+	/// batch past its last nonce are not reported, so solutions always carry it.
+	pub fn compute(&self, job: &MiningJob, start_nonce: u64) -> Result<(), &str> {
+		let busy = self.compute_unthrottled(job, start_nonce)?;
+		self.throttle(busy);
+		Ok(())
+	}
+
+	/// Search the batch after the last one searched for `job`, from nonce 0 (in its
+	/// extranonce) when the job is new. Polling loops call this repeatedly instead of
+	/// `compute` with a fixed start, which would hash the same nonces every time.
+	pub fn search_next(&self, job: &MiningJob) -> Result<(), &str> {
+		self.compute(job, self.next_nonce(job.job_id))
+	}
+
+	pub(crate) fn next_nonce(&self, job_id: u64) -> u64 {
+		match *self.job.lock().unwrap() {
+			Some(ref job) if job.job_id == job_id => job.next_nonce,
+			_ => 0,
+		}
+	}
+
+	/// Percentage of time spent searching, from 1 to 100 (the default). Lower values make
+	/// `compute` sleep after each batch, so a desktop stays responsive while mining.
+	pub fn set_intensity(&self, percent: u32) {
+		self.intensity
+			.store(percent.max(1).min(100), Ordering::Relaxed);
+	}
+
+	pub fn intensity(&self) -> u32 {
+		self.intensity.load(Ordering::Relaxed)
+	}
+
+	/// Sleep long enough after a batch that took `busy` to keep the duty cycle at
+	/// `intensity`.
+	pub(crate) fn throttle(&self, busy: Duration) {
+		let intensity = self.intensity();
+		if intensity < 100 {
+			thread::sleep(busy.min(MAX_THROTTLE_BUSY) * (100 - intensity) / intensity);
+		}
+	}
+
+	/// `compute` without the intensity sleep. Returns the time the batch took.
+	pub(crate) fn compute_unthrottled(
 		&self,
-		hash: [u8; 32],
-		height: u64,
-		epoch: i32,
-		target: u64,
-		startNonce: u64,
-	) -> Result<(), &str> {
+		job: &MiningJob,
+		start_nonce: u64,
+	) -> Result<Duration, &str> {
 		if let None = self.miner {
 			return Err(MINER_UNINITIALIZED);
 		}
 
+		if self.is_paused() {
+			return Err(MINER_PAUSED);
+		}
+
+		if self.is_cancelled() {
+			return Err(MINER_CANCELLED);
+		}
+
+		if self.is_lost() {
+			return Err(DEVICE_LOST);
+		}
+
 		let miner = self.miner.unwrap();
+		job.validate(self.epoch_length()).map_err(|e| e.as_str())?;
+		self.preflight(job.height, job.epoch)?;
+		self.track_job(miner, job.job_id);
+
+		let start_nonce = job.extranonce.apply(start_nonce);
+		let count = (job.extranonce.last() - start_nonce)
+			.saturating_add(1)
+			.min(u32::MAX as u64) as u32;
 
+		let start = Instant::now();
 		unsafe {
 			progpow_gpu_compute(
 				miner,
-				hash.as_ptr() as *const c_void,
+				job.header.as_ptr() as *const c_void,
+				job.height,
+				job.epoch,
+				job.target.as_ptr() as *const c_void,
+				start_nonce,
+				count,
+			);
+		}
+
+		// The DAG build was aborted, no batch ran.
+		if self.is_cancelled() {
+			return Err(MINER_CANCELLED);
+		}
+
+		if self.is_lost() {
+			return Err(DEVICE_LOST);
+		}
+
+		let batch = unsafe { progpow_gpu_batch_size(miner) };
+		self.hashrate.lock().unwrap().record(Instant::now(), batch);
+		if let Some(ref mut job) = *self.job.lock().unwrap() {
+			job.next_nonce = start_nonce.wrapping_add(batch);
+		}
+
+		Ok(start.elapsed())
+	}
+
+	/// Run the kernel for `nonce` alone and return its `(final_hash, mix_hash)`, for
+	/// comparing the device with a CPU verifier. `final_hash` is finished on the host from
+	/// the device mix. Builds the DAG if `height` is in another epoch, and drops a
+	/// solution still pending from `compute`.
+	pub fn hash_one(&self, header: &H256, height: u64, nonce: u64) -> Result<(H256, H256), &str> {
+		let miner = match self.miner {
+			Some(miner) => miner,
+			None => return Err(MINER_UNINITIALIZED),
+		};
+		if self.is_cancelled() {
+			return Err(MINER_CANCELLED);
+		}
+		if self.is_lost() {
+			return Err(DEVICE_LOST);
+		}
+		let epoch = epoch_for_height(height, self.epoch_length());
+		self.preflight(height, epoch)?;
+
+		let mut mix = [0u8; 32];
+		let done = unsafe {
+			progpow_gpu_hash_one(
+				miner,
+				header.as_ptr() as *const c_void,
 				height,
 				epoch,
-				target,
-				startNonce,
-			);
+				nonce,
+				mix.as_mut_ptr() as *mut c_void,
+			)
+		};
+		if !done {
+			return Err(if self.is_lost() {
+				DEVICE_LOST
+			} else {
+				MINER_CANCELLED
+			});
+		}
+		Ok((final_hash(header, nonce, &mix), mix))
+	}
+
+	/// Start building the DAG and kernel for the epoch of `height` in the background, on a
+	/// stream (CUDA) or context (OpenCL) of its own, while `compute` keeps searching the
+	/// current epoch. The first `compute` in that epoch then switches over without
+	/// rebuilding. Needs room for both DAGs on the device; without it, or if the build
+	/// fails, the switch rebuilds in place as usual. DAG progress is reported for the
+	/// background build too.
+	///
+	/// The device holds one standby epoch: after the switch the previous epoch takes that
+	/// place, so switching back to it is just as quick, until the next prefetch.
+	///
+	/// Returns `Ok(false)` if nothing was started: before the first `compute`, for the
+	/// current epoch, or when cancelled. `Ok(true)` if the epoch is building or resident.
+	pub fn prefetch(&self, height: u64) -> Result<bool, &str> {
+		let miner = match self.miner {
+			Some(miner) => miner,
+			None => return Err(MINER_UNINITIALIZED),
+		};
+		if self.is_lost() {
+			return Err(DEVICE_LOST);
+		}
+		let epoch = epoch_for_height(height, self.epoch_length());
+		Ok(unsafe { progpow_gpu_prefetch(miner, height, epoch) })
+	}
+
+	/// Total device memory, `None` before `init` or if the driver does not report it.
+	pub fn total_vram(&self) -> Option<u64> {
+		match self.miner {
+			Some(miner) => match unsafe { progpow_gpu_total_memory(miner) } {
+				0 => None,
+				total => Some(total),
+			},
+			None => None,
 		}
+	}
 
+	/// Whether the device can hold the DAG of `height`, see `required_vram`. Passes if the
+	/// device memory is unknown, or if the missing bytes fit in `set_host_dag_limit`.
+	pub fn check_vram(&self, height: u64) -> Result<(), VramShortage> {
+		let needed = required_vram(height);
+		match self.total_vram() {
+			Some(available) if available + self.host_dag_limit() < needed => {
+				Err(VramShortage { needed, available })
+			}
+			_ => Ok(()),
+		}
+	}
+
+	/// Let up to `bytes` of the DAG stay in host memory when the device is slightly too
+	/// small for it, instead of refusing the epoch. The tail of the DAG is then read by
+	/// the kernel over PCIe, so the hashrate drops roughly with the share of DAG accesses
+	/// that land there; compare `hashrate` with and without the split to measure it. A
+	/// split DAG also leaves no room to `prefetch`. CUDA only, 0 (the default) disables
+	/// it.
+	pub fn set_host_dag_limit(&self, bytes: u64) {
+		let bytes = match self.driver {
+			Driver::CUDA => bytes,
+			Driver::OCL | Driver::Auto => 0,
+		};
+		self.host_dag_limit.store(bytes, Ordering::Relaxed);
+		self.vram_checked.store(-1, Ordering::Relaxed);
+		if let Some(miner) = self.miner {
+			unsafe { progpow_gpu_set_host_dag_limit(miner, bytes) };
+		}
+	}
+
+	pub fn host_dag_limit(&self) -> u64 {
+		self.host_dag_limit.load(Ordering::Relaxed)
+	}
+
+	/// Bytes of the current DAG kept in host memory, 0 unless the DAG is split.
+	pub fn host_dag_bytes(&self) -> u64 {
+		match self.miner {
+			Some(miner) => unsafe { progpow_gpu_host_dag_bytes(miner) },
+			None => 0,
+		}
+	}
+
+	/// `check_vram` once per epoch, before the C++ miner tries to allocate a DAG that
+	/// does not fit and exits.
+	fn preflight(&self, height: u64, epoch: i32) -> Result<(), &str> {
+		if self.vram_checked.load(Ordering::Relaxed) != epoch {
+			self.check_vram(height).map_err(|_| MINER_OUT_OF_MEMORY)?;
+			self.vram_checked.store(epoch, Ordering::Relaxed);
+		}
 		Ok(())
 	}
 
-	pub fn solutions(&self) -> Result<Option<(u64, [u8; 32])>, &str> {
+	/// `prefetch` from the first block of `epoch`.
+	pub fn prepare_epoch(&self, epoch: u64) -> Result<bool, &str> {
+		self.prefetch(epoch * self.epoch_length())
+	}
+
+	/// Blocks per epoch of the chain mined, `EPOCH_LENGTH` by default. Jobs are checked
+	/// against it, and `prefetch` and `hash_one` take the epoch of a height from it.
+	pub fn set_epoch_length(&self, blocks: u64) {
+		self.epoch_length.store(blocks.max(1), Ordering::Relaxed);
+		self.vram_checked.store(-1, Ordering::Relaxed);
+	}
+
+	pub fn epoch_length(&self) -> u64 {
+		self.epoch_length.load(Ordering::Relaxed)
+	}
+
+	/// Id of the job last passed to `compute`.
+	pub fn job_id(&self) -> Option<u64> {
+		self.job.lock().unwrap().as_ref().map(|job| job.job_id)
+	}
+
+	/// Solutions dropped because their job was superseded before they were collected.
+	pub fn stale_solutions(&self) -> u64 {
+		self.stale.load(Ordering::Relaxed)
+	}
+
+	/// Solutions dropped because their nonce was already returned for the same job.
+	pub fn duplicate_solutions(&self) -> u64 {
+		self.duplicates.load(Ordering::Relaxed)
+	}
+
+	/// Every solution found for the current job since the last call, oldest first. A
+	/// nonce is returned once per job, however often it is found again.
+	pub fn solutions(&self) -> Result<Vec<Solution>, &str> {
 		if let None = self.miner {
 			return Err(MINER_UNINITIALIZED);
 		}
 
-		let miner = self.miner.unwrap();
-		let mut result = [0u8; 40];
+		if self.is_lost() {
+			return Err(DEVICE_LOST);
+		}
 
-		let found: bool =
-			unsafe { progpow_gpu_get_solutions(miner, result.as_mut_ptr() as *mut c_void) };
+		let mut job = self.job.lock().unwrap();
+		let mut solutions = self.read_solutions(self.miner.unwrap(), job.as_ref());
+		if let Some(ref mut job) = *job {
+			let found = solutions.len();
+			solutions.retain(|solution| job.returned.insert(solution.nonce));
+			self.duplicates
+				.fetch_add((found - solutions.len()) as u64, Ordering::Relaxed);
+		}
+		Ok(solutions)
+	}
+
+	/// Switch to `job_id`. The solutions the device still holds for the previous job are
+	/// collected and dropped, so they are never reported against the new header.
+	fn track_job(&self, miner: *mut c_void, job_id: u64) {
+		let mut job = self.job.lock().unwrap();
+		if job.as_ref().map_or(false, |job| job.job_id == job_id) {
+			return;
+		}
+
+		let stale = self.read_solutions(miner, job.as_ref()).len();
+		self.stale.fetch_add(stale as u64, Ordering::Relaxed);
 
-		if found {
-			let mut n = [0u8; 8];
-			n.copy_from_slice(&result[0..8]);
+		*job = Some(JobState {
+			job_id,
+			started: Instant::now(),
+			returned: HashSet::new(),
+			next_nonce: 0,
+		});
+	}
+
+	fn read_solutions(&self, miner: *mut c_void, job: Option<&JobState>) -> Vec<Solution> {
+		let (job_id, search_duration) = job.map_or((0, Duration::from_secs(0)), |job| {
+			(job.job_id, job.started.elapsed())
+		});
+
+		let mut solutions = Vec::new();
+		loop {
+			let mut results = [0u8; SOLUTION_BYTES * SOLUTIONS_PER_READ];
+			let found = unsafe {
+				progpow_gpu_get_solutions(
+					miner,
+					results.as_mut_ptr() as *mut c_void,
+					SOLUTIONS_PER_READ as u32,
+				)
+			} as usize;
 
-			let mut mix = [0u8; 32];
-			mix.copy_from_slice(&result[8..40]);
+			for result in results.chunks(SOLUTION_BYTES).take(found) {
+				// The C++ miners write the nonce as a native uint64_t.
+				let mut n = [0u8; 8];
+				n.copy_from_slice(&result[0..8]);
 
-			let nonce: u64 = unsafe { ::std::mem::transmute(n) };
+				let mut mix_hash = [0u8; 32];
+				mix_hash.copy_from_slice(&result[8..40]);
 
-			Ok(Some((nonce, mix)))
-		} else {
-			Ok(None)
+				solutions.push(Solution {
+					nonce: u64::from_ne_bytes(n),
+					mix_hash,
+					final_hash: None,
+					device_id: self.device,
+					job_id,
+					search_duration,
+				});
+			}
+			if found < SOLUTIONS_PER_READ {
+				return solutions;
+			}
 		}
 	}
 }
@@ -107,6 +677,7 @@ impl GPU {
 impl Drop for GPU {
 	fn drop(&mut self) {
 		if let Some(miner) = self.miner {
+			// Waits for in-flight kernels before the miner is freed.
 			unsafe {
 				progpow_destroy(miner);
 			}
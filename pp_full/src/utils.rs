@@ -1,20 +1,22 @@
+use job::{MiningJob, Solution};
 use std::{thread, time};
 use types::{Driver, GPU};
 
-pub fn get_gpu_solution(header: [u8; 32], height: u64, epoch: i32, target: u64) -> (u64, [u8; 32]) {
+pub fn get_gpu_solution(header: [u8; 32], height: u64, epoch: i32, target: [u8; 32]) -> Solution {
 	let mut pp_gpu = GPU::new(0, Driver::OCL);
 
 	pp_gpu.init();
 	let ten_millis = time::Duration::from_millis(100);
 
+	let job = MiningJob::new(0, header, height, epoch, target);
 	loop {
-		pp_gpu.compute(header, height, epoch, target, 0);
+		pp_gpu.search_next(&job);
 
 		thread::sleep(ten_millis);
 
-		let solution = pp_gpu.solutions().unwrap();
+		let solutions = pp_gpu.solutions().unwrap();
 
-		if let Some(sol) = solution {
+		if let Some(sol) = solutions.into_iter().next() {
 			return sol;
 		}
 	}
@@ -0,0 +1,43 @@
+/* C interface of the progpow_cpu light verifier, built with `--features capi`. */
+
+#ifndef PROGPOW_LIGHT_H
+#define PROGPOW_LIGHT_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define PROGPOW_OK 0
+#define PROGPOW_EINVAL -1
+#define PROGPOW_ECACHE -2
+
+#define PROGPOW_MAPPING_STANDARD 0
+#define PROGPOW_MAPPING_ZANO 1
+
+typedef struct progpow_light progpow_light;
+
+/* Directory used by progpow_verify for light caches. NULL restores the default. */
+int progpow_set_cache_dir(const char *path);
+
+/* Hash a 32-byte header with nonce at height. out_hash receives the final hash as
+ * big-endian words, out_mix the mix hash as little-endian words, 32 bytes each. */
+int progpow_verify(const uint8_t *header, uint64_t height, uint64_t nonce,
+		uint8_t *out_hash, uint8_t *out_mix);
+
+/* Explicit light cache for the epoch of height. NULL on failure. */
+progpow_light *progpow_light_new(const char *cache_dir, uint64_t height);
+void progpow_light_free(progpow_light *light);
+int progpow_light_verify(const progpow_light *light, uint32_t mapping,
+		const uint8_t *header, uint64_t height, uint64_t nonce,
+		uint8_t *out_hash, uint8_t *out_mix);
+
+uint64_t progpow_epoch(uint64_t height);
+int progpow_seed_hash(uint64_t epoch, uint8_t *out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
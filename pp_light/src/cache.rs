@@ -14,26 +14,197 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity Ethereum.  If not, see <http://www.gnu.org/licenses/>.
 
+use crate::cancel::{is_cancelled, CancelToken};
 use crate::compute::Light;
 use crate::either::Either;
-use crate::keccak::{keccak_512, H256};
+use crate::keccak::{keccak_256, keccak_512, H256};
 use crate::seed_compute::SeedHashCompute;
-use memmap::MmapMut;
-use parking_lot::Mutex;
+#[cfg(feature = "mmap")]
+use memmap::{MmapMut, MmapOptions};
+use progpow_base::params::ProgPowParams;
+use twox_hash::XxHash64;
 
 use crate::shared::{
-	epoch, get_cache_size, to_hex, Node, ETHASH_CACHE_ROUNDS, NODE_BYTES, NODE_DWORDS,
+	epoch, get_cache_size, to_hex, Node, CACHE_BYTES_GROWTH, CACHE_BYTES_INIT,
+	DATASET_BYTES_GROWTH, DATASET_BYTES_INIT, ETHASH_CACHE_ROUNDS, ETHASH_DATASET_PARENTS,
+	NODE_BYTES, NODE_DWORDS,
 };
 
 use std::borrow::Cow;
-use std::fs;
-use std::io::{self, Read, Write};
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::slice;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 type Cache = Either<Vec<Node>, MmapMut>;
 
+/// Stand-in for `memmap::MmapMut` when built without `mmap`, e.g. for wasm. Never
+/// constructed, so every cache lives in memory.
+#[cfg(not(feature = "mmap"))]
+enum MmapMut {}
+
+#[cfg(not(feature = "mmap"))]
+impl MmapMut {
+	fn flush(&self) -> io::Result<()> {
+		match *self {}
+	}
+}
+
+#[cfg(not(feature = "mmap"))]
+impl std::ops::Deref for MmapMut {
+	type Target = [u8];
+
+	fn deref(&self) -> &[u8] {
+		match *self {}
+	}
+}
+
+const CACHE_MAGIC: [u8; 4] = *b"PPLC";
+const CACHE_VERSION: u32 = 1;
+/// A whole node, so the payload stays node aligned when memory mapped.
+pub(crate) const HEADER_BYTES: usize = NODE_BYTES;
+
+pub(crate) fn invalid_cache(msg: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Streaming form of `checksum`, for payloads too large to hold at once.
+pub(crate) fn checksum_hasher() -> XxHash64 {
+	XxHash64::with_seed(0)
+}
+
+fn checksum(payload: &[u8]) -> u64 {
+	let mut hasher = checksum_hasher();
+	hasher.write(payload);
+	hasher.finish()
+}
+
+/// Identifies `P` and the constants the cache and DAG contents depend on, so files
+/// written for another chain or with other parameters are rejected.
+pub(crate) fn params_id<P: ProgPowParams>() -> u64 {
+	checksum(
+		format!(
+			"{}:{}:{}:{}:{}:{}:{}:{}",
+			P::NAME,
+			P::EPOCH_LENGTH,
+			CACHE_BYTES_INIT,
+			CACHE_BYTES_GROWTH,
+			ETHASH_CACHE_ROUNDS,
+			DATASET_BYTES_INIT,
+			DATASET_BYTES_GROWTH,
+			ETHASH_DATASET_PARENTS
+		)
+		.as_bytes(),
+	)
+}
+
+/// Header at the start of every cache and DAG file: magic, version, epoch, params id,
+/// payload length and xxhash64 of the payload, little-endian.
+pub(crate) struct CacheHeader {
+	magic: [u8; 4],
+	epoch: u64,
+	params_id: u64,
+	pub(crate) payload_len: u64,
+	checksum: u64,
+}
+
+impl CacheHeader {
+	pub(crate) fn new(magic: [u8; 4], epoch: u64, params_id: u64, payload: &[u8]) -> Self {
+		CacheHeader::with_checksum(
+			magic,
+			epoch,
+			params_id,
+			payload.len() as u64,
+			checksum(payload),
+		)
+	}
+
+	pub(crate) fn with_checksum(
+		magic: [u8; 4],
+		epoch: u64,
+		params_id: u64,
+		payload_len: u64,
+		checksum: u64,
+	) -> Self {
+		CacheHeader {
+			magic,
+			epoch,
+			params_id,
+			payload_len,
+			checksum,
+		}
+	}
+
+	pub(crate) fn to_bytes(&self) -> [u8; HEADER_BYTES] {
+		let mut bytes = [0u8; HEADER_BYTES];
+		bytes[0..4].copy_from_slice(&self.magic);
+		bytes[4..8].copy_from_slice(&CACHE_VERSION.to_le_bytes());
+		bytes[8..16].copy_from_slice(&self.epoch.to_le_bytes());
+		bytes[16..24].copy_from_slice(&self.params_id.to_le_bytes());
+		bytes[24..32].copy_from_slice(&self.payload_len.to_le_bytes());
+		bytes[32..40].copy_from_slice(&self.checksum.to_le_bytes());
+		bytes
+	}
+
+	pub(crate) fn read<R: Read>(reader: &mut R, magic: [u8; 4]) -> io::Result<Self> {
+		let mut bytes = [0u8; HEADER_BYTES];
+		reader
+			.read_exact(&mut bytes)
+			.map_err(|_| invalid_cache("Node cache header is truncated"))?;
+		let word = |i: usize| {
+			let mut w = [0u8; 8];
+			w.copy_from_slice(&bytes[i..i + 8]);
+			u64::from_le_bytes(w)
+		};
+
+		if bytes[0..4] != magic {
+			return Err(invalid_cache("Node cache has no header"));
+		}
+		let mut version = [0u8; 4];
+		version.copy_from_slice(&bytes[4..8]);
+		if u32::from_le_bytes(version) != CACHE_VERSION {
+			return Err(invalid_cache("Node cache version is not supported"));
+		}
+		Ok(CacheHeader {
+			magic,
+			epoch: word(8),
+			params_id: word(16),
+			payload_len: word(24),
+			checksum: word(32),
+		})
+	}
+
+	/// Check the header against the expected epoch and params id, and the payload
+	/// actually read.
+	pub(crate) fn validate(&self, epoch: u64, params_id: u64, payload: &[u8]) -> io::Result<()> {
+		if self.epoch != epoch {
+			return Err(invalid_cache("Node cache is for another epoch"));
+		}
+		if self.params_id != params_id {
+			return Err(invalid_cache("Node cache was built with other parameters"));
+		}
+		if self.payload_len != payload.len() as u64 {
+			return Err(invalid_cache("Node cache is truncated"));
+		}
+		if self.checksum != checksum(payload) {
+			return Err(invalid_cache("Node cache checksum mismatch"));
+		}
+		Ok(())
+	}
+}
+
+fn cache_bytes(cache: &Cache) -> &[u8] {
+	match *cache {
+		Either::Left(ref vec) => unsafe {
+			slice::from_raw_parts(vec.as_ptr() as *const u8, vec.len() * NODE_BYTES)
+		},
+		Either::Right(ref mmap) => mmap,
+	}
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum OptimizeFor {
 	Cpu,
@@ -55,15 +226,75 @@ fn byte_size(cache: &Cache) -> usize {
 	}
 }
 
-fn new_buffer(path: &Path, num_nodes: usize, ident: &H256, optimize_for: OptimizeFor) -> Cache {
-	let memmap = match optimize_for {
-		OptimizeFor::Cpu => None,
-		OptimizeFor::Memory => make_memmapped_cache(path, num_nodes, ident).ok(),
-	};
+/// How far a light cache build got, as passed to `NodeCacheBuilder::on_progress`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheProgress {
+	pub epoch: u64,
+	/// Node hashes done, counting the initial hash chain and each of the
+	/// `ETHASH_CACHE_ROUNDS` passes over it.
+	pub done: usize,
+	pub total: usize,
+	pub elapsed: Duration,
+}
 
-	memmap
-		.map(Either::Right)
-		.unwrap_or_else(|| Either::Left(make_memory_cache(num_nodes, ident)))
+impl CacheProgress {
+	pub fn percent(&self) -> f64 {
+		self.done as f64 * 100.0 / self.total as f64
+	}
+
+	/// Time left at the rate so far, `None` until some nodes are done.
+	pub fn eta(&self) -> Option<Duration> {
+		if self.done == 0 {
+			return None;
+		}
+		let per_node = self.elapsed.as_secs_f64() / self.done as f64;
+		Some(Duration::from_secs_f64(
+			per_node * (self.total - self.done) as f64,
+		))
+	}
+}
+
+type ProgressFn = dyn Fn(&CacheProgress) + Send + Sync;
+
+/// What a cache build reports to, checked every `CANCEL_CHECK_NODES` nodes.
+struct Build<'a> {
+	cancel: &'a CancelToken,
+	progress: Option<&'a ProgressFn>,
+	epoch: u64,
+	start: Instant,
+}
+
+impl Build<'_> {
+	fn checkpoint(&self, done: usize, total: usize) -> io::Result<()> {
+		self.cancel.check()?;
+		if let Some(progress) = self.progress {
+			progress(&CacheProgress {
+				epoch: self.epoch,
+				done,
+				total,
+				elapsed: self.start.elapsed(),
+			});
+		}
+		Ok(())
+	}
+}
+
+fn new_buffer(
+	path: &Path,
+	num_nodes: usize,
+	ident: &H256,
+	optimize_for: OptimizeFor,
+	build: &Build,
+) -> io::Result<Cache> {
+	if optimize_for == OptimizeFor::Memory {
+		match make_memmapped_cache(path, num_nodes, ident, build) {
+			Ok(memmap) => return Ok(Either::Right(memmap)),
+			Err(e) if is_cancelled(&e) => return Err(e),
+			Err(_) => {}
+		}
+	}
+
+	make_memory_cache(num_nodes, ident, build).map(Either::Left)
 }
 
 #[derive(Clone)]
@@ -71,6 +302,35 @@ pub struct NodeCacheBuilder {
 	// TODO: Remove this locking and just use an `Rc`?
 	seedhash: Arc<Mutex<SeedHashCompute>>,
 	optimize_for: OptimizeFor,
+	/// Number of most recent epochs whose cache files are kept, `None` keeps all.
+	keep_epochs: Option<u64>,
+	/// Worker threads for the parallel parts of building a cache, `None` uses rayon's
+	/// global pool.
+	threads: Option<usize>,
+	progress: Option<Arc<ProgressFn>>,
+}
+
+/// Nodes hashed between checks of a `CancelToken`, and between progress reports.
+const CANCEL_CHECK_NODES: usize = 1 << 12;
+
+/// Epochs kept by default: the current one and the previous one, for reorgs across an
+/// epoch boundary.
+const DEFAULT_KEEP_EPOCHS: u64 = 2;
+
+/// Last epoch `epoch_from_seed` tries, past block 60 million.
+pub const MAX_SEED_EPOCH: u64 = 2048;
+
+/// Epoch whose seed hash is `seed`, as pools receive it in stratum notifies, or `None`
+/// if it is not one of the first `MAX_SEED_EPOCH` epochs.
+pub fn epoch_from_seed(seed: &H256) -> Option<u64> {
+	let mut hash = [0u8; 32];
+	for epoch in 0..=MAX_SEED_EPOCH {
+		if hash == *seed {
+			return Some(epoch);
+		}
+		keccak_256::inplace(&mut hash);
+	}
+	None
 }
 
 // TODO: Abstract the "optimize for" logic
@@ -78,54 +338,166 @@ pub struct NodeCache {
 	builder: NodeCacheBuilder,
 	cache_dir: Cow<'static, Path>,
 	cache_path: PathBuf,
+	/// `NAME` and `params_id` of the `P` the cache was built for.
+	name: &'static str,
+	params_id: u64,
 	epoch: u64,
 	cache: Cache,
+	/// Held from building the cache until it is flushed or dropped.
+	lock: Option<CacheLock>,
 }
 
 impl NodeCacheBuilder {
-	pub fn light(&self, cache_dir: &Path, block_number: u64) -> Light {
-		Light::new_with_builder(self, cache_dir, block_number)
+	pub fn light<P: ProgPowParams>(&self, cache_dir: &Path, block_number: u64) -> Light {
+		Light::new_with_builder::<P>(self, cache_dir, block_number)
+	}
+
+	/// Like `light`, for the epoch whose seed hash is `seed_hash` (see `epoch_from_seed`).
+	/// Fails with `InvalidInput` if no epoch has it.
+	pub fn light_from_seed<P: ProgPowParams>(
+		&self,
+		cache_dir: &Path,
+		seed_hash: &H256,
+	) -> io::Result<Light> {
+		let epoch = epoch_from_seed(seed_hash).ok_or_else(|| {
+			io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("Unknown seed hash {}", to_hex(seed_hash)),
+			)
+		})?;
+		Ok(self.light::<P>(cache_dir, epoch * P::EPOCH_LENGTH))
 	}
 
-	pub fn light_from_file(&self, cache_dir: &Path, block_number: u64) -> io::Result<Light> {
-		Light::from_file_with_builder(self, cache_dir, block_number)
+	pub fn light_from_file<P: ProgPowParams>(
+		&self,
+		cache_dir: &Path,
+		block_number: u64,
+	) -> io::Result<Light> {
+		Light::from_file_with_builder::<P>(self, cache_dir, block_number)
+	}
+
+	/// Like `light`, stopping early with a `Cancelled` error once `cancel` is set.
+	pub fn light_cancellable<P: ProgPowParams>(
+		&self,
+		cache_dir: &Path,
+		block_number: u64,
+		cancel: &CancelToken,
+	) -> io::Result<Light> {
+		Light::new_cancellable_with_builder::<P>(self, cache_dir, block_number, cancel)
 	}
 
 	pub fn new<T: Into<Option<OptimizeFor>>>(optimize_for: T) -> Self {
 		NodeCacheBuilder {
 			seedhash: Arc::new(Mutex::new(SeedHashCompute::default())),
 			optimize_for: optimize_for.into().unwrap_or_default(),
+			keep_epochs: Some(DEFAULT_KEEP_EPOCHS),
+			threads: None,
+			progress: None,
 		}
 	}
 
-	fn block_number_to_ident(&self, block_number: u64) -> H256 {
-		self.seedhash.lock().hash_block_number(block_number)
+	/// Call `progress` from the building thread as each new cache is built, so a node
+	/// can log how far it is instead of appearing frozen. It is called every few
+	/// thousand nodes and once the cache is complete; caches loaded from files are not
+	/// reported.
+	pub fn on_progress<F>(mut self, progress: F) -> Self
+	where
+		F: Fn(&CacheProgress) + Send + Sync + 'static,
+	{
+		self.progress = Some(Arc::new(progress));
+		self
+	}
+
+	/// How caches built or loaded from now on are held, keeping the other options.
+	pub fn optimize_for(mut self, optimize_for: OptimizeFor) -> Self {
+		self.optimize_for = optimize_for;
+		self
+	}
+
+	/// Threads used to derive DAG items from the cache: the L1 cache of every `Light` and
+	/// the full DAG. The cache itself is a sequential hash chain and stays single-threaded.
+	pub fn threads(mut self, threads: usize) -> Self {
+		self.threads = Some(threads.max(1));
+		self
+	}
+
+	/// Run `f` on this builder's thread pool, so rayon iterators inside it use `threads`.
+	pub(crate) fn install<R, F>(&self, f: F) -> R
+	where
+		R: Send,
+		F: FnOnce() -> R + Send,
+	{
+		let pool = self.threads.and_then(|threads| {
+			rayon::ThreadPoolBuilder::new()
+				.num_threads(threads)
+				.build()
+				.ok()
+		});
+		match pool {
+			Some(pool) => pool.install(f),
+			None => f(),
+		}
+	}
+
+	/// After writing a cache file, delete the files of epochs older than the last
+	/// `n_epochs` (at least 1). Defaults to 2.
+	pub fn keep_last(mut self, n_epochs: u64) -> Self {
+		self.keep_epochs = Some(n_epochs.max(1));
+		self
+	}
+
+	/// Never delete old cache files.
+	pub fn keep_all(mut self) -> Self {
+		self.keep_epochs = None;
+		self
+	}
+
+	pub(crate) fn block_number_to_ident<P: ProgPowParams>(&self, block_number: u64) -> H256 {
+		self.seedhash
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.hash_block_number::<P>(block_number)
 	}
 
 	fn epoch_to_ident(&self, epoch: u64) -> H256 {
-		self.seedhash.lock().hash_epoch(epoch)
+		self.seedhash
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.hash_epoch(epoch)
 	}
 
-	pub fn from_file<P: Into<Cow<'static, Path>>>(
+	pub fn from_file<P: ProgPowParams>(
 		&self,
-		cache_dir: P,
+		cache_dir: impl Into<Cow<'static, Path>>,
 		block_number: u64,
 	) -> io::Result<NodeCache> {
 		let cache_dir = cache_dir.into();
-		let ident = self.block_number_to_ident(block_number);
-
-		let path = cache_path(cache_dir.as_ref(), &ident);
-
-		let cache = cache_from_path(&path, self.optimize_for)?;
-		let expected_cache_size = get_cache_size(block_number);
+		let epoch = epoch::<P>(block_number);
+		let path = cache_path(cache_dir.as_ref(), P::NAME, &self.epoch_to_ident(epoch));
+
+		let cache = match cache_from_path(&path, self.optimize_for, epoch, params_id::<P>()) {
+			Ok(cache) => cache,
+			Err(e) => {
+				// Drop a damaged or outdated file so the regenerated cache replaces it, unless
+				// another process is still building it.
+				if e.kind() == io::ErrorKind::InvalidData && !lock_path(&path).exists() {
+					let _ = fs::remove_file(&path);
+				}
+				return Err(e);
+			}
+		};
+		let expected_cache_size = get_cache_size::<P>(block_number);
 
 		if byte_size(&cache) == expected_cache_size {
 			Ok(NodeCache {
 				builder: self.clone(),
-				epoch: epoch(block_number),
+				epoch,
 				cache_dir: cache_dir,
 				cache_path: path,
+				name: P::NAME,
+				params_id: params_id::<P>(),
 				cache: cache,
+				lock: None,
 			})
 		} else {
 			Err(io::Error::new(
@@ -135,31 +507,100 @@ impl NodeCacheBuilder {
 		}
 	}
 
-	pub fn new_cache<P: Into<Cow<'static, Path>>>(
+	/// Cache for `block_number` from bytes loaded elsewhere, either a cache file or its
+	/// bare payload. The result is never written to disk.
+	pub fn from_bytes<P: ProgPowParams>(
+		&self,
+		block_number: u64,
+		bytes: &[u8],
+	) -> io::Result<NodeCache> {
+		let expected_cache_size = get_cache_size::<P>(block_number);
+		let payload = if bytes.len() == HEADER_BYTES + expected_cache_size {
+			let header = CacheHeader::read(&mut &bytes[..HEADER_BYTES], CACHE_MAGIC)?;
+			let payload = &bytes[HEADER_BYTES..];
+			header.validate(epoch::<P>(block_number), params_id::<P>(), payload)?;
+			payload
+		} else if bytes.len() == expected_cache_size {
+			bytes
+		} else {
+			return Err(invalid_cache("Node cache is of incorrect size"));
+		};
+
+		let mut nodes = vec![
+			Node {
+				bytes: [0u8; NODE_BYTES]
+			};
+			payload.len() / NODE_BYTES
+		];
+		for (node, chunk) in nodes.iter_mut().zip(payload.chunks(NODE_BYTES)) {
+			node.as_bytes_mut().copy_from_slice(chunk);
+		}
+
+		Ok(NodeCache {
+			builder: self.clone(),
+			epoch: epoch::<P>(block_number),
+			cache_dir: Cow::Borrowed(Path::new("")),
+			cache_path: PathBuf::new(),
+			name: P::NAME,
+			params_id: params_id::<P>(),
+			cache: Either::Left(nodes),
+			lock: None,
+		})
+	}
+
+	pub fn new_cache<P: ProgPowParams>(
 		&self,
-		cache_dir: P,
+		cache_dir: impl Into<Cow<'static, Path>>,
 		block_number: u64,
 	) -> NodeCache {
+		self.new_cache_cancellable::<P>(cache_dir, block_number, &CancelToken::new())
+			.expect("a fresh token is never cancelled")
+	}
+
+	/// Like `new_cache`, stopping early with a `Cancelled` error once `cancel` is set.
+	pub fn new_cache_cancellable<P: ProgPowParams>(
+		&self,
+		cache_dir: impl Into<Cow<'static, Path>>,
+		block_number: u64,
+		cancel: &CancelToken,
+	) -> io::Result<NodeCache> {
 		let cache_dir = cache_dir.into();
-		let ident = self.block_number_to_ident(block_number);
+		let epoch = epoch::<P>(block_number);
+		let ident = self.epoch_to_ident(epoch);
 
-		let cache_size = get_cache_size(block_number);
+		let cache_size = get_cache_size::<P>(block_number);
 
 		// We use `debug_assert` since it is impossible for `get_cache_size` to return an unaligned
 		// value with the current implementation. If the implementation changes, CI will catch it.
 		debug_assert!(cache_size % NODE_BYTES == 0, "Unaligned cache size");
 		let num_nodes = cache_size / NODE_BYTES;
 
-		let path = cache_path(cache_dir.as_ref(), &ident);
-		let nodes = new_buffer(&path, num_nodes, &ident, self.optimize_for);
-
-		NodeCache {
+		let path = cache_path(cache_dir.as_ref(), P::NAME, &ident);
+		// Another process building the same epoch leaves the file to us once it is done.
+		let (lock, waited) = lock_cache(&path, cancel)?;
+		if waited {
+			if let Ok(cache) = self.from_file::<P>(cache_dir.clone(), block_number) {
+				return Ok(cache);
+			}
+		}
+		let build = Build {
+			cancel,
+			progress: self.progress.as_deref(),
+			epoch,
+			start: Instant::now(),
+		};
+		let nodes = new_buffer(&path, num_nodes, &ident, self.optimize_for, &build)?;
+
+		Ok(NodeCache {
 			builder: self.clone(),
-			epoch: epoch(block_number),
+			epoch,
 			cache_dir: cache_dir.into(),
 			cache_path: path,
+			name: P::NAME,
+			params_id: params_id::<P>(),
 			cache: nodes,
-		}
+			lock,
+		})
 	}
 }
 
@@ -168,105 +609,225 @@ impl NodeCache {
 		&self.cache_path
 	}
 
+	/// Write the cache to its file, then apply the builder's retention policy.
 	pub fn flush(&mut self) -> io::Result<()> {
-		if let Some(last) = self
-			.epoch
-			.checked_sub(2)
-			.map(|ep| cache_path(self.cache_dir.as_ref(), &self.builder.epoch_to_ident(ep)))
-		{
-			fs::remove_file(last).unwrap_or_else(|error| match error.kind() {
+		consume_cache(
+			&mut self.cache,
+			&self.cache_path,
+			self.epoch,
+			self.params_id,
+		)?;
+		self.lock = None;
+		self.remove_stale();
+		Ok(())
+	}
+
+	fn remove_stale(&self) {
+		let keep = match self.builder.keep_epochs {
+			Some(keep) => keep,
+			None => return,
+		};
+		// Older epochs are swept too, in case the node was offline across several.
+		for ep in 0..(self.epoch + 1).saturating_sub(keep) {
+			let path = cache_path(
+				self.cache_dir.as_ref(),
+				self.name,
+				&self.builder.epoch_to_ident(ep),
+			);
+			fs::remove_file(path).unwrap_or_else(|error| match error.kind() {
 				io::ErrorKind::NotFound => (),
 				_ => warn!("Error removing stale DAG cache: {:?}", error),
 			});
 		}
-
-		consume_cache(&mut self.cache, &self.cache_path)
 	}
 }
 
-fn make_memmapped_cache(path: &Path, num_nodes: usize, ident: &H256) -> io::Result<MmapMut> {
+#[cfg(not(feature = "mmap"))]
+fn make_memmapped_cache(
+	_path: &Path,
+	_num_nodes: usize,
+	_ident: &H256,
+	_build: &Build,
+) -> io::Result<MmapMut> {
+	Err(io::Error::new(
+		io::ErrorKind::Other,
+		"Built without memory mapping",
+	))
+}
+
+#[cfg(feature = "mmap")]
+fn make_memmapped_cache(
+	path: &Path,
+	num_nodes: usize,
+	ident: &H256,
+	build: &Build,
+) -> io::Result<MmapMut> {
 	use std::fs::OpenOptions;
 
 	let file = OpenOptions::new()
 		.read(true)
 		.write(true)
 		.create(true)
+		.truncate(true)
 		.open(&path)?;
-	file.set_len((num_nodes * NODE_BYTES) as _)?;
-
-	let mut memmap = unsafe { MmapMut::map_mut(&file)? };
+	// The header stays zeroed, and the file invalid, until the cache is flushed.
+	file.set_len((HEADER_BYTES + num_nodes * NODE_BYTES) as _)?;
+
+	let mut memmap = unsafe {
+		MmapOptions::new()
+			.offset(HEADER_BYTES as u64)
+			.len(num_nodes * NODE_BYTES)
+			.map_mut(&file)?
+	};
 
-	unsafe { initialize_memory(memmap.as_mut_ptr() as *mut Node, num_nodes, ident) };
+	// A cancelled file keeps its zeroed header, so it is never loaded.
+	unsafe { initialize_memory(memmap.as_mut_ptr() as *mut Node, num_nodes, ident, build)? };
 
 	Ok(memmap)
 }
 
-fn make_memory_cache(num_nodes: usize, ident: &H256) -> Vec<Node> {
+fn make_memory_cache(num_nodes: usize, ident: &H256, build: &Build) -> io::Result<Vec<Node>> {
 	let mut nodes: Vec<Node> = Vec::with_capacity(num_nodes);
 	// Use uninit instead of unnecessarily writing `size_of::<Node>() * num_nodes` 0s
 	unsafe {
-		initialize_memory(nodes.as_mut_ptr(), num_nodes, ident);
+		initialize_memory(nodes.as_mut_ptr(), num_nodes, ident, build)?;
 		nodes.set_len(num_nodes);
 	}
 
-	nodes
+	Ok(nodes)
 }
 
-fn cache_path<'a, P: Into<Cow<'a, Path>>>(path: P, ident: &H256) -> PathBuf {
+/// Files are named by params and seed hash, so chains sharing a directory keep their own.
+pub(crate) fn cache_path<'a, P: Into<Cow<'a, Path>>>(path: P, name: &str, ident: &H256) -> PathBuf {
 	let mut buf = path.into().into_owned();
-	buf.push(to_hex(ident));
+	buf.push(format!("{}-{}", name, to_hex(ident)));
 	buf
 }
 
-fn consume_cache(cache: &mut Cache, path: &Path) -> io::Result<()> {
+/// Interval at which a process waiting for another's build checks its lock.
+const LOCK_POLL: Duration = Duration::from_millis(100);
+
+/// Age after which a lock is taken to belong to a process that died while building.
+const LOCK_STALE_AFTER: Duration = Duration::from_secs(30 * 60);
+
+fn lock_path(path: &Path) -> PathBuf {
+	let mut name = path.as_os_str().to_owned();
+	name.push(".lock");
+	PathBuf::from(name)
+}
+
+/// Lock file next to a cache file, so processes sharing a cache directory build each
+/// epoch once. Removed when dropped.
+struct CacheLock(PathBuf);
+
+impl Drop for CacheLock {
+	fn drop(&mut self) {
+		let _ = fs::remove_file(&self.0);
+	}
+}
+
+/// Take the lock of the cache file at `path`, waiting while another process holds it.
+/// Also returns whether it waited, in which case the file is likely complete.
+///
+/// Without a writable cache directory there is nothing to share, and no lock is taken.
+fn lock_cache(path: &Path, cancel: &CancelToken) -> io::Result<(Option<CacheLock>, bool)> {
+	let lock = lock_path(path);
+	let mut waited = false;
+	loop {
+		match fs::OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open(&lock)
+		{
+			Ok(mut file) => {
+				let _ = write!(file, "{}", std::process::id());
+				return Ok((Some(CacheLock(lock)), waited));
+			}
+			Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+			Err(_) => return Ok((None, waited)),
+		}
+		let stale = fs::metadata(&lock)
+			.and_then(|meta| meta.modified())
+			.ok()
+			.and_then(|modified| modified.elapsed().ok())
+			.map_or(false, |age| age > LOCK_STALE_AFTER);
+		if stale {
+			warn!("Removing stale cache lock {}", lock.display());
+			let _ = fs::remove_file(&lock);
+			continue;
+		}
+		cancel.check()?;
+		waited = true;
+		std::thread::sleep(LOCK_POLL);
+	}
+}
+
+fn consume_cache(cache: &mut Cache, path: &Path, epoch: u64, params_id: u64) -> io::Result<()> {
 	use std::fs::OpenOptions;
 
+	let header = CacheHeader::new(CACHE_MAGIC, epoch, params_id, cache_bytes(cache)).to_bytes();
 	match *cache {
-		Either::Left(ref mut vec) => {
+		Either::Left(_) => {
 			let mut file = OpenOptions::new()
 				.read(true)
 				.write(true)
 				.create(true)
+				.truncate(true)
 				.open(&path)?;
 
-			let buf = unsafe {
-				slice::from_raw_parts_mut(vec.as_mut_ptr() as *mut u8, vec.len() * NODE_BYTES)
-			};
-
-			file.write_all(buf).map(|_| ())
+			file.write_all(&header)?;
+			file.write_all(cache_bytes(cache))
+		}
+		Either::Right(ref mmap) => {
+			mmap.flush()?;
+			// Written last, so a partially flushed cache never looks valid.
+			let mut file = OpenOptions::new().write(true).open(&path)?;
+			file.write_all(&header)
 		}
-		Either::Right(ref mmap) => mmap.flush(),
 	}
 }
 
-fn cache_from_path(path: &Path, optimize_for: OptimizeFor) -> io::Result<Cache> {
+fn cache_from_path(
+	path: &Path,
+	optimize_for: OptimizeFor,
+	epoch: u64,
+	params_id: u64,
+) -> io::Result<Cache> {
+	let mut file = File::open(path)?;
+	let header = CacheHeader::read(&mut file, CACHE_MAGIC)?;
+
 	let memmap = match optimize_for {
+		#[cfg(not(feature = "mmap"))]
+		OptimizeFor::Memory => None,
 		OptimizeFor::Cpu => None,
+		#[cfg(feature = "mmap")]
 		OptimizeFor::Memory => {
-			let file = fs::OpenOptions::new()
-				.read(true)
-				.write(true)
-				.create(true)
-				.open(path)?;
-			unsafe { MmapMut::map_mut(&file).ok() }
+			let file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+			unsafe {
+				MmapOptions::new()
+					.offset(HEADER_BYTES as u64)
+					.len(header.payload_len as usize)
+					.map_mut(&file)
+					.ok()
+			}
 		}
 	};
 
-	memmap
+	let cache = memmap
 		.map(Either::Right)
 		.ok_or(())
-		.or_else(|_| read_from_path(path).map(Either::Left))
+		.or_else(|_| read_payload(&mut file).map(Either::Left))?;
+	header.validate(epoch, params_id, cache_bytes(&cache))?;
+	Ok(cache)
 }
 
-fn read_from_path(path: &Path) -> io::Result<Vec<Node>> {
-	use std::fs::File;
+fn read_payload(file: &mut File) -> io::Result<Vec<Node>> {
 	use std::mem;
 
-	let mut file = File::open(path)?;
-
+	file.seek(SeekFrom::Start(HEADER_BYTES as u64))?;
 	let mut nodes: Vec<u8> = Vec::with_capacity(
 		file.metadata()
-			.map(|m| m.len() as _)
+			.map(|m| (m.len() as usize).saturating_sub(HEADER_BYTES))
 			.unwrap_or(NODE_BYTES * 1_000_000),
 	);
 	file.read_to_end(&mut nodes)?;
@@ -274,10 +835,7 @@ fn read_from_path(path: &Path) -> io::Result<Vec<Node>> {
 	nodes.shrink_to_fit();
 
 	if nodes.len() % NODE_BYTES != 0 || nodes.capacity() % NODE_BYTES != 0 {
-		return Err(io::Error::new(
-			io::ErrorKind::Other,
-			"Node cache is not a multiple of node size",
-		));
+		return Err(invalid_cache("Node cache is not a multiple of node size"));
 	}
 
 	let out: Vec<Node> = unsafe {
@@ -316,13 +874,26 @@ impl AsRef<[Node]> for NodeCache {
 // We have to use raw pointers to read/write uninit, using "normal" indexing causes LLVM to freak
 // out. It counts as a read and causes all writes afterwards to be elided. Yes, really. I know, I
 // want to refactor this to use less `unsafe` as much as the next rustacean.
-unsafe fn initialize_memory(memory: *mut Node, num_nodes: usize, ident: &H256) {
+//
+// Stops with a `Cancelled` error, leaving `memory` partly initialized, once the build's
+// token is cancelled.
+unsafe fn initialize_memory(
+	memory: *mut Node,
+	num_nodes: usize,
+	ident: &H256,
+	build: &Build,
+) -> io::Result<()> {
 	let dst = memory as *mut u8;
+	let total = num_nodes * (1 + ETHASH_CACHE_ROUNDS);
 
 	debug_assert_eq!(ident.len(), 32);
 	keccak_512::unchecked(dst, NODE_BYTES, ident.as_ptr(), ident.len());
 
 	for i in 1..num_nodes {
+		if i % CANCEL_CHECK_NODES == 0 {
+			build.checkpoint(i, total)?;
+		}
+
 		// We use raw pointers here, see above
 		let dst = memory.offset(i as _) as *mut u8;
 		let src = memory.offset(i as isize - 1) as *mut u8;
@@ -338,10 +909,13 @@ unsafe fn initialize_memory(memory: *mut Node, num_nodes: usize, ident: &H256) {
 	debug_assert_eq!(NODE_DWORDS, 8);
 
 	// This _should_ get unrolled by the compiler, since it's not using the loop variable.
-	for _ in 0..ETHASH_CACHE_ROUNDS {
+	for round in 1..=ETHASH_CACHE_ROUNDS {
 		for i in 0..num_nodes {
+			if i % CANCEL_CHECK_NODES == 0 {
+				build.checkpoint(round * num_nodes + i, total)?;
+			}
 			let data_idx = (num_nodes - 1 + i) % num_nodes;
-			let idx = nodes.get_unchecked_mut(i).as_words()[0] as usize % num_nodes;
+			let idx = nodes.get_unchecked_mut(i).le_word(0) as usize % num_nodes;
 
 			let data = {
 				let mut data: Node = nodes.get_unchecked(data_idx).clone();
@@ -360,5 +934,230 @@ unsafe fn initialize_memory(memory: *mut Node, num_nodes: usize, ident: &H256) {
 			keccak_512::write(&data.bytes, &mut nodes.get_unchecked_mut(i).bytes);
 		}
 	}
+
+	build.checkpoint(total, total)
 }
 
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::params::{ProgPow092Params, ProgPow092ZanoParams};
+	use crate::shared::ETHASH_EPOCH_LENGTH;
+	use tempdir::TempDir;
+
+	#[test]
+	fn test_cache_file_integrity() {
+		let tempdir = TempDir::new("").unwrap();
+		let builder = NodeCacheBuilder::new(None);
+		let path = builder
+			.light::<ProgPow092Params>(tempdir.path(), 0)
+			.to_file()
+			.unwrap()
+			.to_owned();
+		assert!(builder
+			.from_file::<ProgPow092Params>(tempdir.path().to_path_buf(), 0)
+			.is_ok());
+
+		// Flip one payload byte: the checksum no longer matches.
+		let mut bytes = fs::read(&path).unwrap();
+		bytes[HEADER_BYTES + 100] ^= 1;
+		fs::write(&path, &bytes).unwrap();
+		let err = builder
+			.from_file::<ProgPow092Params>(tempdir.path().to_path_buf(), 0)
+			.err()
+			.unwrap();
+		assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+		// The damaged file is dropped so it gets regenerated.
+		assert!(!path.exists());
+
+		let path = builder
+			.light::<ProgPow092Params>(tempdir.path(), 0)
+			.to_file()
+			.unwrap()
+			.to_owned();
+		let bytes = fs::read(&path).unwrap();
+		fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+		assert!(builder
+			.from_file::<ProgPow092Params>(tempdir.path().to_path_buf(), 0)
+			.is_err());
+	}
+
+	#[test]
+	fn test_keep_last() {
+		let tempdir = TempDir::new("").unwrap();
+		let write = |builder: &NodeCacheBuilder, ep: u64| {
+			builder
+				.light::<ProgPow092Params>(tempdir.path(), ep * ETHASH_EPOCH_LENGTH)
+				.to_file()
+				.unwrap()
+				.to_owned()
+		};
+
+		let builder = NodeCacheBuilder::new(None).keep_all();
+		let first = write(&builder, 0);
+		let second = write(&builder, 1);
+		let _ = write(&builder, 2);
+		assert!(first.exists() && second.exists());
+
+		let builder = NodeCacheBuilder::new(None).keep_last(1);
+		let third = write(&builder, 3);
+		assert!(!first.exists() && !second.exists());
+		assert!(third.exists());
+	}
+
+	#[test]
+	fn test_cache_from_bytes() {
+		let tempdir = TempDir::new("").unwrap();
+		let builder = NodeCacheBuilder::new(OptimizeFor::Cpu);
+		let mut cache = builder.new_cache::<ProgPow092Params>(tempdir.path().to_path_buf(), 0);
+		cache.flush().unwrap();
+		let file = fs::read(cache.cache_path()).unwrap();
+		let payload = &file[HEADER_BYTES..];
+
+		let from_file = builder.from_bytes::<ProgPow092Params>(0, &file).unwrap();
+		let from_payload = builder.from_bytes::<ProgPow092Params>(0, payload).unwrap();
+		assert_eq!(cache_bytes(&from_file.cache), payload);
+		assert_eq!(cache_bytes(&from_payload.cache), payload);
+
+		let err = builder
+			.from_bytes::<ProgPow092Params>(0, &payload[1..])
+			.err()
+			.unwrap();
+		assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+		let mut corrupt = file.clone();
+		corrupt[HEADER_BYTES] ^= 1;
+		let err = builder
+			.from_bytes::<ProgPow092Params>(0, &corrupt)
+			.err()
+			.unwrap();
+		assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+		// The same cache, but written for other params.
+		let err = builder
+			.from_bytes::<ProgPow092ZanoParams>(0, &file)
+			.err()
+			.unwrap();
+		assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+		assert!(builder
+			.from_bytes::<ProgPow092ZanoParams>(0, payload)
+			.is_ok());
+	}
+
+	#[test]
+	fn test_threads() {
+		let builder = NodeCacheBuilder::new(None).threads(2);
+		assert_eq!(builder.install(rayon::current_num_threads), 2);
+		let builder = NodeCacheBuilder::new(None).threads(0);
+		assert_eq!(builder.install(rayon::current_num_threads), 1);
+	}
+
+	#[test]
+	fn test_optimize_for_keeps_options() {
+		let builder = NodeCacheBuilder::new(OptimizeFor::Cpu)
+			.threads(2)
+			.keep_last(3)
+			.optimize_for(OptimizeFor::Memory);
+		assert_eq!(builder.optimize_for, OptimizeFor::Memory);
+		assert_eq!(builder.keep_epochs, Some(3));
+		assert_eq!(builder.install(rayon::current_num_threads), 2);
+	}
+
+	#[test]
+	fn test_cancelled_cache() {
+		let tempdir = TempDir::new("").unwrap();
+		let builder = NodeCacheBuilder::new(OptimizeFor::Memory);
+		let cancel = CancelToken::new();
+		cancel.cancel();
+		let err = builder
+			.new_cache_cancellable::<ProgPow092Params>(tempdir.path().to_path_buf(), 0, &cancel)
+			.err()
+			.unwrap();
+		assert!(is_cancelled(&err));
+		assert!(builder
+			.from_file::<ProgPow092Params>(tempdir.path().to_path_buf(), 0)
+			.is_err());
+	}
+
+	#[test]
+	fn test_cache_progress() {
+		let tempdir = TempDir::new("").unwrap();
+		let reports = Arc::new(Mutex::new(Vec::new()));
+		let seen = reports.clone();
+		let builder = NodeCacheBuilder::new(OptimizeFor::Cpu)
+			.on_progress(move |progress| seen.lock().unwrap().push(*progress));
+		builder.new_cache::<ProgPow092Params>(tempdir.path().to_path_buf(), 0);
+
+		let reports = reports.lock().unwrap();
+		let last = reports.last().unwrap();
+		let num_nodes = get_cache_size::<ProgPow092Params>(0) / NODE_BYTES;
+		assert_eq!(last.total, num_nodes * (1 + ETHASH_CACHE_ROUNDS));
+		assert_eq!(last.done, last.total);
+		assert_eq!(last.percent(), 100.0);
+		assert_eq!(last.eta(), Some(Duration::from_secs(0)));
+		assert!(reports.len() > 4);
+		assert!(reports.windows(2).all(|w| w[0].done < w[1].done));
+	}
+
+	#[test]
+	fn test_light_from_seed() {
+		let seed = SeedHashCompute::default().hash_epoch(16);
+		assert_eq!(epoch_from_seed(&seed), Some(16));
+		assert_eq!(epoch_from_seed(&[0u8; 32]), Some(0));
+		assert_eq!(epoch_from_seed(&[1u8; 32]), None);
+
+		let tempdir = TempDir::new("").unwrap();
+		let builder = NodeCacheBuilder::new(OptimizeFor::Memory);
+		let err = builder
+			.light_from_seed::<ProgPow092Params>(tempdir.path(), &[1u8; 32])
+			.err()
+			.unwrap();
+		assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+		let light = builder
+			.light_from_seed::<ProgPow092Params>(tempdir.path(), &[0u8; 32])
+			.unwrap();
+		assert_eq!(
+			light.dataset_len(),
+			builder
+				.light::<ProgPow092Params>(tempdir.path(), 0)
+				.dataset_len()
+		);
+	}
+
+	#[test]
+	fn test_cache_lock_waits_for_other_process() {
+		use std::sync::atomic::{AtomicBool, Ordering};
+
+		let tempdir = TempDir::new("").unwrap();
+		let dir = tempdir.path().to_path_buf();
+		let builder = NodeCacheBuilder::new(OptimizeFor::Memory);
+		let path = cache_path(
+			&dir,
+			ProgPow092Params::NAME,
+			&builder.block_number_to_ident::<ProgPow092Params>(0),
+		);
+		let (lock, waited) = lock_cache(&path, &CancelToken::new()).unwrap();
+		assert!(lock.is_some() && !waited);
+
+		let built = Arc::new(AtomicBool::new(false));
+		let flag = built.clone();
+		let waiter = builder
+			.clone()
+			.on_progress(move |_| flag.store(true, Ordering::SeqCst));
+		let waiting = std::thread::spawn(move || waiter.new_cache::<ProgPow092Params>(dir, 0));
+
+		// The other process builds the file, then releases its lock.
+		std::thread::sleep(LOCK_POLL * 2);
+		let other = TempDir::new("").unwrap();
+		let mut cache = builder.new_cache::<ProgPow092Params>(other.path().to_path_buf(), 0);
+		cache.flush().unwrap();
+		fs::copy(cache.cache_path(), &path).unwrap();
+		drop(lock);
+
+		let cache = waiting.join().unwrap();
+		assert!(!built.load(Ordering::SeqCst));
+		assert!(!lock_path(&path).exists());
+		assert_eq!(
+			byte_size(&cache.cache),
+			get_cache_size::<ProgPow092Params>(0)
+		);
+	}
+}
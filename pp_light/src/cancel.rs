@@ -0,0 +1,60 @@
+//! Cooperative cancellation of long cache and DAG builds.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Flag checked between chunks of a long build. Clones share the flag, so one can be
+/// handed to the building thread and the other kept to cancel it.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+	pub fn new() -> Self {
+		CancelToken::default()
+	}
+
+	/// Share an existing stop flag, e.g. a miner's.
+	pub fn from_flag(flag: Arc<AtomicBool>) -> Self {
+		CancelToken(flag)
+	}
+
+	pub fn cancel(&self) {
+		self.0.store(true, Ordering::SeqCst);
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.0.load(Ordering::Relaxed)
+	}
+
+	pub(crate) fn check(&self) -> io::Result<()> {
+		if self.is_cancelled() {
+			Err(cancelled())
+		} else {
+			Ok(())
+		}
+	}
+}
+
+/// Error of a cancelled build, carried by an `io::Error` of kind `Interrupted`.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str("Cancelled")
+	}
+}
+
+impl Error for Cancelled {}
+
+pub(crate) fn cancelled() -> io::Error {
+	io::Error::new(io::ErrorKind::Interrupted, Cancelled)
+}
+
+/// True if `e` comes from a build stopped through its `CancelToken`.
+pub fn is_cancelled(e: &io::Error) -> bool {
+	e.get_ref().map_or(false, |inner| inner.is::<Cancelled>())
+}
@@ -0,0 +1,266 @@
+//! C ABI for the light verifier, declared in `include/progpow_light.h`.
+//!
+//! All functions return 0 on success and a negative `PROGPOW_E*` code on failure.
+//! Final hashes are written as big-endian words, so they compare directly with a
+//! big-endian target; mix hashes are written as little-endian words, as in block headers.
+
+use crate::cache::{NodeCacheBuilder, OptimizeFor};
+use crate::compute::Light;
+use crate::keccak::H256;
+use crate::params::{ProgPow092Params, ProgPow092ZanoParams};
+use crate::seed_compute::SeedHashCompute;
+use crate::shared::epoch;
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::path::PathBuf;
+use std::ptr;
+use std::slice;
+use std::sync::{Arc, Mutex};
+
+pub const PROGPOW_OK: c_int = 0;
+pub const PROGPOW_EINVAL: c_int = -1;
+pub const PROGPOW_ECACHE: c_int = -2;
+
+pub const PROGPOW_MAPPING_STANDARD: u32 = 0;
+pub const PROGPOW_MAPPING_ZANO: u32 = 1;
+
+/// Directory and last light cache used by `progpow_verify`.
+struct Shared {
+	cache_dir: Option<PathBuf>,
+	light: Option<(u64, Arc<Light>)>,
+}
+
+static SHARED: Mutex<Shared> = Mutex::new(Shared {
+	cache_dir: None,
+	light: None,
+});
+
+fn default_cache_dir() -> PathBuf {
+	std::env::temp_dir().join("progpow-light")
+}
+
+fn load_light(cache_dir: PathBuf, height: u64) -> Option<Light> {
+	let builder = NodeCacheBuilder::new(OptimizeFor::Memory);
+	if let Ok(light) =
+		Light::from_file_with_builder::<ProgPow092Params>(&builder, &cache_dir, height)
+	{
+		return Some(light);
+	}
+	std::fs::create_dir_all(&cache_dir).ok()?;
+	let mut light = Light::new_with_builder::<ProgPow092Params>(&builder, &cache_dir, height);
+	// A cache that cannot be persisted still verifies correctly.
+	let _ = light.to_file();
+	Some(light)
+}
+
+unsafe fn write_hashes(digest: [u32; 8], mix: [u32; 8], out_hash: *mut u8, out_mix: *mut u8) {
+	let out_hash = slice::from_raw_parts_mut(out_hash, 32);
+	let out_mix = slice::from_raw_parts_mut(out_mix, 32);
+	for i in 0..8 {
+		out_hash[i * 4..(i + 1) * 4].copy_from_slice(&digest[i].to_be_bytes());
+		out_mix[i * 4..(i + 1) * 4].copy_from_slice(&mix[i].to_le_bytes());
+	}
+}
+
+unsafe fn verify_with(
+	light: &Light,
+	mapping_id: u32,
+	header: *const u8,
+	height: u64,
+	nonce: u64,
+	out_hash: *mut u8,
+	out_mix: *mut u8,
+) -> c_int {
+	if header.is_null() || out_hash.is_null() || out_mix.is_null() {
+		return PROGPOW_EINVAL;
+	}
+	let mut header_hash: H256 = [0u8; 32];
+	header_hash.copy_from_slice(slice::from_raw_parts(header, 32));
+	// The light cache is the same for both, only the math differs.
+	let (digest, mix) = match mapping_id {
+		PROGPOW_MAPPING_STANDARD => light.compute::<ProgPow092Params>(&header_hash, nonce, height),
+		PROGPOW_MAPPING_ZANO => light.compute::<ProgPow092ZanoParams>(&header_hash, nonce, height),
+		_ => return PROGPOW_EINVAL,
+	};
+	write_hashes(digest, mix, out_hash, out_mix);
+	PROGPOW_OK
+}
+
+/// Set the directory `progpow_verify` keeps light caches in. NULL restores the default,
+/// a `progpow-light` directory under the system temp dir.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_set_cache_dir(path: *const c_char) -> c_int {
+	let dir = if path.is_null() {
+		None
+	} else {
+		match CStr::from_ptr(path).to_str() {
+			Ok(dir) => Some(PathBuf::from(dir)),
+			Err(_) => return PROGPOW_EINVAL,
+		}
+	};
+	let mut shared = SHARED.lock().unwrap_or_else(|e| e.into_inner());
+	shared.cache_dir = dir;
+	shared.light = None;
+	PROGPOW_OK
+}
+
+/// Hash `header` (32 bytes) with `nonce` at `height` using the standard ProgPoW math.
+///
+/// The light cache for the epoch is loaded or built on first use and kept until a
+/// height of another epoch is verified.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_verify(
+	header: *const u8,
+	height: u64,
+	nonce: u64,
+	out_hash: *mut u8,
+	out_mix: *mut u8,
+) -> c_int {
+	let light = {
+		let mut shared = SHARED.lock().unwrap_or_else(|e| e.into_inner());
+		match shared.light {
+			Some((e, ref light)) if e == epoch::<ProgPow092Params>(height) => light.clone(),
+			_ => {
+				let dir = shared.cache_dir.clone().unwrap_or_else(default_cache_dir);
+				let light = match load_light(dir, height) {
+					Some(light) => Arc::new(light),
+					None => return PROGPOW_ECACHE,
+				};
+				shared.light = Some((epoch::<ProgPow092Params>(height), light.clone()));
+				light
+			}
+		}
+	};
+	verify_with(
+		&light,
+		PROGPOW_MAPPING_STANDARD,
+		header,
+		height,
+		nonce,
+		out_hash,
+		out_mix,
+	)
+}
+
+/// Load or build the light cache for the epoch of `height` under `cache_dir`.
+/// Returns NULL on failure. Free with `progpow_light_free`.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_light_new(cache_dir: *const c_char, height: u64) -> *mut Light {
+	if cache_dir.is_null() {
+		return ptr::null_mut();
+	}
+	let dir = match CStr::from_ptr(cache_dir).to_str() {
+		Ok(dir) => PathBuf::from(dir),
+		Err(_) => return ptr::null_mut(),
+	};
+	match load_light(dir, height) {
+		Some(light) => Box::into_raw(Box::new(light)),
+		None => ptr::null_mut(),
+	}
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn progpow_light_free(light: *mut Light) {
+	if !light.is_null() {
+		drop(Box::from_raw(light));
+	}
+}
+
+/// Like `progpow_verify` with an explicit light cache and `PROGPOW_MAPPING_*` variant.
+/// `height` must be in the epoch the cache was created for.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_light_verify(
+	light: *const Light,
+	mapping: u32,
+	header: *const u8,
+	height: u64,
+	nonce: u64,
+	out_hash: *mut u8,
+	out_mix: *mut u8,
+) -> c_int {
+	if light.is_null() {
+		return PROGPOW_EINVAL;
+	}
+	verify_with(&*light, mapping, header, height, nonce, out_hash, out_mix)
+}
+
+#[no_mangle]
+pub extern "C" fn progpow_epoch(height: u64) -> u64 {
+	epoch::<ProgPow092Params>(height)
+}
+
+/// Write the 32-byte seed hash of `epoch` to `out`.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_seed_hash(epoch: u64, out: *mut u8) -> c_int {
+	if out.is_null() {
+		return PROGPOW_EINVAL;
+	}
+	let seed = SeedHashCompute::resume_compute_seedhash([0u8; 32], 0, epoch);
+	slice::from_raw_parts_mut(out, 32).copy_from_slice(&seed);
+	PROGPOW_OK
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use tempdir::TempDir;
+
+	#[test]
+	fn test_capi_verify() {
+		let tempdir = TempDir::new("").unwrap();
+		let dir = std::ffi::CString::new(tempdir.path().to_str().unwrap()).unwrap();
+		let header = [0u8; 32];
+		let nonce = 0xd7b3ac70a301a249;
+		let mut hash = [0u8; 32];
+		let mut mix = [0u8; 32];
+		let mut light_hash = [0u8; 32];
+		let mut light_mix = [0u8; 32];
+		unsafe {
+			assert_eq!(progpow_set_cache_dir(dir.as_ptr()), PROGPOW_OK);
+			assert_eq!(
+				progpow_verify(
+					header.as_ptr(),
+					0,
+					nonce,
+					hash.as_mut_ptr(),
+					mix.as_mut_ptr()
+				),
+				PROGPOW_OK
+			);
+
+			let light = progpow_light_new(dir.as_ptr(), 0);
+			assert!(!light.is_null());
+			assert_eq!(
+				progpow_light_verify(
+					light,
+					PROGPOW_MAPPING_STANDARD,
+					header.as_ptr(),
+					0,
+					nonce,
+					light_hash.as_mut_ptr(),
+					light_mix.as_mut_ptr(),
+				),
+				PROGPOW_OK
+			);
+			assert_eq!(
+				progpow_light_verify(
+					light,
+					9,
+					header.as_ptr(),
+					0,
+					nonce,
+					hash.as_mut_ptr(),
+					mix.as_mut_ptr()
+				),
+				PROGPOW_EINVAL
+			);
+			progpow_light_free(light);
+			progpow_set_cache_dir(ptr::null());
+		}
+		assert_eq!(hash, light_hash);
+		assert_eq!(mix, light_mix);
+		// Same vector as `progpow::test::test_keccak_256`.
+		assert_eq!(mix[0..4], 0xd5e0f818u32.to_le_bytes());
+	}
+}
@@ -16,15 +16,22 @@
 
 //! Ethash implementation
 //! See https://github.com/ethereum/wiki/wiki/Ethash
-
-// TODO: fix endianess for big endian
+//!
+//! Hashed bytes are read as little-endian words with `Node::le_word`, and words kept
+//! across several steps are switched to native order with `Node::fix_endian`.
 
 use crate::cache::{NodeCache, NodeCacheBuilder};
+use crate::cancel::CancelToken;
+#[cfg(feature = "mmap")]
+use crate::dag::FullDag;
 use crate::keccak::{keccak_256, keccak_512, H256};
+#[cfg(feature = "mmap")]
+use crate::progpow::progpow_full;
 use crate::progpow::{generate_cdag, progpow, CDag};
 use crate::seed_compute::SeedHashCompute;
 use crate::shared::*;
-use progpow_base::params::MathMapping;
+use progpow_base::params::ProgPowParams;
+use rayon::prelude::*;
 use std::io;
 
 use std::path::Path;
@@ -46,57 +53,155 @@ pub struct PoF {
 }
 
 pub struct Light {
-	block_number: u64,
+	/// Full dataset size of the epoch, for `P` the light was built with.
+	data_size: usize,
 	cache: NodeCache,
 	dag: Box<CDag>,
 }
 
 /// Light cache structure
 impl Light {
-	pub fn new_with_builder(
+	pub fn new_with_builder<P: ProgPowParams>(
 		builder: &NodeCacheBuilder,
 		cache_dir: &Path,
 		block_number: u64,
 	) -> Self {
-		let cache = builder.new_cache(cache_dir.to_path_buf(), block_number);
-		let dag = Box::new(generate_cdag(cache.as_ref()));
+		let cache = builder.new_cache::<P>(cache_dir.to_path_buf(), block_number);
+		let dag = Box::new(builder.install(|| generate_cdag(cache.as_ref())));
 		Light {
-			block_number,
+			data_size: get_data_size::<P>(block_number),
 			cache,
 			dag,
 		}
 	}
 
-	/// Calculate the light boundary data
+	/// Like `new_with_builder`, stopping early with a `Cancelled` error once `cancel` is set.
+	pub fn new_cancellable_with_builder<P: ProgPowParams>(
+		builder: &NodeCacheBuilder,
+		cache_dir: &Path,
+		block_number: u64,
+		cancel: &CancelToken,
+	) -> io::Result<Self> {
+		let cache =
+			builder.new_cache_cancellable::<P>(cache_dir.to_path_buf(), block_number, cancel)?;
+		cancel.check()?;
+		let dag = Box::new(builder.install(|| generate_cdag(cache.as_ref())));
+
+		Ok(Light {
+			data_size: get_data_size::<P>(block_number),
+			cache,
+			dag,
+		})
+	}
+
+	/// Calculate the light boundary data with `P`'s math mapping and keccak rounds
 	/// `header_hash` - The header hash to pack into the mix
 	/// `nonce` - The nonce to pack into the mix
-	pub fn compute(
+	pub fn compute<P: ProgPowParams>(
 		&self,
 		header_hash: &H256,
 		nonce: u64,
 		block_number: u64,
-		mapping: MathMapping,
 	) -> ([u32; 8], [u32; 8]) {
-		progpow(
+		progpow::<P>(
 			*header_hash,
 			nonce,
 			block_number,
 			self.cache.as_ref(),
 			self.dag.as_ref(),
-			mapping,
 		)
 	}
 
-	pub fn from_file_with_builder(
+	/// Like `compute`, reading DAG items from the full `dag` of the same epoch, which is
+	/// about a hundred times faster than computing them from the cache.
+	#[cfg(feature = "mmap")]
+	pub fn compute_with_dag<P: ProgPowParams>(
+		&self,
+		dag: &FullDag,
+		header_hash: &H256,
+		nonce: u64,
+		block_number: u64,
+	) -> ([u32; 8], [u32; 8]) {
+		debug_assert_eq!(dag.epoch(), epoch::<P>(block_number));
+		progpow_full::<P>(
+			*header_hash,
+			nonce,
+			block_number,
+			dag.as_ref(),
+			self.dag.as_ref(),
+		)
+	}
+
+	/// Number of 64-byte items in the full dataset (DAG) of this epoch.
+	pub fn dataset_len(&self) -> u32 {
+		(self.data_size / NODE_BYTES) as u32
+	}
+
+	/// Item `index` of the full dataset, in the byte order GPU kernels read the DAG in.
+	pub fn dataset_item(&self, index: u32) -> [u8; NODE_BYTES] {
+		*calculate_dag_item(index, self.cache.as_ref()).as_bytes()
+	}
+
+	/// Write `count` items from `start` into the first `count * 64` bytes of `out`,
+	/// computing them in parallel. Lets external miners generate the DAG chunk by chunk
+	/// straight into their upload buffers.
+	pub fn calc_dataset_range(&self, start: u32, count: u32, out: &mut [u8]) -> io::Result<()> {
+		let end = start as u64 + count as u64;
+		if end > self.dataset_len() as u64 {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!(
+					"dataset items {}..{} past {}",
+					start,
+					end,
+					self.dataset_len()
+				),
+			));
+		}
+		let bytes = count as usize * NODE_BYTES;
+		if out.len() < bytes {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("{} bytes given for {} dataset items", out.len(), count),
+			));
+		}
+
+		let cache: &[Node] = self.cache.as_ref();
+		out[..bytes]
+			.par_chunks_mut(NODE_BYTES)
+			.enumerate()
+			.for_each(|(i, item)| {
+				item.copy_from_slice(calculate_dag_item(start + i as u32, cache).as_bytes())
+			});
+		Ok(())
+	}
+
+	pub fn from_file_with_builder<P: ProgPowParams>(
 		builder: &NodeCacheBuilder,
 		cache_dir: &Path,
 		block_number: u64,
 	) -> io::Result<Self> {
-		let cache = builder.from_file(cache_dir.to_path_buf(), block_number)?;
-		let dag = Box::new(generate_cdag(cache.as_ref()));
+		let cache = builder.from_file::<P>(cache_dir.to_path_buf(), block_number)?;
+		let dag = Box::new(builder.install(|| generate_cdag(cache.as_ref())));
 
 		Ok(Light {
-			block_number,
+			data_size: get_data_size::<P>(block_number),
+			cache,
+			dag,
+		})
+	}
+
+	/// Light cache from bytes loaded elsewhere, see `NodeCacheBuilder::from_bytes`.
+	pub fn from_bytes_with_builder<P: ProgPowParams>(
+		builder: &NodeCacheBuilder,
+		block_number: u64,
+		bytes: &[u8],
+	) -> io::Result<Self> {
+		let cache = builder.from_bytes::<P>(block_number, bytes)?;
+		let dag = Box::new(builder.install(|| generate_cdag(cache.as_ref())));
+
+		Ok(Light {
+			data_size: get_data_size::<P>(block_number),
 			cache,
 			dag,
 		})
@@ -124,8 +229,8 @@ fn fnv_hash(x: u32, y: u32) -> u32 {
 /// Boundary recovered from mix hash
 /*pub fn quick_get_difficulty(header_hash: &H256, nonce: u64, mix_hash: &H256) -> H256 {
 	unsafe {
-		let seed = keccak_f800_short(*header_hash, nonce, [0u32; 8]);
-		keccak_f800_long(*header_hash, seed, mem::transmute(*mix_hash))
+		let seed = keccak_f800_short(*header_hash, nonce, [0u32; 8], KECCAK_ROUNDS);
+		keccak_f800_long(*header_hash, seed, mem::transmute(*mix_hash), KECCAK_ROUNDS)
 	}
 }*/
 
@@ -134,8 +239,7 @@ fn fnv_hash(x: u32, y: u32) -> u32 {
 /// `header_hash` - The header hash to pack into the mix
 /// `nonce` - The nonce to pack into the mix
 pub fn light_compute(light: &Light, header_hash: &H256, nonce: u64) -> PoW {
-	let full_size = get_data_size(light.block_number);
-	hash_compute(light, full_size, header_hash, nonce)
+	hash_compute(light, light.data_size, header_hash, nonce)
 }
 
 fn hash_compute(light: &Light, full_size: usize, header_hash: &H256, nonce: u64) -> PoW {
@@ -182,11 +286,8 @@ fn hash_compute(light: &Light, full_size: usize, header_hash: &H256, nonce: u64)
 			let mut out: [u8; NODE_BYTES] = mem::zeroed();
 
 			ptr::copy_nonoverlapping(header_hash.as_ptr(), out.as_mut_ptr(), header_hash.len());
-			ptr::copy_nonoverlapping(
-				&nonce as *const u64 as *const u8,
-				out[header_hash.len()..].as_mut_ptr(),
-				mem::size_of::<u64>(),
-			);
+			out[header_hash.len()..header_hash.len() + mem::size_of::<u64>()]
+				.copy_from_slice(&nonce.to_le_bytes());
 
 			// compute keccak-512 hash and replicate across mix
 			keccak_512::unchecked(
@@ -202,22 +303,26 @@ fn hash_compute(light: &Light, full_size: usize, header_hash: &H256, nonce: u64)
 		compress_bytes: unsafe { mem::zeroed() },
 	};
 
+	// `half_mix` keeps its bytes for the final hash, `mix` is in native order.
 	let mut mix: [_; MIX_NODES] = [buf.half_mix.clone(), buf.half_mix.clone()];
+	for node in mix.iter_mut() {
+		node.fix_endian();
+	}
 
 	let page_size = 4 * MIX_WORDS;
-	println!("full_size: {}", full_size);
+	debug!("full_size: {}", full_size);
 	let num_full_pages = (full_size / page_size) as u32;
 	// deref once for better performance
 	let cache: &[Node] = light.cache.as_ref();
-	let first_val = buf.half_mix.as_words()[0];
+	let first_val = buf.half_mix.le_word(0);
 
 	debug_assert_eq!(MIX_NODES, 2);
 	debug_assert_eq!(NODE_WORDS, 16);
 
 	for i in 0..ETHASH_ACCESSES as u32 {
 		let index = {
-			// This is trivially safe, but does not work on big-endian. The safety of this is
-			// asserted in debug builds (see the definition of `make_const_array!`).
+			// This is trivially safe. The safety of this is asserted in debug builds (see the
+			// definition of `make_const_array!`).
 			let mix_words: &mut [u32; MIX_WORDS] =
 				unsafe { make_const_array!(MIX_WORDS, &mut mix) };
 
@@ -238,7 +343,7 @@ fn hash_compute(light: &Light, full_size: usize, header_hash: &H256, nonce: u64)
 						mix[n].as_words_mut()[w] =
 							fnv_hash(
 								mix[n].as_words()[w],
-								tmp_node.as_words()[w],
+								tmp_node.le_word(w),
 							);
 					}
 				}
@@ -250,9 +355,8 @@ fn hash_compute(light: &Light, full_size: usize, header_hash: &H256, nonce: u64)
 
 	{
 		// This is an uninitialized buffer to begin with, but we iterate precisely `compress.len()`
-		// times and set each index, leaving the array fully initialized. THIS ONLY WORKS ON LITTLE-
-		// ENDIAN MACHINES. See a future PR to make this and the rest of the code work correctly on
-		// big-endian arches like mips.
+		// times and set each index, leaving the array fully initialized. Words are stored
+		// little-endian, as the mix hash is defined.
 		let compress: &mut [u32; MIX_WORDS / 4] =
 			unsafe { make_const_array!(MIX_WORDS / 4, &mut buf.compress_bytes) };
 
@@ -266,7 +370,7 @@ fn hash_compute(light: &Light, full_size: usize, header_hash: &H256, nonce: u64)
 				reduction = reduction.wrapping_mul(FNV_PRIME) ^ mix_words[w + 1];
 				reduction = reduction.wrapping_mul(FNV_PRIME) ^ mix_words[w + 2];
 				reduction = reduction.wrapping_mul(FNV_PRIME) ^ mix_words[w + 3];
-				compress[i] = reduction;
+				compress[i] = reduction.to_le();
 			}
 		}
 	}
@@ -297,9 +401,10 @@ fn hash_compute(light: &Light, full_size: usize, header_hash: &H256, nonce: u64)
 pub fn calculate_dag_item(node_index: u32, cache: &[Node]) -> Node {
 	let num_parent_nodes = cache.len();
 	let mut ret = cache[node_index as usize % num_parent_nodes].clone();
-	ret.as_words_mut()[0] ^= node_index;
+	ret.as_words_mut()[0] ^= node_index.to_le();
 
 	keccak_512::inplace(ret.as_bytes_mut());
+	ret.fix_endian();
 
 	debug_assert_eq!(NODE_WORDS, 16);
 	for i in 0..ETHASH_DATASET_PARENTS as u32 {
@@ -309,11 +414,12 @@ pub fn calculate_dag_item(node_index: u32, cache: &[Node]) -> Node {
 
 		unroll! {
 			for w in 0..16 {
-				ret.as_words_mut()[w] = fnv_hash(ret.as_words()[w], parent.as_words()[w]);
+				ret.as_words_mut()[w] = fnv_hash(ret.as_words()[w], parent.le_word(w));
 			}
 		}
 	}
 
+	ret.fix_endian();
 	keccak_512::inplace(ret.as_bytes_mut());
 
 	ret
@@ -322,35 +428,66 @@ pub fn calculate_dag_item(node_index: u32, cache: &[Node]) -> Node {
 #[cfg(test)]
 mod test {
 	use super::*;
+	use crate::params::ProgPow092Params;
 	use std::fs;
 	use tempdir::TempDir;
 
 	#[test]
 	fn test_get_cache_size() {
 		// https://github.com/ethereum/wiki/wiki/Ethash/ef6b93f9596746a088ea95d01ca2778be43ae68f#data-sizes
-		assert_eq!(16776896usize, get_cache_size(0));
-		assert_eq!(16776896usize, get_cache_size(1));
-		assert_eq!(16776896usize, get_cache_size(ETHASH_EPOCH_LENGTH - 1));
-		assert_eq!(16907456usize, get_cache_size(ETHASH_EPOCH_LENGTH));
-		assert_eq!(16907456usize, get_cache_size(ETHASH_EPOCH_LENGTH + 1));
-		assert_eq!(284950208usize, get_cache_size(2046 * ETHASH_EPOCH_LENGTH));
-		assert_eq!(285081536usize, get_cache_size(2047 * ETHASH_EPOCH_LENGTH));
+		assert_eq!(16776896usize, get_cache_size::<ProgPow092Params>(0));
+		assert_eq!(16776896usize, get_cache_size::<ProgPow092Params>(1));
+		assert_eq!(
+			16776896usize,
+			get_cache_size::<ProgPow092Params>(ETHASH_EPOCH_LENGTH - 1)
+		);
+		assert_eq!(
+			16907456usize,
+			get_cache_size::<ProgPow092Params>(ETHASH_EPOCH_LENGTH)
+		);
+		assert_eq!(
+			16907456usize,
+			get_cache_size::<ProgPow092Params>(ETHASH_EPOCH_LENGTH + 1)
+		);
+		assert_eq!(
+			284950208usize,
+			get_cache_size::<ProgPow092Params>(2046 * ETHASH_EPOCH_LENGTH)
+		);
 		assert_eq!(
 			285081536usize,
-			get_cache_size(2048 * ETHASH_EPOCH_LENGTH - 1)
+			get_cache_size::<ProgPow092Params>(2047 * ETHASH_EPOCH_LENGTH)
+		);
+		assert_eq!(
+			285081536usize,
+			get_cache_size::<ProgPow092Params>(2048 * ETHASH_EPOCH_LENGTH - 1)
 		);
 	}
 
 	#[test]
 	fn test_get_data_size() {
 		// https://github.com/ethereum/wiki/wiki/Ethash/ef6b93f9596746a088ea95d01ca2778be43ae68f#data-sizes
-		assert_eq!(1073739904usize, get_data_size(0));
-		assert_eq!(1073739904usize, get_data_size(1));
-		assert_eq!(1073739904usize, get_data_size(ETHASH_EPOCH_LENGTH - 1));
-		assert_eq!(1082130304usize, get_data_size(ETHASH_EPOCH_LENGTH));
-		assert_eq!(1082130304usize, get_data_size(ETHASH_EPOCH_LENGTH + 1));
-		assert_eq!(18236833408usize, get_data_size(2046 * ETHASH_EPOCH_LENGTH));
-		assert_eq!(18245220736usize, get_data_size(2047 * ETHASH_EPOCH_LENGTH));
+		assert_eq!(1073739904usize, get_data_size::<ProgPow092Params>(0));
+		assert_eq!(1073739904usize, get_data_size::<ProgPow092Params>(1));
+		assert_eq!(
+			1073739904usize,
+			get_data_size::<ProgPow092Params>(ETHASH_EPOCH_LENGTH - 1)
+		);
+		assert_eq!(
+			1082130304usize,
+			get_data_size::<ProgPow092Params>(ETHASH_EPOCH_LENGTH)
+		);
+		assert_eq!(
+			1082130304usize,
+			get_data_size::<ProgPow092Params>(ETHASH_EPOCH_LENGTH + 1)
+		);
+		assert_eq!(
+			18236833408usize,
+			get_data_size::<ProgPow092Params>(2046 * ETHASH_EPOCH_LENGTH)
+		);
+		assert_eq!(
+			18245220736usize,
+			get_data_size::<ProgPow092Params>(2047 * ETHASH_EPOCH_LENGTH)
+		);
 	}
 
 	#[test]
@@ -407,37 +544,54 @@ mod test {
 
 		let tempdir = TempDir::new("").unwrap();
 		// difficulty = 0x085657254bd9u64;
-		let light = NodeCacheBuilder::new(None).light(tempdir.path(), 486382);
+		let light = NodeCacheBuilder::new(None).light::<ProgPow092Params>(tempdir.path(), 486382);
 		let (mix_hash_res, value_res) = light_compute(&light, &hash, nonce);
 		assert_eq!(mix_hash_res[..], mix_hash[..]);
 		assert_eq!(value_res[..], boundary[..]);
 	}
 
+	#[test]
+	fn test_dataset_range() {
+		let tempdir = TempDir::new("").unwrap();
+		let light = NodeCacheBuilder::new(None).light::<ProgPow092Params>(tempdir.path(), 0);
+		let start = light.dataset_len() - 3;
+		let mut out = [0u8; 3 * NODE_BYTES];
+		light.calc_dataset_range(start, 3, &mut out).unwrap();
+		for i in 0..3 {
+			assert_eq!(
+				out[i * NODE_BYTES..(i + 1) * NODE_BYTES],
+				light.dataset_item(start + i as u32)[..]
+			);
+		}
+		assert!(light.calc_dataset_range(start, 4, &mut out).is_err());
+		assert!(light.calc_dataset_range(0, 4, &mut out).is_err());
+	}
+
 	#[test]
 	fn test_drop_old_data() {
 		let tempdir = TempDir::new("").unwrap();
 		let builder = NodeCacheBuilder::new(None);
 		let first = builder
-			.light(tempdir.path(), 0)
+			.light::<ProgPow092Params>(tempdir.path(), 0)
 			.to_file()
 			.unwrap()
 			.to_owned();
 
 		let second = builder
-			.light(tempdir.path(), ETHASH_EPOCH_LENGTH)
+			.light::<ProgPow092Params>(tempdir.path(), ETHASH_EPOCH_LENGTH)
 			.to_file()
 			.unwrap()
 			.to_owned();
 		assert!(fs::metadata(&first).is_ok());
 
 		let _ = builder
-			.light(tempdir.path(), ETHASH_EPOCH_LENGTH * 2)
+			.light::<ProgPow092Params>(tempdir.path(), ETHASH_EPOCH_LENGTH * 2)
 			.to_file();
 		assert!(fs::metadata(&first).is_err());
 		assert!(fs::metadata(&second).is_ok());
 
 		let _ = builder
-			.light(tempdir.path(), ETHASH_EPOCH_LENGTH * 3)
+			.light::<ProgPow092Params>(tempdir.path(), ETHASH_EPOCH_LENGTH * 3)
 			.to_file();
 		assert!(fs::metadata(&second).is_err());
 	}
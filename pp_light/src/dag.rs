@@ -0,0 +1,258 @@
+//! Full DAG stored in a file and memory mapped read-only.
+//!
+//! Every process that maps the same file shares its pages through the OS page cache, so
+//! several verifiers on one host hold a single copy of the multi-GB dataset.
+
+use crate::cache::{
+	cache_path, checksum_hasher, invalid_cache, params_id, CacheHeader, NodeCacheBuilder,
+	HEADER_BYTES,
+};
+use crate::cancel::{cancelled, CancelToken};
+use crate::compute::calculate_dag_item;
+use crate::shared::{epoch, get_data_size, Node, NODE_BYTES};
+use memmap::{Mmap, MmapOptions};
+use progpow_base::params::ProgPowParams;
+
+use rayon::prelude::*;
+use std::fs::{self, File, OpenOptions};
+use std::hash::Hasher;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::slice;
+
+const DAG_MAGIC: [u8; 4] = *b"PPLD";
+/// Nodes computed per thread before they are written out.
+const CHUNK_NODES: usize = 1 << 16;
+
+pub struct FullDag {
+	epoch: u64,
+	path: PathBuf,
+	mmap: Mmap,
+}
+
+impl FullDag {
+	pub fn epoch(&self) -> u64 {
+		self.epoch
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	pub fn node(&self, index: usize) -> &Node {
+		&self.as_ref()[index]
+	}
+}
+
+impl AsRef<[Node]> for FullDag {
+	fn as_ref(&self) -> &[Node] {
+		let bytes = &self.mmap[..];
+		unsafe { slice::from_raw_parts(bytes.as_ptr() as *const Node, bytes.len() / NODE_BYTES) }
+	}
+}
+
+impl NodeCacheBuilder {
+	/// Map the full DAG for `block_number` from `cache_dir`, building the file first if it
+	/// is missing or invalid.
+	pub fn full_dag<P: ProgPowParams>(
+		&self,
+		cache_dir: &Path,
+		block_number: u64,
+	) -> io::Result<FullDag> {
+		self.full_dag_cancellable::<P>(cache_dir, block_number, &CancelToken::new())
+	}
+
+	/// Like `full_dag`, stopping early with a `Cancelled` error once `cancel` is set. A
+	/// cancelled build leaves no file behind.
+	pub fn full_dag_cancellable<P: ProgPowParams>(
+		&self,
+		cache_dir: &Path,
+		block_number: u64,
+		cancel: &CancelToken,
+	) -> io::Result<FullDag> {
+		let path = self.dag_path::<P>(cache_dir, block_number);
+		let epoch = epoch::<P>(block_number);
+		let data_size = get_data_size::<P>(block_number);
+		match dag_from_path(&path, epoch, data_size, params_id::<P>()) {
+			Ok(dag) => return Ok(dag),
+			Err(e) => {
+				if e.kind() == io::ErrorKind::InvalidData {
+					let _ = fs::remove_file(&path);
+				}
+			}
+		}
+
+		let cache =
+			self.new_cache_cancellable::<P>(cache_dir.to_path_buf(), block_number, cancel)?;
+		self.install(|| {
+			write_dag(
+				&path,
+				epoch,
+				data_size,
+				params_id::<P>(),
+				cache.as_ref(),
+				cancel,
+			)
+		})?;
+		dag_from_path(&path, epoch, data_size, params_id::<P>())
+	}
+
+	/// Map an existing DAG file without ever building one.
+	pub fn full_dag_from_file<P: ProgPowParams>(
+		&self,
+		cache_dir: &Path,
+		block_number: u64,
+	) -> io::Result<FullDag> {
+		dag_from_path(
+			&self.dag_path::<P>(cache_dir, block_number),
+			epoch::<P>(block_number),
+			get_data_size::<P>(block_number),
+			params_id::<P>(),
+		)
+	}
+
+	fn dag_path<P: ProgPowParams>(&self, cache_dir: &Path, block_number: u64) -> PathBuf {
+		let ident = self.block_number_to_ident::<P>(block_number);
+		cache_path(cache_dir, P::NAME, &ident).with_extension("dag")
+	}
+}
+
+fn dag_from_path(path: &Path, epoch: u64, data_size: usize, params_id: u64) -> io::Result<FullDag> {
+	let mut file = File::open(path)?;
+	let header = CacheHeader::read(&mut file, DAG_MAGIC)?;
+	if header.payload_len != data_size as u64 {
+		return Err(invalid_cache("DAG file is of incorrect size"));
+	}
+	let mmap = unsafe {
+		MmapOptions::new()
+			.offset(HEADER_BYTES as u64)
+			.len(data_size)
+			.map(&file)?
+	};
+	header.validate(epoch, params_id, &mmap[..])?;
+	Ok(FullDag {
+		epoch,
+		path: path.to_path_buf(),
+		mmap,
+	})
+}
+
+/// Compute the DAG into `<path>.tmp` and rename it into place, so concurrent readers only
+/// ever see a complete file.
+fn write_dag(
+	path: &Path,
+	epoch: u64,
+	data_size: usize,
+	params_id: u64,
+	cache: &[Node],
+	cancel: &CancelToken,
+) -> io::Result<()> {
+	let num_nodes = data_size / NODE_BYTES;
+	let threads = rayon::current_num_threads();
+	let tmp = path.with_extension("dag.tmp");
+
+	let mut file = BufWriter::new(
+		OpenOptions::new()
+			.write(true)
+			.create(true)
+			.truncate(true)
+			.open(&tmp)?,
+	);
+	file.write_all(&[0u8; HEADER_BYTES])?;
+
+	let mut bytes = Vec::with_capacity(CHUNK_NODES * threads * NODE_BYTES);
+	let mut hasher = checksum_hasher();
+	let mut start = 0;
+	while start < num_nodes {
+		if cancel.is_cancelled() {
+			drop(file);
+			let _ = fs::remove_file(&tmp);
+			return Err(cancelled());
+		}
+		let end = (start + CHUNK_NODES * threads).min(num_nodes);
+		let mut nodes = vec![
+			Node {
+				bytes: [0u8; NODE_BYTES]
+			};
+			end - start
+		];
+		nodes
+			.par_iter_mut()
+			.enumerate()
+			.for_each(|(i, node)| *node = calculate_dag_item((start + i) as u32, cache));
+
+		bytes.clear();
+		for node in &nodes {
+			bytes.extend_from_slice(node.as_bytes());
+		}
+		hasher.write(&bytes);
+		file.write_all(&bytes)?;
+		start = end;
+	}
+
+	let header = CacheHeader::with_checksum(
+		DAG_MAGIC,
+		epoch,
+		params_id,
+		data_size as u64,
+		hasher.finish(),
+	);
+	let mut file = file.into_inner().map_err(|e| e.into_error())?;
+	file.seek(SeekFrom::Start(0))?;
+	file.write_all(&header.to_bytes())?;
+	file.sync_all()?;
+	fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use tempdir::TempDir;
+
+	#[test]
+	fn test_dag_file_validation() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = tempdir.path().join("test.dag");
+		let payload = vec![7u8; 4 * NODE_BYTES];
+		let header = CacheHeader::new(DAG_MAGIC, 0, 1, &payload);
+		let mut bytes = header.to_bytes().to_vec();
+		bytes.extend_from_slice(&payload);
+		fs::write(&path, &bytes).unwrap();
+
+		let dag = dag_from_path(&path, 0, payload.len(), 1).unwrap();
+		assert_eq!(dag.as_ref().len(), 4);
+		assert_eq!(dag.node(3).as_bytes()[..], payload[..NODE_BYTES]);
+
+		let err = dag_from_path(&path, 1, payload.len(), 1).err().unwrap();
+		assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+		let err = dag_from_path(&path, 0, payload.len(), 2).err().unwrap();
+		assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+		let last = bytes.len() - 1;
+		bytes[last] ^= 1;
+		fs::write(&path, &bytes).unwrap();
+		let err = dag_from_path(&path, 0, payload.len(), 1).err().unwrap();
+		assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn test_cancelled_dag_leaves_no_file() {
+		let tempdir = TempDir::new("").unwrap();
+		let path = tempdir.path().join("test.dag");
+		let cache = vec![
+			Node {
+				bytes: [0u8; NODE_BYTES]
+			};
+			16
+		];
+		let cancel = CancelToken::new();
+		cancel.cancel();
+
+		let err = write_dag(&path, 0, 64 * NODE_BYTES, 1, &cache, &cancel)
+			.err()
+			.unwrap();
+		assert!(crate::cancel::is_cancelled(&err));
+		assert!(!path.exists());
+		assert!(!path.with_extension("dag.tmp").exists());
+	}
+}
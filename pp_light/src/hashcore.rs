@@ -0,0 +1,819 @@
+//! The ProgPoW hash itself: keccak-f800, KISS99, mix initialization and the inner loop.
+//!
+//! Needs neither `std` nor an allocator. DAG nodes come from a caller-supplied function,
+//! so embedded and enclave verifiers can bring their own dataset source; the light cache
+//! and file layers around it are behind the `std` feature.
+
+use progpow_base::params::{MathMapping, ProgPowParams};
+
+pub type H256 = [u8; 32];
+
+pub const PROGPOW_CACHE_BYTES: usize = 16 * 1024;
+pub const PROGPOW_CACHE_WORDS: usize = PROGPOW_CACHE_BYTES / 4;
+/// Cache accesses per loop in ProgPoW 0.9.2, see `Variant::cnt_cache`.
+pub const PROGPOW_CNT_CACHE: usize = 12;
+/// Math operations per loop in ProgPoW 0.9.2, see `Variant::cnt_math`.
+pub const PROGPOW_CNT_MATH: usize = 20;
+/// `ETHASH_ACCESSES`.
+pub const PROGPOW_CNT_DAG: usize = 64;
+pub const PROGPOW_DAG_LOADS: usize = 4;
+/// Twice `ETHASH_MIX_BYTES`.
+pub const PROGPOW_MIX_BYTES: usize = 256;
+pub const PROGPOW_PERIOD_LENGTH: usize = 50; // blocks per progpow epoch (N)
+pub const PROGPOW_LANES: usize = 16;
+pub const PROGPOW_REGS: usize = 32;
+/// Rounds of keccak-f800 in the standard parameters. Variants may use up to
+/// `KECCAK_MAX_ROUNDS`.
+pub const KECCAK_ROUNDS: u32 = 22;
+/// One round constant each is defined.
+pub const KECCAK_MAX_ROUNDS: u32 = 24;
+
+/// What `hash` takes from the chain parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Variant {
+	/// Cache accesses per loop, `ProgPowParams::CNT_CACHE`.
+	pub cnt_cache: usize,
+	/// Math operations per loop, `ProgPowParams::CNT_MATH`.
+	pub cnt_math: usize,
+	pub mapping: MathMapping,
+	/// `ProgPowParams::KECCAK_ROUNDS`, `KECCAK_ROUNDS` for most chains.
+	pub keccak_rounds: u32,
+	/// `ProgPowParams::SEED_BYTE_SWAP`: the seed is read big-endian from the initial
+	/// keccak state, as in ProgPoW 0.9.2, Zano and Sero, rather than as its raw words.
+	/// The final keccak absorbs the seed as used.
+	pub seed_byte_swap: bool,
+}
+
+impl Variant {
+	pub fn of<P: ProgPowParams>() -> Self {
+		Variant {
+			cnt_cache: P::CNT_CACHE as usize,
+			cnt_math: P::CNT_MATH as usize,
+			mapping: P::MATH_MAPPING,
+			keccak_rounds: P::KECCAK_ROUNDS,
+			seed_byte_swap: P::SEED_BYTE_SWAP,
+		}
+	}
+}
+
+const FNV_HASH: u32 = 0x811c9dc5;
+const FNV_PRIME: u32 = 0x01000193;
+
+const KECCAKF_RNDC: [u32; 24] = [
+	0x00000001, 0x00008082, 0x0000808a, 0x80008000, 0x0000808b, 0x80000001, 0x80008081, 0x00008009,
+	0x0000008a, 0x00000088, 0x80008009, 0x8000000a, 0x8000808b, 0x0000008b, 0x00008089, 0x00008003,
+	0x00008002, 0x00000080, 0x0000800a, 0x8000000a, 0x80008081, 0x00008080, 0x80000001, 0x80008008,
+];
+
+const KECCAKF_ROTC: [u32; 24] = [
+	1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,
+];
+
+const KECCAKF_PILN: [usize; 24] = [
+	10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+];
+
+fn keccak_f800_round(st: &mut [u32; 25], r: usize) {
+	// Theta
+	let mut bc = [0u32; 5];
+	for i in 0..bc.len() {
+		bc[i] = st[i] ^ st[i + 5] ^ st[i + 10] ^ st[i + 15] ^ st[i + 20];
+	}
+
+	for i in 0..bc.len() {
+		let t = bc[(i + 4) % 5] ^ bc[(i + 1) % 5].rotate_left(1);
+		for j in (0..st.len()).step_by(5) {
+			st[j + i] ^= t;
+		}
+	}
+
+	// Rho Pi
+	let mut t = st[1];
+
+	debug_assert_eq!(KECCAKF_ROTC.len(), 24);
+	for i in 0..24 {
+		let j = KECCAKF_PILN[i];
+		bc[0] = st[j];
+		st[j] = t.rotate_left(KECCAKF_ROTC[i]);
+		t = bc[0];
+	}
+
+	// Chi
+	for j in (0..st.len()).step_by(5) {
+		for i in 0..bc.len() {
+			bc[i] = st[j + i];
+		}
+		for i in 0..bc.len() {
+			st[j + i] ^= (!bc[(i + 1) % 5]) & bc[(i + 2) % 5];
+		}
+	}
+
+	// Iota
+	debug_assert!(r < KECCAKF_RNDC.len());
+	st[0] ^= KECCAKF_RNDC[r];
+}
+
+fn keccak_f800(header_hash: H256, nonce: u64, result: [u32; 8], rounds: u32, st: &mut [u32; 25]) {
+	assert!(
+		rounds <= KECCAK_MAX_ROUNDS,
+		"keccak-f800 has at most {} rounds",
+		KECCAK_MAX_ROUNDS
+	);
+
+	for i in 0..8 {
+		st[i] = (header_hash[4 * i] as u32)
+			+ ((header_hash[4 * i + 1] as u32) << 8)
+			+ ((header_hash[4 * i + 2] as u32) << 16)
+			+ ((header_hash[4 * i + 3] as u32) << 24);
+	}
+
+	st[8] = nonce as u32;
+	st[9] = (nonce >> 32) as u32;
+
+	for i in 0..8 {
+		st[10 + i] = result[i];
+	}
+
+	for r in 0..rounds as usize {
+		keccak_f800_round(st, r);
+	}
+}
+
+pub fn keccak_f800_short(header_hash: H256, nonce: u64, result: [u32; 8], rounds: u32) -> u64 {
+	let mut st = [0u32; 25];
+	keccak_f800(header_hash, nonce, result, rounds, &mut st);
+	(st[0].swap_bytes() as u64) << 32 | st[1].swap_bytes() as u64
+}
+
+/// The seed `fill_mix` expands, from the header and nonce.
+fn hash_seed(header_hash: H256, nonce: u64, variant: &Variant) -> u64 {
+	if variant.seed_byte_swap {
+		return keccak_f800_short(header_hash, nonce, [0u32; 8], variant.keccak_rounds);
+	}
+	let mut st = [0u32; 25];
+	keccak_f800(
+		header_hash,
+		nonce,
+		[0u32; 8],
+		variant.keccak_rounds,
+		&mut st,
+	);
+	(st[1] as u64) << 32 | st[0] as u64
+}
+
+pub fn keccak_f800_long(header_hash: H256, nonce: u64, result: [u32; 8], rounds: u32) -> [u32; 8] {
+	let mut st = [0u32; 25];
+	keccak_f800(header_hash, nonce, result, rounds, &mut st);
+
+	// NOTE: transmute from `[u32; 8]` to `[u8; 32]`
+	[st[0], st[1], st[2], st[3], st[4], st[5], st[6], st[7]]
+}
+
+#[inline]
+pub fn fnv1a_hash(h: u32, d: u32) -> u32 {
+	(h ^ d).wrapping_mul(FNV_PRIME)
+}
+
+#[derive(Clone)]
+pub struct Kiss99 {
+	z: u32,
+	w: u32,
+	jsr: u32,
+	jcong: u32,
+}
+
+impl Kiss99 {
+	pub fn new(z: u32, w: u32, jsr: u32, jcong: u32) -> Kiss99 {
+		Kiss99 { z, w, jsr, jcong }
+	}
+
+	#[inline]
+	pub fn next_u32(&mut self) -> u32 {
+		self.z = 36969u32
+			.wrapping_mul(self.z & 65535)
+			.wrapping_add(self.z >> 16);
+		self.w = 18000u32
+			.wrapping_mul(self.w & 65535)
+			.wrapping_add(self.w >> 16);
+		let mwc = (self.z << 16).wrapping_add(self.w);
+		self.jsr ^= self.jsr << 17;
+		self.jsr ^= self.jsr >> 13;
+		self.jsr ^= self.jsr << 5;
+		self.jcong = 69069u32.wrapping_mul(self.jcong).wrapping_add(1234567);
+
+		(mwc ^ self.jcong).wrapping_add(self.jsr)
+	}
+}
+
+pub fn fill_mix(seed: u64, lane_id: u32) -> [u32; PROGPOW_REGS] {
+	// Use FNV to expand the per-warp seed to per-lane
+	// Use KISS to expand the per-lane seed to fill mix
+	let z = fnv1a_hash(FNV_HASH, seed as u32);
+	let w = fnv1a_hash(z, (seed >> 32) as u32);
+	let jsr = fnv1a_hash(w, lane_id);
+	let jcong = fnv1a_hash(jsr, lane_id);
+
+	let mut rnd = Kiss99::new(z, w, jsr, jcong);
+
+	let mut mix = [0; PROGPOW_REGS];
+
+	debug_assert_eq!(PROGPOW_REGS, 32);
+	for i in 0..32 {
+		mix[i] = rnd.next_u32();
+	}
+
+	mix
+}
+
+// Merge new data from b into the value in a. Assuming A has high entropy only
+// do ops that retain entropy even if B is low entropy (IE don't do A&B)
+pub fn merge(a: u32, b: u32, r: u32) -> u32 {
+	match r % 4 {
+		0 => a.wrapping_mul(33).wrapping_add(b),
+		1 => (a ^ b).wrapping_mul(33),
+		2 => a.rotate_left(((r >> 16) % 31) + 1) ^ b,
+		_ => a.rotate_right(((r >> 16) % 31) + 1) ^ b,
+	}
+}
+
+pub fn math(a: u32, b: u32, r: u32, mapping: MathMapping) -> u32 {
+	match mapping {
+		MathMapping::Standard | MathMapping::KawPow => match r % 11 {
+			0 => a.wrapping_add(b),
+			1 => a.wrapping_mul(b),
+			2 => ((a as u64).wrapping_mul(b as u64) >> 32) as u32,
+			3 => a.min(b),
+			4 => a.rotate_left(b),
+			5 => a.rotate_right(b),
+			6 => a & b,
+			7 => a | b,
+			8 => a ^ b,
+			9 => a.leading_zeros().wrapping_add(b.leading_zeros()),
+			_ => a.count_ones().wrapping_add(b.count_ones()),
+		},
+		MathMapping::Zano => match r % 11 {
+			0 => a.leading_zeros().wrapping_add(b.leading_zeros()),
+			1 => a.count_ones().wrapping_add(b.count_ones()),
+			2 => a.wrapping_add(b),
+			3 => a.wrapping_mul(b),
+			4 => ((a as u64).wrapping_mul(b as u64) >> 32) as u32,
+			5 => a.min(b),
+			6 => a.rotate_left(b),
+			7 => a.rotate_right(b),
+			8 => a & b,
+			9 => a | b,
+			_ => a ^ b,
+		},
+	}
+}
+
+fn progpow_init(seed: u64) -> (Kiss99, [u32; PROGPOW_REGS], [u32; PROGPOW_REGS]) {
+	let z = fnv1a_hash(FNV_HASH, seed as u32);
+	let w = fnv1a_hash(z, (seed >> 32) as u32);
+	let jsr = fnv1a_hash(w, seed as u32);
+	let jcong = fnv1a_hash(jsr, (seed >> 32) as u32);
+
+	let mut rnd = Kiss99::new(z, w, jsr, jcong);
+
+	// Create a random sequence of mix destinations for merge() and mix sources
+	// for cache reads guarantees every destination merged once and guarantees
+	// no duplicate cache reads, which could be optimized away. Uses
+	// Fisher-Yates shuffle.
+	let mut mix_seq_dst = [0u32; PROGPOW_REGS];
+	let mut mix_seq_cache = [0u32; PROGPOW_REGS];
+	for i in 0..mix_seq_dst.len() {
+		mix_seq_dst[i] = i as u32;
+		mix_seq_cache[i] = i as u32;
+	}
+
+	for i in (1..mix_seq_dst.len()).rev() {
+		let j = rnd.next_u32() as usize % (i + 1);
+		mix_seq_dst.swap(i, j);
+
+		let j = rnd.next_u32() as usize % (i + 1);
+		mix_seq_cache.swap(i, j);
+	}
+
+	(rnd, mix_seq_dst, mix_seq_cache)
+}
+
+pub type CDag = [u32; PROGPOW_CACHE_WORDS];
+
+/// One of the `PROGPOW_CNT_DAG` rounds. `dag_item` returns the words of a 64-byte DAG
+/// node by index and `data_size` is the DAG size in `PROGPOW_MIX_BYTES` units.
+pub fn progpow_loop<F: Fn(u32) -> [u32; 16]>(
+	seed: u64,
+	loop_: usize,
+	mix: &mut [[u32; PROGPOW_REGS]; PROGPOW_LANES],
+	dag_item: &F,
+	c_dag: &CDag,
+	data_size: usize,
+	variant: &Variant,
+) {
+	// All lanes share a base address for the global load. Global offset uses
+	// mix[0] to guarantee it depends on the load result.
+	let g_offset = mix[loop_ % PROGPOW_LANES][0] as usize
+		% (64 * data_size / (PROGPOW_LANES * PROGPOW_DAG_LOADS));
+
+	// 256 bytes of dag data
+	let mut dag_words = [0u32; 64];
+
+	// Fetch DAG nodes (64 bytes each)
+	for l in 0..PROGPOW_DAG_LOADS {
+		let index = g_offset * PROGPOW_LANES * PROGPOW_DAG_LOADS + l * 16;
+		dag_words[l * 16..(l + 1) * 16].copy_from_slice(&dag_item(index as u32 / 16));
+	}
+
+	let (rnd, mix_seq_dst, mix_seq_cache) = progpow_init(seed);
+
+	// Lanes can execute in parallel and will be convergent
+	for l in 0..mix.len() {
+		let mut rnd = rnd.clone();
+
+		// Initialize the seed and mix destination sequence
+		let mut mix_seq_dst_cnt = 0;
+		let mut mix_seq_cache_cnt = 0;
+
+		let mut mix_dst = || {
+			let res = mix_seq_dst[mix_seq_dst_cnt % PROGPOW_REGS] as usize;
+			mix_seq_dst_cnt += 1;
+			res
+		};
+		let mut mix_cache = || {
+			let res = mix_seq_cache[mix_seq_cache_cnt % PROGPOW_REGS] as usize;
+			mix_seq_cache_cnt += 1;
+			res
+		};
+
+		for i in 0..variant.cnt_cache.max(variant.cnt_math) {
+			if i < variant.cnt_cache {
+				// Cached memory access, lanes access random 32-bit locations
+				// within the first portion of the DAG
+				let offset = mix[l][mix_cache()] as usize % PROGPOW_CACHE_WORDS;
+				let data = c_dag[offset];
+				let dst = mix_dst();
+
+				mix[l][dst] = merge(mix[l][dst], data, rnd.next_u32());
+			}
+
+			if i < variant.cnt_math {
+				// Random math
+				// Generate 2 unique sources
+				let src_rnd = rnd.next_u32() % (PROGPOW_REGS * (PROGPOW_REGS - 1)) as u32;
+				let src1 = src_rnd % PROGPOW_REGS as u32; // 0 <= src1 < PROGPOW_REGS
+				let mut src2 = src_rnd / PROGPOW_REGS as u32; // 0 <= src2 < PROGPOW_REGS - 1
+				if src2 >= src1 {
+					src2 += 1; // src2 is now any reg other than src1
+				}
+
+				let data = math(
+					mix[l][src1 as usize],
+					mix[l][src2 as usize],
+					rnd.next_u32(),
+					variant.mapping,
+				);
+				let dst = mix_dst();
+
+				mix[l][dst] = merge(mix[l][dst], data, rnd.next_u32());
+			}
+		}
+
+		// Global load to sequential locations
+		let mut data_g = [0u32; PROGPOW_DAG_LOADS];
+		let index = ((l ^ loop_) % PROGPOW_LANES) * PROGPOW_DAG_LOADS;
+		for i in 0..PROGPOW_DAG_LOADS {
+			data_g[i] = dag_words[index + i];
+		}
+
+		// Consume the global load data at the very end of the loop to allow
+		// full latency hiding. Always merge into `mix[0]` to feed the offset
+		// calculation.
+		mix[l][0] = merge(mix[l][0], data_g[0], rnd.next_u32());
+		for i in 1..PROGPOW_DAG_LOADS {
+			let dst = mix_dst();
+			mix[l][dst] = merge(mix[l][dst], data_g[i], rnd.next_u32());
+		}
+	}
+}
+
+/// Hash `header_hash` with `nonce` for the given ProgPoW `period` (block number divided by
+/// `PROGPOW_PERIOD_LENGTH`). `dag_bytes` is the full DAG size and `dag_item` returns DAG
+/// nodes, computed from the light cache or read from a full DAG. `variant` holds the
+/// chain's loop counts, math mapping and keccak rounds. Returns `(final_hash, mix_hash)`
+/// as words.
+///
+/// Uses the lane-parallel implementation compiled for AVX2 or NEON when the CPU has it,
+/// and `hash_scalar` otherwise.
+pub fn hash<F: Fn(u32) -> [u32; 16]>(
+	header_hash: H256,
+	nonce: u64,
+	period: u64,
+	dag_bytes: usize,
+	c_dag: &CDag,
+	variant: &Variant,
+	dag_item: F,
+) -> ([u32; 8], [u32; 8]) {
+	#[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+	{
+		if std::is_x86_feature_detected!("avx2") {
+			return unsafe {
+				hash_avx2(
+					header_hash,
+					nonce,
+					period,
+					dag_bytes,
+					c_dag,
+					variant,
+					dag_item,
+				)
+			};
+		}
+	}
+	#[cfg(all(feature = "std", target_arch = "aarch64"))]
+	{
+		if std::arch::is_aarch64_feature_detected!("neon") {
+			return unsafe {
+				hash_neon(
+					header_hash,
+					nonce,
+					period,
+					dag_bytes,
+					c_dag,
+					variant,
+					dag_item,
+				)
+			};
+		}
+	}
+	hash_scalar(
+		header_hash,
+		nonce,
+		period,
+		dag_bytes,
+		c_dag,
+		variant,
+		dag_item,
+	)
+}
+
+#[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+#[target_feature(enable = "avx2")]
+unsafe fn hash_avx2<F: Fn(u32) -> [u32; 16]>(
+	header_hash: H256,
+	nonce: u64,
+	period: u64,
+	dag_bytes: usize,
+	c_dag: &CDag,
+	variant: &Variant,
+	dag_item: F,
+) -> ([u32; 8], [u32; 8]) {
+	hash_lanes(
+		header_hash,
+		nonce,
+		period,
+		dag_bytes,
+		c_dag,
+		variant,
+		dag_item,
+	)
+}
+
+#[cfg(all(feature = "std", target_arch = "aarch64"))]
+#[target_feature(enable = "neon")]
+unsafe fn hash_neon<F: Fn(u32) -> [u32; 16]>(
+	header_hash: H256,
+	nonce: u64,
+	period: u64,
+	dag_bytes: usize,
+	c_dag: &CDag,
+	variant: &Variant,
+	dag_item: F,
+) -> ([u32; 8], [u32; 8]) {
+	hash_lanes(
+		header_hash,
+		nonce,
+		period,
+		dag_bytes,
+		c_dag,
+		variant,
+		dag_item,
+	)
+}
+
+/// Mix stored register-major, `mix[reg][lane]`. Every lane runs the same sequence of
+/// operations, so with one register of all lanes contiguous each step is a single loop
+/// over `PROGPOW_LANES` that the compiler vectorizes.
+type LaneMix = [[u32; PROGPOW_LANES]; PROGPOW_REGS];
+
+#[inline(always)]
+fn merge_lanes(a: &mut [u32; PROGPOW_LANES], b: &[u32; PROGPOW_LANES], r: u32) {
+	// Select the operation once for all lanes instead of once per lane.
+	match r % 4 {
+		0 => {
+			for l in 0..PROGPOW_LANES {
+				a[l] = a[l].wrapping_mul(33).wrapping_add(b[l]);
+			}
+		}
+		1 => {
+			for l in 0..PROGPOW_LANES {
+				a[l] = (a[l] ^ b[l]).wrapping_mul(33);
+			}
+		}
+		2 => {
+			let rot = ((r >> 16) % 31) + 1;
+			for l in 0..PROGPOW_LANES {
+				a[l] = a[l].rotate_left(rot) ^ b[l];
+			}
+		}
+		_ => {
+			let rot = ((r >> 16) % 31) + 1;
+			for l in 0..PROGPOW_LANES {
+				a[l] = a[l].rotate_right(rot) ^ b[l];
+			}
+		}
+	}
+}
+
+#[inline(always)]
+fn math_lanes(
+	a: &[u32; PROGPOW_LANES],
+	b: &[u32; PROGPOW_LANES],
+	r: u32,
+	mapping: MathMapping,
+) -> [u32; PROGPOW_LANES] {
+	// Zano uses the standard operations rotated by two, see `math`.
+	let op = match mapping {
+		MathMapping::Zano => (r % 11 + 9) % 11,
+		_ => r % 11,
+	};
+	let mut out = [0u32; PROGPOW_LANES];
+	macro_rules! lanes {
+		($f:expr) => {
+			for l in 0..PROGPOW_LANES {
+				out[l] = $f(a[l], b[l]);
+			}
+		};
+	}
+	match op {
+		0 => lanes!(|a: u32, b: u32| a.wrapping_add(b)),
+		1 => lanes!(|a: u32, b: u32| a.wrapping_mul(b)),
+		2 => lanes!(|a: u32, b: u32| ((a as u64).wrapping_mul(b as u64) >> 32) as u32),
+		3 => lanes!(|a: u32, b: u32| a.min(b)),
+		4 => lanes!(|a: u32, b: u32| a.rotate_left(b)),
+		5 => lanes!(|a: u32, b: u32| a.rotate_right(b)),
+		6 => lanes!(|a: u32, b: u32| a & b),
+		7 => lanes!(|a: u32, b: u32| a | b),
+		8 => lanes!(|a: u32, b: u32| a ^ b),
+		9 => lanes!(|a: u32, b: u32| a.leading_zeros().wrapping_add(b.leading_zeros())),
+		_ => lanes!(|a: u32, b: u32| a.count_ones().wrapping_add(b.count_ones())),
+	}
+	out
+}
+
+#[inline(always)]
+fn progpow_loop_lanes<F: Fn(u32) -> [u32; 16]>(
+	init: &(Kiss99, [u32; PROGPOW_REGS], [u32; PROGPOW_REGS]),
+	loop_: usize,
+	mix: &mut LaneMix,
+	dag_item: &F,
+	c_dag: &CDag,
+	data_size: usize,
+	variant: &Variant,
+) {
+	let g_offset = mix[0][loop_ % PROGPOW_LANES] as usize
+		% (64 * data_size / (PROGPOW_LANES * PROGPOW_DAG_LOADS));
+
+	let mut dag_words = [0u32; 64];
+	for l in 0..PROGPOW_DAG_LOADS {
+		let index = g_offset * PROGPOW_LANES * PROGPOW_DAG_LOADS + l * 16;
+		dag_words[l * 16..(l + 1) * 16].copy_from_slice(&dag_item(index as u32 / 16));
+	}
+
+	let (ref rnd, ref mix_seq_dst, ref mix_seq_cache) = *init;
+	let mut rnd = rnd.clone();
+	let mut mix_seq_dst_cnt = 0;
+	let mut mix_seq_cache_cnt = 0;
+	let mut mix_dst = || {
+		let res = mix_seq_dst[mix_seq_dst_cnt % PROGPOW_REGS] as usize;
+		mix_seq_dst_cnt += 1;
+		res
+	};
+
+	for i in 0..variant.cnt_cache.max(variant.cnt_math) {
+		if i < variant.cnt_cache {
+			let src = mix_seq_cache[mix_seq_cache_cnt % PROGPOW_REGS] as usize;
+			mix_seq_cache_cnt += 1;
+			let mut data = [0u32; PROGPOW_LANES];
+			for l in 0..PROGPOW_LANES {
+				data[l] = c_dag[mix[src][l] as usize % PROGPOW_CACHE_WORDS];
+			}
+			let dst = mix_dst();
+			merge_lanes(&mut mix[dst], &data, rnd.next_u32());
+		}
+
+		if i < variant.cnt_math {
+			let src_rnd = rnd.next_u32() % (PROGPOW_REGS * (PROGPOW_REGS - 1)) as u32;
+			let src1 = src_rnd % PROGPOW_REGS as u32;
+			let mut src2 = src_rnd / PROGPOW_REGS as u32;
+			if src2 >= src1 {
+				src2 += 1;
+			}
+
+			let data = math_lanes(
+				&mix[src1 as usize],
+				&mix[src2 as usize],
+				rnd.next_u32(),
+				variant.mapping,
+			);
+			let dst = mix_dst();
+			merge_lanes(&mut mix[dst], &data, rnd.next_u32());
+		}
+	}
+
+	let mut data_g = [[0u32; PROGPOW_LANES]; PROGPOW_DAG_LOADS];
+	for l in 0..PROGPOW_LANES {
+		let index = ((l ^ loop_) % PROGPOW_LANES) * PROGPOW_DAG_LOADS;
+		for i in 0..PROGPOW_DAG_LOADS {
+			data_g[i][l] = dag_words[index + i];
+		}
+	}
+
+	merge_lanes(&mut mix[0], &data_g[0], rnd.next_u32());
+	for i in 1..PROGPOW_DAG_LOADS {
+		let dst = mix_dst();
+		merge_lanes(&mut mix[dst], &data_g[i], rnd.next_u32());
+	}
+}
+
+/// Same result as `hash_scalar`, computed across lanes. Inlined into the
+/// `target_feature` wrappers so it is compiled for their instruction sets.
+#[inline(always)]
+fn hash_lanes<F: Fn(u32) -> [u32; 16]>(
+	header_hash: H256,
+	nonce: u64,
+	period: u64,
+	dag_bytes: usize,
+	c_dag: &CDag,
+	variant: &Variant,
+	dag_item: F,
+) -> ([u32; 8], [u32; 8]) {
+	let data_size = dag_bytes / PROGPOW_MIX_BYTES;
+	assert!(data_size > 0);
+
+	let seed = hash_seed(header_hash, nonce, variant);
+
+	let mut mix: LaneMix = [[0u32; PROGPOW_LANES]; PROGPOW_REGS];
+	for l in 0..PROGPOW_LANES {
+		let lane = fill_mix(seed, l as u32);
+		for i in 0..PROGPOW_REGS {
+			mix[i][l] = lane[i];
+		}
+	}
+
+	// The program only depends on the period, so it is shared by every loop.
+	let init = progpow_init(period);
+	for i in 0..PROGPOW_CNT_DAG {
+		progpow_loop_lanes(&init, i, &mut mix, &dag_item, c_dag, data_size, variant);
+	}
+
+	let mut lane_results = [FNV_HASH; PROGPOW_LANES];
+	for i in 0..PROGPOW_REGS {
+		for l in 0..PROGPOW_LANES {
+			lane_results[l] = fnv1a_hash(lane_results[l], mix[i][l]);
+		}
+	}
+
+	let mut result = [FNV_HASH; 8];
+	for l in 0..PROGPOW_LANES {
+		result[l % 8] = fnv1a_hash(result[l % 8], lane_results[l]);
+	}
+
+	let digest = keccak_f800_long(header_hash, seed, result, variant.keccak_rounds);
+
+	(digest, result)
+}
+
+/// Reference implementation, one lane at a time.
+pub fn hash_scalar<F: Fn(u32) -> [u32; 16]>(
+	header_hash: H256,
+	nonce: u64,
+	period: u64,
+	dag_bytes: usize,
+	c_dag: &CDag,
+	variant: &Variant,
+	dag_item: F,
+) -> ([u32; 8], [u32; 8]) {
+	let mut mix = [[0u32; PROGPOW_REGS]; PROGPOW_LANES];
+	let mut lane_results = [0u32; PROGPOW_LANES];
+
+	let data_size = dag_bytes / PROGPOW_MIX_BYTES;
+
+	// NOTE: This assert is required to aid the optimizer elide the non-zero
+	// remainder check in `progpow_loop`.
+	assert!(data_size > 0);
+
+	// Initialize mix for all lanes
+	let seed = hash_seed(header_hash, nonce, variant);
+
+	for l in 0..mix.len() {
+		mix[l] = fill_mix(seed, l as u32);
+	}
+
+	// Execute the randomly generated inner loop
+	for i in 0..PROGPOW_CNT_DAG {
+		progpow_loop(period, i, &mut mix, &dag_item, c_dag, data_size, variant);
+	}
+
+	// Reduce mix data to a single per-lane result
+	for l in 0..lane_results.len() {
+		lane_results[l] = FNV_HASH;
+		for i in 0..PROGPOW_REGS {
+			lane_results[l] = fnv1a_hash(lane_results[l], mix[l][i]);
+		}
+	}
+
+	// Reduce all lanes to a single 128-bit result
+	let mut result = [FNV_HASH; 8];
+	for l in 0..PROGPOW_LANES {
+		result[l % 8] = fnv1a_hash(result[l % 8], lane_results[l]);
+	}
+
+	let digest = keccak_f800_long(header_hash, seed, result, variant.keccak_rounds);
+
+	(digest, result)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_lanes_match_scalar() {
+		let mut c_dag = [0u32; PROGPOW_CACHE_WORDS];
+		let mut rnd = Kiss99::new(1, 2, 3, 4);
+		for word in c_dag.iter_mut() {
+			*word = rnd.next_u32();
+		}
+		let dag_item = |index: u32| {
+			let mut rnd = Kiss99::new(index, !index, index ^ 0x5555_5555, 7);
+			let mut node = [0u32; 16];
+			for word in node.iter_mut() {
+				*word = rnd.next_u32();
+			}
+			node
+		};
+
+		let standard = Variant {
+			cnt_cache: PROGPOW_CNT_CACHE,
+			cnt_math: PROGPOW_CNT_MATH,
+			mapping: MathMapping::Standard,
+			keccak_rounds: KECCAK_ROUNDS,
+			seed_byte_swap: true,
+		};
+		for variant in &[
+			standard,
+			Variant {
+				mapping: MathMapping::Zano,
+				..standard
+			},
+			Variant {
+				keccak_rounds: KECCAK_MAX_ROUNDS,
+				..standard
+			},
+			Variant {
+				cnt_cache: 11,
+				cnt_math: 18,
+				..standard
+			},
+			Variant {
+				seed_byte_swap: false,
+				..standard
+			},
+		] {
+			for nonce in 0..4u64 {
+				let header = [nonce as u8; 32];
+				let dag_bytes = 1 << 20;
+				let scalar = hash_scalar(header, nonce, 7, dag_bytes, &c_dag, variant, dag_item);
+				let lanes = hash_lanes(header, nonce, 7, dag_bytes, &c_dag, variant, dag_item);
+				assert_eq!(scalar, lanes);
+				assert_eq!(
+					hash(header, nonce, 7, dag_bytes, &c_dag, variant, dag_item),
+					scalar
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn test_kiss99() {
+		let mut rnd = Kiss99::new(362436069, 521288629, 123456789, 380116160);
+		assert_eq!(rnd.next_u32(), 769445856);
+		assert_eq!(rnd.next_u32(), 742012328);
+		assert_eq!(rnd.next_u32(), 2121196314);
+		assert_eq!(rnd.next_u32(), 2805620942);
+		for _ in 4..99999 {
+			rnd.next_u32();
+		}
+		assert_eq!(rnd.next_u32(), 941074834);
+	}
+}
@@ -1,9 +1,21 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(all(target_endian = "big", not(feature = "big-endian")))]
+compile_error!("big-endian targets need the `big-endian` feature");
+
+#[cfg(feature = "std")]
 extern crate either;
+#[cfg(feature = "mmap")]
 extern crate memmap;
-extern crate parking_lot;
+#[cfg(feature = "std")]
 extern crate primal;
+#[cfg(feature = "std")]
+extern crate rayon;
+#[cfg(feature = "std")]
+extern crate twox_hash;
 #[macro_use]
 extern crate crunchy;
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate log;
 
@@ -14,10 +26,25 @@ extern crate serde_json;
 #[cfg(test)]
 extern crate tempdir;
 
+#[cfg(feature = "std")]
 pub mod cache;
+#[cfg(feature = "std")]
+pub mod cancel;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "std")]
 pub mod compute;
+#[cfg(feature = "mmap")]
+pub mod dag;
+pub mod hashcore;
+#[cfg(feature = "std")]
 mod keccak;
+pub mod params;
+#[cfg(feature = "std")]
 pub mod progpow;
+#[cfg(feature = "std")]
 mod seed_compute;
+#[cfg(feature = "std")]
 mod shared;
-
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,53 @@
+//! Parameters for callers without a chain of their own, such as the C and wasm bindings.
+//!
+//! `hashcore` implements the ProgPoW 0.9.2 program, so of a `ProgPowParams` only the loop
+//! counts, math mapping, keccak rounds, seed byte order, epoch and period lengths change the
+//! hashes computed here.
+
+use progpow_base::params::{MathMapping, ProgPowParams};
+
+/// ProgPoW 0.9.2 with the standard math, as in the reference test vectors.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProgPow092Params;
+
+impl ProgPowParams for ProgPow092Params {
+	const NAME: &'static str = "ProgPow092";
+	const EPOCH_LENGTH: u64 = 30000;
+	const PERIOD_LENGTH: u32 = 50;
+	const REGS: u32 = 32;
+	const DAG_LOADS: u32 = 4;
+	const CNT_CACHE: u32 = 12;
+	const CNT_MATH: u32 = 20;
+	const KECCAK_ROUNDS: u32 = 22;
+	const KECCAK_DOMAIN: u32 = 0;
+	const MATH_MAPPING: MathMapping = MathMapping::Standard;
+	const HAS_KISS99_SHUFFLE: bool = false;
+	const HAS_INITIAL_PADDING: bool = false;
+	const SEED_BYTE_SWAP: bool = true;
+	const HAS_RAVENCOIN_RNDC: bool = false;
+	const HAS_MEOWCOIN_RNDC: bool = false;
+	const HAS_EVRMORE_RNDC: bool = false;
+}
+
+/// ProgPoW 0.9.2 with Zano's shifted math mapping, the hash Zano and Sero mine.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProgPow092ZanoParams;
+
+impl ProgPowParams for ProgPow092ZanoParams {
+	const NAME: &'static str = "ProgPow092Zano";
+	const EPOCH_LENGTH: u64 = 30000;
+	const PERIOD_LENGTH: u32 = 50;
+	const REGS: u32 = 32;
+	const DAG_LOADS: u32 = 4;
+	const CNT_CACHE: u32 = 12;
+	const CNT_MATH: u32 = 20;
+	const KECCAK_ROUNDS: u32 = 22;
+	const KECCAK_DOMAIN: u32 = 0;
+	const MATH_MAPPING: MathMapping = MathMapping::Zano;
+	const HAS_KISS99_SHUFFLE: bool = false;
+	const HAS_INITIAL_PADDING: bool = false;
+	const SEED_BYTE_SWAP: bool = true;
+	const HAS_RAVENCOIN_RNDC: bool = false;
+	const HAS_MEOWCOIN_RNDC: bool = false;
+	const HAS_EVRMORE_RNDC: bool = false;
+}
@@ -16,6 +16,7 @@
 
 use crate::keccak::{keccak_256, H256};
 use crate::shared;
+use progpow_base::params::ProgPowParams;
 
 use std::cell::Cell;
 
@@ -33,8 +34,8 @@ impl SeedHashCompute {
 	}
 
 	#[inline]
-	pub fn hash_block_number(&self, block_number: u64) -> H256 {
-		self.hash_epoch(shared::epoch(block_number))
+	pub fn hash_block_number<P: ProgPowParams>(&self, block_number: u64) -> H256 {
+		self.hash_epoch(shared::epoch::<P>(block_number))
 	}
 
 	#[inline]
@@ -67,6 +68,7 @@ impl SeedHashCompute {
 #[cfg(test)]
 mod tests {
 	use super::SeedHashCompute;
+	use crate::params::ProgPow092Params;
 
 	#[test]
 	fn test_seed_compute_once() {
@@ -75,38 +77,48 @@ mod tests {
 			241, 175, 44, 134, 39, 121, 245, 239, 228, 236, 43, 160, 195, 152, 46, 7, 199, 5, 253,
 			147, 241, 206, 98, 43, 3, 104, 17, 40, 192, 79, 106, 162,
 		];
-		assert_eq!(seed_compute.hash_block_number(486382), hash);
+		assert_eq!(
+			seed_compute.hash_block_number::<ProgPow092Params>(486382),
+			hash
+		);
 	}
 
 	#[test]
 	fn test_seed_compute_zero() {
 		let seed_compute = SeedHashCompute::default();
-		assert_eq!(seed_compute.hash_block_number(0), [0u8; 32]);
+		assert_eq!(
+			seed_compute.hash_block_number::<ProgPow092Params>(0),
+			[0u8; 32]
+		);
 	}
 
 	#[test]
 	fn test_seed_compute_after_older() {
 		let seed_compute = SeedHashCompute::default();
 		// calculating an older value first shouldn't affect the result
-		let _ = seed_compute.hash_block_number(50000);
+		let _ = seed_compute.hash_block_number::<ProgPow092Params>(50000);
 		let hash = [
 			241, 175, 44, 134, 39, 121, 245, 239, 228, 236, 43, 160, 195, 152, 46, 7, 199, 5, 253,
 			147, 241, 206, 98, 43, 3, 104, 17, 40, 192, 79, 106, 162,
 		];
-		assert_eq!(seed_compute.hash_block_number(486382), hash);
+		assert_eq!(
+			seed_compute.hash_block_number::<ProgPow092Params>(486382),
+			hash
+		);
 	}
 
 	#[test]
 	fn test_seed_compute_after_newer() {
 		let seed_compute = SeedHashCompute::default();
 		// calculating an newer value first shouldn't affect the result
-		let _ = seed_compute.hash_block_number(972764);
+		let _ = seed_compute.hash_block_number::<ProgPow092Params>(972764);
 		let hash = [
 			241, 175, 44, 134, 39, 121, 245, 239, 228, 236, 43, 160, 195, 152, 46, 7, 199, 5, 253,
 			147, 241, 206, 98, 43, 3, 104, 17, 40, 192, 79, 106, 162,
 		];
-		assert_eq!(seed_compute.hash_block_number(486382), hash);
+		assert_eq!(
+			seed_compute.hash_block_number::<ProgPow092Params>(486382),
+			hash
+		);
 	}
-
 }
-
@@ -15,6 +15,7 @@
 // along with Parity Ethereum.  If not, see <http://www.gnu.org/licenses/>.
 
 use primal::is_prime;
+use progpow_base::params::ProgPowParams;
 
 pub const DATASET_BYTES_INIT: u64 = 1 << 30;
 pub const DATASET_BYTES_GROWTH: u64 = 1 << 23;
@@ -30,8 +31,8 @@ pub const NODE_DWORDS: usize = NODE_WORDS / 2;
 pub const NODE_WORDS: usize = NODE_BYTES / 4;
 pub const NODE_BYTES: usize = 64;
 
-pub fn epoch(block_number: u64) -> u64 {
-	block_number / ETHASH_EPOCH_LENGTH
+pub fn epoch<P: ProgPowParams>(block_number: u64) -> u64 {
+	block_number / P::EPOCH_LENGTH
 }
 
 static CHARS: &'static [u8] = b"0123456789abcdef";
@@ -45,9 +46,9 @@ pub fn to_hex(bytes: &[u8]) -> String {
 	unsafe { String::from_utf8_unchecked(v) }
 }
 
-pub fn get_cache_size(block_number: u64) -> usize {
+pub fn get_cache_size<P: ProgPowParams>(block_number: u64) -> usize {
 	// TODO: Memoise
-	let mut sz: u64 = CACHE_BYTES_INIT + CACHE_BYTES_GROWTH * (block_number / ETHASH_EPOCH_LENGTH);
+	let mut sz: u64 = CACHE_BYTES_INIT + CACHE_BYTES_GROWTH * epoch::<P>(block_number);
 	sz = sz - NODE_BYTES as u64;
 	while !is_prime(sz / NODE_BYTES as u64) {
 		sz = sz - 2 * NODE_BYTES as u64;
@@ -55,10 +56,9 @@ pub fn get_cache_size(block_number: u64) -> usize {
 	sz as usize
 }
 
-pub fn get_data_size(block_number: u64) -> usize {
+pub fn get_data_size<P: ProgPowParams>(block_number: u64) -> usize {
 	// TODO: Memoise
-	let mut sz: u64 =
-		DATASET_BYTES_INIT + DATASET_BYTES_GROWTH * (block_number / ETHASH_EPOCH_LENGTH);
+	let mut sz: u64 = DATASET_BYTES_INIT + DATASET_BYTES_GROWTH * epoch::<P>(block_number);
 	sz = sz - ETHASH_MIX_BYTES as u64;
 	while !is_prime(sz / ETHASH_MIX_BYTES as u64) {
 		sz = sz - 2 * ETHASH_MIX_BYTES as u64;
@@ -151,4 +151,32 @@ impl Node {
 	pub fn as_dwords_mut(&mut self) -> &mut NodeDwords {
 		unsafe { &mut self.dwords }
 	}
+
+	/// Word `w` of the hashed bytes, which ethash reads as little-endian. The same as
+	/// `as_words()[w]` on little-endian hosts.
+	#[inline(always)]
+	pub fn le_word(&self, w: usize) -> u32 {
+		u32::from_le(self.as_words()[w])
+	}
+
+	/// All words of the hashed bytes, see `le_word`.
+	#[inline(always)]
+	pub fn le_words(&self) -> NodeWords {
+		let mut words = [0u32; NODE_WORDS];
+		for (word, bytes) in words.iter_mut().zip(self.as_bytes().chunks_exact(4)) {
+			*word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+		}
+		words
+	}
+
+	/// Switch the words between little-endian and native byte order, before and after
+	/// arithmetic on hashed bytes. Does nothing on little-endian hosts.
+	#[inline(always)]
+	pub fn fix_endian(&mut self) {
+		if cfg!(target_endian = "big") {
+			for word in self.as_words_mut().iter_mut() {
+				*word = word.swap_bytes();
+			}
+		}
+	}
 }
@@ -0,0 +1,42 @@
+//! Light verification for `wasm32-unknown-unknown`, with the cache supplied by the caller.
+//!
+//! Build with `--no-default-features --features wasm`.
+
+use crate::cache::{NodeCacheBuilder, OptimizeFor};
+use crate::compute::Light;
+use crate::keccak::H256;
+use crate::params::ProgPow092Params;
+use wasm_bindgen::prelude::*;
+
+/// Hash `header` with `nonce` at `height` using a preloaded light cache, either a cache
+/// file or its bare payload.
+///
+/// Returns 64 bytes: the final hash as big-endian words followed by the mix hash as
+/// little-endian words.
+#[wasm_bindgen]
+pub fn verify_wasm(
+	header: &[u8],
+	height: u64,
+	nonce: u64,
+	cache_bytes: &[u8],
+) -> Result<Vec<u8>, JsValue> {
+	if header.len() != 32 {
+		return Err(JsValue::from_str("header must be 32 bytes"));
+	}
+	let mut header_hash: H256 = [0u8; 32];
+	header_hash.copy_from_slice(header);
+
+	let builder = NodeCacheBuilder::new(OptimizeFor::Cpu);
+	let light = Light::from_bytes_with_builder::<ProgPow092Params>(&builder, height, cache_bytes)
+		.map_err(|e| JsValue::from_str(&e.to_string()))?;
+	let (digest, mix) = light.compute::<ProgPow092Params>(&header_hash, nonce, height);
+
+	let mut out = Vec::with_capacity(64);
+	for word in digest.iter() {
+		out.extend_from_slice(&word.to_be_bytes());
+	}
+	for word in mix.iter() {
+		out.extend_from_slice(&word.to_le_bytes());
+	}
+	Ok(out)
+}
@@ -0,0 +1,256 @@
+//! Python bindings, built as the `progpow` extension module.
+//!
+//! ```python
+//! import progpow
+//! final_hash, mix = progpow.hash(header, height, nonce)
+//! ok = progpow.verify(header, height, nonce, mix, target)
+//! ```
+//!
+//! Hashes and targets are 32-byte `bytes`, in the byte order of `Verifier::verify_hash`.
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use progpow::hardware::PpCPU;
+use progpow::params::DynParams;
+use progpow::presets;
+use progpow::types::{ProgPowError, ShareStatus, Verifier, H256};
+
+fn params(name: &str) -> PyResult<DynParams> {
+	presets::by_name(name).ok_or_else(|| PyValueError::new_err(format!("unknown params: {}", name)))
+}
+
+fn h256(bytes: &[u8], what: &str) -> PyResult<H256> {
+	if bytes.len() != 32 {
+		return Err(PyValueError::new_err(format!("{} must be 32 bytes", what)));
+	}
+	let mut out = [0u8; 32];
+	out.copy_from_slice(bytes);
+	Ok(out)
+}
+
+fn runtime(e: ProgPowError) -> PyErr {
+	PyRuntimeError::new_err(format!("{:?}", e))
+}
+
+/// CPU verifier that keeps its light cache between calls. Prefer it over the module
+/// functions when verifying many hashes.
+#[pyclass]
+struct Verifier {
+	cpu: PpCPU<DynParams>,
+}
+
+#[pymethods]
+impl Verifier {
+	#[new]
+	#[pyo3(signature = (params = "kawpow"))]
+	fn new(params: &str) -> PyResult<Self> {
+		Ok(Verifier {
+			cpu: PpCPU::new_dyn(self::params(params)?),
+		})
+	}
+
+	/// Returns `(final_hash, mix_hash)`.
+	fn hash<'py>(
+		&self,
+		py: Python<'py>,
+		header: &[u8],
+		height: u64,
+		nonce: u64,
+	) -> PyResult<(&'py PyBytes, &'py PyBytes)> {
+		let header = h256(header, "header")?;
+		let (final_hash, mix) = py
+			.allow_threads(|| self.cpu.verify_hash(&header, height, nonce))
+			.map_err(runtime)?;
+		Ok((PyBytes::new(py, &final_hash), PyBytes::new(py, &mix)))
+	}
+
+	/// True if `mix_hash` matches and the final hash meets `target`.
+	fn verify(
+		&self,
+		py: Python,
+		header: &[u8],
+		height: u64,
+		nonce: u64,
+		mix_hash: &[u8],
+		target: &[u8],
+	) -> PyResult<bool> {
+		let header = h256(header, "header")?;
+		let mix_hash = h256(mix_hash, "mix_hash")?;
+		let target = h256(target, "target")?;
+		let status = py
+			.allow_threads(|| {
+				self.cpu
+					.validate_share(&header, height, nonce, &mix_hash, &target)
+			})
+			.map_err(runtime)?;
+		Ok(status == ShareStatus::Ok)
+	}
+}
+
+#[pyfunction]
+#[pyo3(signature = (header, height, nonce, params = "kawpow"))]
+fn hash<'py>(
+	py: Python<'py>,
+	header: &[u8],
+	height: u64,
+	nonce: u64,
+	params: &str,
+) -> PyResult<(&'py PyBytes, &'py PyBytes)> {
+	Verifier::new(params)?.hash(py, header, height, nonce)
+}
+
+#[pyfunction]
+#[pyo3(signature = (header, height, nonce, mix_hash, target, params = "kawpow"))]
+fn verify(
+	py: Python,
+	header: &[u8],
+	height: u64,
+	nonce: u64,
+	mix_hash: &[u8],
+	target: &[u8],
+	params: &str,
+) -> PyResult<bool> {
+	Verifier::new(params)?.verify(py, header, height, nonce, mix_hash, target)
+}
+
+#[pyfunction]
+#[pyo3(signature = (height, params = "kawpow"))]
+fn epoch(height: u64, params: &str) -> PyResult<u64> {
+	Ok(self::params(params)?.epoch(height))
+}
+
+#[pyfunction]
+fn seed_hash(py: Python, epoch: u64) -> &PyBytes {
+	PyBytes::new(py, &progpow::seed_hash(epoch))
+}
+
+/// A device driven through the C++ ethminer backends.
+#[cfg(feature = "gpu")]
+#[pyclass]
+struct Gpu {
+	gpu: progpow_gpu::SharedGpu,
+}
+
+#[cfg(feature = "gpu")]
+#[pymethods]
+impl Gpu {
+	/// `driver` is `"cuda"`, `"opencl"` or `"auto"`.
+	#[new]
+	fn new(py: Python, device: u32, driver: &str) -> PyResult<Self> {
+		let driver: progpow_gpu::Driver = driver
+			.parse()
+			.map_err(|_| PyValueError::new_err(format!("unknown driver: {}", driver)))?;
+		let gpu =
+			py.allow_threads(|| progpow_gpu::SharedGpu::new(progpow_gpu::GPU::new(device, driver)));
+		if !gpu.is_initialized() {
+			return Err(PyRuntimeError::new_err("GPU failed to initialize"));
+		}
+		Ok(Gpu { gpu })
+	}
+
+	/// Search one batch of job `job_id` from `start_nonce`. A new `job_id` drops the
+	/// solution pending for the previous job.
+	fn compute(
+		&self,
+		py: Python,
+		job_id: u64,
+		header: &[u8],
+		height: u64,
+		epoch: i32,
+		target: &[u8],
+		start_nonce: u64,
+	) -> PyResult<()> {
+		let job = progpow_gpu::MiningJob::new(
+			job_id,
+			h256(header, "header")?,
+			height,
+			epoch,
+			h256(target, "target")?,
+		);
+		job.validate(self.gpu.epoch_length())
+			.map_err(|e| PyValueError::new_err(e.to_string()))?;
+		py.allow_threads(|| {
+			self.gpu
+				.compute(&job, start_nonce)
+				.map_err(|e| e.to_string())
+		})
+		.map_err(PyRuntimeError::new_err)
+	}
+
+	/// `(nonce, mix_hash, job_id)` of every solution found for the current job since the
+	/// last call.
+	fn solutions<'py>(&self, py: Python<'py>) -> PyResult<Vec<(u64, &'py PyBytes, u64)>> {
+		let found = self
+			.gpu
+			.solutions()
+			.map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+		Ok(found
+			.into_iter()
+			.map(|s| (s.nonce, PyBytes::new(py, &s.mix_hash), s.job_id))
+			.collect())
+	}
+
+	fn pause(&self, py: Python) {
+		py.allow_threads(|| self.gpu.pause())
+	}
+
+	fn resume(&self) {
+		self.gpu.resume()
+	}
+
+	/// `(final_hash, mix_hash)` of `nonce` computed by the kernel alone, for comparing the
+	/// device with `verify`.
+	fn hash_one<'py>(
+		&self,
+		py: Python<'py>,
+		header: &[u8],
+		height: u64,
+		nonce: u64,
+	) -> PyResult<(&'py PyBytes, &'py PyBytes)> {
+		let header = h256(header, "header")?;
+		let (final_hash, mix_hash) = py
+			.allow_threads(|| {
+				self.gpu
+					.hash_one(&header, height, nonce)
+					.map_err(|e| e.to_string())
+			})
+			.map_err(PyRuntimeError::new_err)?;
+		Ok((PyBytes::new(py, &final_hash), PyBytes::new(py, &mix_hash)))
+	}
+
+	/// Build the DAG of `epoch` in the background, to switch to it without a pause. False
+	/// if nothing was started.
+	fn prepare_epoch(&self, py: Python, epoch: u64) -> PyResult<bool> {
+		py.allow_threads(|| self.gpu.prepare_epoch(epoch).map_err(|e| e.to_string()))
+			.map_err(PyRuntimeError::new_err)
+	}
+
+	/// Percentage of time spent searching, from 1 to 100.
+	fn set_intensity(&self, percent: u32) {
+		self.gpu.set_intensity(percent)
+	}
+
+	fn stop(&self) {
+		self.gpu.stop()
+	}
+
+	/// Hashes per second over the recent window.
+	fn hashrate(&self) -> f64 {
+		self.gpu.hashrate()
+	}
+}
+
+#[pymodule]
+#[pyo3(name = "progpow")]
+fn module(_py: Python, m: &PyModule) -> PyResult<()> {
+	m.add_class::<Verifier>()?;
+	m.add_function(wrap_pyfunction!(hash, m)?)?;
+	m.add_function(wrap_pyfunction!(verify, m)?)?;
+	m.add_function(wrap_pyfunction!(epoch, m)?)?;
+	m.add_function(wrap_pyfunction!(seed_hash, m)?)?;
+	#[cfg(feature = "gpu")]
+	m.add_class::<Gpu>()?;
+	Ok(())
+}
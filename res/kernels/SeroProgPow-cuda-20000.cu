@@ -0,0 +1,653 @@
+
+typedef unsigned int       uint32_t;
+typedef unsigned long long uint64_t;
+
+#ifndef SEARCH_RESULTS
+#define SEARCH_RESULTS 16
+#endif
+
+typedef struct {
+        uint64_t nonce;
+        uint32_t mix[8];
+        uint32_t debug[8];
+    } search_result;
+
+typedef struct {
+    uint32_t count;
+    uint32_t _padding; // Explicitly match Rust struct alignment
+    search_result result[SEARCH_RESULTS];
+} search_results;
+
+#if __CUDA_ARCH__ < 350
+    #define ROTL32(x,n) (((x) << (n % 32)) | ((x) >> (32 - (n % 32))))
+    #define ROTR32(x,n) (((x) >> (n % 32)) | ((x) << (32 - (n % 32))))
+#else
+    #define ROTL32(x,n) __funnelshift_l((x), (x), (n))
+    #define ROTR32(x,n) __funnelshift_r((x), (x), (n))
+#endif
+
+#define min(a,b)     ((a<b) ? a : b)
+#define mul_hi(a, b) __umulhi(a, b)
+#define clz(a)       __clz(a)
+#define popcount(a)  __popc(a)
+
+#define DEV_INLINE __device__ __forceinline__
+
+#if (__CUDACC_VER_MAJOR__ > 8)
+    #define SHFL(x, y, z) __shfl_sync(0xFFFFFFFF, (x), (y), (z))
+#else
+    #define SHFL(x, y, z) __shfl((x), (y), (z))
+#endif
+
+#define PROGPOW_LANES           16
+// PROGPOW_REGS is injected with the defines below
+#define PROGPOW_DAG_LOADS       4
+#define PROGPOW_CACHE_WORDS     4096
+#define PROGPOW_CNT_DAG         64
+#define KAWPOW_IS_RAVENCOIN       0
+#define KAWPOW_IS_MEOWCOIN        0
+#define KAWPOW_IS_EVRMORE         0
+#define PROGPOW_IS_ZANO           1
+#define PROGPOW_IS_FIRO           0
+#define PROGPOW_IS_SERO           1
+#define PROGPOW_HAS_FINAL_PADDING 0
+#define PROGPOW_CNT_CACHE         12
+#define PROGPOW_CNT_MATH          20
+#define PROGPOW_REGS              32
+#define PROGPOW_START_OFFSET      0
+#define PROGPOW_DEBUG_TRACE       0
+
+typedef struct __align__(16) {uint32_t s[PROGPOW_DAG_LOADS];} dag_t;
+
+DEV_INLINE void progPowLoop(const uint32_t loop, uint32_t mix[PROGPOW_REGS], const dag_t *g_dag, const uint32_t c_dag[PROGPOW_CACHE_WORDS], const bool hack_false)
+{
+    dag_t data_dag;
+    uint32_t offset, data;
+    const uint32_t lane_id = threadIdx.x & (PROGPOW_LANES-1);
+
+    // global load
+    offset = SHFL(mix[0], loop % PROGPOW_LANES, PROGPOW_LANES);
+
+    // OFFSET MOD LOGIC
+    offset -= (__umulhi(offset, 3414643388) >> 22) * 5275631;
+
+
+    offset = offset * PROGPOW_LANES + (lane_id ^ loop) % PROGPOW_LANES;
+    data_dag = g_dag[offset];
+
+    if (hack_false) __threadfence_block();
+
+    // Random math and cache operations
+        // cache load 0
+    offset = mix[0] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[0] = (mix[0] ^ data) * 33;
+    // random math 0
+    data = mix[3] ^ mix[30];
+    mix[1] = (mix[1] * 33) + data;
+    // cache load 1
+    offset = mix[1] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[2] = ROTR32(mix[2], 10) ^ data;
+    // random math 1
+    data = ROTL32(mix[20], mix[26] & 31);
+    mix[3] = ROTL32(mix[3], 27) ^ data;
+    // cache load 2
+    offset = mix[2] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[4] = ROTR32(mix[4], 9) ^ data;
+    // random math 2
+    data = mix[25] * mix[6];
+    mix[5] = (mix[5] ^ data) * 33;
+    // cache load 3
+    offset = mix[3] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[6] = ROTR32(mix[6], 14) ^ data;
+    // random math 3
+    data = min(mix[26], mix[0]);
+    mix[7] = (mix[7] ^ data) * 33;
+    // cache load 4
+    offset = mix[4] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[8] = ROTR32(mix[8], 22) ^ data;
+    // random math 4
+    data = mix[23] | mix[14];
+    mix[9] = (mix[9] * 33) + data;
+    // cache load 5
+    offset = mix[5] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[10] = ROTR32(mix[10], 26) ^ data;
+    // random math 5
+    data = popcount(mix[17]) + popcount(mix[24]);
+    mix[11] = (mix[11] ^ data) * 33;
+    // cache load 6
+    offset = mix[6] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[12] = ROTL32(mix[12], 12) ^ data;
+    // random math 6
+    data = ROTR32(mix[23], mix[19] & 31);
+    mix[13] = ROTR32(mix[13], 14) ^ data;
+    // cache load 7
+    offset = mix[7] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[14] = (mix[14] * 33) + data;
+    // random math 7
+    data = mix[31] | mix[29];
+    mix[15] = ROTR32(mix[15], 1) ^ data;
+    // cache load 8
+    offset = mix[8] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[16] = (mix[16] ^ data) * 33;
+    // random math 8
+    data = mix[24] & mix[2];
+    mix[17] = ROTL32(mix[17], 5) ^ data;
+    // cache load 9
+    offset = mix[9] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[18] = ROTL32(mix[18], 23) ^ data;
+    // random math 9
+    data = ROTR32(mix[28], mix[29] & 31);
+    mix[19] = ROTR32(mix[19], 24) ^ data;
+    // cache load 10
+    offset = mix[10] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[20] = (mix[20] ^ data) * 33;
+    // random math 10
+    data = clz(mix[17]) + clz(mix[13]);
+    mix[21] = (mix[21] * 33) + data;
+    // cache load 11
+    offset = mix[11] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[22] = (mix[22] ^ data) * 33;
+    // random math 11
+    data = mix[22] & mix[24];
+    mix[23] = ROTL32(mix[23], 24) ^ data;
+    // random math 12
+    data = mix[28] | mix[31];
+    mix[24] = ROTR32(mix[24], 22) ^ data;
+    // random math 13
+    data = mix[12] + mix[19];
+    mix[25] = ROTL32(mix[25], 1) ^ data;
+    // random math 14
+    data = mix[7] + mix[4];
+    mix[26] = (mix[26] * 33) + data;
+    // random math 15
+    data = clz(mix[10]) + clz(mix[26]);
+    mix[27] = ROTL32(mix[27], 24) ^ data;
+    // random math 16
+    data = mix[8] * mix[27];
+    mix[28] = (mix[28] ^ data) * 33;
+    // random math 17
+    data = ROTR32(mix[9], mix[26] & 31);
+    mix[29] = ROTR32(mix[29], 21) ^ data;
+    // random math 18
+    data = clz(mix[31]) + clz(mix[11]);
+    mix[30] = ROTR32(mix[30], 24) ^ data;
+    // random math 19
+    data = ROTL32(mix[0], mix[26] & 31);
+    mix[31] = (mix[31] ^ data) * 33;
+
+
+    // DAG data loads (merge data_dag into mix)
+        mix[0] = (mix[0] ^ data_dag.s[0]) * 33;
+    mix[0] = ROTL32(mix[0], 9) ^ data_dag.s[1];
+    mix[1] = (mix[1] ^ data_dag.s[2]) * 33;
+    mix[2] = (mix[2] ^ data_dag.s[3]) * 33;
+
+}
+
+#define FNV_PRIME 0x1000193
+#define FNV_OFFSET_BASIS 0x811c9dc5
+
+typedef struct
+{
+    uint32_t uint32s[32 / sizeof(uint32_t)];
+} hash32_t;
+
+__device__ __constant__ const uint32_t keccakf_rndc[24] = {
+    0x00000001, 0x00008082, 0x0000808a, 0x80008000, 0x0000808b, 0x80000001,
+    0x80008081, 0x00008009, 0x0000008a, 0x00000088, 0x80008009, 0x8000000a,
+    0x8000808b, 0x0000008b, 0x00008089, 0x00008003, 0x00008002, 0x00000080,
+    0x0000800a, 0x8000000a, 0x80008081, 0x00008080, 0x80000001, 0x80008008
+};
+
+
+
+__device__ __constant__ const uint32_t ravencoin_rndc[15] = {
+    0x00000072, 0x00000041, 0x00000056, 0x00000045, // rAVE
+    0x0000004E, 0x00000043, 0x0000004F, 0x00000049, // NCOI
+    0x0000004E, 0x0000004B, 0x00000041, 0x00000057, // NKAW
+    0x00000050, 0x0000004F, 0x00000057              // POW
+};
+
+__device__ __constant__ const uint32_t meowcoin_rndc[15] = {
+    0x0000004D, 0x00000045, 0x0000004F, 0x00000057, 0x00000043, 0x0000004F, 0x00000049, 0x0000004E,
+    0x0000004D, 0x00000045, 0x0000004F, 0x00000057, 0x00000050, 0x0000004F, 0x00000057
+};
+
+__device__ __constant__ const uint32_t evrmore_rndc[15] = {
+    0x00000065, 0x00000076, 0x00000072, 0x0000006D, 0x0000006F, 0x00000072, 0x00000065, 0x00000000,
+    0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000
+};
+
+
+
+
+    // Theta
+    __device__ __forceinline__ void keccak_f800_round(uint32_t* st, const int r)
+    {
+
+    // Theta
+    uint32_t bc0 = st[0] ^ st[5] ^ st[10] ^ st[15] ^ st[20];
+    uint32_t bc1 = st[1] ^ st[6] ^ st[11] ^ st[16] ^ st[21];
+    uint32_t bc2 = st[2] ^ st[7] ^ st[12] ^ st[17] ^ st[22];
+    uint32_t bc3 = st[3] ^ st[8] ^ st[13] ^ st[18] ^ st[23];
+    uint32_t bc4 = st[4] ^ st[9] ^ st[14] ^ st[19] ^ st[24];
+
+    uint32_t t0 = bc4 ^ ROTL32(bc1, 1);
+    uint32_t t1 = bc0 ^ ROTL32(bc2, 1);
+    uint32_t t2 = bc1 ^ ROTL32(bc3, 1);
+    uint32_t t3 = bc2 ^ ROTL32(bc4, 1);
+    uint32_t t4 = bc3 ^ ROTL32(bc0, 1);
+
+    st[0] ^= t0;
+    st[5] ^= t0;
+    st[10] ^= t0;
+    st[15] ^= t0;
+    st[20] ^= t0;
+
+    st[1] ^= t1;
+    st[6] ^= t1;
+    st[11] ^= t1;
+    st[16] ^= t1;
+    st[21] ^= t1;
+
+    st[2] ^= t2;
+    st[7] ^= t2;
+    st[12] ^= t2;
+    st[17] ^= t2;
+    st[22] ^= t2;
+
+    st[3] ^= t3;
+    st[8] ^= t3;
+    st[13] ^= t3;
+    st[18] ^= t3;
+    st[23] ^= t3;
+
+    st[4] ^= t4;
+    st[9] ^= t4;
+    st[14] ^= t4;
+    st[19] ^= t4;
+    st[24] ^= t4;
+
+    // Rho Pi
+    uint32_t t = st[1];
+    uint32_t tmp;
+
+    tmp = st[10]; st[10] = ROTL32(t, 1); t = tmp;
+    tmp = st[7];  st[7]  = ROTL32(t, 3); t = tmp;
+    tmp = st[11]; st[11] = ROTL32(t, 6); t = tmp;
+    tmp = st[17]; st[17] = ROTL32(t, 10); t = tmp;
+    tmp = st[18]; st[18] = ROTL32(t, 15); t = tmp;
+    tmp = st[3];  st[3]  = ROTL32(t, 21); t = tmp;
+    tmp = st[5];  st[5]  = ROTL32(t, 28); t = tmp;
+    tmp = st[16]; st[16] = ROTL32(t, 36); t = tmp;
+    tmp = st[8];  st[8]  = ROTL32(t, 45); t = tmp;
+    tmp = st[21]; st[21] = ROTL32(t, 55); t = tmp;
+    tmp = st[24]; st[24] = ROTL32(t, 2); t = tmp;
+    tmp = st[4];  st[4]  = ROTL32(t, 14); t = tmp;
+    tmp = st[15]; st[15] = ROTL32(t, 27); t = tmp;
+    tmp = st[23]; st[23] = ROTL32(t, 41); t = tmp;
+    tmp = st[19]; st[19] = ROTL32(t, 56); t = tmp;
+    tmp = st[13]; st[13] = ROTL32(t, 8); t = tmp;
+    tmp = st[12]; st[12] = ROTL32(t, 25); t = tmp;
+    tmp = st[2];  st[2]  = ROTL32(t, 43); t = tmp;
+    tmp = st[20]; st[20] = ROTL32(t, 62); t = tmp;
+    tmp = st[14]; st[14] = ROTL32(t, 18); t = tmp;
+    tmp = st[22]; st[22] = ROTL32(t, 39); t = tmp;
+    tmp = st[9];  st[9]  = ROTL32(t, 61); t = tmp;
+    tmp = st[6];  st[6]  = ROTL32(t, 20); t = tmp;
+    st[1] = ROTL32(t, 44);
+
+    // Chi
+    uint32_t c0, c1, c2, c3, c4;
+    c0 = st[0]; c1 = st[1]; c2 = st[2]; c3 = st[3]; c4 = st[4];
+    st[0] ^= (~c1) & c2;
+    st[1] ^= (~c2) & c3;
+    st[2] ^= (~c3) & c4;
+    st[3] ^= (~c4) & c0;
+    st[4] ^= (~c0) & c1;
+
+    c0 = st[5]; c1 = st[6]; c2 = st[7]; c3 = st[8]; c4 = st[9];
+    st[5] ^= (~c1) & c2;
+    st[6] ^= (~c2) & c3;
+    st[7] ^= (~c3) & c4;
+    st[8] ^= (~c4) & c0;
+    st[9] ^= (~c0) & c1;
+
+    c0 = st[10]; c1 = st[11]; c2 = st[12]; c3 = st[13]; c4 = st[14];
+    st[10] ^= (~c1) & c2;
+    st[11] ^= (~c2) & c3;
+    st[12] ^= (~c3) & c4;
+    st[13] ^= (~c4) & c0;
+    st[14] ^= (~c0) & c1;
+
+    c0 = st[15]; c1 = st[16]; c2 = st[17]; c3 = st[18]; c4 = st[19];
+    st[15] ^= (~c1) & c2;
+    st[16] ^= (~c2) & c3;
+    st[17] ^= (~c3) & c4;
+    st[18] ^= (~c4) & c0;
+    st[19] ^= (~c0) & c1;
+
+    c0 = st[20]; c1 = st[21]; c2 = st[22]; c3 = st[23]; c4 = st[24];
+    st[20] ^= (~c1) & c2;
+    st[21] ^= (~c2) & c3;
+    st[22] ^= (~c3) & c4;
+    st[23] ^= (~c4) & c0;
+    st[24] ^= (~c0) & c1;
+
+    // Iota
+    st[0] ^= keccakf_rndc[r];
+}
+
+__device__ __forceinline__ uint32_t cuda_swab32(const uint32_t x)
+{
+    // Explicit byte swap using shifts to ensure correctness on all archs
+    return ((x & 0x000000FF) << 24) |
+           ((x & 0x0000FF00) << 8)  |
+           ((x & 0x00FF0000) >> 8)  |
+           ((x & 0xFF000000) >> 24);
+}
+
+__device__ __forceinline__ void keccak_f800(uint32_t* st)
+{
+    #pragma unroll
+    for (int r = 0; r < 22; r++)
+        keccak_f800_round(st, r);
+}
+
+__device__ __forceinline__ uint32_t fnv1a_dev(uint32_t h, uint32_t d)
+{
+    return (h ^ d) * FNV_PRIME;
+}
+
+typedef struct {
+    uint32_t z, w, jsr, jcong;
+} kiss99_t;
+
+__device__ __forceinline__ uint32_t kiss99(kiss99_t &st)
+{
+    st.z = 36969 * (st.z & 65535) + (st.z >> 16);
+    st.w = 18000 * (st.w & 65535) + (st.w >> 16);
+    uint32_t MWC = ((st.z << 16) + st.w);
+
+
+    st.jcong = 69069 * st.jcong + 1234567;
+    st.jsr ^= (st.jsr << 17);
+    st.jsr ^= (st.jsr >> 13);
+    st.jsr ^= (st.jsr << 5);
+
+
+    uint32_t res = ((MWC^st.jcong) + st.jsr);
+    return res;
+}
+
+__device__ __forceinline__ void fill_mix(uint32_t* hash_seed, uint32_t lane_id, uint32_t* mix, uint32_t* g_debug_trace)
+{
+    uint32_t fnv_hash = FNV_OFFSET_BASIS;
+    kiss99_t st;
+    st.z = fnv1a_dev(fnv_hash, hash_seed[0]);
+    st.w = fnv1a_dev(st.z, hash_seed[1]);
+    st.jsr = fnv1a_dev(st.w, lane_id);
+    st.jcong = fnv1a_dev(st.jsr, lane_id);
+#if PROGPOW_DEBUG_TRACE
+    if (lane_id == 0 && (blockIdx.x * blockDim.x + threadIdx.x) == 0 && g_debug_trace != NULL) {
+        g_debug_trace[210] = st.z;
+        g_debug_trace[211] = st.w;
+        g_debug_trace[212] = st.jsr;
+        g_debug_trace[213] = st.jcong;
+    }
+#endif
+    // Iteration 0
+    mix[0] = kiss99(st);
+#if PROGPOW_DEBUG_TRACE
+    if (lane_id == 0 && (blockIdx.x * blockDim.x + threadIdx.x) == 0 && g_debug_trace != NULL) {
+        g_debug_trace[220] = st.z;
+        g_debug_trace[221] = st.w;
+        g_debug_trace[222] = st.jsr;
+        g_debug_trace[223] = st.jcong;
+        g_debug_trace[224] = mix[0];
+    }
+#endif
+    #pragma unroll
+    for (int i = 1; i < PROGPOW_REGS; i++)
+        mix[i] = kiss99(st);
+}
+
+__device__ __forceinline__ bool u64_le(uint64_t a, uint64_t b)
+{
+    uint32_t a_hi = (uint32_t)(a >> 32);
+    uint32_t b_hi = (uint32_t)(b >> 32);
+    if (a_hi < b_hi) return true;
+    if (a_hi > b_hi) return false;
+    return (uint32_t)a <= (uint32_t)b;
+}
+
+extern "C" __global__ void progpow_search_v3(
+    const uint64_t start_nonce,
+    const uint64_t target,
+    const uint64_t h0_64, const uint64_t h1_64, const uint64_t h2_64, const uint64_t h3_64,
+    const dag_t* g_dag,
+    const uint32_t* c_cache,
+    volatile search_results* g_output,
+    uint32_t* g_debug_trace
+    )
+{
+    // Unpack 4x u64 into 8x u32
+    const uint32_t header_hash[8] = {
+        (uint32_t)h0_64, (uint32_t)(h0_64 >> 32),
+        (uint32_t)h1_64, (uint32_t)(h1_64 >> 32),
+        (uint32_t)h2_64, (uint32_t)(h2_64 >> 32),
+        (uint32_t)h3_64, (uint32_t)(h3_64 >> 32)
+    };
+
+    const bool hack_false = false;
+    __shared__ uint32_t c_dag[PROGPOW_CACHE_WORDS];
+
+    const uint32_t gid = blockIdx.x * blockDim.x + threadIdx.x;
+    const uint32_t lane_id = gid & (PROGPOW_LANES - 1);
+    const uint32_t nonce_id = gid / PROGPOW_LANES;
+
+    // Load Cache
+    for (uint32_t word = threadIdx.x; word < PROGPOW_CACHE_WORDS; word += blockDim.x)
+    {
+        c_dag[word] = c_cache[word];
+    }
+    __syncthreads();
+
+    uint64_t nonce = start_nonce + nonce_id;
+
+    uint32_t mix[PROGPOW_REGS];
+    uint32_t hash_seed[2];
+    uint32_t state2[16];
+
+#if PROGPOW_DEBUG_TRACE
+    // Dump kernel arguments (gid 0)
+    if (gid == 0 && g_debug_trace != NULL) {
+        // 500: Header (h0_64) - First 8 bytes instead of pointer
+        g_debug_trace[500] = (uint32_t)h0_64;
+        g_debug_trace[501] = (uint32_t)(h0_64 >> 32);
+
+        // 502: DAG Ptr
+        uint64_t dag_val = (uint64_t)g_dag;
+        g_debug_trace[502] = (uint32_t)dag_val;
+        g_debug_trace[503] = (uint32_t)(dag_val >> 32);
+
+        // 504: Cache Ptr
+        uint64_t cache_val = (uint64_t)c_cache;
+        g_debug_trace[504] = (uint32_t)cache_val;
+        g_debug_trace[505] = (uint32_t)(cache_val >> 32);
+
+        // 506: Start Nonce
+        g_debug_trace[506] = (uint32_t)start_nonce;
+        g_debug_trace[507] = (uint32_t)(start_nonce >> 32);
+
+        // 508: Target
+        g_debug_trace[508] = (uint32_t)target;
+        g_debug_trace[509] = (uint32_t)(target >> 32);
+
+        // 514: Output Ptr
+        uint64_t out_val = (uint64_t)g_output;
+        g_debug_trace[514] = (uint32_t)out_val;
+        g_debug_trace[515] = (uint32_t)(out_val >> 32);
+
+        // 516: Debug Ptr
+        uint64_t dbg_val = (uint64_t)g_debug_trace;
+        g_debug_trace[516] = (uint32_t)dbg_val;
+        g_debug_trace[517] = (uint32_t)(dbg_val >> 32);
+    }
+#endif
+
+    {
+        // Initial state
+        uint32_t state[25];
+        for (int i = 0; i < 25; i++) state[i] = 0;
+
+        for (int i = 0; i < 8; i++)
+            state[i] = header_hash[i];
+
+        state[8] = (uint32_t)nonce;
+        state[9] = (uint32_t)(nonce >> 32);
+
+
+        for (int i = 10; i < 25; i++) state[i] = 0;
+
+        keccak_f800(state);
+
+        for (int i = 0; i < 16; i++)
+            state2[i] = state[i];
+
+        uint32_t hash_seed_small[2];
+            hash_seed_small[0] = cuda_swab32(state2[1]);
+    hash_seed_small[1] = cuda_swab32(state2[0]);
+        hash_seed[0] = hash_seed_small[0];
+        hash_seed[1] = hash_seed_small[1];
+    }
+#if PROGPOW_DEBUG_TRACE
+    if (gid == 0 && g_debug_trace != NULL) {
+        g_debug_trace[200] = hash_seed[0];
+        g_debug_trace[201] = hash_seed[1];
+    }
+#endif
+    fill_mix(hash_seed, lane_id, mix, g_debug_trace);
+
+#if PROGPOW_DEBUG_TRACE
+    if (gid == 0 && g_debug_trace != NULL) {
+        // Trace Mix Init (Offset 32)
+        for (int i = 0; i < 8; i++) g_debug_trace[32 + i] = mix[i]; // Store lane 0 mix
+    }
+#endif
+
+    #pragma unroll 1
+    for (uint32_t l = 0; l < PROGPOW_CNT_DAG; l++) {
+        progPowLoop(l, mix, g_dag, c_dag, hack_false);
+#if PROGPOW_DEBUG_TRACE
+        if (gid == 0 && l == 0 && g_debug_trace != NULL) {
+            // Trace Mix Loop 0 (Offset 48)
+            for (int i = 0; i < 8; i++) g_debug_trace[48 + i] = mix[i];
+        }
+#endif
+    }
+
+
+    // Reduction
+    uint32_t digest_lane = FNV_OFFSET_BASIS;
+    #pragma unroll
+    for (int i = 0; i < PROGPOW_REGS; i++)
+        digest_lane = fnv1a_dev(digest_lane, mix[i]);
+
+    hash32_t digest;
+    for (int i = 0; i < 8; i++)
+    {
+        uint32_t res = FNV_OFFSET_BASIS;
+        res = fnv1a_dev(res, SHFL(digest_lane, i, PROGPOW_LANES));
+        res = fnv1a_dev(res, SHFL(digest_lane, i + 8, PROGPOW_LANES));
+        digest.uint32s[i] = res;
+    }
+
+    uint64_t result;
+    {
+        uint32_t final_state[25];
+        for (int i = 0; i < 25; i++) final_state[i] = 0;
+
+#if KAWPOW_IS_RAVENCOIN
+        // Reference: cpp-kawpow/lib/ethash/progpow.cpp
+        // state[0..7]   = state2 (carry-over from initial keccak)
+        // state[8..15]  = mix_hash (digest)
+        // state[16..24] = ravencoin_kawpow[0..8] (9 personalization words)
+        for (int i = 0; i < 8; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[8 + i] = digest.uint32s[i];
+        for (int i = 0; i < 9; i++) final_state[16 + i] = ravencoin_rndc[i];
+#elif KAWPOW_IS_MEOWCOIN
+        // MeowPow: state[0..7] = state2 (initial Keccak output), state[8..15] = mix, state[16..24] = personalization
+        // NOTE: Meowcoin uses the absorbed Keccak state (state2), NOT raw header_hash!
+        // Same as Ravencoin but without nonce in final state.
+        for (int i = 0; i < 8; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[8 + i] = digest.uint32s[i];
+        for (int i = 0; i < 9; i++) final_state[16 + i] = meowcoin_rndc[i];
+#elif KAWPOW_IS_EVRMORE
+        for (int i = 0; i < 8; i++) final_state[i] = header_hash[i];
+        final_state[8] = (uint32_t)nonce;
+        final_state[9] = (uint32_t)(nonce >> 32);
+        for (int i = 0; i < 8; i++) final_state[10 + i] = digest.uint32s[i];
+        for (int i = 0; i < 7; i++) final_state[18 + i] = evrmore_rndc[i];
+#elif PROGPOW_IS_FIRO
+        // FiroPow: seed_hash (16 words) + mix (8 words) + padding (1 word)
+        for (int i = 0; i < 16; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[16 + i] = digest.uint32s[i];
+        final_state[24] = 0x80000001;
+#else
+        // Standard ProgPow / Zano / Epic / Sero: header + nonce/seed + mix
+        for (int i = 0; i < 8; i++) final_state[i] = header_hash[i];
+#if PROGPOW_IS_ZANO
+        final_state[8] = cuda_swab32(state2[1]);
+        final_state[9] = cuda_swab32(state2[0]);
+#else
+        final_state[8] = state2[0];
+        final_state[9] = state2[1];
+#endif
+        for (int i = 10; i < 18; i++) final_state[i] = digest.uint32s[i - 10];
+
+        #if PROGPOW_HAS_FINAL_PADDING
+        final_state[18] = 0x00000001;
+        final_state[24] = 0x80008081;
+        #endif
+#endif
+        keccak_f800(final_state);
+        // KawPoW: The 64-bit result for target comparison is the first 8 bytes of the hash
+        // as a big-endian integer to match CPU verifier.
+        result = ((uint64_t)cuda_swab32(final_state[0]) << 32) | (uint64_t)cuda_swab32(final_state[1]);
+
+#if PROGPOW_DEBUG_TRACE
+        if (gid == 0 && g_debug_trace != NULL) {
+            for (int i = 0; i < 25; i++) g_debug_trace[64 + i] = final_state[i];
+            g_debug_trace[90] = (uint32_t)(result >> 32);
+            g_debug_trace[91] = (uint32_t)result;
+            g_debug_trace[92] = (uint32_t)(target >> 32);
+            g_debug_trace[93] = (uint32_t)target;
+        }
+#endif
+    }
+
+     if (u64_le(result, target) && result > 0 && lane_id == 0)
+     {
+         uint32_t index = atomicAdd((uint32_t*)&g_output->count, 1);
+         if (index < SEARCH_RESULTS)
+         {
+             g_output->result[index].nonce = nonce;
+             for (int i = 0; i < 8; i++) g_output->result[index].mix[i] = digest.uint32s[i];
+
+            for (int i = 0; i < 8; i++) g_output->result[index].debug[i] = state2[i];
+        }
+    }
+}
@@ -0,0 +1,490 @@
+
+#ifndef SEARCH_RESULTS
+#define SEARCH_RESULTS 16
+#endif
+
+#ifndef GROUP_SIZE
+#define GROUP_SIZE 256
+#endif
+
+typedef struct {
+    ulong nonce;
+    uint mix[8];
+    uint debug[8];
+} search_result;
+
+typedef struct {
+    uint count;
+    uint _padding; // Explicitly match Rust struct alignment
+    search_result result[SEARCH_RESULTS];
+} search_results;
+
+#define ROTL32(x,n) rotate((uint)(x), (uint)((n) % 32))
+#define ROTR32(x,n) rotate((uint)(x), (uint)(32 - ((n) % 32)))
+#define __umulhi(a, b) mul_hi((uint)(a), (uint)(b))
+
+#define PROGPOW_LANES           16
+// PROGPOW_REGS is injected with the defines below
+#define PROGPOW_DAG_LOADS       4
+#define PROGPOW_CACHE_WORDS     4096
+#define PROGPOW_CNT_DAG         64
+#define KAWPOW_IS_RAVENCOIN       0
+#define KAWPOW_IS_MEOWCOIN        0
+#define KAWPOW_IS_EVRMORE         0
+#define PROGPOW_IS_ZANO           1
+#define PROGPOW_IS_FIRO           0
+#define PROGPOW_IS_SERO           1
+#define PROGPOW_HAS_FINAL_PADDING 0
+#define PROGPOW_CNT_CACHE         12
+#define PROGPOW_CNT_MATH          20
+#define PROGPOW_REGS              32
+#define PROGPOW_START_OFFSET      0
+#define PROGPOW_DEBUG_TRACE       0
+
+#define HASHES_PER_GROUP (GROUP_SIZE / PROGPOW_LANES)
+
+typedef struct {uint s[PROGPOW_DAG_LOADS];} dag_t;
+
+// Lane exchange through local memory, the OpenCL counterpart of CUDA's __shfl_sync.
+// Must be reached by every work-item of the group.
+inline uint shfl(__local uint* share, const uint x, const uint src_lane, const uint lane_id)
+{
+    barrier(CLK_LOCAL_MEM_FENCE);
+    if (lane_id == src_lane)
+        *share = x;
+    barrier(CLK_LOCAL_MEM_FENCE);
+    return *share;
+}
+
+#define SHFL(x, y, z) shfl(share, (x), (y), lane_id)
+
+void progPowLoop(const uint loop, uint mix[PROGPOW_REGS], __global const dag_t *g_dag, __local const uint *c_dag, __local uint *share, const uint lane_id)
+{
+    dag_t data_dag;
+    uint offset, data;
+
+    // global load
+    offset = SHFL(mix[0], loop % PROGPOW_LANES, PROGPOW_LANES);
+
+    // OFFSET MOD LOGIC
+    offset -= (__umulhi(offset, 3414643388) >> 22) * 5275631;
+
+
+    offset = offset * PROGPOW_LANES + (lane_id ^ loop) % PROGPOW_LANES;
+    data_dag = g_dag[offset];
+
+    // Random math and cache operations
+        // cache load 0
+    offset = mix[0] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[0] = (mix[0] ^ data) * 33;
+    // random math 0
+    data = mix[3] ^ mix[30];
+    mix[1] = (mix[1] * 33) + data;
+    // cache load 1
+    offset = mix[1] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[2] = ROTR32(mix[2], 10) ^ data;
+    // random math 1
+    data = ROTL32(mix[20], mix[26] & 31);
+    mix[3] = ROTL32(mix[3], 27) ^ data;
+    // cache load 2
+    offset = mix[2] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[4] = ROTR32(mix[4], 9) ^ data;
+    // random math 2
+    data = mix[25] * mix[6];
+    mix[5] = (mix[5] ^ data) * 33;
+    // cache load 3
+    offset = mix[3] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[6] = ROTR32(mix[6], 14) ^ data;
+    // random math 3
+    data = min(mix[26], mix[0]);
+    mix[7] = (mix[7] ^ data) * 33;
+    // cache load 4
+    offset = mix[4] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[8] = ROTR32(mix[8], 22) ^ data;
+    // random math 4
+    data = mix[23] | mix[14];
+    mix[9] = (mix[9] * 33) + data;
+    // cache load 5
+    offset = mix[5] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[10] = ROTR32(mix[10], 26) ^ data;
+    // random math 5
+    data = popcount(mix[17]) + popcount(mix[24]);
+    mix[11] = (mix[11] ^ data) * 33;
+    // cache load 6
+    offset = mix[6] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[12] = ROTL32(mix[12], 12) ^ data;
+    // random math 6
+    data = ROTR32(mix[23], mix[19] & 31);
+    mix[13] = ROTR32(mix[13], 14) ^ data;
+    // cache load 7
+    offset = mix[7] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[14] = (mix[14] * 33) + data;
+    // random math 7
+    data = mix[31] | mix[29];
+    mix[15] = ROTR32(mix[15], 1) ^ data;
+    // cache load 8
+    offset = mix[8] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[16] = (mix[16] ^ data) * 33;
+    // random math 8
+    data = mix[24] & mix[2];
+    mix[17] = ROTL32(mix[17], 5) ^ data;
+    // cache load 9
+    offset = mix[9] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[18] = ROTL32(mix[18], 23) ^ data;
+    // random math 9
+    data = ROTR32(mix[28], mix[29] & 31);
+    mix[19] = ROTR32(mix[19], 24) ^ data;
+    // cache load 10
+    offset = mix[10] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[20] = (mix[20] ^ data) * 33;
+    // random math 10
+    data = clz(mix[17]) + clz(mix[13]);
+    mix[21] = (mix[21] * 33) + data;
+    // cache load 11
+    offset = mix[11] % PROGPOW_CACHE_WORDS;
+    data = c_dag[offset];
+    mix[22] = (mix[22] ^ data) * 33;
+    // random math 11
+    data = mix[22] & mix[24];
+    mix[23] = ROTL32(mix[23], 24) ^ data;
+    // random math 12
+    data = mix[28] | mix[31];
+    mix[24] = ROTR32(mix[24], 22) ^ data;
+    // random math 13
+    data = mix[12] + mix[19];
+    mix[25] = ROTL32(mix[25], 1) ^ data;
+    // random math 14
+    data = mix[7] + mix[4];
+    mix[26] = (mix[26] * 33) + data;
+    // random math 15
+    data = clz(mix[10]) + clz(mix[26]);
+    mix[27] = ROTL32(mix[27], 24) ^ data;
+    // random math 16
+    data = mix[8] * mix[27];
+    mix[28] = (mix[28] ^ data) * 33;
+    // random math 17
+    data = ROTR32(mix[9], mix[26] & 31);
+    mix[29] = ROTR32(mix[29], 21) ^ data;
+    // random math 18
+    data = clz(mix[31]) + clz(mix[11]);
+    mix[30] = ROTR32(mix[30], 24) ^ data;
+    // random math 19
+    data = ROTL32(mix[0], mix[26] & 31);
+    mix[31] = (mix[31] ^ data) * 33;
+
+
+    // DAG data loads (merge data_dag into mix)
+        mix[0] = (mix[0] ^ data_dag.s[0]) * 33;
+    mix[0] = ROTL32(mix[0], 9) ^ data_dag.s[1];
+    mix[1] = (mix[1] ^ data_dag.s[2]) * 33;
+    mix[2] = (mix[2] ^ data_dag.s[3]) * 33;
+
+}
+
+#define FNV_PRIME 0x1000193
+#define FNV_OFFSET_BASIS 0x811c9dc5
+
+typedef struct {
+    uint uint32s[8];
+} hash32_t;
+
+__constant uint keccakf_rndc[24] = {
+    0x00000001, 0x00008082, 0x0000808a, 0x80008000, 0x0000808b, 0x80000001,
+    0x80008081, 0x00008009, 0x0000008a, 0x00000088, 0x80008009, 0x8000000a,
+    0x8000808b, 0x0000008b, 0x00008089, 0x00008003, 0x00008002, 0x00000080,
+    0x0000800a, 0x8000000a, 0x80008081, 0x00008080, 0x80000001, 0x80008008
+};
+
+__constant uint ravencoin_rndc[15] = {
+    0x00000072, 0x00000041, 0x00000056, 0x00000045, // rAVE
+    0x0000004E, 0x00000043, 0x0000004F, 0x00000049, // NCOI
+    0x0000004E, 0x0000004B, 0x00000041, 0x00000057, // NKAW
+    0x00000050, 0x0000004F, 0x00000057              // POW
+};
+
+__constant uint meowcoin_rndc[15] = {
+    0x0000004D, 0x00000045, 0x0000004F, 0x00000057, 0x00000043, 0x0000004F, 0x00000049, 0x0000004E,
+    0x0000004D, 0x00000045, 0x0000004F, 0x00000057, 0x00000050, 0x0000004F, 0x00000057
+};
+
+__constant uint evrmore_rndc[15] = {
+    0x00000065, 0x00000076, 0x00000072, 0x0000006D, 0x0000006F, 0x00000072, 0x00000065, 0x00000000,
+    0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000
+};
+
+__constant uint keccakf_rotc[24] = {
+    1,  3,  6,  10, 15, 21, 28, 36, 45, 55, 2,  14,
+    27, 41, 56, 8,  25, 43, 62, 18, 39, 61, 20, 44
+};
+
+__constant uint keccakf_piln[24] = {
+    10, 7,  11, 17, 18, 3, 5,  16, 8,  21, 24, 4,
+    15, 23, 19, 13, 12, 2, 20, 14, 22, 9,  6,  1
+};
+
+inline void keccak_f800_round(uint st[25], const int r)
+{
+    uint t, bc[5];
+    // Theta
+    for (int i = 0; i < 5; i++)
+        bc[i] = st[i] ^ st[i + 5] ^ st[i + 10] ^ st[i + 15] ^ st[i + 20];
+
+    for (int i = 0; i < 5; i++) {
+        t = bc[(i + 4) % 5] ^ ROTL32(bc[(i + 1) % 5], 1);
+        for (uint j = 0; j < 25; j += 5)
+            st[j + i] ^= t;
+    }
+
+    // Rho Pi
+    t = st[1];
+    for (int i = 0; i < 24; i++) {
+        uint j = keccakf_piln[i];
+        bc[0] = st[j];
+        st[j] = ROTL32(t, keccakf_rotc[i]);
+        t = bc[0];
+    }
+
+    // Chi
+    for (int j = 0; j < 25; j += 5) {
+        for (int i = 0; i < 5; i++)
+            bc[i] = st[j + i];
+        for (int i = 0; i < 5; i++)
+            st[j + i] ^= (~bc[(i + 1) % 5]) & bc[(i + 2) % 5];
+    }
+
+    // Iota
+    st[0] ^= keccakf_rndc[r];
+}
+
+inline uint cuda_swab32(const uint x)
+{
+    return ((x & 0x000000FF) << 24) |
+           ((x & 0x0000FF00) << 8)  |
+           ((x & 0x00FF0000) >> 8)  |
+           ((x & 0xFF000000) >> 24);
+}
+
+inline void keccak_f800(uint st[25])
+{
+    for (int r = 0; r < 22; r++)
+        keccak_f800_round(st, r);
+}
+
+inline uint fnv1a_dev(uint h, uint d)
+{
+    return (h ^ d) * FNV_PRIME;
+}
+
+typedef struct {
+    uint z, w, jsr, jcong;
+} kiss99_t;
+
+inline uint kiss99(kiss99_t *p)
+{
+    kiss99_t st = *p;
+    st.z = 36969 * (st.z & 65535) + (st.z >> 16);
+    st.w = 18000 * (st.w & 65535) + (st.w >> 16);
+    uint MWC = ((st.z << 16) + st.w);
+
+
+    st.jcong = 69069 * st.jcong + 1234567;
+    st.jsr ^= (st.jsr << 17);
+    st.jsr ^= (st.jsr >> 13);
+    st.jsr ^= (st.jsr << 5);
+
+
+    *p = st;
+    return ((MWC ^ st.jcong) + st.jsr);
+}
+
+inline void fill_mix(uint* hash_seed, uint lane_id, uint* mix)
+{
+    kiss99_t st;
+    st.z = fnv1a_dev(FNV_OFFSET_BASIS, hash_seed[0]);
+    st.w = fnv1a_dev(st.z, hash_seed[1]);
+    st.jsr = fnv1a_dev(st.w, lane_id);
+    st.jcong = fnv1a_dev(st.jsr, lane_id);
+
+    for (int i = 0; i < PROGPOW_REGS; i++)
+        mix[i] = kiss99(&st);
+}
+
+__attribute__((reqd_work_group_size(GROUP_SIZE, 1, 1)))
+__kernel void progpow_search(
+    const ulong start_nonce,
+    const ulong target,
+    const ulong h0_64, const ulong h1_64, const ulong h2_64, const ulong h3_64,
+    __global const dag_t* g_dag,
+    __global const uint* c_cache,
+    __global volatile search_results* g_output,
+    __global uint* g_debug_trace
+    )
+{
+    // Unpack 4x u64 into 8x u32
+    const uint header_hash[8] = {
+        (uint)h0_64, (uint)(h0_64 >> 32),
+        (uint)h1_64, (uint)(h1_64 >> 32),
+        (uint)h2_64, (uint)(h2_64 >> 32),
+        (uint)h3_64, (uint)(h3_64 >> 32)
+    };
+
+    __local uint c_dag[PROGPOW_CACHE_WORDS];
+    __local uint g_share[HASHES_PER_GROUP];
+
+    const uint gid = get_global_id(0);
+    const uint lid = get_local_id(0);
+    const uint lane_id = lid & (PROGPOW_LANES - 1);
+    __local uint* share = &g_share[lid / PROGPOW_LANES];
+    const ulong nonce = start_nonce + gid / PROGPOW_LANES;
+
+    // Load Cache
+    for (uint word = lid; word < PROGPOW_CACHE_WORDS; word += GROUP_SIZE)
+        c_dag[word] = c_cache[word];
+    barrier(CLK_LOCAL_MEM_FENCE);
+
+    uint mix[PROGPOW_REGS];
+    uint hash_seed[2];
+    uint state2[16];
+
+    {
+        // Initial state
+        uint state[25];
+        for (int i = 0; i < 25; i++) state[i] = 0;
+
+        for (int i = 0; i < 8; i++)
+            state[i] = header_hash[i];
+
+        state[8] = (uint)nonce;
+        state[9] = (uint)(nonce >> 32);
+
+        for (int i = 10; i < 25; i++) state[i] = 0;
+
+        keccak_f800(state);
+
+        for (int i = 0; i < 16; i++)
+            state2[i] = state[i];
+
+        uint hash_seed_small[2];
+            hash_seed_small[0] = cuda_swab32(state2[1]);
+    hash_seed_small[1] = cuda_swab32(state2[0]);
+        hash_seed[0] = hash_seed_small[0];
+        hash_seed[1] = hash_seed_small[1];
+    }
+#if PROGPOW_DEBUG_TRACE
+    if (gid == 0 && g_debug_trace != 0) {
+        g_debug_trace[200] = hash_seed[0];
+        g_debug_trace[201] = hash_seed[1];
+    }
+#endif
+    fill_mix(hash_seed, lane_id, mix);
+
+#if PROGPOW_DEBUG_TRACE
+    if (gid == 0 && g_debug_trace != 0) {
+        // Trace Mix Init (Offset 32)
+        for (int i = 0; i < 8; i++) g_debug_trace[32 + i] = mix[i];
+    }
+#endif
+
+    #pragma unroll 1
+    for (uint l = 0; l < PROGPOW_CNT_DAG; l++) {
+        progPowLoop(l, mix, g_dag, c_dag, share, lane_id);
+#if PROGPOW_DEBUG_TRACE
+        if (gid == 0 && l == 0 && g_debug_trace != 0) {
+            // Trace Mix Loop 0 (Offset 48)
+            for (int i = 0; i < 8; i++) g_debug_trace[48 + i] = mix[i];
+        }
+#endif
+    }
+
+    // Reduction
+    uint digest_lane = FNV_OFFSET_BASIS;
+    for (int i = 0; i < PROGPOW_REGS; i++)
+        digest_lane = fnv1a_dev(digest_lane, mix[i]);
+
+    hash32_t digest;
+    for (int i = 0; i < 8; i++)
+    {
+        uint res = FNV_OFFSET_BASIS;
+        res = fnv1a_dev(res, SHFL(digest_lane, i, PROGPOW_LANES));
+        res = fnv1a_dev(res, SHFL(digest_lane, i + 8, PROGPOW_LANES));
+        digest.uint32s[i] = res;
+    }
+
+    ulong result;
+    {
+        uint final_state[25];
+        for (int i = 0; i < 25; i++) final_state[i] = 0;
+
+#if KAWPOW_IS_RAVENCOIN
+        for (int i = 0; i < 8; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[8 + i] = digest.uint32s[i];
+        for (int i = 0; i < 9; i++) final_state[16 + i] = ravencoin_rndc[i];
+#elif KAWPOW_IS_MEOWCOIN
+        for (int i = 0; i < 8; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[8 + i] = digest.uint32s[i];
+        for (int i = 0; i < 9; i++) final_state[16 + i] = meowcoin_rndc[i];
+#elif KAWPOW_IS_EVRMORE
+        for (int i = 0; i < 8; i++) final_state[i] = header_hash[i];
+        final_state[8] = (uint)nonce;
+        final_state[9] = (uint)(nonce >> 32);
+        for (int i = 0; i < 8; i++) final_state[10 + i] = digest.uint32s[i];
+        for (int i = 0; i < 7; i++) final_state[18 + i] = evrmore_rndc[i];
+#elif PROGPOW_IS_FIRO
+        for (int i = 0; i < 16; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[16 + i] = digest.uint32s[i];
+        final_state[24] = 0x80000001;
+#else
+        // Standard ProgPow / Zano / Epic / Sero: header + nonce/seed + mix
+        for (int i = 0; i < 8; i++) final_state[i] = header_hash[i];
+#if PROGPOW_IS_ZANO
+        final_state[8] = cuda_swab32(state2[1]);
+        final_state[9] = cuda_swab32(state2[0]);
+#else
+        final_state[8] = state2[0];
+        final_state[9] = state2[1];
+#endif
+        for (int i = 10; i < 18; i++) final_state[i] = digest.uint32s[i - 10];
+
+#if PROGPOW_HAS_FINAL_PADDING
+        final_state[18] = 0x00000001;
+        final_state[24] = 0x80008081;
+#endif
+#endif
+        keccak_f800(final_state);
+        // The 64-bit result for target comparison is the first 8 bytes of the hash
+        // as a big-endian integer to match CPU verifier.
+        result = ((ulong)cuda_swab32(final_state[0]) << 32) | (ulong)cuda_swab32(final_state[1]);
+
+#if PROGPOW_DEBUG_TRACE
+        if (gid == 0 && g_debug_trace != 0) {
+            for (int i = 0; i < 25; i++) g_debug_trace[64 + i] = final_state[i];
+            g_debug_trace[90] = (uint)(result >> 32);
+            g_debug_trace[91] = (uint)result;
+            g_debug_trace[92] = (uint)(target >> 32);
+            g_debug_trace[93] = (uint)target;
+        }
+#endif
+    }
+
+    if (result <= target && result > 0 && lane_id == 0)
+    {
+        uint index = atomic_inc(&g_output->count);
+        if (index < SEARCH_RESULTS)
+        {
+            g_output->result[index].nonce = nonce;
+            for (int i = 0; i < 8; i++) g_output->result[index].mix[i] = digest.uint32s[i];
+            for (int i = 0; i < 8; i++) g_output->result[index].debug[i] = state2[i];
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Async wrappers for tokio applications.
+//!
+//! Verifying and mining block, and the first hash of an epoch builds or loads its light
+//! cache, so the work runs on tokio's blocking pool instead of the runtime's threads.
+
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::sync::mpsc;
+use tokio::task;
+
+use crate::types::{Miner, MiningJob, ProgPowError, Verifier, H256};
+
+fn join_error(e: task::JoinError) -> ProgPowError {
+	ProgPowError::Driver(format!("blocking task failed: {}", e))
+}
+
+/// `Verifier::verify` on the blocking pool.
+pub async fn verify_async<V>(
+	verifier: Arc<V>,
+	header_hash: H256,
+	height: u64,
+	nonce: u64,
+) -> Result<([u32; 8], [u32; 8]), ProgPowError>
+where
+	V: Verifier + Send + Sync + 'static,
+{
+	task::spawn_blocking(move || verifier.verify(&header_hash, height, nonce))
+		.await
+		.map_err(join_error)?
+}
+
+/// Search `nonce_range` of `job` on the blocking pool, returning the solutions found as
+/// `Miner::take_solutions` does.
+pub async fn mine_job_async<M>(
+	miner: Arc<M>,
+	job: MiningJob,
+	nonce_range: Range<u64>,
+) -> Result<Vec<(u64, H256)>, ProgPowError>
+where
+	M: Miner + Send + Sync + 'static,
+{
+	task::spawn_blocking(move || {
+		miner.compute(&job, nonce_range)?;
+		Ok(miner.take_solutions())
+	})
+	.await
+	.map_err(join_error)?
+}
+
+/// Solutions of a job, mined in the background until the stream is dropped.
+///
+/// A failed `compute` is yielded once and ends the stream.
+pub struct SolutionStream {
+	solutions: mpsc::Receiver<Result<(u64, H256), ProgPowError>>,
+	stop: Arc<AtomicBool>,
+}
+
+impl SolutionStream {
+	/// Mine `job` from `start_nonce` on, `batch` nonces per `compute` call.
+	pub fn spawn<M>(miner: Arc<M>, job: MiningJob, start_nonce: u64, batch: u64) -> Self
+	where
+		M: Miner + Send + Sync + 'static,
+	{
+		let (sender, solutions) = mpsc::channel(16);
+		let stop = Arc::new(AtomicBool::new(false));
+		let stopped = stop.clone();
+		task::spawn_blocking(move || {
+			let mut nonce = start_nonce;
+			while !stopped.load(Ordering::Relaxed) {
+				let end = nonce.saturating_add(batch.max(1));
+				if let Err(e) = miner.compute(&job, nonce..end) {
+					let _ = sender.blocking_send(Err(e));
+					return;
+				}
+				for solution in miner.take_solutions() {
+					// Fails once the stream is dropped.
+					if sender.blocking_send(Ok(solution)).is_err() {
+						return;
+					}
+				}
+				if end == u64::MAX {
+					return;
+				}
+				nonce = end;
+			}
+		});
+		SolutionStream { solutions, stop }
+	}
+}
+
+impl Stream for SolutionStream {
+	type Item = Result<(u64, H256), ProgPowError>;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+		self.solutions.poll_recv(cx)
+	}
+}
+
+impl Drop for SolutionStream {
+	fn drop(&mut self) {
+		self.stop.store(true, Ordering::Relaxed);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::hardware::PpCPU;
+	use progpow_base::params::KawPowParams;
+	use std::future::poll_fn;
+
+	#[test]
+	fn test_async_matches_blocking() {
+		let runtime = tokio::runtime::Builder::new_current_thread()
+			.build()
+			.unwrap();
+		let cpu = Arc::new(PpCPU::<KawPowParams>::new());
+		let header = [0u8; 32];
+		let expected = cpu.verify(&header, 20, 10123012301).unwrap();
+		runtime.block_on(async {
+			let result = verify_async(cpu.clone(), header, 20, 10123012301).await;
+			assert_eq!(result.unwrap(), expected);
+
+			// Every nonce meets the maximum target.
+			let job = MiningJob::new(1, header, 20, 0, [0xff; 32]);
+			let found = mine_job_async(cpu.clone(), job.clone(), 5..9)
+				.await
+				.unwrap();
+			let nonces: Vec<u64> = found.iter().map(|&(nonce, _)| nonce).collect();
+			assert_eq!(nonces, vec![5, 6, 7, 8]);
+
+			let mut stream = SolutionStream::spawn(cpu, job, 100, 4);
+			let first = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await;
+			assert_eq!(first.unwrap().unwrap().0, 100);
+		});
+	}
+}
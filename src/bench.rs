@@ -0,0 +1,156 @@
+//! Throughput benchmarks for comparing devices.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::hardware::{PpCPU, VerifyScratch};
+use crate::params::DynParams;
+use crate::types::{ProgPowError, Verifier};
+
+/// Height benchmarks run at. Epoch 0 keeps the light cache and DAG small.
+pub const BENCH_HEIGHT: u64 = 1;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BenchReport {
+	pub device: String,
+	pub hashes: u64,
+	/// Time spent hashing, excluding setup.
+	pub elapsed: Duration,
+	/// Light cache build time on CPU, DAG build and upload time on GPU.
+	pub dag_build: Duration,
+	/// `None` on CPU.
+	pub kernel_compile: Option<Duration>,
+}
+
+impl BenchReport {
+	/// Hashes per second.
+	pub fn hashrate(&self) -> f64 {
+		let secs = self.elapsed.as_secs_f64();
+		if secs == 0.0 {
+			return 0.0;
+		}
+		self.hashes as f64 / secs
+	}
+}
+
+impl fmt::Display for BenchReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{}: {:.3} MH/s ({} hashes in {:.1}s), DAG build {:.1}s",
+			self.device,
+			self.hashrate() / 1e6,
+			self.hashes,
+			self.elapsed.as_secs_f64(),
+			self.dag_build.as_secs_f64()
+		)?;
+		if let Some(compile) = self.kernel_compile {
+			write!(f, ", kernel compile {:.1}s", compile.as_secs_f64())?;
+		}
+		Ok(())
+	}
+}
+
+/// CPU search rate over all threads for `seconds`.
+pub fn bench_cpu(params: DynParams, seconds: u64) -> Result<BenchReport, ProgPowError> {
+	const BATCH: u64 = 1 << 10;
+
+	let cpu = PpCPU::new_dyn(params);
+	let mut scratch = VerifyScratch::new();
+	let header = [0u8; 32];
+
+	// The first hash builds (or loads) the light cache, which `scratch` then keeps.
+	let start = Instant::now();
+	cpu.verify_with_scratch(&mut scratch, &header, BENCH_HEIGHT, 0)?;
+	let dag_build = start.elapsed();
+
+	let duration = Duration::from_secs(seconds);
+	let start = Instant::now();
+	let mut hashes = 0u64;
+	while start.elapsed() < duration {
+		cpu.search_with_scratch(&mut scratch, &header, BENCH_HEIGHT, 0, hashes, BATCH)?;
+		hashes += BATCH;
+	}
+
+	Ok(BenchReport {
+		device: format!("cpu x{}", cpu.threads()),
+		hashes,
+		elapsed: start.elapsed(),
+		dag_build,
+		kernel_compile: None,
+	})
+}
+
+/// Share validation rate of one thread for `seconds`, verifying one nonce at a time
+/// as a pool does, with a `VerifyScratch`.
+pub fn bench_verify(params: DynParams, seconds: u64) -> Result<BenchReport, ProgPowError> {
+	let cpu = PpCPU::new_dyn(params);
+	let mut scratch = VerifyScratch::new();
+	let header = [0u8; 32];
+
+	let start = Instant::now();
+	cpu.verify_with_scratch(&mut scratch, &header, BENCH_HEIGHT, 0)?;
+	let dag_build = start.elapsed();
+
+	let duration = Duration::from_secs(seconds);
+	let start = Instant::now();
+	let mut hashes = 0u64;
+	while start.elapsed() < duration {
+		hashes += 1;
+		cpu.verify_with_scratch(&mut scratch, &header, BENCH_HEIGHT, hashes)?;
+	}
+
+	Ok(BenchReport {
+		device: "cpu verify".to_string(),
+		hashes,
+		elapsed: start.elapsed(),
+		dag_build,
+		kernel_compile: None,
+	})
+}
+
+/// Search rate of GPU `device` for `seconds`, through the `cuda-rust` miner if enabled
+/// and the `opencl-rust` one otherwise.
+#[cfg(any(feature = "cuda-rust", feature = "opencl-rust"))]
+pub fn bench_gpu<P: progpow_base::params::ProgPowParams>(
+	device: u32,
+	seconds: u64,
+) -> Result<BenchReport, ProgPowError> {
+	#[cfg(feature = "cuda-rust")]
+	let (name, mut miner) = (
+		format!("cuda:{}", device),
+		crate::hardware::CudaMiner::new(device)?,
+	);
+	#[cfg(not(feature = "cuda-rust"))]
+	let (name, mut miner) = (
+		format!("opencl:{}", device),
+		crate::hardware::OpenClMiner::new(None, device as usize)?,
+	);
+
+	let start = Instant::now();
+	miner.prepare_kernel::<P>(BENCH_HEIGHT)?;
+	let kernel_compile = start.elapsed();
+
+	let start = Instant::now();
+	miner.prepare_dag::<P>(BENCH_HEIGHT)?;
+	let dag_build = start.elapsed();
+
+	let header = [0u8; 32];
+	let duration = Duration::from_secs(seconds);
+	let start = Instant::now();
+	let mut hashes = 0u64;
+	while start.elapsed() < duration {
+		// A zero target never matches, so every launch does the full batch.
+		miner.search::<P>(&header, BENCH_HEIGHT, 0, hashes)?;
+		hashes += miner.batch_size();
+	}
+
+	Ok(BenchReport {
+		device: name,
+		hashes,
+		elapsed: start.elapsed(),
+		dag_build,
+		kernel_compile: Some(kernel_compile),
+	})
+}
@@ -0,0 +1,301 @@
+//! Reference miner and verifier built on the library.
+//!
+//! cargo run --release --features cli --bin progpow-cli -- --help
+
+use std::path::PathBuf;
+use std::process;
+use std::time::Instant;
+
+use clap::{Parser, Subcommand};
+
+use progpow::bench;
+use progpow::config::{self, MinerConfig};
+use progpow::generator::program;
+use progpow::hardware::{DeviceThread, PpCPU};
+use progpow::params::DynParams;
+use progpow::stratum::{Client, Job};
+use progpow::supervisor::{RestartPolicy, Supervisor};
+use progpow::types::{h256_from_hex, to_hex, MiningJob, ProgPowError, Verifier};
+
+#[derive(Parser)]
+#[command(name = "progpow-cli", version, about = "ProgPoW miner and verifier")]
+struct Cli {
+	#[command(subcommand)]
+	command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+	/// Mine against the pool in a config file.
+	Mine {
+		#[arg(short, long)]
+		config: PathBuf,
+	},
+	/// Hash one nonce on the CPU, and check it against a mix and target if given.
+	Verify {
+		#[arg(long, default_value = "kawpow")]
+		params: String,
+		#[arg(long)]
+		header: String,
+		#[arg(long)]
+		height: u64,
+		#[arg(long)]
+		nonce: u64,
+		#[arg(long)]
+		mix: Option<String>,
+		/// Big-endian share target, any hash passes if unset.
+		#[arg(long)]
+		target: Option<String>,
+	},
+	/// Measure the hashrate of the CPU or a GPU.
+	Bench {
+		#[arg(long, default_value = "kawpow")]
+		params: String,
+		#[arg(long, default_value_t = 10)]
+		seconds: u64,
+		/// GPU index, the CPU if unset.
+		#[arg(long)]
+		gpu: Option<u32>,
+	},
+	/// Manage DAG files.
+	Dag {
+		#[command(subcommand)]
+		command: DagCommand,
+	},
+	/// List the devices in a config file and check that each one initializes.
+	Devices {
+		#[arg(short, long)]
+		config: Option<PathBuf>,
+	},
+	/// Print the random program of a period as the CPU or a GPU kernel executes it.
+	Program {
+		#[arg(long, default_value = "kawpow")]
+		params: String,
+		#[arg(long)]
+		period: u64,
+		/// `cpu`, `cuda` or `opencl`.
+		#[arg(long, default_value = "cpu")]
+		backend: String,
+	},
+}
+
+#[derive(Subcommand)]
+enum DagCommand {
+	/// Build the light cache for the epoch of `height` ahead of time.
+	Build {
+		#[arg(long, default_value = "kawpow")]
+		params: String,
+		#[arg(long)]
+		height: u64,
+		#[arg(long)]
+		cache_dir: Option<PathBuf>,
+	},
+}
+
+fn params(name: &str) -> Result<DynParams, ProgPowError> {
+	config::preset(name).ok_or_else(|| ProgPowError::Config(format!("unknown params {:?}", name)))
+}
+
+fn h256_arg(name: &str, hex: &str) -> Result<[u8; 32], ProgPowError> {
+	h256_from_hex(hex)
+		.ok_or_else(|| ProgPowError::Config(format!("--{} is not 32 bytes of hex", name)))
+}
+
+fn mine(path: PathBuf) -> Result<(), ProgPowError> {
+	progpow::self_check()?;
+	let config = MinerConfig::load(path)?;
+	let pool = config
+		.pool
+		.clone()
+		.ok_or_else(|| ProgPowError::Config("mine needs a [pool] section".to_string()))?;
+	let mut supervisor = Supervisor::new(config.hardware_specs()?, RestartPolicy::default())
+		.on_status(|device, status| println!("device {}: {:?}", device, status));
+
+	let mut client = Client::connect(pool.url.as_str(), &pool.worker, &pool.password)?;
+	println!("connected to {} as {}", pool.url, pool.worker);
+
+	let extranonce = client.fixed_nonce();
+	let mut job_id = 0u64;
+	let mut last_header = None;
+	client.run(|job: &Job, nonce| {
+		if last_header != Some(job.header_hash) {
+			job_id += 1;
+			last_header = Some(job.header_hash);
+		}
+		let work = MiningJob::new(
+			job_id,
+			job.header_hash,
+			job.height,
+			config.params.epoch(job.height) as i32,
+			job.target,
+		)
+		.with_extranonce(extranonce);
+
+		let batch = supervisor.search(&work, nonce)?;
+		for (nonce, _) in &batch.found {
+			println!(
+				"job {} height {}: found nonce {}",
+				job.id, job.height, nonce
+			);
+		}
+		Ok(batch)
+	})
+}
+
+fn verify(
+	params_name: &str,
+	header: &str,
+	height: u64,
+	nonce: u64,
+	mix: Option<String>,
+	target: Option<String>,
+) -> Result<(), ProgPowError> {
+	let cpu = PpCPU::new_dyn(params(params_name)?);
+	let header = h256_arg("header", header)?;
+	let (final_hash, mix_hash) = cpu.verify_hash(&header, height, nonce)?;
+	println!("final {}", to_hex(&final_hash));
+	println!("mix   {}", to_hex(&mix_hash));
+
+	if let Some(mix) = mix {
+		let mix = h256_arg("mix", &mix)?;
+		let target = match target {
+			Some(target) => h256_arg("target", &target)?,
+			None => [0xff; 32],
+		};
+		let status = cpu.validate_share(&header, height, nonce, &mix, &target)?;
+		println!("share {:?}", status);
+	}
+	Ok(())
+}
+
+fn run_bench(params_name: &str, seconds: u64, gpu: Option<u32>) -> Result<(), ProgPowError> {
+	let report = match gpu {
+		None => bench::bench_cpu(params(params_name)?, seconds)?,
+		#[cfg(any(feature = "cuda-rust", feature = "opencl-rust"))]
+		Some(device) => {
+			use progpow::params::SeroParams;
+			use progpow_base::params::{KawPowParams, ZanoParams};
+
+			let params = params(params_name)?;
+			if params.is::<KawPowParams>() {
+				bench::bench_gpu::<KawPowParams>(device, seconds)?
+			} else if params.is::<ZanoParams>() {
+				bench::bench_gpu::<ZanoParams>(device, seconds)?
+			} else if params.is::<SeroParams>() {
+				bench::bench_gpu::<SeroParams>(device, seconds)?
+			} else {
+				return Err(ProgPowError::UnsupportedParams);
+			}
+		}
+		#[cfg(not(any(feature = "cuda-rust", feature = "opencl-rust")))]
+		Some(_) => {
+			return Err(ProgPowError::Config(
+				"GPU benchmarks need the cuda-rust or opencl-rust feature".to_string(),
+			))
+		}
+	};
+	println!("{}", report);
+	Ok(())
+}
+
+fn dag_build(
+	params_name: &str,
+	height: u64,
+	cache_dir: Option<PathBuf>,
+) -> Result<(), ProgPowError> {
+	let params = params(params_name)?;
+	let epoch = params.epoch(height);
+	let mut cpu = PpCPU::new_dyn(params);
+	if let Some(dir) = cache_dir {
+		cpu.set_cache_dir(dir);
+	}
+
+	// The first hash of an epoch builds the cache file, later runs only load it.
+	let start = Instant::now();
+	cpu.verify(&[0u8; 32], height, 0)?;
+	println!(
+		"epoch {}: light cache ready in {:.1}s",
+		epoch,
+		start.elapsed().as_secs_f64()
+	);
+	Ok(())
+}
+
+fn devices(path: Option<PathBuf>) -> Result<(), ProgPowError> {
+	let mut features = vec!["cpu"];
+	if cfg!(feature = "cuda") {
+		features.push("cuda");
+	}
+	if cfg!(feature = "opencl") {
+		features.push("opencl");
+	}
+	println!("drivers: {}", features.join(", "));
+
+	let path = match path {
+		Some(path) => path,
+		None => return Ok(()),
+	};
+	let config = MinerConfig::load(path)?;
+	for (device, spec) in config.devices.iter().zip(config.hardware_specs()?) {
+		let name = format!("{:?}:{}", device.driver, device.index);
+		match DeviceThread::spawn(spec) {
+			Ok(_) => println!("{}: ok", name),
+			Err(e) => println!("{}: {:?}", name, e),
+		}
+	}
+	Ok(())
+}
+
+fn print_program(params_name: &str, period: u64, backend: &str) -> Result<(), ProgPowError> {
+	let backend = match backend {
+		"cpu" => program::Backend::Cpu,
+		"cuda" => program::Backend::Cuda,
+		"opencl" => program::Backend::OpenCl,
+		_ => {
+			return Err(ProgPowError::Config(format!(
+				"unknown backend {:?}",
+				backend
+			)))
+		}
+	};
+	print!("{}", program::dump(period, &params(params_name)?, backend)?);
+	Ok(())
+}
+
+fn main() {
+	let cli = Cli::parse();
+	let result = match cli.command {
+		Command::Mine { config } => mine(config),
+		Command::Verify {
+			params,
+			header,
+			height,
+			nonce,
+			mix,
+			target,
+		} => verify(&params, &header, height, nonce, mix, target),
+		Command::Bench {
+			params,
+			seconds,
+			gpu,
+		} => run_bench(&params, seconds, gpu),
+		Command::Dag {
+			command: DagCommand::Build {
+				params,
+				height,
+				cache_dir,
+			},
+		} => dag_build(&params, height, cache_dir),
+		Command::Devices { config } => devices(config),
+		Command::Program {
+			params,
+			period,
+			backend,
+		} => print_program(&params, period, &backend),
+	};
+
+	if let Err(e) = result {
+		eprintln!("error: {}", e);
+		process::exit(1);
+	}
+}
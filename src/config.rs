@@ -0,0 +1,264 @@
+//! Miner settings read from a TOML file.
+//!
+//! ```toml
+//! params = "kawpow"
+//! cache_dir = "/var/cache/progpow"
+//!
+//! [pool]
+//! url = "stratum.example.org:3333"
+//! worker = "wallet.rig1"
+//!
+//! [[devices]]
+//! driver = "cuda"
+//! index = 0
+//! intensity = 80
+//!
+//! [[devices]]
+//! driver = "opencl"
+//! id = "0000:41:00"
+//!
+//! [[devices]]
+//! driver = "cpu"
+//! threads = 4
+//! ```
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::hardware::HardwareSpec;
+use crate::params::DynParams;
+use crate::presets;
+use crate::types::ProgPowError;
+
+/// Backend of one configured device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DriverKind {
+	Cpu,
+	Cuda,
+	OpenCl,
+	/// Whichever compiled-in GPU driver works on the device, see `progpow_gpu::Driver`.
+	Auto,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceConfig {
+	pub driver: DriverKind,
+	/// Device index within its driver. Ignored for `cpu`.
+	#[serde(default)]
+	pub index: u32,
+	/// UUID (as `nvidia-smi -L` prints it) or PCI bus id of a GPU, looked up when the
+	/// miner starts. Takes precedence over `index`, which changes when cards are moved.
+	pub id: Option<String>,
+	/// Percentage of time spent searching, from 1 to 100.
+	#[serde(default = "default_intensity")]
+	pub intensity: u32,
+	/// CPU threads, one per core if unset. Ignored for GPUs.
+	pub threads: Option<usize>,
+}
+
+fn default_intensity() -> u32 {
+	100
+}
+
+/// Stratum endpoint, see `stratum::Client::connect`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PoolConfig {
+	/// `host:port`.
+	pub url: String,
+	pub worker: String,
+	#[serde(default)]
+	pub password: String,
+}
+
+/// The file as written, before validation.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+	params: String,
+	cache_dir: Option<PathBuf>,
+	pool: Option<PoolConfig>,
+	devices: Vec<DeviceConfig>,
+}
+
+/// Validated miner settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinerConfig {
+	pub params: DynParams,
+	pub cache_dir: Option<PathBuf>,
+	pub pool: Option<PoolConfig>,
+	pub devices: Vec<DeviceConfig>,
+}
+
+fn invalid<S: Into<String>>(msg: S) -> ProgPowError {
+	ProgPowError::Config(msg.into())
+}
+
+/// The GPU `device` configures, resolving its `id` to an index of `driver`, or of the
+/// driver that has it for `Auto`.
+#[cfg(any(feature = "cuda", feature = "opencl"))]
+fn gpu_spec(
+	device: &DeviceConfig,
+	driver: progpow_gpu::Driver,
+) -> Result<HardwareSpec, ProgPowError> {
+	let (index, driver) = match device.id {
+		Some(ref id) => {
+			let selector: progpow_gpu::DeviceSelector = id
+				.parse()
+				.map_err(|e| invalid(format!("device {:?}: {}", id, e)))?;
+			let info = selector
+				.find(driver)
+				.ok_or_else(|| invalid(format!("no {} device matches {:?}", driver, id)))?;
+			(info.index, info.driver)
+		}
+		None => (device.index, driver),
+	};
+	Ok(HardwareSpec::Gpu {
+		device: index,
+		driver,
+		intensity: device.intensity,
+	})
+}
+
+/// Compiled-in preset called `name`, as written in the `params` key. See
+/// `presets::by_name`.
+pub fn preset(name: &str) -> Option<DynParams> {
+	presets::by_name(name)
+}
+
+impl MinerConfig {
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ProgPowError> {
+		let path = path.as_ref();
+		let text =
+			fs::read_to_string(path).map_err(|e| invalid(format!("{}: {}", path.display(), e)))?;
+		MinerConfig::from_toml(&text)
+	}
+
+	pub fn from_toml(text: &str) -> Result<Self, ProgPowError> {
+		let raw: RawConfig = toml::from_str(text).map_err(|e| invalid(e.to_string()))?;
+
+		let params = preset(&raw.params)
+			.ok_or_else(|| invalid(format!("unknown params {:?}", raw.params)))?;
+		if raw.devices.is_empty() {
+			return Err(invalid("no devices configured"));
+		}
+
+		let mut seen = HashSet::new();
+		for device in &raw.devices {
+			if device.intensity == 0 || device.intensity > 100 {
+				return Err(invalid(format!(
+					"intensity {} is outside 1..=100",
+					device.intensity
+				)));
+			}
+			if device.threads == Some(0) {
+				return Err(invalid("threads must be at least 1"));
+			}
+			let id = match (device.driver, &device.id) {
+				(DriverKind::Cpu, Some(_)) => return Err(invalid("id is only valid for GPUs")),
+				(DriverKind::Cpu, None) => String::new(),
+				(_, Some(id)) => id.clone(),
+				(_, None) => device.index.to_string(),
+			};
+			if !seen.insert((device.driver, id.clone())) {
+				return Err(invalid(format!(
+					"{:?} device {} is listed twice",
+					device.driver, id
+				)));
+			}
+		}
+
+		Ok(MinerConfig {
+			params,
+			cache_dir: raw.cache_dir,
+			pool: raw.pool,
+			devices: raw.devices,
+		})
+	}
+
+	/// One `HardwareSpec` per device, for `hardware::create_miner`. Fails on a GPU device
+	/// if its driver is not compiled in.
+	pub fn hardware_specs(&self) -> Result<Vec<HardwareSpec>, ProgPowError> {
+		self.devices
+			.iter()
+			.map(|device| match device.driver {
+				DriverKind::Cpu => Ok(HardwareSpec::Cpu {
+					params: self.params.clone(),
+					threads: device.threads,
+					cache_dir: self.cache_dir.clone(),
+				}),
+				#[cfg(feature = "cuda")]
+				DriverKind::Cuda => gpu_spec(device, progpow_gpu::Driver::CUDA),
+				#[cfg(feature = "opencl")]
+				DriverKind::OpenCl => gpu_spec(device, progpow_gpu::Driver::OCL),
+				#[cfg(any(feature = "cuda", feature = "opencl"))]
+				DriverKind::Auto => gpu_spec(device, progpow_gpu::Driver::Auto),
+				#[allow(unreachable_patterns)]
+				driver => Err(invalid(format!("{:?} support is not compiled in", driver))),
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use progpow_base::params::ZanoParams;
+
+	#[test]
+	fn test_parse_config() {
+		let config = MinerConfig::from_toml(
+			r#"
+			params = "zano"
+			cache_dir = "/tmp/progpow"
+
+			[pool]
+			url = "pool.example.org:3333"
+			worker = "wallet.rig"
+
+			[[devices]]
+			driver = "cpu"
+			threads = 2
+			"#,
+		)
+		.unwrap();
+		assert!(config.params.is::<ZanoParams>());
+		assert_eq!(config.pool.as_ref().unwrap().password, "");
+		assert_eq!(config.devices[0].intensity, 100);
+
+		let specs = config.hardware_specs().unwrap();
+		assert!(matches!(
+			specs[0],
+			HardwareSpec::Cpu {
+				threads: Some(2),
+				..
+			}
+		));
+	}
+
+	#[test]
+	fn test_reject_invalid_config() {
+		let device = "[[devices]]\ndriver = \"cpu\"\n";
+		let gpu = "[[devices]]\ndriver = \"cuda\"\nid = \"41:00\"\n";
+		for text in [
+			format!("params = \"nope\"\n{}", device),
+			"params = \"kawpow\"\ndevices = []\n".to_string(),
+			format!("params = \"kawpow\"\n{}intensity = 0\n", device),
+			format!("params = \"kawpow\"\n{}{}", device, device),
+			format!("params = \"kawpow\"\ncolour = \"red\"\n{}", device),
+			format!("params = \"kawpow\"\n{}id = \"41:00\"\n", device),
+			format!("params = \"kawpow\"\n{}{}", gpu, gpu),
+		] {
+			assert!(
+				matches!(MinerConfig::from_toml(&text), Err(ProgPowError::Config(_))),
+				"{}",
+				text
+			);
+		}
+	}
+}
@@ -0,0 +1,80 @@
+//! Typed view of the `g_debug_trace` buffer written by the generated kernels.
+//!
+//! The kernels only write the trace when generated with the `debug-trace` feature; otherwise
+//! the buffer argument is ignored and the trace code is compiled out.
+
+/// Number of `u32` words the host must allocate for `g_debug_trace`.
+pub const TRACE_WORDS: usize = 518;
+
+/// Intermediate values of the first hash (gid 0) of a kernel launch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KernelDebug {
+	/// Seed extracted from the initial keccak, as fed to `fill_mix`.
+	pub hash_seed: [u32; 2],
+	/// KISS99 state (z, w, jsr, jcong) of lane 0 before its first draw.
+	pub kiss99_seed: [u32; 4],
+	/// First eight registers of lane 0 after `fill_mix`.
+	pub mix_init: [u32; 8],
+	/// First eight registers of lane 0 after the first `progPowLoop` iteration.
+	pub mix_loop0: [u32; 8],
+	/// Keccak state after the final permutation.
+	pub final_state: [u32; 25],
+	/// Big-endian 64-bit result compared against `target`.
+	pub result: u64,
+	pub target: u64,
+	/// Launch start nonce. Only written by the CUDA kernel.
+	pub start_nonce: u64,
+}
+
+fn words<const N: usize>(trace: &[u32], offset: usize) -> [u32; N] {
+	let mut out = [0u32; N];
+	out.copy_from_slice(&trace[offset..offset + N]);
+	out
+}
+
+impl KernelDebug {
+	/// Zeroed buffer of the size the kernels expect.
+	pub fn buffer() -> Vec<u32> {
+		vec![0; TRACE_WORDS]
+	}
+
+	/// Decode a trace buffer read back from the device. Returns `None` if it is too short.
+	pub fn from_trace(trace: &[u32]) -> Option<Self> {
+		if trace.len() < TRACE_WORDS {
+			return None;
+		}
+
+		Some(KernelDebug {
+			hash_seed: words(trace, 200),
+			kiss99_seed: words(trace, 210),
+			mix_init: words(trace, 32),
+			mix_loop0: words(trace, 48),
+			final_state: words(trace, 64),
+			result: (trace[90] as u64) << 32 | trace[91] as u64,
+			target: (trace[92] as u64) << 32 | trace[93] as u64,
+			start_nonce: (trace[507] as u64) << 32 | trace[506] as u64,
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_from_trace() {
+		assert!(KernelDebug::from_trace(&[0; 10]).is_none());
+
+		let mut trace = KernelDebug::buffer();
+		trace[200] = 7;
+		trace[201] = 8;
+		trace[90] = 1;
+		trace[91] = 2;
+		trace[506] = 0xdead;
+		trace[507] = 1;
+		let debug = KernelDebug::from_trace(&trace).unwrap();
+		assert_eq!(debug.hash_seed, [7, 8]);
+		assert_eq!(debug.result, 1 << 32 | 2);
+		assert_eq!(debug.start_nonce, 1 << 32 | 0xdead);
+	}
+}
@@ -0,0 +1,198 @@
+//! Epic Cash integration.
+//!
+//! Epic Cash blocks are mined with ProgPoW, RandomX or Cuckoo, and the node tells miners
+//! which one the next block is for. `EpicMiner` wraps a ProgPoW miner so an embedding
+//! miner can leave the other algorithms' slots to their own workers.
+
+use std::ops::Range;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use progpow_base::params::{MathMapping, ProgPowParams};
+use tracing::debug;
+
+use crate::epoch;
+use crate::params::DynParams;
+use crate::types::{Hardware, Miner, MiningJob, ProgPowError, H256};
+
+/// ProgPoW 0.9.2 as Epic Cash runs it, on ethash's 30000-block epochs. Light caches go
+/// to `~/.epic/main/cache` unless `PpCPU::set_cache_dir` says otherwise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EpicParams;
+
+impl ProgPowParams for EpicParams {
+	const NAME: &'static str = "EpicProgPow";
+	const EPOCH_LENGTH: u64 = 30000;
+	const PERIOD_LENGTH: u32 = 50;
+	const REGS: u32 = 32;
+	const DAG_LOADS: u32 = 4;
+	const CNT_CACHE: u32 = 12;
+	const CNT_MATH: u32 = 20;
+	const KECCAK_ROUNDS: u32 = 22;
+	const KECCAK_DOMAIN: u32 = 0;
+	const MATH_MAPPING: MathMapping = MathMapping::Standard;
+	const HAS_KISS99_SHUFFLE: bool = false;
+	const HAS_INITIAL_PADDING: bool = false;
+	const SEED_BYTE_SWAP: bool = true;
+	const HAS_RAVENCOIN_RNDC: bool = false;
+	const HAS_MEOWCOIN_RNDC: bool = false;
+	const HAS_EVRMORE_RNDC: bool = false;
+}
+
+pub fn params() -> DynParams {
+	DynParams::of::<EpicParams>()
+}
+
+pub fn epoch_for_height(height: u64) -> u64 {
+	epoch::epoch_for_height::<EpicParams>(height)
+}
+
+/// Seed hash of the epoch of `height`, as Epic's ProgPoW headers carry it.
+pub fn seed_hash(height: u64) -> H256 {
+	epoch::seed_hash(epoch_for_height(height))
+}
+
+/// Proof of work of an Epic Cash block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+	ProgPow,
+	RandomX,
+	Cuckoo,
+}
+
+/// Which algorithm the block at a height is mined with.
+pub trait AlgorithmPolicy: Send + Sync {
+	fn algorithm(&self, height: u64) -> Algorithm;
+}
+
+impl<F: Fn(u64) -> Algorithm + Send + Sync> AlgorithmPolicy for F {
+	fn algorithm(&self, height: u64) -> Algorithm {
+		self(height)
+	}
+}
+
+/// The algorithm the node last announced, for miners that learn it from their node
+/// connection rather than per height. Clones share it.
+#[derive(Debug, Clone)]
+pub struct AlgorithmSwitch(Arc<AtomicU8>);
+
+impl AlgorithmSwitch {
+	/// Starts on ProgPoW.
+	pub fn new() -> Self {
+		AlgorithmSwitch(Arc::new(AtomicU8::new(Algorithm::ProgPow as u8)))
+	}
+
+	pub fn set(&self, algorithm: Algorithm) {
+		self.0.store(algorithm as u8, Ordering::Relaxed);
+	}
+
+	pub fn get(&self) -> Algorithm {
+		match self.0.load(Ordering::Relaxed) {
+			x if x == Algorithm::RandomX as u8 => Algorithm::RandomX,
+			x if x == Algorithm::Cuckoo as u8 => Algorithm::Cuckoo,
+			_ => Algorithm::ProgPow,
+		}
+	}
+}
+
+impl Default for AlgorithmSwitch {
+	fn default() -> Self {
+		AlgorithmSwitch::new()
+	}
+}
+
+impl AlgorithmPolicy for AlgorithmSwitch {
+	fn algorithm(&self, _height: u64) -> Algorithm {
+		self.get()
+	}
+}
+
+/// A ProgPoW miner that only searches while its policy picks ProgPoW for the job's
+/// height. Otherwise `compute` returns at once without searching, so the device is free
+/// for whichever miner won the slot.
+pub struct EpicMiner<M> {
+	miner: M,
+	policy: Box<dyn AlgorithmPolicy>,
+}
+
+impl<M: Miner> EpicMiner<M> {
+	pub fn new<A: AlgorithmPolicy + 'static>(miner: M, policy: A) -> Self {
+		EpicMiner {
+			miner,
+			policy: Box::new(policy),
+		}
+	}
+
+	/// Whether `compute` would skip `job`.
+	pub fn suspended(&self, job: &MiningJob) -> bool {
+		self.policy.algorithm(job.height) != Algorithm::ProgPow
+	}
+
+	pub fn into_inner(self) -> M {
+		self.miner
+	}
+}
+
+impl<M: Miner> Miner for EpicMiner<M> {
+	fn init(&mut self) -> Result<(), ProgPowError> {
+		self.miner.init()
+	}
+
+	fn hardware(&self) -> Hardware {
+		self.miner.hardware()
+	}
+
+	fn compute(&self, job: &MiningJob, nonce_range: Range<u64>) -> Result<(), ProgPowError> {
+		let algorithm = self.policy.algorithm(job.height);
+		if algorithm != Algorithm::ProgPow {
+			debug!(height = job.height, ?algorithm, "progpow suspended");
+			return Ok(());
+		}
+		self.miner.compute(job, nonce_range)
+	}
+
+	fn take_solutions(&self) -> Vec<(u64, H256)> {
+		self.miner.take_solutions()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::hardware::MockMiner;
+
+	#[test]
+	fn test_epic_epochs() {
+		assert_eq!(epoch_for_height(29999), 0);
+		assert_eq!(epoch_for_height(30000), 1);
+		assert_eq!(seed_hash(29999), [0; 32]);
+		assert_eq!(seed_hash(60000), epoch::seed_hash(2));
+		assert!(params().is::<EpicParams>());
+	}
+
+	#[test]
+	fn test_epic_miner_follows_switch() {
+		let switch = AlgorithmSwitch::new();
+		let mock = MockMiner::new().with_solution([1; 32], 5, [2; 32]);
+		let miner = EpicMiner::new(mock, switch.clone());
+		let job = MiningJob::new(1, [1; 32], 20, 0, [0; 32]);
+
+		switch.set(Algorithm::RandomX);
+		assert!(miner.suspended(&job));
+		miner.compute(&job, 0..10).unwrap();
+		assert!(miner.take_solutions().is_empty());
+
+		switch.set(Algorithm::ProgPow);
+		miner.compute(&job, 0..10).unwrap();
+		assert_eq!(miner.take_solutions(), vec![(5, [2; 32])]);
+
+		// Per-height policies, e.g. from a schedule the node published.
+		let by_height = EpicMiner::new(MockMiner::new(), |height: u64| match height % 3 {
+			0 => Algorithm::ProgPow,
+			1 => Algorithm::RandomX,
+			_ => Algorithm::Cuckoo,
+		});
+		assert!(!by_height.suspended(&MiningJob::new(1, [1; 32], 21, 0, [0; 32])));
+		assert!(by_height.suspended(&job));
+	}
+}
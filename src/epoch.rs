@@ -0,0 +1,140 @@
+//! Epoch bookkeeping for verifiers, miners and pools.
+
+use progpow_base::params::ProgPowParams;
+use progpow_base::shared::{get_cache_size, get_data_size};
+
+use crate::params::{with_preset, DynParams};
+use crate::types::{ProgPowError, H256};
+
+pub fn epoch_for_height<P: ProgPowParams>(height: u64) -> u64 {
+	height / P::EPOCH_LENGTH
+}
+
+/// First block height of `epoch`.
+pub fn epoch_start<P: ProgPowParams>(epoch: u64) -> u64 {
+	epoch * P::EPOCH_LENGTH
+}
+
+/// ProgPoW period of `height`, which selects the random program and so the kernel.
+pub fn period_for_height(height: u64, params: &DynParams) -> u64 {
+	params.period(height)
+}
+
+/// Blocks from `height`, inclusive, to the first block of the next period.
+pub fn blocks_until_next_period<P: ProgPowParams>(height: u64) -> u64 {
+	P::PERIOD_LENGTH as u64 - height % P::PERIOD_LENGTH as u64
+}
+
+/// First block height of the period after the one of `height`.
+pub fn next_period_height<P: ProgPowParams>(height: u64) -> u64 {
+	height + blocks_until_next_period::<P>(height)
+}
+
+/// Seed hash of `epoch`: keccak256 applied `epoch` times to 32 zero bytes.
+///
+/// This is the value sent as `seed_hash` in stratum notifies and `eth_getWork`.
+pub fn seed_hash(epoch: u64) -> H256 {
+	let mut seed = [0u8; 32];
+	for _ in 0..epoch {
+		let prev = seed;
+		keccak_hash::keccak_256(&prev, &mut seed);
+	}
+	seed
+}
+
+/// Epoch whose seed hash is `seed`, searching up to `max_epoch`.
+pub fn epoch_for_seed(seed: &H256, max_epoch: u64) -> Option<u64> {
+	let mut hash = [0u8; 32];
+	for epoch in 0..=max_epoch {
+		if hash == *seed {
+			return Some(epoch);
+		}
+		let prev = hash;
+		keccak_hash::keccak_256(&prev, &mut hash);
+	}
+	None
+}
+
+/// Size in bytes of the full DAG for `epoch`.
+pub fn dag_size<P: ProgPowParams>(epoch: u64) -> usize {
+	get_data_size::<P>(epoch_start::<P>(epoch))
+}
+
+/// Size in bytes of the light cache for `epoch`.
+pub fn cache_size<P: ProgPowParams>(epoch: u64) -> usize {
+	get_cache_size::<P>(epoch_start::<P>(epoch))
+}
+
+/// Device memory in bytes a GPU needs to mine `epoch`: the DAG and the light cache it
+/// is generated from.
+pub fn required_vram(epoch: u64, params: &DynParams) -> Result<u64, ProgPowError> {
+	with_preset!(params, P => Ok((dag_size::<P>(epoch) + cache_size::<P>(epoch)) as u64))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use progpow_base::params::KawPowParams;
+
+	#[test]
+	fn test_seed_hash() {
+		assert_eq!(seed_hash(0), [0u8; 32]);
+		// Epoch 16 from the ethash reference (block 486382).
+		let hash = [
+			241, 175, 44, 134, 39, 121, 245, 239, 228, 236, 43, 160, 195, 152, 46, 7, 199, 5, 253,
+			147, 241, 206, 98, 43, 3, 104, 17, 40, 192, 79, 106, 162,
+		];
+		assert_eq!(seed_hash(16), hash);
+		assert_eq!(epoch_for_seed(&hash, 100), Some(16));
+		assert_eq!(epoch_for_seed(&hash, 10), None);
+	}
+
+	#[test]
+	fn test_period_prediction() {
+		let params = DynParams::of::<KawPowParams>();
+		let length = KawPowParams::PERIOD_LENGTH as u64;
+		for height in [0, 1, length - 1, length, 5 * length + 2] {
+			let next = next_period_height::<KawPowParams>(height);
+			assert_eq!(
+				period_for_height(height, &params),
+				KawPowParams::prog_seed(height)
+			);
+			assert_eq!(
+				KawPowParams::prog_seed(next),
+				KawPowParams::prog_seed(height) + 1
+			);
+			assert_eq!(
+				KawPowParams::prog_seed(next - 1),
+				KawPowParams::prog_seed(height)
+			);
+			assert_eq!(
+				blocks_until_next_period::<KawPowParams>(height),
+				next - height
+			);
+		}
+	}
+
+	#[test]
+	fn test_sizes_follow_epoch() {
+		let epoch = epoch_for_height::<KawPowParams>(KawPowParams::EPOCH_LENGTH * 3 + 1);
+		assert_eq!(epoch, 3);
+		assert!(dag_size::<KawPowParams>(epoch) > dag_size::<KawPowParams>(epoch - 1));
+		assert!(cache_size::<KawPowParams>(epoch) > cache_size::<KawPowParams>(epoch - 1));
+		assert_eq!(dag_size::<KawPowParams>(epoch) % 128, 0);
+	}
+
+	#[test]
+	fn test_required_vram() {
+		let params = DynParams::of::<KawPowParams>();
+		let needed = required_vram(3, &params).unwrap();
+		assert_eq!(
+			needed,
+			(dag_size::<KawPowParams>(3) + cache_size::<KawPowParams>(3)) as u64
+		);
+		assert!(required_vram(4, &params).unwrap() > needed);
+
+		let mut custom = params;
+		custom.regs = 1;
+		assert!(required_vram(3, &custom).is_err());
+	}
+}
@@ -0,0 +1,247 @@
+//! Randomized comparison of a device's hashes with the CPU's.
+//!
+//! Meant for ignored tests and bring-up of new backends: `run` hashes random headers,
+//! heights and nonces on both sides and reports the first sample that differs, with the
+//! kernel's trace of it when the device records one (see `debug`).
+
+use crate::debug::KernelDebug;
+use crate::hardware::PpCPU;
+use crate::params::DynParams;
+use crate::types::{ProgPowError, Verifier, H256};
+
+/// Seed of `run`, so a divergence found once is found again.
+pub const DEFAULT_SEED: u64 = 0x5eed;
+
+/// A device `run` can compare with the CPU.
+pub trait Device {
+	/// `(final_hash, mix_hash)` words of `nonce` as `Verifier::verify` returns them, with
+	/// the trace of the kernel if available. Devices built for given params ignore
+	/// `params`.
+	fn hash_one(
+		&mut self,
+		params: &DynParams,
+		header: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<Sample, ProgPowError>;
+}
+
+/// One hash computed by a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+	/// `None` for kernels that only return the mix.
+	pub value: Option<[u32; 8]>,
+	pub mix: [u32; 8],
+	pub trace: Option<KernelDebug>,
+}
+
+impl<V: Verifier> Device for V {
+	fn hash_one(
+		&mut self,
+		_params: &DynParams,
+		header: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<Sample, ProgPowError> {
+		let (value, mix) = self.verify(header, height, nonce)?;
+		Ok(Sample {
+			value: Some(value),
+			mix,
+			trace: None,
+		})
+	}
+}
+
+#[cfg(feature = "cuda-rust")]
+impl Device for crate::hardware::CudaMiner {
+	fn hash_one(
+		&mut self,
+		params: &DynParams,
+		header: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<Sample, ProgPowError> {
+		let (mix, trace) =
+			crate::params::with_preset!(params, P => self.hash_one::<P>(header, height, nonce))?;
+		Ok(Sample {
+			value: None,
+			mix,
+			trace,
+		})
+	}
+}
+
+/// First step of the hash whose result differs, in pipeline order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+	/// The mix digest, i.e. the DAG loop or anything before it. `KernelDebug` has the
+	/// seeds and the first registers to narrow it down.
+	Mix,
+	/// The keccak state after the final permutation, from the trace.
+	FinalState,
+	/// The final hash returned by the device.
+	FinalHash,
+	/// The 64-bit word the kernel compares with the target, from the trace.
+	TargetWord,
+}
+
+/// A sample on which the device and the CPU disagree.
+#[derive(Debug, Clone)]
+pub struct Divergence {
+	pub header: H256,
+	pub height: u64,
+	pub nonce: u64,
+	pub stage: Stage,
+	pub device: Sample,
+	/// `(final_hash, mix_hash)` of `PpCPU`.
+	pub cpu: ([u32; 8], [u32; 8]),
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+	/// Samples compared, the diverging one included.
+	pub samples: usize,
+	pub divergence: Option<Divergence>,
+}
+
+/// splitmix64, enough to spread samples without a dependency on `rand`.
+struct Rng(u64);
+
+impl Rng {
+	fn next(&mut self) -> u64 {
+		self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+		let mut z = self.0;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+		z ^ (z >> 31)
+	}
+}
+
+/// The stage at which `device` departs from the CPU's `(value, mix)`, if any.
+fn diverging_stage(device: &Sample, value: &[u32; 8], mix: &[u32; 8]) -> Option<Stage> {
+	if device.mix != *mix {
+		return Some(Stage::Mix);
+	}
+	if let Some(ref trace) = device.trace {
+		// The kernel keeps the state in big-endian words.
+		if (0..8).any(|i| trace.final_state[i].swap_bytes() != value[i]) {
+			return Some(Stage::FinalState);
+		}
+	}
+	if device.value.map_or(false, |v| v != *value) {
+		return Some(Stage::FinalHash);
+	}
+	match device.trace {
+		Some(ref trace) if trace.result != (value[0] as u64) << 32 | value[1] as u64 => {
+			Some(Stage::TargetWord)
+		}
+		_ => None,
+	}
+}
+
+/// `run_seeded` with `DEFAULT_SEED`.
+pub fn run<D: Device>(
+	device: &mut D,
+	params: &DynParams,
+	n_samples: usize,
+) -> Result<Report, ProgPowError> {
+	run_seeded(device, params, n_samples, DEFAULT_SEED)
+}
+
+/// Hash `n_samples` random headers and nonces on `device` and on the CPU, stopping at
+/// the first divergence. Heights are drawn from the first two epochs, so only two light
+/// caches and DAGs are built.
+pub fn run_seeded<D: Device>(
+	device: &mut D,
+	params: &DynParams,
+	n_samples: usize,
+	seed: u64,
+) -> Result<Report, ProgPowError> {
+	let cpu = PpCPU::new_dyn(params.clone());
+	let mut rng = Rng(seed);
+	for sample in 1..=n_samples {
+		let mut header = [0u8; 32];
+		for chunk in header.chunks_mut(8) {
+			chunk.copy_from_slice(&rng.next().to_le_bytes());
+		}
+		let height = rng.next() % (2 * params.epoch_length);
+		let nonce = rng.next();
+
+		let (value, mix) = cpu.verify(&header, height, nonce)?;
+		let hashed = device.hash_one(params, &header, height, nonce)?;
+		if let Some(stage) = diverging_stage(&hashed, &value, &mix) {
+			return Ok(Report {
+				samples: sample,
+				divergence: Some(Divergence {
+					header,
+					height,
+					nonce,
+					stage,
+					device: hashed,
+					cpu: (value, mix),
+				}),
+			});
+		}
+	}
+	Ok(Report {
+		samples: n_samples,
+		divergence: None,
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use progpow_base::params::KawPowParams;
+
+	/// The CPU with one bit of the mix flipped, as a miscompiled kernel would.
+	struct Corrupt(PpCPU);
+
+	impl Device for Corrupt {
+		fn hash_one(
+			&mut self,
+			params: &DynParams,
+			header: &H256,
+			height: u64,
+			nonce: u64,
+		) -> Result<Sample, ProgPowError> {
+			let mut sample = self.0.hash_one(params, header, height, nonce)?;
+			sample.mix[3] ^= 1;
+			Ok(sample)
+		}
+	}
+
+	#[test]
+	fn test_equivalence_reports_first_divergence() {
+		let params = DynParams::of::<KawPowParams>();
+		let mut cpu = PpCPU::new_dyn(params.clone());
+		let report = run(&mut cpu, &params, 2).unwrap();
+		assert_eq!(report.samples, 2);
+		assert!(report.divergence.is_none());
+
+		let mut corrupt = Corrupt(PpCPU::new_dyn(params.clone()));
+		let report = run(&mut corrupt, &params, 2).unwrap();
+		assert_eq!(report.samples, 1);
+		let divergence = report.divergence.unwrap();
+		assert_eq!(divergence.stage, Stage::Mix);
+		assert_eq!(divergence.device.mix[3], divergence.cpu.1[3] ^ 1);
+	}
+
+	#[test]
+	#[ignore = "needs an NVIDIA GPU"]
+	#[cfg(feature = "cuda-rust")]
+	fn test_cuda_matches_cpu() {
+		let mut miner = crate::hardware::CudaMiner::new(0).unwrap();
+		let report = run(&mut miner, &DynParams::of::<KawPowParams>(), 64).unwrap();
+		assert!(report.divergence.is_none(), "{:?}", report.divergence);
+	}
+
+	#[test]
+	#[ignore = "needs a GPU"]
+	#[cfg(any(feature = "cuda", feature = "opencl"))]
+	fn test_gpu_matches_cpu() {
+		let mut gpu = crate::hardware::PpGPU::new(0, progpow_gpu::Driver::Auto);
+		let report = run(&mut gpu, &DynParams::of::<KawPowParams>(), 64).unwrap();
+		assert!(report.divergence.is_none(), "{:?}", report.divergence);
+	}
+}
@@ -0,0 +1,263 @@
+//! Classic ethash (hashimoto) verification, for Ethereum-Classic-style chains.
+//!
+//! For a given epoch number ethash and KawPoW use the same light cache and dataset; only
+//! the blocks per epoch differ. Caches are therefore built by the shared
+//! `NodeCacheBuilder` and stored in the same files, named by seed hash, that `PpCPU`
+//! uses, so a node verifying both kinds of chain holds one copy per epoch.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::hardware::cpu::get_cache_path;
+use crate::types::difficulty::meets_target;
+use crate::types::{Mix, ProgPowError, ShareStatus, Verifier, H256};
+use progpow_base::compute::calculate_dag_item;
+use progpow_base::params::{KawPowParams, ProgPowParams};
+use progpow_base::shared::{get_data_size, Node};
+use progpow_cpu::cache::{NodeCache, NodeCacheBuilder};
+use tracing::{debug, warn};
+
+/// Name of the ethash preset, alongside the `ProgPowParams::NAME` of the ProgPoW ones.
+pub const NAME: &str = "ethash";
+/// Blocks per ethash epoch.
+pub const EPOCH_LENGTH: u64 = 30000;
+
+const ACCESSES: u32 = 64;
+/// 32-bit words in the 128-byte mix.
+const MIX_WORDS: usize = 32;
+const FNV_PRIME: u32 = 0x01000193;
+
+/// Parameters the shared cache builder is instantiated with. Only their epoch length
+/// differs from ethash, see `cache_height`.
+type CacheParams = KawPowParams;
+
+pub fn epoch_for_height(height: u64) -> u64 {
+	height / EPOCH_LENGTH
+}
+
+/// A height in `epoch` as `CacheParams` counts it.
+fn cache_height(epoch: u64) -> u64 {
+	epoch * CacheParams::EPOCH_LENGTH
+}
+
+/// Size in bytes of the full dataset for `epoch`.
+pub fn dataset_size(epoch: u64) -> usize {
+	get_data_size::<CacheParams>(cache_height(epoch))
+}
+
+fn fnv(a: u32, b: u32) -> u32 {
+	a.wrapping_mul(FNV_PRIME) ^ b
+}
+
+/// Hashimoto over the light cache of a dataset of `full_size` bytes, computing each
+/// dataset item it touches. Returns `(final_hash, mix_hash)`.
+pub fn hashimoto_light(
+	cache: &[Node],
+	full_size: usize,
+	header_hash: &H256,
+	nonce: u64,
+) -> (H256, H256) {
+	let mut seed_input = [0u8; 40];
+	seed_input[..32].copy_from_slice(header_hash);
+	seed_input[32..].copy_from_slice(&nonce.to_le_bytes());
+	let mut seed = [0u8; 64];
+	keccak_hash::keccak_512(&seed_input, &mut seed);
+
+	let mut seed_words = [0u32; 16];
+	for (word, bytes) in seed_words.iter_mut().zip(seed.chunks(4)) {
+		*word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+	}
+	let mut mix = [0u32; MIX_WORDS];
+	for (i, word) in mix.iter_mut().enumerate() {
+		*word = seed_words[i % 16];
+	}
+
+	let pages = (full_size / (MIX_WORDS * 4)) as u32;
+	for i in 0..ACCESSES {
+		let page = fnv(i ^ seed_words[0], mix[i as usize % MIX_WORDS]) % pages;
+		for n in 0..2 {
+			let item = calculate_dag_item::<CacheParams>(page * 2 + n, cache);
+			for (w, word) in item.as_words().iter().enumerate() {
+				let m = &mut mix[n as usize * 16 + w];
+				*m = fnv(*m, *word);
+			}
+		}
+	}
+
+	let mut mix_hash = [0u8; 32];
+	for (i, words) in mix.chunks(4).enumerate() {
+		let word = words[1..].iter().fold(words[0], |acc, &w| fnv(acc, w));
+		mix_hash[i * 4..(i + 1) * 4].copy_from_slice(&word.to_le_bytes());
+	}
+
+	let mut final_input = [0u8; 96];
+	final_input[..64].copy_from_slice(&seed);
+	final_input[64..].copy_from_slice(&mix_hash);
+	let mut final_hash = [0u8; 32];
+	keccak_hash::keccak_256(&final_input, &mut final_hash);
+	(final_hash, mix_hash)
+}
+
+/// CPU verifier for ethash. Keeps the light cache of the last epoch it verified.
+pub struct Ethash {
+	cache_builder: NodeCacheBuilder,
+	cache_dir: Option<PathBuf>,
+	cache: Mutex<Option<(u64, Arc<NodeCache>)>>,
+}
+
+impl Ethash {
+	pub fn new() -> Self {
+		Ethash {
+			cache_builder: NodeCacheBuilder::new(None),
+			cache_dir: None,
+			cache: Mutex::new(None),
+		}
+	}
+
+	/// Directory light caches are read from and written to, shared with `PpCPU`.
+	pub fn set_cache_dir<D: Into<PathBuf>>(&mut self, dir: D) {
+		self.cache_dir = Some(dir.into());
+	}
+
+	fn cache(&self, epoch: u64) -> Result<Arc<NodeCache>, ProgPowError> {
+		let mut current = self.cache.lock().unwrap();
+		if let Some((cached, ref cache)) = *current {
+			if cached == epoch {
+				return Ok(cache.clone());
+			}
+		}
+
+		let path = match self.cache_dir {
+			Some(ref dir) => dir.clone(),
+			None => get_cache_path().map_err(|_| ProgPowError::CACHE)?,
+		};
+		let height = cache_height(epoch);
+		let cache = match self
+			.cache_builder
+			.from_file::<CacheParams>(path.clone(), height)
+		{
+			Ok(cache) => cache,
+			Err(_) => {
+				let mut cache = self.cache_builder.new_cache::<CacheParams>(path, height);
+				debug!(epoch, "ethash light cache built");
+				if let Err(e) = cache.flush() {
+					warn!(error = %e, "light cache file write failed");
+				}
+				cache
+			}
+		};
+		let cache = Arc::new(cache);
+		*current = Some((epoch, cache.clone()));
+		Ok(cache)
+	}
+
+	/// `(final_hash, mix_hash)` of `nonce`, in the byte order of `Verifier::verify_hash`.
+	pub fn hash(
+		&self,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<(H256, H256), ProgPowError> {
+		let epoch = epoch_for_height(height);
+		let cache = self.cache(epoch)?;
+		Ok(hashimoto_light(
+			(*cache).as_ref(),
+			dataset_size(epoch),
+			header_hash,
+			nonce,
+		))
+	}
+
+	/// Check a submitted share, see `PpCPU::validate_share`.
+	pub fn validate_share(
+		&self,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+		expected_mix: &H256,
+		target: &H256,
+	) -> Result<ShareStatus, ProgPowError> {
+		let (final_hash, mix_hash) = self.hash(header_hash, height, nonce)?;
+		if &mix_hash != expected_mix {
+			Ok(ShareStatus::BadMix)
+		} else if !meets_target(&final_hash, target) {
+			Ok(ShareStatus::LowDifficulty)
+		} else {
+			Ok(ShareStatus::Ok)
+		}
+	}
+}
+
+impl Default for Ethash {
+	fn default() -> Self {
+		Ethash::new()
+	}
+}
+
+impl Verifier for Ethash {
+	/// The words of `(final_hash, mix_hash)`, in the layout `final_hash_bytes` and
+	/// `mix_hash_bytes` expect.
+	fn verify(
+		&self,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<([u32; 8], [u32; 8]), ProgPowError> {
+		let (final_hash, mix_hash) = self.hash(header_hash, height, nonce)?;
+		let mut value = [0u32; 8];
+		for (word, bytes) in value.iter_mut().zip(final_hash.chunks_exact(4)) {
+			*word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+		}
+		Ok((value, Mix::from_le_bytes(&mix_hash).0))
+	}
+
+	fn verify_hash(
+		&self,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<(H256, H256), ProgPowError> {
+		self.hash(header_hash, height, nonce)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_ethash_light() {
+		// Block 486382 from the ethash reference tests.
+		let header = [
+			0xf5, 0x7e, 0x6f, 0x3a, 0xcf, 0xc0, 0xdd, 0x4b, 0x5b, 0xf2, 0xbe, 0xe4, 0x0a, 0xb3,
+			0x35, 0x8a, 0xa6, 0x87, 0x73, 0xa8, 0xd0, 0x9f, 0x5e, 0x59, 0x5e, 0xab, 0x55, 0x94,
+			0x05, 0x52, 0x7d, 0x72,
+		];
+		let mix_hash = [
+			0x1f, 0xff, 0x04, 0xce, 0xc9, 0x41, 0x73, 0xfd, 0x59, 0x1e, 0x3d, 0x89, 0x60, 0xce,
+			0x6b, 0xdf, 0x8b, 0x19, 0x71, 0x04, 0x8c, 0x71, 0xff, 0x93, 0x7b, 0xb2, 0xd3, 0x2a,
+			0x64, 0x31, 0xab, 0x6d,
+		];
+		let final_hash = [
+			0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x3e, 0x9b, 0x6c, 0x69, 0xbc, 0x2c, 0xe2, 0xa2,
+			0x4a, 0x8e, 0x95, 0x69, 0xef, 0xc7, 0xd7, 0x1b, 0x33, 0x35, 0xdf, 0x36, 0x8c, 0x9a,
+			0xe9, 0x7e, 0x53, 0x84,
+		];
+		let nonce = 0xd7b3ac70a301a249;
+
+		let dir = std::env::temp_dir().join(format!("progpow-ethash-{}", std::process::id()));
+		let mut ethash = Ethash::new();
+		ethash.set_cache_dir(&dir);
+		assert_eq!(
+			ethash.verify_hash(&header, 486382, nonce).unwrap(),
+			(final_hash, mix_hash)
+		);
+		assert_eq!(
+			ethash
+				.validate_share(&header, 486382, nonce, &mix_hash, &final_hash)
+				.unwrap(),
+			ShareStatus::Ok
+		);
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+}
@@ -1,15 +1,17 @@
-// Implementation of the CUDA kernel generator for ProgPow/KawPow
+// Implementation of the CUDA and OpenCL kernel generators for ProgPow/KawPow
 // Ported from the official xmrig-cuda implementation (CudaKawPow_gen.cpp & KawPow.h)
 
 // Assuming progpow_base is a sibling crate in the workspace
-use progpow_base::params::ProgPowParams;
+use progpow_base::params::{MathMapping, ProgPowParams};
 use std::fmt::Write;
+use tracing::{debug, debug_span};
 
-const PROGPOW_REGS: usize = 32;
-const PROGPOW_LANES: usize = 16;
-const PROGPOW_DAG_LOADS: usize = 4;
-const PROGPOW_CACHE_BYTES: usize = 16384;
-const PROGPOW_CNT_DAG: usize = 64;
+use self::program::{MathOp, Merge, Operation};
+use crate::params::{with_preset, DynParams};
+use crate::types::ProgPowError;
+
+pub mod dag;
+pub mod program;
 
 // KISS99 generator
 struct Kiss99 {
@@ -49,17 +51,282 @@ fn fnv1a(h: &mut u32, d: u32) -> u32 {
 	*h
 }
 
-// lazy_static! {
-// 	pub static ref KAWPOW_PARAMS: ProgPowParams = ProgPowParams::kawpow();
-// }
+/// Capacity of the `search_results` buffer the generated kernels write into.
+pub const SEARCH_RESULTS: usize = 16;
+/// Work-items cooperating on one nonce.
+pub const PROGPOW_LANES: usize = 16;
+/// Length of the kernels' `keccakf_rndc`, and so the most rounds of keccak-f800 a preset
+/// may ask for.
+pub const KECCAK_MAX_ROUNDS: u32 = 24;
+
+/// GPU source dialect a kernel is generated for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Backend {
+	Cuda,
+	OpenCl,
+	/// The CUDA kernel adapted for hiprtc, see `generate_hip_kernel`.
+	Hip,
+	/// Metal Shading Language, see `generate_metal_kernel`.
+	Metal,
+	/// GLSL compute shader compiled to SPIR-V, see `generate_vulkan_kernel`.
+	Vulkan,
+	/// OpenCL C for Intel GPUs under Level Zero, see `generate_level_zero_kernel`.
+	LevelZero,
+}
+
+impl Backend {
+	pub fn generate<P: ProgPowParams>(self, height: u64) -> String {
+		match self {
+			Backend::Cuda => generate_cuda_kernel::<P>(0, height),
+			Backend::OpenCl => generate_opencl_kernel::<P>(0, height),
+			Backend::Hip => generate_hip_kernel::<P>(0, height),
+			Backend::Metal => generate_metal_kernel::<P>(0, height),
+			Backend::Vulkan => generate_vulkan_kernel::<P>(0, height),
+			Backend::LevelZero => generate_level_zero_kernel::<P>(0, height),
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Backend::Cuda => "cuda",
+			Backend::OpenCl => "opencl",
+			Backend::Hip => "hip",
+			Backend::Metal => "metal",
+			Backend::Vulkan => "vulkan",
+			Backend::LevelZero => "level-zero",
+		}
+	}
+
+	/// Name of the search function in the generated source.
+	pub fn entry_point(self) -> &'static str {
+		match self {
+			Backend::Cuda | Backend::Hip => "progpow_search_v3",
+			Backend::OpenCl | Backend::Metal | Backend::LevelZero => "progpow_search",
+			Backend::Vulkan => "main",
+		}
+	}
+}
+
+/// A generated kernel and what an external toolchain needs to compile and launch it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KernelSource {
+	pub backend: Backend,
+	/// Complete source, needing no include paths or extra defines.
+	pub source: String,
+	pub entry_point: String,
+	/// The `#define`s generated into `source`, in order.
+	pub defines: Vec<(String, u32)>,
+	pub period: u64,
+	/// DAG size in 256-byte elements, compiled into the kernel's offset modulo.
+	pub dag_elements: u64,
+	/// Capacity of the `search_results` buffer the kernel writes into.
+	pub search_results: usize,
+}
+
+impl KernelSource {
+	/// Whether a launch dropped solutions, given the `count` word it left in the
+	/// `search_results` buffer. The kernel counts every match, also those past the last
+	/// slot.
+	pub fn overflowed(&self, count: u32) -> bool {
+		count as usize > self.search_results
+	}
+}
+
+/// Generate the kernel of `backend` running the random program of `period`, normally
+/// `ProgPowParams::prog_seed` of `height`, over the DAG of `height`. For miners that
+/// compile kernels with their own NVRTC or clang toolchains.
+pub fn generate_kernel_source(
+	backend: Backend,
+	params: &DynParams,
+	period: u64,
+	height: u64,
+) -> Result<KernelSource, ProgPowError> {
+	generate_kernel_source_with_results(backend, params, period, height, SEARCH_RESULTS)
+}
+
+/// `generate_kernel_source` with room for `search_results` solutions per launch instead
+/// of `SEARCH_RESULTS`, for chains whose difficulty is low enough to fill the buffer. The
+/// caller sizes the buffer to match, see `KernelSource::overflowed`.
+pub fn generate_kernel_source_with_results(
+	backend: Backend,
+	params: &DynParams,
+	period: u64,
+	height: u64,
+	search_results: usize,
+) -> Result<KernelSource, ProgPowError> {
+	if search_results == 0 {
+		return Err(ProgPowError::Config(
+			"search_results must be at least 1".to_string(),
+		));
+	}
+	with_preset!(*params, P => kernel_source::<P>(backend, period, height, search_results))
+}
+
+/// Set the capacity of the `search_results` buffer in a generated kernel `source`.
+pub(crate) fn set_search_results(
+	source: String,
+	search_results: usize,
+) -> Result<String, ProgPowError> {
+	let define = format!("#define SEARCH_RESULTS {}\n", SEARCH_RESULTS);
+	if !source.contains(&define) {
+		return Err(ProgPowError::Config(
+			"kernel source has no SEARCH_RESULTS define".to_string(),
+		));
+	}
+	Ok(source.replacen(
+		&define,
+		&format!("#define SEARCH_RESULTS {}\n", search_results),
+		1,
+	))
+}
+
+fn kernel_source<P: ProgPowParams>(
+	backend: Backend,
+	period: u64,
+	height: u64,
+	search_results: usize,
+) -> Result<KernelSource, ProgPowError> {
+	let source = match backend {
+		Backend::Cuda => fill_template::<P>(PROGPOW_KERNEL_TEMPLATE, period, height),
+		Backend::OpenCl => fill_template::<P>(PROGPOW_OPENCL_KERNEL_TEMPLATE, period, height),
+		Backend::Hip => cuda_to_hip(fill_template::<P>(PROGPOW_KERNEL_TEMPLATE, period, height)),
+		Backend::Metal => fill_template::<P>(PROGPOW_METAL_KERNEL_TEMPLATE, period, height),
+		Backend::Vulkan => to_glsl(fill_template::<P>(
+			PROGPOW_VULKAN_KERNEL_TEMPLATE,
+			period,
+			height,
+		)),
+		Backend::LevelZero => opencl_to_level_zero(fill_template::<P>(
+			PROGPOW_OPENCL_KERNEL_TEMPLATE,
+			period,
+			height,
+		)),
+	};
+	let source = set_search_results(source, search_results)?;
+	Ok(KernelSource {
+		backend,
+		source,
+		entry_point: backend.entry_point().to_string(),
+		defines: kernel_defines(&DynParams::of::<P>())
+			.into_iter()
+			.map(|(name, value)| (name.to_string(), value))
+			.collect(),
+		period,
+		dag_elements: dag_elements::<P>(height),
+		search_results,
+	})
+}
+
+/// Number of 256-byte DAG elements the kernel indexes at `height`.
+pub fn dag_elements<P: ProgPowParams>(height: u64) -> u64 {
+	(crate::epoch::dag_size::<P>(crate::epoch::epoch_for_height::<P>(height)) / 256) as u64
+}
+
+pub fn generate_cuda_kernel<P: ProgPowParams>(_period: u64, height: u64) -> String {
+	fill_template::<P>(PROGPOW_KERNEL_TEMPLATE, P::prog_seed(height), height)
+}
+
+/// OpenCL counterpart of `generate_cuda_kernel`. Both backends share the placeholder
+/// substitution below, so they execute the same random program for a given height.
+pub fn generate_opencl_kernel<P: ProgPowParams>(_period: u64, height: u64) -> String {
+	fill_template::<P>(PROGPOW_OPENCL_KERNEL_TEMPLATE, P::prog_seed(height), height)
+}
+
+/// The CUDA kernel for AMD GPUs through HIP. The launch interface is the same.
+pub fn generate_hip_kernel<P: ProgPowParams>(_period: u64, height: u64) -> String {
+	cuda_to_hip(generate_cuda_kernel::<P>(0, height))
+}
+
+/// Metal translation of the OpenCL kernel, with the same buffers in argument order and
+/// `GROUP_SIZE` threads per threadgroup.
+pub fn generate_metal_kernel<P: ProgPowParams>(_period: u64, height: u64) -> String {
+	fill_template::<P>(PROGPOW_METAL_KERNEL_TEMPLATE, P::prog_seed(height), height)
+}
+
+/// GLSL compute shader for Vulkan. The workgroup size is specialization constant 0 and
+/// the DAG is split across several storage buffers, see the template's push constants.
+pub fn generate_vulkan_kernel<P: ProgPowParams>(_period: u64, height: u64) -> String {
+	to_glsl(fill_template::<P>(
+		PROGPOW_VULKAN_KERNEL_TEMPLATE,
+		P::prog_seed(height),
+		height,
+	))
+}
+
+/// The shared offset modulo tests a `uint32_t` for zero, which GLSL only allows on `bool`.
+fn to_glsl(source: String) -> String {
+	source.replace("(offset1 ? ", "(offset1 != 0u ? ")
+}
+
+/// The OpenCL kernel for Intel GPUs, compiled to SPIR-V with ocloc and run through Level
+/// Zero. Takes the same arguments as the OpenCL kernel.
+pub fn generate_level_zero_kernel<P: ProgPowParams>(_period: u64, height: u64) -> String {
+	opencl_to_level_zero(generate_opencl_kernel::<P>(0, height))
+}
+
+/// Intel GPUs run sub-groups of 16 work-items, one per ProgPoW lane group, so lanes
+/// exchange values with `sub_group_shuffle` instead of two barriers through local memory.
+fn opencl_to_level_zero(source: String) -> String {
+	let prelude = "#pragma OPENCL EXTENSION cl_intel_subgroups : enable\n";
+	let source = source
+		.replacen(
+			"    barrier(CLK_LOCAL_MEM_FENCE);\n    if (lane_id == src_lane)\n        *share = x;\n    barrier(CLK_LOCAL_MEM_FENCE);\n    return *share;\n",
+			"    return intel_sub_group_shuffle(x, src_lane);\n",
+			1,
+		)
+		.replacen(
+			"__attribute__((reqd_work_group_size(GROUP_SIZE, 1, 1)))\n",
+			"__attribute__((reqd_work_group_size(GROUP_SIZE, 1, 1)))\n__attribute__((intel_reqd_sub_group_size(PROGPOW_LANES)))\n",
+			1,
+		);
+	prelude.to_string() + &source
+}
+
+/// hiprtc compiles the CUDA template apart from two details. Its implicit runtime header
+/// may already define `uint32_t` and `uint64_t`, so the template's own typedefs are
+/// renamed, and `__CUDA_ARCH__` is undefined although every AMD GPU has funnel shifts.
+fn cuda_to_hip(source: String) -> String {
+	let prelude = "#define uint32_t progpow_uint32_t\n#define uint64_t progpow_uint64_t\n";
+	prelude.to_string() + &source.replacen("#if __CUDA_ARCH__ < 350", "#if 0", 1)
+}
+
+/// The `#define`s `fill_template` generates for `params`.
+fn kernel_defines(params: &DynParams) -> Vec<(&'static str, u32)> {
+	let flag = |set: bool| if set { 1 } else { 0 };
+	vec![
+		("KAWPOW_IS_RAVENCOIN", flag(params.has_ravencoin_rndc)),
+		("KAWPOW_IS_MEOWCOIN", flag(params.has_meowcoin_rndc)),
+		("KAWPOW_IS_EVRMORE", flag(params.has_evrmore_rndc)),
+		(
+			"PROGPOW_IS_ZANO",
+			flag(params.math_mapping == MathMapping::Zano),
+		),
+		("PROGPOW_IS_FIRO", flag(params.has_firo_final_state)),
+		("PROGPOW_IS_SERO", flag(params.is_sero)),
+		("PROGPOW_HAS_FINAL_PADDING", flag(params.has_final_padding)),
+		("PROGPOW_CNT_CACHE", params.cnt_cache as u32),
+		("PROGPOW_CNT_MATH", params.cnt_math as u32),
+		("PROGPOW_REGS", params.regs as u32),
+		("PROGPOW_START_OFFSET", 0),
+		("PROGPOW_DEBUG_TRACE", flag(cfg!(feature = "debug-trace"))),
+	]
+}
 
-pub fn generate_cuda_kernel<P: ProgPowParams>(period: u64, _height: u64) -> String {
-	let mut code = String::from(PROGPOW_KERNEL_TEMPLATE);
+// Substitutes the XMRIG_INCLUDE_* placeholders. The generated snippets only use
+// ROTL32/ROTR32/mul_hi/min/clz/popcount/__umulhi/cuda_swab32, which each template defines.
+fn fill_template<P: ProgPowParams>(template: &str, prog_seed: u64, height: u64) -> String {
+	let _span = debug_span!("kernel_gen", params = P::NAME, height).entered();
+	assert!(
+		P::KECCAK_ROUNDS as u32 <= KECCAK_MAX_ROUNDS,
+		"{} asks for {} keccak rounds",
+		P::NAME,
+		P::KECCAK_ROUNDS
+	);
+	let mut code = String::from(template);
 
-	let prog_seed = P::prog_seed(_height);
-	let epoch = _height / P::EPOCH_LENGTH;
-	let dag_size = progpow_base::shared::get_data_size::<P>(epoch * P::EPOCH_LENGTH);
-	let dag_elements = dag_size / 256;
+	let dag_elements = dag_elements::<P>(height);
 
 	// Generate Random Math and DAG Loads logic
 	let (random_math, dag_loads) = get_code::<P>(prog_seed);
@@ -69,7 +336,6 @@ pub fn generate_cuda_kernel<P: ProgPowParams>(period: u64, _height: u64) -> Stri
 	code = code.replace("XMRIG_INCLUDE_PROGPOW_DATA_LOADS", &dag_loads);
 
 	// Calculate Fast Modulo Data
-	// println!("DEBUG: generate_cuda_kernel dag_elements={}", dag_elements);
 	let mut mod_logic = String::new();
 	if (dag_elements & (dag_elements - 1)) == 0 {
 		// Power of two optimization
@@ -96,7 +362,6 @@ pub fn generate_cuda_kernel<P: ProgPowParams>(period: u64, _height: u64) -> Stri
 		}
 	}
 
-	// println!("GENERATED MOD LOGIC:\n{}", mod_logic);
 	code = code.replace("XMRIG_INCLUDE_OFFSET_MOD_DAG_ELEMENTS", &mod_logic);
 
 	// Launch bounds (Hardcoded to 256 threads as per typical usage, or parameterized if needed)
@@ -107,36 +372,12 @@ pub fn generate_cuda_kernel<P: ProgPowParams>(period: u64, _height: u64) -> Stri
 	code = code.replace("XMRIG_INCLUDE_LAUNCH_BOUNDS", "");
 	code = code.replace("XMRIG_INCLUDE_KECCAK_ROUNDS", &P::KECCAK_ROUNDS.to_string());
 
-	// Inject Constants derived from params
-	// The template uses defines. We should ensuring they match params.
-	// Ideally we prepend defines if the template relies on them being unset,
-	// but the template below has them defined. We should replace them or ensure they match.
-	// For now, we assume standard KawPow params 16/32/4/64/11/18 if params match.
-	// If dynamic params are needed, we would replace the #defines in the template string.
-
-	// Replace hardcoded defines if they differ from params
-	// Replace hardcoded defines if they differ from params
-	// Inject KAWPOW_IS_RAVENCOIN
-	// Inject Constants derived from params
-	// We use a placeholder XMRIG_INCLUDE_DEFINES to inject all dynamic defines
-	let is_zano = P::MATH_MAPPING == progpow_base::params::MathMapping::Zano;
-	let is_firo = P::NAME == "FiroPow";
-	let has_final_padding = P::NAME == "ProgPow" || P::NAME == "EpicProgPow";
-	let defines = format!(
-		"#define KAWPOW_IS_RAVENCOIN       {}\n#define KAWPOW_IS_MEOWCOIN        {}\n#define KAWPOW_IS_EVRMORE         {}\n#define PROGPOW_IS_ZANO           {}\n#define PROGPOW_IS_FIRO           {}\n#define PROGPOW_IS_SERO           {}\n#define PROGPOW_HAS_FINAL_PADDING {}\n#define PROGPOW_CNT_CACHE         {}\n#define PROGPOW_CNT_MATH          {}\n#define PROGPOW_REGS              {}\n#define PROGPOW_START_OFFSET      0",
-		if P::HAS_RAVENCOIN_RNDC { 1 } else { 0 },
-		if P::HAS_MEOWCOIN_RNDC { 1 } else { 0 },
-		if P::HAS_EVRMORE_RNDC { 1 } else { 0 },
-		if is_zano { 1 } else { 0 },
-		if is_firo { 1 } else { 0 },
-		if P::NAME == "SeroProgPow" { 1 } else { 0 },
-		if has_final_padding { 1 } else { 0 },
-		P::CNT_CACHE,
-		P::CNT_MATH,
-		P::REGS
-	);
+	let defines = kernel_defines(&DynParams::of::<P>())
+		.iter()
+		.map(|(name, value)| format!("#define {:<25} {}", name, value))
+		.collect::<Vec<_>>()
+		.join("\n");
 	code = code.replace("XMRIG_INCLUDE_DEFINES", &defines);
-	// println!("GENERATED DEFINES:\n{}", defines);
 
 	code = code.replace("XMRIG_INCLUDE_KECCAK_ROUNDS", &P::KECCAK_ROUNDS.to_string());
 	// Padding Logic Replacement
@@ -161,17 +402,8 @@ pub fn generate_cuda_kernel<P: ProgPowParams>(period: u64, _height: u64) -> Stri
 		)
 	};
 
-	// println!("GENERATED PADDING LOGIC:\n{}", padding_logic);
 	code = code.replace("XMRIG_INCLUDE_PROGPOW_INITIAL_PADDING", &padding_logic);
 
-	// let is_zano = P::MATH_MAPPING == progpow_base::params::MathMapping::Zano;
-	/*
-	println!(
-		"DEBUG: generate_cuda_kernel is_zano={} NAME={}",
-		is_zano,
-		P::NAME
-	);
-	*/
 	let kiss99_logic = r#"
     st.jcong = 69069 * st.jcong + 1234567;
     st.jsr ^= (st.jsr << 17);
@@ -193,109 +425,67 @@ pub fn generate_cuda_kernel<P: ProgPowParams>(period: u64, _height: u64) -> Stri
 		r#"    hash_seed_small[0] = state2[0];
     hash_seed_small[1] = state2[1];"#
 	};
-	// println!("DEBUG: hash_seed_extract = {}", hash_seed_extract);
 	code = code.replace("XMRIG_INCLUDE_HASH_SEED_EXTRACT", hash_seed_extract);
 
+	debug!(
+		prog_seed,
+		dag_elements,
+		bytes = code.len(),
+		"kernel generated"
+	);
 	code
 }
 
-// Logic from xmrig-cuda/CudaKawPow_gen.cpp
+// Logic from xmrig-cuda/CudaKawPow_gen.cpp, rendering the program of `program::describe`.
 fn get_code<P: ProgPowParams>(prog_seed: u64) -> (String, String) {
 	let mut random_math = String::with_capacity(4096);
 	let mut dag_loads = String::with_capacity(1024);
 
-	let seed0 = prog_seed as u32;
-	let seed1 = (prog_seed >> 32) as u32;
-
-	let is_zano = P::MATH_MAPPING == progpow_base::params::MathMapping::Zano;
-	// Both KawPow and Zano use FNV-1a chaining for program RNG initialization
-	// Reference: progpow-light/src/progpow.rs:progpow_init()
-	let mut h = 0x811c9dc5u32; // FNV_HASH
-	let z = fnv1a(&mut h, seed0);
-	let w = fnv1a(&mut h, seed1);
-	let jsr = fnv1a(&mut h, seed0);
-	let jcong = fnv1a(&mut h, seed1);
-	let mut rng = Kiss99::new(z, w, jsr, jcong);
-
-	let regs = P::REGS;
-	let mut mix_seq_dst = (0..regs).map(|i| i as i32).collect::<Vec<i32>>();
-	let mut mix_seq_cache = (0..regs).map(|i| i as i32).collect::<Vec<i32>>();
-	let mut mix_seq_dst_cnt = 0;
-	let mut mix_seq_cache_cnt = 0;
-
-	if P::HAS_KISS99_SHUFFLE {
-		for i in (1..regs).rev() {
-			let j = (rng.rnd(is_zano) as usize) % (i + 1);
-			mix_seq_dst.swap(i, j);
-			let j = (rng.rnd(is_zano) as usize) % (i + 1);
-			mix_seq_cache.swap(i, j);
-		}
-	}
-
-	/*
-	// Debug: Print shuffle sequences to verify they match CPU
-	println!("DEBUG GPU Generator: prog_seed={}", prog_seed);
-	println!(
-		"DEBUG GPU Generator: mix_seq_dst[0..4] = {} {} {} {}",
-		mix_seq_dst[0], mix_seq_dst[1], mix_seq_dst[2], mix_seq_dst[3]
-	);
-	println!(
-		"DEBUG GPU Generator: mix_seq_cache[0..4] = {} {} {} {}",
-		mix_seq_cache[0], mix_seq_cache[1], mix_seq_cache[2], mix_seq_cache[3]
-	);
-	*/
-
-	let cnt_cache = P::CNT_CACHE;
-	let cnt_math = P::CNT_MATH;
-	let max_ops = std::cmp::max(cnt_cache, cnt_math);
-
-	for i in 0..max_ops {
-		if i < cnt_cache {
-			let src = format!("mix[{}]", mix_seq_cache[mix_seq_cache_cnt % regs]);
-			mix_seq_cache_cnt += 1;
-			let dest = format!("mix[{}]", mix_seq_dst[mix_seq_dst_cnt % regs]);
-			mix_seq_dst_cnt += 1;
-			let r = rng.rnd(is_zano);
-
-			let _ = writeln!(random_math, "    // cache load {}", i);
-			let _ = writeln!(random_math, "    offset = {} % PROGPOW_CACHE_WORDS;", src);
-			let _ = writeln!(random_math, "    data = c_dag[offset];");
-			random_math.push_str(&merge(&dest, "data", r));
-		}
-
-		if i < cnt_math {
-			let src_rnd = (rng.rnd(is_zano) as usize) % ((regs - 1) * regs);
-			let src1 = src_rnd % regs;
-			let mut src2 = src_rnd / regs;
-			if src2 >= src1 {
-				src2 += 1;
+	let program = program::describe(prog_seed, &DynParams::of::<P>());
+	let mut cache_loads = 0;
+	let mut math_ops = 0;
+	for op in &program.operations {
+		match *op {
+			Operation::Cache { src, dst, merge } => {
+				let _ = writeln!(random_math, "    // cache load {}", cache_loads);
+				let _ = writeln!(
+					random_math,
+					"    offset = mix[{}] % PROGPOW_CACHE_WORDS;",
+					src
+				);
+				let _ = writeln!(random_math, "    data = c_dag[offset];");
+				random_math.push_str(&merge_code(&format!("mix[{}]", dst), "data", merge));
+				cache_loads += 1;
+			}
+			Operation::Math {
+				src1,
+				src2,
+				math,
+				dst,
+				merge,
+			} => {
+				let _ = writeln!(random_math, "    // random math {}", math_ops);
+				random_math.push_str(&math_code(
+					"data",
+					&format!("mix[{}]", src1),
+					&format!("mix[{}]", src2),
+					math,
+					P::MATH_MAPPING,
+				));
+				random_math.push_str(&merge_code(&format!("mix[{}]", dst), "data", merge));
+				math_ops += 1;
 			}
-
-			let src1_str = format!("mix[{}]", src1);
-			let src2_str = format!("mix[{}]", src2);
-			let r1 = rng.rnd(is_zano);
-
-			let dest = format!("mix[{}]", mix_seq_dst[mix_seq_dst_cnt % regs]);
-			mix_seq_dst_cnt += 1;
-			let r2 = rng.rnd(is_zano);
-
-			let _ = writeln!(random_math, "    // random math {}", i);
-			random_math.push_str(&math("data", &src1_str, &src2_str, r1, P::MATH_MAPPING));
-			random_math.push_str(&merge(&dest, "data", r2));
 		}
 	}
 
-	// DAG Loads
-	dag_loads.push_str(&merge("mix[0]", "data_dag.s[0]", rng.rnd(is_zano)));
-	for i in 1..P::DAG_LOADS {
-		let dest = format!("mix[{}]", mix_seq_dst[mix_seq_dst_cnt % regs]);
-		mix_seq_dst_cnt += 1;
-		let r = rng.rnd(is_zano);
-		dag_loads.push_str(&merge(&dest, &format!("data_dag.s[{}]", i), r));
+	for load in &program.dag_merges {
+		dag_loads.push_str(&merge_code(
+			&format!("mix[{}]", load.dst),
+			&format!("data_dag.s[{}]", load.word),
+			load.merge,
+		));
 	}
 
-	// println!("GENERATED RANDOM MATH:\n{}", random_math);
-	// println!("GENERATED DAG LOADS:\n{}", dag_loads);
 	(random_math, dag_loads)
 }
 
@@ -324,254 +514,35 @@ fn calculate_fast_mod_data(divisor: u32) -> (u32, u32, u32) {
 	(reciprocal, increment, shift)
 }
 
-fn merge(a: &str, b: &str, r: u32) -> String {
-	match r % 4 {
-		0 => format!("    {} = ({} * 33) + {};\n", a, a, b),
-		1 => format!("    {} = ({} ^ {}) * 33;\n", a, a, b),
-		2 => format!(
-			"    {} = ROTL32({}, {}) ^ {};\n",
-			a,
-			a,
-			((r >> 16) % 31) + 1,
-			b
-		),
-		3 => format!(
-			"    {} = ROTR32({}, {}) ^ {};\n",
-			a,
-			a,
-			((r >> 16) % 31) + 1,
-			b
-		),
-		_ => String::from("#error\n"),
-	}
-}
-
-fn math(d: &str, a: &str, b: &str, r: u32, mapping: progpow_base::params::MathMapping) -> String {
-	use progpow_base::params::MathMapping;
-	match mapping {
-		MathMapping::Standard | MathMapping::KawPow => match r % 11 {
-			0 => format!("    {} = {} + {};\n", d, a, b),
-			1 => format!("    {} = {} * {};\n", d, a, b),
-			2 => format!("    {} = mul_hi({}, {});\n", d, a, b),
-			3 => format!("    {} = min({}, {});\n", d, a, b),
-			4 => format!("    {} = ROTL32({}, {} % 32);\n", d, a, b),
-			5 => format!("    {} = ROTR32({}, {} % 32);\n", d, a, b),
-			6 => format!("    {} = {} & {};\n", d, a, b),
-			7 => format!("    {} = {} | {};\n", d, a, b),
-			8 => format!("    {} = {} ^ {};\n", d, a, b),
-			9 => format!("    {} = clz({}) + clz({});\n", d, a, b),
-			_ => format!("    {} = popcount({}) + popcount({});\n", d, a, b),
-		},
-		MathMapping::Zano => match r % 11 {
-			0 => format!("    {} = clz({}) + clz({});\n", d, a, b),
-			1 => format!("    {} = popcount({}) + popcount({});\n", d, a, b),
-			2 => format!("    {} = {} + {};\n", d, a, b),
-			3 => format!("    {} = {} * {};\n", d, a, b),
-			4 => format!("    {} = mul_hi({}, {});\n", d, a, b),
-			5 => format!("    {} = min({}, {});\n", d, a, b),
-			6 => format!("    {} = ROTL32({}, {} & 31);\n", d, a, b),
-			7 => format!("    {} = ROTR32({}, {} & 31);\n", d, a, b),
-			8 => format!("    {} = {} & {};\n", d, a, b),
-			9 => format!("    {} = {} | {};\n", d, a, b),
-			_ => format!("    {} = {} ^ {};\n", d, a, b),
-		},
+fn merge_code(a: &str, b: &str, merge: Merge) -> String {
+	match merge {
+		Merge::MulAdd => format!("    {} = ({} * 33) + {};\n", a, a, b),
+		Merge::XorMul => format!("    {} = ({} ^ {}) * 33;\n", a, a, b),
+		Merge::RotlXor(n) => format!("    {} = ROTL32({}, {}) ^ {};\n", a, a, n, b),
+		Merge::RotrXor(n) => format!("    {} = ROTR32({}, {}) ^ {};\n", a, a, n, b),
 	}
 }
 
-// TODO: The existing opencl generator can validly remain as is or be updated similarly.
-// For now, keeping the existing one but patched is safer than deleting it if other code uses it.
-// However, the user request focused on rewriting the cuda code.
-// I will keep the OpenCL function signature but implement it similarly if needed, or leave the previous fix.
-// Given strict instructions, I will apply similar logic to OpenCL if possible, but prioritize CUDA.
-// For now, I'll copy the previous OpenCL function back in to avoid breaking the build, as I am replacing the whole file.
-
-pub fn generate_opencl_kernel<P: ProgPowParams>(period: u64, _height: u64) -> String {
-	// Re-using the logic for OpenCL? Ideally yes.
-	// For now, let's just use the previous implementation to pass compilation,
-	// unless the user wants OpenCL fixed too. They said "Rewrite entire cuda related code".
-	// I will return a placeholder or the old code.
-	// Actually, I can use the template approach for OpenCL too if I had an OpenCL template.
-	// I will restore the OLD OpenCL code (with my previous fixes) to ensure no regression there.
-
-	let prog_seed = period;
-	let epoch = _height / P::EPOCH_LENGTH;
-	let dag_size = progpow_base::shared::get_data_size::<P>(epoch * P::EPOCH_LENGTH);
-	let dag_elements = dag_size / 256;
-
-	let seed0 = prog_seed as u32;
-	let seed1 = (prog_seed >> 32) as u32;
-	let fnv_hash = 0x811c9dc5;
-	let mut h = fnv_hash;
-	let z = fnv1a(&mut h, seed0);
-	let w = fnv1a(&mut h, seed1);
-	let jsr = fnv1a(&mut h, seed0);
-	let jcong = fnv1a(&mut h, seed1);
-	let mut rng = Kiss99::new(z, w, jsr, jcong);
-
-	let mut mix_seq_dst = (0..PROGPOW_REGS).map(|i| i as i32).collect::<Vec<i32>>();
-	let mut mix_seq_cache = (0..PROGPOW_REGS).map(|i| i as i32).collect::<Vec<i32>>();
-	let mut mix_seq_dst_cnt = 0;
-	let mut mix_seq_cache_cnt = 0;
-
-	let is_zano = P::MATH_MAPPING == progpow_base::params::MathMapping::Zano;
-	for i in (1..PROGPOW_REGS).rev() {
-		let j = (rng.rnd(is_zano) as usize) % (i + 1);
-		mix_seq_dst.swap(i, j);
-		let j = (rng.rnd(is_zano) as usize) % (i + 1);
-		mix_seq_cache.swap(i, j);
-	}
-
-	let mut inner_code = String::new();
-	inner_code.push_str("#pragma OPENCL EXTENSION cl_khr_subgroups : enable\n");
-	inner_code.push_str("#pragma OPENCL EXTENSION cl_khr_int64_base_atomics : enable\n\n");
-	inner_code.push_str("#define ROTL32(x,n) rotate((uint)(x), (uint)(n))\n");
-	inner_code.push_str("#define ROTR32(x,n) rotate((uint)(x), (uint)(32-(n)))\n");
-	inner_code.push_str("#define mul_hi(a, b) mul_hi((uint)(a), (uint)(b))\n");
-	inner_code.push_str("#define clz(a) clz((uint)(a))\n");
-	inner_code.push_str("#define popcount(a) popcount((uint)(a))\n\n");
-
-	let _ = writeln!(
-		inner_code,
-		"#define PROGPOW_LANES           {}",
-		PROGPOW_LANES
-	);
-	let _ = writeln!(
-		inner_code,
-		"#define PROGPOW_REGS            {}",
-		PROGPOW_REGS
-	);
-	let _ = writeln!(
-		inner_code,
-		"#define PROGPOW_DAG_LOADS       {}",
-		PROGPOW_DAG_LOADS
-	);
-	let _ = writeln!(
-		inner_code,
-		"#define PROGPOW_CACHE_WORDS     {}",
-		PROGPOW_CACHE_BYTES / 4
-	);
-	let _ = writeln!(
-		inner_code,
-		"#define PROGPOW_CNT_DAG         {}",
-		PROGPOW_CNT_DAG
-	);
-	let _ = writeln!(
-		inner_code,
-		"#define PROGPOW_CNT_MATH        {}",
-		P::CNT_MATH
-	);
-	let _ = writeln!(
-		inner_code,
-		"#define PROGPOW_DAG_ELEMENTS    {}",
-		dag_elements
-	);
-	inner_code.push_str("typedef struct {uint s[PROGPOW_DAG_LOADS];} dag_t;\n\n");
-
-	inner_code.push_str("typedef struct { uint z, w, jsr, jcong; } kiss99_t;\n\n");
-
-	let kiss99_logic = if is_zano {
-		r#"
-    st->jcong = 69069 * st->jcong + 1234567;
-    st->jsr ^= (st->jsr << 13);
-    st->jsr ^= (st->jsr >> 17);
-    st->jsr ^= (st->jsr << 5);
-"#
+fn math_code(d: &str, a: &str, b: &str, math: MathOp, mapping: MathMapping) -> String {
+	// Zano's reference masks rotations where the others take the remainder.
+	let rot = if mapping == MathMapping::Zano {
+		"& 31"
 	} else {
-		r#"
-    st->jsr ^= (st->jsr << 17);
-    st->jsr ^= (st->jsr >> 13);
-    st->jsr ^= (st->jsr << 5);
-    st->jcong = 69069 * st->jcong + 1234567;
-"#
+		"% 32"
 	};
-
-	let _ = writeln!(
-		inner_code,
-		"uint kiss99(kiss99_t *st) {{
-    st->z = 36969 * (st->z & 65535) + (st->z >> 16);
-    st->w = 18000 * (st->w & 65535) + (st->w >> 16);
-    uint MWC = ((st->z << 16) + st->w);
-{}
-    return (MWC ^ st->jcong) + st->jsr;
-}}",
-		kiss99_logic
-	);
-
-	inner_code.push_str("void progPowLoop(const uint loop_cnt, uint mix[PROGPOW_REGS], __global const dag_t *g_dag, __local const uint *c_dag) {\n");
-	inner_code.push_str("    dag_t data_dag;\n    uint offset, data;\n    const uint lane_id = get_local_id(0) & (PROGPOW_LANES-1);\n");
-
-	// Global Load (OpenCL specific)
-	inner_code.push_str("    offset = sub_group_broadcast(mix[0], loop_cnt % PROGPOW_LANES);\n");
-	inner_code.push_str("    offset %= PROGPOW_DAG_ELEMENTS;\n");
-	inner_code
-		.push_str("    offset = offset * PROGPOW_LANES + (lane_id ^ loop_cnt) % PROGPOW_LANES;\n");
-	inner_code.push_str("    data_dag = g_dag[offset];\n");
-
-	// Math Generation (Identical logic, different formatting helper if needed, but C/OpenCL is close enough for math)
-	// We reuse the 'math' and 'merge' functions but ensure they output valid OpenCL.
-	// 'math' uses standard C ops which OpenCL supports. 'rotate' vs 'rotl32' macro handles difference.
-
-	let max_ops = std::cmp::max(P::CNT_CACHE, P::CNT_MATH);
-	for i in 0..max_ops {
-		if i < P::CNT_CACHE {
-			let src = format!("mix[{}]", mix_seq_cache[mix_seq_cache_cnt % PROGPOW_REGS]);
-			mix_seq_cache_cnt += 1;
-			let dest = format!("mix[{}]", mix_seq_dst[mix_seq_dst_cnt % PROGPOW_REGS]);
-			mix_seq_dst_cnt += 1;
-			let r = rng.rnd(is_zano);
-			let _ = writeln!(inner_code, "    offset = {} % PROGPOW_CACHE_WORDS;", src);
-			let _ = writeln!(inner_code, "    data = c_dag[offset];");
-			inner_code.push_str(&merge(&dest, "data", r)); // merge is safe for OpenCL (macros handle rot)
-		}
-		if i < P::CNT_MATH {
-			let src_rnd = (rng.rnd(is_zano) as usize) % ((PROGPOW_REGS - 1) * PROGPOW_REGS);
-			let src1 = src_rnd % PROGPOW_REGS;
-			let mut src2 = src_rnd / PROGPOW_REGS;
-			if src2 >= src1 {
-				src2 += 1;
-			}
-			let r1 = rng.rnd(is_zano);
-			let dest = format!("mix[{}]", mix_seq_dst[mix_seq_dst_cnt % PROGPOW_REGS]);
-			mix_seq_dst_cnt += 1;
-			let r2 = rng.rnd(is_zano);
-			inner_code.push_str(&math(
-				"data",
-				&format!("mix[{}]", src1),
-				&format!("mix[{}]", src2),
-				r1,
-				P::MATH_MAPPING,
-			));
-			inner_code.push_str(&merge(&dest, "data", r2));
-		}
-	}
-
-	// DAG Loads
-	inner_code.push_str(&merge("mix[0]", "data_dag.s[0]", rng.rnd(is_zano)));
-	for i in 1..PROGPOW_DAG_LOADS {
-		let dest = format!("mix[{}]", mix_seq_dst[mix_seq_dst_cnt % PROGPOW_REGS]);
-		mix_seq_dst_cnt += 1;
-		let r = rng.rnd(is_zano);
-		inner_code.push_str(&merge(&dest, &format!("data_dag.s[{}]", i), r));
+	match math {
+		MathOp::Add => format!("    {} = {} + {};\n", d, a, b),
+		MathOp::Mul => format!("    {} = {} * {};\n", d, a, b),
+		MathOp::MulHi => format!("    {} = mul_hi({}, {});\n", d, a, b),
+		MathOp::Min => format!("    {} = min({}, {});\n", d, a, b),
+		MathOp::Rotl => format!("    {} = ROTL32({}, {} {});\n", d, a, b, rot),
+		MathOp::Rotr => format!("    {} = ROTR32({}, {} {});\n", d, a, b, rot),
+		MathOp::And => format!("    {} = {} & {};\n", d, a, b),
+		MathOp::Or => format!("    {} = {} | {};\n", d, a, b),
+		MathOp::Xor => format!("    {} = {} ^ {};\n", d, a, b),
+		MathOp::Clz => format!("    {} = clz({}) + clz({});\n", d, a, b),
+		MathOp::Popcount => format!("    {} = popcount({}) + popcount({});\n", d, a, b),
 	}
-
-	inner_code.push_str("}\n\n");
-	let mut final_source = String::from(STATIC_OPENCL_KERNEL_SOURCE);
-
-	// Inject KAWPOW_IS_RAVENCOIN for OpenCL
-	let opencl_defines = format!(
-		"#define KAWPOW_IS_RAVENCOIN     {}\n#define XMRIG_INCLUDE_KECCAK_ROUNDS {}\n",
-		if P::HAS_RAVENCOIN_RNDC { 1 } else { 0 },
-		P::KECCAK_ROUNDS
-	);
-
-	final_source = final_source.replace(
-		"#ifndef SEARCH_RESULTS",
-		&format!("{}\n#ifndef SEARCH_RESULTS", opencl_defines),
-	);
-
-	inner_code.push_str(&final_source); // This footer is valid OpenCL
-
-	inner_code
 }
 
 // --- TEMPLATES ---
@@ -618,7 +589,7 @@ typedef struct {
 #endif
 
 #define PROGPOW_LANES           16
-// PROGPOW_REGS is injected by XMRIG_INCLUDE_DEFINES
+// PROGPOW_REGS is injected with the defines below
 #define PROGPOW_DAG_LOADS       4
 #define PROGPOW_CACHE_WORDS     4096
 #define PROGPOW_CNT_DAG         64
@@ -816,13 +787,8 @@ __device__ __forceinline__ uint32_t cuda_swab32(const uint32_t x)
 __device__ __forceinline__ void keccak_f800(uint32_t* st)
 {
     #pragma unroll
-    for (int r = 0; r < XMRIG_INCLUDE_KECCAK_ROUNDS; r++) {
+    for (int r = 0; r < XMRIG_INCLUDE_KECCAK_ROUNDS; r++)
         keccak_f800_round(st, r);
-        if (r == 0) {
-            // Forensic: Capture Round 0 state for gid 0 to verify alignment
-            for (int i = 0; i < 25; i++) st[i] = st[i]; // No-op to allow breakpoint or just tracing if needed
-        }
-    }
 }
 
 __device__ __forceinline__ uint32_t fnv1a_dev(uint32_t h, uint32_t d)
@@ -854,23 +820,25 @@ __device__ __forceinline__ void fill_mix(uint32_t* hash_seed, uint32_t lane_id,
     st.w = fnv1a_dev(st.z, hash_seed[1]);
     st.jsr = fnv1a_dev(st.w, lane_id);
     st.jcong = fnv1a_dev(st.jsr, lane_id);
-    if (lane_id == 0 && (blockIdx.x * blockDim.x + threadIdx.x) == 0) {
-        if (g_debug_trace != NULL) {
-             g_debug_trace[210] = st.z;
-             g_debug_trace[211] = st.w;
-             g_debug_trace[212] = st.jsr;
-             g_debug_trace[213] = st.jcong;
-        }
+#if PROGPOW_DEBUG_TRACE
+    if (lane_id == 0 && (blockIdx.x * blockDim.x + threadIdx.x) == 0 && g_debug_trace != NULL) {
+        g_debug_trace[210] = st.z;
+        g_debug_trace[211] = st.w;
+        g_debug_trace[212] = st.jsr;
+        g_debug_trace[213] = st.jcong;
     }
+#endif
     // Iteration 0
     mix[0] = kiss99(st);
+#if PROGPOW_DEBUG_TRACE
     if (lane_id == 0 && (blockIdx.x * blockDim.x + threadIdx.x) == 0 && g_debug_trace != NULL) {
-         g_debug_trace[220] = st.z;
-         g_debug_trace[221] = st.w;
-         g_debug_trace[222] = st.jsr;
-         g_debug_trace[223] = st.jcong;
-         g_debug_trace[224] = mix[0];
+        g_debug_trace[220] = st.z;
+        g_debug_trace[221] = st.w;
+        g_debug_trace[222] = st.jsr;
+        g_debug_trace[223] = st.jcong;
+        g_debug_trace[224] = mix[0];
     }
+#endif
     #pragma unroll
     for (int i = 1; i < PROGPOW_REGS; i++)
         mix[i] = kiss99(st);
@@ -902,8 +870,6 @@ extern "C" __global__ void progpow_search_v3(
         (uint32_t)h2_64, (uint32_t)(h2_64 >> 32),
         (uint32_t)h3_64, (uint32_t)(h3_64 >> 32)
     };
-    // const uint32_t header_hash[8] = {0, 0, 0, 0, 0, 0, 0, 0};
-    // const uint32_t* job_blob = (const uint32_t*)args.job_blob_addr; // No longer needed
 
     const bool hack_false = false;
     __shared__ uint32_t c_dag[PROGPOW_CACHE_WORDS];
@@ -925,7 +891,8 @@ extern "C" __global__ void progpow_search_v3(
     uint32_t hash_seed[2];
     uint32_t state2[16];
 
-    // Debug: Dump kernel arguments (gid 0)
+#if PROGPOW_DEBUG_TRACE
+    // Dump kernel arguments (gid 0)
     if (gid == 0 && g_debug_trace != NULL) {
         // 500: Header (h0_64) - First 8 bytes instead of pointer
         g_debug_trace[500] = (uint32_t)h0_64;
@@ -959,6 +926,7 @@ extern "C" __global__ void progpow_search_v3(
         g_debug_trace[516] = (uint32_t)dbg_val;
         g_debug_trace[517] = (uint32_t)(dbg_val >> 32);
     }
+#endif
 
     {
         // Initial state
@@ -971,10 +939,6 @@ extern "C" __global__ void progpow_search_v3(
         state[8] = (uint32_t)nonce;
         state[9] = (uint32_t)(nonce >> 32);
 
-        if (gid == 0 && g_debug_trace != NULL) {
-             // for(int i=0; i<8; i++) g_debug_trace[100+i] = state[i]; // Trace initial state (header)
-        }
-
 
 XMRIG_INCLUDE_PROGPOW_INITIAL_PADDING
 
@@ -988,28 +952,30 @@ XMRIG_INCLUDE_PROGPOW_INITIAL_PADDING
         hash_seed[0] = hash_seed_small[0];
         hash_seed[1] = hash_seed_small[1];
     }
-    if (gid == 0) {
-        if (g_debug_trace != NULL) {
-            g_debug_trace[200] = hash_seed[0];
-            g_debug_trace[201] = hash_seed[1];
-        }
+#if PROGPOW_DEBUG_TRACE
+    if (gid == 0 && g_debug_trace != NULL) {
+        g_debug_trace[200] = hash_seed[0];
+        g_debug_trace[201] = hash_seed[1];
     }
+#endif
     fill_mix(hash_seed, lane_id, mix, g_debug_trace);
 
+#if PROGPOW_DEBUG_TRACE
     if (gid == 0 && g_debug_trace != NULL) {
         // Trace Mix Init (Offset 32)
-        for(int i=0; i<8; i++) g_debug_trace[32+i] = mix[i]; // Store lane 0 mix
+        for (int i = 0; i < 8; i++) g_debug_trace[32 + i] = mix[i]; // Store lane 0 mix
     }
+#endif
 
     #pragma unroll 1
     for (uint32_t l = 0; l < PROGPOW_CNT_DAG; l++) {
         progPowLoop(l, mix, g_dag, c_dag, hack_false);
-        if (gid == 0 && l == 0) {
-             if (g_debug_trace != NULL) {
-                 // Trace Mix Loop 0 (Offset 48)
-                 for(int i=0; i<8; i++) g_debug_trace[48+i] = mix[i];
-             }
+#if PROGPOW_DEBUG_TRACE
+        if (gid == 0 && l == 0 && g_debug_trace != NULL) {
+            // Trace Mix Loop 0 (Offset 48)
+            for (int i = 0; i < 8; i++) g_debug_trace[48 + i] = mix[i];
         }
+#endif
     }
 
 
@@ -1081,15 +1047,15 @@ XMRIG_INCLUDE_PROGPOW_INITIAL_PADDING
         // as a big-endian integer to match CPU verifier.
         result = ((uint64_t)cuda_swab32(final_state[0]) << 32) | (uint64_t)cuda_swab32(final_state[1]);
 
+#if PROGPOW_DEBUG_TRACE
         if (gid == 0 && g_debug_trace != NULL) {
-             // Optional trace logic can stay if guarded by explicit non-null check, but user asked to chill output.
-             // We'll keep the g_debug_trace writes as they are silent, but remove printf.
-             for(int i=0; i<25; i++) g_debug_trace[64+i] = final_state[i];
-             g_debug_trace[90] = (uint32_t)(result >> 32);
-             g_debug_trace[91] = (uint32_t)result;
-             g_debug_trace[92] = (uint32_t)(target >> 32);
-             g_debug_trace[93] = (uint32_t)target;
-         }
+            for (int i = 0; i < 25; i++) g_debug_trace[64 + i] = final_state[i];
+            g_debug_trace[90] = (uint32_t)(result >> 32);
+            g_debug_trace[91] = (uint32_t)result;
+            g_debug_trace[92] = (uint32_t)(target >> 32);
+            g_debug_trace[93] = (uint32_t)target;
+        }
+#endif
     }
 
      if (u64_le(result, target) && result > 0 && lane_id == 0)
@@ -1106,20 +1072,79 @@ XMRIG_INCLUDE_PROGPOW_INITIAL_PADDING
 }
 "#;
 
-const STATIC_OPENCL_KERNEL_SOURCE: &str = r#"
+const PROGPOW_OPENCL_KERNEL_TEMPLATE: &str = r#"
 #ifndef SEARCH_RESULTS
-#define SEARCH_RESULTS 4
+#define SEARCH_RESULTS 16
+#endif
+
+#ifndef GROUP_SIZE
+#define GROUP_SIZE 256
 #endif
 
+typedef struct {
+    ulong nonce;
+    uint mix[8];
+    uint debug[8];
+} search_result;
+
 typedef struct {
     uint count;
-    struct {
-        ulong nonce;
-        uint mix[8];
-        uint debug[8];
-    } result[SEARCH_RESULTS];
+    uint _padding; // Explicitly match Rust struct alignment
+    search_result result[SEARCH_RESULTS];
 } search_results;
 
+#define ROTL32(x,n) rotate((uint)(x), (uint)((n) % 32))
+#define ROTR32(x,n) rotate((uint)(x), (uint)(32 - ((n) % 32)))
+#define __umulhi(a, b) mul_hi((uint)(a), (uint)(b))
+
+#define PROGPOW_LANES           16
+// PROGPOW_REGS is injected with the defines below
+#define PROGPOW_DAG_LOADS       4
+#define PROGPOW_CACHE_WORDS     4096
+#define PROGPOW_CNT_DAG         64
+XMRIG_INCLUDE_DEFINES
+
+#define HASHES_PER_GROUP (GROUP_SIZE / PROGPOW_LANES)
+
+typedef struct {uint s[PROGPOW_DAG_LOADS];} dag_t;
+
+// Lane exchange through local memory, the OpenCL counterpart of CUDA's __shfl_sync.
+// Must be reached by every work-item of the group.
+inline uint shfl(__local uint* share, const uint x, const uint src_lane, const uint lane_id)
+{
+    barrier(CLK_LOCAL_MEM_FENCE);
+    if (lane_id == src_lane)
+        *share = x;
+    barrier(CLK_LOCAL_MEM_FENCE);
+    return *share;
+}
+
+#define SHFL(x, y, z) shfl(share, (x), (y), lane_id)
+
+void progPowLoop(const uint loop, uint mix[PROGPOW_REGS], __global const dag_t *g_dag, __local const uint *c_dag, __local uint *share, const uint lane_id)
+{
+    dag_t data_dag;
+    uint offset, data;
+
+    // global load
+    offset = SHFL(mix[0], loop % PROGPOW_LANES, PROGPOW_LANES);
+
+    // OFFSET MOD LOGIC
+    XMRIG_INCLUDE_OFFSET_MOD_DAG_ELEMENTS
+
+    offset = offset * PROGPOW_LANES + (lane_id ^ loop) % PROGPOW_LANES;
+    data_dag = g_dag[offset];
+
+    // Random math and cache operations
+    XMRIG_INCLUDE_PROGPOW_RANDOM_MATH
+
+    // DAG data loads (merge data_dag into mix)
+    XMRIG_INCLUDE_PROGPOW_DATA_LOADS
+}
+
+#define FNV_PRIME 0x1000193
+#define FNV_OFFSET_BASIS 0x811c9dc5
+
 typedef struct {
     uint uint32s[8];
 } hash32_t;
@@ -1131,23 +1156,35 @@ __constant uint keccakf_rndc[24] = {
     0x0000800a, 0x8000000a, 0x80008081, 0x00008080, 0x80000001, 0x80008008
 };
 
-__constant uint ravencoin_kawpow[15] = {
-    0x00000072, 0x00000041, 0x00000056, 0x00000045, 0x0000004E, // RAVEN
-    0x00000043, 0x0000004F, 0x00000049, 0x0000004E,             // COIN
-    0x0000004B, 0x00000041, 0x00000057,                         // KAW
-    0x00000050, 0x0000004F, 0x00000057                          // POW
+__constant uint ravencoin_rndc[15] = {
+    0x00000072, 0x00000041, 0x00000056, 0x00000045, // rAVE
+    0x0000004E, 0x00000043, 0x0000004F, 0x00000049, // NCOI
+    0x0000004E, 0x0000004B, 0x00000041, 0x00000057, // NKAW
+    0x00000050, 0x0000004F, 0x00000057              // POW
+};
+
+__constant uint meowcoin_rndc[15] = {
+    0x0000004D, 0x00000045, 0x0000004F, 0x00000057, 0x00000043, 0x0000004F, 0x00000049, 0x0000004E,
+    0x0000004D, 0x00000045, 0x0000004F, 0x00000057, 0x00000050, 0x0000004F, 0x00000057
+};
+
+__constant uint evrmore_rndc[15] = {
+    0x00000065, 0x00000076, 0x00000072, 0x0000006D, 0x0000006F, 0x00000072, 0x00000065, 0x00000000,
+    0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000
+};
+
+__constant uint keccakf_rotc[24] = {
+    1,  3,  6,  10, 15, 21, 28, 36, 45, 55, 2,  14,
+    27, 41, 56, 8,  25, 43, 62, 18, 39, 61, 20, 44
+};
+
+__constant uint keccakf_piln[24] = {
+    10, 7,  11, 17, 18, 3, 5,  16, 8,  21, 24, 4,
+    15, 23, 19, 13, 12, 2, 20, 14, 22, 9,  6,  1
 };
 
 inline void keccak_f800_round(uint st[25], const int r)
 {
-    const uint keccakf_rotc[24] = {
-        1,  3,  6,  10, 15, 21, 28, 4,  13, 23, 2,  14,
-        27, 9,  24, 8,  25, 11, 30, 18, 7,  29, 20, 12
-    };
-    const uint keccakf_piln[24] = {
-        10, 7,  11, 17, 18, 3, 5,  16, 8,  21, 24, 4,
-        15, 23, 19, 13, 12, 2, 20, 14, 22, 9,  6,  1
-    };
     uint t, bc[5];
     // Theta
     for (int i = 0; i < 5; i++)
@@ -1158,6 +1195,7 @@ inline void keccak_f800_round(uint st[25], const int r)
         for (uint j = 0; j < 25; j += 5)
             st[j + i] ^= t;
     }
+
     // Rho Pi
     t = st[1];
     for (int i = 0; i < 24; i++) {
@@ -1166,6 +1204,7 @@ inline void keccak_f800_round(uint st[25], const int r)
         st[j] = ROTL32(t, keccakf_rotc[i]);
         t = bc[0];
     }
+
     // Chi
     for (int j = 0; j < 25; j += 5) {
         for (int i = 0; i < 5; i++)
@@ -1173,194 +1212,1158 @@ inline void keccak_f800_round(uint st[25], const int r)
         for (int i = 0; i < 5; i++)
             st[j + i] ^= (~bc[(i + 1) % 5]) & bc[(i + 2) % 5];
     }
+
     // Iota
     st[0] ^= keccakf_rndc[r];
 }
 
+inline uint cuda_swab32(const uint x)
+{
+    return ((x & 0x000000FF) << 24) |
+           ((x & 0x0000FF00) << 8)  |
+           ((x & 0x00FF0000) >> 8)  |
+           ((x & 0xFF000000) >> 24);
+}
+
 inline void keccak_f800(uint st[25])
 {
-    for (int i = 0; i < XMRIG_INCLUDE_KECCAK_ROUNDS; i++)
-        keccak_f800_round(st, i);
+    for (int r = 0; r < XMRIG_INCLUDE_KECCAK_ROUNDS; r++)
+        keccak_f800_round(st, r);
 }
 
-#define fnv1(h, d) (h = (uint(h) * uint(0x1000193)) ^ uint(d))
-#define fnv1a(h, d) (h = (uint(h) ^ uint(d)) * uint(0x1000193))
+inline uint fnv1a_dev(uint h, uint d)
+{
+    return (h ^ d) * FNV_PRIME;
+}
 
 typedef struct {
     uint z, w, jsr, jcong;
 } kiss99_t;
 
-inline uint kiss99(kiss99_t *st) {
-    st->z = 36969 * (st->z & 65535) + (st->z >> 16);
-    st->w = 18000 * (st->w & 65535) + (st->w >> 16);
-    uint mwc = (st->z << 16) + st->w;
-    st->jsr ^= st->jsr << 17;
-    st->jsr ^= st->jsr >> 13;
-    st->jsr ^= st->jsr << 5;
-    st->jcong = 69069 * st->jcong + 1234567;
-    return (mwc ^ st->jcong) + st->jsr;
+inline uint kiss99(kiss99_t *p)
+{
+    kiss99_t st = *p;
+    st.z = 36969 * (st.z & 65535) + (st.z >> 16);
+    st.w = 18000 * (st.w & 65535) + (st.w >> 16);
+    uint MWC = ((st.z << 16) + st.w);
+
+XMRIG_INCLUDE_KISS99_LOGIC
+
+    *p = st;
+    return ((MWC ^ st.jcong) + st.jsr);
 }
 
-void fill_mix(uint hash_seed[2], uint lane_id, uint mix[PROGPOW_REGS])
+inline void fill_mix(uint* hash_seed, uint lane_id, uint* mix)
 {
     kiss99_t st;
-    st.z = (0x811c9dc5u ^ hash_seed[0]) * 0x1000193u;
-    st.w = (st.z ^ hash_seed[1]) * 0x1000193u;
-    st.jsr = (st.w ^ lane_id) * 0x1000193u;
-    st.jcong = (st.jsr ^ lane_id) * 0x1000193u;
+    st.z = fnv1a_dev(FNV_OFFSET_BASIS, hash_seed[0]);
+    st.w = fnv1a_dev(st.z, hash_seed[1]);
+    st.jsr = fnv1a_dev(st.w, lane_id);
+    st.jcong = fnv1a_dev(st.jsr, lane_id);
 
     for (int i = 0; i < PROGPOW_REGS; i++)
         mix[i] = kiss99(&st);
 }
 
+__attribute__((reqd_work_group_size(GROUP_SIZE, 1, 1)))
 __kernel void progpow_search(
     const ulong start_nonce,
     const ulong target,
-    __global const hash32_t* header,
-    __global const dag_t *g_dag,
-    __global const uint *c_cache,
+    const ulong h0_64, const ulong h1_64, const ulong h2_64, const ulong h3_64,
+    __global const dag_t* g_dag,
+    __global const uint* c_cache,
     __global volatile search_results* g_output,
     __global uint* g_debug_trace
     )
 {
+    // Unpack 4x u64 into 8x u32
+    const uint header_hash[8] = {
+        (uint)h0_64, (uint)(h0_64 >> 32),
+        (uint)h1_64, (uint)(h1_64 >> 32),
+        (uint)h2_64, (uint)(h2_64 >> 32),
+        (uint)h3_64, (uint)(h3_64 >> 32)
+    };
+
     __local uint c_dag[PROGPOW_CACHE_WORDS];
-    uint const gid = get_global_id(0);
-    ulong const nonce = start_nonce + (gid / PROGPOW_LANES);
+    __local uint g_share[HASHES_PER_GROUP];
 
-    uint const lane_id = get_local_id(0) & (PROGPOW_LANES - 1);
+    const uint gid = get_global_id(0);
+    const uint lid = get_local_id(0);
+    const uint lane_id = lid & (PROGPOW_LANES - 1);
+    __local uint* share = &g_share[lid / PROGPOW_LANES];
+    const ulong nonce = start_nonce + gid / PROGPOW_LANES;
 
-    // Load light cache into local memory
-    for (uint word = get_local_id(0); word < PROGPOW_CACHE_WORDS; word += get_local_size(0))
-    {
+    // Load Cache
+    for (uint word = lid; word < PROGPOW_CACHE_WORDS; word += GROUP_SIZE)
         c_dag[word] = c_cache[word];
-    }
     barrier(CLK_LOCAL_MEM_FENCE);
 
+    uint mix[PROGPOW_REGS];
     uint hash_seed[2];
-    hash32_t digest;
-    uint state2[8];
+    uint state2[16];
 
     {
+        // Initial state
         uint state[25];
-        for(int i=0; i<25; i++) state[i] = 0;
+        for (int i = 0; i < 25; i++) state[i] = 0;
 
         for (int i = 0; i < 8; i++)
-            state[i] = header->uint32s[i];
-
-        if (gid == 0 && g_debug_trace != NULL) {
-            // Write Initial State (Header) to debug buffer at offset 64
-            for (int i = 0; i < 8; i++) g_debug_trace[64 + i] = state[i];
-
-            // Dump Arguments to debug buffer
-            // 80: Header Ptr
-            uint64_t v;
-            v = (uint64_t)header; g_debug_trace[80] = (uint32_t)v; g_debug_trace[81] = (uint32_t)(v>>32);
-            // 82: DAG Ptr
-            v = (uint64_t)g_dag; g_debug_trace[82] = (uint32_t)v; g_debug_trace[83] = (uint32_t)(v>>32);
-            // 84: Cache Ptr
-            v = (uint64_t)c_cache; g_debug_trace[84] = (uint32_t)v; g_debug_trace[85] = (uint32_t)(v>>32);
-            // 86: Start Nonce
-            v = start_nonce; g_debug_trace[86] = (uint32_t)v; g_debug_trace[87] = (uint32_t)(v>>32);
-            // 88: Target
-            v = target; g_debug_trace[88] = (uint32_t)v; g_debug_trace[89] = (uint32_t)(v>>32);
-
-
-        }
+            state[i] = header_hash[i];
 
         state[8] = (uint)nonce;
         state[9] = (uint)(nonce >> 32);
 
-        XMRIG_INCLUDE_PROGPOW_INITIAL_PADDING
+XMRIG_INCLUDE_PROGPOW_INITIAL_PADDING
 
         keccak_f800(state);
 
-        for (int i = 0; i < 8; i++)
+        for (int i = 0; i < 16; i++)
             state2[i] = state[i];
-    }
 
-    uint hash_seed[2];
-    hash_seed[0] = state2[0];
-    hash_seed[1] = state2[1];
-    uint mix[PROGPOW_REGS];
+        uint hash_seed_small[2];
+        XMRIG_INCLUDE_HASH_SEED_EXTRACT
+        hash_seed[0] = hash_seed_small[0];
+        hash_seed[1] = hash_seed_small[1];
+    }
+#if PROGPOW_DEBUG_TRACE
+    if (gid == 0 && g_debug_trace != 0) {
+        g_debug_trace[200] = hash_seed[0];
+        g_debug_trace[201] = hash_seed[1];
+    }
+#endif
     fill_mix(hash_seed, lane_id, mix);
 
+#if PROGPOW_DEBUG_TRACE
+    if (gid == 0 && g_debug_trace != 0) {
+        // Trace Mix Init (Offset 32)
+        for (int i = 0; i < 8; i++) g_debug_trace[32 + i] = mix[i];
+    }
+#endif
+
     #pragma unroll 1
-    for (uint l = 0; l < PROGPOW_CNT_DAG; l++)
-        progPowLoop(l, mix, g_dag, c_dag);
+    for (uint l = 0; l < PROGPOW_CNT_DAG; l++) {
+        progPowLoop(l, mix, g_dag, c_dag, share, lane_id);
+#if PROGPOW_DEBUG_TRACE
+        if (gid == 0 && l == 0 && g_debug_trace != 0) {
+            // Trace Mix Loop 0 (Offset 48)
+            for (int i = 0; i < 8; i++) g_debug_trace[48 + i] = mix[i];
+        }
+#endif
+    }
 
-    uint digest_lane = 0x811c9dc5u;
+    // Reduction
+    uint digest_lane = FNV_OFFSET_BASIS;
     for (int i = 0; i < PROGPOW_REGS; i++)
-        digest_lane = (digest_lane ^ mix[i]) * 0x1000193u;
+        digest_lane = fnv1a_dev(digest_lane, mix[i]);
 
-    hash32_t digest_temp;
+    hash32_t digest;
     for (int i = 0; i < 8; i++)
-        digest_temp.uint32s[i] = 0x811c9dc5;
-
-    for (int i = 0; i < PROGPOW_LANES; i += 8)
-        for (int j = 0; j < 8; j++) {
-            uint val = sub_group_broadcast(digest_lane, i + j);
-            digest_temp.uint32s[j] = (digest_temp.uint32s[j] ^ val) * 0x1000193u;
-        }
-
-    digest = digest_temp;
-
     {
-        uint state[25];
-        // 1. Initial State (256 bits)
-        for(int i=0; i<8; i++) state[i] = state2[i];
-
-        // 2. Mix Hash (256 bits)
-        for(int i=0; i<8; i++) state[8+i] = digest.uint32s[i];
-
-        // 3. Personalization
-        #if KAWPOW_IS_RAVENCOIN
-            for(int i=0; i<9; i++) state[16+i] = ravencoin_rndc[i];
-        #elif KAWPOW_IS_MEOWCOIN
-            for(int i=0; i<9; i++) state[16+i] = meowcoin_rndc[i];
-        #elif KAWPOW_IS_EVRMORE
-            for(int i=0; i<9; i++) state[16+i] = evrmore_rndc[i];
-        #else
-            // Zero padding or other coins
-            for(int i=16; i<25; i++) state[i] = 0;
-        if (gid == 0) {
-            printf("GPU Final Input st[0]: %08x st[8]: %08x st[10]: %08x\n", state[0], state[8], state[10]);
-        }
-
-        keccak_f800(state);
-
-        if (gid == 0) {
-            printf("GPU Final Output st[0]: %08x\n", state[0]);
-        }s
-
-        // Result Construction (Big Endian for Target Check)
-        // Swap bytes of first two words to form Big Endian u64
-        // Logic: Low Address (Word 0) is Higher Significance in Target?
-        // Usually Target is Big Endian Hex String.
-        // HASH: [00 00 00 02 ...] (LE Memory) -> Word 0 = 0x02000000.
-        // Swap(Word 0) = 0x00000002.
-        // We want Swap(Word 0) << 32 | Swap(Word 1).
-
-        uint t0 = ((state[0] >> 24) & 0xff) | ((state[0] >> 8) & 0xff00) | ((state[0] << 8) & 0xff0000) | ((state[0] << 24) & 0xff000000);
-        uint t1 = ((state[1] >> 24) & 0xff) | ((state[1] >> 8) & 0xff00) | ((state[1] << 8) & 0xff0000) | ((state[1] << 24) & 0xff000000);
-
-        result = ((ulong)t0 << 32) | t1;
-
-        // Debugging Final State if trace enabled (very expensive, only for single thread?)
-        // if (gid == 0 && g_debug_trace != NULL) {
-        //    for(int i=0; i<8; i++) g_debug_trace[96+i] = state[i];
-        // }
+        uint res = FNV_OFFSET_BASIS;
+        res = fnv1a_dev(res, SHFL(digest_lane, i, PROGPOW_LANES));
+        res = fnv1a_dev(res, SHFL(digest_lane, i + 8, PROGPOW_LANES));
+        digest.uint32s[i] = res;
     }
 
-    if (result <= target)
+    ulong result;
     {
-        uint32_t index = atomicAdd((uint32_t*)&g_output->count, 1);
+        uint final_state[25];
+        for (int i = 0; i < 25; i++) final_state[i] = 0;
+
+#if KAWPOW_IS_RAVENCOIN
+        for (int i = 0; i < 8; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[8 + i] = digest.uint32s[i];
+        for (int i = 0; i < 9; i++) final_state[16 + i] = ravencoin_rndc[i];
+#elif KAWPOW_IS_MEOWCOIN
+        for (int i = 0; i < 8; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[8 + i] = digest.uint32s[i];
+        for (int i = 0; i < 9; i++) final_state[16 + i] = meowcoin_rndc[i];
+#elif KAWPOW_IS_EVRMORE
+        for (int i = 0; i < 8; i++) final_state[i] = header_hash[i];
+        final_state[8] = (uint)nonce;
+        final_state[9] = (uint)(nonce >> 32);
+        for (int i = 0; i < 8; i++) final_state[10 + i] = digest.uint32s[i];
+        for (int i = 0; i < 7; i++) final_state[18 + i] = evrmore_rndc[i];
+#elif PROGPOW_IS_FIRO
+        for (int i = 0; i < 16; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[16 + i] = digest.uint32s[i];
+        final_state[24] = 0x80000001;
+#else
+        // Standard ProgPow / Zano / Epic / Sero: header + nonce/seed + mix
+        for (int i = 0; i < 8; i++) final_state[i] = header_hash[i];
+#if PROGPOW_IS_ZANO
+        final_state[8] = cuda_swab32(state2[1]);
+        final_state[9] = cuda_swab32(state2[0]);
+#else
+        final_state[8] = state2[0];
+        final_state[9] = state2[1];
+#endif
+        for (int i = 10; i < 18; i++) final_state[i] = digest.uint32s[i - 10];
+
+#if PROGPOW_HAS_FINAL_PADDING
+        final_state[18] = 0x00000001;
+        final_state[24] = 0x80008081;
+#endif
+#endif
+        keccak_f800(final_state);
+        // The 64-bit result for target comparison is the first 8 bytes of the hash
+        // as a big-endian integer to match CPU verifier.
+        result = ((ulong)cuda_swab32(final_state[0]) << 32) | (ulong)cuda_swab32(final_state[1]);
+
+#if PROGPOW_DEBUG_TRACE
+        if (gid == 0 && g_debug_trace != 0) {
+            for (int i = 0; i < 25; i++) g_debug_trace[64 + i] = final_state[i];
+            g_debug_trace[90] = (uint)(result >> 32);
+            g_debug_trace[91] = (uint)result;
+            g_debug_trace[92] = (uint)(target >> 32);
+            g_debug_trace[93] = (uint)target;
+        }
+#endif
+    }
+
+    if (result <= target && result > 0 && lane_id == 0)
+    {
+        uint index = atomic_inc(&g_output->count);
+        if (index < SEARCH_RESULTS)
+        {
+            g_output->result[index].nonce = nonce;
+            for (int i = 0; i < 8; i++) g_output->result[index].mix[i] = digest.uint32s[i];
+            for (int i = 0; i < 8; i++) g_output->result[index].debug[i] = state2[i];
+        }
+    }
+}
+"#;
+
+const PROGPOW_METAL_KERNEL_TEMPLATE: &str = r#"
+#include <metal_stdlib>
+using namespace metal;
+
+#ifndef SEARCH_RESULTS
+#define SEARCH_RESULTS 16
+#endif
+
+#ifndef GROUP_SIZE
+#define GROUP_SIZE 256
+#endif
+
+typedef struct {
+    ulong nonce;
+    uint mix[8];
+    uint debug[8];
+} search_result;
+
+typedef struct {
+    atomic_uint count;
+    uint _padding; // Explicitly match Rust struct alignment
+    search_result result[SEARCH_RESULTS];
+} search_results;
+
+#define ROTL32(x,n) rotate((uint)(x), (uint)((n) % 32))
+#define ROTR32(x,n) rotate((uint)(x), (uint)(32 - ((n) % 32)))
+#define mul_hi(a, b) mulhi((uint)(a), (uint)(b))
+#define __umulhi(a, b) mulhi((uint)(a), (uint)(b))
+
+#define PROGPOW_LANES           16
+// PROGPOW_REGS is injected with the defines below
+#define PROGPOW_DAG_LOADS       4
+#define PROGPOW_CACHE_WORDS     4096
+#define PROGPOW_CNT_DAG         64
+XMRIG_INCLUDE_DEFINES
+
+#define HASHES_PER_GROUP (GROUP_SIZE / PROGPOW_LANES)
+
+typedef struct {uint s[PROGPOW_DAG_LOADS];} dag_t;
+
+// Lane exchange through threadgroup memory, as in the OpenCL kernel. Must be reached by
+// every thread of the group.
+inline uint shfl(threadgroup uint* share, const uint x, const uint src_lane, const uint lane_id)
+{
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+    if (lane_id == src_lane)
+        *share = x;
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+    return *share;
+}
+
+#define SHFL(x, y, z) shfl(share, (x), (y), lane_id)
+
+void progPowLoop(const uint loop, thread uint* mix, device const dag_t *g_dag, threadgroup const uint *c_dag, threadgroup uint *share, const uint lane_id)
+{
+    dag_t data_dag;
+    uint offset, data;
+
+    // global load
+    offset = SHFL(mix[0], loop % PROGPOW_LANES, PROGPOW_LANES);
+
+    // OFFSET MOD LOGIC
+    XMRIG_INCLUDE_OFFSET_MOD_DAG_ELEMENTS
+
+    offset = offset * PROGPOW_LANES + (lane_id ^ loop) % PROGPOW_LANES;
+    data_dag = g_dag[offset];
+
+    // Random math and cache operations
+    XMRIG_INCLUDE_PROGPOW_RANDOM_MATH
+
+    // DAG data loads (merge data_dag into mix)
+    XMRIG_INCLUDE_PROGPOW_DATA_LOADS
+}
+
+#define FNV_PRIME 0x1000193
+#define FNV_OFFSET_BASIS 0x811c9dc5
+
+typedef struct {
+    uint uint32s[8];
+} hash32_t;
+
+constant uint keccakf_rndc[24] = {
+    0x00000001, 0x00008082, 0x0000808a, 0x80008000, 0x0000808b, 0x80000001,
+    0x80008081, 0x00008009, 0x0000008a, 0x00000088, 0x80008009, 0x8000000a,
+    0x8000808b, 0x0000008b, 0x00008089, 0x00008003, 0x00008002, 0x00000080,
+    0x0000800a, 0x8000000a, 0x80008081, 0x00008080, 0x80000001, 0x80008008
+};
+
+constant uint ravencoin_rndc[15] = {
+    0x00000072, 0x00000041, 0x00000056, 0x00000045, // rAVE
+    0x0000004E, 0x00000043, 0x0000004F, 0x00000049, // NCOI
+    0x0000004E, 0x0000004B, 0x00000041, 0x00000057, // NKAW
+    0x00000050, 0x0000004F, 0x00000057              // POW
+};
+
+constant uint meowcoin_rndc[15] = {
+    0x0000004D, 0x00000045, 0x0000004F, 0x00000057, 0x00000043, 0x0000004F, 0x00000049, 0x0000004E,
+    0x0000004D, 0x00000045, 0x0000004F, 0x00000057, 0x00000050, 0x0000004F, 0x00000057
+};
+
+constant uint evrmore_rndc[15] = {
+    0x00000065, 0x00000076, 0x00000072, 0x0000006D, 0x0000006F, 0x00000072, 0x00000065, 0x00000000,
+    0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000, 0x00000000
+};
+
+constant uint keccakf_rotc[24] = {
+    1,  3,  6,  10, 15, 21, 28, 36, 45, 55, 2,  14,
+    27, 41, 56, 8,  25, 43, 62, 18, 39, 61, 20, 44
+};
+
+constant uint keccakf_piln[24] = {
+    10, 7,  11, 17, 18, 3, 5,  16, 8,  21, 24, 4,
+    15, 23, 19, 13, 12, 2, 20, 14, 22, 9,  6,  1
+};
+
+inline void keccak_f800_round(thread uint* st, const int r)
+{
+    uint t, bc[5];
+    // Theta
+    for (int i = 0; i < 5; i++)
+        bc[i] = st[i] ^ st[i + 5] ^ st[i + 10] ^ st[i + 15] ^ st[i + 20];
+
+    for (int i = 0; i < 5; i++) {
+        t = bc[(i + 4) % 5] ^ ROTL32(bc[(i + 1) % 5], 1);
+        for (uint j = 0; j < 25; j += 5)
+            st[j + i] ^= t;
+    }
+
+    // Rho Pi
+    t = st[1];
+    for (int i = 0; i < 24; i++) {
+        uint j = keccakf_piln[i];
+        bc[0] = st[j];
+        st[j] = ROTL32(t, keccakf_rotc[i]);
+        t = bc[0];
+    }
+
+    // Chi
+    for (int j = 0; j < 25; j += 5) {
+        for (int i = 0; i < 5; i++)
+            bc[i] = st[j + i];
+        for (int i = 0; i < 5; i++)
+            st[j + i] ^= (~bc[(i + 1) % 5]) & bc[(i + 2) % 5];
+    }
+
+    // Iota
+    st[0] ^= keccakf_rndc[r];
+}
+
+inline uint cuda_swab32(const uint x)
+{
+    return ((x & 0x000000FF) << 24) |
+           ((x & 0x0000FF00) << 8)  |
+           ((x & 0x00FF0000) >> 8)  |
+           ((x & 0xFF000000) >> 24);
+}
+
+inline void keccak_f800(thread uint* st)
+{
+    for (int r = 0; r < XMRIG_INCLUDE_KECCAK_ROUNDS; r++)
+        keccak_f800_round(st, r);
+}
+
+inline uint fnv1a_dev(uint h, uint d)
+{
+    return (h ^ d) * FNV_PRIME;
+}
+
+typedef struct {
+    uint z, w, jsr, jcong;
+} kiss99_t;
+
+inline uint kiss99(thread kiss99_t *p)
+{
+    kiss99_t st = *p;
+    st.z = 36969 * (st.z & 65535) + (st.z >> 16);
+    st.w = 18000 * (st.w & 65535) + (st.w >> 16);
+    uint MWC = ((st.z << 16) + st.w);
+
+XMRIG_INCLUDE_KISS99_LOGIC
+
+    *p = st;
+    return ((MWC ^ st.jcong) + st.jsr);
+}
+
+inline void fill_mix(thread uint* hash_seed, uint lane_id, thread uint* mix)
+{
+    kiss99_t st;
+    st.z = fnv1a_dev(FNV_OFFSET_BASIS, hash_seed[0]);
+    st.w = fnv1a_dev(st.z, hash_seed[1]);
+    st.jsr = fnv1a_dev(st.w, lane_id);
+    st.jcong = fnv1a_dev(st.jsr, lane_id);
+
+    for (int i = 0; i < PROGPOW_REGS; i++)
+        mix[i] = kiss99(&st);
+}
+
+kernel void progpow_search(
+    constant ulong& start_nonce [[buffer(0)]],
+    constant ulong& target [[buffer(1)]],
+    constant ulong& h0_64 [[buffer(2)]], constant ulong& h1_64 [[buffer(3)]],
+    constant ulong& h2_64 [[buffer(4)]], constant ulong& h3_64 [[buffer(5)]],
+    device const dag_t* g_dag [[buffer(6)]],
+    device const uint* c_cache [[buffer(7)]],
+    device search_results* g_output [[buffer(8)]],
+    device uint* g_debug_trace [[buffer(9)]],
+    const uint gid [[thread_position_in_grid]],
+    const uint lid [[thread_position_in_threadgroup]]
+    )
+{
+    // Unpack 4x u64 into 8x u32
+    const uint header_hash[8] = {
+        (uint)h0_64, (uint)(h0_64 >> 32),
+        (uint)h1_64, (uint)(h1_64 >> 32),
+        (uint)h2_64, (uint)(h2_64 >> 32),
+        (uint)h3_64, (uint)(h3_64 >> 32)
+    };
+
+    threadgroup uint c_dag[PROGPOW_CACHE_WORDS];
+    threadgroup uint g_share[HASHES_PER_GROUP];
+
+    const uint lane_id = lid & (PROGPOW_LANES - 1);
+    threadgroup uint* share = &g_share[lid / PROGPOW_LANES];
+    const ulong nonce = start_nonce + gid / PROGPOW_LANES;
+
+    // Load Cache
+    for (uint word = lid; word < PROGPOW_CACHE_WORDS; word += GROUP_SIZE)
+        c_dag[word] = c_cache[word];
+    threadgroup_barrier(mem_flags::mem_threadgroup);
+
+    uint mix[PROGPOW_REGS];
+    uint hash_seed[2];
+    uint state2[16];
+
+    {
+        // Initial state
+        uint state[25];
+        for (int i = 0; i < 25; i++) state[i] = 0;
+
+        for (int i = 0; i < 8; i++)
+            state[i] = header_hash[i];
+
+        state[8] = (uint)nonce;
+        state[9] = (uint)(nonce >> 32);
+
+XMRIG_INCLUDE_PROGPOW_INITIAL_PADDING
+
+        keccak_f800(state);
+
+        for (int i = 0; i < 16; i++)
+            state2[i] = state[i];
+
+        uint hash_seed_small[2];
+        XMRIG_INCLUDE_HASH_SEED_EXTRACT
+        hash_seed[0] = hash_seed_small[0];
+        hash_seed[1] = hash_seed_small[1];
+    }
+#if PROGPOW_DEBUG_TRACE
+    if (gid == 0 && g_debug_trace != 0) {
+        g_debug_trace[200] = hash_seed[0];
+        g_debug_trace[201] = hash_seed[1];
+    }
+#endif
+    fill_mix(hash_seed, lane_id, mix);
+
+#if PROGPOW_DEBUG_TRACE
+    if (gid == 0 && g_debug_trace != 0) {
+        // Trace Mix Init (Offset 32)
+        for (int i = 0; i < 8; i++) g_debug_trace[32 + i] = mix[i];
+    }
+#endif
+
+    #pragma unroll 1
+    for (uint l = 0; l < PROGPOW_CNT_DAG; l++) {
+        progPowLoop(l, mix, g_dag, c_dag, share, lane_id);
+#if PROGPOW_DEBUG_TRACE
+        if (gid == 0 && l == 0 && g_debug_trace != 0) {
+            // Trace Mix Loop 0 (Offset 48)
+            for (int i = 0; i < 8; i++) g_debug_trace[48 + i] = mix[i];
+        }
+#endif
+    }
+
+    // Reduction
+    uint digest_lane = FNV_OFFSET_BASIS;
+    for (int i = 0; i < PROGPOW_REGS; i++)
+        digest_lane = fnv1a_dev(digest_lane, mix[i]);
+
+    hash32_t digest;
+    for (int i = 0; i < 8; i++)
+    {
+        uint res = FNV_OFFSET_BASIS;
+        res = fnv1a_dev(res, SHFL(digest_lane, i, PROGPOW_LANES));
+        res = fnv1a_dev(res, SHFL(digest_lane, i + 8, PROGPOW_LANES));
+        digest.uint32s[i] = res;
+    }
+
+    ulong result;
+    {
+        uint final_state[25];
+        for (int i = 0; i < 25; i++) final_state[i] = 0;
+
+#if KAWPOW_IS_RAVENCOIN
+        for (int i = 0; i < 8; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[8 + i] = digest.uint32s[i];
+        for (int i = 0; i < 9; i++) final_state[16 + i] = ravencoin_rndc[i];
+#elif KAWPOW_IS_MEOWCOIN
+        for (int i = 0; i < 8; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[8 + i] = digest.uint32s[i];
+        for (int i = 0; i < 9; i++) final_state[16 + i] = meowcoin_rndc[i];
+#elif KAWPOW_IS_EVRMORE
+        for (int i = 0; i < 8; i++) final_state[i] = header_hash[i];
+        final_state[8] = (uint)nonce;
+        final_state[9] = (uint)(nonce >> 32);
+        for (int i = 0; i < 8; i++) final_state[10 + i] = digest.uint32s[i];
+        for (int i = 0; i < 7; i++) final_state[18 + i] = evrmore_rndc[i];
+#elif PROGPOW_IS_FIRO
+        for (int i = 0; i < 16; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[16 + i] = digest.uint32s[i];
+        final_state[24] = 0x80000001;
+#else
+        // Standard ProgPow / Zano / Epic / Sero: header + nonce/seed + mix
+        for (int i = 0; i < 8; i++) final_state[i] = header_hash[i];
+#if PROGPOW_IS_ZANO
+        final_state[8] = cuda_swab32(state2[1]);
+        final_state[9] = cuda_swab32(state2[0]);
+#else
+        final_state[8] = state2[0];
+        final_state[9] = state2[1];
+#endif
+        for (int i = 10; i < 18; i++) final_state[i] = digest.uint32s[i - 10];
+
+#if PROGPOW_HAS_FINAL_PADDING
+        final_state[18] = 0x00000001;
+        final_state[24] = 0x80008081;
+#endif
+#endif
+        keccak_f800(final_state);
+        // The 64-bit result for target comparison is the first 8 bytes of the hash
+        // as a big-endian integer to match CPU verifier.
+        result = ((ulong)cuda_swab32(final_state[0]) << 32) | (ulong)cuda_swab32(final_state[1]);
+
+#if PROGPOW_DEBUG_TRACE
+        if (gid == 0 && g_debug_trace != 0) {
+            for (int i = 0; i < 25; i++) g_debug_trace[64 + i] = final_state[i];
+            g_debug_trace[90] = (uint)(result >> 32);
+            g_debug_trace[91] = (uint)result;
+            g_debug_trace[92] = (uint)(target >> 32);
+            g_debug_trace[93] = (uint)target;
+        }
+#endif
+    }
+
+    if (result <= target && result > 0 && lane_id == 0)
+    {
+        uint index = atomic_fetch_add_explicit(&g_output->count, 1u, memory_order_relaxed);
         if (index < SEARCH_RESULTS)
         {
             g_output->result[index].nonce = nonce;
-            for (int i = 0; i < 8; i++){
-                g_output->result[index].mix[i] = digest.uint32s[i];
-                g_output->result[index].debug[i] = state2[i];
-            }
+            for (int i = 0; i < 8; i++) g_output->result[index].mix[i] = digest.uint32s[i];
+            for (int i = 0; i < 8; i++) g_output->result[index].debug[i] = state2[i];
+        }
+    }
+}
+"#;
+
+const PROGPOW_VULKAN_KERNEL_TEMPLATE: &str = r#"
+#version 450
+
+#ifndef SEARCH_RESULTS
+#define SEARCH_RESULTS 16
+#endif
+
+// The group size is a specialization constant, so one SPIR-V module serves every launch
+// shape.
+layout(local_size_x_id = 0) in;
+#define GROUP_SIZE gl_WorkGroupSize.x
+
+#define uint32_t uint
+
+#define ROTL32(x,n) rotl32(uint(x), uint(n))
+#define ROTR32(x,n) rotl32(uint(x), 32u - (uint(n) % 32u))
+#define __umulhi(a, b) mul_hi(uint(a), uint(b))
+
+#define PROGPOW_LANES           16
+// PROGPOW_REGS is injected with the defines below
+#define PROGPOW_DAG_LOADS       4
+#define PROGPOW_CACHE_WORDS     4096
+#define PROGPOW_CNT_DAG         64
+// Storage buffers the DAG is split across, each below maxStorageBufferRange.
+#define DAG_BINDINGS            4
+XMRIG_INCLUDE_DEFINES
+
+#define HASHES_PER_GROUP (GROUP_SIZE / PROGPOW_LANES)
+
+struct dag_t {
+    uint s[PROGPOW_DAG_LOADS];
+};
+
+struct search_result {
+    uint nonce[2];
+    uint mix[8];
+    uint debug[8];
+};
+
+layout(push_constant) uniform Params {
+    uvec2 start_nonce;
+    uvec2 target;
+    uint header_hash[8];
+    // dag_t elements in each DAG binding.
+    uint dag_split;
+} params;
+
+layout(std430, binding = 0) readonly buffer Dag0 { dag_t g_dag0[]; };
+layout(std430, binding = 1) readonly buffer Dag1 { dag_t g_dag1[]; };
+layout(std430, binding = 2) readonly buffer Dag2 { dag_t g_dag2[]; };
+layout(std430, binding = 3) readonly buffer Dag3 { dag_t g_dag3[]; };
+layout(std430, binding = 4) readonly buffer Cache { uint c_cache[]; };
+layout(std430, binding = 5) buffer SearchResults {
+    uint count;
+    uint _padding; // Explicitly match Rust struct alignment
+    search_result result[SEARCH_RESULTS];
+} g_output;
+
+shared uint c_dag[PROGPOW_CACHE_WORDS];
+shared uint g_share[HASHES_PER_GROUP];
+
+uint lane_id;
+uint share_slot;
+
+uint rotl32(uint x, uint n)
+{
+    n %= 32u;
+    return n == 0u ? x : (x << n) | (x >> (32u - n));
+}
+
+uint mul_hi(uint a, uint b)
+{
+    uint hi, lo;
+    umulExtended(a, b, hi, lo);
+    return hi;
+}
+
+uint clz(uint x)
+{
+    return uint(31 - findMSB(x));
+}
+
+uint popcount(uint x)
+{
+    return uint(bitCount(x));
+}
+
+dag_t load_dag(uint i)
+{
+    uint part = i / params.dag_split;
+    uint j = i - part * params.dag_split;
+    if (part == 0u)
+        return g_dag0[j];
+    if (part == 1u)
+        return g_dag1[j];
+    if (part == 2u)
+        return g_dag2[j];
+    return g_dag3[j];
+}
+
+// Lane exchange through shared memory, as in the OpenCL kernel. Must be reached by every
+// invocation of the group.
+uint shfl(const uint x, const uint src_lane)
+{
+    barrier();
+    if (lane_id == src_lane)
+        g_share[share_slot] = x;
+    memoryBarrierShared();
+    barrier();
+    return g_share[share_slot];
+}
+
+#define SHFL(x, y, z) shfl((x), (y))
+
+void progPowLoop(const uint loop, inout uint mix[PROGPOW_REGS])
+{
+    dag_t data_dag;
+    uint offset, data;
+
+    // global load
+    offset = SHFL(mix[0], loop % PROGPOW_LANES, PROGPOW_LANES);
+
+    // OFFSET MOD LOGIC
+    XMRIG_INCLUDE_OFFSET_MOD_DAG_ELEMENTS
+
+    offset = offset * PROGPOW_LANES + (lane_id ^ loop) % PROGPOW_LANES;
+    data_dag = load_dag(offset);
+
+    // Random math and cache operations
+    XMRIG_INCLUDE_PROGPOW_RANDOM_MATH
+
+    // DAG data loads (merge data_dag into mix)
+    XMRIG_INCLUDE_PROGPOW_DATA_LOADS
+}
+
+#define FNV_PRIME 0x1000193u
+#define FNV_OFFSET_BASIS 0x811c9dc5u
+
+const uint keccakf_rndc[24] = uint[](
+    0x00000001u, 0x00008082u, 0x0000808au, 0x80008000u, 0x0000808bu, 0x80000001u,
+    0x80008081u, 0x00008009u, 0x0000008au, 0x00000088u, 0x80008009u, 0x8000000au,
+    0x8000808bu, 0x0000008bu, 0x00008089u, 0x00008003u, 0x00008002u, 0x00000080u,
+    0x0000800au, 0x8000000au, 0x80008081u, 0x00008080u, 0x80000001u, 0x80008008u
+);
+
+const uint ravencoin_rndc[15] = uint[](
+    0x00000072u, 0x00000041u, 0x00000056u, 0x00000045u, // rAVE
+    0x0000004Eu, 0x00000043u, 0x0000004Fu, 0x00000049u, // NCOI
+    0x0000004Eu, 0x0000004Bu, 0x00000041u, 0x00000057u, // NKAW
+    0x00000050u, 0x0000004Fu, 0x00000057u               // POW
+);
+
+const uint meowcoin_rndc[15] = uint[](
+    0x0000004Du, 0x00000045u, 0x0000004Fu, 0x00000057u, 0x00000043u, 0x0000004Fu, 0x00000049u, 0x0000004Eu,
+    0x0000004Du, 0x00000045u, 0x0000004Fu, 0x00000057u, 0x00000050u, 0x0000004Fu, 0x00000057u
+);
+
+const uint evrmore_rndc[15] = uint[](
+    0x00000065u, 0x00000076u, 0x00000072u, 0x0000006Du, 0x0000006Fu, 0x00000072u, 0x00000065u, 0x00000000u,
+    0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u, 0x00000000u
+);
+
+const uint keccakf_rotc[24] = uint[](
+    1u,  3u,  6u,  10u, 15u, 21u, 28u, 36u, 45u, 55u, 2u,  14u,
+    27u, 41u, 56u, 8u,  25u, 43u, 62u, 18u, 39u, 61u, 20u, 44u
+);
+
+const uint keccakf_piln[24] = uint[](
+    10u, 7u,  11u, 17u, 18u, 3u, 5u,  16u, 8u,  21u, 24u, 4u,
+    15u, 23u, 19u, 13u, 12u, 2u, 20u, 14u, 22u, 9u,  6u,  1u
+);
+
+void keccak_f800_round(inout uint st[25], const int r)
+{
+    uint t, bc[5];
+    // Theta
+    for (int i = 0; i < 5; i++)
+        bc[i] = st[i] ^ st[i + 5] ^ st[i + 10] ^ st[i + 15] ^ st[i + 20];
+
+    for (int i = 0; i < 5; i++) {
+        t = bc[(i + 4) % 5] ^ ROTL32(bc[(i + 1) % 5], 1);
+        for (int j = 0; j < 25; j += 5)
+            st[j + i] ^= t;
+    }
+
+    // Rho Pi
+    t = st[1];
+    for (int i = 0; i < 24; i++) {
+        uint j = keccakf_piln[i];
+        bc[0] = st[j];
+        st[j] = ROTL32(t, keccakf_rotc[i]);
+        t = bc[0];
+    }
+
+    // Chi
+    for (int j = 0; j < 25; j += 5) {
+        for (int i = 0; i < 5; i++)
+            bc[i] = st[j + i];
+        for (int i = 0; i < 5; i++)
+            st[j + i] ^= (~bc[(i + 1) % 5]) & bc[(i + 2) % 5];
+    }
+
+    // Iota
+    st[0] ^= keccakf_rndc[r];
+}
+
+uint cuda_swab32(const uint x)
+{
+    return ((x & 0x000000FFu) << 24) |
+           ((x & 0x0000FF00u) << 8)  |
+           ((x & 0x00FF0000u) >> 8)  |
+           ((x & 0xFF000000u) >> 24);
+}
+
+void keccak_f800(inout uint st[25])
+{
+    for (int r = 0; r < XMRIG_INCLUDE_KECCAK_ROUNDS; r++)
+        keccak_f800_round(st, r);
+}
+
+uint fnv1a_dev(uint h, uint d)
+{
+    return (h ^ d) * FNV_PRIME;
+}
+
+struct kiss99_t {
+    uint z, w, jsr, jcong;
+};
+
+uint kiss99(inout kiss99_t st)
+{
+    st.z = 36969u * (st.z & 65535u) + (st.z >> 16);
+    st.w = 18000u * (st.w & 65535u) + (st.w >> 16);
+    uint MWC = ((st.z << 16) + st.w);
+
+XMRIG_INCLUDE_KISS99_LOGIC
+
+    return ((MWC ^ st.jcong) + st.jsr);
+}
+
+void fill_mix(uint hash_seed[2], uint lane_id, out uint mix[PROGPOW_REGS])
+{
+    kiss99_t st;
+    st.z = fnv1a_dev(FNV_OFFSET_BASIS, hash_seed[0]);
+    st.w = fnv1a_dev(st.z, hash_seed[1]);
+    st.jsr = fnv1a_dev(st.w, lane_id);
+    st.jcong = fnv1a_dev(st.jsr, lane_id);
+
+    for (int i = 0; i < PROGPOW_REGS; i++)
+        mix[i] = kiss99(st);
+}
+
+void main()
+{
+    uint header_hash[8] = params.header_hash;
+
+    uint gid = gl_GlobalInvocationID.x;
+    uint lid = gl_LocalInvocationID.x;
+    lane_id = lid & (PROGPOW_LANES - 1);
+    share_slot = lid / PROGPOW_LANES;
+
+    // 64-bit nonce as two words, since shaderInt64 is optional.
+    uint carry;
+    uint nonce_lo = uaddCarry(params.start_nonce.x, gid / PROGPOW_LANES, carry);
+    uint nonce_hi = params.start_nonce.y + carry;
+
+    // Load Cache
+    for (uint word = lid; word < PROGPOW_CACHE_WORDS; word += GROUP_SIZE)
+        c_dag[word] = c_cache[word];
+    barrier();
+
+    uint mix[PROGPOW_REGS];
+    uint hash_seed[2];
+    uint state2[16];
+
+    {
+        // Initial state
+        uint state[25];
+        for (int i = 0; i < 25; i++) state[i] = 0u;
+
+        for (int i = 0; i < 8; i++)
+            state[i] = header_hash[i];
+
+        state[8] = nonce_lo;
+        state[9] = nonce_hi;
+
+XMRIG_INCLUDE_PROGPOW_INITIAL_PADDING
+
+        keccak_f800(state);
+
+        for (int i = 0; i < 16; i++)
+            state2[i] = state[i];
+
+        uint hash_seed_small[2];
+        XMRIG_INCLUDE_HASH_SEED_EXTRACT
+        hash_seed[0] = hash_seed_small[0];
+        hash_seed[1] = hash_seed_small[1];
+    }
+    fill_mix(hash_seed, lane_id, mix);
+
+    for (uint l = 0u; l < PROGPOW_CNT_DAG; l++)
+        progPowLoop(l, mix);
+
+    // Reduction
+    uint digest_lane = FNV_OFFSET_BASIS;
+    for (int i = 0; i < PROGPOW_REGS; i++)
+        digest_lane = fnv1a_dev(digest_lane, mix[i]);
+
+    uint digest[8];
+    for (uint i = 0u; i < 8u; i++)
+    {
+        uint res = FNV_OFFSET_BASIS;
+        res = fnv1a_dev(res, SHFL(digest_lane, i, PROGPOW_LANES));
+        res = fnv1a_dev(res, SHFL(digest_lane, i + 8u, PROGPOW_LANES));
+        digest[i] = res;
+    }
+
+    uint result_hi, result_lo;
+    {
+        uint final_state[25];
+        for (int i = 0; i < 25; i++) final_state[i] = 0u;
+
+#if KAWPOW_IS_RAVENCOIN
+        for (int i = 0; i < 8; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[8 + i] = digest[i];
+        for (int i = 0; i < 9; i++) final_state[16 + i] = ravencoin_rndc[i];
+#elif KAWPOW_IS_MEOWCOIN
+        for (int i = 0; i < 8; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[8 + i] = digest[i];
+        for (int i = 0; i < 9; i++) final_state[16 + i] = meowcoin_rndc[i];
+#elif KAWPOW_IS_EVRMORE
+        for (int i = 0; i < 8; i++) final_state[i] = header_hash[i];
+        final_state[8] = nonce_lo;
+        final_state[9] = nonce_hi;
+        for (int i = 0; i < 8; i++) final_state[10 + i] = digest[i];
+        for (int i = 0; i < 7; i++) final_state[18 + i] = evrmore_rndc[i];
+#elif PROGPOW_IS_FIRO
+        for (int i = 0; i < 16; i++) final_state[i] = state2[i];
+        for (int i = 0; i < 8; i++) final_state[16 + i] = digest[i];
+        final_state[24] = 0x80000001u;
+#else
+        // Standard ProgPow / Zano / Epic / Sero: header + nonce/seed + mix
+        for (int i = 0; i < 8; i++) final_state[i] = header_hash[i];
+#if PROGPOW_IS_ZANO
+        final_state[8] = cuda_swab32(state2[1]);
+        final_state[9] = cuda_swab32(state2[0]);
+#else
+        final_state[8] = state2[0];
+        final_state[9] = state2[1];
+#endif
+        for (int i = 10; i < 18; i++) final_state[i] = digest[i - 10];
+
+#if PROGPOW_HAS_FINAL_PADDING
+        final_state[18] = 0x00000001u;
+        final_state[24] = 0x80008081u;
+#endif
+#endif
+        keccak_f800(final_state);
+        // The first 8 bytes of the hash as a big-endian integer, as the CPU verifier
+        // compares them.
+        result_hi = cuda_swab32(final_state[0]);
+        result_lo = cuda_swab32(final_state[1]);
+    }
+
+    bool below = result_hi < params.target.y ||
+        (result_hi == params.target.y && result_lo <= params.target.x);
+    if (below && (result_hi | result_lo) != 0u && lane_id == 0u)
+    {
+        uint index = atomicAdd(g_output.count, 1u);
+        if (index < SEARCH_RESULTS)
+        {
+            g_output.result[index].nonce[0] = nonce_lo;
+            g_output.result[index].nonce[1] = nonce_hi;
+            for (int i = 0; i < 8; i++) g_output.result[index].mix[i] = digest[i];
+            for (int i = 0; i < 8; i++) g_output.result[index].debug[i] = state2[i];
         }
     }
+}
 "#;
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::params::SeroParams;
+	use progpow_base::params::{KawPowParams, ZanoParams};
+
+	fn assert_parity<P: ProgPowParams>(height: u64) {
+		let cuda = generate_cuda_kernel::<P>(0, height);
+		let opencl = generate_opencl_kernel::<P>(0, height);
+		let metal = generate_metal_kernel::<P>(0, height);
+		let vulkan = generate_vulkan_kernel::<P>(0, height);
+		let level_zero = generate_level_zero_kernel::<P>(0, height);
+		let (random_math, dag_loads) = get_code::<P>(P::prog_seed(height));
+
+		// The same round count as `DynParams`, which the CPU verifier hashes with.
+		let rounds = format!("r < {}; r++", DynParams::of::<P>().keccak_rounds);
+		for code in [&cuda, &opencl, &metal, &vulkan, &level_zero] {
+			assert!(!code.contains("XMRIG_INCLUDE"));
+			assert!(code.contains(&random_math));
+			assert!(code.contains(&dag_loads));
+			assert!(code.contains(&rounds));
+		}
+	}
+
+	#[test]
+	fn test_generate_kernel_source() {
+		let params = DynParams::of::<KawPowParams>();
+		let height = 1_000_000;
+		let period = KawPowParams::prog_seed(height);
+		let kernel = generate_kernel_source(Backend::OpenCl, &params, period, height).unwrap();
+		assert_eq!(
+			kernel.source,
+			generate_opencl_kernel::<KawPowParams>(0, height)
+		);
+		assert_eq!(kernel.dag_elements, dag_elements::<KawPowParams>(height));
+		assert!(kernel
+			.source
+			.contains(&format!("__kernel void {}(", kernel.entry_point)));
+		for (name, value) in &kernel.defines {
+			assert!(kernel
+				.source
+				.contains(&format!("#define {:<25} {}\n", name, value)));
+		}
+
+		let other = generate_kernel_source(Backend::OpenCl, &params, period + 1, height).unwrap();
+		assert_ne!(other.source, kernel.source);
+	}
+
+	#[test]
+	fn test_kernel_search_results() {
+		let params = DynParams::of::<KawPowParams>();
+		let height = 1_000_000;
+		let period = KawPowParams::prog_seed(height);
+		for backend in [
+			Backend::Cuda,
+			Backend::OpenCl,
+			Backend::Hip,
+			Backend::Metal,
+			Backend::Vulkan,
+			Backend::LevelZero,
+		] {
+			let kernel =
+				generate_kernel_source_with_results(backend, &params, period, height, 256).unwrap();
+			assert_eq!(kernel.search_results, 256);
+			assert!(kernel.source.contains("#define SEARCH_RESULTS 256\n"));
+			assert!(!kernel.overflowed(256));
+			assert!(kernel.overflowed(257));
+		}
+		assert!(
+			generate_kernel_source_with_results(Backend::Cuda, &params, period, height, 0).is_err()
+		);
+		assert!(set_search_results("__kernel void f() {}".to_string(), 256).is_err());
+	}
+
+	#[test]
+	fn test_hip_kernel() {
+		let cuda = generate_cuda_kernel::<KawPowParams>(0, 1);
+		let hip = generate_hip_kernel::<KawPowParams>(0, 1);
+		assert!(hip.ends_with(&cuda.replacen("#if __CUDA_ARCH__ < 350", "#if 0", 1)));
+		assert!(hip.contains("extern \"C\" __global__ void progpow_search_v3("));
+		assert!(!hip.contains("__CUDA_ARCH__"));
+	}
+
+	#[test]
+	fn test_metal_kernel() {
+		let metal = generate_metal_kernel::<KawPowParams>(0, 1);
+		assert!(metal.starts_with("\n#include <metal_stdlib>\n"));
+		assert!(metal.contains("kernel void progpow_search("));
+		for opencl_only in ["__kernel", "__global", "__local", "barrier(CLK_"] {
+			assert!(!metal.contains(opencl_only), "{}", opencl_only);
+		}
+	}
+
+	#[test]
+	fn test_vulkan_kernel() {
+		// Height 1 has a DAG size that is not a power of two, so the offset modulo tests
+		// `offset1`.
+		let glsl = generate_vulkan_kernel::<KawPowParams>(0, 1);
+		assert!(glsl.starts_with("\n#version 450\n"));
+		assert!(glsl.contains("(offset1 != 0u ? "));
+		assert!(!glsl.contains("(offset1 ? "));
+	}
+
+	#[test]
+	fn test_level_zero_kernel() {
+		let source = generate_level_zero_kernel::<KawPowParams>(0, 1);
+		assert!(source.contains("return intel_sub_group_shuffle(x, src_lane);"));
+		assert!(source.contains("intel_reqd_sub_group_size(PROGPOW_LANES)"));
+		// The cache load is the only barrier left.
+		assert_eq!(source.matches("barrier(CLK_LOCAL_MEM_FENCE)").count(), 1);
+	}
+
+	#[test]
+	fn test_opencl_cuda_parity() {
+		assert_parity::<KawPowParams>(1);
+		assert_parity::<KawPowParams>(1_000_000);
+		assert_parity::<ZanoParams>(3528114);
+		assert_parity::<SeroParams>(1_000_000);
+		assert_parity::<crate::epic::EpicParams>(1);
+	}
+
+	#[test]
+	fn test_sero_kernel() {
+		let sero = generate_cuda_kernel::<SeroParams>(0, 1_000_000);
+		assert!(sero.contains("#define PROGPOW_IS_SERO           1"));
+		assert!(sero.contains("#define PROGPOW_IS_ZANO           1"));
+		assert!(sero.contains("hash_seed_small[0] = cuda_swab32(state2[1]);"));
+		assert!(generate_cuda_kernel::<ZanoParams>(0, 1_000_000)
+			.contains("#define PROGPOW_IS_SERO           0"));
+	}
+
+	/// Compares the generated kernel with `res/kernels/<preset>-<backend>-<period>.<ext>`.
+	///
+	/// Set `PROGPOW_BLESS=1` to write missing goldens and overwrite those that differ after an
+	/// intended template change.
+	fn assert_golden<P: ProgPowParams>(backend: Backend, height: u64) {
+		let ext = match backend {
+			Backend::Cuda => "cu",
+			Backend::OpenCl => "cl",
+			Backend::Hip => "hip",
+			Backend::Metal => "metal",
+			Backend::Vulkan => "comp",
+			Backend::LevelZero => "cl",
+		};
+		let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+			.join("res/kernels")
+			.join(format!(
+				"{}-{}-{}.{}",
+				P::NAME,
+				backend.name(),
+				P::prog_seed(height),
+				ext
+			));
+		let source = backend.generate::<P>(height);
+		let bless = std::env::var_os("PROGPOW_BLESS").map_or(false, |v| v == "1");
+
+		if bless {
+			std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+			std::fs::write(&path, &source).unwrap();
+			return;
+		}
+		match std::fs::read_to_string(&path) {
+			Ok(golden) => assert!(
+				golden == source,
+				"{} differs from the generated kernel, rerun with PROGPOW_BLESS=1 if the change is intended",
+				path.display()
+			),
+			Err(e) => panic!(
+				"{}: {}, generate it with PROGPOW_BLESS=1",
+				path.display(),
+				e
+			),
+		}
+	}
+
+	// The debug-trace feature changes the emitted defines, so goldens only hold without it.
+	#[cfg(not(feature = "debug-trace"))]
+	#[test]
+	fn test_golden_kernels() {
+		for backend in [Backend::Cuda, Backend::OpenCl] {
+			assert_golden::<KawPowParams>(backend, 1);
+			assert_golden::<KawPowParams>(backend, 1_000_000);
+			assert_golden::<ZanoParams>(backend, 3528114);
+			assert_golden::<SeroParams>(backend, 1_000_000);
+		}
+	}
+}
@@ -0,0 +1,160 @@
+//! Kernel generating DAG nodes on the device from the light cache, so an epoch switch
+//! uploads the light cache instead of the whole DAG.
+//!
+//! Each work-item computes one 64-byte node as `calculate_dag_item` does on the host:
+//! keccak-512 of a cache node, `DATASET_PARENTS` FNV rounds over pseudo-random parents
+//! and keccak-512 again. The source does not depend on the period or epoch.
+
+use super::{cuda_to_hip, Backend};
+
+/// Name of the DAG generation function in the generated source.
+pub const DAG_ENTRY_POINT: &str = "progpow_generate_dag";
+
+/// Cache nodes mixed into each DAG node, as in ethash.
+pub const DATASET_PARENTS: u32 = 256;
+
+/// The DAG generation kernel of `backend`, `None` if it only generates on the host.
+///
+/// The kernel takes `(start, dag_nodes, light, light_nodes, dag)`: work-item `i` writes
+/// node `start + i` of `dag`, skipping those at or past `dag_nodes`.
+pub fn generate_dag_kernel(backend: Backend) -> Option<String> {
+	let source = DAG_KERNEL_TEMPLATE.replace(
+		"#define DATASET_PARENTS",
+		&format!("#define DATASET_PARENTS {}", DATASET_PARENTS),
+	);
+	match backend {
+		Backend::Cuda => Some(source),
+		Backend::Hip => Some(cuda_to_hip(source)),
+		Backend::OpenCl | Backend::Metal | Backend::Vulkan | Backend::LevelZero => None,
+	}
+}
+
+const DAG_KERNEL_TEMPLATE: &str = r#"
+typedef unsigned int       uint32_t;
+typedef unsigned long long uint64_t;
+
+#define DATASET_PARENTS
+#define FNV_PRIME 0x01000193U
+#define fnv(x, y) ((x) * FNV_PRIME ^ (y))
+
+__constant__ const uint64_t keccakf_rndc64[24] = {
+    0x0000000000000001ULL, 0x0000000000008082ULL, 0x800000000000808aULL,
+    0x8000000080008000ULL, 0x000000000000808bULL, 0x0000000080000001ULL,
+    0x8000000080008081ULL, 0x8000000000008009ULL, 0x000000000000008aULL,
+    0x0000000000000088ULL, 0x0000000080008009ULL, 0x000000008000000aULL,
+    0x000000008000808bULL, 0x800000000000008bULL, 0x8000000000008089ULL,
+    0x8000000000008003ULL, 0x8000000000008002ULL, 0x8000000000000080ULL,
+    0x000000000000800aULL, 0x800000008000000aULL, 0x8000000080008081ULL,
+    0x8000000000008080ULL, 0x0000000080000001ULL, 0x8000000080008008ULL
+};
+
+__constant__ const uint32_t keccakf_rotc[24] = {
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44
+};
+
+__constant__ const uint32_t keccakf_piln[24] = {
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1
+};
+
+__device__ __forceinline__ uint64_t rotl64(uint64_t x, uint32_t n)
+{
+    return (x << n) | (x >> (64 - n));
+}
+
+__device__ void keccak_f1600(uint64_t* st)
+{
+    uint64_t bc[5];
+    for (int r = 0; r < 24; r++) {
+        for (int i = 0; i < 5; i++)
+            bc[i] = st[i] ^ st[i + 5] ^ st[i + 10] ^ st[i + 15] ^ st[i + 20];
+        for (int i = 0; i < 5; i++) {
+            uint64_t t = bc[(i + 4) % 5] ^ rotl64(bc[(i + 1) % 5], 1);
+            for (int j = 0; j < 25; j += 5)
+                st[j + i] ^= t;
+        }
+
+        uint64_t t = st[1];
+        for (int i = 0; i < 24; i++) {
+            uint32_t j = keccakf_piln[i];
+            bc[0] = st[j];
+            st[j] = rotl64(t, keccakf_rotc[i]);
+            t = bc[0];
+        }
+
+        for (int j = 0; j < 25; j += 5) {
+            for (int i = 0; i < 5; i++)
+                bc[i] = st[j + i];
+            for (int i = 0; i < 5; i++)
+                st[j + i] ^= (~bc[(i + 1) % 5]) & bc[(i + 2) % 5];
+        }
+
+        st[0] ^= keccakf_rndc64[r];
+    }
+}
+
+// Keccak-512 of one 64-byte node, in place, with the original Keccak padding.
+__device__ void keccak_512_node(uint32_t* words)
+{
+    uint64_t st[25];
+    for (int i = 0; i < 8; i++)
+        st[i] = (uint64_t)words[2 * i] | ((uint64_t)words[2 * i + 1] << 32);
+    st[8] = 0x8000000000000001ULL;
+    for (int i = 9; i < 25; i++)
+        st[i] = 0;
+
+    keccak_f1600(st);
+
+    for (int i = 0; i < 8; i++) {
+        words[2 * i] = (uint32_t)st[i];
+        words[2 * i + 1] = (uint32_t)(st[i] >> 32);
+    }
+}
+
+extern "C" __global__ void progpow_generate_dag(
+    uint32_t start,
+    uint32_t dag_nodes,
+    const uint32_t* __restrict__ light,
+    uint32_t light_nodes,
+    uint32_t* __restrict__ dag)
+{
+    uint32_t node_index = start + blockIdx.x * blockDim.x + threadIdx.x;
+    if (node_index >= dag_nodes)
+        return;
+
+    uint32_t mix[16];
+    const uint32_t* seed = light + (uint64_t)(node_index % light_nodes) * 16;
+    for (int w = 0; w < 16; w++)
+        mix[w] = seed[w];
+    mix[0] ^= node_index;
+    keccak_512_node(mix);
+
+    for (uint32_t i = 0; i < DATASET_PARENTS; i++) {
+        uint32_t parent_index = fnv(node_index ^ i, mix[i % 16]) % light_nodes;
+        const uint32_t* parent = light + (uint64_t)parent_index * 16;
+        for (int w = 0; w < 16; w++)
+            mix[w] = fnv(mix[w], parent[w]);
+    }
+
+    keccak_512_node(mix);
+
+    uint32_t* out = dag + (uint64_t)node_index * 16;
+    for (int w = 0; w < 16; w++)
+        out[w] = mix[w];
+}
+"#;
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_dag_kernel_backends() {
+		let cuda = generate_dag_kernel(Backend::Cuda).unwrap();
+		assert!(cuda.contains(DAG_ENTRY_POINT));
+		assert!(cuda.contains("#define DATASET_PARENTS 256\n"));
+		assert!(generate_dag_kernel(Backend::Hip)
+			.unwrap()
+			.starts_with("#define uint32_t progpow_uint32_t"));
+		assert!(generate_dag_kernel(Backend::Metal).is_none());
+	}
+}
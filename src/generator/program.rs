@@ -0,0 +1,541 @@
+//! The random program of a ProgPoW period in structured form, for pools and explorers
+//! that display or check it. The CUDA and OpenCL kernels are rendered from the same
+//! description, see `generator::get_code`.
+
+use progpow_base::params::MathMapping;
+use std::fmt::{self, Write};
+
+use super::{fnv1a, kernel_source, Kiss99, SEARCH_RESULTS};
+use crate::params::{with_preset, DynParams};
+use crate::types::ProgPowError;
+
+/// How a value is merged into a mix register, keeping the register's entropy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Merge {
+	/// `a * 33 + b`
+	MulAdd,
+	/// `(a ^ b) * 33`
+	XorMul,
+	/// `rotl(a, n) ^ b`
+	RotlXor(u32),
+	/// `rotr(a, n) ^ b`
+	RotrXor(u32),
+}
+
+impl Merge {
+	fn from_rnd(r: u32) -> Self {
+		let rot = ((r >> 16) % 31) + 1;
+		match r % 4 {
+			0 => Merge::MulAdd,
+			1 => Merge::XorMul,
+			2 => Merge::RotlXor(rot),
+			_ => Merge::RotrXor(rot),
+		}
+	}
+
+	pub fn apply(self, a: u32, b: u32) -> u32 {
+		match self {
+			Merge::MulAdd => a.wrapping_mul(33).wrapping_add(b),
+			Merge::XorMul => (a ^ b).wrapping_mul(33),
+			Merge::RotlXor(n) => a.rotate_left(n) ^ b,
+			Merge::RotrXor(n) => a.rotate_right(n) ^ b,
+		}
+	}
+}
+
+/// Random math on two registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MathOp {
+	Add,
+	Mul,
+	/// High 32 bits of the 64-bit product.
+	MulHi,
+	Min,
+	Rotl,
+	Rotr,
+	And,
+	Or,
+	Xor,
+	/// `clz(a) + clz(b)`
+	Clz,
+	/// `popcount(a) + popcount(b)`
+	Popcount,
+}
+
+impl MathOp {
+	fn from_rnd(r: u32, mapping: MathMapping) -> Self {
+		const STANDARD: [MathOp; 11] = [
+			MathOp::Add,
+			MathOp::Mul,
+			MathOp::MulHi,
+			MathOp::Min,
+			MathOp::Rotl,
+			MathOp::Rotr,
+			MathOp::And,
+			MathOp::Or,
+			MathOp::Xor,
+			MathOp::Clz,
+			MathOp::Popcount,
+		];
+		let r = r as usize % 11;
+		match mapping {
+			MathMapping::Standard | MathMapping::KawPow => STANDARD[r],
+			// The standard order rotated by two.
+			MathMapping::Zano => STANDARD[(r + 9) % 11],
+		}
+	}
+
+	pub fn apply(self, a: u32, b: u32) -> u32 {
+		match self {
+			MathOp::Add => a.wrapping_add(b),
+			MathOp::Mul => a.wrapping_mul(b),
+			MathOp::MulHi => ((a as u64 * b as u64) >> 32) as u32,
+			MathOp::Min => a.min(b),
+			MathOp::Rotl => a.rotate_left(b % 32),
+			MathOp::Rotr => a.rotate_right(b % 32),
+			MathOp::And => a & b,
+			MathOp::Or => a | b,
+			MathOp::Xor => a ^ b,
+			MathOp::Clz => a.leading_zeros() + b.leading_zeros(),
+			MathOp::Popcount => a.count_ones() + b.count_ones(),
+		}
+	}
+}
+
+/// One step of the inner loop. Registers are indices into the lane's mix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Operation {
+	/// `mix[dst] = merge(mix[dst], c_dag[mix[src] % PROGPOW_CACHE_WORDS])`
+	Cache {
+		src: usize,
+		dst: usize,
+		merge: Merge,
+	},
+	/// `mix[dst] = merge(mix[dst], math(mix[src1], mix[src2]))`
+	Math {
+		src1: usize,
+		src2: usize,
+		math: MathOp,
+		dst: usize,
+		merge: Merge,
+	},
+}
+
+/// `mix[dst] = merge(mix[dst], dag_word)`, consuming word `word` of the lane's DAG load at
+/// the end of the loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DagMerge {
+	pub word: usize,
+	pub dst: usize,
+	pub merge: Merge,
+}
+
+/// The program every lane runs in each of the `PROGPOW_CNT_DAG` loops of a period.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProgramDescription {
+	pub period: u64,
+	/// Cache loads and random math, interleaved in execution order.
+	pub operations: Vec<Operation>,
+	/// One per DAG load word, the first always into `mix[0]`.
+	pub dag_merges: Vec<DagMerge>,
+}
+
+/// The program of `period`, the program seed `ProgPowParams::prog_seed` returns for a
+/// height.
+pub fn describe(period: u64, params: &DynParams) -> ProgramDescription {
+	let is_zano = params.math_mapping == MathMapping::Zano;
+	let seed0 = period as u32;
+	let seed1 = (period >> 32) as u32;
+
+	let mut h = 0x811c9dc5u32; // FNV_HASH
+	let z = fnv1a(&mut h, seed0);
+	let w = fnv1a(&mut h, seed1);
+	let jsr = fnv1a(&mut h, seed0);
+	let jcong = fnv1a(&mut h, seed1);
+	let mut rng = Kiss99::new(z, w, jsr, jcong);
+
+	let regs = params.regs;
+	let mut mix_seq_dst = (0..regs).collect::<Vec<usize>>();
+	let mut mix_seq_cache = (0..regs).collect::<Vec<usize>>();
+	let mut mix_seq_dst_cnt = 0;
+	let mut mix_seq_cache_cnt = 0;
+
+	if params.has_kiss99_shuffle {
+		for i in (1..regs).rev() {
+			let j = (rng.rnd(is_zano) as usize) % (i + 1);
+			mix_seq_dst.swap(i, j);
+			let j = (rng.rnd(is_zano) as usize) % (i + 1);
+			mix_seq_cache.swap(i, j);
+		}
+	}
+
+	let mut operations = Vec::with_capacity(params.cnt_cache + params.cnt_math);
+	for i in 0..params.cnt_cache.max(params.cnt_math) {
+		if i < params.cnt_cache {
+			let src = mix_seq_cache[mix_seq_cache_cnt % regs];
+			mix_seq_cache_cnt += 1;
+			let dst = mix_seq_dst[mix_seq_dst_cnt % regs];
+			mix_seq_dst_cnt += 1;
+			let merge = Merge::from_rnd(rng.rnd(is_zano));
+			operations.push(Operation::Cache { src, dst, merge });
+		}
+
+		if i < params.cnt_math {
+			let src_rnd = (rng.rnd(is_zano) as usize) % ((regs - 1) * regs);
+			let src1 = src_rnd % regs;
+			let mut src2 = src_rnd / regs;
+			if src2 >= src1 {
+				src2 += 1;
+			}
+			let math = MathOp::from_rnd(rng.rnd(is_zano), params.math_mapping);
+			let dst = mix_seq_dst[mix_seq_dst_cnt % regs];
+			mix_seq_dst_cnt += 1;
+			let merge = Merge::from_rnd(rng.rnd(is_zano));
+			operations.push(Operation::Math {
+				src1,
+				src2,
+				math,
+				dst,
+				merge,
+			});
+		}
+	}
+
+	let mut dag_merges = Vec::with_capacity(params.dag_loads);
+	for word in 0..params.dag_loads {
+		let dst = if word == 0 {
+			0
+		} else {
+			let dst = mix_seq_dst[mix_seq_dst_cnt % regs];
+			mix_seq_dst_cnt += 1;
+			dst
+		};
+		let merge = Merge::from_rnd(rng.rnd(is_zano));
+		dag_merges.push(DagMerge { word, dst, merge });
+	}
+
+	ProgramDescription {
+		period,
+		operations,
+		dag_merges,
+	}
+}
+
+/// Where `dump` reads a program from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+	/// `describe`, which the host hash implements.
+	Cpu,
+	/// The generated CUDA kernel.
+	Cuda,
+	/// The generated OpenCL kernel.
+	OpenCl,
+}
+
+/// The program of `period` as `backend` executes it, one op per line in a notation all
+/// backends share, so two dumps diff clean when the backends agree. GPU dumps are read
+/// back from the generated kernel source; statements the reader does not recognize are
+/// printed as `? <statement>`.
+pub fn dump(period: u64, params: &DynParams, backend: Backend) -> Result<String, ProgPowError> {
+	let gpu = match backend {
+		Backend::Cpu => return Ok(dump_description(&describe(period, params))),
+		Backend::Cuda => super::Backend::Cuda,
+		Backend::OpenCl => super::Backend::OpenCl,
+	};
+	with_preset!(*params, P => {
+		let source = kernel_source::<P>(gpu, period, 0, SEARCH_RESULTS)?.source;
+		Ok(dump_kernel(&source))
+	})
+}
+
+fn dump_description(program: &ProgramDescription) -> String {
+	let mut out = String::new();
+	let mut cache_loads = 0;
+	let mut math_ops = 0;
+	for op in &program.operations {
+		match *op {
+			Operation::Cache { src, dst, merge } => {
+				let c_dag = format!("c_dag[mix[{}]]", src);
+				push_op(&mut out, "cache", cache_loads, &reg(dst), merge, &c_dag);
+				cache_loads += 1;
+			}
+			Operation::Math {
+				src1,
+				src2,
+				math,
+				dst,
+				merge,
+			} => {
+				let data = math_expr(math, &reg(src1), &reg(src2));
+				push_op(&mut out, "math", math_ops, &reg(dst), merge, &data);
+				math_ops += 1;
+			}
+		}
+	}
+	for load in &program.dag_merges {
+		let dag = format!("dag[{}]", load.word);
+		push_op(&mut out, "dag", load.word, &reg(load.dst), load.merge, &dag);
+	}
+	out
+}
+
+// Reads the statements `generator::get_code` renders, from the random math to the end of
+// the loop body.
+fn dump_kernel(source: &str) -> String {
+	let mut out = String::new();
+	let lines = source
+		.lines()
+		.map(str::trim)
+		.skip_while(|line| *line != "// Random math and cache operations")
+		.skip(1);
+	let mut op = ("", "");
+	let mut offset = None;
+	let mut data = None;
+	for line in lines {
+		if line == "}" {
+			break;
+		}
+		let recognized = if line.is_empty() || line.starts_with("// DAG data loads") {
+			true
+		} else if let Some(index) = line.strip_prefix("// cache load ") {
+			op = ("cache", index);
+			true
+		} else if let Some(index) = line.strip_prefix("// random math ") {
+			op = ("math", index);
+			true
+		} else if let Some(src) = line
+			.strip_prefix("offset = ")
+			.and_then(|s| s.strip_suffix(" % PROGPOW_CACHE_WORDS;"))
+		{
+			offset = Some(src);
+			true
+		} else if line == "data = c_dag[offset];" {
+			data = offset.take().map(|src| format!("c_dag[{}]", src));
+			data.is_some()
+		} else if let Some(expr) = line
+			.strip_prefix("data = ")
+			.and_then(|s| s.strip_suffix(';'))
+		{
+			data = parse_math(expr);
+			data.is_some()
+		} else if let Some((dst, expr)) = line.strip_suffix(';').and_then(|s| s.split_once(" = ")) {
+			match parse_merge(dst, expr) {
+				Some((merge, "data")) => match data.take() {
+					Some(data) => {
+						push_op(&mut out, op.0, op.1, dst, merge, &data);
+						true
+					}
+					None => false,
+				},
+				Some((merge, word)) => match word
+					.strip_prefix("data_dag.s[")
+					.and_then(|s| s.strip_suffix(']'))
+				{
+					Some(word) => {
+						let dag = format!("dag[{}]", word);
+						push_op(&mut out, "dag", word, dst, merge, &dag);
+						true
+					}
+					None => false,
+				},
+				None => false,
+			}
+		} else {
+			false
+		};
+		if !recognized {
+			let _ = writeln!(out, "? {}", line);
+		}
+	}
+	out
+}
+
+fn reg(i: usize) -> String {
+	format!("mix[{}]", i)
+}
+
+fn is_reg(s: &str) -> bool {
+	s.strip_prefix("mix[")
+		.and_then(|s| s.strip_suffix(']'))
+		.map_or(false, |i| i.parse::<usize>().is_ok())
+}
+
+fn push_op(
+	out: &mut String,
+	kind: &str,
+	index: impl fmt::Display,
+	dst: &str,
+	merge: Merge,
+	data: &str,
+) {
+	let _ = writeln!(
+		out,
+		"{} {}: {} = {}",
+		kind,
+		index,
+		dst,
+		merge_expr(merge, dst, data)
+	);
+}
+
+fn merge_expr(merge: Merge, a: &str, b: &str) -> String {
+	match merge {
+		Merge::MulAdd => format!("mul_add({}, {})", a, b),
+		Merge::XorMul => format!("xor_mul({}, {})", a, b),
+		Merge::RotlXor(n) => format!("rotl_xor({}, {}, {})", a, b, n),
+		Merge::RotrXor(n) => format!("rotr_xor({}, {}, {})", a, b, n),
+	}
+}
+
+fn math_expr(math: MathOp, a: &str, b: &str) -> String {
+	let name = match math {
+		MathOp::Add => "add",
+		MathOp::Mul => "mul",
+		MathOp::MulHi => "mul_hi",
+		MathOp::Min => "min",
+		MathOp::Rotl => "rotl",
+		MathOp::Rotr => "rotr",
+		MathOp::And => "and",
+		MathOp::Or => "or",
+		MathOp::Xor => "xor",
+		MathOp::Clz => "clz",
+		MathOp::Popcount => "popcount",
+	};
+	format!("{}({}, {})", name, a, b)
+}
+
+fn call<'a>(expr: &'a str, name: &str) -> Option<(&'a str, &'a str)> {
+	expr.strip_prefix(name)?
+		.strip_prefix('(')?
+		.strip_suffix(')')?
+		.split_once(", ")
+}
+
+// Rotations are masked with `% 32` or `& 31` depending on the params, which rotate the
+// same.
+fn rot_amount(b: &str) -> Option<&str> {
+	b.strip_suffix(" % 32").or_else(|| b.strip_suffix(" & 31"))
+}
+
+// The inverse of `generator::math_code`.
+fn parse_math(expr: &str) -> Option<String> {
+	let (math, a, b) = if let Some((a, b)) = call(expr, "mul_hi") {
+		(MathOp::MulHi, a, b)
+	} else if let Some((a, b)) = call(expr, "min") {
+		(MathOp::Min, a, b)
+	} else if let Some((a, b)) = call(expr, "ROTL32") {
+		(MathOp::Rotl, a, rot_amount(b)?)
+	} else if let Some((a, b)) = call(expr, "ROTR32") {
+		(MathOp::Rotr, a, rot_amount(b)?)
+	} else if let Some((a, b)) = expr.split_once(" + ") {
+		let unary = |name| {
+			let a = a.strip_prefix(name)?.strip_prefix('(')?.strip_suffix(')')?;
+			let b = b.strip_prefix(name)?.strip_prefix('(')?.strip_suffix(')')?;
+			Some((a, b))
+		};
+		if let Some((a, b)) = unary("clz") {
+			(MathOp::Clz, a, b)
+		} else if let Some((a, b)) = unary("popcount") {
+			(MathOp::Popcount, a, b)
+		} else {
+			(MathOp::Add, a, b)
+		}
+	} else if let Some((a, b)) = expr.split_once(" * ") {
+		(MathOp::Mul, a, b)
+	} else if let Some((a, b)) = expr.split_once(" & ") {
+		(MathOp::And, a, b)
+	} else if let Some((a, b)) = expr.split_once(" | ") {
+		(MathOp::Or, a, b)
+	} else if let Some((a, b)) = expr.split_once(" ^ ") {
+		(MathOp::Xor, a, b)
+	} else {
+		return None;
+	};
+	if !is_reg(a) || !is_reg(b) {
+		return None;
+	}
+	Some(math_expr(math, a, b))
+}
+
+// The inverse of `generator::merge_code` for a merge into `a`, returning the merged value.
+fn parse_merge<'a>(a: &str, expr: &'a str) -> Option<(Merge, &'a str)> {
+	if !is_reg(a) {
+		return None;
+	}
+	if let Some(b) = expr.strip_prefix(&format!("({} * 33) + ", a)) {
+		return Some((Merge::MulAdd, b));
+	}
+	if let Some(b) = expr
+		.strip_prefix(&format!("({} ^ ", a))
+		.and_then(|s| s.strip_suffix(") * 33"))
+	{
+		return Some((Merge::XorMul, b));
+	}
+	for (macro_name, merge) in [
+		("ROTL32", Merge::RotlXor as fn(u32) -> Merge),
+		("ROTR32", Merge::RotrXor),
+	] {
+		if let Some((n, b)) = expr
+			.strip_prefix(&format!("{}({}, ", macro_name, a))
+			.and_then(|s| s.split_once(") ^ "))
+		{
+			return Some((merge(n.parse().ok()?), b));
+		}
+	}
+	None
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use progpow_base::params::KawPowParams;
+
+	#[test]
+	fn test_describe() {
+		let params = DynParams::of::<KawPowParams>();
+		let program = describe(7, &params);
+		assert_eq!(program.operations.len(), params.cnt_cache + params.cnt_math);
+		assert_eq!(program.dag_merges.len(), params.dag_loads);
+		assert_eq!(program.dag_merges[0].dst, 0);
+		assert_eq!(describe(7, &params), program);
+		assert_ne!(describe(8, &params), program);
+
+		assert_eq!(Merge::from_rnd(0).apply(2, 3), 69);
+		assert_eq!(MathOp::from_rnd(2, MathMapping::Zano), MathOp::Add);
+		assert_eq!(MathOp::MulHi.apply(0x8000_0000, 4), 2);
+	}
+
+	#[test]
+	fn test_dump() {
+		use progpow_base::params::ZanoParams;
+
+		for params in [
+			DynParams::of::<KawPowParams>(),
+			DynParams::of::<ZanoParams>(),
+		] {
+			let cpu = dump(7, &params, Backend::Cpu).unwrap();
+			assert_eq!(
+				cpu.lines().count(),
+				params.cnt_cache + params.cnt_math + params.dag_loads
+			);
+			assert!(cpu.starts_with("cache 0: mix["));
+			assert_eq!(dump(7, &params, Backend::Cuda).unwrap(), cpu);
+			assert_eq!(dump(7, &params, Backend::OpenCl).unwrap(), cpu);
+		}
+
+		let kernel = "// Random math and cache operations\n\
+			// random math 0\n\
+			data = mix[1] - mix[2];\n\
+			mix[3] = (mix[3] * 33) + data_dag.s[1];\n\
+			}\n";
+		assert_eq!(
+			dump_kernel(kernel),
+			"? data = mix[1] - mix[2];\ndag 1: mix[3] = mul_add(mix[3], dag[1])\n"
+		);
+	}
+}
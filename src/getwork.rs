@@ -0,0 +1,180 @@
+//! Ethereum-style getwork over HTTP JSON-RPC (`eth_getWork` / `eth_submitWork`), for
+//! mining against a node without a pool.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::types::{h256_from_hex, to_hex, Batch, ProgPowError, H256};
+
+/// How often `Client::run` asks the node for new work by default.
+pub const DEFAULT_POLL: Duration = Duration::from_millis(500);
+
+/// Work returned by `eth_getWork`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Work {
+	pub header_hash: H256,
+	pub seed_hash: H256,
+	/// Big-endian boundary, see `types::difficulty`.
+	pub target: H256,
+	pub height: u64,
+}
+
+pub struct Client {
+	url: String,
+	agent: ureq::Agent,
+	next_id: u64,
+	poll: Duration,
+}
+
+fn protocol<S: Into<String>>(msg: S) -> ProgPowError {
+	ProgPowError::Protocol(msg.into())
+}
+
+fn parse_h256(value: &Value) -> Result<H256, ProgPowError> {
+	value
+		.as_str()
+		.and_then(h256_from_hex)
+		.ok_or_else(|| protocol(format!("expected 32 byte hex, got {}", value)))
+}
+
+/// Parse the `eth_getWork` result. The block number is the optional fourth element;
+/// nodes that leave it out are not supported.
+fn parse_work(result: &Value) -> Result<Work, ProgPowError> {
+	let height = match &result[3] {
+		Value::String(hex) => u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok(),
+		Value::Number(n) => n.as_u64(),
+		_ => None,
+	}
+	.ok_or_else(|| protocol("eth_getWork did not return the block number"))?;
+
+	Ok(Work {
+		header_hash: parse_h256(&result[0])?,
+		seed_hash: parse_h256(&result[1])?,
+		target: parse_h256(&result[2])?,
+		height,
+	})
+}
+
+impl Client {
+	/// `url` is the node's HTTP RPC endpoint, e.g. `http://127.0.0.1:8545`.
+	pub fn new(url: &str) -> Self {
+		Client {
+			url: url.to_string(),
+			agent: ureq::AgentBuilder::new()
+				.timeout(Duration::from_secs(10))
+				.build(),
+			next_id: 1,
+			poll: DEFAULT_POLL,
+		}
+	}
+
+	pub fn set_poll_interval(&mut self, poll: Duration) {
+		self.poll = poll;
+	}
+
+	fn call(&mut self, method: &str, params: Value) -> Result<Value, ProgPowError> {
+		let id = self.next_id;
+		self.next_id += 1;
+		let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+		let body = self
+			.agent
+			.post(&self.url)
+			.set("Content-Type", "application/json")
+			.send_string(&request.to_string())
+			.map_err(|e| ProgPowError::Network(e.to_string()))?
+			.into_string()
+			.map_err(|e| ProgPowError::Network(e.to_string()))?;
+		let response: Value = serde_json::from_str(&body).map_err(|e| protocol(e.to_string()))?;
+		if !response["error"].is_null() {
+			return Err(protocol(format!(
+				"{} failed: {}",
+				method, response["error"]
+			)));
+		}
+		Ok(response["result"].clone())
+	}
+
+	pub fn get_work(&mut self) -> Result<Work, ProgPowError> {
+		let result = self.call("eth_getWork", json!([]))?;
+		parse_work(&result)
+	}
+
+	/// Returns whether the node accepted the solution.
+	pub fn submit_work(
+		&mut self,
+		nonce: u64,
+		header_hash: &H256,
+		mix_hash: &H256,
+	) -> Result<bool, ProgPowError> {
+		let params = json!([
+			to_hex(&nonce.to_be_bytes()),
+			to_hex(header_hash),
+			to_hex(mix_hash),
+		]);
+		Ok(self.call("eth_submitWork", params)? == Value::Bool(true))
+	}
+
+	/// Report the local hashrate (hashes per second) under the 32-byte miner `id`.
+	pub fn submit_hashrate(&mut self, hashrate: u64, id: &H256) -> Result<bool, ProgPowError> {
+		let mut rate = [0u8; 32];
+		rate[24..].copy_from_slice(&hashrate.to_be_bytes());
+		let params = json!([to_hex(&rate), to_hex(id)]);
+		Ok(self.call("eth_submitHashrate", params)? == Value::Bool(true))
+	}
+
+	/// Mine until an error occurs. `search` is called with the current work and a start
+	/// nonce and should search a single batch; the node is polled for new work between
+	/// batches at most every poll interval.
+	pub fn run<F>(&mut self, mut search: F) -> Result<(), ProgPowError>
+	where
+		F: FnMut(&Work, u64) -> Result<Batch, ProgPowError>,
+	{
+		let mut work = self.get_work()?;
+		let mut polled = Instant::now();
+		let mut nonce = 0u64;
+		loop {
+			let batch = search(&work, nonce)?;
+			for (found, mix) in &batch.found {
+				self.submit_work(*found, &work.header_hash, mix)?;
+			}
+			nonce = nonce.wrapping_add(batch.searched);
+
+			let since = polled.elapsed();
+			if since < self.poll && batch.searched == 0 {
+				thread::sleep(self.poll - since);
+			}
+			if polled.elapsed() >= self.poll {
+				let next = self.get_work()?;
+				polled = Instant::now();
+				if next.header_hash != work.header_hash {
+					nonce = 0;
+					work = next;
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_parse_work() {
+		let result = json!([
+			"0x470d42a9f6ea35569d6aa7206cf1d4b292a1bc11b0165523f95bbb8678c85d0e",
+			"0x0000000000000000000000000000000000000000000000000000000000000000",
+			"0x00000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+			"0x35d5b2"
+		]);
+		let work = parse_work(&result).unwrap();
+		assert_eq!(work.header_hash[0], 0x47);
+		assert_eq!(work.target[..4], [0; 4]);
+		assert_eq!(work.height, 3528114);
+
+		assert!(parse_work(&json!([result[0], result[1], result[2]])).is_err());
+	}
+}
@@ -1,17 +1,38 @@
 use dirs;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "parallel")]
+use std::collections::BTreeMap;
 use std::fs;
+use std::io;
+use std::ops::Range;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
 
-use crate::types::{Hardware, PpCompute, ProgPowError, H256};
+#[cfg(feature = "parallel")]
+use super::policy::VerificationPolicy;
+use super::verify_cache::{CacheStats, VerifyCache};
+use crate::epoch::epoch_for_height;
+use crate::params::{with_preset, DynParams, Preset};
+use crate::types::difficulty::{meets_target, target_from_target64};
+use crate::types::{
+	final_hash_bytes, mix_hash_bytes, Hardware, Miner, MiningJob, ProgPowError, ShareStatus,
+	Verifier, H256,
+};
 use progpow_base::params::ProgPowParams;
-use progpow_cpu::cache::NodeCacheBuilder;
-// use progpow_cpu::cache::OptimizeFor;
+use progpow_cpu::cache::{NodeCacheBuilder, OptimizeFor};
+use progpow_cpu::compute::Light;
+use progpow_cpu::dag::FullDag;
+use tracing::{debug, debug_span, trace, warn};
 // use progpow_cpu::compute::{light_compute, PoW};
 
 const CACHE_DIR: &str = "cache";
 const EPIC_HOME: &str = ".epic";
 
-fn get_cache_path() -> Result<PathBuf, ::std::io::Error> {
+pub(crate) fn get_cache_path() -> Result<PathBuf, ::std::io::Error> {
 	// Check if epic dir exists
 	let mut epic_path = match dirs::home_dir() {
 		Some(p) => p,
@@ -28,56 +49,876 @@ fn get_cache_path() -> Result<PathBuf, ::std::io::Error> {
 	Ok(epic_path)
 }
 
-pub struct PpCPU<P: ProgPowParams> {
+/// How light caches are held, see `PpCPU::set_cache_optimization`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CacheOptimization {
+	/// Memory-map cache files, so the OS pages nodes in and out as needed. For verifiers
+	/// on small-RAM machines.
+	Memory,
+	/// Keep caches on the heap, so nodes are never read back from disk. For pool
+	/// validators hashing many shares.
+	#[default]
+	Cpu,
+}
+
+impl From<CacheOptimization> for OptimizeFor {
+	fn from(optimization: CacheOptimization) -> Self {
+		match optimization {
+			CacheOptimization::Memory => OptimizeFor::Memory,
+			CacheOptimization::Cpu => OptimizeFor::Cpu,
+		}
+	}
+}
+
+/// CPU verifier. `P` selects the chain parameters at compile time; `PpCPU<DynParams>`
+/// (see `PpCPU::new_dyn`) selects them at runtime instead.
+pub struct PpCPU<P = DynParams> {
 	cache_builder: NodeCacheBuilder,
+	params: DynParams,
+	/// `params` as a preset the CPU hashes, resolved once for `CpuParams for DynParams`.
+	preset: Option<Preset>,
+	threads: usize,
+	cache_dir: Option<PathBuf>,
+	stop: Arc<AtomicBool>,
+	solutions: Mutex<Vec<(u64, [u32; 8])>>,
+	verify_cache: Option<Mutex<VerifyCache>>,
+	use_full_dag: bool,
+	/// Params name and epoch last looked up, with the full DAG found for it if any.
+	full_dag: Mutex<Option<(&'static str, u64, Option<Arc<FullDag>>)>>,
+	read_only_cache: bool,
+	cache_warning: Option<Box<dyn Fn(&io::Error) + Send + Sync>>,
+	/// Set once a write failure was reported in read-only mode.
+	cache_warned: AtomicBool,
 	_marker: std::marker::PhantomData<P>,
 }
 
+fn default_threads() -> usize {
+	thread::available_parallelism().map_or(1, |n| n.get())
+}
+
 impl<P: ProgPowParams> PpCPU<P> {
 	pub fn new() -> Self {
+		PpCPU::from_params(DynParams::of::<P>())
+	}
+}
+
+impl PpCPU<DynParams> {
+	/// Create a verifier whose chain parameters are chosen at runtime.
+	pub fn new_dyn(params: DynParams) -> Self {
+		PpCPU::from_params(params)
+	}
+}
+
+/// State kept between `PpCPU::verify_with_scratch` calls, one per verifying thread.
+///
+/// It holds the light cache and c_dag of the last epoch verified. The hash itself runs
+/// on stack arrays, so once the cache is loaded verifying another nonce of the same
+/// epoch makes no heap allocation.
+#[derive(Default)]
+pub struct VerifyScratch {
+	/// Params name and epoch of `light`.
+	light: Option<(&'static str, u64, Light)>,
+}
+
+impl VerifyScratch {
+	pub fn new() -> Self {
+		VerifyScratch::default()
+	}
+
+	/// Free the cached light cache.
+	pub fn clear(&mut self) {
+		self.light = None;
+	}
+}
+
+/// Chain parameters a `PpCPU` can verify with, either a compile-time
+/// `ProgPowParams` or a runtime `DynParams`.
+pub trait CpuParams: Sized {
+	fn verify_light(
+		cpu: &PpCPU<Self>,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<([u32; 8], [u32; 8]), ProgPowError>;
+
+	fn verify_scratch(
+		cpu: &PpCPU<Self>,
+		scratch: &mut VerifyScratch,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<([u32; 8], [u32; 8]), ProgPowError>;
+
+	fn search(
+		cpu: &PpCPU<Self>,
+		header_hash: &H256,
+		height: u64,
+		target: &H256,
+		start_nonce: u64,
+		count: u64,
+	) -> Result<Vec<(u64, [u32; 8])>, ProgPowError>;
+
+	fn search_scratch(
+		cpu: &PpCPU<Self>,
+		scratch: &mut VerifyScratch,
+		header_hash: &H256,
+		height: u64,
+		target: &H256,
+		start_nonce: u64,
+		count: u64,
+	) -> Result<Vec<(u64, [u32; 8])>, ProgPowError>;
+
+	#[cfg(feature = "parallel")]
+	fn verify_batch(
+		cpu: &PpCPU<Self>,
+		headers: &[(H256, u64, u64)],
+		threads: usize,
+		policy: &dyn VerificationPolicy,
+	) -> Result<Vec<Option<([u32; 8], [u32; 8])>>, ProgPowError>;
+}
+
+impl<P: ProgPowParams> CpuParams for P {
+	fn verify_light(
+		cpu: &PpCPU<Self>,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<([u32; 8], [u32; 8]), ProgPowError> {
+		cpu.verify_with::<P>(header_hash, height, nonce)
+	}
+
+	fn verify_scratch(
+		cpu: &PpCPU<Self>,
+		scratch: &mut VerifyScratch,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<([u32; 8], [u32; 8]), ProgPowError> {
+		cpu.verify_scratch_with::<P>(scratch, header_hash, height, nonce)
+	}
+
+	fn search(
+		cpu: &PpCPU<Self>,
+		header_hash: &H256,
+		height: u64,
+		target: &H256,
+		start_nonce: u64,
+		count: u64,
+	) -> Result<Vec<(u64, [u32; 8])>, ProgPowError> {
+		cpu.search_with::<P>(header_hash, height, target, start_nonce, count)
+	}
+
+	fn search_scratch(
+		cpu: &PpCPU<Self>,
+		scratch: &mut VerifyScratch,
+		header_hash: &H256,
+		height: u64,
+		target: &H256,
+		start_nonce: u64,
+		count: u64,
+	) -> Result<Vec<(u64, [u32; 8])>, ProgPowError> {
+		cpu.search_scratch_with::<P>(scratch, header_hash, height, target, start_nonce, count)
+	}
+
+	#[cfg(feature = "parallel")]
+	fn verify_batch(
+		cpu: &PpCPU<Self>,
+		headers: &[(H256, u64, u64)],
+		threads: usize,
+		policy: &dyn VerificationPolicy,
+	) -> Result<Vec<Option<([u32; 8], [u32; 8])>>, ProgPowError> {
+		cpu.verify_batch_with::<P>(headers, threads, policy)
+	}
+}
+
+impl CpuParams for DynParams {
+	fn verify_light(
+		cpu: &PpCPU<Self>,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<([u32; 8], [u32; 8]), ProgPowError> {
+		with_preset!(preset: cpu.preset, P => cpu.verify_with::<P>(header_hash, height, nonce))
+	}
+
+	fn verify_scratch(
+		cpu: &PpCPU<Self>,
+		scratch: &mut VerifyScratch,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<([u32; 8], [u32; 8]), ProgPowError> {
+		with_preset!(preset: cpu.preset, P => {
+			cpu.verify_scratch_with::<P>(scratch, header_hash, height, nonce)
+		})
+	}
+
+	fn search(
+		cpu: &PpCPU<Self>,
+		header_hash: &H256,
+		height: u64,
+		target: &H256,
+		start_nonce: u64,
+		count: u64,
+	) -> Result<Vec<(u64, [u32; 8])>, ProgPowError> {
+		with_preset!(preset: cpu.preset, P => {
+			cpu.search_with::<P>(header_hash, height, target, start_nonce, count)
+		})
+	}
+
+	fn search_scratch(
+		cpu: &PpCPU<Self>,
+		scratch: &mut VerifyScratch,
+		header_hash: &H256,
+		height: u64,
+		target: &H256,
+		start_nonce: u64,
+		count: u64,
+	) -> Result<Vec<(u64, [u32; 8])>, ProgPowError> {
+		with_preset!(preset: cpu.preset, P => {
+			cpu.search_scratch_with::<P>(scratch, header_hash, height, target, start_nonce, count)
+		})
+	}
+
+	#[cfg(feature = "parallel")]
+	fn verify_batch(
+		cpu: &PpCPU<Self>,
+		headers: &[(H256, u64, u64)],
+		threads: usize,
+		policy: &dyn VerificationPolicy,
+	) -> Result<Vec<Option<([u32; 8], [u32; 8])>>, ProgPowError> {
+		with_preset!(preset: cpu.preset, P => cpu.verify_batch_with::<P>(headers, threads, policy))
+	}
+}
+
+impl<T> PpCPU<T> {
+	fn from_params(params: DynParams) -> Self {
 		PpCPU {
 			cache_builder: NodeCacheBuilder::new(None),
+			preset: Preset::of(&params).and_then(Preset::cpu),
+			params,
+			threads: default_threads(),
+			cache_dir: None,
+			stop: Arc::new(AtomicBool::new(false)),
+			solutions: Mutex::new(Vec::new()),
+			verify_cache: None,
+			use_full_dag: true,
+			full_dag: Mutex::new(None),
+			read_only_cache: false,
+			cache_warning: None,
+			cache_warned: AtomicBool::new(false),
 			_marker: std::marker::PhantomData,
 		}
 	}
-}
 
-impl<P: ProgPowParams> PpCompute for PpCPU<P> {
-	fn init(&mut self) -> Result<(), ProgPowError> {
-		Ok(())
+	pub fn params(&self) -> &DynParams {
+		&self.params
 	}
 
-	fn verify(
+	pub(crate) fn preset(&self) -> Option<Preset> {
+		self.preset
+	}
+
+	/// Number of threads used by `search` and `compute`.
+	pub fn set_threads(&mut self, threads: usize) {
+		self.threads = threads.max(1);
+	}
+
+	pub fn threads(&self) -> usize {
+		self.threads
+	}
+
+	/// Ask a running `search` or `compute` to return early. The flag stays set, so one
+	/// that is just starting returns too, until `resume`.
+	pub fn stop(&self) {
+		self.stop.store(true, Ordering::SeqCst);
+	}
+
+	/// Clear a `stop`, when setting up the next job.
+	pub fn resume(&self) {
+		self.stop.store(false, Ordering::SeqCst);
+	}
+
+	/// Flag that stops the search when set, for use from other threads.
+	pub fn stop_handle(&self) -> Arc<AtomicBool> {
+		self.stop.clone()
+	}
+
+	/// Directory the light caches are stored in, `~/.epic/main/cache` by default.
+	pub fn set_cache_dir<D: Into<PathBuf>>(&mut self, dir: D) {
+		self.cache_dir = Some(dir.into());
+	}
+
+	/// How light caches built or loaded from now on are held, `CacheOptimization::Cpu`
+	/// by default.
+	pub fn set_cache_optimization(&mut self, optimization: CacheOptimization) {
+		self.cache_builder = self
+			.cache_builder
+			.clone()
+			.optimize_for(OptimizeFor::from(optimization));
+	}
+
+	/// Treat the cache directory as read-only, e.g. one prebaked into an image and
+	/// mounted read-only. Light caches are still loaded from it, and those of missing
+	/// epochs are built in memory as usual, but failing to write them is expected: only
+	/// the first failure is reported, to `set_cache_warning` or as a log warning.
+	pub fn set_read_only_cache(&mut self, read_only: bool) {
+		self.read_only_cache = read_only;
+		self.cache_warned.store(false, Ordering::Relaxed);
+	}
+
+	/// Call `handler` with the error when a light cache cannot be written in read-only
+	/// mode, instead of logging it.
+	pub fn set_cache_warning<F>(&mut self, handler: F)
+	where
+		F: Fn(&io::Error) + Send + Sync + 'static,
+	{
+		self.cache_warning = Some(Box::new(handler));
+	}
+
+	fn cache_write_failed(&self, e: &io::Error) {
+		if !self.read_only_cache {
+			warn!(error = %e, "light cache file write failed");
+		} else if !self.cache_warned.swap(true, Ordering::Relaxed) {
+			match self.cache_warning {
+				Some(ref handler) => handler(e),
+				None => warn!(error = %e, "read-only cache directory, light caches kept in memory"),
+			}
+		} else {
+			trace!(error = %e, "light cache file not written");
+		}
+	}
+
+	/// Whether `verify` reads DAG items from a full DAG file in the cache directory when
+	/// one exists for the epoch, instead of computing them from the light cache. On by
+	/// default. The file is looked for once per epoch and never built here.
+	pub fn set_full_dag(&mut self, enabled: bool) {
+		self.use_full_dag = enabled;
+		*self.full_dag.lock().unwrap() = None;
+	}
+
+	/// Remember the last `capacity` results of `verify`, keyed by header, height and
+	/// nonce, so a header checked again during propagation or a reorg is not rehashed.
+	/// 0 disables the cache, which is the default.
+	pub fn set_verify_cache(&mut self, capacity: usize) {
+		self.verify_cache = match capacity {
+			0 => None,
+			_ => Some(Mutex::new(VerifyCache::new(capacity))),
+		};
+	}
+
+	/// Hits and misses of the `verify` cache, if enabled.
+	pub fn verify_cache_stats(&self) -> Option<CacheStats> {
+		self.verify_cache
+			.as_ref()
+			.map(|cache| cache.lock().unwrap().stats())
+	}
+
+	/// Look the result up in the `verify` cache, if enabled, before running `verify`.
+	fn cached<F>(
 		&self,
 		header_hash: &H256,
 		height: u64,
 		nonce: u64,
-	) -> Result<([u32; 8], [u32; 8]), ProgPowError> {
-		let path_cache: PathBuf = get_cache_path().unwrap();
-
-		// Using standalone functions from progpow-light if builder methods are not available or matching?
-		// Actually, let's try to use the builder methods first, assuming they exist but need generic P.
-		// If they don't exist, I'll need to check cache.rs.
-		// But assuming the error was "unexpected argument", the method exists.
-		let light = match self.cache_builder.light_from_file::<P>(&path_cache, height) {
-			Ok(l) => l,
+		verify: F,
+	) -> Result<([u32; 8], [u32; 8]), ProgPowError>
+	where
+		F: FnOnce() -> Result<([u32; 8], [u32; 8]), ProgPowError>,
+	{
+		let cache = match self.verify_cache {
+			Some(ref cache) => cache,
+			None => return verify(),
+		};
+		if let Some(result) = cache.lock().unwrap().get(header_hash, height, nonce) {
+			return Ok(result);
+		}
+		// Not held across the hash, so other threads keep hitting the cache meanwhile.
+		let result = verify()?;
+		cache
+			.lock()
+			.unwrap()
+			.insert(header_hash, height, nonce, result);
+		Ok(result)
+	}
+
+	/// Take the `(nonce, mix)` pairs found by `compute` so far.
+	pub fn solutions(&self) -> Vec<(u64, [u32; 8])> {
+		std::mem::take(&mut *self.solutions.lock().unwrap())
+	}
+
+	fn cache_path(&self) -> PathBuf {
+		match self.cache_dir {
+			Some(ref dir) => dir.clone(),
+			None => get_cache_path().unwrap(),
+		}
+	}
+
+	pub(crate) fn light<P: ProgPowParams>(&self, height: u64) -> Light {
+		let path_cache = self.cache_path();
+		let _span = debug_span!("epoch_build", params = P::NAME, height).entered();
+
+		match self.cache_builder.light_from_file::<P>(&path_cache, height) {
+			Ok(l) => {
+				trace!("light cache loaded from file");
+				l
+			}
 			Err(_e) => {
+				let start = Instant::now();
 				let mut light = self.cache_builder.light::<P>(&path_cache, height);
+				debug!(elapsed = ?start.elapsed(), "light cache built");
 				if let Err(e) = light.to_file() {
-					println!("Light cache file write error: {}", e);
+					self.cache_write_failed(&e);
 				}
 				light
 			}
+		}
+	}
+
+	/// The full DAG of `height`, if enabled and on disk.
+	fn full_dag<P: ProgPowParams>(&self, height: u64) -> Option<Arc<FullDag>> {
+		if !self.use_full_dag {
+			return None;
+		}
+		let key = (P::NAME, epoch_for_height::<P>(height));
+		let mut slot = self.full_dag.lock().unwrap();
+		if let Some((name, epoch, ref dag)) = *slot {
+			if (name, epoch) == key {
+				return dag.clone();
+			}
+		}
+		// Unmap the previous epoch's DAG before mapping the next one.
+		*slot = None;
+		let dag = match self
+			.cache_builder
+			.full_dag_from_file::<P>(&self.cache_path(), height)
+		{
+			Ok(dag) => {
+				debug!(params = P::NAME, height, "verifying with the full DAG");
+				Some(Arc::new(dag))
+			}
+			Err(e) => {
+				trace!(error = %e, "no full DAG, verifying with the light cache");
+				None
+			}
 		};
+		*slot = Some((key.0, key.1, dag.clone()));
+		dag
+	}
+
+	/// Hash with the full DAG when there is one, computing DAG items from `light`
+	/// otherwise.
+	fn hash<P: ProgPowParams>(
+		&self,
+		light: &Light,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> ([u32; 8], [u32; 8]) {
+		match self.full_dag::<P>(height) {
+			Some(dag) => light.compute_with_dag::<P>(&dag, header_hash, nonce, height),
+			None => light.compute::<P>(header_hash, nonce, height),
+		}
+	}
+
+	fn verify_with<P: ProgPowParams>(
+		&self,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<([u32; 8], [u32; 8]), ProgPowError> {
+		let light = self.light::<P>(height);
+		Ok(self.hash::<P>(&light, header_hash, height, nonce))
+	}
+
+	fn verify_scratch_with<P: ProgPowParams>(
+		&self,
+		scratch: &mut VerifyScratch,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<([u32; 8], [u32; 8]), ProgPowError> {
+		let light = self.scratch_light::<P>(scratch, height);
+		Ok(self.hash::<P>(light, header_hash, height, nonce))
+	}
+
+	/// The light cache of `height` in `scratch`, loaded if it holds another epoch's.
+	fn scratch_light<'a, P: ProgPowParams>(
+		&self,
+		scratch: &'a mut VerifyScratch,
+		height: u64,
+	) -> &'a Light {
+		let key = (P::NAME, epoch_for_height::<P>(height));
+		if scratch
+			.light
+			.as_ref()
+			.map(|(name, epoch, _)| (*name, *epoch))
+			!= Some(key)
+		{
+			// Free the old cache before loading the next one.
+			scratch.light = None;
+			scratch.light = Some((key.0, key.1, self.light::<P>(height)));
+		}
+		&scratch.light.as_ref().unwrap().2
+	}
+
+	fn search_with<P: ProgPowParams>(
+		&self,
+		header_hash: &H256,
+		height: u64,
+		target: &H256,
+		start_nonce: u64,
+		count: u64,
+	) -> Result<Vec<(u64, [u32; 8])>, ProgPowError> {
+		let light = self.light::<P>(height);
+		Ok(self.search_light::<P>(&light, header_hash, height, target, start_nonce, count))
+	}
+
+	fn search_scratch_with<P: ProgPowParams>(
+		&self,
+		scratch: &mut VerifyScratch,
+		header_hash: &H256,
+		height: u64,
+		target: &H256,
+		start_nonce: u64,
+		count: u64,
+	) -> Result<Vec<(u64, [u32; 8])>, ProgPowError> {
+		let light = self.scratch_light::<P>(scratch, height);
+		Ok(self.search_light::<P>(light, header_hash, height, target, start_nonce, count))
+	}
+
+	/// Hash `count` nonces from `start_nonce` over all threads, returning those whose
+	/// final hash is at or below `target` along with their mix.
+	fn search_light<P: ProgPowParams>(
+		&self,
+		light: &Light,
+		header_hash: &H256,
+		height: u64,
+		target: &H256,
+		start_nonce: u64,
+		count: u64,
+	) -> Vec<(u64, [u32; 8])> {
+		let threads = self.threads as u64;
+		let found = Mutex::new(Vec::new());
+
+		thread::scope(|s| {
+			for t in 0..threads.min(count.max(1)) {
+				let (found, stop) = (&found, &self.stop);
+				s.spawn(move || {
+					let mut i = t;
+					while i < count && !stop.load(Ordering::Relaxed) {
+						let nonce = start_nonce.wrapping_add(i);
+						let (value, mix) = light.compute::<P>(header_hash, nonce, height);
+						if meets_target(&final_hash_bytes(&value), target) {
+							found.lock().unwrap().push((nonce, mix));
+						}
+						i += threads;
+					}
+				});
+			}
+		});
+
+		let mut found = found.into_inner().unwrap();
+		found.sort_by_key(|&(nonce, _)| nonce);
+		found
+	}
+
+	#[cfg(feature = "parallel")]
+	fn verify_batch_with<P: ProgPowParams>(
+		&self,
+		headers: &[(H256, u64, u64)],
+		threads: usize,
+		policy: &dyn VerificationPolicy,
+	) -> Result<Vec<Option<([u32; 8], [u32; 8])>>, ProgPowError> {
+		let pool = rayon::ThreadPoolBuilder::new()
+			.num_threads(threads.max(1))
+			.build()
+			.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+		let mut epochs = BTreeMap::<u64, Vec<usize>>::new();
+		for (i, (header_hash, height, _)) in headers.iter().enumerate() {
+			// Epochs with nothing to verify never build their cache.
+			if !policy.should_verify(header_hash, *height) {
+				continue;
+			}
+			epochs
+				.entry(epoch_for_height::<P>(*height))
+				.or_default()
+				.push(i);
+		}
+
+		let mut results = vec![None; headers.len()];
+		for indices in epochs.values() {
+			// One epoch's cache at a time, so a burst spanning many epochs does not hold
+			// them all.
+			let height = headers[indices[0]].1;
+			let light = self.light::<P>(height);
+			let dag = self.full_dag::<P>(height);
+			let hashed: Vec<_> = pool.install(|| {
+				indices
+					.par_iter()
+					.map(|&i| {
+						let (ref header_hash, height, nonce) = headers[i];
+						match dag {
+							Some(ref dag) => {
+								light.compute_with_dag::<P>(dag, header_hash, nonce, height)
+							}
+							None => light.compute::<P>(header_hash, nonce, height),
+						}
+					})
+					.collect()
+			});
+			for (&i, result) in indices.iter().zip(hashed) {
+				results[i] = Some(result);
+			}
+		}
+		Ok(results)
+	}
+}
+
+impl<P: CpuParams> PpCPU<P> {
+	/// Hash `count` nonces from `start_nonce` on `threads()` threads using the light
+	/// cache, returning every `(nonce, mix)` whose final hash meets `target`, the upper
+	/// 64 bits of a `types::difficulty` target. Returns early, with what was found, if
+	/// `stop` is called.
+	pub fn search(
+		&self,
+		header_hash: &H256,
+		height: u64,
+		target: u64,
+		start_nonce: u64,
+		count: u64,
+	) -> Result<Vec<(u64, [u32; 8])>, ProgPowError> {
+		let target = target_from_target64(target);
+		P::search(self, header_hash, height, &target, start_nonce, count)
+	}
+
+	/// `search` with the light cache kept in `scratch`, so repeated searches of an epoch
+	/// do not reload it.
+	pub fn search_with_scratch(
+		&self,
+		scratch: &mut VerifyScratch,
+		header_hash: &H256,
+		height: u64,
+		target: u64,
+		start_nonce: u64,
+		count: u64,
+	) -> Result<Vec<(u64, [u32; 8])>, ProgPowError> {
+		let target = target_from_target64(target);
+		P::search_scratch(
+			self,
+			scratch,
+			header_hash,
+			height,
+			&target,
+			start_nonce,
+			count,
+		)
+	}
+
+	/// `search` against a full 256-bit big-endian `target`.
+	pub fn search_target(
+		&self,
+		header_hash: &H256,
+		height: u64,
+		target: &H256,
+		start_nonce: u64,
+		count: u64,
+	) -> Result<Vec<(u64, [u32; 8])>, ProgPowError> {
+		P::search(self, header_hash, height, target, start_nonce, count)
+	}
+
+	/// `Verifier::verify` of each `(header_hash, height, nonce)` in `headers`, for nodes
+	/// verifying thousands of headers during initial block download. Items are grouped
+	/// by epoch, each epoch's cache is built once and its items are hashed on a rayon
+	/// pool of `threads` threads. Results are in the order of `headers`, `None` for the
+	/// items `policy` skips; pass `VerifyAll` to hash every item.
+	#[cfg(feature = "parallel")]
+	pub fn verify_headers_parallel(
+		&self,
+		headers: &[(H256, u64, u64)],
+		threads: usize,
+		policy: &dyn VerificationPolicy,
+	) -> Result<Vec<Option<([u32; 8], [u32; 8])>>, ProgPowError> {
+		P::verify_batch(self, headers, threads, policy)
+	}
+
+	/// Like `Verifier::verify`, keeping the light cache in `scratch` so pools validating
+	/// many shares per epoch neither reload it nor allocate per share.
+	pub fn verify_with_scratch(
+		&self,
+		scratch: &mut VerifyScratch,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<([u32; 8], [u32; 8]), ProgPowError> {
+		self.cached(header_hash, height, nonce, || {
+			P::verify_scratch(self, scratch, header_hash, height, nonce)
+		})
+	}
 
-		Ok(light.compute::<P>(header_hash, nonce, height))
+	/// Check a submitted share: `expected_mix` is compared with the computed mix hash and
+	/// the final hash with `target`, both in the byte order of `Verifier::verify_hash`.
+	pub fn validate_share(
+		&self,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+		expected_mix: &H256,
+		target: &H256,
+	) -> Result<ShareStatus, ProgPowError> {
+		let (final_hash, mix_hash) = self.verify_hash(header_hash, height, nonce)?;
+		if &mix_hash != expected_mix {
+			Ok(ShareStatus::BadMix)
+		} else if !meets_target(&final_hash, target) {
+			Ok(ShareStatus::LowDifficulty)
+		} else {
+			Ok(ShareStatus::Ok)
+		}
 	}
+}
 
-	fn compute(&self, _header: [u8; 32], _height: u64, _epoch: i32, _boundary: u64) {
-		unimplemented!()
+impl<P: CpuParams> Verifier for PpCPU<P> {
+	fn verify(
+		&self,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<([u32; 8], [u32; 8]), ProgPowError> {
+		self.cached(header_hash, height, nonce, || {
+			P::verify_light(self, header_hash, height, nonce)
+		})
+	}
+}
+
+impl<P: CpuParams> Miner for PpCPU<P> {
+	fn init(&mut self) -> Result<(), ProgPowError> {
+		Ok(())
+	}
+
+	/// Search `nonce_range` until a solution is found or `stop` is called, returning at
+	/// once while stopped. Results are read with `solutions`.
+	fn compute(&self, job: &MiningJob, nonce_range: Range<u64>) -> Result<(), ProgPowError> {
+		const BATCH: u64 = 1 << 12;
+
+		job.validate(self.params.epoch_length)?;
+		let nonce_range = job.extranonce.clamp(nonce_range);
+		let mut nonce = nonce_range.start;
+		while nonce < nonce_range.end && !self.stop.load(Ordering::SeqCst) {
+			let count = BATCH.min(nonce_range.end - nonce);
+			let found = self.search_target(&job.header, job.height, &job.target, nonce, count)?;
+			if !found.is_empty() {
+				self.solutions.lock().unwrap().extend(found);
+				break;
+			}
+			nonce += count;
+		}
+		Ok(())
+	}
+
+	fn take_solutions(&self) -> Vec<(u64, H256)> {
+		self.solutions()
+			.into_iter()
+			.map(|(nonce, mix)| (nonce, mix_hash_bytes(&mix)))
+			.collect()
 	}
 
 	fn hardware(&self) -> Hardware {
 		Hardware::CPU
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use progpow_base::params::KawPowParams;
+	use std::alloc::{GlobalAlloc, Layout, System};
+	use std::cell::Cell;
+
+	thread_local! {
+		/// Heap allocations made by this thread, counted by `CountingAllocator`.
+		static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+	}
+
+	struct CountingAllocator;
+
+	unsafe impl GlobalAlloc for CountingAllocator {
+		unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+			let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+			System.alloc(layout)
+		}
+
+		unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+			System.dealloc(ptr, layout)
+		}
+	}
+
+	#[global_allocator]
+	static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+	#[test]
+	fn test_read_only_cache_warns_once() {
+		let warnings = Arc::new(Mutex::new(Vec::new()));
+		let seen = warnings.clone();
+		let mut cpu = PpCPU::<KawPowParams>::new();
+		// Neither readable nor writable, as a read-only mount without this epoch.
+		cpu.set_cache_dir(
+			std::env::temp_dir().join(format!("progpow-ro-{}/missing", std::process::id())),
+		);
+		cpu.set_read_only_cache(true);
+		cpu.set_cache_warning(move |e| seen.lock().unwrap().push(e.kind()));
+
+		let first = cpu.verify(&[0u8; 32], 0, 0).unwrap();
+		assert_eq!(cpu.verify(&[0u8; 32], 0, 0).unwrap(), first);
+		assert_eq!(*warnings.lock().unwrap(), vec![io::ErrorKind::NotFound]);
+	}
+
+	#[test]
+	fn test_cache_optimizations_agree() {
+		let dir = std::env::temp_dir().join(format!("progpow-opt-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let mut results = Vec::new();
+		for optimization in [CacheOptimization::Memory, CacheOptimization::Cpu] {
+			let mut cpu = PpCPU::<KawPowParams>::new();
+			cpu.set_cache_dir(&dir);
+			cpu.set_cache_optimization(optimization);
+			results.push(cpu.verify(&[1u8; 32], 0, 7).unwrap());
+		}
+		assert_eq!(results[0], results[1]);
+		let _ = fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn test_verify_with_scratch_does_not_allocate() {
+		let dir = std::env::temp_dir().join(format!("progpow-alloc-{}", std::process::id()));
+		let mut cpu = PpCPU::new_dyn(DynParams::of::<KawPowParams>());
+		cpu.set_cache_dir(&dir);
+		let mut scratch = VerifyScratch::new();
+		// Loads the light cache and looks for a full DAG.
+		cpu.verify_with_scratch(&mut scratch, &[2u8; 32], 0, 0)
+			.unwrap();
+
+		let before = ALLOCATIONS.with(Cell::get);
+		for nonce in 1..8 {
+			cpu.verify_with_scratch(&mut scratch, &[2u8; 32], 0, nonce)
+				.unwrap();
+		}
+		assert_eq!(ALLOCATIONS.with(Cell::get), before);
+		let _ = fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn test_unverified_presets_rejected() {
+		use crate::params::{FiroPowParams, ProgPow093Params};
+		for params in [
+			DynParams::of::<ProgPow093Params>(),
+			DynParams::of::<FiroPowParams>(),
+		] {
+			let cpu = PpCPU::new_dyn(params);
+			assert!(matches!(
+				cpu.verify(&[0u8; 32], 0, 0),
+				Err(ProgPowError::UnsupportedParams)
+			));
+		}
+	}
+}
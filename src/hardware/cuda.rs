@@ -0,0 +1,436 @@
+//! CUDA miner driven entirely from Rust: the generated kernel is compiled with NVRTC and
+//! launched through `cust`, without the C++ ethminer bridge.
+
+use cust::context::Context;
+use cust::device::{Device, DeviceAttribute};
+use cust::launch;
+use cust::memory::{
+	AsyncCopyDestination, CopyDestination, DeviceBuffer, DevicePointer, DeviceSliceIndex,
+	LockedBuffer,
+};
+use cust::module::Module;
+use cust::stream::{Stream, StreamFlags};
+
+use super::kernel::{
+	dag_words, driver, header_words, host_dag_chunked, host_light_cache, nonces_per_launch,
+	DagUploadOptions, Found, SearchResults,
+};
+use super::nvrtc;
+use super::tune::{self, LaunchConfig};
+use crate::debug::KernelDebug;
+use crate::epoch::{epoch_for_height, next_period_height};
+use crate::generator::dag::{generate_dag_kernel, DAG_ENTRY_POINT};
+use crate::generator::{Backend, PROGPOW_LANES, SEARCH_RESULTS};
+use crate::kernel_cache::{CompileService, KernelCache};
+use crate::types::{ProgPowError, H256};
+use crate::CDag;
+use progpow_base::params::ProgPowParams;
+use std::path::Path;
+use std::sync::Arc;
+
+/// One CUDA device with its DAG, light cache and result buffer.
+pub struct CudaMiner {
+	device: Device,
+	stream: Stream,
+	kernels: KernelCache,
+	compiler: Option<Arc<CompileService>>,
+	module: Option<(u64, Module)>,
+	/// The DAG generation kernel, compiled on first use.
+	dag_module: Option<Module>,
+	dag: Option<(u64, DeviceBuffer<u32>)>,
+	cdag: DeviceBuffer<u32>,
+	/// Sized for the kernels of `search_results`.
+	results: DeviceBuffer<u8>,
+	launch: LaunchConfig,
+	dag_upload: DagUploadOptions,
+	// Dropped last, every other resource belongs to it.
+	_context: Context,
+}
+
+impl CudaMiner {
+	pub fn new(ordinal: u32) -> Result<Self, ProgPowError> {
+		cust::init(cust::CudaFlags::empty()).map_err(driver)?;
+		let device = Device::get_device(ordinal).map_err(driver)?;
+		let context = Context::new(device).map_err(driver)?;
+		let stream = Stream::new(StreamFlags::NON_BLOCKING, None).map_err(driver)?;
+		let cdag = DeviceBuffer::zeroed(crate::PROGPOW_CACHE_WORDS).map_err(driver)?;
+		let kernels = KernelCache::new();
+		let results =
+			DeviceBuffer::zeroed(SearchResults::bytes(kernels.search_results())).map_err(driver)?;
+
+		Ok(CudaMiner {
+			device,
+			stream,
+			kernels,
+			compiler: None,
+			module: None,
+			dag_module: None,
+			dag: None,
+			cdag,
+			results,
+			launch: LaunchConfig::default(),
+			dag_upload: DagUploadOptions::default(),
+			_context: context,
+		})
+	}
+
+	/// Nonces searched by each call to `search`.
+	pub fn batch_size(&self) -> u64 {
+		nonces_per_launch(self.launch.work_items())
+	}
+
+	pub fn launch_config(&self) -> LaunchConfig {
+		self.launch
+	}
+
+	pub fn set_launch_config(&mut self, launch: LaunchConfig) {
+		self.launch = launch;
+	}
+
+	/// How the next DAG is built and uploaded. By default it is generated on the device;
+	/// otherwise pinned staging copies on `streams` streams at once.
+	pub fn set_dag_upload(&mut self, options: DagUploadOptions) {
+		self.dag_upload = options;
+	}
+
+	/// Keep compiled kernels in `dir` so a restarted miner loads them instead of
+	/// compiling again.
+	pub fn set_kernel_dir(&mut self, dir: &Path) -> Result<(), ProgPowError> {
+		let version = nvrtc::version().map_err(ProgPowError::Driver)?;
+		self.kernels = KernelCache::with_dir(dir)
+			.map_err(driver)?
+			.with_driver(&version);
+		Ok(())
+	}
+
+	/// Share compiled kernels with the other devices of the rig through `service`
+	/// instead of compiling them for this device alone.
+	pub fn set_compile_service(&mut self, service: Arc<CompileService>) {
+		self.compiler = Some(service);
+	}
+
+	/// Result slots of the kernels this device runs, from the cache that generates them.
+	fn search_results(&self) -> usize {
+		match self.compiler {
+			Some(ref service) => service.cache().search_results(),
+			None => self.kernels.search_results(),
+		}
+	}
+
+	/// The `sm_XY` architecture of this device.
+	pub fn arch(&self) -> Result<String, ProgPowError> {
+		let (major, minor) = self.compute_capability()?;
+		Ok(format!("sm_{}{}", major, minor))
+	}
+
+	fn compute_capability(&self) -> Result<(u32, u32), ProgPowError> {
+		let major = self
+			.device
+			.get_attribute(DeviceAttribute::ComputeCapabilityMajor)
+			.map_err(driver)? as u32;
+		let minor = self
+			.device
+			.get_attribute(DeviceAttribute::ComputeCapabilityMinor)
+			.map_err(driver)? as u32;
+		Ok((major, minor))
+	}
+
+	/// Use the launch shape saved for this device model in `dir`, benchmarking
+	/// `LaunchConfig::candidates` at `height` to create it on first use.
+	pub fn autotune<P: ProgPowParams>(
+		&mut self,
+		dir: &Path,
+		height: u64,
+	) -> Result<LaunchConfig, ProgPowError> {
+		self.prepare::<P>(height)?;
+		let name = self.device.name().map_err(driver)?;
+		let path = tune::tuning_path(dir, "cuda", &name);
+		let header = [0u8; 32];
+		let launch = tune::load_or_tune(&path, |config| {
+			tune::measure(config, |config| {
+				self.launch = config;
+				// A zero target never matches, so every launch does the full batch.
+				self.search::<P>(&header, height, 0, 0)?;
+				Ok(self.batch_size())
+			})
+		})?;
+		self.launch = launch;
+		Ok(launch)
+	}
+
+	/// Compile the kernel for the period of `height` and build the DAG for its epoch,
+	/// skipping whatever is already current.
+	pub fn prepare<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		self.prepare_kernel::<P>(height)?;
+		self.prepare_dag::<P>(height)
+	}
+
+	/// Compile the kernel for the period of `height` unless it is already current.
+	pub fn prepare_kernel<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		let period = P::prog_seed(height);
+		if self.module.as_ref().map(|(p, _)| *p) != Some(period) {
+			let ptx = self.kernel_binary::<P>(height)?;
+			let ptx = String::from_utf8_lossy(&ptx);
+			let module = Module::from_ptx(&ptx, &[]).map_err(driver)?;
+			let bytes = SearchResults::bytes(self.search_results());
+			if self.results.len() != bytes {
+				self.results = DeviceBuffer::zeroed(bytes).map_err(driver)?;
+			}
+			self.kernels.evict_before(period);
+			if let Some(ref service) = self.compiler {
+				service.evict_before(period);
+			}
+			self.module = Some((period, module));
+		}
+		Ok(())
+	}
+
+	/// Compile the kernel of the period after the one of `height` ahead of time, so the
+	/// switch only has to load it. Returns the first height of that period.
+	pub fn precompile_next_period<P: ProgPowParams>(
+		&self,
+		height: u64,
+	) -> Result<u64, ProgPowError> {
+		let next = next_period_height::<P>(height);
+		self.kernel_binary::<P>(next)?;
+		Ok(next)
+	}
+
+	fn kernel_binary<P: ProgPowParams>(&self, height: u64) -> Result<Arc<Vec<u8>>, ProgPowError> {
+		let (major, minor) = self.compute_capability()?;
+		let arch = format!("sm_{}{}", major, minor);
+		let compile = |source: &str| nvrtc::compile_ptx(source, major, minor);
+		match self.compiler {
+			Some(ref service) => service.compile::<P, _>(Backend::Cuda, &arch, height, compile),
+			None => self
+				.kernels
+				.binary_for_arch::<P, _, _>(Backend::Cuda, &arch, height, compile),
+		}
+		.map_err(ProgPowError::Driver)
+	}
+
+	/// Build the DAG for the epoch of `height` unless it is already current.
+	pub fn prepare_dag<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		let epoch = epoch_for_height::<P>(height);
+		if self.dag.as_ref().map(|(e, _)| *e) != Some(epoch) {
+			// Free the old DAG before allocating the next one.
+			self.dag = None;
+			self.dag = Some((epoch, self.build_dag::<P>(height)?));
+		}
+		Ok(())
+	}
+
+	fn build_dag<P: ProgPowParams>(
+		&mut self,
+		height: u64,
+	) -> Result<DeviceBuffer<u32>, ProgPowError> {
+		let needed = (dag_words::<P>(height) * 4) as u64;
+		let (free, _) = cust::memory::mem_get_info().map_err(driver)?;
+		if (free as u64) < needed {
+			return Err(ProgPowError::InsufficientVram {
+				needed,
+				available: free as u64,
+			});
+		}
+
+		let mut dag = unsafe { DeviceBuffer::<u32>::uninitialized(dag_words::<P>(height)) }
+			.map_err(driver)?;
+		let options = self.dag_upload;
+		let cdag = if options.on_device {
+			self.generate_on_device::<P>(&dag, height, &options)?
+		} else if options.pinned {
+			self.upload_pinned::<P>(&mut dag, height, &options)?
+		} else {
+			host_dag_chunked::<P, _>(height, options.chunk_nodes(), |offset, chunk| {
+				dag.index(offset..offset + chunk.len())
+					.copy_from(chunk)
+					.map_err(|_| ProgPowError::DAG)
+			})?
+		};
+		self.cdag.copy_from(&cdag[..]).map_err(driver)?;
+		Ok(dag)
+	}
+
+	/// Upload the light cache and generate the DAG from it with `generator::dag`'s
+	/// kernel, `chunk_nodes` nodes per launch.
+	fn generate_on_device<P: ProgPowParams>(
+		&mut self,
+		dag: &DeviceBuffer<u32>,
+		height: u64,
+		options: &DagUploadOptions,
+	) -> Result<CDag, ProgPowError> {
+		if self.dag_module.is_none() {
+			let source = generate_dag_kernel(Backend::Cuda).ok_or(ProgPowError::DAG)?;
+			let (major, minor) = self.compute_capability()?;
+			let ptx = nvrtc::compile_ptx(&source, major, minor).map_err(ProgPowError::Driver)?;
+			let ptx = String::from_utf8_lossy(&ptx);
+			self.dag_module = Some(Module::from_ptx(&ptx, &[]).map_err(driver)?);
+		}
+		let function = self
+			.dag_module
+			.as_ref()
+			.unwrap()
+			.get_function(DAG_ENTRY_POINT)
+			.map_err(driver)?;
+
+		let (light_words, cdag) = host_light_cache::<P>(height)?;
+		let light = DeviceBuffer::from_slice(&light_words).map_err(driver)?;
+		let light_nodes = (light_words.len() / 16) as u32;
+		let dag_nodes = (dag_words::<P>(height) / 16) as u32;
+		let threads = 128u32;
+		let chunk = (options.chunk_nodes() as u32).max(threads);
+		let stream = &self.stream;
+		for start in (0..dag_nodes).step_by(chunk as usize) {
+			let blocks = (chunk.min(dag_nodes - start) + threads - 1) / threads;
+			unsafe {
+				launch!(function<<<blocks, threads, 0, stream>>>(
+					start,
+					dag_nodes,
+					light.as_device_ptr(),
+					light_nodes,
+					dag.as_device_ptr()
+				))
+				.map_err(|_| ProgPowError::DAG)?;
+			}
+		}
+		// The light cache is freed on return, so every launch must be done with it.
+		self.stream.synchronize().map_err(|_| ProgPowError::DAG)?;
+		Ok(cdag)
+	}
+
+	/// Upload through one page-locked staging buffer per stream, so the host generates
+	/// the next chunk while earlier ones are still copying.
+	fn upload_pinned<P: ProgPowParams>(
+		&self,
+		dag: &mut DeviceBuffer<u32>,
+		height: u64,
+		options: &DagUploadOptions,
+	) -> Result<CDag, ProgPowError> {
+		let chunk_words = options.chunk_nodes() * 16;
+		let mut slots = (0..options.streams.max(1))
+			.map(|_| {
+				let staging = unsafe { LockedBuffer::<u32>::uninitialized(chunk_words) }?;
+				let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+				Ok((staging, stream))
+			})
+			.collect::<cust::error::CudaResult<Vec<_>>>()
+			.map_err(driver)?;
+
+		let mut next = 0;
+		let cdag = host_dag_chunked::<P, _>(height, options.chunk_nodes(), |offset, chunk| {
+			let (staging, stream) = &mut slots[next];
+			next = (next + 1) % options.streams.max(1);
+			// The slot's previous copy must finish before its staging buffer is reused.
+			stream.synchronize().map_err(|_| ProgPowError::DAG)?;
+			staging[..chunk.len()].copy_from_slice(chunk);
+			unsafe {
+				dag.index(offset..offset + chunk.len())
+					.async_copy_from(&staging[..chunk.len()], stream)
+			}
+			.map_err(|_| ProgPowError::DAG)
+		});
+		// Wait for every copy even on failure, as they still read the staging buffers.
+		for (_, stream) in &slots {
+			stream.synchronize().map_err(|_| ProgPowError::DAG)?;
+		}
+		cdag
+	}
+
+	/// Search `batch_size()` nonces from `start_nonce`, returning each nonce whose
+	/// result is at or below `target` together with its mix, see `Found`.
+	pub fn search<P: ProgPowParams>(
+		&mut self,
+		header: &H256,
+		height: u64,
+		target: u64,
+		start_nonce: u64,
+	) -> Result<Found, ProgPowError> {
+		self.prepare::<P>(height)?;
+		let module = &self.module.as_ref().ok_or(ProgPowError::NoInitialized)?.1;
+		let dag = &self.dag.as_ref().ok_or(ProgPowError::NoInitialized)?.1;
+		let function = module
+			.get_function(Backend::Cuda.entry_point())
+			.map_err(driver)?;
+		let [h0, h1, h2, h3] = header_words(header);
+
+		let mut bytes = vec![0u8; self.results.len()];
+		self.results.copy_from(&bytes[..]).map_err(driver)?;
+		let stream = &self.stream;
+		unsafe {
+			launch!(function<<<self.launch.blocks, self.launch.threads, 0, stream>>>(
+				start_nonce,
+				target,
+				h0,
+				h1,
+				h2,
+				h3,
+				dag.as_device_ptr(),
+				self.cdag.as_device_ptr(),
+				self.results.as_device_ptr(),
+				DevicePointer::<u32>::null()
+			))
+			.map_err(driver)?;
+		}
+		self.stream.synchronize().map_err(driver)?;
+
+		self.results.copy_to(&mut bytes[..]).map_err(driver)?;
+		Ok(SearchResults::from_bytes(&bytes).to_found())
+	}
+
+	/// The mix of `nonce` alone, with the kernel's trace of it when built with
+	/// `debug-trace`. For comparing the device with the CPU, see `equivalence`.
+	pub fn hash_one<P: ProgPowParams>(
+		&mut self,
+		header: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<([u32; 8], Option<KernelDebug>), ProgPowError> {
+		self.prepare::<P>(height)?;
+		let module = &self.module.as_ref().ok_or(ProgPowError::NoInitialized)?.1;
+		let dag = &self.dag.as_ref().ok_or(ProgPowError::NoInitialized)?.1;
+		let function = module
+			.get_function(Backend::Cuda.entry_point())
+			.map_err(driver)?;
+		let [h0, h1, h2, h3] = header_words(header);
+		let trace = DeviceBuffer::from_slice(&KernelDebug::buffer()).map_err(driver)?;
+
+		let mut bytes = vec![0u8; self.results.len()];
+		self.results.copy_from(&bytes[..]).map_err(driver)?;
+		// A single block of no more nonces than there are result slots: under the maximum
+		// target each is reported, `nonce` included. It is gid 0, which the trace records.
+		let threads = (self.search_results().min(SEARCH_RESULTS) * PROGPOW_LANES) as u32;
+		let stream = &self.stream;
+		unsafe {
+			launch!(function<<<1, threads, 0, stream>>>(
+				nonce,
+				u64::MAX,
+				h0,
+				h1,
+				h2,
+				h3,
+				dag.as_device_ptr(),
+				self.cdag.as_device_ptr(),
+				self.results.as_device_ptr(),
+				trace.as_device_ptr()
+			))
+			.map_err(driver)?;
+		}
+		self.stream.synchronize().map_err(driver)?;
+
+		self.results.copy_to(&mut bytes[..]).map_err(driver)?;
+		let mix = SearchResults::from_bytes(&bytes)
+			.found()
+			.iter()
+			.find(|r| r.nonce == nonce)
+			.map(|r| r.mix)
+			.ok_or_else(|| ProgPowError::Driver(format!("nonce {} was not reported", nonce)))?;
+		let trace = if cfg!(feature = "debug-trace") {
+			let mut words = KernelDebug::buffer();
+			trace.copy_to(&mut words[..]).map_err(driver)?;
+			KernelDebug::from_trace(&words)
+		} else {
+			None
+		};
+		Ok((mix, trace))
+	}
+}
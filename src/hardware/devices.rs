@@ -0,0 +1,140 @@
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use super::{create_miner, HardwareSpec};
+use crate::types::{Batch, Hardware, MiningJob, ProgPowError, H256};
+
+/// Nonces one device searches per round.
+fn round_size(hardware: &Hardware) -> u64 {
+	match hardware {
+		Hardware::CPU => 1 << 10,
+		Hardware::GPU => 1 << 24,
+	}
+}
+
+fn exited() -> ProgPowError {
+	ProgPowError::Driver("device thread exited".to_string())
+}
+
+/// A miner on a thread of its own. The miner is created on that thread, so it need not
+/// be `Send`.
+pub struct DeviceThread {
+	size: u64,
+	work: mpsc::Sender<(MiningJob, u64)>,
+	found: mpsc::Receiver<Result<Vec<(u64, H256)>, ProgPowError>>,
+}
+
+impl DeviceThread {
+	/// Create the miner for `spec` and `init` it, returning its error if that fails.
+	pub fn spawn(spec: HardwareSpec) -> Result<Self, ProgPowError> {
+		let (work_tx, work_rx) = mpsc::channel::<(MiningJob, u64)>();
+		let (found_tx, found_rx) = mpsc::channel();
+		let (ready_tx, ready_rx) = mpsc::channel();
+
+		thread::spawn(move || {
+			let mut miner = create_miner(spec);
+			match miner.init() {
+				Ok(()) => {
+					let _ = ready_tx.send(Ok(round_size(&miner.hardware())));
+				}
+				Err(e) => {
+					let _ = ready_tx.send(Err(e));
+					return;
+				}
+			}
+			for (job, start) in work_rx {
+				let size = round_size(&miner.hardware());
+				let result = miner
+					.compute(&job, start..start.saturating_add(size))
+					.map(|()| miner.take_solutions());
+				if found_tx.send(result).is_err() {
+					break;
+				}
+			}
+		});
+
+		let size = ready_rx.recv().map_err(|_| exited())??;
+		Ok(DeviceThread {
+			size,
+			work: work_tx,
+			found: found_rx,
+		})
+	}
+
+	/// Nonces one round covers.
+	pub fn round_size(&self) -> u64 {
+		self.size
+	}
+
+	pub(crate) fn start(&self, job: &MiningJob, start: u64) -> Result<(), ProgPowError> {
+		self.work.send((job.clone(), start)).map_err(|_| exited())
+	}
+
+	pub(crate) fn finish(&self) -> Result<Vec<(u64, H256)>, ProgPowError> {
+		self.found.recv().map_err(|_| exited())?
+	}
+
+	/// `finish`, giving up after `timeout`. The round is then still running, so the
+	/// thread must not be given more work.
+	pub(crate) fn finish_within(
+		&self,
+		timeout: Duration,
+	) -> Result<Vec<(u64, H256)>, ProgPowError> {
+		match self.found.recv_timeout(timeout) {
+			Ok(found) => found,
+			Err(RecvTimeoutError::Timeout) => Err(ProgPowError::Driver(format!(
+				"search hung, no result after {:?}",
+				timeout
+			))),
+			Err(RecvTimeoutError::Disconnected) => Err(exited()),
+		}
+	}
+}
+
+/// Device threads searching consecutive nonce ranges of the same job.
+pub struct Devices {
+	devices: Vec<DeviceThread>,
+}
+
+impl Devices {
+	pub fn spawn(specs: Vec<HardwareSpec>) -> Result<Self, ProgPowError> {
+		let devices = specs
+			.into_iter()
+			.map(DeviceThread::spawn)
+			.collect::<Result<Vec<_>, _>>()?;
+		Ok(Devices { devices })
+	}
+
+	pub fn len(&self) -> usize {
+		self.devices.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.devices.is_empty()
+	}
+
+	/// Nonces one `search` covers over all devices.
+	pub fn round_size(&self) -> u64 {
+		self.devices.iter().map(|d| d.size).sum()
+	}
+
+	/// Search one round of `job` from `start` on every device at once, each taking the
+	/// range after the previous one. Suits the search closure of the pool and node
+	/// clients' `run`.
+	pub fn search(&self, job: &MiningJob, start: u64) -> Result<Batch, ProgPowError> {
+		let mut next = start;
+		for device in &self.devices {
+			device.start(job, next)?;
+			next = next.wrapping_add(device.size);
+		}
+		let mut batch = Batch {
+			searched: self.round_size(),
+			found: Vec::new(),
+		};
+		for device in &self.devices {
+			batch.found.extend(device.finish()?);
+		}
+		Ok(batch)
+	}
+}
@@ -0,0 +1,131 @@
+//! Process-wide CPU verifiers, for libraries that only need `verify` and would rather not
+//! thread a `PpCPU` through every call site.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
+
+use super::cpu::{CacheOptimization, PpCPU};
+use crate::params::DynParams;
+use crate::types::{ProgPowError, Verifier, H256};
+use progpow_base::params::ProgPowParams;
+
+/// Settings the global verifiers are created with.
+#[derive(Debug, Clone, Default)]
+pub struct GlobalVerifierBuilder {
+	cache_dir: Option<PathBuf>,
+	verify_cache: usize,
+	threads: Option<usize>,
+	cache_optimization: CacheOptimization,
+}
+
+impl GlobalVerifierBuilder {
+	/// See `PpCPU::set_cache_dir`.
+	pub fn cache_dir<D: Into<PathBuf>>(mut self, dir: D) -> Self {
+		self.cache_dir = Some(dir.into());
+		self
+	}
+
+	/// See `PpCPU::set_verify_cache`.
+	pub fn verify_cache(mut self, capacity: usize) -> Self {
+		self.verify_cache = capacity;
+		self
+	}
+
+	/// See `PpCPU::set_threads`.
+	pub fn threads(mut self, threads: usize) -> Self {
+		self.threads = Some(threads);
+		self
+	}
+
+	/// See `PpCPU::set_cache_optimization`.
+	pub fn cache_optimization(mut self, optimization: CacheOptimization) -> Self {
+		self.cache_optimization = optimization;
+		self
+	}
+
+	/// Use these settings for the global verifiers. Those already created are replaced;
+	/// callers still holding one keep using it.
+	pub fn install(self) {
+		let mut state = STATE.lock().unwrap();
+		state.builder = self;
+		state.verifiers.clear();
+	}
+
+	fn build(&self, params: DynParams) -> PpCPU {
+		let mut cpu = PpCPU::new_dyn(params);
+		if let Some(ref dir) = self.cache_dir {
+			cpu.set_cache_dir(dir.clone());
+		}
+		cpu.set_verify_cache(self.verify_cache);
+		cpu.set_cache_optimization(self.cache_optimization);
+		if let Some(threads) = self.threads {
+			cpu.set_threads(threads);
+		}
+		cpu
+	}
+}
+
+#[derive(Default)]
+struct State {
+	builder: GlobalVerifierBuilder,
+	/// One verifier per params. There are only ever a few, so a list is enough.
+	verifiers: Vec<(DynParams, Arc<PpCPU>)>,
+}
+
+static STATE: Lazy<Mutex<State>> = Lazy::new(Default::default);
+
+/// Shared, thread-safe `PpCPU`s, one per params, created on first use with the settings
+/// of the last `GlobalVerifierBuilder::install`.
+pub struct GlobalVerifier;
+
+impl GlobalVerifier {
+	pub fn builder() -> GlobalVerifierBuilder {
+		GlobalVerifierBuilder::default()
+	}
+
+	/// The verifier for `params`.
+	pub fn get(params: &DynParams) -> Arc<PpCPU> {
+		let mut state = STATE.lock().unwrap();
+		if let Some((_, cpu)) = state.verifiers.iter().find(|(p, _)| p == params) {
+			return cpu.clone();
+		}
+		let cpu = Arc::new(state.builder.build(params.clone()));
+		state.verifiers.push((params.clone(), cpu.clone()));
+		cpu
+	}
+
+	/// The verifier for `P`.
+	pub fn of<P: ProgPowParams>() -> Arc<PpCPU> {
+		GlobalVerifier::get(&DynParams::of::<P>())
+	}
+
+	/// `Verifier::verify` with the verifier for `params`.
+	pub fn verify(
+		params: &DynParams,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<([u32; 8], [u32; 8]), ProgPowError> {
+		GlobalVerifier::get(params).verify(header_hash, height, nonce)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use progpow_base::params::{KawPowParams, ZanoParams};
+
+	#[test]
+	fn test_global_verifier_is_shared_per_params() {
+		let kawpow = GlobalVerifier::of::<KawPowParams>();
+		assert!(Arc::ptr_eq(
+			&kawpow,
+			&GlobalVerifier::get(&DynParams::of::<KawPowParams>())
+		));
+		let zano = GlobalVerifier::of::<ZanoParams>();
+		assert!(!Arc::ptr_eq(&kawpow, &zano));
+		assert!(zano.params().is::<ZanoParams>());
+	}
+}
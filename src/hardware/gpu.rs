@@ -1,48 +1,212 @@
-use crate::types::{Hardware, PpCompute, ProgPowError, H256};
-use progpow_gpu::{Driver, GPU};
+use std::ops::Range;
+use std::sync::atomic::{AtomicI32, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::types::{Hardware, Miner, MiningJob, Mix, ProgPowError, Verifier, H256};
+use progpow_gpu::{epoch_for_height, Driver, Solution, GPU};
+use tracing::{debug, debug_span, trace, warn};
+
+/// Default of `PpGPU::set_prefetch_blocks`.
+const PREFETCH_BLOCKS: u64 = 20;
 
 pub struct PpGPU {
 	pub gpu: GPU,
+	/// Solutions collected by `Miner::compute` and not yet taken.
+	found: Mutex<Vec<Solution>>,
+	/// Epoch of the last job, -1 before the first.
+	epoch: AtomicI32,
+	prefetch_blocks: AtomicU64,
+}
+
+impl From<progpow_gpu::JobError> for ProgPowError {
+	fn from(e: progpow_gpu::JobError) -> Self {
+		match e {
+			progpow_gpu::JobError::ZeroTarget => ProgPowError::ZeroTarget,
+			progpow_gpu::JobError::EpochMismatch {
+				height,
+				epoch,
+				expected,
+			} => ProgPowError::EpochMismatch {
+				height,
+				epoch,
+				expected,
+			},
+		}
+	}
+}
+
+/// The device-side copy of `job`, for `epoch`.
+fn gpu_job(job: &MiningJob, epoch: i32) -> progpow_gpu::MiningJob {
+	let extranonce = progpow_gpu::Extranonce::new(job.extranonce.value, job.extranonce.bytes);
+	progpow_gpu::MiningJob::new(job.job_id, job.header, job.height, epoch, job.target)
+		.with_extranonce(extranonce)
 }
 
 impl PpGPU {
-	pub fn new(device: u32, driver: u8) -> Self {
-		let dr: Driver = Driver::from_u8(driver);
+	/// `Driver::Auto` picks the first compiled-in driver that works on `device`.
+	pub fn new(device: u32, driver: Driver) -> Self {
 		PpGPU {
-			gpu: GPU::new(device, dr),
+			gpu: GPU::new(device, driver),
+			found: Mutex::new(Vec::new()),
+			epoch: AtomicI32::new(-1),
+			prefetch_blocks: AtomicU64::new(PREFETCH_BLOCKS),
+		}
+	}
+
+	/// The next solution for the current job, if any.
+	pub fn get_solutions(&self) -> Option<Solution> {
+		let mut found = self.found.lock().unwrap();
+		if found.is_empty() {
+			found.extend(self.gpu.solutions().unwrap_or_default());
+		}
+		if found.is_empty() {
+			None
+		} else {
+			Some(found.remove(0))
 		}
 	}
 
-	pub fn compute_with_startnonce(&self, header: [u8; 32], height: u64, epoch: i32, target: u64, start_nonce: u64) {
-		self.gpu.compute(header, height, epoch, target, start_nonce);
+	/// See `GPU::set_intensity`.
+	pub fn set_intensity(&self, percent: u32) {
+		self.gpu.set_intensity(percent);
 	}
 
-	pub fn get_solutions(&self) -> Option<(u64, [u8; 32])> {
-		self.gpu.solutions().unwrap()
+	/// See `GPU::set_host_dag_limit`.
+	pub fn set_host_dag_limit(&self, bytes: u64) {
+		self.gpu.set_host_dag_limit(bytes);
 	}
-}
 
-impl PpCompute for PpGPU {
-	fn init(&mut self) -> Result<(), ProgPowError> {
-		self.gpu.init();
-		Ok(())
+	/// See `GPU::set_epoch_length`. Jobs whose `epoch` is not the epoch of their height at
+	/// this length are rejected.
+	pub fn set_epoch_length(&self, blocks: u64) {
+		self.gpu.set_epoch_length(blocks);
+	}
+
+	/// Start building the next epoch's DAG, see `GPU::prefetch`, once a job is at most
+	/// `blocks` below the boundary. 0 rebuilds at the boundary instead.
+	pub fn set_prefetch_blocks(&self, blocks: u64) {
+		self.prefetch_blocks.store(blocks, Ordering::Relaxed);
+	}
+
+	/// Epoch of the last job passed to `compute`.
+	pub fn epoch(&self) -> Option<u64> {
+		match self.epoch.load(Ordering::Relaxed) {
+			-1 => None,
+			epoch => Some(epoch as u64),
+		}
 	}
 
+	/// `e` as a `ProgPowError`, `DeviceLost` once the C++ miner has given up on the
+	/// device.
+	fn driver_error(&self, e: &str) -> ProgPowError {
+		if self.gpu.is_lost() {
+			ProgPowError::DeviceLost(format!("{} device {}", self.gpu.driver, self.gpu.device))
+		} else {
+			e.into()
+		}
+	}
+
+	/// The epoch of `height`, prefetching the next one when the boundary is near. Fails if
+	/// the device cannot hold the DAG of a new epoch.
+	fn track_epoch(&self, height: u64) -> Result<i32, ProgPowError> {
+		let epoch_length = self.gpu.epoch_length();
+		let epoch = epoch_for_height(height, epoch_length);
+		let previous = self.epoch.swap(epoch, Ordering::Relaxed);
+		if previous != epoch {
+			if let Err(e) = self.gpu.check_vram(height) {
+				self.epoch.store(previous, Ordering::Relaxed);
+				return Err(ProgPowError::InsufficientVram {
+					needed: e.needed,
+					available: e.available,
+				});
+			}
+			if previous != -1 {
+				debug!(from = previous, to = epoch, "epoch switch");
+			}
+		}
+
+		let next = epoch as u64 + 1;
+		let blocks = self.prefetch_blocks.load(Ordering::Relaxed);
+		if blocks > 0 && next * epoch_length - height <= blocks {
+			match self.gpu.prepare_epoch(next) {
+				Ok(started) => trace!(next_epoch = next, started, "prefetch"),
+				Err(e) => warn!(error = e, "prefetch failed"),
+			}
+		}
+		Ok(epoch)
+	}
+}
+
+/// Runs the kernel for the single nonce, see `GPU::hash_one`.
+impl Verifier for PpGPU {
 	fn verify(
 		&self,
 		header: &[u8; 32],
 		height: u64,
 		nonce: u64,
 	) -> Result<([u32; 8], [u32; 8]), ProgPowError> {
-		unimplemented!()
+		let (final_hash, mix_hash) = self
+			.gpu
+			.hash_one(header, height, nonce)
+			.map_err(|e| self.driver_error(e))?;
+		let mut value = [0u32; 8];
+		for (word, bytes) in value.iter_mut().zip(final_hash.chunks_exact(4)) {
+			*word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+		}
+		Ok((value, Mix::from_le_bytes(&mix_hash).0))
+	}
+}
+
+impl Miner for PpGPU {
+	fn init(&mut self) -> Result<(), ProgPowError> {
+		self.gpu
+			.init()
+			.map(|_| ())
+			.ok_or(ProgPowError::NoInitialized)
+	}
+
+	/// Searches whole batches, so the last one may run past `nonce_range.end`, though never
+	/// past the end of `job.extranonce`. `job.epoch` must be the epoch of `job.height`, see
+	/// `set_epoch_length`, and the next epoch's DAG is built ahead of the boundary, see
+	/// `set_prefetch_blocks`.
+	fn compute(&self, job: &MiningJob, nonce_range: Range<u64>) -> Result<(), ProgPowError> {
+		let _span =
+			debug_span!("gpu_search", device = self.gpu.device, job_id = job.job_id).entered();
+		job.validate(self.gpu.epoch_length())?;
+		let batch = self.gpu.batch_size().max(1);
+		let nonce_range = job.extranonce.clamp(nonce_range);
+		let job = gpu_job(job, self.track_epoch(job.height)?);
+		if self.gpu.job_id() != Some(job.job_id) && job.floods_results(batch) {
+			warn!(
+				expected = job.expected_solutions(batch),
+				capacity = progpow_gpu::SEARCH_RESULTS,
+				"target so easy that each batch finds more solutions than the device reports"
+			);
+		}
+		let mut nonce = nonce_range.start;
+		while nonce < nonce_range.end {
+			trace!(start_nonce = nonce, batch, "search round");
+			self.gpu
+				.compute(&job, nonce)
+				.map_err(|e| self.driver_error(e))?;
+			let solutions = self.gpu.solutions().map_err(|e| self.driver_error(e))?;
+			if !solutions.is_empty() {
+				debug!(count = solutions.len(), "solutions found");
+				self.found.lock().unwrap().extend(solutions);
+				break;
+			}
+			nonce = nonce.saturating_add(batch);
+		}
+		Ok(())
 	}
 
-	fn compute(&self, header: [u8; 32], height: u64, epoch: i32, target: u64) {
-		self.gpu.compute(header, height, epoch, target, 0);
+	fn take_solutions(&self) -> Vec<(u64, H256)> {
+		let mut found = std::mem::take(&mut *self.found.lock().unwrap());
+		found.extend(self.gpu.solutions().unwrap_or_default());
+		found.into_iter().map(|s| (s.nonce, s.mix_hash)).collect()
 	}
 
 	fn hardware(&self) -> Hardware {
 		Hardware::GPU
 	}
 }
-
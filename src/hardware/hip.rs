@@ -0,0 +1,411 @@
+//! HIP miner for AMD GPUs on ROCm: the generated kernel is compiled with hiprtc and
+//! launched through the HIP module API, like `CudaMiner` does on NVIDIA.
+
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::path::Path;
+use std::ptr;
+use std::sync::Arc;
+
+use super::hiprtc;
+use super::kernel::{
+	dag_words, driver, header_words, host_dag_chunked, nonces_per_launch, DagUploadOptions, Found,
+	SearchResults,
+};
+use super::tune::{self, LaunchConfig};
+use crate::epoch::{epoch_for_height, next_period_height};
+use crate::generator::Backend;
+use crate::kernel_cache::KernelCache;
+use crate::types::{ProgPowError, H256};
+use progpow_base::params::ProgPowParams;
+
+type HipError = c_int;
+type HipStream = *mut c_void;
+type HipModule = *mut c_void;
+type HipFunction = *mut c_void;
+
+const HIP_SUCCESS: HipError = 0;
+
+#[link(name = "amdhip64")]
+extern "C" {
+	fn hipInit(flags: c_uint) -> HipError;
+	fn hipSetDevice(device: c_int) -> HipError;
+	fn hipDeviceGetName(name: *mut c_char, len: c_int, device: c_int) -> HipError;
+	fn hipMemGetInfo(free: *mut usize, total: *mut usize) -> HipError;
+	fn hipMalloc(ptr: *mut *mut c_void, size: usize) -> HipError;
+	fn hipFree(ptr: *mut c_void) -> HipError;
+	fn hipMemcpyHtoD(dst: *mut c_void, src: *const c_void, size: usize) -> HipError;
+	fn hipMemcpyDtoH(dst: *mut c_void, src: *mut c_void, size: usize) -> HipError;
+	fn hipStreamCreate(stream: *mut HipStream) -> HipError;
+	fn hipStreamSynchronize(stream: HipStream) -> HipError;
+	fn hipStreamDestroy(stream: HipStream) -> HipError;
+	fn hipModuleLoadData(module: *mut HipModule, image: *const c_void) -> HipError;
+	fn hipModuleUnload(module: HipModule) -> HipError;
+	fn hipModuleGetFunction(
+		function: *mut HipFunction,
+		module: HipModule,
+		name: *const c_char,
+	) -> HipError;
+	fn hipModuleLaunchKernel(
+		function: HipFunction,
+		grid_x: c_uint,
+		grid_y: c_uint,
+		grid_z: c_uint,
+		block_x: c_uint,
+		block_y: c_uint,
+		block_z: c_uint,
+		shared_bytes: c_uint,
+		stream: HipStream,
+		params: *mut *mut c_void,
+		extra: *mut *mut c_void,
+	) -> HipError;
+	fn hipGetErrorString(error: HipError) -> *const c_char;
+}
+
+fn check(error: HipError, call: &str) -> Result<(), ProgPowError> {
+	if error == HIP_SUCCESS {
+		return Ok(());
+	}
+	let message = unsafe { CStr::from_ptr(hipGetErrorString(error)) };
+	Err(ProgPowError::Driver(format!(
+		"{}: {}",
+		call,
+		message.to_string_lossy()
+	)))
+}
+
+/// Device allocation, freed on drop.
+struct DeviceMem {
+	ptr: *mut c_void,
+	bytes: usize,
+}
+
+impl DeviceMem {
+	fn new(bytes: usize) -> Result<Self, ProgPowError> {
+		let mut ptr = ptr::null_mut();
+		check(unsafe { hipMalloc(&mut ptr, bytes) }, "hipMalloc")?;
+		Ok(DeviceMem { ptr, bytes })
+	}
+
+	/// Copy `data` to `offset` bytes into the allocation.
+	fn upload<T: Copy>(&self, offset: usize, data: &[T]) -> Result<(), ProgPowError> {
+		let bytes = mem::size_of_val(data);
+		if offset + bytes > self.bytes {
+			return Err(ProgPowError::Driver(
+				"upload past the allocation".to_string(),
+			));
+		}
+		let dst = unsafe { (self.ptr as *mut u8).add(offset) } as *mut c_void;
+		check(
+			unsafe { hipMemcpyHtoD(dst, data.as_ptr() as *const c_void, bytes) },
+			"hipMemcpyHtoD",
+		)
+	}
+
+	fn download<T: Copy>(&self, out: &mut [T]) -> Result<(), ProgPowError> {
+		let bytes = mem::size_of_val(out).min(self.bytes);
+		check(
+			unsafe { hipMemcpyDtoH(out.as_mut_ptr() as *mut c_void, self.ptr, bytes) },
+			"hipMemcpyDtoH",
+		)
+	}
+}
+
+impl Drop for DeviceMem {
+	fn drop(&mut self) {
+		unsafe {
+			hipFree(self.ptr);
+		}
+	}
+}
+
+/// A loaded code object and its search function.
+struct Module {
+	module: HipModule,
+	function: HipFunction,
+}
+
+impl Module {
+	fn load(code: &[u8]) -> Result<Self, ProgPowError> {
+		let mut module = ptr::null_mut();
+		check(
+			unsafe { hipModuleLoadData(&mut module, code.as_ptr() as *const c_void) },
+			"hipModuleLoadData",
+		)?;
+		let name = CString::new(Backend::Hip.entry_point()).unwrap();
+		let mut function = ptr::null_mut();
+		if let Err(e) = check(
+			unsafe { hipModuleGetFunction(&mut function, module, name.as_ptr()) },
+			"hipModuleGetFunction",
+		) {
+			unsafe {
+				hipModuleUnload(module);
+			}
+			return Err(e);
+		}
+		Ok(Module { module, function })
+	}
+}
+
+impl Drop for Module {
+	fn drop(&mut self) {
+		unsafe {
+			hipModuleUnload(self.module);
+		}
+	}
+}
+
+/// One HIP device with its DAG, light cache and result buffer.
+///
+/// HIP keeps the current device per thread, so every call first selects this one.
+pub struct HipMiner {
+	device: c_int,
+	stream: HipStream,
+	kernels: KernelCache,
+	module: Option<(u64, Module)>,
+	dag: Option<(u64, DeviceMem)>,
+	cdag: DeviceMem,
+	results: DeviceMem,
+	launch: LaunchConfig,
+	dag_upload: DagUploadOptions,
+}
+
+impl HipMiner {
+	pub fn new(ordinal: u32) -> Result<Self, ProgPowError> {
+		let device = ordinal as c_int;
+		check(unsafe { hipInit(0) }, "hipInit")?;
+		check(unsafe { hipSetDevice(device) }, "hipSetDevice")?;
+		let mut stream = ptr::null_mut();
+		check(unsafe { hipStreamCreate(&mut stream) }, "hipStreamCreate")?;
+		let kernels = KernelCache::new();
+		let results = DeviceMem::new(SearchResults::bytes(kernels.search_results()))?;
+		Ok(HipMiner {
+			device,
+			stream,
+			kernels,
+			module: None,
+			dag: None,
+			cdag: DeviceMem::new(crate::PROGPOW_CACHE_WORDS * 4)?,
+			results,
+			launch: LaunchConfig::default(),
+			dag_upload: DagUploadOptions::default(),
+		})
+	}
+
+	fn select(&self) -> Result<(), ProgPowError> {
+		check(unsafe { hipSetDevice(self.device) }, "hipSetDevice")
+	}
+
+	pub fn name(&self) -> Result<String, ProgPowError> {
+		let mut name = [0 as c_char; 256];
+		check(
+			unsafe { hipDeviceGetName(name.as_mut_ptr(), name.len() as c_int, self.device) },
+			"hipDeviceGetName",
+		)?;
+		Ok(unsafe { CStr::from_ptr(name.as_ptr()) }
+			.to_string_lossy()
+			.into_owned())
+	}
+
+	/// Keep compiled kernels in `dir` so a restarted miner loads them instead of
+	/// compiling again.
+	pub fn set_kernel_dir(&mut self, dir: &Path) -> Result<(), ProgPowError> {
+		let version = hiprtc::version().map_err(ProgPowError::Driver)?;
+		self.kernels = KernelCache::with_dir(dir)
+			.map_err(driver)?
+			.with_driver(&version);
+		Ok(())
+	}
+
+	/// Nonces searched by each call to `search`.
+	pub fn batch_size(&self) -> u64 {
+		nonces_per_launch(self.launch.work_items())
+	}
+
+	pub fn launch_config(&self) -> LaunchConfig {
+		self.launch
+	}
+
+	pub fn set_launch_config(&mut self, launch: LaunchConfig) {
+		self.launch = launch;
+	}
+
+	/// How the next DAG is uploaded. Only `chunk_bytes` applies to this backend.
+	pub fn set_dag_upload(&mut self, options: DagUploadOptions) {
+		self.dag_upload = options;
+	}
+
+	/// Use the launch shape saved for this device model in `dir`, benchmarking
+	/// `LaunchConfig::candidates` at `height` to create it on first use.
+	pub fn autotune<P: ProgPowParams>(
+		&mut self,
+		dir: &Path,
+		height: u64,
+	) -> Result<LaunchConfig, ProgPowError> {
+		self.prepare::<P>(height)?;
+		let path = tune::tuning_path(dir, "hip", &self.name()?);
+		let header = [0u8; 32];
+		let launch = tune::load_or_tune(&path, |config| {
+			tune::measure(config, |config| {
+				self.launch = config;
+				// A zero target never matches, so every launch does the full batch.
+				self.search::<P>(&header, height, 0, 0)?;
+				Ok(self.batch_size())
+			})
+		})?;
+		self.launch = launch;
+		Ok(launch)
+	}
+
+	/// Compile the kernel for the period of `height` and build the DAG for its epoch,
+	/// skipping whatever is already current.
+	pub fn prepare<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		self.prepare_kernel::<P>(height)?;
+		self.prepare_dag::<P>(height)
+	}
+
+	/// Compile the kernel for the period of `height` unless it is already current.
+	pub fn prepare_kernel<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		let period = P::prog_seed(height);
+		if self.module.as_ref().map(|(p, _)| *p) != Some(period) {
+			let code = self.kernel_binary::<P>(height)?;
+			let module = Module::load(&code)?;
+			self.kernels.evict_before(period);
+			self.module = Some((period, module));
+		}
+		Ok(())
+	}
+
+	/// Compile the kernel of the period after the one of `height` ahead of time, so the
+	/// switch only has to load it. Returns the first height of that period.
+	pub fn precompile_next_period<P: ProgPowParams>(
+		&self,
+		height: u64,
+	) -> Result<u64, ProgPowError> {
+		let next = next_period_height::<P>(height);
+		self.kernel_binary::<P>(next)?;
+		Ok(next)
+	}
+
+	fn kernel_binary<P: ProgPowParams>(&self, height: u64) -> Result<Arc<Vec<u8>>, ProgPowError> {
+		self.select()?;
+		// hiprtc compiles for the current device, so code objects are kept per model.
+		let arch = self.name()?;
+		self.kernels
+			.binary_for_arch::<P, _, _>(Backend::Hip, &arch, height, hiprtc::compile_code_object)
+			.map_err(ProgPowError::Driver)
+	}
+
+	/// Build the DAG for the epoch of `height` unless it is already current.
+	pub fn prepare_dag<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		let epoch = epoch_for_height::<P>(height);
+		if self.dag.as_ref().map(|(e, _)| *e) != Some(epoch) {
+			self.select()?;
+			// Free the old DAG before allocating the next one.
+			self.dag = None;
+			self.dag = Some((epoch, self.build_dag::<P>(height)?));
+		}
+		Ok(())
+	}
+
+	fn build_dag<P: ProgPowParams>(&mut self, height: u64) -> Result<DeviceMem, ProgPowError> {
+		let needed = dag_words::<P>(height) * 4;
+		let (mut free, mut total) = (0, 0);
+		check(
+			unsafe { hipMemGetInfo(&mut free, &mut total) },
+			"hipMemGetInfo",
+		)?;
+		if free < needed {
+			return Err(ProgPowError::InsufficientVram {
+				needed: needed as u64,
+				available: free as u64,
+			});
+		}
+
+		let dag = DeviceMem::new(needed)?;
+		let chunk_nodes = self.dag_upload.chunk_nodes();
+		let cdag = host_dag_chunked::<P, _>(height, chunk_nodes, |offset, chunk| {
+			dag.upload(offset * 4, chunk).map_err(|_| ProgPowError::DAG)
+		})?;
+		self.cdag.upload(0, &cdag[..])?;
+		Ok(dag)
+	}
+
+	/// Search `batch_size()` nonces from `start_nonce`, returning each nonce whose
+	/// result is at or below `target` together with its mix, see `Found`.
+	pub fn search<P: ProgPowParams>(
+		&mut self,
+		header: &H256,
+		height: u64,
+		target: u64,
+		start_nonce: u64,
+	) -> Result<Found, ProgPowError> {
+		self.prepare::<P>(height)?;
+		self.select()?;
+		let function = self
+			.module
+			.as_ref()
+			.ok_or(ProgPowError::NoInitialized)?
+			.1
+			.function;
+		let dag = &self.dag.as_ref().ok_or(ProgPowError::NoInitialized)?.1;
+		let [mut h0, mut h1, mut h2, mut h3] = header_words(header);
+		let (mut start_nonce, mut target) = (start_nonce, target);
+		let (mut dag_ptr, mut cdag_ptr, mut results_ptr) =
+			(dag.ptr, self.cdag.ptr, self.results.ptr);
+		let mut debug_ptr: *mut c_void = ptr::null_mut();
+
+		let mut bytes = vec![0u8; self.results.bytes];
+		self.results.upload(0, &bytes[..])?;
+		let mut params: [*mut c_void; 10] = [
+			&mut start_nonce as *mut u64 as *mut c_void,
+			&mut target as *mut u64 as *mut c_void,
+			&mut h0 as *mut u64 as *mut c_void,
+			&mut h1 as *mut u64 as *mut c_void,
+			&mut h2 as *mut u64 as *mut c_void,
+			&mut h3 as *mut u64 as *mut c_void,
+			&mut dag_ptr as *mut *mut c_void as *mut c_void,
+			&mut cdag_ptr as *mut *mut c_void as *mut c_void,
+			&mut results_ptr as *mut *mut c_void as *mut c_void,
+			&mut debug_ptr as *mut *mut c_void as *mut c_void,
+		];
+		check(
+			unsafe {
+				hipModuleLaunchKernel(
+					function,
+					self.launch.blocks,
+					1,
+					1,
+					self.launch.threads,
+					1,
+					1,
+					0,
+					self.stream,
+					params.as_mut_ptr(),
+					ptr::null_mut(),
+				)
+			},
+			"hipModuleLaunchKernel",
+		)?;
+		check(
+			unsafe { hipStreamSynchronize(self.stream) },
+			"hipStreamSynchronize",
+		)?;
+
+		self.results.download(&mut bytes[..])?;
+		Ok(SearchResults::from_bytes(&bytes).to_found())
+	}
+}
+
+impl Drop for HipMiner {
+	fn drop(&mut self) {
+		let _ = self.select();
+		// Release device memory and the module before the stream.
+		self.module = None;
+		self.dag = None;
+		unsafe {
+			hipStreamDestroy(self.stream);
+		}
+	}
+}
@@ -0,0 +1,90 @@
+//! Minimal hiprtc bindings used to compile generated kernels to AMD code objects.
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+type HiprtcProgram = *mut c_void;
+
+const HIPRTC_SUCCESS: c_int = 0;
+
+#[link(name = "hiprtc")]
+extern "C" {
+	fn hiprtcCreateProgram(
+		prog: *mut HiprtcProgram,
+		src: *const c_char,
+		name: *const c_char,
+		num_headers: c_int,
+		headers: *const *const c_char,
+		include_names: *const *const c_char,
+	) -> c_int;
+	fn hiprtcCompileProgram(
+		prog: HiprtcProgram,
+		num_options: c_int,
+		options: *const *const c_char,
+	) -> c_int;
+	fn hiprtcGetCodeSize(prog: HiprtcProgram, size: *mut usize) -> c_int;
+	fn hiprtcGetCode(prog: HiprtcProgram, code: *mut c_char) -> c_int;
+	fn hiprtcGetProgramLogSize(prog: HiprtcProgram, size: *mut usize) -> c_int;
+	fn hiprtcGetProgramLog(prog: HiprtcProgram, log: *mut c_char) -> c_int;
+	fn hiprtcDestroyProgram(prog: *mut HiprtcProgram) -> c_int;
+	fn hiprtcVersion(major: *mut c_int, minor: *mut c_int) -> c_int;
+}
+
+/// hiprtc version as `hiprtcMAJOR.MINOR`, to tag the code objects it produces.
+pub fn version() -> Result<String, String> {
+	let (mut major, mut minor) = (0, 0);
+	if unsafe { hiprtcVersion(&mut major, &mut minor) } != HIPRTC_SUCCESS {
+		return Err("hiprtcVersion failed".to_string());
+	}
+	Ok(format!("hiprtc{}.{}", major, minor))
+}
+
+unsafe fn program_log(prog: HiprtcProgram) -> String {
+	let mut size = 0;
+	if hiprtcGetProgramLogSize(prog, &mut size) != HIPRTC_SUCCESS || size == 0 {
+		return String::new();
+	}
+	let mut log = vec![0u8; size];
+	hiprtcGetProgramLog(prog, log.as_mut_ptr() as *mut c_char);
+	String::from_utf8_lossy(&log[..size - 1]).into_owned()
+}
+
+/// Compile `source` for the GPU of the current HIP device, returning its code object. On
+/// failure the error holds the hiprtc log.
+pub fn compile_code_object(source: &str) -> Result<Vec<u8>, String> {
+	let src = CString::new(source).map_err(|e| e.to_string())?;
+	let name = CString::new("progpow.hip").unwrap();
+	let options = [CString::new("-O3").unwrap()];
+	let option_ptrs: Vec<*const c_char> = options.iter().map(|o| o.as_ptr()).collect();
+
+	unsafe {
+		let mut prog: HiprtcProgram = ptr::null_mut();
+		if hiprtcCreateProgram(
+			&mut prog,
+			src.as_ptr(),
+			name.as_ptr(),
+			0,
+			ptr::null(),
+			ptr::null(),
+		) != HIPRTC_SUCCESS
+		{
+			return Err("hiprtcCreateProgram failed".to_string());
+		}
+
+		let result = if hiprtcCompileProgram(prog, option_ptrs.len() as c_int, option_ptrs.as_ptr())
+			!= HIPRTC_SUCCESS
+		{
+			Err(program_log(prog))
+		} else {
+			let mut size = 0;
+			hiprtcGetCodeSize(prog, &mut size);
+			let mut code = vec![0u8; size];
+			hiprtcGetCode(prog, code.as_mut_ptr() as *mut c_char);
+			Ok(code)
+		};
+
+		hiprtcDestroyProgram(&mut prog);
+		result
+	}
+}
@@ -0,0 +1,210 @@
+//! Host-side mirror of the buffers shared with the generated kernels.
+
+use std::fmt::Display;
+use std::mem;
+
+use super::cpu::get_cache_path;
+use crate::generator::PROGPOW_LANES;
+use crate::types::{ProgPowError, H256};
+use crate::{generate_cdag, CDag};
+use progpow_base::compute::calculate_dag_item;
+use progpow_base::params::ProgPowParams;
+use progpow_base::shared::get_data_size;
+use progpow_cpu::cache::NodeCacheBuilder;
+
+/// Bytes per DAG node.
+const DAG_NODE_BYTES: usize = 64;
+
+/// How the DAG gets to a GPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DagUploadOptions {
+	/// Upload the light cache and generate the DAG with `generator::dag`'s kernel, on
+	/// backends that have one, instead of generating it on the host and copying it. The
+	/// fields below then only size the launches.
+	pub on_device: bool,
+	/// Bytes per copy, rounded down to whole DAG nodes. Some Windows drivers reset the
+	/// device when one transfer runs past their watchdog, so keep it small there.
+	pub chunk_bytes: usize,
+	/// Stage chunks in page-locked host memory, letting copies run asynchronously.
+	pub pinned: bool,
+	/// Copies in flight at once, each on its own stream. Only used with `pinned`.
+	pub streams: usize,
+}
+
+impl Default for DagUploadOptions {
+	fn default() -> Self {
+		DagUploadOptions {
+			on_device: true,
+			chunk_bytes: 4 << 20,
+			pinned: false,
+			streams: 1,
+		}
+	}
+}
+
+impl DagUploadOptions {
+	/// DAG nodes per chunk, at least one.
+	pub fn chunk_nodes(&self) -> usize {
+		(self.chunk_bytes / DAG_NODE_BYTES).max(1)
+	}
+}
+
+/// One entry of the kernel's `search_results` buffer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchResult {
+	pub nonce: u64,
+	pub mix: [u32; 8],
+	pub debug: [u32; 8],
+}
+
+/// Host copy of `search_results` in the kernel templates: the `count` word, padding and
+/// as many entries as the kernel was generated with, see `KernelSource::search_results`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchResults {
+	pub count: u32,
+	pub result: Vec<SearchResult>,
+}
+
+#[cfg(feature = "cuda-rust")]
+unsafe impl cust::memory::DeviceCopy for SearchResult {}
+
+impl SearchResults {
+	/// Size of the device buffer of a kernel with room for `search_results` entries.
+	pub fn bytes(search_results: usize) -> usize {
+		8 + search_results * mem::size_of::<SearchResult>()
+	}
+
+	/// Parse the buffer read back from the device. Its length gives the capacity.
+	pub fn from_bytes(bytes: &[u8]) -> Self {
+		let word = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+		let words = |b: &[u8]| {
+			let mut out = [0u32; 8];
+			for (i, w) in out.iter_mut().enumerate() {
+				*w = word(&b[i * 4..]);
+			}
+			out
+		};
+		let result = bytes[8..]
+			.chunks_exact(mem::size_of::<SearchResult>())
+			.map(|entry| SearchResult {
+				nonce: word(entry) as u64 | (word(&entry[4..]) as u64) << 32,
+				mix: words(&entry[8..]),
+				debug: words(&entry[40..]),
+			})
+			.collect();
+		SearchResults {
+			count: word(bytes),
+			result,
+		}
+	}
+
+	/// Found entries, ignoring any the kernel had to drop.
+	pub fn found(&self) -> &[SearchResult] {
+		let count = (self.count as usize).min(self.result.len());
+		&self.result[..count]
+	}
+
+	/// Whether the kernel dropped entries. It counts every match, also those past the
+	/// last slot.
+	pub fn overflowed(&self) -> bool {
+		self.count as usize > self.result.len()
+	}
+
+	pub fn to_found(&self) -> Found {
+		Found {
+			solutions: self.found().iter().map(|r| (r.nonce, r.mix)).collect(),
+			overflowed: self.overflowed(),
+		}
+	}
+}
+
+/// What one search launch found.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Found {
+	/// `(nonce, mix)` of every reported nonce at or below the target.
+	pub solutions: Vec<(u64, [u32; 8])>,
+	/// More nonces met the target than the kernel has result slots, and the rest were
+	/// dropped. Only happens at very low difficulty, as on test chains; search smaller
+	/// batches there.
+	pub overflowed: bool,
+}
+
+pub(crate) fn driver<E: Display>(e: E) -> ProgPowError {
+	ProgPowError::Driver(e.to_string())
+}
+
+/// The header as the four little-endian words the kernels take by value.
+pub fn header_words(header: &H256) -> [u64; 4] {
+	let mut words = [0u64; 4];
+	for (i, word) in words.iter_mut().enumerate() {
+		let mut bytes = [0u8; 8];
+		bytes.copy_from_slice(&header[i * 8..(i + 1) * 8]);
+		*word = u64::from_le_bytes(bytes);
+	}
+	words
+}
+
+/// Nonces covered by one launch of `work_items` work-items.
+pub fn nonces_per_launch(work_items: u64) -> u64 {
+	work_items / PROGPOW_LANES as u64
+}
+
+/// Number of `u32` words in the full DAG for `height`.
+pub fn dag_words<P: ProgPowParams>(height: u64) -> usize {
+	get_data_size::<P>(height) / 4
+}
+
+/// Generate the DAG for `height` on the host, handing it to `upload` in chunks along with
+/// the word offset of each chunk. Returns the c_dag of the same light cache.
+pub fn host_dag<P, F>(height: u64, upload: F) -> Result<CDag, ProgPowError>
+where
+	P: ProgPowParams,
+	F: FnMut(usize, &[u32]) -> Result<(), ProgPowError>,
+{
+	host_dag_chunked::<P, F>(height, DagUploadOptions::default().chunk_nodes(), upload)
+}
+
+/// `host_dag` with `chunk_nodes` DAG nodes per chunk.
+pub fn host_dag_chunked<P, F>(
+	height: u64,
+	chunk_nodes: usize,
+	mut upload: F,
+) -> Result<CDag, ProgPowError>
+where
+	P: ProgPowParams,
+	F: FnMut(usize, &[u32]) -> Result<(), ProgPowError>,
+{
+	let chunk_nodes = chunk_nodes.max(1);
+	let path = get_cache_path().map_err(|_| ProgPowError::CACHE)?;
+	let cache = NodeCacheBuilder::new(None).new_cache::<P>(path, height);
+	let cache = cache.as_ref();
+
+	let nodes = dag_words::<P>(height) / 16;
+	let mut chunk = Vec::with_capacity(chunk_nodes.min(nodes) * 16);
+	for start in (0..nodes).step_by(chunk_nodes) {
+		let end = (start + chunk_nodes).min(nodes);
+		chunk.clear();
+		for i in start..end {
+			chunk.extend_from_slice(calculate_dag_item::<P>(i as u32, cache).as_words());
+		}
+		upload(start * 16, &chunk)?;
+	}
+	Ok(generate_cdag::<P>(cache))
+}
+
+/// The light cache of `height` as `u32` words, 16 per node, for backends generating the
+/// DAG on the device. Returns the c_dag of the same cache.
+pub fn host_light_cache<P: ProgPowParams>(height: u64) -> Result<(Vec<u32>, CDag), ProgPowError> {
+	let path = get_cache_path().map_err(|_| ProgPowError::CACHE)?;
+	let cache = NodeCacheBuilder::new(None).new_cache::<P>(path, height);
+	let nodes = cache.as_ref();
+	let words = nodes
+		.iter()
+		.flat_map(|node| node.as_words().iter().copied());
+	Ok((words.collect(), generate_cdag::<P>(nodes)))
+}
@@ -0,0 +1,689 @@
+//! Level Zero miner for Intel GPUs: the generated OpenCL C kernel is compiled to SPIR-V
+//! with ocloc, built for the device by the Level Zero driver and launched on a
+//! synchronous immediate command list.
+
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::os::raw::{c_char, c_void};
+use std::path::Path;
+use std::ptr;
+use std::sync::Arc;
+
+use super::kernel::{
+	dag_words, driver, header_words, host_dag, nonces_per_launch, Found, SearchResults,
+};
+use super::ocloc;
+use super::tune::{self, LaunchConfig};
+use crate::debug::TRACE_WORDS;
+use crate::epoch::{epoch_for_height, next_period_height};
+use crate::generator::Backend;
+use crate::kernel_cache::KernelCache;
+use crate::types::{ProgPowError, H256};
+use crate::PROGPOW_CACHE_WORDS;
+use progpow_base::params::ProgPowParams;
+
+type ZeResult = u32;
+type Handle = *mut c_void;
+
+const ZE_RESULT_SUCCESS: ZeResult = 0;
+const ZE_INIT_FLAG_GPU_ONLY: u32 = 1;
+const ZE_STRUCTURE_TYPE_DEVICE_PROPERTIES: u32 = 0x3;
+const ZE_STRUCTURE_TYPE_CONTEXT_DESC: u32 = 0xd;
+const ZE_STRUCTURE_TYPE_COMMAND_QUEUE_DESC: u32 = 0xe;
+const ZE_STRUCTURE_TYPE_DEVICE_MEM_ALLOC_DESC: u32 = 0x15;
+const ZE_STRUCTURE_TYPE_HOST_MEM_ALLOC_DESC: u32 = 0x16;
+const ZE_STRUCTURE_TYPE_MODULE_DESC: u32 = 0x1b;
+const ZE_STRUCTURE_TYPE_KERNEL_DESC: u32 = 0x1d;
+const ZE_STRUCTURE_TYPE_RELAXED_ALLOCATION_LIMITS_EXP_DESC: u32 = 0x0002_0001;
+const ZE_RELAXED_ALLOCATION_LIMITS_EXP_FLAG_MAX_SIZE: u32 = 1;
+const ZE_COMMAND_QUEUE_MODE_SYNCHRONOUS: u32 = 1;
+const ZE_MODULE_FORMAT_IL_SPIRV: u32 = 0;
+/// Lets kernels index buffers past 4 GiB, which current DAGs need.
+const BUILD_FLAGS: &str = "-ze-opt-greater-than-4GB-buffer-required";
+
+#[repr(C)]
+struct DeviceProperties {
+	stype: u32,
+	p_next: *mut c_void,
+	device_type: u32,
+	vendor_id: u32,
+	device_id: u32,
+	flags: u32,
+	subdevice_id: u32,
+	core_clock_rate: u32,
+	max_mem_alloc_size: u64,
+	max_hardware_contexts: u32,
+	max_command_queue_priority: u32,
+	num_threads_per_eu: u32,
+	physical_eu_simd_width: u32,
+	num_eus_per_subslice: u32,
+	num_subslices_per_slice: u32,
+	num_slices: u32,
+	timer_resolution: u64,
+	timestamp_valid_bits: u32,
+	kernel_timestamp_valid_bits: u32,
+	uuid: [u8; 16],
+	name: [c_char; 256],
+}
+
+#[repr(C)]
+struct ContextDesc {
+	stype: u32,
+	p_next: *const c_void,
+	flags: u32,
+}
+
+#[repr(C)]
+struct CommandQueueDesc {
+	stype: u32,
+	p_next: *const c_void,
+	ordinal: u32,
+	index: u32,
+	flags: u32,
+	mode: u32,
+	priority: u32,
+}
+
+#[repr(C)]
+struct RelaxedAllocationLimitsDesc {
+	stype: u32,
+	p_next: *const c_void,
+	flags: u32,
+}
+
+#[repr(C)]
+struct DeviceMemAllocDesc {
+	stype: u32,
+	p_next: *const c_void,
+	flags: u32,
+	ordinal: u32,
+}
+
+#[repr(C)]
+struct HostMemAllocDesc {
+	stype: u32,
+	p_next: *const c_void,
+	flags: u32,
+}
+
+#[repr(C)]
+struct ModuleDesc {
+	stype: u32,
+	p_next: *const c_void,
+	format: u32,
+	input_size: usize,
+	input_module: *const u8,
+	build_flags: *const c_char,
+	constants: *const c_void,
+}
+
+#[repr(C)]
+struct KernelDesc {
+	stype: u32,
+	p_next: *const c_void,
+	flags: u32,
+	kernel_name: *const c_char,
+}
+
+#[repr(C)]
+struct GroupCount {
+	x: u32,
+	y: u32,
+	z: u32,
+}
+
+#[link(name = "ze_loader")]
+extern "C" {
+	fn zeInit(flags: u32) -> ZeResult;
+	fn zeDriverGet(count: *mut u32, drivers: *mut Handle) -> ZeResult;
+	fn zeDeviceGet(driver: Handle, count: *mut u32, devices: *mut Handle) -> ZeResult;
+	fn zeDeviceGetProperties(device: Handle, properties: *mut DeviceProperties) -> ZeResult;
+	fn zeContextCreate(driver: Handle, desc: *const ContextDesc, context: *mut Handle) -> ZeResult;
+	fn zeContextDestroy(context: Handle) -> ZeResult;
+	fn zeCommandListCreateImmediate(
+		context: Handle,
+		device: Handle,
+		desc: *const CommandQueueDesc,
+		list: *mut Handle,
+	) -> ZeResult;
+	fn zeCommandListDestroy(list: Handle) -> ZeResult;
+	fn zeCommandListAppendMemoryCopy(
+		list: Handle,
+		dst: *mut c_void,
+		src: *const c_void,
+		size: usize,
+		signal_event: Handle,
+		num_wait_events: u32,
+		wait_events: *const Handle,
+	) -> ZeResult;
+	fn zeCommandListAppendLaunchKernel(
+		list: Handle,
+		kernel: Handle,
+		group_count: *const GroupCount,
+		signal_event: Handle,
+		num_wait_events: u32,
+		wait_events: *const Handle,
+	) -> ZeResult;
+	fn zeMemAllocDevice(
+		context: Handle,
+		desc: *const DeviceMemAllocDesc,
+		size: usize,
+		alignment: usize,
+		device: Handle,
+		ptr: *mut *mut c_void,
+	) -> ZeResult;
+	fn zeMemAllocShared(
+		context: Handle,
+		device_desc: *const DeviceMemAllocDesc,
+		host_desc: *const HostMemAllocDesc,
+		size: usize,
+		alignment: usize,
+		device: Handle,
+		ptr: *mut *mut c_void,
+	) -> ZeResult;
+	fn zeMemFree(context: Handle, ptr: *mut c_void) -> ZeResult;
+	fn zeModuleCreate(
+		context: Handle,
+		device: Handle,
+		desc: *const ModuleDesc,
+		module: *mut Handle,
+		build_log: *mut Handle,
+	) -> ZeResult;
+	fn zeModuleDestroy(module: Handle) -> ZeResult;
+	fn zeModuleBuildLogGetString(
+		log: Handle,
+		size: *mut usize,
+		log_string: *mut c_char,
+	) -> ZeResult;
+	fn zeModuleBuildLogDestroy(log: Handle) -> ZeResult;
+	fn zeKernelCreate(module: Handle, desc: *const KernelDesc, kernel: *mut Handle) -> ZeResult;
+	fn zeKernelDestroy(kernel: Handle) -> ZeResult;
+	fn zeKernelSetGroupSize(kernel: Handle, x: u32, y: u32, z: u32) -> ZeResult;
+	fn zeKernelSetArgumentValue(
+		kernel: Handle,
+		index: u32,
+		size: usize,
+		value: *const c_void,
+	) -> ZeResult;
+}
+
+fn check(result: ZeResult, call: &str) -> Result<(), ProgPowError> {
+	if result == ZE_RESULT_SUCCESS {
+		Ok(())
+	} else {
+		Err(ProgPowError::Driver(format!("{}: 0x{:x}", call, result)))
+	}
+}
+
+/// A built module and its search kernel.
+struct Program {
+	module: Handle,
+	kernel: Handle,
+}
+
+impl Drop for Program {
+	fn drop(&mut self) {
+		unsafe {
+			zeKernelDestroy(self.kernel);
+			zeModuleDestroy(self.module);
+		}
+	}
+}
+
+/// One Level Zero GPU with its DAG, light cache and result buffer.
+pub struct LevelZeroMiner {
+	device: Handle,
+	context: Handle,
+	list: Handle,
+	name: String,
+	max_alloc: u64,
+	kernels: KernelCache,
+	/// Period and `GROUP_SIZE` the program was built for.
+	program: Option<((u64, u32), Program)>,
+	dag: Option<(u64, *mut c_void)>,
+	/// Shared allocations the host reads and writes directly.
+	cdag: *mut c_void,
+	results: *mut c_void,
+	debug: *mut c_void,
+	launch: LaunchConfig,
+}
+
+impl LevelZeroMiner {
+	/// Open GPU `index` across all Level Zero drivers.
+	pub fn new(index: usize) -> Result<Self, ProgPowError> {
+		unsafe {
+			check(zeInit(ZE_INIT_FLAG_GPU_ONLY), "zeInit")?;
+			let mut count = 0;
+			check(zeDriverGet(&mut count, ptr::null_mut()), "zeDriverGet")?;
+			let mut drivers = vec![ptr::null_mut(); count as usize];
+			check(zeDriverGet(&mut count, drivers.as_mut_ptr()), "zeDriverGet")?;
+
+			let mut gpus = Vec::new();
+			for &driver in &drivers {
+				let mut count = 0;
+				check(
+					zeDeviceGet(driver, &mut count, ptr::null_mut()),
+					"zeDeviceGet",
+				)?;
+				let mut devices = vec![ptr::null_mut(); count as usize];
+				check(
+					zeDeviceGet(driver, &mut count, devices.as_mut_ptr()),
+					"zeDeviceGet",
+				)?;
+				gpus.extend(devices.into_iter().map(|device| (driver, device)));
+			}
+			let (driver, device) = *gpus
+				.get(index)
+				.ok_or_else(|| ProgPowError::Driver(format!("no Level Zero device {}", index)))?;
+
+			let mut properties: DeviceProperties = mem::zeroed();
+			properties.stype = ZE_STRUCTURE_TYPE_DEVICE_PROPERTIES;
+			check(
+				zeDeviceGetProperties(device, &mut properties),
+				"zeDeviceGetProperties",
+			)?;
+			let name = CStr::from_ptr(properties.name.as_ptr())
+				.to_string_lossy()
+				.into_owned();
+
+			let mut context = ptr::null_mut();
+			let desc = ContextDesc {
+				stype: ZE_STRUCTURE_TYPE_CONTEXT_DESC,
+				p_next: ptr::null(),
+				flags: 0,
+			};
+			check(
+				zeContextCreate(driver, &desc, &mut context),
+				"zeContextCreate",
+			)?;
+			let mut miner = LevelZeroMiner {
+				device,
+				context,
+				list: ptr::null_mut(),
+				name,
+				max_alloc: properties.max_mem_alloc_size,
+				kernels: KernelCache::new(),
+				program: None,
+				dag: None,
+				cdag: ptr::null_mut(),
+				results: ptr::null_mut(),
+				debug: ptr::null_mut(),
+				launch: LaunchConfig::default(),
+			};
+
+			// Ordinal 0 is the compute engine group on Intel GPUs.
+			let desc = CommandQueueDesc {
+				stype: ZE_STRUCTURE_TYPE_COMMAND_QUEUE_DESC,
+				p_next: ptr::null(),
+				ordinal: 0,
+				index: 0,
+				flags: 0,
+				mode: ZE_COMMAND_QUEUE_MODE_SYNCHRONOUS,
+				priority: 0,
+			};
+			check(
+				zeCommandListCreateImmediate(context, device, &desc, &mut miner.list),
+				"zeCommandListCreateImmediate",
+			)?;
+			miner.cdag = miner.alloc_shared(PROGPOW_CACHE_WORDS * 4)?;
+			miner.results =
+				miner.alloc_shared(SearchResults::bytes(miner.kernels.search_results()))?;
+			// The kernel takes the trace buffer unconditionally; it is only written to when
+			// built with `debug-trace`, up to `TRACE_WORDS` words.
+			let trace_words = if cfg!(feature = "debug-trace") {
+				TRACE_WORDS
+			} else {
+				1
+			};
+			miner.debug = miner.alloc_shared(trace_words * 4)?;
+			Ok(miner)
+		}
+	}
+
+	fn alloc_shared(&self, size: usize) -> Result<*mut c_void, ProgPowError> {
+		let device_desc = DeviceMemAllocDesc {
+			stype: ZE_STRUCTURE_TYPE_DEVICE_MEM_ALLOC_DESC,
+			p_next: ptr::null(),
+			flags: 0,
+			ordinal: 0,
+		};
+		let host_desc = HostMemAllocDesc {
+			stype: ZE_STRUCTURE_TYPE_HOST_MEM_ALLOC_DESC,
+			p_next: ptr::null(),
+			flags: 0,
+		};
+		let mut ptr = ptr::null_mut();
+		check(
+			unsafe {
+				zeMemAllocShared(
+					self.context,
+					&device_desc,
+					&host_desc,
+					size,
+					64,
+					self.device,
+					&mut ptr,
+				)
+			},
+			"zeMemAllocShared",
+		)?;
+		Ok(ptr)
+	}
+
+	/// Keep compiled SPIR-V in `dir` so a restarted miner loads it instead of compiling
+	/// again.
+	pub fn set_kernel_dir(&mut self, dir: &Path) -> Result<(), ProgPowError> {
+		self.kernels = KernelCache::with_dir(dir).map_err(driver)?;
+		Ok(())
+	}
+
+	/// Nonces searched by each call to `search`.
+	pub fn batch_size(&self) -> u64 {
+		nonces_per_launch(self.launch.work_items())
+	}
+
+	pub fn launch_config(&self) -> LaunchConfig {
+		self.launch
+	}
+
+	/// A new thread count rebuilds the program on the next `search`, since the kernel
+	/// requires its work-group size at compile time.
+	pub fn set_launch_config(&mut self, launch: LaunchConfig) {
+		self.launch = launch;
+	}
+
+	/// Use the launch shape saved for this device model in `dir`, benchmarking
+	/// `LaunchConfig::candidates` at `height` to create it on first use.
+	pub fn autotune<P: ProgPowParams>(
+		&mut self,
+		dir: &Path,
+		height: u64,
+	) -> Result<LaunchConfig, ProgPowError> {
+		self.prepare::<P>(height)?;
+		let path = tune::tuning_path(dir, "level-zero", &self.name);
+		let header = [0u8; 32];
+		let launch = tune::load_or_tune(&path, |config| {
+			tune::measure(config, |config| {
+				self.launch = config;
+				// A zero target never matches, so every launch does the full batch.
+				self.search::<P>(&header, height, 0, 0)?;
+				Ok(self.batch_size())
+			})
+		})?;
+		self.launch = launch;
+		Ok(launch)
+	}
+
+	/// Build the program for the period of `height` and the DAG for its epoch,
+	/// skipping whatever is already current.
+	pub fn prepare<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		self.prepare_kernel::<P>(height)?;
+		self.prepare_dag::<P>(height)
+	}
+
+	/// Build the program for the period of `height` unless it is already current.
+	pub fn prepare_kernel<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		let key = (P::prog_seed(height), self.launch.threads);
+		if self.program.as_ref().map(|(k, _)| *k) != Some(key) {
+			let spirv = self.kernel_binary::<P>(height)?;
+			let program = self.build(&spirv)?;
+			self.kernels.evict_before(key.0);
+			self.program = Some((key, program));
+		}
+		Ok(())
+	}
+
+	/// Compile the kernel of the period after the one of `height` ahead of time, so the
+	/// switch only has to load it. Returns the first height of that period.
+	pub fn precompile_next_period<P: ProgPowParams>(
+		&self,
+		height: u64,
+	) -> Result<u64, ProgPowError> {
+		let next = next_period_height::<P>(height);
+		self.kernel_binary::<P>(next)?;
+		Ok(next)
+	}
+
+	fn kernel_binary<P: ProgPowParams>(&self, height: u64) -> Result<Arc<Vec<u8>>, ProgPowError> {
+		// The work-group size is compiled in, so binaries are kept per size.
+		let threads = self.launch.threads;
+		let options = format!("-cl-std=CL2.0 -DGROUP_SIZE={}", threads);
+		self.kernels
+			.binary_for_arch::<P, _, _>(
+				Backend::LevelZero,
+				&format!("wg{}", threads),
+				height,
+				|source| ocloc::compile_spirv(source, &options),
+			)
+			.map_err(ProgPowError::Driver)
+	}
+
+	fn build(&self, spirv: &[u8]) -> Result<Program, ProgPowError> {
+		let flags = CString::new(BUILD_FLAGS).unwrap();
+		let desc = ModuleDesc {
+			stype: ZE_STRUCTURE_TYPE_MODULE_DESC,
+			p_next: ptr::null(),
+			format: ZE_MODULE_FORMAT_IL_SPIRV,
+			input_size: spirv.len(),
+			input_module: spirv.as_ptr(),
+			build_flags: flags.as_ptr(),
+			constants: ptr::null(),
+		};
+		unsafe {
+			let mut module = ptr::null_mut();
+			let mut log = ptr::null_mut();
+			let result = zeModuleCreate(self.context, self.device, &desc, &mut module, &mut log);
+			if result != ZE_RESULT_SUCCESS {
+				let mut size = 0;
+				zeModuleBuildLogGetString(log, &mut size, ptr::null_mut());
+				let mut text = vec![0 as c_char; size.max(1)];
+				zeModuleBuildLogGetString(log, &mut size, text.as_mut_ptr());
+				zeModuleBuildLogDestroy(log);
+				let text = CStr::from_ptr(text.as_ptr()).to_string_lossy();
+				return Err(ProgPowError::Driver(format!(
+					"zeModuleCreate: 0x{:x}: {}",
+					result, text
+				)));
+			}
+			zeModuleBuildLogDestroy(log);
+
+			let name = CString::new(Backend::LevelZero.entry_point()).unwrap();
+			let desc = KernelDesc {
+				stype: ZE_STRUCTURE_TYPE_KERNEL_DESC,
+				p_next: ptr::null(),
+				flags: 0,
+				kernel_name: name.as_ptr(),
+			};
+			let mut kernel = ptr::null_mut();
+			if let Err(e) = check(zeKernelCreate(module, &desc, &mut kernel), "zeKernelCreate") {
+				zeModuleDestroy(module);
+				return Err(e);
+			}
+			Ok(Program { module, kernel })
+		}
+	}
+
+	/// Build the DAG for the epoch of `height` unless it is already current.
+	pub fn prepare_dag<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		let epoch = epoch_for_height::<P>(height);
+		if self.dag.as_ref().map(|(e, _)| *e) != Some(epoch) {
+			// Free the old DAG before allocating the next one.
+			if let Some((_, dag)) = self.dag.take() {
+				unsafe { zeMemFree(self.context, dag) };
+			}
+			self.dag = Some((epoch, self.build_dag::<P>(height)?));
+		}
+		Ok(())
+	}
+
+	fn build_dag<P: ProgPowParams>(&self, height: u64) -> Result<*mut c_void, ProgPowError> {
+		let needed = (dag_words::<P>(height) * 4) as u64;
+		// Allocations past `max_alloc` need the relaxed limit below; the device memory
+		// itself is checked by the allocation.
+		let relaxed = RelaxedAllocationLimitsDesc {
+			stype: ZE_STRUCTURE_TYPE_RELAXED_ALLOCATION_LIMITS_EXP_DESC,
+			p_next: ptr::null(),
+			flags: ZE_RELAXED_ALLOCATION_LIMITS_EXP_FLAG_MAX_SIZE,
+		};
+		let desc = DeviceMemAllocDesc {
+			stype: ZE_STRUCTURE_TYPE_DEVICE_MEM_ALLOC_DESC,
+			p_next: if needed > self.max_alloc {
+				&relaxed as *const _ as *const c_void
+			} else {
+				ptr::null()
+			},
+			flags: 0,
+			ordinal: 0,
+		};
+		let mut dag = ptr::null_mut();
+		let result = unsafe {
+			zeMemAllocDevice(
+				self.context,
+				&desc,
+				needed as usize,
+				64,
+				self.device,
+				&mut dag,
+			)
+		};
+		if result != ZE_RESULT_SUCCESS {
+			return Err(ProgPowError::InsufficientVram {
+				needed,
+				available: self.max_alloc,
+			});
+		}
+
+		let mut staging: Option<(usize, *mut c_void)> = None;
+		let list = self.list;
+		let cdag = host_dag::<P, _>(height, |offset, chunk| {
+			let bytes = mem::size_of_val(chunk);
+			if staging.filter(|(size, _)| *size >= bytes).is_none() {
+				if let Some((_, old)) = staging.take() {
+					unsafe { zeMemFree(self.context, old) };
+				}
+				staging = Some((bytes, self.alloc_shared(bytes)?));
+			}
+			let (_, host) = staging.unwrap();
+			unsafe {
+				ptr::copy_nonoverlapping(chunk.as_ptr(), host as *mut u32, chunk.len());
+				let dst = (dag as *mut u8).add(offset * 4) as *mut c_void;
+				check(
+					zeCommandListAppendMemoryCopy(
+						list,
+						dst,
+						host,
+						bytes,
+						ptr::null_mut(),
+						0,
+						ptr::null(),
+					),
+					"zeCommandListAppendMemoryCopy",
+				)
+				.map_err(|_| ProgPowError::DAG)
+			}
+		});
+		if let Some((_, host)) = staging {
+			unsafe { zeMemFree(self.context, host) };
+		}
+		match cdag {
+			Ok(cdag) => {
+				unsafe {
+					ptr::copy_nonoverlapping(cdag.as_ptr(), self.cdag as *mut u32, cdag.len());
+				}
+				Ok(dag)
+			}
+			Err(e) => {
+				unsafe { zeMemFree(self.context, dag) };
+				Err(e)
+			}
+		}
+	}
+
+	/// Search `batch_size()` nonces from `start_nonce`, returning each nonce whose
+	/// result is at or below `target` together with its mix, see `Found`.
+	pub fn search<P: ProgPowParams>(
+		&mut self,
+		header: &H256,
+		height: u64,
+		target: u64,
+		start_nonce: u64,
+	) -> Result<Found, ProgPowError> {
+		self.prepare::<P>(height)?;
+		let kernel = self
+			.program
+			.as_ref()
+			.ok_or(ProgPowError::NoInitialized)?
+			.1
+			.kernel;
+		let dag = self.dag.ok_or(ProgPowError::NoInitialized)?.1;
+		let [h0, h1, h2, h3] = header_words(header);
+		let bytes = SearchResults::bytes(self.kernels.search_results());
+
+		unsafe {
+			ptr::write_bytes(self.results as *mut u8, 0, bytes);
+			let scalars = [start_nonce, target, h0, h1, h2, h3];
+			for (index, value) in scalars.iter().enumerate() {
+				check(
+					zeKernelSetArgumentValue(kernel, index as u32, 8, value as *const u64 as _),
+					"zeKernelSetArgumentValue",
+				)?;
+			}
+			let buffers = [dag, self.cdag, self.results, self.debug];
+			for (index, buffer) in buffers.iter().enumerate() {
+				check(
+					zeKernelSetArgumentValue(
+						kernel,
+						(scalars.len() + index) as u32,
+						mem::size_of::<*mut c_void>(),
+						buffer as *const *mut c_void as _,
+					),
+					"zeKernelSetArgumentValue",
+				)?;
+			}
+			check(
+				zeKernelSetGroupSize(kernel, self.launch.threads, 1, 1),
+				"zeKernelSetGroupSize",
+			)?;
+			let groups = GroupCount {
+				x: self.launch.blocks,
+				y: 1,
+				z: 1,
+			};
+			// Returns once the kernel has run, the list being synchronous.
+			check(
+				zeCommandListAppendLaunchKernel(
+					self.list,
+					kernel,
+					&groups,
+					ptr::null_mut(),
+					0,
+					ptr::null(),
+				),
+				"zeCommandListAppendLaunchKernel",
+			)?;
+
+			let results = std::slice::from_raw_parts(self.results as *const u8, bytes);
+			Ok(SearchResults::from_bytes(results).to_found())
+		}
+	}
+}
+
+impl Drop for LevelZeroMiner {
+	fn drop(&mut self) {
+		self.program = None;
+		unsafe {
+			if let Some((_, dag)) = self.dag.take() {
+				zeMemFree(self.context, dag);
+			}
+			for buffer in [self.cdag, self.results, self.debug] {
+				if !buffer.is_null() {
+					zeMemFree(self.context, buffer);
+				}
+			}
+			if !self.list.is_null() {
+				zeCommandListDestroy(self.list);
+			}
+			zeContextDestroy(self.context);
+		}
+	}
+}
@@ -0,0 +1,236 @@
+//! Experimental Metal miner for Apple GPUs through `metal-rs`, mainly for benchmarking
+//! and checking GPU parity on macOS machines without an NVIDIA or AMD card.
+//!
+//! Buffers use shared storage, so the DAG is written straight into memory the GPU reads.
+
+use std::ffi::c_void;
+use std::path::Path;
+use std::ptr;
+
+use metal::{
+	Buffer, CommandQueue, CompileOptions, ComputePipelineState, Device, MTLCommandBufferStatus,
+	MTLResourceOptions, MTLSize,
+};
+
+use super::kernel::{
+	dag_words, driver, header_words, host_dag, nonces_per_launch, Found, SearchResults,
+};
+use super::tune::{self, LaunchConfig};
+use crate::debug::TRACE_WORDS;
+use crate::epoch::epoch_for_height;
+use crate::generator::Backend;
+use crate::kernel_cache::KernelCache;
+use crate::types::{ProgPowError, H256};
+use crate::PROGPOW_CACHE_WORDS;
+use progpow_base::params::ProgPowParams;
+
+/// Copy `data` to `offset` elements into a shared buffer.
+fn write<T: Copy>(buffer: &Buffer, offset: usize, data: &[T]) {
+	debug_assert!(((offset + data.len()) * std::mem::size_of::<T>()) as u64 <= buffer.length());
+	unsafe {
+		let dst = (buffer.contents() as *mut T).add(offset);
+		ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len());
+	}
+}
+
+/// One Metal device with its DAG, light cache and result buffer.
+pub struct MetalMiner {
+	device: Device,
+	queue: CommandQueue,
+	kernels: KernelCache,
+	/// Period and `GROUP_SIZE` the pipeline was built for.
+	pipeline: Option<((u64, u32), ComputePipelineState)>,
+	dag: Option<(u64, Buffer)>,
+	cdag: Buffer,
+	results: Buffer,
+	debug: Buffer,
+	launch: LaunchConfig,
+}
+
+impl MetalMiner {
+	/// Open device `index` of `Device::all`, the system default GPU being first.
+	pub fn new(index: usize) -> Result<Self, ProgPowError> {
+		let device = Device::all()
+			.into_iter()
+			.nth(index)
+			.ok_or_else(|| ProgPowError::Driver(format!("no Metal device {}", index)))?;
+		let queue = device.new_command_queue();
+		let buffer =
+			|bytes: usize| device.new_buffer(bytes as u64, MTLResourceOptions::StorageModeShared);
+		let cdag = buffer(PROGPOW_CACHE_WORDS * 4);
+		let kernels = KernelCache::new();
+		let results = buffer(SearchResults::bytes(kernels.search_results()));
+		// The kernel takes the trace buffer unconditionally; it is only written to when
+		// built with `debug-trace`, up to `TRACE_WORDS` words.
+		let debug = buffer(if cfg!(feature = "debug-trace") {
+			TRACE_WORDS * 4
+		} else {
+			4
+		});
+
+		Ok(MetalMiner {
+			device,
+			queue,
+			kernels,
+			pipeline: None,
+			dag: None,
+			cdag,
+			results,
+			debug,
+			launch: LaunchConfig::default(),
+		})
+	}
+
+	/// Nonces searched by each call to `search`.
+	pub fn batch_size(&self) -> u64 {
+		nonces_per_launch(self.launch.work_items())
+	}
+
+	pub fn launch_config(&self) -> LaunchConfig {
+		self.launch
+	}
+
+	/// A new thread count rebuilds the pipeline on the next `search`, since the kernel
+	/// sizes its threadgroup memory at compile time.
+	pub fn set_launch_config(&mut self, launch: LaunchConfig) {
+		self.launch = launch;
+	}
+
+	/// Use the launch shape saved for this device model in `dir`, benchmarking
+	/// `LaunchConfig::candidates` at `height` to create it on first use.
+	pub fn autotune<P: ProgPowParams>(
+		&mut self,
+		dir: &Path,
+		height: u64,
+	) -> Result<LaunchConfig, ProgPowError> {
+		self.prepare::<P>(height)?;
+		let path = tune::tuning_path(dir, "metal", self.device.name());
+		let header = [0u8; 32];
+		let launch = tune::load_or_tune(&path, |config| {
+			tune::measure(config, |config| {
+				self.launch = config;
+				// A zero target never matches, so every launch does the full batch.
+				self.search::<P>(&header, height, 0, 0)?;
+				Ok(self.batch_size())
+			})
+		})?;
+		self.launch = launch;
+		Ok(launch)
+	}
+
+	/// Build the pipeline for the period of `height` and the DAG for its epoch,
+	/// skipping whatever is already current.
+	pub fn prepare<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		self.prepare_kernel::<P>(height)?;
+		self.prepare_dag::<P>(height)
+	}
+
+	/// Build the pipeline for the period of `height` unless it is already current.
+	pub fn prepare_kernel<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		let key = (P::prog_seed(height), self.launch.threads);
+		if self.pipeline.as_ref().map(|(k, _)| *k) != Some(key) {
+			let source = format!(
+				"#define GROUP_SIZE {}\n{}",
+				key.1,
+				self.kernels.source::<P>(Backend::Metal, height)
+			);
+			let library = self
+				.device
+				.new_library_with_source(&source, &CompileOptions::new())
+				.map_err(driver)?;
+			let function = library
+				.get_function(Backend::Metal.entry_point(), None)
+				.map_err(driver)?;
+			let pipeline = self
+				.device
+				.new_compute_pipeline_state_with_function(&function)
+				.map_err(driver)?;
+			if pipeline.max_total_threads_per_threadgroup() < key.1 as u64 {
+				return Err(ProgPowError::Driver(format!(
+					"{} threads per threadgroup exceed the device limit of {}",
+					key.1,
+					pipeline.max_total_threads_per_threadgroup()
+				)));
+			}
+			self.kernels.evict_before(key.0);
+			self.pipeline = Some((key, pipeline));
+		}
+		Ok(())
+	}
+
+	/// Build the DAG for the epoch of `height` unless it is already current.
+	pub fn prepare_dag<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		let epoch = epoch_for_height::<P>(height);
+		if self.dag.as_ref().map(|(e, _)| *e) != Some(epoch) {
+			// Free the old DAG before allocating the next one.
+			self.dag = None;
+			self.dag = Some((epoch, self.build_dag::<P>(height)?));
+		}
+		Ok(())
+	}
+
+	fn build_dag<P: ProgPowParams>(&mut self, height: u64) -> Result<Buffer, ProgPowError> {
+		let needed = (dag_words::<P>(height) * 4) as u64;
+		let available = self
+			.device
+			.max_buffer_length()
+			.min(self.device.recommended_max_working_set_size());
+		if available < needed {
+			return Err(ProgPowError::InsufficientVram { needed, available });
+		}
+
+		let dag = self
+			.device
+			.new_buffer(needed, MTLResourceOptions::StorageModeShared);
+		let cdag = host_dag::<P, _>(height, |offset, chunk| {
+			write(&dag, offset, chunk);
+			Ok(())
+		})?;
+		write(&self.cdag, 0, &cdag[..]);
+		Ok(dag)
+	}
+
+	/// Search `batch_size()` nonces from `start_nonce`, returning each nonce whose
+	/// result is at or below `target` together with its mix, see `Found`.
+	pub fn search<P: ProgPowParams>(
+		&mut self,
+		header: &H256,
+		height: u64,
+		target: u64,
+		start_nonce: u64,
+	) -> Result<Found, ProgPowError> {
+		self.prepare::<P>(height)?;
+		let pipeline = &self.pipeline.as_ref().ok_or(ProgPowError::NoInitialized)?.1;
+		let dag = &self.dag.as_ref().ok_or(ProgPowError::NoInitialized)?.1;
+		let [h0, h1, h2, h3] = header_words(header);
+
+		let bytes = self.results.length() as usize;
+		write(&self.results, 0, &vec![0u8; bytes]);
+		let commands = self.queue.new_command_buffer();
+		let encoder = commands.new_compute_command_encoder();
+		encoder.set_compute_pipeline_state(pipeline);
+		for (index, value) in [start_nonce, target, h0, h1, h2, h3].iter().enumerate() {
+			encoder.set_bytes(index as u64, 8, value as *const u64 as *const c_void);
+		}
+		encoder.set_buffer(6, Some(dag), 0);
+		encoder.set_buffer(7, Some(&self.cdag), 0);
+		encoder.set_buffer(8, Some(&self.results), 0);
+		encoder.set_buffer(9, Some(&self.debug), 0);
+		encoder.dispatch_thread_groups(
+			MTLSize::new(self.launch.blocks as u64, 1, 1),
+			MTLSize::new(self.launch.threads as u64, 1, 1),
+		);
+		encoder.end_encoding();
+		commands.commit();
+		commands.wait_until_completed();
+		if commands.status() == MTLCommandBufferStatus::Error {
+			return Err(ProgPowError::Driver(
+				"Metal command buffer failed".to_string(),
+			));
+		}
+
+		let results =
+			unsafe { std::slice::from_raw_parts(self.results.contents() as *const u8, bytes) };
+		Ok(SearchResults::from_bytes(results).to_found())
+	}
+}
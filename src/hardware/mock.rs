@@ -0,0 +1,119 @@
+//! A miner that finds known solutions without hashing, for testing mining pipelines on
+//! CI machines with neither a GPU nor CPU time to spare.
+
+use std::ops::Range;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::types::{Hardware, Miner, MiningJob, ProgPowError, H256};
+
+/// Reports a precomputed `(nonce, mix_hash)` for a header whenever `compute` covers its
+/// nonce, after sleeping `delay` per call. Solutions can be computed once with
+/// `PpCPU::search_target` and kept in the test suite.
+///
+/// The mock does not check them: a wrong mix is passed on as a GPU hardware error
+/// would be.
+#[derive(Debug, Default)]
+pub struct MockMiner {
+	solutions: Vec<(H256, u64, H256)>,
+	delay: Duration,
+	lost: bool,
+	found: Mutex<Vec<(u64, H256)>>,
+}
+
+impl MockMiner {
+	pub fn new() -> Self {
+		MockMiner::default()
+	}
+
+	/// Find `nonce`, with `mix_hash` in the byte order of `mix_hash_bytes`, in jobs for
+	/// `header`.
+	pub fn with_solution(mut self, header: H256, nonce: u64, mix_hash: H256) -> Self {
+		self.solutions.push((header, nonce, mix_hash));
+		self
+	}
+
+	/// Time each `compute` takes, standing in for the search.
+	pub fn with_delay(mut self, delay: Duration) -> Self {
+		self.delay = delay;
+		self
+	}
+
+	/// Fail every `compute` with `ProgPowError::DeviceLost`, as an unplugged GPU does.
+	pub fn with_device_lost(mut self) -> Self {
+		self.lost = true;
+		self
+	}
+}
+
+/// Copies the solutions, delay and loss, not the solutions found so far.
+impl Clone for MockMiner {
+	fn clone(&self) -> Self {
+		MockMiner {
+			solutions: self.solutions.clone(),
+			delay: self.delay,
+			lost: self.lost,
+			found: Mutex::new(Vec::new()),
+		}
+	}
+}
+
+impl Miner for MockMiner {
+	fn init(&mut self) -> Result<(), ProgPowError> {
+		Ok(())
+	}
+
+	fn hardware(&self) -> Hardware {
+		Hardware::CPU
+	}
+
+	fn compute(&self, job: &MiningJob, nonce_range: Range<u64>) -> Result<(), ProgPowError> {
+		thread::sleep(self.delay);
+		if self.lost {
+			return Err(ProgPowError::DeviceLost("mock device".to_string()));
+		}
+		let nonce_range = job.extranonce.clamp(nonce_range);
+		let mut found = self.found.lock().unwrap();
+		for &(header, nonce, mix_hash) in &self.solutions {
+			if header == job.header && nonce_range.contains(&nonce) {
+				found.push((nonce, mix_hash));
+			}
+		}
+		Ok(())
+	}
+
+	fn take_solutions(&self) -> Vec<(u64, H256)> {
+		std::mem::take(&mut *self.found.lock().unwrap())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::hardware::Devices;
+	use crate::hardware::HardwareSpec;
+
+	#[test]
+	fn test_mock_miner_finds_known_nonce() {
+		let mock = MockMiner::new()
+			.with_solution([1; 32], 1500, [2; 32])
+			.with_delay(Duration::from_millis(1));
+		let job = MiningJob::new(1, [1; 32], 20, 0, [0; 32]);
+
+		mock.compute(&job, 0..1000).unwrap();
+		assert!(mock.take_solutions().is_empty());
+		mock.compute(&job, 1000..2000).unwrap();
+		assert_eq!(mock.take_solutions(), vec![(1500, [2; 32])]);
+
+		let other = MiningJob::new(2, [3; 32], 20, 0, [0; 32]);
+		mock.compute(&other, 0..u64::MAX).unwrap();
+		assert!(mock.take_solutions().is_empty());
+
+		// Through the same threads as real devices, CPU rounds being 1024 nonces.
+		let devices = Devices::spawn(vec![HardwareSpec::Mock(mock.clone()); 2]).unwrap();
+		let batch = devices.search(&job, 0).unwrap();
+		assert_eq!(batch.searched, 2048);
+		assert_eq!(batch.found, vec![(1500, [2; 32])]);
+	}
+}
@@ -1,3 +1,146 @@
 pub mod cpu;
+#[cfg(feature = "cuda-rust")]
+pub mod cuda;
+mod devices;
+pub mod global_verifier;
+#[cfg(any(feature = "cuda", feature = "opencl"))]
+pub mod gpu;
+#[cfg(feature = "hip")]
+pub mod hip;
+#[cfg(feature = "hip")]
+mod hiprtc;
+pub mod kernel;
+#[cfg(feature = "intel")]
+pub mod level_zero;
+#[cfg(all(feature = "metal", target_os = "macos"))]
+pub mod metal;
+mod mock;
+#[cfg(feature = "cuda-rust")]
+mod nvrtc;
+#[cfg(feature = "intel")]
+mod ocloc;
+#[cfg(feature = "opencl-rust")]
+pub mod opencl;
+#[cfg(feature = "parallel")]
+mod policy;
+pub mod tune;
+#[cfg(any(feature = "cuda", feature = "opencl"))]
+pub mod verified;
+mod verify_cache;
+pub mod verify_service;
+#[cfg(feature = "vulkan")]
+pub mod vulkan;
 
-pub use self::cpu::PpCPU;
+pub use self::cpu::{CacheOptimization, PpCPU, VerifyScratch};
+#[cfg(feature = "cuda-rust")]
+pub use self::cuda::CudaMiner;
+pub use self::devices::{DeviceThread, Devices};
+pub use self::global_verifier::{GlobalVerifier, GlobalVerifierBuilder};
+#[cfg(any(feature = "cuda", feature = "opencl"))]
+pub use self::gpu::PpGPU;
+#[cfg(feature = "hip")]
+pub use self::hip::HipMiner;
+pub use self::kernel::DagUploadOptions;
+#[cfg(feature = "intel")]
+pub use self::level_zero::LevelZeroMiner;
+#[cfg(all(feature = "metal", target_os = "macos"))]
+pub use self::metal::MetalMiner;
+pub use self::mock::MockMiner;
+#[cfg(feature = "opencl-rust")]
+pub use self::opencl::OpenClMiner;
+#[cfg(feature = "parallel")]
+pub use self::policy::{Checkpoint, Sample, VerificationPolicy, VerifyAll};
+pub use self::tune::LaunchConfig;
+#[cfg(any(feature = "cuda", feature = "opencl"))]
+pub use self::verified::VerifiedMiner;
+pub use self::verify_cache::CacheStats;
+pub use self::verify_service::VerifyService;
+#[cfg(feature = "vulkan")]
+pub use self::vulkan::VulkanMiner;
+
+use std::path::PathBuf;
+
+use crate::params::DynParams;
+use crate::types::Miner;
+
+/// Which backend `create_miner` builds.
+#[derive(Debug, Clone)]
+pub enum HardwareSpec {
+	/// `PpCPU` over `threads` threads, or one per core if `None`.
+	Cpu {
+		params: DynParams,
+		threads: Option<usize>,
+		cache_dir: Option<PathBuf>,
+	},
+	/// `PpGPU` on `device` with `driver`. `intensity` is a percentage, see
+	/// `PpGPU::set_intensity`.
+	#[cfg(any(feature = "cuda", feature = "opencl"))]
+	Gpu {
+		device: u32,
+		driver: progpow_gpu::Driver,
+		intensity: u32,
+	},
+	/// A copy of the `MockMiner`, for tests.
+	Mock(MockMiner),
+}
+
+/// Build the miner described by `spec`. It still has to be `init`ed.
+pub fn create_miner(spec: HardwareSpec) -> Box<dyn Miner> {
+	match spec {
+		HardwareSpec::Cpu {
+			params,
+			threads,
+			cache_dir,
+		} => {
+			let mut cpu = PpCPU::new_dyn(params);
+			if let Some(threads) = threads {
+				cpu.set_threads(threads);
+			}
+			if let Some(dir) = cache_dir {
+				cpu.set_cache_dir(dir);
+			}
+			Box::new(cpu)
+		}
+		#[cfg(any(feature = "cuda", feature = "opencl"))]
+		HardwareSpec::Gpu {
+			device,
+			driver,
+			intensity,
+		} => {
+			let gpu = PpGPU::new(device, driver);
+			gpu.set_intensity(intensity);
+			Box::new(gpu)
+		}
+		HardwareSpec::Mock(mock) => Box::new(mock),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::types::{difficulty::target_from_difficulty, Hardware, MiningJob, Verifier};
+	use progpow_base::params::KawPowParams;
+
+	#[test]
+	fn test_create_miner_cpu() {
+		let mut miner = create_miner(HardwareSpec::Cpu {
+			params: DynParams::of::<KawPowParams>(),
+			threads: Some(2),
+			cache_dir: None,
+		});
+		miner.init().unwrap();
+		assert!(matches!(miner.hardware(), Hardware::CPU));
+
+		let header = [0u8; 32];
+		let job = MiningJob::new(0, header, 20, 0, target_from_difficulty(16));
+		miner.compute(&job, 0..1 << 16).unwrap();
+		let found = miner.take_solutions();
+		assert!(!found.is_empty());
+
+		let verifier: Box<dyn Verifier> = Box::new(PpCPU::<KawPowParams>::new());
+		for (nonce, mix_hash) in found {
+			let (_, expected) = verifier.verify_hash(&header, 20, nonce).unwrap();
+			assert_eq!(mix_hash, expected);
+		}
+	}
+}
@@ -0,0 +1,94 @@
+//! Minimal NVRTC bindings used to compile generated kernels to PTX.
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+type NvrtcProgram = *mut c_void;
+
+const NVRTC_SUCCESS: c_int = 0;
+
+#[link(name = "nvrtc")]
+extern "C" {
+	fn nvrtcCreateProgram(
+		prog: *mut NvrtcProgram,
+		src: *const c_char,
+		name: *const c_char,
+		num_headers: c_int,
+		headers: *const *const c_char,
+		include_names: *const *const c_char,
+	) -> c_int;
+	fn nvrtcCompileProgram(
+		prog: NvrtcProgram,
+		num_options: c_int,
+		options: *const *const c_char,
+	) -> c_int;
+	fn nvrtcGetPTXSize(prog: NvrtcProgram, size: *mut usize) -> c_int;
+	fn nvrtcGetPTX(prog: NvrtcProgram, ptx: *mut c_char) -> c_int;
+	fn nvrtcGetProgramLogSize(prog: NvrtcProgram, size: *mut usize) -> c_int;
+	fn nvrtcGetProgramLog(prog: NvrtcProgram, log: *mut c_char) -> c_int;
+	fn nvrtcDestroyProgram(prog: *mut NvrtcProgram) -> c_int;
+	fn nvrtcVersion(major: *mut c_int, minor: *mut c_int) -> c_int;
+}
+
+/// NVRTC version as `nvrtcMAJOR.MINOR`, to tag the PTX it produces.
+pub fn version() -> Result<String, String> {
+	let (mut major, mut minor) = (0, 0);
+	if unsafe { nvrtcVersion(&mut major, &mut minor) } != NVRTC_SUCCESS {
+		return Err("nvrtcVersion failed".to_string());
+	}
+	Ok(format!("nvrtc{}.{}", major, minor))
+}
+
+unsafe fn program_log(prog: NvrtcProgram) -> String {
+	let mut size = 0;
+	if nvrtcGetProgramLogSize(prog, &mut size) != NVRTC_SUCCESS || size == 0 {
+		return String::new();
+	}
+	let mut log = vec![0u8; size];
+	nvrtcGetProgramLog(prog, log.as_mut_ptr() as *mut c_char);
+	String::from_utf8_lossy(&log[..size - 1]).into_owned()
+}
+
+/// Compile `source` for compute capability `major.minor`, returning PTX without the
+/// trailing NUL. On failure the error holds the NVRTC log.
+pub fn compile_ptx(source: &str, major: u32, minor: u32) -> Result<Vec<u8>, String> {
+	let src = CString::new(source).map_err(|e| e.to_string())?;
+	let name = CString::new("progpow.cu").unwrap();
+	let options = [
+		CString::new(format!("--gpu-architecture=compute_{}{}", major, minor)).unwrap(),
+		CString::new("-lineinfo").unwrap(),
+	];
+	let option_ptrs: Vec<*const c_char> = options.iter().map(|o| o.as_ptr()).collect();
+
+	unsafe {
+		let mut prog: NvrtcProgram = ptr::null_mut();
+		if nvrtcCreateProgram(
+			&mut prog,
+			src.as_ptr(),
+			name.as_ptr(),
+			0,
+			ptr::null(),
+			ptr::null(),
+		) != NVRTC_SUCCESS
+		{
+			return Err("nvrtcCreateProgram failed".to_string());
+		}
+
+		let result = if nvrtcCompileProgram(prog, option_ptrs.len() as c_int, option_ptrs.as_ptr())
+			!= NVRTC_SUCCESS
+		{
+			Err(program_log(prog))
+		} else {
+			let mut size = 0;
+			nvrtcGetPTXSize(prog, &mut size);
+			let mut ptx = vec![0u8; size];
+			nvrtcGetPTX(prog, ptx.as_mut_ptr() as *mut c_char);
+			ptx.truncate(size.saturating_sub(1));
+			Ok(ptx)
+		};
+
+		nvrtcDestroyProgram(&mut prog);
+		result
+	}
+}
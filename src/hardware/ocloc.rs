@@ -0,0 +1,102 @@
+//! Minimal bindings to Intel's offline compiler library, used to compile generated OpenCL
+//! C kernels to SPIR-V for Level Zero.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+
+#[link(name = "ocloc")]
+extern "C" {
+	fn oclocInvoke(
+		num_args: u32,
+		argv: *const *const c_char,
+		num_sources: u32,
+		data_sources: *const *const u8,
+		len_sources: *const u64,
+		name_sources: *const *const c_char,
+		num_input_headers: u32,
+		data_input_headers: *const *const u8,
+		len_input_headers: *const u64,
+		name_input_headers: *const *const c_char,
+		num_outputs: *mut u32,
+		data_outputs: *mut *mut *mut u8,
+		len_outputs: *mut *mut u64,
+		name_outputs: *mut *mut *mut c_char,
+	) -> c_int;
+	fn oclocFreeOutput(
+		num_outputs: *mut u32,
+		data_outputs: *mut *mut *mut u8,
+		len_outputs: *mut *mut u64,
+		name_outputs: *mut *mut *mut c_char,
+	) -> c_int;
+}
+
+/// Compile OpenCL C `source` with `options` to SPIR-V. On failure the error holds the
+/// compiler log.
+pub fn compile_spirv(source: &str, options: &str) -> Result<Vec<u8>, String> {
+	let file = CString::new("progpow.cl").unwrap();
+	let args = [
+		"ocloc",
+		"compile",
+		"-file",
+		"progpow.cl",
+		"-spv_only",
+		"-options",
+		options,
+	]
+	.iter()
+	.map(|arg| CString::new(*arg).map_err(|e| e.to_string()))
+	.collect::<Result<Vec<_>, _>>()?;
+	let arg_ptrs: Vec<*const c_char> = args.iter().map(|a| a.as_ptr()).collect();
+	// ocloc expects sources to be NUL-terminated and counted with the terminator.
+	let src = CString::new(source).map_err(|e| e.to_string())?;
+	let src = src.as_bytes_with_nul();
+
+	unsafe {
+		let mut num_outputs = 0;
+		let mut data_outputs = ptr::null_mut();
+		let mut len_outputs = ptr::null_mut();
+		let mut name_outputs = ptr::null_mut();
+		let status = oclocInvoke(
+			arg_ptrs.len() as u32,
+			arg_ptrs.as_ptr(),
+			1,
+			&src.as_ptr(),
+			&(src.len() as u64),
+			&file.as_ptr(),
+			0,
+			ptr::null(),
+			ptr::null(),
+			ptr::null(),
+			&mut num_outputs,
+			&mut data_outputs,
+			&mut len_outputs,
+			&mut name_outputs,
+		);
+
+		let mut spirv = None;
+		let mut log = String::new();
+		for i in 0..num_outputs as usize {
+			let name = CStr::from_ptr(*name_outputs.add(i)).to_string_lossy();
+			let data = slice::from_raw_parts(*data_outputs.add(i), *len_outputs.add(i) as usize);
+			if name.ends_with(".spv") {
+				spirv = Some(data.to_vec());
+			} else if name.ends_with(".log") {
+				log.push_str(&String::from_utf8_lossy(data));
+			}
+		}
+		oclocFreeOutput(
+			&mut num_outputs,
+			&mut data_outputs,
+			&mut len_outputs,
+			&mut name_outputs,
+		);
+
+		match spirv {
+			Some(spirv) if status == 0 => Ok(spirv),
+			_ if log.is_empty() => Err(format!("ocloc failed with status {}", status)),
+			_ => Err(log),
+		}
+	}
+}
@@ -0,0 +1,296 @@
+//! OpenCL miner driven entirely from Rust through the `ocl` crate, without libethash-cl.
+
+use ocl::enums::{DeviceInfo, DeviceInfoResult, ProgramInfo, ProgramInfoResult};
+use ocl::{Buffer, Context, Device, Kernel, Platform, Program, Queue};
+
+use super::kernel::{
+	dag_words, driver, header_words, host_dag_chunked, nonces_per_launch, DagUploadOptions, Found,
+	SearchResults,
+};
+use super::tune::{self, LaunchConfig};
+use crate::debug::TRACE_WORDS;
+use crate::epoch::{epoch_for_height, next_period_height};
+use crate::generator::Backend;
+use crate::kernel_cache::KernelCache;
+use crate::types::{ProgPowError, H256};
+use crate::PROGPOW_CACHE_WORDS;
+use progpow_base::params::ProgPowParams;
+use std::path::Path;
+use std::sync::Arc;
+
+/// One OpenCL device with its DAG, light cache and result buffer.
+pub struct OpenClMiner {
+	device: Device,
+	context: Context,
+	queue: Queue,
+	kernels: KernelCache,
+	/// Period and `GROUP_SIZE` the program was built for.
+	program: Option<((u64, u32), Program)>,
+	dag: Option<(u64, Buffer<u32>)>,
+	cdag: Buffer<u32>,
+	/// Sized for the kernels of `kernels`.
+	results: Buffer<u8>,
+	debug: Buffer<u32>,
+	launch: LaunchConfig,
+	dag_upload: DagUploadOptions,
+}
+
+impl OpenClMiner {
+	/// Open device `index` of `platform` (the first platform if `None`).
+	pub fn new(platform: Option<usize>, index: usize) -> Result<Self, ProgPowError> {
+		let platform = match platform {
+			Some(i) => *Platform::list()
+				.get(i)
+				.ok_or_else(|| ProgPowError::Driver(format!("no OpenCL platform {}", i)))?,
+			None => Platform::default(),
+		};
+		let device = Device::by_idx_wrap(platform, index).map_err(driver)?;
+		let context = Context::builder()
+			.platform(platform)
+			.devices(device)
+			.build()
+			.map_err(driver)?;
+		let queue = Queue::new(&context, device, None).map_err(driver)?;
+		let cdag = Buffer::<u32>::builder()
+			.queue(queue.clone())
+			.len(PROGPOW_CACHE_WORDS)
+			.build()
+			.map_err(driver)?;
+		let kernels = KernelCache::new();
+		let results = Buffer::<u8>::builder()
+			.queue(queue.clone())
+			.len(SearchResults::bytes(kernels.search_results()))
+			.build()
+			.map_err(driver)?;
+		// The kernel takes the trace buffer unconditionally; it is only written to when
+		// built with `debug-trace`, up to `TRACE_WORDS` words.
+		let trace_words = if cfg!(feature = "debug-trace") {
+			TRACE_WORDS
+		} else {
+			1
+		};
+		let debug = Buffer::<u32>::builder()
+			.queue(queue.clone())
+			.len(trace_words)
+			.build()
+			.map_err(driver)?;
+
+		Ok(OpenClMiner {
+			device,
+			context,
+			queue,
+			kernels,
+			program: None,
+			dag: None,
+			cdag,
+			results,
+			debug,
+			launch: LaunchConfig::default(),
+			dag_upload: DagUploadOptions::default(),
+		})
+	}
+
+	/// Keep built program binaries in `dir` so a restarted miner loads them instead of
+	/// compiling again.
+	pub fn set_kernel_dir(&mut self, dir: &Path) -> Result<(), ProgPowError> {
+		let version = match self
+			.device
+			.info(DeviceInfo::DriverVersion)
+			.map_err(driver)?
+		{
+			DeviceInfoResult::DriverVersion(version) => version,
+			other => return Err(driver(format!("unexpected device info {:?}", other))),
+		};
+		self.kernels = KernelCache::with_dir(dir)
+			.map_err(driver)?
+			.with_driver(&version);
+		Ok(())
+	}
+
+	/// Nonces searched by each call to `search`.
+	pub fn batch_size(&self) -> u64 {
+		nonces_per_launch(self.launch.work_items())
+	}
+
+	pub fn launch_config(&self) -> LaunchConfig {
+		self.launch
+	}
+
+	/// A new thread count rebuilds the program on the next `search`, since the kernel
+	/// requires its work-group size at compile time.
+	pub fn set_launch_config(&mut self, launch: LaunchConfig) {
+		self.launch = launch;
+	}
+
+	/// How the next DAG is uploaded. Only `chunk_bytes` applies to this backend.
+	pub fn set_dag_upload(&mut self, options: DagUploadOptions) {
+		self.dag_upload = options;
+	}
+
+	/// Use the launch shape saved for this device model in `dir`, benchmarking
+	/// `LaunchConfig::candidates` at `height` to create it on first use.
+	pub fn autotune<P: ProgPowParams>(
+		&mut self,
+		dir: &Path,
+		height: u64,
+	) -> Result<LaunchConfig, ProgPowError> {
+		self.prepare::<P>(height)?;
+		let name = self.device.name().map_err(driver)?;
+		let path = tune::tuning_path(dir, "opencl", &name);
+		let header = [0u8; 32];
+		let launch = tune::load_or_tune(&path, |config| {
+			tune::measure(config, |config| {
+				self.launch = config;
+				// A zero target never matches, so every launch does the full batch.
+				self.search::<P>(&header, height, 0, 0)?;
+				Ok(self.batch_size())
+			})
+		})?;
+		self.launch = launch;
+		Ok(launch)
+	}
+
+	/// Build the program for the period of `height` and the DAG for its epoch,
+	/// skipping whatever is already current.
+	pub fn prepare<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		self.prepare_kernel::<P>(height)?;
+		self.prepare_dag::<P>(height)
+	}
+
+	/// Build the program for the period of `height` unless it is already current.
+	pub fn prepare_kernel<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		let key = (P::prog_seed(height), self.launch.threads);
+		if self.program.as_ref().map(|(k, _)| *k) != Some(key) {
+			let binary = self.kernel_binary::<P>(height)?;
+			let program = Program::builder()
+				.binaries(&[&binary[..]])
+				.devices(self.device)
+				.build(&self.context)
+				.map_err(driver)?;
+			self.kernels.evict_before(key.0);
+			self.program = Some((key, program));
+		}
+		Ok(())
+	}
+
+	/// Build the kernel of the period after the one of `height` ahead of time, so the
+	/// switch only has to load it. Returns the first height of that period.
+	pub fn precompile_next_period<P: ProgPowParams>(
+		&self,
+		height: u64,
+	) -> Result<u64, ProgPowError> {
+		let next = next_period_height::<P>(height);
+		self.kernel_binary::<P>(next)?;
+		Ok(next)
+	}
+
+	fn kernel_binary<P: ProgPowParams>(&self, height: u64) -> Result<Arc<Vec<u8>>, ProgPowError> {
+		let threads = self.launch.threads;
+		// Binaries are specific to the device model and the compiled-in work-group size.
+		let arch = format!("{}-wg{}", self.device.name().map_err(driver)?, threads);
+		self.kernels
+			.binary_for_arch::<P, _, _>(Backend::OpenCl, &arch, height, |source| {
+				let program = Program::builder()
+					.src(source)
+					.cmplr_def("GROUP_SIZE", threads as i32)
+					.devices(self.device)
+					.build(&self.context)
+					.map_err(driver)?;
+				match program.info(ProgramInfo::Binaries).map_err(driver)? {
+					ProgramInfoResult::Binaries(mut binaries) if !binaries.is_empty() => {
+						Ok(binaries.swap_remove(0))
+					}
+					_ => Err(ProgPowError::Driver(
+						"OpenCL returned no program binary".to_string(),
+					)),
+				}
+			})
+	}
+
+	/// Build the DAG for the epoch of `height` unless it is already current.
+	pub fn prepare_dag<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		let epoch = epoch_for_height::<P>(height);
+		if self.dag.as_ref().map(|(e, _)| *e) != Some(epoch) {
+			// Free the old DAG before allocating the next one.
+			self.dag = None;
+			self.dag = Some((epoch, self.build_dag::<P>(height)?));
+		}
+		Ok(())
+	}
+
+	fn build_dag<P: ProgPowParams>(&mut self, height: u64) -> Result<Buffer<u32>, ProgPowError> {
+		let needed = (dag_words::<P>(height) * 4) as u64;
+		let available = self.max_buffer_size()?;
+		if available < needed {
+			return Err(ProgPowError::InsufficientVram { needed, available });
+		}
+
+		let dag = Buffer::<u32>::builder()
+			.queue(self.queue.clone())
+			.len(dag_words::<P>(height))
+			.build()
+			.map_err(driver)?;
+		let chunk_nodes = self.dag_upload.chunk_nodes();
+		let cdag = host_dag_chunked::<P, _>(height, chunk_nodes, |offset, chunk| {
+			dag.write(chunk)
+				.offset(offset)
+				.enq()
+				.map_err(|_| ProgPowError::DAG)
+		})?;
+		self.cdag.write(&cdag[..]).enq().map_err(driver)?;
+		Ok(dag)
+	}
+
+	/// Largest buffer the device can allocate, which the DAG has to fit in.
+	fn max_buffer_size(&self) -> Result<u64, ProgPowError> {
+		let info = |kind| match self.device.info(kind).map_err(driver)? {
+			DeviceInfoResult::GlobalMemSize(bytes) | DeviceInfoResult::MaxMemAllocSize(bytes) => {
+				Ok(bytes)
+			}
+			other => Err(driver(format!("unexpected device info {:?}", other))),
+		};
+		Ok(info(DeviceInfo::GlobalMemSize)?.min(info(DeviceInfo::MaxMemAllocSize)?))
+	}
+
+	/// Search `batch_size()` nonces from `start_nonce`, returning each nonce whose
+	/// result is at or below `target` together with its mix, see `Found`.
+	pub fn search<P: ProgPowParams>(
+		&mut self,
+		header: &H256,
+		height: u64,
+		target: u64,
+		start_nonce: u64,
+	) -> Result<Found, ProgPowError> {
+		self.prepare::<P>(height)?;
+		let program = &self.program.as_ref().ok_or(ProgPowError::NoInitialized)?.1;
+		let dag = &self.dag.as_ref().ok_or(ProgPowError::NoInitialized)?.1;
+		let [h0, h1, h2, h3] = header_words(header);
+
+		let mut bytes = vec![0u8; self.results.len()];
+		self.results.write(&bytes[..]).enq().map_err(driver)?;
+		let kernel = Kernel::builder()
+			.program(program)
+			.name(Backend::OpenCl.entry_point())
+			.queue(self.queue.clone())
+			.global_work_size(self.launch.work_items() as usize)
+			.local_work_size(self.launch.threads as usize)
+			.arg(start_nonce)
+			.arg(target)
+			.arg(h0)
+			.arg(h1)
+			.arg(h2)
+			.arg(h3)
+			.arg(dag)
+			.arg(&self.cdag)
+			.arg(&self.results)
+			.arg(&self.debug)
+			.build()
+			.map_err(driver)?;
+		unsafe {
+			kernel.enq().map_err(driver)?;
+		}
+
+		self.results.read(&mut bytes[..]).enq().map_err(driver)?;
+		Ok(SearchResults::from_bytes(&bytes).to_found())
+	}
+}
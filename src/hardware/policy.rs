@@ -0,0 +1,73 @@
+//! Which headers `PpCPU::verify_headers_parallel` hashes, so nodes can encode their sync
+//! strategy, e.g. trusting a checkpoint or sampling during fast sync.
+
+use crate::types::H256;
+
+pub trait VerificationPolicy {
+	/// Whether to hash the header at `height`. Skipped items are `None` in the results.
+	fn should_verify(&self, header_hash: &H256, height: u64) -> bool;
+}
+
+/// Any `Fn(&H256, u64) -> bool` is a policy.
+impl<F: Fn(&H256, u64) -> bool> VerificationPolicy for F {
+	fn should_verify(&self, header_hash: &H256, height: u64) -> bool {
+		self(header_hash, height)
+	}
+}
+
+/// Verify every header.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VerifyAll;
+
+impl VerificationPolicy for VerifyAll {
+	fn should_verify(&self, _: &H256, _: u64) -> bool {
+		true
+	}
+}
+
+/// Trust headers at or below a checkpoint height the node ships with, verify those above.
+#[derive(Debug, Clone, Copy)]
+pub struct Checkpoint(pub u64);
+
+impl VerificationPolicy for Checkpoint {
+	fn should_verify(&self, _: &H256, height: u64) -> bool {
+		height > self.0
+	}
+}
+
+/// Verify one height in `every`, and every height above `until`, for fast sync.
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+	pub every: u64,
+	pub until: u64,
+}
+
+impl VerificationPolicy for Sample {
+	fn should_verify(&self, _: &H256, height: u64) -> bool {
+		height > self.until || height % self.every.max(1) == 0
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_policies() {
+		let header = [0; 32];
+		assert!(VerifyAll.should_verify(&header, 0));
+		assert!(!Checkpoint(100).should_verify(&header, 100));
+		assert!(Checkpoint(100).should_verify(&header, 101));
+
+		let sample = Sample {
+			every: 10,
+			until: 1000,
+		};
+		assert!(sample.should_verify(&header, 990));
+		assert!(!sample.should_verify(&header, 991));
+		assert!(sample.should_verify(&header, 1001));
+
+		let odd = |_: &H256, height: u64| height % 2 == 1;
+		assert!(odd.should_verify(&header, 3));
+	}
+}
@@ -0,0 +1,177 @@
+//! Launch shape of the generated kernels, chosen by benchmarking on first use.
+//!
+//! The kernels hash one nonce per group of `PROGPOW_LANES` threads, so the shape is just
+//! the grid and block sizes. The winner is written to a per-device tuning file and read
+//! back on later launches.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::generator::PROGPOW_LANES;
+use crate::types::ProgPowError;
+
+/// Launches timed per candidate, after one warm-up launch.
+const TUNE_LAUNCHES: u32 = 4;
+
+/// Blocks (work-groups) and threads per block of one kernel launch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LaunchConfig {
+	pub blocks: u32,
+	pub threads: u32,
+}
+
+impl Default for LaunchConfig {
+	fn default() -> Self {
+		LaunchConfig {
+			blocks: 1024,
+			threads: 256,
+		}
+	}
+}
+
+impl LaunchConfig {
+	/// Threads in the whole grid.
+	pub fn work_items(&self) -> u64 {
+		self.blocks as u64 * self.threads as u64
+	}
+
+	/// Shapes tried by `tune`.
+	pub fn candidates() -> Vec<LaunchConfig> {
+		let mut out = Vec::new();
+		for &threads in &[128, 256, 512] {
+			for &blocks in &[512, 1024, 2048, 4096, 8192] {
+				out.push(LaunchConfig { blocks, threads });
+			}
+		}
+		out
+	}
+
+	/// Read a tuning file written by `save`. `None` if it is missing or malformed.
+	pub fn load(path: &Path) -> Option<LaunchConfig> {
+		let text = fs::read_to_string(path).ok()?;
+		let (mut blocks, mut threads) = (None, None);
+		for line in text.lines() {
+			match line.split_once('=') {
+				Some(("blocks", v)) => blocks = v.trim().parse().ok(),
+				Some(("threads", v)) => threads = v.trim().parse().ok(),
+				_ => {}
+			}
+		}
+		let config = LaunchConfig {
+			blocks: blocks?,
+			threads: threads?,
+		};
+		if config.blocks == 0 || config.threads == 0 || config.threads % PROGPOW_LANES as u32 != 0 {
+			return None;
+		}
+		Some(config)
+	}
+
+	pub fn save(&self, path: &Path) -> io::Result<()> {
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		fs::write(
+			path,
+			format!("blocks={}\nthreads={}\n", self.blocks, self.threads),
+		)
+	}
+}
+
+/// Tuning file of the device called `device` on `backend` inside `dir`.
+pub fn tuning_path(dir: &Path, backend: &str, device: &str) -> PathBuf {
+	let device: String = device
+		.chars()
+		.map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+		.collect();
+	dir.join(format!("{}-{}.tune", backend, device))
+}
+
+/// Nonces per second of `launch`, which runs one launch of `config` and returns the
+/// nonces it covered.
+pub fn measure<F>(config: LaunchConfig, mut launch: F) -> Result<f64, ProgPowError>
+where
+	F: FnMut(LaunchConfig) -> Result<u64, ProgPowError>,
+{
+	launch(config)?;
+	let start = Instant::now();
+	let mut nonces = 0;
+	for _ in 0..TUNE_LAUNCHES {
+		nonces += launch(config)?;
+	}
+	let secs = start.elapsed().as_secs_f64();
+	Ok(if secs == 0.0 {
+		0.0
+	} else {
+		nonces as f64 / secs
+	})
+}
+
+/// The fastest of `candidates` by `rate`. Candidates the device rejects are skipped; the
+/// last error is returned if none runs.
+pub fn tune<F>(candidates: &[LaunchConfig], mut rate: F) -> Result<LaunchConfig, ProgPowError>
+where
+	F: FnMut(LaunchConfig) -> Result<f64, ProgPowError>,
+{
+	let mut best: Option<(LaunchConfig, f64)> = None;
+	let mut last_err = ProgPowError::NoInitialized;
+	for &config in candidates {
+		match rate(config) {
+			Ok(r) if best.map_or(true, |(_, b)| r > b) => best = Some((config, r)),
+			Ok(_) => {}
+			Err(e) => last_err = e,
+		}
+	}
+	best.map(|(config, _)| config).ok_or(last_err)
+}
+
+/// The config saved at `path`, or the result of `tune` over `LaunchConfig::candidates`,
+/// which is then saved there.
+pub fn load_or_tune<F>(path: &Path, rate: F) -> Result<LaunchConfig, ProgPowError>
+where
+	F: FnMut(LaunchConfig) -> Result<f64, ProgPowError>,
+{
+	if let Some(config) = LaunchConfig::load(path) {
+		return Ok(config);
+	}
+	let config = tune(&LaunchConfig::candidates(), rate)?;
+	// A missing file only costs a re-tune next time.
+	let _ = config.save(path);
+	Ok(config)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_tune_picks_fastest_and_persists() {
+		let path = std::env::temp_dir().join(format!("progpow-tune-{}.tune", std::process::id()));
+		let _ = fs::remove_file(&path);
+
+		let mut runs = 0;
+		let config = load_or_tune(&path, |c| {
+			runs += 1;
+			if c.threads == 512 {
+				return Err(ProgPowError::Driver("too many threads".into()));
+			}
+			Ok((c.blocks * c.threads) as f64 / if c.blocks > 2048 { 4.0 } else { 1.0 })
+		})
+		.unwrap();
+		assert_eq!(
+			config,
+			LaunchConfig {
+				blocks: 2048,
+				threads: 256
+			}
+		);
+		assert_eq!(runs, LaunchConfig::candidates().len());
+
+		let again = load_or_tune(&path, |_| panic!("tuning file not used")).unwrap();
+		assert_eq!(again, config);
+		let _ = fs::remove_file(&path);
+	}
+}
@@ -0,0 +1,142 @@
+//! GPU mining with every solution re-checked on the CPU.
+
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use super::cpu::{CpuParams, PpCPU};
+use super::gpu::PpGPU;
+use crate::types::difficulty::meets_target;
+use crate::types::{Miner, MiningJob, ProgPowError, Verifier};
+use progpow_gpu::Solution;
+
+/// A `PpGPU` whose solutions are recomputed by a `PpCPU` before they are returned.
+///
+/// A solution whose mix differs from the CPU's, or whose final hash misses the job
+/// target, is a hardware error: it is counted and dropped instead of being submitted.
+pub struct VerifiedMiner<P: CpuParams> {
+	gpu: PpGPU,
+	cpu: PpCPU<P>,
+	job: Mutex<Option<MiningJob>>,
+	verified: AtomicU64,
+	hardware_errors: AtomicU64,
+}
+
+impl<P: CpuParams> VerifiedMiner<P> {
+	pub fn new(gpu: PpGPU, cpu: PpCPU<P>) -> Self {
+		VerifiedMiner {
+			gpu,
+			cpu,
+			job: Mutex::new(None),
+			verified: AtomicU64::new(0),
+			hardware_errors: AtomicU64::new(0),
+		}
+	}
+
+	pub fn init(&mut self) -> Result<(), ProgPowError> {
+		self.gpu.init()
+	}
+
+	/// See `Miner::compute`.
+	pub fn compute(&self, job: &MiningJob, nonce_range: Range<u64>) -> Result<(), ProgPowError> {
+		*self.job.lock().unwrap() = Some(job.clone());
+		self.gpu.compute(job, nonce_range)
+	}
+
+	/// The GPU solution for the current job, once the CPU agrees with it. `final_hash` is
+	/// filled in from the CPU result.
+	pub fn solution(&self) -> Result<Option<Solution>, ProgPowError> {
+		let solution = match self.gpu.get_solutions() {
+			Some(solution) => solution,
+			None => return Ok(None),
+		};
+		let job = match *self.job.lock().unwrap() {
+			Some(ref job) if job.job_id == solution.job_id => job.clone(),
+			_ => return Ok(None),
+		};
+		self.recheck(&job, solution)
+	}
+
+	fn recheck(
+		&self,
+		job: &MiningJob,
+		mut solution: Solution,
+	) -> Result<Option<Solution>, ProgPowError> {
+		let (final_hash, mix_hash) =
+			self.cpu
+				.verify_hash(&job.header, job.height, solution.nonce)?;
+		if mix_hash != solution.mix_hash || !meets_target(&final_hash, &job.target) {
+			self.hardware_errors.fetch_add(1, Ordering::Relaxed);
+			return Ok(None);
+		}
+
+		self.verified.fetch_add(1, Ordering::Relaxed);
+		solution.final_hash = Some(final_hash);
+		Ok(Some(solution))
+	}
+
+	/// Solutions that passed the CPU check.
+	pub fn verified(&self) -> u64 {
+		self.verified.load(Ordering::Relaxed)
+	}
+
+	/// Solutions the CPU rejected.
+	pub fn hardware_errors(&self) -> u64 {
+		self.hardware_errors.load(Ordering::Relaxed)
+	}
+
+	pub fn gpu(&self) -> &PpGPU {
+		&self.gpu
+	}
+
+	pub fn cpu(&self) -> &PpCPU<P> {
+		&self.cpu
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use progpow_base::params::KawPowParams;
+	use std::time::Duration;
+
+	#[test]
+	fn test_recheck_counts_hardware_errors() {
+		let miner = VerifiedMiner::new(
+			PpGPU::new(0, progpow_gpu::Driver::OCL),
+			PpCPU::<KawPowParams>::new(),
+		);
+		let job = MiningJob::new(7, [0u8; 32], 20, 0, [0xff; 32]);
+		let nonce = 10123012301;
+		let (final_hash, mix_hash) = miner
+			.cpu()
+			.verify_hash(&job.header, job.height, nonce)
+			.unwrap();
+		let solution = Solution {
+			nonce,
+			mix_hash,
+			final_hash: None,
+			device_id: 0,
+			job_id: job.job_id,
+			search_duration: Duration::from_secs(0),
+		};
+
+		let ok = miner.recheck(&job, solution.clone()).unwrap().unwrap();
+		assert_eq!(ok.final_hash, Some(final_hash));
+
+		let bad_mix = Solution {
+			mix_hash: [0u8; 32],
+			..solution.clone()
+		};
+		assert_eq!(miner.recheck(&job, bad_mix).unwrap(), None);
+
+		let hard = MiningJob {
+			target: [0; 32],
+			..job
+		};
+		assert_eq!(miner.recheck(&hard, solution).unwrap(), None);
+
+		assert_eq!(miner.verified(), 1);
+		assert_eq!(miner.hardware_errors(), 2);
+	}
+}
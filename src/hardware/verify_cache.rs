@@ -0,0 +1,126 @@
+//! Small LRU of verification results, for nodes that check the same header repeatedly.
+
+use std::collections::HashMap;
+
+use crate::types::H256;
+
+/// Hit and miss counts of a `PpCPU` verification cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CacheStats {
+	pub hits: u64,
+	pub misses: u64,
+	/// Entries currently held.
+	pub len: usize,
+	pub capacity: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+	header: H256,
+	height: u64,
+	nonce: u64,
+}
+
+/// `verify` results keyed by header, height and nonce. Eviction scans for the least
+/// recently used entry, which is cheap at the few thousand entries this is meant for.
+pub(crate) struct VerifyCache {
+	capacity: usize,
+	tick: u64,
+	entries: HashMap<Key, (u64, [u32; 8], [u32; 8])>,
+	hits: u64,
+	misses: u64,
+}
+
+impl VerifyCache {
+	pub fn new(capacity: usize) -> Self {
+		VerifyCache {
+			capacity,
+			tick: 0,
+			entries: HashMap::with_capacity(capacity),
+			hits: 0,
+			misses: 0,
+		}
+	}
+
+	pub fn get(&mut self, header: &H256, height: u64, nonce: u64) -> Option<([u32; 8], [u32; 8])> {
+		self.tick += 1;
+		let key = Key {
+			header: *header,
+			height,
+			nonce,
+		};
+		match self.entries.get_mut(&key) {
+			Some(entry) => {
+				entry.0 = self.tick;
+				self.hits += 1;
+				Some((entry.1, entry.2))
+			}
+			None => {
+				self.misses += 1;
+				None
+			}
+		}
+	}
+
+	pub fn insert(&mut self, header: &H256, height: u64, nonce: u64, result: ([u32; 8], [u32; 8])) {
+		if self.capacity == 0 {
+			return;
+		}
+		if self.entries.len() >= self.capacity {
+			let oldest = self
+				.entries
+				.iter()
+				.min_by_key(|(_, entry)| entry.0)
+				.map(|(key, _)| *key);
+			if let Some(oldest) = oldest {
+				self.entries.remove(&oldest);
+			}
+		}
+		self.tick += 1;
+		let key = Key {
+			header: *header,
+			height,
+			nonce,
+		};
+		self.entries.insert(key, (self.tick, result.0, result.1));
+	}
+
+	pub fn stats(&self) -> CacheStats {
+		CacheStats {
+			hits: self.hits,
+			misses: self.misses,
+			len: self.entries.len(),
+			capacity: self.capacity,
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_lru_eviction() {
+		let mut cache = VerifyCache::new(2);
+		let result = ([1u32; 8], [2u32; 8]);
+		cache.insert(&[0u8; 32], 1, 1, result);
+		cache.insert(&[0u8; 32], 1, 2, result);
+		// Touch nonce 1 so nonce 2 is the one evicted.
+		assert_eq!(cache.get(&[0u8; 32], 1, 1), Some(result));
+		cache.insert(&[0u8; 32], 1, 3, result);
+
+		assert_eq!(cache.get(&[0u8; 32], 1, 2), None);
+		assert_eq!(cache.get(&[0u8; 32], 1, 1), Some(result));
+		assert_eq!(cache.get(&[0u8; 32], 2, 1), None);
+		assert_eq!(
+			cache.stats(),
+			CacheStats {
+				hits: 2,
+				misses: 2,
+				len: 2,
+				capacity: 2
+			}
+		);
+	}
+}
@@ -0,0 +1,205 @@
+//! Verification on a pool of worker threads sharing one light cache per epoch, for nodes
+//! validating bursts of headers during sync.
+
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use parking_lot::RwLock;
+use progpow_cpu::compute::Light;
+
+use super::cpu::PpCPU;
+use crate::params::{with_preset, DynParams};
+use crate::types::{ProgPowError, H256};
+use progpow_base::params::ProgPowParams;
+
+/// `(final_hash, mix_hash)` words, as returned by `Verifier::verify`.
+pub type VerifyResult = Result<([u32; 8], [u32; 8]), ProgPowError>;
+
+/// Light caches kept at once. Sync moves forward an epoch at a time, so the current and
+/// previous ones are enough; the third covers tips verified meanwhile.
+const LIGHTS_KEPT: usize = 3;
+
+struct Request {
+	header: H256,
+	height: u64,
+	nonce: u64,
+	reply: oneshot::Sender<VerifyResult>,
+}
+
+struct Shared {
+	cpu: PpCPU,
+	/// Light caches by epoch, oldest first.
+	lights: RwLock<Vec<(u64, Arc<Light>)>>,
+	/// Held while a light cache is built, so two workers never build the same one.
+	building: Mutex<()>,
+}
+
+impl Shared {
+	fn find(&self, epoch: u64) -> Option<Arc<Light>> {
+		self.lights
+			.read()
+			.iter()
+			.find(|(e, _)| *e == epoch)
+			.map(|(_, light)| light.clone())
+	}
+
+	/// The light cache of `height`, built by this thread if no other has it yet. Only
+	/// workers of the same epoch wait on a build.
+	fn light<P: ProgPowParams>(&self, height: u64) -> Arc<Light> {
+		let epoch = self.cpu.params().epoch(height);
+		if let Some(light) = self.find(epoch) {
+			return light;
+		}
+		let _building = self.building.lock().unwrap();
+		if let Some(light) = self.find(epoch) {
+			return light;
+		}
+		let light = Arc::new(self.cpu.light::<P>(height));
+		let mut lights = self.lights.write();
+		if lights.len() == LIGHTS_KEPT {
+			lights.remove(0);
+		}
+		lights.push((epoch, light.clone()));
+		light
+	}
+
+	fn verify(&self, header: &H256, height: u64, nonce: u64) -> VerifyResult {
+		with_preset!(preset: self.cpu.preset(), P => {
+			Ok(self.light::<P>(height).compute::<P>(header, nonce, height))
+		})
+	}
+}
+
+/// Verifies submitted nonces on `threads` worker threads.
+///
+/// Requests wait in a bounded queue; `submit` blocks while it is full and `try_submit`
+/// returns `None`, so a burst cannot grow memory without bound. Dropping the
+/// service lets the workers finish the queue and joins them.
+pub struct VerifyService {
+	queue: Option<SyncSender<Request>>,
+	workers: Vec<JoinHandle<()>>,
+}
+
+impl VerifyService {
+	/// Service for `params` with a queue of 64 requests per thread.
+	pub fn spawn(threads: usize, params: DynParams) -> Result<Self, ProgPowError> {
+		let threads = threads.max(1);
+		VerifyService::with_queue(threads, params, threads * 64)
+	}
+
+	/// Service whose queue holds at most `queue` pending requests.
+	pub fn with_queue(
+		threads: usize,
+		params: DynParams,
+		queue: usize,
+	) -> Result<Self, ProgPowError> {
+		VerifyService::with_cpu(threads, PpCPU::new_dyn(params), queue)
+	}
+
+	/// Service building its light caches with `cpu`, e.g. to use its cache directory.
+	pub fn with_cpu(threads: usize, cpu: PpCPU, queue: usize) -> Result<Self, ProgPowError> {
+		with_preset!(preset: cpu.preset(), _P => Ok(()))?;
+		let shared = Arc::new(Shared {
+			cpu,
+			lights: RwLock::new(Vec::new()),
+			building: Mutex::new(()),
+		});
+		let (sender, receiver) = mpsc::sync_channel(queue);
+		let receiver = Arc::new(Mutex::new(receiver));
+		let workers = (0..threads.max(1))
+			.map(|_| {
+				let (shared, receiver) = (shared.clone(), receiver.clone());
+				thread::spawn(move || work(&shared, &receiver))
+			})
+			.collect();
+		Ok(VerifyService {
+			queue: Some(sender),
+			workers,
+		})
+	}
+
+	/// Queue `nonce` for verification, waiting for room in the queue if it is full.
+	pub fn submit(
+		&self,
+		header: &H256,
+		height: u64,
+		nonce: u64,
+	) -> oneshot::Receiver<VerifyResult> {
+		let (request, receiver) = request(header, height, nonce);
+		// The workers only exit once `queue` is dropped.
+		let _ = self.queue.as_ref().unwrap().send(request);
+		receiver
+	}
+
+	/// Like `submit`, returning `None` instead of waiting when the queue is full.
+	pub fn try_submit(
+		&self,
+		header: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Option<oneshot::Receiver<VerifyResult>> {
+		let (request, receiver) = request(header, height, nonce);
+		match self.queue.as_ref().unwrap().try_send(request) {
+			Ok(()) => Some(receiver),
+			Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => None,
+		}
+	}
+}
+
+fn request(header: &H256, height: u64, nonce: u64) -> (Request, oneshot::Receiver<VerifyResult>) {
+	let (reply, receiver) = oneshot::channel();
+	let request = Request {
+		header: *header,
+		height,
+		nonce,
+		reply,
+	};
+	(request, receiver)
+}
+
+fn work(shared: &Shared, receiver: &Mutex<Receiver<Request>>) {
+	loop {
+		// The lock is released before verifying, so other workers keep taking requests.
+		let request = receiver.lock().unwrap().recv();
+		let request = match request {
+			Ok(request) => request,
+			Err(_) => return,
+		};
+		let result = shared.verify(&request.header, request.height, request.nonce);
+		// The submitter may have stopped waiting.
+		let _ = request.reply.send(result);
+	}
+}
+
+impl Drop for VerifyService {
+	fn drop(&mut self) {
+		self.queue = None;
+		for worker in self.workers.drain(..) {
+			let _ = worker.join();
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::types::Verifier;
+	use progpow_base::params::KawPowParams;
+
+	#[test]
+	fn test_verify_service_matches_cpu() {
+		let service = VerifyService::spawn(2, DynParams::of::<KawPowParams>()).unwrap();
+		let cpu = PpCPU::<KawPowParams>::new();
+		let header = [7u8; 32];
+		let pending: Vec<_> = (0..8)
+			.map(|nonce| (nonce, service.submit(&header, 20, nonce)))
+			.collect();
+		for (nonce, result) in pending {
+			assert_eq!(
+				result.recv().unwrap().unwrap(),
+				cpu.verify(&header, 20, nonce).unwrap()
+			);
+		}
+	}
+}
@@ -0,0 +1,732 @@
+//! Vulkan miner through `ash`: the GLSL kernel is compiled to SPIR-V with shaderc and
+//! dispatched as a compute shader, a vendor-neutral path for stacks with broken OpenCL.
+//!
+//! Storage buffers are limited to `maxStorageBufferRange` bytes, often 4 GiB or less, so
+//! the DAG is split across up to `DAG_BINDINGS` buffers the shader indexes in turn.
+
+use std::ffi::{CStr, CString};
+use std::io::Cursor;
+use std::path::Path;
+use std::ptr;
+use std::sync::Arc;
+
+use ash::vk;
+
+use super::kernel::{
+	dag_words, driver, header_words, host_dag_chunked, nonces_per_launch, DagUploadOptions, Found,
+	SearchResults,
+};
+use super::tune::{self, LaunchConfig};
+use crate::epoch::{epoch_for_height, next_period_height};
+use crate::generator::Backend;
+use crate::kernel_cache::KernelCache;
+use crate::types::{ProgPowError, H256};
+use crate::PROGPOW_CACHE_WORDS;
+use progpow_base::params::ProgPowParams;
+
+/// DAG storage buffers the kernel binds, `DAG_BINDINGS` in the template.
+const DAG_BINDINGS: usize = 4;
+/// Bytes of one `dag_t` element.
+const DAG_ELEMENT_BYTES: u64 = 16;
+/// `Params` in the template: start nonce, target, header words and `dag_split`.
+const PUSH_CONSTANT_WORDS: usize = 13;
+
+fn compile_spirv(source: &str) -> Result<Vec<u8>, String> {
+	let compiler = shaderc::Compiler::new().ok_or("shaderc is unavailable")?;
+	let mut options = shaderc::CompileOptions::new().ok_or("shaderc is unavailable")?;
+	options.set_optimization_level(shaderc::OptimizationLevel::Performance);
+	compiler
+		.compile_into_spirv(
+			source,
+			shaderc::ShaderKind::Compute,
+			"progpow.comp",
+			Backend::Vulkan.entry_point(),
+			Some(&options),
+		)
+		.map(|artifact| artifact.as_binary_u8().to_vec())
+		.map_err(|e| e.to_string())
+}
+
+/// A buffer and the memory bound to it. Destroyed by `VulkanMiner::destroy_buffer`.
+struct GpuBuffer {
+	buffer: vk::Buffer,
+	memory: vk::DeviceMemory,
+	size: u64,
+}
+
+/// One Vulkan physical device with its DAG, light cache and result buffer.
+pub struct VulkanMiner {
+	_entry: ash::Entry,
+	instance: ash::Instance,
+	device: ash::Device,
+	name: String,
+	memory_properties: vk::PhysicalDeviceMemoryProperties,
+	max_storage_range: u64,
+	queue: vk::Queue,
+	command_pool: vk::CommandPool,
+	fence: vk::Fence,
+	set_layout: vk::DescriptorSetLayout,
+	pipeline_layout: vk::PipelineLayout,
+	descriptor_pool: vk::DescriptorPool,
+	descriptor_set: vk::DescriptorSet,
+	kernels: KernelCache,
+	/// Period and workgroup size the pipeline was built for.
+	pipeline: Option<((u64, u32), vk::Pipeline)>,
+	/// The DAG buffers and the `dag_t` elements in each.
+	dag: Option<(u64, Vec<GpuBuffer>, u32)>,
+	cdag: GpuBuffer,
+	results: GpuBuffer,
+	launch: LaunchConfig,
+	dag_upload: DagUploadOptions,
+}
+
+impl VulkanMiner {
+	/// Open physical device `index` of the Vulkan loader.
+	pub fn new(index: usize) -> Result<Self, ProgPowError> {
+		unsafe {
+			let entry = ash::Entry::load().map_err(driver)?;
+			let app_name = CString::new("progpow").unwrap();
+			let app_info = vk::ApplicationInfo::builder()
+				.application_name(&app_name)
+				.api_version(vk::API_VERSION_1_1);
+			let instance = entry
+				.create_instance(
+					&vk::InstanceCreateInfo::builder().application_info(&app_info),
+					None,
+				)
+				.map_err(driver)?;
+
+			let physical = instance
+				.enumerate_physical_devices()
+				.map_err(driver)?
+				.into_iter()
+				.nth(index)
+				.ok_or_else(|| ProgPowError::Driver(format!("no Vulkan device {}", index)))?;
+			let properties = instance.get_physical_device_properties(physical);
+			let name = CStr::from_ptr(properties.device_name.as_ptr())
+				.to_string_lossy()
+				.into_owned();
+			let family = instance
+				.get_physical_device_queue_family_properties(physical)
+				.iter()
+				.position(|f| f.queue_flags.contains(vk::QueueFlags::COMPUTE))
+				.ok_or_else(|| ProgPowError::Driver(format!("{} has no compute queue", name)))?
+				as u32;
+
+			let priorities = [1.0];
+			let queue_info = vk::DeviceQueueCreateInfo::builder()
+				.queue_family_index(family)
+				.queue_priorities(&priorities)
+				.build();
+			let device = instance
+				.create_device(
+					physical,
+					&vk::DeviceCreateInfo::builder()
+						.queue_create_infos(std::slice::from_ref(&queue_info)),
+					None,
+				)
+				.map_err(driver)?;
+			let queue = device.get_device_queue(family, 0);
+			let command_pool = device
+				.create_command_pool(
+					&vk::CommandPoolCreateInfo::builder()
+						.queue_family_index(family)
+						.flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER),
+					None,
+				)
+				.map_err(driver)?;
+			let fence = device
+				.create_fence(&vk::FenceCreateInfo::default(), None)
+				.map_err(driver)?;
+
+			let bindings = (0..DAG_BINDINGS as u32 + 2)
+				.map(|binding| {
+					vk::DescriptorSetLayoutBinding::builder()
+						.binding(binding)
+						.descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+						.descriptor_count(1)
+						.stage_flags(vk::ShaderStageFlags::COMPUTE)
+						.build()
+				})
+				.collect::<Vec<_>>();
+			let set_layout = device
+				.create_descriptor_set_layout(
+					&vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+					None,
+				)
+				.map_err(driver)?;
+			let push_range = vk::PushConstantRange {
+				stage_flags: vk::ShaderStageFlags::COMPUTE,
+				offset: 0,
+				size: (PUSH_CONSTANT_WORDS * 4) as u32,
+			};
+			let pipeline_layout = device
+				.create_pipeline_layout(
+					&vk::PipelineLayoutCreateInfo::builder()
+						.set_layouts(std::slice::from_ref(&set_layout))
+						.push_constant_ranges(std::slice::from_ref(&push_range)),
+					None,
+				)
+				.map_err(driver)?;
+			let pool_size = vk::DescriptorPoolSize {
+				ty: vk::DescriptorType::STORAGE_BUFFER,
+				descriptor_count: bindings.len() as u32,
+			};
+			let descriptor_pool = device
+				.create_descriptor_pool(
+					&vk::DescriptorPoolCreateInfo::builder()
+						.max_sets(1)
+						.pool_sizes(std::slice::from_ref(&pool_size)),
+					None,
+				)
+				.map_err(driver)?;
+			let descriptor_set = device
+				.allocate_descriptor_sets(
+					&vk::DescriptorSetAllocateInfo::builder()
+						.descriptor_pool(descriptor_pool)
+						.set_layouts(std::slice::from_ref(&set_layout)),
+				)
+				.map_err(driver)?[0];
+
+			let host_visible =
+				vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+			let memory_properties = instance.get_physical_device_memory_properties(physical);
+			let mut miner = VulkanMiner {
+				_entry: entry,
+				instance,
+				device,
+				name,
+				memory_properties,
+				max_storage_range: properties.limits.max_storage_buffer_range as u64,
+				queue,
+				command_pool,
+				fence,
+				set_layout,
+				pipeline_layout,
+				descriptor_pool,
+				descriptor_set,
+				kernels: KernelCache::new(),
+				pipeline: None,
+				dag: None,
+				cdag: GpuBuffer {
+					buffer: vk::Buffer::null(),
+					memory: vk::DeviceMemory::null(),
+					size: 0,
+				},
+				results: GpuBuffer {
+					buffer: vk::Buffer::null(),
+					memory: vk::DeviceMemory::null(),
+					size: 0,
+				},
+				launch: LaunchConfig::default(),
+				dag_upload: DagUploadOptions::default(),
+			};
+			miner.cdag = miner.create_buffer((PROGPOW_CACHE_WORDS * 4) as u64, host_visible)?;
+			let bytes = SearchResults::bytes(miner.kernels.search_results());
+			miner.results = miner.create_buffer(bytes as u64, host_visible)?;
+			Ok(miner)
+		}
+	}
+
+	fn memory_type(&self, bits: u32, flags: vk::MemoryPropertyFlags) -> Option<u32> {
+		(0..self.memory_properties.memory_type_count).find(|&i| {
+			bits & (1 << i) != 0
+				&& self.memory_properties.memory_types[i as usize]
+					.property_flags
+					.contains(flags)
+		})
+	}
+
+	fn create_buffer(
+		&self,
+		size: u64,
+		flags: vk::MemoryPropertyFlags,
+	) -> Result<GpuBuffer, ProgPowError> {
+		unsafe {
+			let usage = vk::BufferUsageFlags::STORAGE_BUFFER
+				| vk::BufferUsageFlags::TRANSFER_SRC
+				| vk::BufferUsageFlags::TRANSFER_DST;
+			let buffer = self
+				.device
+				.create_buffer(
+					&vk::BufferCreateInfo::builder()
+						.size(size)
+						.usage(usage)
+						.sharing_mode(vk::SharingMode::EXCLUSIVE),
+					None,
+				)
+				.map_err(driver)?;
+			let requirements = self.device.get_buffer_memory_requirements(buffer);
+			let memory = self
+				.memory_type(requirements.memory_type_bits, flags)
+				.ok_or_else(|| ProgPowError::Driver(format!("no {:?} memory", flags)))
+				.and_then(|index| {
+					self.device
+						.allocate_memory(
+							&vk::MemoryAllocateInfo::builder()
+								.allocation_size(requirements.size)
+								.memory_type_index(index),
+							None,
+						)
+						.map_err(|e| match e {
+							vk::Result::ERROR_OUT_OF_DEVICE_MEMORY => {
+								ProgPowError::InsufficientVram {
+									needed: size,
+									available: 0,
+								}
+							}
+							e => driver(e),
+						})
+				});
+			let memory = match memory {
+				Ok(memory) => memory,
+				Err(e) => {
+					self.device.destroy_buffer(buffer, None);
+					return Err(e);
+				}
+			};
+			let buffer = GpuBuffer {
+				buffer,
+				memory,
+				size,
+			};
+			if let Err(e) = self.device.bind_buffer_memory(buffer.buffer, memory, 0) {
+				self.destroy_buffer(&buffer);
+				return Err(driver(e));
+			}
+			Ok(buffer)
+		}
+	}
+
+	fn destroy_buffer(&self, buffer: &GpuBuffer) {
+		unsafe {
+			self.device.destroy_buffer(buffer.buffer, None);
+			self.device.free_memory(buffer.memory, None);
+		}
+	}
+
+	/// Copy `data` into a host-visible buffer at byte `offset`.
+	fn write<T: Copy>(
+		&self,
+		buffer: &GpuBuffer,
+		offset: u64,
+		data: &[T],
+	) -> Result<(), ProgPowError> {
+		let bytes = std::mem::size_of_val(data) as u64;
+		unsafe {
+			let dst = self
+				.device
+				.map_memory(buffer.memory, offset, bytes, vk::MemoryMapFlags::empty())
+				.map_err(driver)?;
+			ptr::copy_nonoverlapping(data.as_ptr() as *const u8, dst as *mut u8, bytes as usize);
+			self.device.unmap_memory(buffer.memory);
+		}
+		Ok(())
+	}
+
+	/// Record commands with `record`, submit them and wait for them to finish.
+	fn submit<F: FnOnce(vk::CommandBuffer)>(&self, record: F) -> Result<(), ProgPowError> {
+		unsafe {
+			let commands = self
+				.device
+				.allocate_command_buffers(
+					&vk::CommandBufferAllocateInfo::builder()
+						.command_pool(self.command_pool)
+						.level(vk::CommandBufferLevel::PRIMARY)
+						.command_buffer_count(1),
+				)
+				.map_err(driver)?[0];
+			let result = self.run(commands, record);
+			self.device
+				.free_command_buffers(self.command_pool, &[commands]);
+			result
+		}
+	}
+
+	unsafe fn run<F: FnOnce(vk::CommandBuffer)>(
+		&self,
+		commands: vk::CommandBuffer,
+		record: F,
+	) -> Result<(), ProgPowError> {
+		self.device
+			.begin_command_buffer(
+				commands,
+				&vk::CommandBufferBeginInfo::builder()
+					.flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+			)
+			.map_err(driver)?;
+		record(commands);
+		self.device.end_command_buffer(commands).map_err(driver)?;
+		let submit = vk::SubmitInfo::builder()
+			.command_buffers(std::slice::from_ref(&commands))
+			.build();
+		self.device
+			.queue_submit(self.queue, std::slice::from_ref(&submit), self.fence)
+			.map_err(driver)?;
+		self.device
+			.wait_for_fences(&[self.fence], true, u64::MAX)
+			.map_err(driver)?;
+		self.device.reset_fences(&[self.fence]).map_err(driver)
+	}
+
+	/// Keep compiled SPIR-V in `dir` so a restarted miner loads it instead of compiling
+	/// again. SPIR-V does not depend on the device, so every Vulkan miner can share `dir`.
+	pub fn set_kernel_dir(&mut self, dir: &Path) -> Result<(), ProgPowError> {
+		self.kernels = KernelCache::with_dir(dir).map_err(driver)?;
+		Ok(())
+	}
+
+	/// Nonces searched by each call to `search`.
+	pub fn batch_size(&self) -> u64 {
+		nonces_per_launch(self.launch.work_items())
+	}
+
+	pub fn launch_config(&self) -> LaunchConfig {
+		self.launch
+	}
+
+	/// A new thread count builds another pipeline on the next `search`, specializing the
+	/// same SPIR-V module.
+	pub fn set_launch_config(&mut self, launch: LaunchConfig) {
+		self.launch = launch;
+	}
+
+	/// How the next DAG is uploaded. Only `chunk_bytes` applies to this backend.
+	pub fn set_dag_upload(&mut self, options: DagUploadOptions) {
+		self.dag_upload = options;
+	}
+
+	/// Use the launch shape saved for this device model in `dir`, benchmarking
+	/// `LaunchConfig::candidates` at `height` to create it on first use.
+	pub fn autotune<P: ProgPowParams>(
+		&mut self,
+		dir: &Path,
+		height: u64,
+	) -> Result<LaunchConfig, ProgPowError> {
+		self.prepare::<P>(height)?;
+		let path = tune::tuning_path(dir, "vulkan", &self.name);
+		let header = [0u8; 32];
+		let launch = tune::load_or_tune(&path, |config| {
+			tune::measure(config, |config| {
+				self.launch = config;
+				// A zero target never matches, so every launch does the full batch.
+				self.search::<P>(&header, height, 0, 0)?;
+				Ok(self.batch_size())
+			})
+		})?;
+		self.launch = launch;
+		Ok(launch)
+	}
+
+	/// Build the pipeline for the period of `height` and the DAG for its epoch,
+	/// skipping whatever is already current.
+	pub fn prepare<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		self.prepare_kernel::<P>(height)?;
+		self.prepare_dag::<P>(height)
+	}
+
+	/// Build the pipeline for the period of `height` unless it is already current.
+	pub fn prepare_kernel<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		let key = (P::prog_seed(height), self.launch.threads);
+		if self.pipeline.as_ref().map(|(k, _)| *k) != Some(key) {
+			let spirv = self.kernel_binary::<P>(height)?;
+			let pipeline = self.create_pipeline(&spirv, key.1)?;
+			if let Some((_, old)) = self.pipeline.replace((key, pipeline)) {
+				unsafe { self.device.destroy_pipeline(old, None) };
+			}
+			self.kernels.evict_before(key.0);
+		}
+		Ok(())
+	}
+
+	/// Compile the kernel of the period after the one of `height` ahead of time, so the
+	/// switch only has to load it. Returns the first height of that period.
+	pub fn precompile_next_period<P: ProgPowParams>(
+		&self,
+		height: u64,
+	) -> Result<u64, ProgPowError> {
+		let next = next_period_height::<P>(height);
+		self.kernel_binary::<P>(next)?;
+		Ok(next)
+	}
+
+	fn kernel_binary<P: ProgPowParams>(&self, height: u64) -> Result<Arc<Vec<u8>>, ProgPowError> {
+		self.kernels
+			.binary::<P, _, _>(Backend::Vulkan, height, compile_spirv)
+			.map_err(ProgPowError::Driver)
+	}
+
+	fn create_pipeline(&self, spirv: &[u8], threads: u32) -> Result<vk::Pipeline, ProgPowError> {
+		let code = ash::util::read_spv(&mut Cursor::new(spirv)).map_err(driver)?;
+		let entry_point = CString::new(Backend::Vulkan.entry_point()).unwrap();
+		let map_entry = vk::SpecializationMapEntry {
+			constant_id: 0,
+			offset: 0,
+			size: 4,
+		};
+		let threads = threads.to_ne_bytes();
+		unsafe {
+			let module = self
+				.device
+				.create_shader_module(&vk::ShaderModuleCreateInfo::builder().code(&code), None)
+				.map_err(driver)?;
+			let specialization = vk::SpecializationInfo::builder()
+				.map_entries(std::slice::from_ref(&map_entry))
+				.data(&threads);
+			let stage = vk::PipelineShaderStageCreateInfo::builder()
+				.stage(vk::ShaderStageFlags::COMPUTE)
+				.module(module)
+				.name(&entry_point)
+				.specialization_info(&specialization);
+			let info = vk::ComputePipelineCreateInfo::builder()
+				.stage(stage.build())
+				.layout(self.pipeline_layout)
+				.build();
+			let pipeline = self.device.create_compute_pipelines(
+				vk::PipelineCache::null(),
+				std::slice::from_ref(&info),
+				None,
+			);
+			self.device.destroy_shader_module(module, None);
+			pipeline.map(|p| p[0]).map_err(|(_, e)| driver(e))
+		}
+	}
+
+	/// Build the DAG for the epoch of `height` unless it is already current.
+	pub fn prepare_dag<P: ProgPowParams>(&mut self, height: u64) -> Result<(), ProgPowError> {
+		let epoch = epoch_for_height::<P>(height);
+		if self.dag.as_ref().map(|(e, _, _)| *e) != Some(epoch) {
+			// Free the old DAG before allocating the next one.
+			if let Some((_, parts, _)) = self.dag.take() {
+				parts.iter().for_each(|part| self.destroy_buffer(part));
+			}
+			let (parts, split) = self.build_dag::<P>(height)?;
+			self.bind(&parts);
+			self.dag = Some((epoch, parts, split));
+		}
+		Ok(())
+	}
+
+	fn build_dag<P: ProgPowParams>(
+		&self,
+		height: u64,
+	) -> Result<(Vec<GpuBuffer>, u32), ProgPowError> {
+		let needed = (dag_words::<P>(height) * 4) as u64;
+		let elements = needed / DAG_ELEMENT_BYTES;
+		let split = (self.max_storage_range / DAG_ELEMENT_BYTES).min(elements);
+		if elements.div_ceil(split) > DAG_BINDINGS as u64 {
+			return Err(ProgPowError::Driver(format!(
+				"a {} byte DAG needs more than {} storage buffers of {} bytes",
+				needed, DAG_BINDINGS, self.max_storage_range
+			)));
+		}
+
+		let mut parts = Vec::new();
+		let mut start = 0;
+		while start < elements {
+			let len = split.min(elements - start);
+			match self.create_buffer(
+				len * DAG_ELEMENT_BYTES,
+				vk::MemoryPropertyFlags::DEVICE_LOCAL,
+			) {
+				Ok(part) => parts.push(part),
+				Err(e) => {
+					parts.iter().for_each(|part| self.destroy_buffer(part));
+					return Err(e);
+				}
+			}
+			start += len;
+		}
+
+		let part_bytes = split * DAG_ELEMENT_BYTES;
+		let mut staging: Option<GpuBuffer> = None;
+		let chunk_nodes = self.dag_upload.chunk_nodes();
+		let result = host_dag_chunked::<P, _>(height, chunk_nodes, |offset, chunk| {
+			let bytes = std::mem::size_of_val(chunk) as u64;
+			if staging.as_ref().filter(|s| s.size >= bytes).is_none() {
+				if let Some(old) = staging.take() {
+					self.destroy_buffer(&old);
+				}
+				staging = Some(self.create_buffer(
+					bytes,
+					vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+				)?);
+			}
+			let staging = staging.as_ref().unwrap();
+			self.write(staging, 0, chunk)?;
+
+			// A chunk may straddle two DAG buffers.
+			let mut dst = offset as u64 * 4;
+			let mut src = 0;
+			let mut copies = Vec::new();
+			while src < bytes {
+				let part = (dst / part_bytes) as usize;
+				let part_offset = dst % part_bytes;
+				let size = (part_bytes - part_offset).min(bytes - src);
+				copies.push((part, src, part_offset, size));
+				src += size;
+				dst += size;
+			}
+			self.submit(|commands| {
+				for &(part, src_offset, dst_offset, size) in &copies {
+					unsafe {
+						self.device.cmd_copy_buffer(
+							commands,
+							staging.buffer,
+							parts[part].buffer,
+							&[vk::BufferCopy {
+								src_offset,
+								dst_offset,
+								size,
+							}],
+						);
+					}
+				}
+			})
+			.map_err(|_| ProgPowError::DAG)
+		});
+		if let Some(staging) = staging {
+			self.destroy_buffer(&staging);
+		}
+		let cdag = match result {
+			Ok(cdag) => cdag,
+			Err(e) => {
+				parts.iter().for_each(|part| self.destroy_buffer(part));
+				return Err(e);
+			}
+		};
+		self.write(&self.cdag, 0, &cdag[..])?;
+		Ok((parts, split as u32))
+	}
+
+	/// Point the descriptor set at `dag`, repeating its first buffer in unused bindings.
+	fn bind(&self, dag: &[GpuBuffer]) {
+		let buffers = (0..DAG_BINDINGS)
+			.map(|i| dag.get(i).unwrap_or(&dag[0]))
+			.chain([&self.cdag, &self.results])
+			.map(|buffer| vk::DescriptorBufferInfo {
+				buffer: buffer.buffer,
+				offset: 0,
+				range: vk::WHOLE_SIZE,
+			})
+			.collect::<Vec<_>>();
+		let writes = buffers
+			.iter()
+			.enumerate()
+			.map(|(binding, info)| {
+				vk::WriteDescriptorSet::builder()
+					.dst_set(self.descriptor_set)
+					.dst_binding(binding as u32)
+					.descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+					.buffer_info(std::slice::from_ref(info))
+					.build()
+			})
+			.collect::<Vec<_>>();
+		unsafe { self.device.update_descriptor_sets(&writes, &[]) };
+	}
+
+	/// Search `batch_size()` nonces from `start_nonce`, returning each nonce whose
+	/// result is at or below `target` together with its mix, see `Found`.
+	pub fn search<P: ProgPowParams>(
+		&mut self,
+		header: &H256,
+		height: u64,
+		target: u64,
+		start_nonce: u64,
+	) -> Result<Found, ProgPowError> {
+		self.prepare::<P>(height)?;
+		let pipeline = self.pipeline.as_ref().ok_or(ProgPowError::NoInitialized)?.1;
+		let split = self.dag.as_ref().ok_or(ProgPowError::NoInitialized)?.2;
+
+		let mut params = Vec::with_capacity(PUSH_CONSTANT_WORDS);
+		for word in [start_nonce, target]
+			.into_iter()
+			.chain(header_words(header))
+		{
+			params.extend([word as u32, (word >> 32) as u32]);
+		}
+		params.push(split);
+		let params = params
+			.iter()
+			.flat_map(|w| w.to_ne_bytes())
+			.collect::<Vec<u8>>();
+
+		let mut bytes = vec![0u8; self.results.size as usize];
+		self.write(&self.results, 0, &bytes[..])?;
+		self.submit(|commands| unsafe {
+			self.device
+				.cmd_bind_pipeline(commands, vk::PipelineBindPoint::COMPUTE, pipeline);
+			self.device.cmd_bind_descriptor_sets(
+				commands,
+				vk::PipelineBindPoint::COMPUTE,
+				self.pipeline_layout,
+				0,
+				&[self.descriptor_set],
+				&[],
+			);
+			self.device.cmd_push_constants(
+				commands,
+				self.pipeline_layout,
+				vk::ShaderStageFlags::COMPUTE,
+				0,
+				&params,
+			);
+			self.device.cmd_dispatch(commands, self.launch.blocks, 1, 1);
+			// Make the shader's writes to the results visible to the host.
+			let barrier = vk::MemoryBarrier::builder()
+				.src_access_mask(vk::AccessFlags::SHADER_WRITE)
+				.dst_access_mask(vk::AccessFlags::HOST_READ)
+				.build();
+			self.device.cmd_pipeline_barrier(
+				commands,
+				vk::PipelineStageFlags::COMPUTE_SHADER,
+				vk::PipelineStageFlags::HOST,
+				vk::DependencyFlags::empty(),
+				std::slice::from_ref(&barrier),
+				&[],
+				&[],
+			);
+		})?;
+
+		unsafe {
+			let src = self
+				.device
+				.map_memory(
+					self.results.memory,
+					0,
+					self.results.size,
+					vk::MemoryMapFlags::empty(),
+				)
+				.map_err(driver)?;
+			ptr::copy_nonoverlapping(src as *const u8, bytes.as_mut_ptr(), bytes.len());
+			self.device.unmap_memory(self.results.memory);
+		}
+		Ok(SearchResults::from_bytes(&bytes).to_found())
+	}
+}
+
+impl Drop for VulkanMiner {
+	fn drop(&mut self) {
+		unsafe {
+			let _ = self.device.device_wait_idle();
+			if let Some((_, pipeline)) = self.pipeline.take() {
+				self.device.destroy_pipeline(pipeline, None);
+			}
+			if let Some((_, parts, _)) = self.dag.take() {
+				parts.iter().for_each(|part| self.destroy_buffer(part));
+			}
+			self.destroy_buffer(&self.cdag);
+			self.destroy_buffer(&self.results);
+			self.device
+				.destroy_descriptor_pool(self.descriptor_pool, None);
+			self.device
+				.destroy_pipeline_layout(self.pipeline_layout, None);
+			self.device
+				.destroy_descriptor_set_layout(self.set_layout, None);
+			self.device.destroy_fence(self.fence, None);
+			self.device.destroy_command_pool(self.command_pool, None);
+			self.device.destroy_device(None);
+			self.instance.destroy_instance(None);
+		}
+	}
+}
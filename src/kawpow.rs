@@ -0,0 +1,85 @@
+//! Helpers for KawPow chains, from a raw block header to a checked proof of work.
+//!
+//! The hashed header is 80 bytes: version, previous block, merkle root, time, bits and
+//! height, without the nonce and mix hash that are checked against it.
+
+use progpow_base::params::KawPowParams;
+
+use crate::hardware::GlobalVerifier;
+use crate::types::difficulty::{meets_target, target_from_compact};
+use crate::types::{ProgPowError, ShareStatus, Verifier, H256};
+
+/// Length of the serialized header `header_hash` takes.
+pub const HEADER_BYTES: usize = 80;
+
+const BITS_OFFSET: usize = 72;
+const HEIGHT_OFFSET: usize = 76;
+
+/// The `header_hash` ProgPoW takes: keccak-256 of the serialized header.
+pub fn header_hash(raw_header: &[u8; HEADER_BYTES]) -> H256 {
+	let mut hash = [0u8; 32];
+	keccak_hash::keccak_256(raw_header, &mut hash);
+	hash
+}
+
+/// Block height, stored little-endian in the last 4 bytes of the header.
+pub fn header_height(raw_header: &[u8; HEADER_BYTES]) -> u64 {
+	let mut height = [0u8; 4];
+	height.copy_from_slice(&raw_header[HEIGHT_OFFSET..]);
+	u32::from_le_bytes(height) as u64
+}
+
+/// Block target, from the compact `nBits` field of the header.
+pub fn header_target(raw_header: &[u8; HEADER_BYTES]) -> H256 {
+	let mut bits = [0u8; 4];
+	bits.copy_from_slice(&raw_header[BITS_OFFSET..HEIGHT_OFFSET]);
+	target_from_compact(u32::from_le_bytes(bits))
+}
+
+/// Check the proof of work of a block: `mix` against the one computed for `nonce`, and
+/// the final hash against the target of the header's `nBits`. Uses the KawPow
+/// `GlobalVerifier`; call `PpCPU::validate_share` with `header_hash`, `header_height` and
+/// `header_target` to pick another verifier.
+pub fn verify_block(
+	raw_header: &[u8; HEADER_BYTES],
+	nonce: u64,
+	mix: &H256,
+) -> Result<ShareStatus, ProgPowError> {
+	let cpu = GlobalVerifier::of::<KawPowParams>();
+	let (final_hash, mix_hash) =
+		cpu.verify_hash(&header_hash(raw_header), header_height(raw_header), nonce)?;
+	if &mix_hash != mix {
+		Ok(ShareStatus::BadMix)
+	} else if !meets_target(&final_hash, &header_target(raw_header)) {
+		Ok(ShareStatus::LowDifficulty)
+	} else {
+		Ok(ShareStatus::Ok)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::hardware::PpCPU;
+
+	#[test]
+	fn test_header_fields() {
+		let mut header = [0u8; HEADER_BYTES];
+		header[BITS_OFFSET..HEIGHT_OFFSET].copy_from_slice(&0x1d00ffffu32.to_le_bytes());
+		header[HEIGHT_OFFSET..].copy_from_slice(&20u32.to_le_bytes());
+		assert_eq!(header_height(&header), 20);
+		assert_eq!(header_target(&header), target_from_compact(0x1d00ffff));
+
+		let hash = header_hash(&header);
+		header[0] = 1;
+		assert_ne!(header_hash(&header), hash);
+
+		let cpu = PpCPU::<KawPowParams>::new();
+		let (_, mix) = cpu.verify_hash(&header_hash(&header), 20, 1).unwrap();
+		assert_eq!(verify_block(&header, 2, &mix).unwrap(), ShareStatus::BadMix);
+		assert_eq!(
+			verify_block(&header, 1, &mix).unwrap(),
+			ShareStatus::LowDifficulty
+		);
+	}
+}
@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::generator::{dag_elements, set_search_results, Backend, SEARCH_RESULTS};
+use crate::types::ProgPowError;
+use progpow_base::params::ProgPowParams;
+
+/// Identifies one generated kernel. Two heights with the same key share the same source.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KernelKey {
+	pub params: &'static str,
+	pub backend: Backend,
+	/// GPU architecture the binary targets, e.g. `sm_86`. Empty when the cache only ever
+	/// holds binaries for one architecture.
+	pub arch: String,
+	pub period: u64,
+	pub dag_elements: u64,
+}
+
+impl KernelKey {
+	pub fn new<P: ProgPowParams>(backend: Backend, height: u64) -> Self {
+		KernelKey::for_arch::<P>(backend, "", height)
+	}
+
+	pub fn for_arch<P: ProgPowParams>(backend: Backend, arch: &str, height: u64) -> Self {
+		KernelKey {
+			params: P::NAME,
+			backend,
+			arch: arch.to_string(),
+			period: P::prog_seed(height),
+			dag_elements: dag_elements::<P>(height),
+		}
+	}
+
+	fn file_name(&self, driver: &str) -> String {
+		let ext = match self.backend {
+			Backend::Cuda => "ptx",
+			Backend::OpenCl => "clbin",
+			Backend::Hip => "hsaco",
+			Backend::Metal => "metallib",
+			Backend::Vulkan | Backend::LevelZero => "spv",
+		};
+		let mut name = format!("{}-{}", self.params, self.backend.name());
+		for tag in [self.arch.as_str(), driver] {
+			if !tag.is_empty() {
+				name.push('-');
+				name.push_str(&sanitize(tag));
+			}
+		}
+		format!("{}-{}-{}.{}", name, self.period, self.dag_elements, ext)
+	}
+}
+
+/// Keep `tag` usable in a file name.
+fn sanitize(tag: &str) -> String {
+	tag.chars()
+		.map(|c| match c {
+			'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '_' => c,
+			_ => '_',
+		})
+		.collect()
+}
+
+/// Binaries on disk start with this, then the keccak-256 of the source they were compiled
+/// from and the keccak-256 of the binary itself.
+const MAGIC: &[u8; 8] = b"PPKERN01";
+const HEADER_LEN: usize = MAGIC.len() + 64;
+
+fn keccak(data: &[u8]) -> [u8; 32] {
+	let mut hash = [0u8; 32];
+	keccak_hash::keccak_256(data, &mut hash);
+	hash
+}
+
+fn encode(source: &str, binary: &[u8]) -> Vec<u8> {
+	let mut file = Vec::with_capacity(HEADER_LEN + binary.len());
+	file.extend_from_slice(MAGIC);
+	file.extend_from_slice(&keccak(source.as_bytes()));
+	file.extend_from_slice(&keccak(binary));
+	file.extend_from_slice(binary);
+	file
+}
+
+/// The binary stored in `file`, or `None` if it was truncated, corrupted or compiled from
+/// a different source.
+fn decode(mut file: Vec<u8>, source: &str) -> Option<Vec<u8>> {
+	if file.len() < HEADER_LEN || &file[..MAGIC.len()] != MAGIC {
+		return None;
+	}
+	let (source_hash, binary_hash) = file[MAGIC.len()..HEADER_LEN].split_at(32);
+	if source_hash != keccak(source.as_bytes()) || binary_hash != keccak(&file[HEADER_LEN..]) {
+		return None;
+	}
+	Some(file.split_off(HEADER_LEN))
+}
+
+/// Memoizes generated kernel sources and compiled kernel binaries.
+///
+/// Binaries are also persisted to `dir` when one is given, so a restarted miner does not
+/// pay the compile again for the current period. Files are named after the params, backend,
+/// architecture, driver version and period, and carry hashes of their source and contents
+/// so a file left half-written by a crash is compiled again rather than loaded.
+pub struct KernelCache {
+	dir: Option<PathBuf>,
+	driver: String,
+	search_results: usize,
+	sources: Mutex<HashMap<KernelKey, Arc<String>>>,
+	binaries: Mutex<HashMap<KernelKey, Arc<Vec<u8>>>>,
+}
+
+impl KernelCache {
+	/// In-memory only cache.
+	pub fn new() -> Self {
+		KernelCache {
+			dir: None,
+			driver: String::new(),
+			search_results: SEARCH_RESULTS,
+			sources: Mutex::new(HashMap::new()),
+			binaries: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Cache that also stores compiled binaries under `dir`.
+	pub fn with_dir<D: Into<PathBuf>>(dir: D) -> io::Result<Self> {
+		let dir = dir.into();
+		fs::create_dir_all(&dir)?;
+		Ok(KernelCache {
+			dir: Some(dir),
+			..KernelCache::new()
+		})
+	}
+
+	/// Tag binaries stored on disk with the version of the driver or compiler that built
+	/// them, so an upgrade does not load stale ones.
+	pub fn with_driver(mut self, driver: &str) -> Self {
+		self.driver = driver.to_string();
+		self
+	}
+
+	/// Generate kernels with room for `search_results` solutions per launch instead of
+	/// `SEARCH_RESULTS`. Miners size their result buffers from `search_results()`.
+	pub fn with_search_results(mut self, search_results: usize) -> Result<Self, ProgPowError> {
+		if search_results == 0 {
+			return Err(ProgPowError::Config(
+				"search_results must be at least 1".to_string(),
+			));
+		}
+		self.search_results = search_results;
+		Ok(self)
+	}
+
+	/// Capacity of the `search_results` buffer of the kernels this cache generates.
+	pub fn search_results(&self) -> usize {
+		self.search_results
+	}
+
+	pub fn source<P: ProgPowParams>(&self, backend: Backend, height: u64) -> Arc<String> {
+		let key = KernelKey::new::<P>(backend, height);
+		let mut sources = self.sources.lock().unwrap();
+		sources
+			.entry(key)
+			.or_insert_with(|| {
+				let source = set_search_results(backend.generate::<P>(height), self.search_results)
+					.expect("kernel templates define SEARCH_RESULTS");
+				Arc::new(source)
+			})
+			.clone()
+	}
+
+	/// Returns the compiled kernel for `height`, calling `compile` with the generated source
+	/// only if neither memory nor the cache directory already holds it.
+	pub fn binary<P, F, E>(
+		&self,
+		backend: Backend,
+		height: u64,
+		compile: F,
+	) -> Result<Arc<Vec<u8>>, E>
+	where
+		P: ProgPowParams,
+		F: FnOnce(&str) -> Result<Vec<u8>, E>,
+	{
+		self.binary_for_arch::<P, F, E>(backend, "", height, compile)
+	}
+
+	/// Like `binary`, keeping binaries for different `arch`s apart.
+	pub fn binary_for_arch<P, F, E>(
+		&self,
+		backend: Backend,
+		arch: &str,
+		height: u64,
+		compile: F,
+	) -> Result<Arc<Vec<u8>>, E>
+	where
+		P: ProgPowParams,
+		F: FnOnce(&str) -> Result<Vec<u8>, E>,
+	{
+		let key = KernelKey::for_arch::<P>(backend, arch, height);
+		if let Some(binary) = self.binaries.lock().unwrap().get(&key) {
+			return Ok(binary.clone());
+		}
+
+		let source = self.source::<P>(backend, height);
+		let path = self
+			.dir
+			.as_ref()
+			.map(|dir| dir.join(key.file_name(&self.driver)));
+		let cached = path
+			.as_ref()
+			.and_then(|p| fs::read(p).ok())
+			.and_then(|file| decode(file, &source));
+		let binary = match cached {
+			Some(binary) => binary,
+			None => {
+				let binary = compile(&source)?;
+				if let Some(path) = path {
+					// Write then rename so a concurrent reader never sees a partial file.
+					let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+					if fs::write(&tmp, encode(&source, &binary)).is_ok() {
+						let _ = fs::rename(&tmp, &path);
+					}
+				}
+				binary
+			}
+		};
+
+		let binary = Arc::new(binary);
+		self.binaries.lock().unwrap().insert(key, binary.clone());
+		Ok(binary)
+	}
+
+	/// Drop memoized entries for periods before `period`. Files on disk are kept.
+	pub fn evict_before(&self, period: u64) {
+		self.sources
+			.lock()
+			.unwrap()
+			.retain(|k, _| k.period >= period);
+		self.binaries
+			.lock()
+			.unwrap()
+			.retain(|k, _| k.period >= period);
+	}
+}
+
+impl Default for KernelCache {
+	fn default() -> Self {
+		KernelCache::new()
+	}
+}
+
+type CompileResult = Result<Arc<Vec<u8>>, String>;
+
+/// A compile in progress, which other devices of the same architecture wait on.
+#[derive(Default)]
+struct InFlight {
+	result: Mutex<Option<CompileResult>>,
+	done: Condvar,
+}
+
+/// Compiles each period's kernel once per GPU architecture and shares the binary between
+/// every device of that architecture.
+///
+/// Devices call `compile` from their own threads: the first one to ask for a kernel
+/// compiles it and the others block until it is ready. `spawn` starts a compile on a
+/// worker thread, so all architectures of a rig can be built in parallel ahead of the
+/// period change.
+pub struct CompileService {
+	cache: KernelCache,
+	in_flight: Mutex<HashMap<KernelKey, Arc<InFlight>>>,
+}
+
+impl CompileService {
+	pub fn new(cache: KernelCache) -> Arc<Self> {
+		Arc::new(CompileService {
+			cache,
+			in_flight: Mutex::new(HashMap::new()),
+		})
+	}
+
+	pub fn cache(&self) -> &KernelCache {
+		&self.cache
+	}
+
+	/// Returns the kernel for `height` compiled for `arch`, calling `compile` only if the
+	/// cache does not hold it and no other thread is already compiling it.
+	pub fn compile<P, F>(
+		&self,
+		backend: Backend,
+		arch: &str,
+		height: u64,
+		compile: F,
+	) -> CompileResult
+	where
+		P: ProgPowParams,
+		F: FnOnce(&str) -> Result<Vec<u8>, String>,
+	{
+		let key = KernelKey::for_arch::<P>(backend, arch, height);
+		let (flight, leader) = {
+			let mut in_flight = self.in_flight.lock().unwrap();
+			match in_flight.get(&key) {
+				Some(flight) => (flight.clone(), false),
+				None => {
+					let flight = Arc::new(InFlight::default());
+					in_flight.insert(key.clone(), flight.clone());
+					(flight, true)
+				}
+			}
+		};
+
+		if leader {
+			let result = self
+				.cache
+				.binary_for_arch::<P, F, String>(backend, arch, height, compile);
+			*flight.result.lock().unwrap() = Some(result.clone());
+			flight.done.notify_all();
+			// Later calls hit the cache, or retry if this compile failed.
+			self.in_flight.lock().unwrap().remove(&key);
+			return result;
+		}
+
+		let mut result = flight.result.lock().unwrap();
+		while result.is_none() {
+			result = flight.done.wait(result).unwrap();
+		}
+		result.clone().unwrap()
+	}
+
+	/// Run `compile` for `arch` on a worker thread.
+	pub fn spawn<P, F>(
+		self: &Arc<Self>,
+		backend: Backend,
+		arch: &str,
+		height: u64,
+		compile: F,
+	) -> JoinHandle<CompileResult>
+	where
+		P: ProgPowParams,
+		F: FnOnce(&str) -> Result<Vec<u8>, String> + Send + 'static,
+	{
+		let service = self.clone();
+		let arch = arch.to_string();
+		thread::spawn(move || service.compile::<P, F>(backend, &arch, height, compile))
+	}
+
+	pub fn evict_before(&self, period: u64) {
+		self.cache.evict_before(period);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use progpow_base::params::KawPowParams;
+	use std::cell::Cell;
+
+	#[test]
+	fn test_kernel_cache_compiles_once() {
+		let dir = std::env::temp_dir().join(format!("progpow-kernels-{}", std::process::id()));
+		let compiles = Cell::new(0);
+		let compile = |src: &str| -> Result<Vec<u8>, ()> {
+			compiles.set(compiles.get() + 1);
+			Ok(src.as_bytes()[..16].to_vec())
+		};
+
+		let cache = KernelCache::with_dir(&dir).unwrap();
+		let first = cache
+			.binary::<KawPowParams, _, _>(Backend::Cuda, 10, compile)
+			.unwrap();
+		let again = cache
+			.binary::<KawPowParams, _, _>(Backend::Cuda, 10, compile)
+			.unwrap();
+		assert_eq!(first, again);
+		assert_eq!(compiles.get(), 1);
+
+		// A fresh cache over the same directory loads the binary from disk.
+		let reloaded = KernelCache::with_dir(&dir).unwrap();
+		let from_disk = reloaded
+			.binary::<KawPowParams, _, _>(Backend::Cuda, 10, compile)
+			.unwrap();
+		assert_eq!(first, from_disk);
+		assert_eq!(compiles.get(), 1);
+
+		let _ = fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn test_kernel_cache_recompiles_corrupt_files() {
+		let dir = std::env::temp_dir().join(format!("progpow-corrupt-{}", std::process::id()));
+		let compiles = Cell::new(0);
+		let compile = |src: &str| -> Result<Vec<u8>, ()> {
+			compiles.set(compiles.get() + 1);
+			Ok(src.as_bytes()[..16].to_vec())
+		};
+
+		let cache = KernelCache::with_dir(&dir).unwrap().with_driver("12.4");
+		let first = cache
+			.binary_for_arch::<KawPowParams, _, _>(Backend::Cuda, "sm_86", 10, compile)
+			.unwrap();
+		let path = dir.join(
+			KernelKey::for_arch::<KawPowParams>(Backend::Cuda, "sm_86", 10).file_name("12.4"),
+		);
+		let mut file = fs::read(&path).unwrap();
+		file.truncate(file.len() - 1);
+		fs::write(&path, file).unwrap();
+
+		let reloaded = KernelCache::with_dir(&dir).unwrap().with_driver("12.4");
+		let again = reloaded
+			.binary_for_arch::<KawPowParams, _, _>(Backend::Cuda, "sm_86", 10, compile)
+			.unwrap();
+		assert_eq!(first, again);
+		assert_eq!(compiles.get(), 2);
+
+		// Another driver version does not reuse the file either.
+		let upgraded = KernelCache::with_dir(&dir).unwrap().with_driver("12.5");
+		upgraded
+			.binary_for_arch::<KawPowParams, _, _>(Backend::Cuda, "sm_86", 10, compile)
+			.unwrap();
+		assert_eq!(compiles.get(), 3);
+
+		let _ = fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn test_compile_service_once_per_arch() {
+		use std::sync::atomic::{AtomicUsize, Ordering};
+
+		let service = CompileService::new(KernelCache::new());
+		let compiles = Arc::new(AtomicUsize::new(0));
+		let handles: Vec<_> = ["sm_86", "sm_86", "sm_86", "sm_75", "sm_75"]
+			.iter()
+			.map(|arch| {
+				let compiles = compiles.clone();
+				let tag = arch.as_bytes().to_vec();
+				service.spawn::<KawPowParams, _>(Backend::Cuda, arch, 10, move |_| {
+					compiles.fetch_add(1, Ordering::SeqCst);
+					thread::sleep(std::time::Duration::from_millis(50));
+					Ok(tag)
+				})
+			})
+			.collect();
+		let binaries: Vec<_> = handles
+			.into_iter()
+			.map(|h| h.join().unwrap().unwrap())
+			.collect();
+
+		assert_eq!(compiles.load(Ordering::SeqCst), 2);
+		assert_eq!(&binaries[0][..], b"sm_86");
+		assert!(Arc::ptr_eq(&binaries[0], &binaries[2]));
+		assert_eq!(&binaries[3][..], b"sm_75");
+		assert!(Arc::ptr_eq(&binaries[3], &binaries[4]));
+	}
+
+	#[test]
+	fn test_kernel_cache_search_results() {
+		let cache = KernelCache::new();
+		assert_eq!(cache.search_results(), SEARCH_RESULTS);
+		let cache = cache.with_search_results(256).unwrap();
+		assert_eq!(cache.search_results(), 256);
+		assert!(cache
+			.source::<KawPowParams>(Backend::Cuda, 10)
+			.contains("#define SEARCH_RESULTS 256\n"));
+		assert!(KernelCache::new().with_search_results(0).is_err());
+	}
+}
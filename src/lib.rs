@@ -1,11 +1,40 @@
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod bench;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod debug;
+pub mod epic;
+pub mod epoch;
+pub mod equivalence;
+pub mod ethash;
 pub mod generator;
+#[cfg(feature = "getwork")]
+pub mod getwork;
 pub mod hardware;
+pub mod kawpow;
+pub mod kernel_cache;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod params;
+pub mod presets;
+pub mod solo;
+#[cfg(feature = "stratum")]
+pub mod stratum;
+pub mod supervisor;
+#[cfg(any(test, feature = "testvectors"))]
+pub mod testvectors;
 pub mod types;
 
+pub use epoch::{cache_size, dag_size, epoch_for_height, required_vram, seed_hash};
+
 use progpow_base::compute::calculate_dag_item;
-use progpow_base::params::ProgPowParams;
+use progpow_base::params::{KawPowParams, ProgPowParams};
 use progpow_base::shared::Node;
 
+use hardware::PpCPU;
+use types::{Mix, ProgPowError, Verifier};
+
 pub const PROGPOW_CACHE_WORDS: usize = 4096;
 pub type CDag = [u32; PROGPOW_CACHE_WORDS];
 
@@ -21,14 +50,38 @@ pub fn generate_cdag<P: ProgPowParams>(cache: &[Node]) -> CDag {
 	c_dag
 }
 
+/// Hash a known KawPow vector on the CPU, so a platform this build computes wrong hashes
+/// on, e.g. a big-endian one, fails at startup instead of submitting bad shares. Builds
+/// the epoch 0 light cache in the default cache directory the first time.
+pub fn self_check() -> Result<(), ProgPowError> {
+	const HEIGHT: u64 = 20;
+	const NONCE: u64 = 10123012301;
+	const MIX: [u32; 8] = [
+		2257276933, 1807452103, 2437354717, 3964690328, 2418543553, 1799256823, 2347030976,
+		2107140455,
+	];
+
+	let cpu = PpCPU::<KawPowParams>::new();
+	let (value, mix) = cpu.verify(&[0; 32], HEIGHT, NONCE)?;
+	if mix != MIX {
+		return Err(ProgPowError::SelfCheck(format!(
+			"mix {:08x?}, expected {:08x?}",
+			mix, MIX
+		)));
+	}
+	let (final_hash, mix_hash) = cpu.verify_hash(&[0; 32], HEIGHT, NONCE)?;
+	if mix_hash != Mix(MIX).to_le_bytes() || final_hash[..4] != value[0].to_be_bytes() {
+		return Err(ProgPowError::SelfCheck("hash byte order".to_string()));
+	}
+	Ok(())
+}
+
 #[cfg(test)]
 mod test {
 	use super::*;
 
-	use hardware::PpCPU;
-	use num_bigint::BigUint;
 	// use num_traits::One;
-	use types::PpCompute;
+	use types::Miner;
 
 	#[test]
 	fn test_compute_cpu() {
@@ -46,6 +99,153 @@ mod test {
 		);
 	}
 
+	#[test]
+	fn test_verify_hash_byte_order() {
+		let height: u64 = 20;
+		let nonce: u64 = 10123012301;
+		let header_hash: [u8; 32] = [0; 32];
+		let pp_cpu = PpCPU::<progpow_base::params::KawPowParams>::new();
+		let (value, mix) = pp_cpu.verify(&header_hash, height, nonce).unwrap();
+		let (final_hash, mix_hash) = pp_cpu.verify_hash(&header_hash, height, nonce).unwrap();
+
+		assert_eq!(final_hash[..4], value[0].to_be_bytes());
+		assert_eq!(mix_hash[..4], mix[0].to_le_bytes());
+		assert_eq!(types::Mix::from_le_bytes(&mix_hash), types::Mix(mix));
+		assert_eq!(types::Mix(mix).to_le_bytes(), mix_hash);
+	}
+
+	#[test]
+	fn test_verify_with_scratch() {
+		let header_hash: [u8; 32] = [0; 32];
+		let pp_cpu = PpCPU::<progpow_base::params::KawPowParams>::new();
+		let mut scratch = hardware::VerifyScratch::new();
+		for nonce in [10123012301, 1, 2] {
+			assert_eq!(
+				pp_cpu
+					.verify_with_scratch(&mut scratch, &header_hash, 20, nonce)
+					.unwrap(),
+				pp_cpu.verify(&header_hash, 20, nonce).unwrap()
+			);
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "parallel")]
+	fn test_verify_headers_parallel() {
+		let pp_cpu = PpCPU::<progpow_base::params::KawPowParams>::new();
+		let headers: Vec<_> = (0..16u8)
+			.map(|i| ([i; 32], 20 + i as u64, 10123012301 + i as u64))
+			.collect();
+		let results = pp_cpu
+			.verify_headers_parallel(&headers, 4, &hardware::VerifyAll)
+			.unwrap();
+		assert_eq!(results.len(), headers.len());
+		for (&(header_hash, height, nonce), result) in headers.iter().zip(results) {
+			assert_eq!(
+				result,
+				Some(pp_cpu.verify(&header_hash, height, nonce).unwrap())
+			);
+		}
+
+		let results = pp_cpu
+			.verify_headers_parallel(&headers, 4, &hardware::Checkpoint(30))
+			.unwrap();
+		assert!(results[..11].iter().all(Option::is_none));
+		assert!(results[11..].iter().all(Option::is_some));
+	}
+
+	#[test]
+	fn test_self_check() {
+		self_check().unwrap();
+	}
+
+	#[test]
+	fn test_validate_share() {
+		use types::ShareStatus;
+
+		let height: u64 = 20;
+		let nonce: u64 = 10123012301;
+		let header_hash: [u8; 32] = [0; 32];
+		let pp_cpu = PpCPU::<progpow_base::params::KawPowParams>::new();
+		let (final_hash, mix_hash) = pp_cpu.verify_hash(&header_hash, height, nonce).unwrap();
+
+		let validate = |mix: &[u8; 32], target: &[u8; 32]| {
+			pp_cpu
+				.validate_share(&header_hash, height, nonce, mix, target)
+				.unwrap()
+		};
+		assert_eq!(validate(&mix_hash, &final_hash), ShareStatus::Ok);
+		assert_eq!(validate(&mix_hash, &[0; 32]), ShareStatus::LowDifficulty);
+		assert_eq!(validate(&[0; 32], &[0xff; 32]), ShareStatus::BadMix);
+	}
+
+	#[test]
+	fn test_cpu_search() {
+		let height: u64 = 20;
+		let header_hash: [u8; 32] = [0; 32];
+		let mut pp_cpu = PpCPU::<progpow_base::params::KawPowParams>::new();
+		pp_cpu.set_threads(3);
+
+		// Easy enough that a few hundred nonces contain solutions.
+		let target = types::difficulty::target64_from_difficulty(16);
+		let found = pp_cpu.search(&header_hash, height, target, 0, 256).unwrap();
+		assert!(!found.is_empty());
+		let mut scratch = hardware::VerifyScratch::new();
+		assert_eq!(
+			pp_cpu
+				.search_with_scratch(&mut scratch, &header_hash, height, target, 0, 256)
+				.unwrap(),
+			found
+		);
+		for (nonce, mix) in found {
+			let (value, expected) = pp_cpu.verify(&header_hash, height, nonce).unwrap();
+			assert_eq!(mix, expected);
+			assert!(types::difficulty::hash_meets_boundary(
+				&types::final_hash_bytes(&value),
+				16
+			));
+		}
+
+		let job = types::MiningJob::new(
+			0,
+			header_hash,
+			height,
+			0,
+			types::difficulty::target_from_difficulty(16),
+		);
+		pp_cpu.compute(&job, 0..u64::MAX).unwrap();
+		assert!(!pp_cpu.solutions().is_empty());
+
+		// A stop issued before `compute` starts is not lost.
+		pp_cpu.stop();
+		pp_cpu.compute(&job, 0..u64::MAX).unwrap();
+		assert!(pp_cpu.solutions().is_empty());
+		pp_cpu.resume();
+		pp_cpu.compute(&job, 0..u64::MAX).unwrap();
+		assert!(!pp_cpu.solutions().is_empty());
+	}
+
+	#[test]
+	fn test_compute_cpu_dyn() {
+		use params::DynParams;
+
+		let height: u64 = 20;
+		let nonce: u64 = 10123012301;
+		let header_hash: [u8; 32] = [0; 32];
+		let static_cpu = PpCPU::<progpow_base::params::KawPowParams>::new();
+		let dyn_cpu = PpCPU::new_dyn(DynParams::of::<progpow_base::params::KawPowParams>());
+		assert_eq!(
+			static_cpu.verify(&header_hash, height, nonce).unwrap(),
+			dyn_cpu.verify(&header_hash, height, nonce).unwrap()
+		);
+
+		let mut custom = DynParams::of::<progpow_base::params::KawPowParams>();
+		custom.cnt_math = 1;
+		assert!(PpCPU::new_dyn(custom)
+			.verify(&header_hash, height, nonce)
+			.is_err());
+	}
+
 	#[test]
 	#[cfg(any(feature = "cuda", feature = "opencl"))]
 	fn test_compute_gpu() {
@@ -56,20 +256,18 @@ mod test {
 		let height: u64 = 1;
 		let boundary: u64 = 100000000;
 
-		let mut difficulty: BigUint = BigUint::from(1u64) << 256; // Equivalent to U256::max_value()
-		difficulty = difficulty / BigUint::from(boundary);
-		let target: BigUint = difficulty >> 192;
-
-		let (nonce, mix) = get_gpu_solution(header.clone(), height, epoch, boundary);
+		let target = types::difficulty::target_from_difficulty(boundary);
+		let solution = get_gpu_solution(header.clone(), height, epoch, target);
 		let cpu = PpCPU::<progpow_base::params::KawPowParams>::new();
-		let (value, mix_hash) = cpu.verify(&header, height, nonce).unwrap();
+		let (value, mix_hash) = cpu.verify(&header, height, solution.nonce).unwrap();
 
-		let mix32: [u32; 8] = unsafe { ::std::mem::transmute(mix) };
-		let target_val: u64 = target.to_u64_digits().first().copied().unwrap_or(0);
-		let value_val: u64 = ((value[0] as u64) << 32) | (value[1] as u64);
+		let mix32 = types::Mix::from_le_bytes(&solution.mix_hash);
 
-		assert_eq!(mix32, mix_hash);
-		assert!(value_val < target_val);
+		assert_eq!(mix32, types::Mix(mix_hash));
+		assert!(types::difficulty::hash_meets_boundary(
+			&types::final_hash_bytes(&value),
+			boundary
+		));
 	}
 
 	#[test]
@@ -96,15 +294,7 @@ mod test {
 		}
 
 		let pp_cpu = PpCPU::<progpow_base::params::ZanoParams>::new();
-		let (_, mix) = pp_cpu.verify(&header, height, nonce).unwrap();
-
-		let mut actual_mix_bytes = [0u8; 32];
-		for (i, &word) in mix.iter().enumerate() {
-			actual_mix_bytes[i * 4..(i + 1) * 4].copy_from_slice(&word.to_le_bytes());
-		}
-
-		println!("Actual Mix Bytes:   {:02x?}", actual_mix_bytes);
-		println!("Expected Mix Bytes: {:02x?}", expected_mix_bytes);
+		let (_, actual_mix_bytes) = pp_cpu.verify_hash(&header, height, nonce).unwrap();
 
 		assert_eq!(
 			actual_mix_bytes, expected_mix_bytes,
@@ -0,0 +1,189 @@
+//! Prometheus metrics for mining farms to scrape.
+//!
+//! `Metrics` holds the collectors; mount them on the process registry with `register`
+//! and update them from the mining loop. Per-device series are labelled `device`.
+
+use std::time::Duration;
+
+use prometheus::{
+	Gauge, GaugeVec, Histogram, HistogramOpts, IntCounterVec, Opts, Registry, Result,
+};
+
+use crate::bench::BenchReport;
+
+/// Cache and DAG builds take seconds to minutes.
+const BUILD_BUCKETS: &[f64] = &[0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+/// Kernel compiles take from a fraction of a second to tens of seconds.
+const COMPILE_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+pub struct Metrics {
+	pub hashrate: GaugeVec,
+	pub solutions: IntCounterVec,
+	pub stale_solutions: IntCounterVec,
+	pub duplicate_solutions: IntCounterVec,
+	pub cache_build_seconds: Histogram,
+	pub kernel_compile_seconds: Histogram,
+	pub gpu_temperature: GaugeVec,
+	/// Results of the `PpCPU` verify cache, see `PpCPU::set_verify_cache`.
+	pub verify_cache_hit_ratio: Gauge,
+}
+
+impl Metrics {
+	pub fn new() -> Result<Self> {
+		Ok(Metrics {
+			hashrate: GaugeVec::new(
+				Opts::new("progpow_hashrate", "Hashes per second."),
+				&["device"],
+			)?,
+			solutions: IntCounterVec::new(
+				Opts::new("progpow_solutions_total", "Solutions found."),
+				&["device"],
+			)?,
+			stale_solutions: IntCounterVec::new(
+				Opts::new(
+					"progpow_stale_solutions_total",
+					"Solutions dropped because their job was superseded.",
+				),
+				&["device"],
+			)?,
+			duplicate_solutions: IntCounterVec::new(
+				Opts::new(
+					"progpow_duplicate_solutions_total",
+					"Solutions dropped because their nonce was already found for the job.",
+				),
+				&["device"],
+			)?,
+			cache_build_seconds: Histogram::with_opts(
+				HistogramOpts::new(
+					"progpow_cache_build_seconds",
+					"Light cache or DAG build time.",
+				)
+				.buckets(BUILD_BUCKETS.to_vec()),
+			)?,
+			kernel_compile_seconds: Histogram::with_opts(
+				HistogramOpts::new("progpow_kernel_compile_seconds", "Kernel compile time.")
+					.buckets(COMPILE_BUCKETS.to_vec()),
+			)?,
+			gpu_temperature: GaugeVec::new(
+				Opts::new("progpow_gpu_temperature_celsius", "GPU core temperature."),
+				&["device"],
+			)?,
+			verify_cache_hit_ratio: Gauge::new(
+				"progpow_verify_cache_hit_ratio",
+				"Share of verify calls answered from the cache.",
+			)?,
+		})
+	}
+
+	/// Add every collector to `registry`, e.g. `prometheus::default_registry()`.
+	pub fn register(&self, registry: &Registry) -> Result<()> {
+		registry.register(Box::new(self.hashrate.clone()))?;
+		registry.register(Box::new(self.solutions.clone()))?;
+		registry.register(Box::new(self.stale_solutions.clone()))?;
+		registry.register(Box::new(self.duplicate_solutions.clone()))?;
+		registry.register(Box::new(self.cache_build_seconds.clone()))?;
+		registry.register(Box::new(self.kernel_compile_seconds.clone()))?;
+		registry.register(Box::new(self.gpu_temperature.clone()))?;
+		registry.register(Box::new(self.verify_cache_hit_ratio.clone()))?;
+		Ok(())
+	}
+
+	pub fn set_hashrate(&self, device: &str, hashes_per_second: f64) {
+		self.hashrate
+			.with_label_values(&[device])
+			.set(hashes_per_second);
+	}
+
+	pub fn solution_found(&self, device: &str) {
+		self.solutions.with_label_values(&[device]).inc();
+	}
+
+	/// Bring the stale counter of `device` up to `total`, as reported by
+	/// `GPU::stale_solutions`.
+	pub fn set_stale_solutions(&self, device: &str, total: u64) {
+		let counter = self.stale_solutions.with_label_values(&[device]);
+		counter.inc_by(total.saturating_sub(counter.get()));
+	}
+
+	/// Like `set_stale_solutions`, for `GPU::duplicate_solutions`.
+	pub fn set_duplicate_solutions(&self, device: &str, total: u64) {
+		let counter = self.duplicate_solutions.with_label_values(&[device]);
+		counter.inc_by(total.saturating_sub(counter.get()));
+	}
+
+	pub fn observe_cache_build(&self, took: Duration) {
+		self.cache_build_seconds.observe(took.as_secs_f64());
+	}
+
+	pub fn observe_kernel_compile(&self, took: Duration) {
+		self.kernel_compile_seconds.observe(took.as_secs_f64());
+	}
+
+	pub fn set_gpu_temperature(&self, device: &str, celsius: f64) {
+		self.gpu_temperature
+			.with_label_values(&[device])
+			.set(celsius);
+	}
+
+	pub fn set_verify_cache_stats(&self, stats: &crate::hardware::CacheStats) {
+		let total = stats.hits + stats.misses;
+		if total > 0 {
+			self.verify_cache_hit_ratio
+				.set(stats.hits as f64 / total as f64);
+		}
+	}
+
+	/// Record the hashrate and setup times of a `bench` run.
+	pub fn record_bench(&self, report: &BenchReport) {
+		self.set_hashrate(&report.device, report.hashrate());
+		self.observe_cache_build(report.dag_build);
+		if let Some(compile) = report.kernel_compile {
+			self.observe_kernel_compile(compile);
+		}
+	}
+
+	/// Update the hashrate, stale and duplicate counters of `gpu`, labelled with its device
+	/// index.
+	#[cfg(any(feature = "cuda", feature = "opencl"))]
+	pub fn record_gpu(&self, gpu: &progpow_gpu::GPU) {
+		let device = gpu.device.to_string();
+		self.set_hashrate(&device, gpu.hashrate());
+		self.set_stale_solutions(&device, gpu.stale_solutions());
+		self.set_duplicate_solutions(&device, gpu.duplicate_solutions());
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_register_and_update() {
+		let registry = Registry::new();
+		let metrics = Metrics::new().unwrap();
+		metrics.register(&registry).unwrap();
+
+		metrics.set_hashrate("0", 25e6);
+		metrics.solution_found("0");
+		metrics.set_stale_solutions("0", 3);
+		metrics.set_stale_solutions("0", 5);
+		metrics.observe_kernel_compile(Duration::from_millis(800));
+
+		assert_eq!(metrics.stale_solutions.with_label_values(&["0"]).get(), 5);
+		metrics.set_duplicate_solutions("0", 2);
+		assert_eq!(
+			metrics.duplicate_solutions.with_label_values(&["0"]).get(),
+			2
+		);
+		let names: Vec<String> = registry
+			.gather()
+			.iter()
+			.map(|family| family.get_name().to_string())
+			.collect();
+		assert!(names.contains(&"progpow_hashrate".to_string()));
+		assert!(names.contains(&"progpow_kernel_compile_seconds".to_string()));
+
+		// Registering twice is refused rather than double counting.
+		assert!(metrics.register(&registry).is_err());
+	}
+}
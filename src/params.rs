@@ -0,0 +1,272 @@
+use progpow_base::params::{MathMapping, ProgPowParams};
+
+/// Runtime copy of the associated constants of a `ProgPowParams` implementation.
+///
+/// Lets a single binary carry the parameters of several chains as values instead of
+/// monomorphizing every caller over the chain type. Hashing and kernel generation still
+/// run on a compiled-in preset: a `DynParams` selects one through `with_preset!`, and
+/// values matching none of them fail with `ProgPowError::UnsupportedParams`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DynParams {
+	pub name: String,
+	pub epoch_length: u64,
+	pub period_length: u64,
+	pub regs: usize,
+	pub dag_loads: usize,
+	pub cnt_cache: usize,
+	pub cnt_math: usize,
+	pub keccak_rounds: u32,
+	pub keccak_domain: u32,
+	#[cfg_attr(feature = "serde", serde(with = "MathMappingDef"))]
+	pub math_mapping: MathMapping,
+	pub has_kiss99_shuffle: bool,
+	pub has_initial_padding: bool,
+	pub seed_byte_swap: bool,
+	pub has_ravencoin_rndc: bool,
+	pub has_meowcoin_rndc: bool,
+	pub has_evrmore_rndc: bool,
+	// Kernel switches `ProgPowParams` has no constant for, set by `of` for the presets
+	// that need them.
+	/// Pad the final keccak state as ProgPoW 0.9.3 does.
+	#[cfg_attr(feature = "serde", serde(default))]
+	pub has_final_padding: bool,
+	/// Absorb the whole seed state in the final keccak, as FiroPoW does.
+	#[cfg_attr(feature = "serde", serde(default))]
+	pub has_firo_final_state: bool,
+	/// Sero's ProgPoW, emitted as `PROGPOW_IS_SERO`.
+	#[cfg_attr(feature = "serde", serde(default))]
+	pub is_sero: bool,
+}
+
+impl DynParams {
+	/// Capture the constants of `P`.
+	pub fn of<P: ProgPowParams>() -> Self {
+		DynParams {
+			name: P::NAME.to_string(),
+			epoch_length: P::EPOCH_LENGTH as u64,
+			period_length: P::PERIOD_LENGTH as u64,
+			regs: P::REGS as usize,
+			dag_loads: P::DAG_LOADS as usize,
+			cnt_cache: P::CNT_CACHE as usize,
+			cnt_math: P::CNT_MATH as usize,
+			keccak_rounds: P::KECCAK_ROUNDS as u32,
+			keccak_domain: P::KECCAK_DOMAIN as u32,
+			math_mapping: P::MATH_MAPPING,
+			has_kiss99_shuffle: P::HAS_KISS99_SHUFFLE,
+			has_initial_padding: P::HAS_INITIAL_PADDING,
+			seed_byte_swap: P::SEED_BYTE_SWAP,
+			has_ravencoin_rndc: P::HAS_RAVENCOIN_RNDC,
+			has_meowcoin_rndc: P::HAS_MEOWCOIN_RNDC,
+			has_evrmore_rndc: P::HAS_EVRMORE_RNDC,
+			has_final_padding: has_final_padding::<P>(),
+			has_firo_final_state: P::NAME == FiroPowParams::NAME,
+			is_sero: P::NAME == SeroParams::NAME,
+		}
+	}
+
+	/// Returns true if these are exactly the constants of `P`. Compares in place, so it
+	/// does not allocate.
+	pub fn is<P: ProgPowParams>(&self) -> bool {
+		self.name == P::NAME
+			&& self.epoch_length == P::EPOCH_LENGTH as u64
+			&& self.period_length == P::PERIOD_LENGTH as u64
+			&& self.regs == P::REGS as usize
+			&& self.dag_loads == P::DAG_LOADS as usize
+			&& self.cnt_cache == P::CNT_CACHE as usize
+			&& self.cnt_math == P::CNT_MATH as usize
+			&& self.keccak_rounds == P::KECCAK_ROUNDS as u32
+			&& self.keccak_domain == P::KECCAK_DOMAIN as u32
+			&& self.math_mapping == P::MATH_MAPPING
+			&& self.has_kiss99_shuffle == P::HAS_KISS99_SHUFFLE
+			&& self.has_initial_padding == P::HAS_INITIAL_PADDING
+			&& self.seed_byte_swap == P::SEED_BYTE_SWAP
+			&& self.has_ravencoin_rndc == P::HAS_RAVENCOIN_RNDC
+			&& self.has_meowcoin_rndc == P::HAS_MEOWCOIN_RNDC
+			&& self.has_evrmore_rndc == P::HAS_EVRMORE_RNDC
+			&& self.has_final_padding == has_final_padding::<P>()
+			&& self.has_firo_final_state == (P::NAME == FiroPowParams::NAME)
+			&& self.is_sero == (P::NAME == SeroParams::NAME)
+	}
+
+	pub fn epoch(&self, height: u64) -> u64 {
+		height / self.epoch_length
+	}
+
+	pub fn period(&self, height: u64) -> u64 {
+		height / self.period_length
+	}
+}
+
+fn has_final_padding<P: ProgPowParams>() -> bool {
+	P::NAME == ProgPow093Params::NAME || P::NAME == crate::epic::EpicParams::NAME
+}
+
+/// Sero's ProgPoW 0.9.2. Like Zano it uses the shifted math mapping, byte-swaps the hash
+/// seed and pads the keccak state with zeros, but keeps ethash's 30000-block epochs and
+/// DAG schedule. `DynParams::is_sero` marks it for the generator; the CPU verifier needs
+/// nothing beyond the math mapping and seed byte swap.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SeroParams;
+
+impl ProgPowParams for SeroParams {
+	const NAME: &'static str = "SeroProgPow";
+	const EPOCH_LENGTH: u64 = 30000;
+	const PERIOD_LENGTH: u32 = 50;
+	const REGS: u32 = 32;
+	const DAG_LOADS: u32 = 4;
+	const CNT_CACHE: u32 = 12;
+	const CNT_MATH: u32 = 20;
+	const KECCAK_ROUNDS: u32 = 22;
+	const KECCAK_DOMAIN: u32 = 0;
+	const MATH_MAPPING: MathMapping = MathMapping::Zano;
+	const HAS_KISS99_SHUFFLE: bool = false;
+	const HAS_INITIAL_PADDING: bool = false;
+	const SEED_BYTE_SWAP: bool = true;
+	const HAS_RAVENCOIN_RNDC: bool = false;
+	const HAS_MEOWCOIN_RNDC: bool = false;
+	const HAS_EVRMORE_RNDC: bool = false;
+}
+
+/// ProgPoW 0.9.3, the revision proposed for Ethereum, with its 10-block periods.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProgPow093Params;
+
+impl ProgPowParams for ProgPow093Params {
+	const NAME: &'static str = "ProgPow";
+	const EPOCH_LENGTH: u64 = 30000;
+	const PERIOD_LENGTH: u32 = 10;
+	const REGS: u32 = 32;
+	const DAG_LOADS: u32 = 4;
+	const CNT_CACHE: u32 = 11;
+	const CNT_MATH: u32 = 18;
+	const KECCAK_ROUNDS: u32 = 22;
+	const KECCAK_DOMAIN: u32 = 0;
+	const MATH_MAPPING: MathMapping = MathMapping::Standard;
+	const HAS_KISS99_SHUFFLE: bool = false;
+	const HAS_INITIAL_PADDING: bool = true;
+	const SEED_BYTE_SWAP: bool = true;
+	const HAS_RAVENCOIN_RNDC: bool = false;
+	const HAS_MEOWCOIN_RNDC: bool = false;
+	const HAS_EVRMORE_RNDC: bool = false;
+}
+
+/// Firo's FiroPoW, ProgPoW 0.9.4 with a new program every block and 1300-block epochs.
+/// The final keccak absorbs the whole seed state, see `DynParams::has_firo_final_state`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FiroPowParams;
+
+impl ProgPowParams for FiroPowParams {
+	const NAME: &'static str = "FiroPow";
+	const EPOCH_LENGTH: u64 = 1300;
+	const PERIOD_LENGTH: u32 = 1;
+	const REGS: u32 = 32;
+	const DAG_LOADS: u32 = 4;
+	const CNT_CACHE: u32 = 11;
+	const CNT_MATH: u32 = 18;
+	const KECCAK_ROUNDS: u32 = 22;
+	const KECCAK_DOMAIN: u32 = 0;
+	const MATH_MAPPING: MathMapping = MathMapping::KawPow;
+	const HAS_KISS99_SHUFFLE: bool = false;
+	const HAS_INITIAL_PADDING: bool = false;
+	const SEED_BYTE_SWAP: bool = false;
+	const HAS_RAVENCOIN_RNDC: bool = false;
+	const HAS_MEOWCOIN_RNDC: bool = false;
+	const HAS_EVRMORE_RNDC: bool = false;
+}
+
+/// Serde mirror of `MathMapping`, which lives in progpow-base.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(remote = "MathMapping")]
+#[allow(dead_code)]
+enum MathMappingDef {
+	Standard,
+	KawPow,
+	Zano,
+}
+
+/// A compiled-in preset, resolved from a `DynParams` once so hot paths dispatch on it
+/// without comparing params.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Preset {
+	KawPow,
+	Zano,
+	Sero,
+	Epic,
+	ProgPow093,
+	FiroPow,
+}
+
+impl Preset {
+	/// The preset whose constants `params` are.
+	pub(crate) fn of(params: &DynParams) -> Option<Preset> {
+		use progpow_base::params::{KawPowParams, ZanoParams};
+		if params.is::<KawPowParams>() {
+			Some(Preset::KawPow)
+		} else if params.is::<ZanoParams>() {
+			Some(Preset::Zano)
+		} else if params.is::<SeroParams>() {
+			Some(Preset::Sero)
+		} else if params.is::<crate::epic::EpicParams>() {
+			Some(Preset::Epic)
+		} else if params.is::<ProgPow093Params>() {
+			Some(Preset::ProgPow093)
+		} else if params.is::<FiroPowParams>() {
+			Some(Preset::FiroPow)
+		} else {
+			None
+		}
+	}
+
+	/// `self` if the CPU verifier hashes it. ProgPoW 0.9.3 and FiroPoW are left out until
+	/// it does their final keccak.
+	pub(crate) fn cpu(self) -> Option<Preset> {
+		match self {
+			Preset::ProgPow093 | Preset::FiroPow => None,
+			_ => Some(self),
+		}
+	}
+}
+
+/// Run `$body` with `$P` bound to the compiled-in preset whose constants `$params` are.
+///
+/// Only presets can be selected, since `$P` must be a type; any other value evaluates to
+/// `Err(ProgPowError::UnsupportedParams)`. `with_preset!(preset: ...)` takes an
+/// `Option<Preset>` resolved beforehand instead, as `PpCPU` keeps one.
+macro_rules! with_preset {
+	(preset: $preset:expr, $P:ident => $body:expr) => {
+		match $preset {
+			Some($crate::params::Preset::KawPow) => {
+				type $P = progpow_base::params::KawPowParams;
+				$body
+			}
+			Some($crate::params::Preset::Zano) => {
+				type $P = progpow_base::params::ZanoParams;
+				$body
+			}
+			Some($crate::params::Preset::Sero) => {
+				type $P = $crate::params::SeroParams;
+				$body
+			}
+			Some($crate::params::Preset::Epic) => {
+				type $P = $crate::epic::EpicParams;
+				$body
+			}
+			Some($crate::params::Preset::ProgPow093) => {
+				type $P = $crate::params::ProgPow093Params;
+				$body
+			}
+			Some($crate::params::Preset::FiroPow) => {
+				type $P = $crate::params::FiroPowParams;
+				$body
+			}
+			None => Err($crate::types::ProgPowError::UnsupportedParams),
+		}
+	};
+	($params:expr, $P:ident => $body:expr) => {
+		$crate::params::with_preset!(preset: $crate::params::Preset::of(&$params), $P => $body)
+	};
+}
+
+pub(crate) use with_preset;
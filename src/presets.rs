@@ -0,0 +1,76 @@
+//! Compiled-in params by name, for config files and command lines.
+
+use progpow_base::params::{KawPowParams, ZanoParams};
+
+use crate::epic::EpicParams;
+use crate::params::{DynParams, SeroParams};
+
+/// Preset names, in the order of `all`. ProgPoW 0.9.3 and FiroPoW are left out until the
+/// CPU verifier does their final keccak; `ProgPow093Params` and `FiroPowParams` still
+/// generate kernels.
+pub const NAMES: [&str; 4] = ["kawpow", "zano", "sero", "epic"];
+
+/// The preset called `name`, ignoring case.
+pub fn by_name(name: &str) -> Option<DynParams> {
+	let params = match name.to_ascii_lowercase().as_str() {
+		"kawpow" => DynParams::of::<KawPowParams>(),
+		"zano" => DynParams::of::<ZanoParams>(),
+		"sero" => DynParams::of::<SeroParams>(),
+		"epic" => DynParams::of::<EpicParams>(),
+		_ => return None,
+	};
+	Some(params)
+}
+
+/// Every preset with its name.
+pub fn all() -> Vec<(&'static str, DynParams)> {
+	NAMES
+		.iter()
+		.map(|&name| (name, by_name(name).unwrap()))
+		.collect()
+}
+
+/// The name `params` are listed under, if they are a preset.
+pub fn name_of(params: &DynParams) -> Option<&'static str> {
+	NAMES
+		.iter()
+		.copied()
+		.find(|&name| by_name(name).as_ref() == Some(params))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_presets_by_name() {
+		assert!(by_name("KawPow").unwrap().is::<KawPowParams>());
+		assert!(by_name("ethash").is_none());
+		assert!(by_name("progpow-0.9.3").is_none());
+		assert!(by_name("firopow").is_none());
+
+		let mut tweaked = by_name("zano").unwrap();
+		tweaked.cnt_math += 1;
+		assert!(!tweaked.is::<ZanoParams>());
+		assert_eq!(crate::params::Preset::of(&tweaked), None);
+
+		let all = all();
+		assert_eq!(all.len(), NAMES.len());
+		for (i, (name, params)) in all.iter().enumerate() {
+			assert_eq!(name_of(params), Some(*name));
+			assert!(crate::params::Preset::of(params).is_some());
+			// Each one is its own preset, so `with_preset!` can tell them apart.
+			assert!(all[..i].iter().all(|(_, other)| other != params));
+		}
+
+		let flagged = |flag: fn(&DynParams) -> bool| {
+			all.iter()
+				.filter(|(_, params)| flag(params))
+				.map(|(name, _)| *name)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(flagged(|p| p.has_final_padding), ["epic"]);
+		assert!(flagged(|p| p.has_firo_final_state).is_empty());
+		assert_eq!(flagged(|p| p.is_sero), ["sero"]);
+	}
+}
@@ -0,0 +1,260 @@
+//! Solo mining: search a node's block template across devices and assemble the solved
+//! header for submission.
+//!
+//! Headers are the 80-byte KawPow layout of `kawpow`. Several rigs mining the same
+//! template split the 64-bit nonce with an extra-nonce, see `Solo::set_extranonce`.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use progpow_base::params::KawPowParams;
+use tracing::warn;
+
+use crate::epoch::epoch_for_height;
+use crate::hardware::PpCPU;
+use crate::kawpow::{self, HEADER_BYTES};
+use crate::types::difficulty::{meets_target, target_from_compact};
+use crate::types::{Batch, MiningJob, ProgPowError, Verifier, H256};
+
+/// Header fields of a block template, as a node's `getblocktemplate` returns them.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockTemplate {
+	pub version: u32,
+	/// Hash of the previous block, in header byte order.
+	pub prev_hash: H256,
+	pub merkle_root: H256,
+	pub time: u32,
+	pub bits: u32,
+	pub height: u32,
+	/// Big-endian target the final hash must meet, see `types::difficulty`.
+	pub target: H256,
+}
+
+impl BlockTemplate {
+	/// Template whose target is the one `bits` encodes.
+	pub fn new(
+		version: u32,
+		prev_hash: H256,
+		merkle_root: H256,
+		time: u32,
+		bits: u32,
+		height: u32,
+	) -> Self {
+		BlockTemplate {
+			version,
+			prev_hash,
+			merkle_root,
+			time,
+			bits,
+			height,
+			target: target_from_compact(bits),
+		}
+	}
+
+	/// The serialized header `kawpow::header_hash` takes.
+	pub fn header(&self) -> [u8; HEADER_BYTES] {
+		let mut header = [0u8; HEADER_BYTES];
+		header[..4].copy_from_slice(&self.version.to_le_bytes());
+		header[4..36].copy_from_slice(&self.prev_hash);
+		header[36..68].copy_from_slice(&self.merkle_root);
+		header[68..72].copy_from_slice(&self.time.to_le_bytes());
+		header[72..76].copy_from_slice(&self.bits.to_le_bytes());
+		header[76..].copy_from_slice(&self.height.to_le_bytes());
+		header
+	}
+
+	pub fn header_hash(&self) -> H256 {
+		kawpow::header_hash(&self.header())
+	}
+
+	/// The job devices search.
+	pub fn job(&self, job_id: u64) -> MiningJob {
+		let height = self.height as u64;
+		MiningJob::new(
+			job_id,
+			self.header_hash(),
+			height,
+			epoch_for_height::<KawPowParams>(height) as i32,
+			self.target,
+		)
+	}
+}
+
+/// A header with its proof of work, checked on the CPU against the full target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolvedBlock {
+	pub header: [u8; HEADER_BYTES],
+	pub nonce: u64,
+	pub mix_hash: H256,
+	pub final_hash: H256,
+}
+
+impl SolvedBlock {
+	/// The block header as submitted: the hashed header, then the little-endian nonce
+	/// and the mix hash.
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(HEADER_BYTES + 8 + 32);
+		bytes.extend_from_slice(&self.header);
+		bytes.extend_from_slice(&self.nonce.to_le_bytes());
+		bytes.extend_from_slice(&self.mix_hash);
+		bytes
+	}
+}
+
+/// Searches block templates until one is solved.
+pub struct Solo {
+	verifier: PpCPU<KawPowParams>,
+	extranonce: u64,
+	extranonce_bits: u32,
+	stop: Arc<AtomicBool>,
+	job_id: u64,
+}
+
+impl Solo {
+	pub fn new() -> Self {
+		Solo {
+			verifier: PpCPU::new(),
+			extranonce: 0,
+			extranonce_bits: 0,
+			stop: Arc::new(AtomicBool::new(false)),
+			job_id: 0,
+		}
+	}
+
+	/// Directory of the light caches solutions are checked with, see `PpCPU`.
+	pub fn set_cache_dir<D: Into<PathBuf>>(&mut self, dir: D) {
+		self.verifier.set_cache_dir(dir);
+	}
+
+	/// Fix the top `bits` of every nonce to `extranonce`, so rigs given different
+	/// extra-nonces never search the same nonce. Up to 32 bits.
+	pub fn set_extranonce(&mut self, extranonce: u64, bits: u32) {
+		let bits = bits.min(32);
+		self.extranonce_bits = bits;
+		self.extranonce = extranonce & ((1u64 << bits) - 1);
+	}
+
+	/// Make the current or next `mine` return `None`, e.g. because the node has a new
+	/// template.
+	pub fn stop(&self) {
+		self.stop.store(true, Ordering::SeqCst);
+	}
+
+	pub fn stop_handle(&self) -> Arc<AtomicBool> {
+		self.stop.clone()
+	}
+
+	/// The nonce `offset` nonces into this rig's range.
+	fn nonce(&self, offset: u64) -> u64 {
+		match self.extranonce_bits {
+			0 => offset,
+			bits => (self.extranonce << (64 - bits)) | offset,
+		}
+	}
+
+	/// Search `template` until it is solved. `search` is called with the job and a start
+	/// nonce and searches one batch, as `hardware::Devices::search` does across devices.
+	/// Every solution is rechecked on the CPU against the full 256-bit target. Returns
+	/// `None` if stopped or once the extra-nonce range is exhausted.
+	pub fn mine<F>(
+		&mut self,
+		template: &BlockTemplate,
+		mut search: F,
+	) -> Result<Option<SolvedBlock>, ProgPowError>
+	where
+		F: FnMut(&MiningJob, u64) -> Result<Batch, ProgPowError>,
+	{
+		self.job_id += 1;
+		let job = template.job(self.job_id);
+		let header = template.header();
+		let last = u64::MAX >> self.extranonce_bits;
+		let mut offset = 0u64;
+		loop {
+			if self.stop.swap(false, Ordering::SeqCst) {
+				return Ok(None);
+			}
+			let batch = search(&job, self.nonce(offset))?;
+			for (nonce, mix_hash) in &batch.found {
+				if let Some(block) = self.check(template, &header, *nonce, mix_hash)? {
+					return Ok(Some(block));
+				}
+			}
+			offset = match offset.checked_add(batch.searched) {
+				Some(next) if next <= last => next,
+				_ => return Ok(None),
+			};
+		}
+	}
+
+	fn check(
+		&self,
+		template: &BlockTemplate,
+		header: &[u8; HEADER_BYTES],
+		nonce: u64,
+		mix_hash: &H256,
+	) -> Result<Option<SolvedBlock>, ProgPowError> {
+		let (final_hash, expected_mix) = self.verifier.verify_hash(
+			&kawpow::header_hash(header),
+			template.height as u64,
+			nonce,
+		)?;
+		if &expected_mix != mix_hash {
+			warn!(nonce, "device mix hash differs from the CPU");
+			return Ok(None);
+		}
+		// Devices only compare the upper 64 bits.
+		if !meets_target(&final_hash, &template.target) {
+			return Ok(None);
+		}
+		Ok(Some(SolvedBlock {
+			header: *header,
+			nonce,
+			mix_hash: *mix_hash,
+			final_hash,
+		}))
+	}
+}
+
+impl Default for Solo {
+	fn default() -> Self {
+		Solo::new()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::types::{mix_hash_bytes, ShareStatus};
+
+	#[test]
+	fn test_solo_mine() {
+		// A target of 0xffff00.. that most hashes meet.
+		let template = BlockTemplate::new(4, [1; 32], [2; 32], 1700000000, 0x2100ffff, 20);
+		let cpu = PpCPU::<KawPowParams>::new();
+		let search = |job: &MiningJob, start: u64| -> Result<Batch, ProgPowError> {
+			let found = cpu.search_target(&job.header, job.height, &job.target, start, 16)?;
+			Ok(Batch {
+				searched: 16,
+				found: found
+					.into_iter()
+					.map(|(nonce, mix)| (nonce, mix_hash_bytes(&mix)))
+					.collect(),
+			})
+		};
+
+		let mut solo = Solo::new();
+		solo.set_extranonce(3, 8);
+		let block = solo.mine(&template, search).unwrap().unwrap();
+		assert_eq!(block.nonce >> 56, 3);
+		assert_eq!(block.to_bytes().len(), 120);
+		assert_eq!(
+			kawpow::verify_block(&block.header, block.nonce, &block.mix_hash).unwrap(),
+			ShareStatus::Ok
+		);
+
+		solo.stop();
+		assert_eq!(solo.mine(&template, search).unwrap(), None);
+	}
+}
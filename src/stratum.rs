@@ -0,0 +1,334 @@
+//! Stratum v1 client for KawPow pools.
+//!
+//! Speaks the KawPow dialect used by Ravencoin pools: `mining.notify` carries
+//! `[job_id, header_hash, seed_hash, target, clean_jobs, height, bits]` and shares are
+//! submitted as `[worker, job_id, nonce, header_hash, mix_hash]`, all hex with `0x`.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use serde_json::{json, Value};
+
+use crate::types::{h256_from_hex, to_hex, Batch, Extranonce, ProgPowError, H256};
+
+/// A job announced by `mining.notify`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Job {
+	pub id: String,
+	pub header_hash: H256,
+	pub seed_hash: H256,
+	/// Big-endian share target, see `types::difficulty`.
+	pub target: H256,
+	pub height: u64,
+	/// The pool asks to drop any work on previous jobs.
+	pub clean: bool,
+}
+
+/// Something the pool sent.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Event {
+	Job(Job),
+	/// New share target from `mining.set_target`, applied to the following jobs.
+	Target(H256),
+	/// Reply to `submit` with the id it returned.
+	Accepted(u64),
+	Rejected(u64, String),
+}
+
+pub struct Client {
+	reader: BufReader<TcpStream>,
+	writer: TcpStream,
+	worker: String,
+	next_id: u64,
+	extranonce: String,
+	target: Option<H256>,
+	/// Messages `call` read while waiting for its reply, for `next_event`.
+	queued: VecDeque<Value>,
+}
+
+fn network<E: std::fmt::Display>(e: E) -> ProgPowError {
+	ProgPowError::Network(e.to_string())
+}
+
+fn protocol<S: Into<String>>(msg: S) -> ProgPowError {
+	ProgPowError::Protocol(msg.into())
+}
+
+pub(crate) fn parse_h256(value: &Value) -> Result<H256, ProgPowError> {
+	value
+		.as_str()
+		.and_then(h256_from_hex)
+		.ok_or_else(|| protocol(format!("expected 32 byte hex, got {}", value)))
+}
+
+/// Parse `mining.notify` params. `target` is the last `mining.set_target`, used when the
+/// job does not carry its own.
+fn parse_notify(params: &Value, target: Option<H256>) -> Result<Job, ProgPowError> {
+	let id = params[0]
+		.as_str()
+		.ok_or_else(|| protocol("mining.notify without job id"))?;
+	let target = match (&params[3], target) {
+		(Value::String(_), _) => parse_h256(&params[3])?,
+		(_, Some(target)) => target,
+		_ => return Err(protocol("mining.notify without target")),
+	};
+	Ok(Job {
+		id: id.to_string(),
+		header_hash: parse_h256(&params[1])?,
+		seed_hash: parse_h256(&params[2])?,
+		target,
+		clean: params[4].as_bool().unwrap_or(false),
+		height: params[5]
+			.as_u64()
+			.ok_or_else(|| protocol("mining.notify without height"))?,
+	})
+}
+
+impl Client {
+	/// Connect, subscribe and authorize `worker`.
+	pub fn connect<A: ToSocketAddrs>(
+		addr: A,
+		worker: &str,
+		password: &str,
+	) -> Result<Self, ProgPowError> {
+		let stream = TcpStream::connect(addr).map_err(network)?;
+		let mut client = Client {
+			reader: BufReader::new(stream.try_clone().map_err(network)?),
+			writer: stream,
+			worker: worker.to_string(),
+			next_id: 1,
+			extranonce: String::new(),
+			target: None,
+			queued: VecDeque::new(),
+		};
+
+		let subscribed = client.call("mining.subscribe", json!(["progpow-rust/0.1"]))?;
+		if let Some(extranonce) = subscribed.get(1).and_then(Value::as_str) {
+			client.extranonce = extranonce.to_string();
+		}
+		let authorized = client.call("mining.authorize", json!([worker, password]))?;
+		if authorized != Value::Bool(true) {
+			return Err(protocol(format!("authorization refused: {}", authorized)));
+		}
+		Ok(client)
+	}
+
+	/// Extranonce assigned at subscription, as hex.
+	pub fn extranonce(&self) -> &str {
+		&self.extranonce
+	}
+
+	/// The extranonce as fixed nonce bytes, for `MiningJob::with_extranonce`. Empty if the
+	/// pool sent none or an odd number of digits.
+	pub fn fixed_nonce(&self) -> Extranonce {
+		Extranonce::from_hex(&self.extranonce).unwrap_or_default()
+	}
+
+	/// First nonce of the range this connection owns: the extranonce in the top bytes.
+	pub fn start_nonce(&self) -> u64 {
+		let bits = self.extranonce.len() * 4;
+		match u64::from_str_radix(&self.extranonce, 16) {
+			Ok(prefix) if bits > 0 && bits < 64 => prefix << (64 - bits),
+			_ => 0,
+		}
+	}
+
+	fn send(&mut self, method: &str, params: Value) -> Result<u64, ProgPowError> {
+		let id = self.next_id;
+		self.next_id += 1;
+		let msg = json!({ "id": id, "method": method, "params": params });
+		writeln!(self.writer, "{}", msg).map_err(network)?;
+		Ok(id)
+	}
+
+	fn read(&mut self) -> Result<Value, ProgPowError> {
+		let mut line = String::new();
+		if self.reader.read_line(&mut line).map_err(network)? == 0 {
+			return Err(network("connection closed by pool"));
+		}
+		serde_json::from_str(&line).map_err(|e| protocol(e.to_string()))
+	}
+
+	/// Send a request and wait for its reply. Messages received meanwhile, such as the
+	/// target pools send during `connect`, are queued for `next_event`.
+	fn call(&mut self, method: &str, params: Value) -> Result<Value, ProgPowError> {
+		let id = self.send(method, params)?;
+		loop {
+			let msg = self.read()?;
+			if msg["id"].as_u64() == Some(id) {
+				if !msg["error"].is_null() {
+					return Err(protocol(format!("{} failed: {}", method, msg["error"])));
+				}
+				return Ok(msg["result"].clone());
+			}
+			self.queued.push_back(msg);
+		}
+	}
+
+	/// Block until the pool sends something worth acting on.
+	pub fn next_event(&mut self) -> Result<Event, ProgPowError> {
+		loop {
+			let msg = match self.queued.pop_front() {
+				Some(msg) => msg,
+				None => self.read()?,
+			};
+			match msg["method"].as_str() {
+				Some("mining.notify") => {
+					return parse_notify(&msg["params"], self.target).map(Event::Job)
+				}
+				Some("mining.set_target") => {
+					let target = parse_h256(&msg["params"][0])?;
+					self.target = Some(target);
+					return Ok(Event::Target(target));
+				}
+				Some(_) => continue,
+				None => {}
+			}
+			if let Some(id) = msg["id"].as_u64() {
+				if msg["result"] == Value::Bool(true) {
+					return Ok(Event::Accepted(id));
+				}
+				return Ok(Event::Rejected(id, msg["error"].to_string()));
+			}
+		}
+	}
+
+	/// Submit a share for `job`. The reply arrives later as `Event::Accepted` or
+	/// `Event::Rejected` with the returned id.
+	pub fn submit(&mut self, job: &Job, nonce: u64, mix_hash: &H256) -> Result<u64, ProgPowError> {
+		let params = json!([
+			self.worker,
+			job.id,
+			to_hex(&nonce.to_be_bytes()),
+			to_hex(&job.header_hash),
+			to_hex(mix_hash),
+		]);
+		self.send("mining.submit", params)
+	}
+
+	/// Mine until an error occurs. `search` is called with the current job and a start
+	/// nonce and should search a single batch, as new jobs are only picked up between calls.
+	pub fn run<F>(&mut self, mut search: F) -> Result<(), ProgPowError>
+	where
+		F: FnMut(&Job, u64) -> Result<Batch, ProgPowError>,
+	{
+		let mut job = loop {
+			if let Event::Job(job) = self.next_event()? {
+				break job;
+			}
+		};
+		let mut nonce = self.start_nonce();
+		loop {
+			let batch = search(&job, nonce)?;
+			for (found, mix) in &batch.found {
+				self.submit(&job, *found, mix)?;
+			}
+			nonce = nonce.wrapping_add(batch.searched);
+
+			while self.has_pending()? {
+				if let Event::Job(next) = self.next_event()? {
+					if next.clean || next.header_hash != job.header_hash {
+						nonce = self.start_nonce();
+					}
+					job = next;
+				}
+			}
+		}
+	}
+
+	/// Whether a message from the pool is waiting to be read.
+	fn has_pending(&mut self) -> Result<bool, ProgPowError> {
+		if !self.queued.is_empty() || !self.reader.buffer().is_empty() {
+			return Ok(true);
+		}
+		let socket = self.reader.get_ref();
+		socket.set_nonblocking(true).map_err(network)?;
+		let mut probe = [0u8; 1];
+		let pending = match socket.peek(&mut probe) {
+			Ok(0) => Err(network("connection closed by pool")),
+			Ok(_) => Ok(true),
+			Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(false),
+			Err(e) => Err(network(e)),
+		};
+		socket.set_nonblocking(false).map_err(network)?;
+		pending
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_parse_notify() {
+		let msg: Value = serde_json::from_str(
+			r#"{"id":null,"method":"mining.notify","params":["1a2b",
+			"0x470d42a9f6ea35569d6aa7206cf1d4b292a1bc11b0165523f95bbb8678c85d0e",
+			"0x0000000000000000000000000000000000000000000000000000000000000000",
+			"0x00000000ffff0000000000000000000000000000000000000000000000000000",
+			true, 3528114, "1b00f968"]}"#,
+		)
+		.unwrap();
+
+		let job = parse_notify(&msg["params"], None).unwrap();
+		assert_eq!(job.id, "1a2b");
+		assert_eq!(job.header_hash[0], 0x47);
+		assert_eq!(job.target[4..6], [0xff, 0xff]);
+		assert_eq!(job.height, 3528114);
+		assert!(job.clean);
+
+		// Pools that send the target separately leave it out of the job.
+		let mut params = msg["params"].clone();
+		params[3] = Value::Null;
+		assert!(parse_notify(&params, None).is_err());
+		assert_eq!(
+			parse_notify(&params, Some([1; 32])).unwrap().target,
+			[1; 32]
+		);
+	}
+
+	#[test]
+	fn test_notifications_during_connect() {
+		use std::net::TcpListener;
+		use std::thread;
+
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+		let target = format!("0x{}", "00ff".repeat(16));
+		let pool = thread::spawn(move || {
+			let (stream, _) = listener.accept().unwrap();
+			let mut reader = BufReader::new(stream.try_clone().unwrap());
+			let mut writer = stream;
+			let mut line = String::new();
+			reader.read_line(&mut line).unwrap();
+			writeln!(
+				writer,
+				r#"{{"id":null,"method":"mining.set_target","params":["{}"]}}"#,
+				target
+			)
+			.unwrap();
+			writeln!(writer, r#"{{"id":1,"result":[null,"ab12"],"error":null}}"#).unwrap();
+			reader.read_line(&mut line).unwrap();
+			writeln!(writer, r#"{{"id":2,"result":true,"error":null}}"#).unwrap();
+		});
+
+		let mut client = Client::connect(addr, "worker", "x").unwrap();
+		pool.join().unwrap();
+		assert_eq!(client.extranonce(), "ab12");
+		// The target sent before the subscribe reply is not lost.
+		let expected: H256 = [0x00, 0xff].repeat(16).try_into().unwrap();
+		assert!(client.has_pending().unwrap());
+		assert_eq!(client.next_event().unwrap(), Event::Target(expected));
+		assert_eq!(client.target, Some(expected));
+	}
+
+	#[test]
+	fn test_hex_round_trip() {
+		let bytes = [0xabu8; 32];
+		assert_eq!(parse_h256(&Value::String(to_hex(&bytes))).unwrap(), bytes);
+		assert!(parse_h256(&json!("0x1234")).is_err());
+	}
+}
@@ -0,0 +1,364 @@
+//! Device restarts for rigs left mining unattended, e.g. as a service or daemon.
+//!
+//! After a driver reset or a launch failure a GPU fails every `compute` until its miner
+//! is rebuilt. The supervisor drops a failing device's miner and starts a new one after
+//! a backoff, while the other devices keep mining. A search that never returns, as
+//! after a kernel hang, is caught by a watchdog and handled the same way. A device that
+//! disappeared, as an unplugged eGPU, is reported as `DeviceStatus::Lost` first and then
+//! restarted like any other, so it resumes once it is back.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+use crate::hardware::{DeviceThread, HardwareSpec};
+use crate::types::{Batch, MiningJob, ProgPowError};
+
+/// Default for `Supervisor::with_watchdog`. The first search of an epoch builds its
+/// DAG, which must fit in it.
+pub const DEFAULT_WATCHDOG: Duration = Duration::from_secs(300);
+
+/// When a failed device is restarted, and when it is given up on.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RestartPolicy {
+	/// Wait before the first restart, doubled after each one that fails again.
+	pub initial_backoff: Duration,
+	pub max_backoff: Duration,
+	/// Failures in a row after which the device is left stopped. `None` never gives up.
+	pub max_restarts: Option<u32>,
+	/// A device that ran this long before failing counts as healthy again, so its next
+	/// restart waits `initial_backoff`.
+	pub reset_after: Duration,
+}
+
+impl Default for RestartPolicy {
+	fn default() -> Self {
+		RestartPolicy {
+			initial_backoff: Duration::from_secs(1),
+			max_backoff: Duration::from_secs(300),
+			max_restarts: Some(10),
+			reset_after: Duration::from_secs(600),
+		}
+	}
+}
+
+impl RestartPolicy {
+	/// Wait before restart number `attempt`, from 1.
+	pub fn backoff(&self, attempt: u32) -> Duration {
+		let doublings = attempt.saturating_sub(1).min(31);
+		self.initial_backoff
+			.saturating_mul(1 << doublings)
+			.min(self.max_backoff)
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceStatus {
+	/// Not started yet.
+	Starting,
+	Running,
+	/// Failed with `error`, restarted after `backoff`.
+	Restarting {
+		error: String,
+		attempt: u32,
+		backoff: Duration,
+	},
+	/// Failed more than `RestartPolicy::max_restarts` times in a row.
+	GaveUp(String),
+	/// The device stopped responding, see `ProgPowError::DeviceLost`. Followed by
+	/// `Restarting` or `GaveUp`.
+	Lost(String),
+}
+
+struct Slot {
+	spec: HardwareSpec,
+	thread: Option<DeviceThread>,
+	status: DeviceStatus,
+	/// Failures since the device last ran for `reset_after`.
+	failures: u32,
+	retry_at: Instant,
+	started: Instant,
+}
+
+/// Device threads searching consecutive nonce ranges like `Devices`, restarting those
+/// that fail according to a `RestartPolicy`.
+pub struct Supervisor {
+	slots: Vec<Slot>,
+	policy: RestartPolicy,
+	watchdog: Option<Duration>,
+	handler: Option<Box<dyn Fn(usize, &DeviceStatus) + Send + Sync>>,
+}
+
+impl Supervisor {
+	/// Devices are started by the first `search`, so `on_status` sees every transition.
+	pub fn new(specs: Vec<HardwareSpec>, policy: RestartPolicy) -> Self {
+		let now = Instant::now();
+		let slots = specs
+			.into_iter()
+			.map(|spec| Slot {
+				spec,
+				thread: None,
+				status: DeviceStatus::Starting,
+				failures: 0,
+				retry_at: now,
+				started: now,
+			})
+			.collect();
+		Supervisor {
+			slots,
+			policy,
+			watchdog: Some(DEFAULT_WATCHDOG),
+			handler: None,
+		}
+	}
+
+	/// Treat a device whose search has not returned after `timeout` as failed. Its thread
+	/// cannot be interrupted, so it is abandoned with its miner and a new one is started
+	/// after the backoff. `None` waits forever.
+	pub fn with_watchdog(mut self, timeout: Option<Duration>) -> Self {
+		self.watchdog = timeout;
+		self
+	}
+
+	/// Call `handler` with the index of the device, in `specs` order, and its new status
+	/// on every change.
+	pub fn on_status<F>(mut self, handler: F) -> Self
+	where
+		F: Fn(usize, &DeviceStatus) + Send + Sync + 'static,
+	{
+		self.handler = Some(Box::new(handler));
+		self
+	}
+
+	pub fn statuses(&self) -> Vec<DeviceStatus> {
+		self.slots.iter().map(|slot| slot.status.clone()).collect()
+	}
+
+	/// Search one round of `job` from `start` on every running device, as
+	/// `Devices::search` does. The range of a device that fails is skipped this round.
+	///
+	/// Devices due for a restart are started first, which blocks until their miner is
+	/// initialized. If none is running, waits for the next restart; fails only once every
+	/// device was given up on.
+	pub fn search(&mut self, job: &MiningJob, start: u64) -> Result<Batch, ProgPowError> {
+		loop {
+			self.restart_due();
+			if self.slots.iter().any(|slot| slot.thread.is_some()) {
+				break;
+			}
+			let next = self
+				.slots
+				.iter()
+				.filter(|slot| !matches!(slot.status, DeviceStatus::GaveUp(_)))
+				.map(|slot| slot.retry_at)
+				.min();
+			match next {
+				Some(next) => thread::sleep(next.saturating_duration_since(Instant::now())),
+				None => return Err(ProgPowError::Driver("every device gave up".to_string())),
+			}
+		}
+
+		let mut failed = Vec::new();
+		let mut started = Vec::new();
+		let mut next = start;
+		for (i, slot) in self.slots.iter().enumerate() {
+			if let Some(ref device) = slot.thread {
+				match device.start(job, next) {
+					Ok(()) => started.push(i),
+					Err(e) => failed.push((i, e)),
+				}
+				next = next.wrapping_add(device.round_size());
+			}
+		}
+		let mut batch = Batch {
+			searched: next.wrapping_sub(start),
+			found: Vec::new(),
+		};
+		for i in started {
+			let device = self.slots[i].thread.as_ref().unwrap();
+			let found = match self.watchdog {
+				Some(timeout) => device.finish_within(timeout),
+				None => device.finish(),
+			};
+			match found {
+				Ok(found) => batch.found.extend(found),
+				Err(e) => failed.push((i, e)),
+			}
+		}
+		for (i, e) in failed {
+			self.fail(i, e);
+		}
+		Ok(batch)
+	}
+
+	fn restart_due(&mut self) {
+		let now = Instant::now();
+		for i in 0..self.slots.len() {
+			let slot = &self.slots[i];
+			if slot.thread.is_some()
+				|| matches!(slot.status, DeviceStatus::GaveUp(_))
+				|| slot.retry_at > now
+			{
+				continue;
+			}
+			match DeviceThread::spawn(slot.spec.clone()) {
+				Ok(device) => {
+					let slot = &mut self.slots[i];
+					slot.thread = Some(device);
+					slot.started = Instant::now();
+					self.set_status(i, DeviceStatus::Running);
+				}
+				Err(e) => self.fail(i, e),
+			}
+		}
+	}
+
+	fn fail(&mut self, i: usize, error: ProgPowError) {
+		if let ProgPowError::DeviceLost(ref device) = error {
+			self.set_status(i, DeviceStatus::Lost(device.clone()));
+		}
+		let error = format!("{:?}", error);
+		let slot = &mut self.slots[i];
+		// Dropping the thread's work channel ends it and frees the miner.
+		if slot.thread.take().is_some() && slot.started.elapsed() >= self.policy.reset_after {
+			slot.failures = 0;
+		}
+		slot.failures += 1;
+		let status = match self.policy.max_restarts {
+			Some(max) if slot.failures > max => DeviceStatus::GaveUp(error),
+			_ => {
+				let backoff = self.policy.backoff(slot.failures);
+				slot.retry_at = Instant::now() + backoff;
+				DeviceStatus::Restarting {
+					error,
+					attempt: slot.failures,
+					backoff,
+				}
+			}
+		};
+		self.set_status(i, status);
+	}
+
+	fn set_status(&mut self, i: usize, status: DeviceStatus) {
+		match status {
+			DeviceStatus::Running => info!(device = i, "device running"),
+			_ => warn!(device = i, ?status, "device stopped"),
+		}
+		if let Some(ref handler) = self.handler {
+			handler(i, &status);
+		}
+		self.slots[i].status = status;
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::params::DynParams;
+	use progpow_base::params::KawPowParams;
+	use std::sync::{Arc, Mutex};
+
+	#[test]
+	fn test_supervisor_gives_up_on_failing_device() {
+		let good = HardwareSpec::Cpu {
+			params: DynParams::of::<KawPowParams>(),
+			threads: Some(1),
+			cache_dir: None,
+		};
+		// No preset matches, so every search fails like a device after a driver reset.
+		let mut params = DynParams::of::<KawPowParams>();
+		params.name = "broken".to_string();
+		let broken = HardwareSpec::Cpu {
+			params,
+			threads: Some(1),
+			cache_dir: None,
+		};
+		let policy = RestartPolicy {
+			initial_backoff: Duration::from_millis(1),
+			max_restarts: Some(1),
+			..RestartPolicy::default()
+		};
+		let seen = Arc::new(Mutex::new(Vec::new()));
+		let log = seen.clone();
+		let mut supervisor = Supervisor::new(vec![good, broken], policy)
+			.on_status(move |i, status| log.lock().unwrap().push((i, status.clone())));
+
+		let job = MiningJob::new(1, [0u8; 32], 20, 0, [0xff; 32]);
+		for _ in 0..3 {
+			thread::sleep(Duration::from_millis(2));
+			let batch = supervisor.search(&job, 0).unwrap();
+			assert_eq!(batch.found[0].0, 0);
+		}
+
+		let statuses = supervisor.statuses();
+		assert_eq!(statuses[0], DeviceStatus::Running);
+		assert!(matches!(statuses[1], DeviceStatus::GaveUp(_)));
+		let seen = seen.lock().unwrap();
+		let broken: Vec<_> = seen.iter().filter(|(i, _)| *i == 1).collect();
+		assert_eq!(broken.len(), 4);
+		assert!(matches!(
+			broken[1].1,
+			DeviceStatus::Restarting { attempt: 1, .. }
+		));
+	}
+
+	#[test]
+	fn test_supervisor_reports_lost_device() {
+		use crate::hardware::MockMiner;
+
+		let policy = RestartPolicy {
+			initial_backoff: Duration::from_millis(1),
+			..RestartPolicy::default()
+		};
+		let seen = Arc::new(Mutex::new(Vec::new()));
+		let log = seen.clone();
+		let lost = HardwareSpec::Mock(MockMiner::new().with_device_lost());
+		let mut supervisor =
+			Supervisor::new(vec![HardwareSpec::Mock(MockMiner::new()), lost], policy)
+				.on_status(move |i, status| log.lock().unwrap().push((i, status.clone())));
+
+		let job = MiningJob::new(1, [0u8; 32], 20, 0, [0xff; 32]);
+		supervisor.search(&job, 0).unwrap();
+
+		let seen = seen.lock().unwrap();
+		let lost: Vec<_> = seen
+			.iter()
+			.filter(|(i, _)| *i == 1)
+			.map(|(_, s)| s)
+			.collect();
+		assert_eq!(lost[1], &DeviceStatus::Lost("mock device".to_string()));
+		assert!(matches!(
+			lost[2],
+			DeviceStatus::Restarting { attempt: 1, .. }
+		));
+	}
+
+	#[test]
+	fn test_watchdog_restarts_hung_device() {
+		let spec = HardwareSpec::Cpu {
+			params: DynParams::of::<KawPowParams>(),
+			threads: Some(1),
+			cache_dir: None,
+		};
+		let policy = RestartPolicy {
+			initial_backoff: Duration::from_millis(1),
+			..RestartPolicy::default()
+		};
+		// Building the light cache alone outlasts the timeout, as a hung kernel would.
+		let mut supervisor =
+			Supervisor::new(vec![spec], policy).with_watchdog(Some(Duration::from_millis(1)));
+		let job = MiningJob::new(1, [0u8; 32], 20, 0, [0xff; 32]);
+		let batch = supervisor.search(&job, 0).unwrap();
+		assert!(batch.found.is_empty());
+		match &supervisor.statuses()[0] {
+			DeviceStatus::Restarting { error, attempt, .. } => {
+				assert!(error.contains("hung"));
+				assert_eq!(*attempt, 1);
+			}
+			status => panic!("unexpected status {:?}", status),
+		}
+	}
+}
@@ -0,0 +1,178 @@
+//! Loader for published ProgPoW test vectors, and a check of `Verifier::verify_hash`
+//! against them.
+//!
+//! Two layouts are read:
+//! - the chfast/ethash one, an array of `[height, header, nonce, mix_hash, final_hash]`
+//!   arrays with the nonce as 16 hex digits;
+//! - the kawpow one, an array of objects with `block_height` (or `height`), `header_hash`,
+//!   `nonce`, `mix_hash` and `final_hash`, the nonce as hex or a number.
+//!
+//! Hashes are compared byte for byte with `verify_hash`, which uses the byte order of
+//! those files and of pool submissions.
+
+use serde_json::Value;
+
+use crate::types::{h256_from_hex, to_hex, ProgPowError, Verifier, H256};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestVector {
+	pub height: u64,
+	pub header_hash: H256,
+	pub nonce: u64,
+	pub mix_hash: H256,
+	pub final_hash: H256,
+}
+
+/// A vector `verify_hash` disagrees with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+	pub vector: TestVector,
+	pub mix_hash: H256,
+	pub final_hash: H256,
+}
+
+impl std::fmt::Display for Mismatch {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(
+			f,
+			"height {} nonce {:#x}: mix {} (expected {}), final {} (expected {})",
+			self.vector.height,
+			self.vector.nonce,
+			to_hex(&self.mix_hash),
+			to_hex(&self.vector.mix_hash),
+			to_hex(&self.final_hash),
+			to_hex(&self.vector.final_hash)
+		)
+	}
+}
+
+fn invalid(what: &str, i: usize) -> ProgPowError {
+	ProgPowError::Protocol(format!("test vector {}: bad {}", i, what))
+}
+
+fn hash(value: &Value, what: &str, i: usize) -> Result<H256, ProgPowError> {
+	value
+		.as_str()
+		.and_then(h256_from_hex)
+		.ok_or_else(|| invalid(what, i))
+}
+
+fn number(value: &Value, what: &str, i: usize) -> Result<u64, ProgPowError> {
+	match value {
+		Value::Number(n) => n.as_u64(),
+		Value::String(s) => u64::from_str_radix(s.trim_start_matches("0x"), 16).ok(),
+		_ => None,
+	}
+	.ok_or_else(|| invalid(what, i))
+}
+
+/// Parse a test vector file in either layout.
+pub fn parse(json: &str) -> Result<Vec<TestVector>, ProgPowError> {
+	let entries: Vec<Value> =
+		serde_json::from_str(json).map_err(|e| ProgPowError::Protocol(e.to_string()))?;
+	entries
+		.iter()
+		.enumerate()
+		.map(|(i, entry)| match entry {
+			Value::Array(fields) if fields.len() == 5 => Ok(TestVector {
+				height: fields[0].as_u64().ok_or_else(|| invalid("height", i))?,
+				header_hash: hash(&fields[1], "header_hash", i)?,
+				nonce: number(&fields[2], "nonce", i)?,
+				mix_hash: hash(&fields[3], "mix_hash", i)?,
+				final_hash: hash(&fields[4], "final_hash", i)?,
+			}),
+			Value::Object(fields) => {
+				let field = |name: &str| fields.get(name).ok_or_else(|| invalid(name, i));
+				let height = fields
+					.get("block_height")
+					.or_else(|| fields.get("height"))
+					.and_then(Value::as_u64)
+					.ok_or_else(|| invalid("block_height", i))?;
+				Ok(TestVector {
+					height,
+					header_hash: hash(field("header_hash")?, "header_hash", i)?,
+					nonce: number(field("nonce")?, "nonce", i)?,
+					mix_hash: hash(field("mix_hash")?, "mix_hash", i)?,
+					final_hash: hash(field("final_hash")?, "final_hash", i)?,
+				})
+			}
+			_ => Err(invalid("entry", i)),
+		})
+		.collect()
+}
+
+/// Run `verify_hash` of `compute` on every vector, returning those it gets wrong.
+pub fn check<C: Verifier + ?Sized>(
+	compute: &C,
+	vectors: &[TestVector],
+) -> Result<Vec<Mismatch>, ProgPowError> {
+	let mut mismatches = Vec::new();
+	for vector in vectors {
+		let (final_hash, mix_hash) =
+			compute.verify_hash(&vector.header_hash, vector.height, vector.nonce)?;
+		if final_hash != vector.final_hash || mix_hash != vector.mix_hash {
+			mismatches.push(Mismatch {
+				vector: vector.clone(),
+				mix_hash,
+				final_hash,
+			});
+		}
+	}
+	Ok(mismatches)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::hardware::PpCPU;
+	use std::fs;
+	use std::path::Path;
+
+	#[test]
+	fn test_parse_both_layouts() {
+		let ethash = r#"[[49, "63155f732f2bf556967f906155b510c917e48e99685ead76ea83f4eca03ab12b",
+			"0000000006ff2c47",
+			"c789c1180f890ec555ff42042913465481e8e6bc512cb981e1c1108dc3f2227d",
+			"9e7248f20914913a73d80a70174c331b1d34f260535ac3631d770e656b5dd922"]]"#;
+		let kawpow = r#"[{"block_height": 49,
+			"header_hash": "63155f732f2bf556967f906155b510c917e48e99685ead76ea83f4eca03ab12b",
+			"nonce": 117386311,
+			"mix_hash": "0xc789c1180f890ec555ff42042913465481e8e6bc512cb981e1c1108dc3f2227d",
+			"final_hash": "9e7248f20914913a73d80a70174c331b1d34f260535ac3631d770e656b5dd922"}]"#;
+
+		let a = parse(ethash).unwrap();
+		assert_eq!(a, parse(kawpow).unwrap());
+		assert_eq!(a[0].nonce, 0x6ff2c47);
+		assert!(parse(r#"[[1, "00"]]"#).is_err());
+	}
+
+	/// Presets whose vector file ships in `res/testvectors`.
+	const SHIPPED: [&str; 2] = ["zano", "epic"];
+
+	/// Checks every `res/testvectors/<preset>.json` against the CPU verifier. The `SHIPPED`
+	/// files must exist, the others are checked when present.
+	#[test]
+	fn test_vector_files() {
+		let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("res/testvectors");
+		for (name, params) in crate::presets::all() {
+			let path = dir.join(format!("{}.json", name));
+			let json = match fs::read_to_string(&path) {
+				Ok(json) => json,
+				Err(e) if SHIPPED.contains(&name) => panic!("{}: {}", path.display(), e),
+				Err(_) => continue,
+			};
+			let vectors = parse(&json).unwrap();
+			let mismatches = check(&PpCPU::new_dyn(params), &vectors).unwrap();
+			assert!(
+				mismatches.is_empty(),
+				"{}: {}",
+				name,
+				mismatches
+					.iter()
+					.map(|m| m.to_string())
+					.collect::<Vec<_>>()
+					.join("\n")
+			);
+		}
+	}
+}
@@ -0,0 +1,227 @@
+//! Conversions between difficulty, 256-bit targets and hashes.
+//!
+//! Targets and hashes are big-endian `H256`s: byte 0 is the most significant, so they
+//! compare as numbers when compared as byte arrays.
+
+use num_bigint::BigUint;
+use num_traits::{One, ToPrimitive, Zero};
+
+use super::H256;
+
+fn to_h256(value: &BigUint) -> H256 {
+	let bytes = value.to_bytes_be();
+	let mut out = [0u8; 32];
+	out[32 - bytes.len()..].copy_from_slice(&bytes);
+	out
+}
+
+fn max_target() -> BigUint {
+	(BigUint::one() << 256) - BigUint::one()
+}
+
+/// The target a hash must not exceed to meet `difficulty`, `(2^256 - 1) / difficulty`.
+/// Difficulty 0 is treated as 1.
+pub fn target_from_difficulty(difficulty: u64) -> H256 {
+	to_h256(&(max_target() / BigUint::from(difficulty.max(1))))
+}
+
+/// The upper 64 bits of `target_from_difficulty`, as taken by `MiningJob`
+/// and the GPU kernels.
+pub fn target64_from_difficulty(difficulty: u64) -> u64 {
+	let target = target_from_difficulty(difficulty);
+	let mut upper = [0u8; 8];
+	upper.copy_from_slice(&target[..8]);
+	u64::from_be_bytes(upper)
+}
+
+/// The 256-bit target with upper 64 bits `target` and all lower bits set, accepting the
+/// same hashes as a comparison of the upper 64 bits.
+pub fn target_from_target64(target: u64) -> H256 {
+	let mut out = [0xff; 32];
+	out[..8].copy_from_slice(&target.to_be_bytes());
+	out
+}
+
+/// Difficulty a target corresponds to, saturating at `u64::MAX`.
+pub fn difficulty_from_target(target: &H256) -> u64 {
+	difficulty_from_hash(target)
+}
+
+/// Highest difficulty `hash` meets, `(2^256 - 1) / hash`, saturating at `u64::MAX`.
+pub fn difficulty_from_hash(hash: &H256) -> u64 {
+	let hash = BigUint::from_bytes_be(hash);
+	if hash.is_zero() {
+		return u64::MAX;
+	}
+	(max_target() / hash).to_u64().unwrap_or(u64::MAX)
+}
+
+/// Difficulty of a share whose final hash is `final_hash`, `(2^256 - 1) / final_hash` in
+/// full precision. A zero hash gives `2^256 - 1`.
+pub fn share_difficulty(final_hash: &H256) -> BigUint {
+	let hash = BigUint::from_bytes_be(final_hash);
+	if hash.is_zero() {
+		return max_target();
+	}
+	max_target() / hash
+}
+
+/// Whether `final_hash` meets difficulty `boundary`, compared against the whole 256-bit
+/// target rather than its upper 64 bits.
+pub fn hash_meets_boundary(final_hash: &H256, boundary: u64) -> bool {
+	meets_target(final_hash, &target_from_difficulty(boundary))
+}
+
+/// Target encoded in the compact `nBits` form of Bitcoin-style headers: a 23-bit
+/// mantissa times `256^(exponent - 3)`. Negative or overflowing encodings give a zero
+/// target, which no hash meets.
+pub fn target_from_compact(bits: u32) -> H256 {
+	let exponent = bits >> 24;
+	let mantissa = BigUint::from(bits & 0x007f_ffff);
+	if bits & 0x0080_0000 != 0 && !mantissa.is_zero() {
+		return [0; 32];
+	}
+	let target = if exponent <= 3 {
+		mantissa >> (8 * (3 - exponent))
+	} else {
+		mantissa << (8 * (exponent - 3))
+	};
+	if target > max_target() {
+		return [0; 32];
+	}
+	to_h256(&target)
+}
+
+/// Whether `hash` is at or below `target`.
+pub fn meets_target(hash: &H256, target: &H256) -> bool {
+	hash <= target
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn from_hex(hex: &str) -> H256 {
+		let mut out = [0u8; 32];
+		for i in 0..32 {
+			out[i] = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+		}
+		out
+	}
+
+	#[test]
+	fn test_target_from_difficulty() {
+		assert_eq!(target_from_difficulty(0), [0xff; 32]);
+		assert_eq!(target_from_difficulty(1), [0xff; 32]);
+		assert_eq!(
+			target_from_difficulty(2),
+			from_hex("7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff")
+		);
+		// Pool difficulty 1 in Bitcoin-style share terms (2^32 hashes per share).
+		assert_eq!(
+			target_from_difficulty(1 << 32),
+			from_hex("00000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffff")
+		);
+		// Same math as the inline `(1 << 256) / boundary >> 192` it replaces.
+		assert_eq!(target64_from_difficulty(100000000), 0x0000002af31dc461);
+		assert_eq!(target_from_target64(u64::MAX), [0xff; 32]);
+		assert_eq!(
+			target_from_target64(0x0000002af31dc461),
+			from_hex("0000002af31dc461ffffffffffffffffffffffffffffffffffffffffffffffff")
+		);
+	}
+
+	#[test]
+	fn test_difficulty_from_hash() {
+		assert_eq!(difficulty_from_hash(&[0xff; 32]), 1);
+		assert_eq!(difficulty_from_hash(&[0; 32]), u64::MAX);
+		for &difficulty in &[1u64, 3, 1000, 100000000, 1 << 40] {
+			let target = target_from_difficulty(difficulty);
+			assert_eq!(difficulty_from_target(&target), difficulty);
+		}
+	}
+
+	#[test]
+	fn test_share_difficulty() {
+		assert_eq!(share_difficulty(&[0xff; 32]), BigUint::one());
+		assert_eq!(share_difficulty(&[0; 32]), max_target());
+		// Beyond what `difficulty_from_hash` can return.
+		let mut hash = [0u8; 32];
+		hash[20] = 1;
+		assert_eq!(share_difficulty(&hash), max_target() >> 88);
+		assert_eq!(difficulty_from_hash(&hash), u64::MAX);
+	}
+
+	#[test]
+	fn test_hash_meets_boundary() {
+		let boundary = 100000000;
+		let target = target_from_difficulty(boundary);
+		assert!(hash_meets_boundary(&target, boundary));
+		// Equal to the target in its upper 64 bits, but above it.
+		let mut hash = target;
+		hash[8..].copy_from_slice(&[0xff; 24]);
+		assert!(!hash_meets_boundary(&hash, boundary));
+		assert!(!hash_meets_boundary(&[0xff; 32], 2));
+		assert!(hash_meets_boundary(&[0; 32], u64::MAX));
+	}
+
+	#[test]
+	fn test_target_from_compact() {
+		assert_eq!(
+			target_from_compact(0x1d00ffff),
+			from_hex("00000000ffff0000000000000000000000000000000000000000000000000000")
+		);
+		assert_eq!(
+			target_from_compact(0x1b0404cb),
+			from_hex("00000000000404cb000000000000000000000000000000000000000000000000")
+		);
+		assert_eq!(target_from_compact(0x01803456), [0; 32]);
+		assert_eq!(target_from_compact(0xff123456), [0; 32]);
+	}
+
+	/// `nBits` and hashes of mainnet blocks, with the difficulties block explorers show.
+	#[test]
+	fn test_mainnet_blocks() {
+		// Bitcoin genesis, difficulty 1.
+		let diff1 = target_from_compact(0x1d00ffff);
+		let genesis = from_hex("000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f");
+		assert!(meets_target(&genesis, &diff1));
+		assert_eq!(difficulty_from_target(&diff1), 4295032833);
+
+		// Bitcoin block 100000, difficulty 14484.16236122.
+		let target = target_from_compact(0x1b04864c);
+		assert_eq!(
+			target,
+			from_hex("000000000004864c000000000000000000000000000000000000000000000000")
+		);
+		let block = from_hex("000000000003ba27aa200b1cecaad478d2b00432346c3f1f3986da1afd33e506");
+		assert!(meets_target(&block, &target));
+		assert_eq!(difficulty_from_target(&target), 62209952899966);
+		assert_eq!(
+			difficulty_from_target(&target) / difficulty_from_target(&diff1),
+			14484
+		);
+
+		// Ravencoin genesis, which only meets its own easier limit.
+		let target = target_from_compact(0x1e00ffff);
+		assert_eq!(
+			target,
+			from_hex("000000ffff000000000000000000000000000000000000000000000000000000")
+		);
+		let genesis = from_hex("0000006b444bc2f2ffe627be9d9e7e7a0730000870ef6eb6da46c8eae389df90");
+		assert!(meets_target(&genesis, &target));
+		assert!(!meets_target(&genesis, &diff1));
+		assert_eq!(difficulty_from_target(&target), 16777472);
+	}
+
+	#[test]
+	fn test_meets_target() {
+		let target = target_from_difficulty(1 << 32);
+		let mut hash = [0u8; 32];
+		hash[4] = 0xff;
+		assert!(meets_target(&hash, &target));
+		hash[3] = 1;
+		assert!(!meets_target(&hash, &target));
+		assert!(meets_target(&target, &target));
+	}
+}
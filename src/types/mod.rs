@@ -0,0 +1,470 @@
+pub mod difficulty;
+
+use std::fmt;
+use std::io;
+use std::ops::Range;
+
+pub type H256 = [u8; 32];
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Hardware {
+	CPU,
+	GPU,
+}
+
+#[derive(Debug)]
+pub enum ProgPowError {
+	NoInitialized,
+	DAG,
+	CACHE,
+	/// The runtime params do not match any compiled-in preset.
+	UnsupportedParams,
+	/// Error reported by the GPU driver or kernel compiler.
+	Driver(String),
+	/// The named device stopped responding, after it was unplugged or its driver reset.
+	/// Its miner must be dropped and built again once the device is back.
+	DeviceLost(String),
+	/// Connection to a pool or node failed.
+	Network(String),
+	/// A pool or node sent something unexpected or refused a request.
+	Protocol(String),
+	/// A configuration file is unreadable or invalid.
+	Config(String),
+	/// The device has less memory than the DAG needs, in bytes.
+	InsufficientVram {
+		needed: u64,
+		available: u64,
+	},
+	/// A known vector hashed differently on this platform, see `self_check`.
+	SelfCheck(String),
+	/// The job's target is zero, which no hash meets.
+	ZeroTarget,
+	/// A job's `epoch` is not the epoch of its `height`.
+	EpochMismatch {
+		height: u64,
+		epoch: i32,
+		expected: i32,
+	},
+	/// Reading or building a light cache or DAG file failed, as `progpow_cpu` reports it.
+	Io(io::Error),
+}
+
+/// Result of the crate's fallible calls.
+pub type ProgPowResult<T> = Result<T, ProgPowError>;
+
+impl fmt::Display for ProgPowError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ProgPowError::NoInitialized => f.write_str("miner not initialized"),
+			ProgPowError::DAG => f.write_str("DAG generation failed"),
+			ProgPowError::CACHE => f.write_str("light cache unavailable"),
+			ProgPowError::UnsupportedParams => f.write_str("params match no compiled-in preset"),
+			ProgPowError::Driver(e) => write!(f, "driver error: {}", e),
+			ProgPowError::DeviceLost(device) => write!(f, "{} was lost", device),
+			ProgPowError::Network(e) => write!(f, "network error: {}", e),
+			ProgPowError::Protocol(e) => write!(f, "protocol error: {}", e),
+			ProgPowError::Config(e) => write!(f, "invalid config: {}", e),
+			ProgPowError::InsufficientVram { needed, available } => write!(
+				f,
+				"DAG needs {} bytes of device memory, {} available",
+				needed, available
+			),
+			ProgPowError::SelfCheck(e) => write!(f, "self check failed: {}", e),
+			ProgPowError::ZeroTarget => f.write_str("target is zero"),
+			ProgPowError::EpochMismatch {
+				height,
+				epoch,
+				expected,
+			} => write!(
+				f,
+				"epoch {} does not match height {}, which is in epoch {}",
+				epoch, height, expected
+			),
+			ProgPowError::Io(e) => write!(f, "{}", e),
+		}
+	}
+}
+
+impl std::error::Error for ProgPowError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ProgPowError::Io(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for ProgPowError {
+	fn from(e: io::Error) -> Self {
+		ProgPowError::Io(e)
+	}
+}
+
+/// `progpow_gpu` reports driver and kernel failures as strings.
+impl From<&str> for ProgPowError {
+	fn from(e: &str) -> Self {
+		ProgPowError::Driver(e.to_string())
+	}
+}
+
+/// Parse 32 bytes of hex, with or without a `0x` prefix.
+pub fn h256_from_hex(hex: &str) -> Option<H256> {
+	let hex = hex.trim_start_matches("0x");
+	if hex.len() != 64 || !hex.is_ascii() {
+		return None;
+	}
+	let mut out = [0u8; 32];
+	for (i, byte) in out.iter_mut().enumerate() {
+		*byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+	}
+	Some(out)
+}
+
+/// Lowercase hex with a `0x` prefix.
+pub fn to_hex(bytes: &[u8]) -> String {
+	let mut out = String::with_capacity(2 + bytes.len() * 2);
+	out.push_str("0x");
+	for byte in bytes {
+		out.push_str(&format!("{:02x}", byte));
+	}
+	out
+}
+
+/// What one call of a search closure, as taken by the pool and node clients' `run`, did.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Batch {
+	/// Nonces covered, the next call starts after them.
+	pub searched: u64,
+	/// `(nonce, mix_hash)` of every nonce at or below the target.
+	pub found: Vec<(u64, H256)>,
+}
+
+/// One unit of work for a `Miner`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MiningJob {
+	/// Chosen by the caller. A GPU drops the solution pending for a previous id.
+	pub job_id: u64,
+	pub header: H256,
+	pub height: u64,
+	/// Epoch of `height`, checked by `validate`.
+	pub epoch: i32,
+	/// The final hash must not exceed it, see `difficulty::target_from_difficulty`.
+	pub target: H256,
+	/// Top nonce bytes fixed by the pool. Miners only search nonces starting with them.
+	pub extranonce: Extranonce,
+}
+
+impl MiningJob {
+	pub fn new(job_id: u64, header: H256, height: u64, epoch: i32, target: H256) -> Self {
+		MiningJob {
+			job_id,
+			header,
+			height,
+			epoch,
+			target,
+			extranonce: Extranonce::default(),
+		}
+	}
+
+	pub fn with_extranonce(mut self, extranonce: Extranonce) -> Self {
+		self.extranonce = extranonce;
+		self
+	}
+
+	/// Reject a job no search can succeed on, before it reaches a kernel, on a chain with
+	/// `epoch_length` blocks per epoch (`ProgPowParams::EPOCH_LENGTH`). Miners check it in
+	/// `compute`.
+	pub fn validate(&self, epoch_length: u64) -> Result<(), ProgPowError> {
+		if self.target == [0; 32] {
+			return Err(ProgPowError::ZeroTarget);
+		}
+		let expected = (self.height / epoch_length) as i32;
+		if self.epoch != expected {
+			return Err(ProgPowError::EpochMismatch {
+				height: self.height,
+				epoch: self.epoch,
+				expected,
+			});
+		}
+		Ok(())
+	}
+}
+
+/// The top `bytes` bytes of every nonce, as assigned by a pool so its miners never search
+/// the same nonces. The default fixes nothing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Extranonce {
+	pub value: u64,
+	pub bytes: u32,
+}
+
+impl Extranonce {
+	/// `bytes` is capped at 7, leaving at least one byte to search. `value` is cut to fit.
+	pub fn new(value: u64, bytes: u32) -> Self {
+		let bytes = bytes.min(7);
+		let value = if bytes == 0 {
+			0
+		} else {
+			value & (u64::MAX >> (64 - 8 * bytes))
+		};
+		Extranonce { value, bytes }
+	}
+
+	/// Parse the extranonce sent with `mining.subscribe`, two hex digits per byte.
+	pub fn from_hex(hex: &str) -> Option<Self> {
+		let hex = hex.trim_start_matches("0x");
+		if hex.is_empty() {
+			return Some(Extranonce::default());
+		}
+		if hex.len() % 2 != 0 || hex.len() > 14 {
+			return None;
+		}
+		let value = u64::from_str_radix(hex, 16).ok()?;
+		Some(Extranonce::new(value, hex.len() as u32 / 2))
+	}
+
+	/// `nonce` with its top bytes replaced by the extranonce.
+	pub fn apply(&self, nonce: u64) -> u64 {
+		if self.bytes == 0 {
+			return nonce;
+		}
+		let free = 64 - 8 * self.bytes;
+		self.value << free | (nonce & (u64::MAX >> (8 * self.bytes)))
+	}
+
+	pub fn contains(&self, nonce: u64) -> bool {
+		self.apply(nonce) == nonce
+	}
+
+	pub fn first(&self) -> u64 {
+		self.apply(0)
+	}
+
+	pub fn last(&self) -> u64 {
+		self.apply(u64::MAX)
+	}
+
+	/// `range` moved into the extranonce's nonces and cut at their end. The very last
+	/// nonce is out of reach of an exclusive range, as with `0..u64::MAX`.
+	pub fn clamp(&self, range: Range<u64>) -> Range<u64> {
+		if self.bytes == 0 {
+			return range;
+		}
+		let start = self.apply(range.start);
+		let len = range.end.saturating_sub(range.start);
+		let end = start.saturating_add(len.min((self.last() - start).saturating_add(1)));
+		start..end
+	}
+}
+
+/// Outcome of validating a submitted share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ShareStatus {
+	Ok,
+	/// The final hash is above the target.
+	LowDifficulty,
+	/// The claimed mix hash does not match the computed one.
+	BadMix,
+}
+
+/// Final hash words as a big-endian number, directly comparable with the targets in
+/// `difficulty`.
+pub fn final_hash_bytes(words: &[u32; 8]) -> H256 {
+	let mut out = [0u8; 32];
+	for (i, word) in words.iter().enumerate() {
+		out[i * 4..(i + 1) * 4].copy_from_slice(&word.to_be_bytes());
+	}
+	out
+}
+
+/// Mix hash words in the byte order pools and nodes exchange them (little-endian words).
+pub fn mix_hash_bytes(words: &[u32; 8]) -> H256 {
+	let mut out = [0u8; 32];
+	for (i, word) in words.iter().enumerate() {
+		out[i * 4..(i + 1) * 4].copy_from_slice(&word.to_le_bytes());
+	}
+	out
+}
+
+/// Mix hash as the eight 32-bit words the hash computes.
+///
+/// On the wire each word is little-endian, see `mix_hash_bytes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mix(pub [u32; 8]);
+
+impl Mix {
+	pub fn from_le_bytes(bytes: &H256) -> Self {
+		let mut words = [0u32; 8];
+		for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+			*word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+		}
+		Mix(words)
+	}
+
+	pub fn to_le_bytes(&self) -> H256 {
+		mix_hash_bytes(&self.0)
+	}
+}
+
+impl From<[u32; 8]> for Mix {
+	fn from(words: [u32; 8]) -> Self {
+		Mix(words)
+	}
+}
+
+impl From<Mix> for [u32; 8] {
+	fn from(mix: Mix) -> Self {
+		mix.0
+	}
+}
+
+/// A 64-bit nonce.
+///
+/// Block headers store it little-endian; stratum and `eth_submitWork` send it as
+/// big-endian hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Nonce(pub u64);
+
+impl Nonce {
+	pub fn from_le_bytes(bytes: [u8; 8]) -> Self {
+		Nonce(u64::from_le_bytes(bytes))
+	}
+
+	pub fn to_le_bytes(self) -> [u8; 8] {
+		self.0.to_le_bytes()
+	}
+
+	pub fn from_be_bytes(bytes: [u8; 8]) -> Self {
+		Nonce(u64::from_be_bytes(bytes))
+	}
+
+	pub fn to_be_bytes(self) -> [u8; 8] {
+		self.0.to_be_bytes()
+	}
+}
+
+impl From<u64> for Nonce {
+	fn from(nonce: u64) -> Self {
+		Nonce(nonce)
+	}
+}
+
+impl From<Nonce> for u64 {
+	fn from(nonce: Nonce) -> Self {
+		nonce.0
+	}
+}
+
+/// Hashes single nonces. Object safe, so a verifier can be picked at runtime and held as
+/// `Box<dyn Verifier>`.
+pub trait Verifier {
+	fn verify(
+		&self,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<([u32; 8], [u32; 8]), ProgPowError>;
+	/// Like `verify`, returning `(final_hash, mix_hash)` as bytes. See `final_hash_bytes`
+	/// and `mix_hash_bytes` for their byte order.
+	fn verify_hash(
+		&self,
+		header_hash: &H256,
+		height: u64,
+		nonce: u64,
+	) -> Result<(H256, H256), ProgPowError> {
+		let (value, mix) = self.verify(header_hash, height, nonce)?;
+		Ok((final_hash_bytes(&value), mix_hash_bytes(&mix)))
+	}
+}
+
+/// Searches for nonces meeting a target. Object safe, see `hardware::create_miner`.
+pub trait Miner {
+	fn init(&mut self) -> Result<(), ProgPowError>;
+	fn hardware(&self) -> Hardware;
+	/// Search `nonce_range` of `job` until a solution is found, the range is exhausted or
+	/// the miner is stopped. Disjoint ranges let several devices or rigs share one job.
+	/// The range is first moved into `job.extranonce`, see `Extranonce::clamp`.
+	fn compute(&self, job: &MiningJob, nonce_range: Range<u64>) -> Result<(), ProgPowError>;
+	/// Take the `(nonce, mix_hash)` pairs found by `compute` so far, the mix in the byte
+	/// order of `mix_hash_bytes`.
+	fn take_solutions(&self) -> Vec<(u64, H256)>;
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_error_conversions() {
+		let err: ProgPowError = io::Error::new(io::ErrorKind::NotFound, "no cache").into();
+		assert!(matches!(err, ProgPowError::Io(_)));
+		assert!(std::error::Error::source(&err).is_some());
+		assert_eq!(err.to_string(), "no cache");
+
+		let err = ProgPowError::from("launch failed");
+		assert_eq!(err.to_string(), "driver error: launch failed");
+	}
+
+	#[test]
+	fn test_job_validation() {
+		let job = MiningJob::new(1, [0; 32], 20, 0, [0xff; 32]);
+		assert!(job.validate(30000).is_ok());
+		let job = MiningJob::new(1, [0; 32], 20, 0, [0; 32]);
+		assert!(matches!(job.validate(30000), Err(ProgPowError::ZeroTarget)));
+
+		// Height 45000 is in epoch 1 of 30000 blocks but in epoch 34 of Epic's 1300.
+		let job = MiningJob::new(1, [0; 32], 45000, 1, [0xff; 32]);
+		assert!(job.validate(30000).is_ok());
+		assert!(matches!(
+			job.validate(1300),
+			Err(ProgPowError::EpochMismatch {
+				height: 45000,
+				epoch: 1,
+				expected: 34,
+			})
+		));
+	}
+
+	#[test]
+	fn test_extranonce() {
+		let none = Extranonce::default();
+		assert_eq!(none.apply(12345), 12345);
+		assert_eq!(none.clamp(5..9), 5..9);
+		assert_eq!(none.clamp(0..1000), 0..1000);
+		assert_eq!(none.clamp(0..u64::MAX), 0..u64::MAX);
+
+		let extranonce = Extranonce::from_hex("0xa1b2").unwrap();
+		assert_eq!(extranonce, Extranonce::new(0xa1b2, 2));
+		assert_eq!(extranonce.first(), 0xa1b2_0000_0000_0000);
+		assert_eq!(extranonce.last(), 0xa1b2_ffff_ffff_ffff);
+		assert_eq!(
+			extranonce.apply(0xffff_0000_0000_0007),
+			0xa1b2_0000_0000_0007
+		);
+		assert!(extranonce.contains(0xa1b2_0000_1234_0000));
+		assert!(!extranonce.contains(0xa1b3_0000_0000_0000));
+
+		// Ranges stay inside the fixed prefix instead of running into the next one.
+		assert_eq!(
+			extranonce.clamp(0x10..0x20),
+			0xa1b2_0000_0000_0010..0xa1b2_0000_0000_0020
+		);
+		assert_eq!(
+			extranonce.clamp(0xffff_ffff_fff0..0x1_0000_0000_0010),
+			0xa1b2_ffff_ffff_fff0..0xa1b3_0000_0000_0000
+		);
+
+		assert_eq!(Extranonce::new(0x1ff, 1), Extranonce::new(0xff, 1));
+		assert_eq!(Extranonce::new(1, 9).bytes, 7);
+		assert_eq!(Extranonce::from_hex(""), Some(none));
+		assert_eq!(Extranonce::from_hex("abc"), None);
+		assert_eq!(Extranonce::from_hex("zz"), None);
+	}
+}